@@ -0,0 +1,56 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use entity_engine::bench::{call_overhead, enhanced, iteration};
+use mlua::Lua;
+
+fn enhanced_script(c: &mut Criterion) {
+    let lua = Lua::new();
+    enhanced::setup(&lua).unwrap();
+    let script = lua.load(enhanced::script(10_000)).into_function().unwrap();
+
+    c.bench_function("enhanced/10k_iterations", |b| {
+        b.iter(|| script.call::<mlua::Value>(()).unwrap())
+    });
+}
+
+fn call_overhead(c: &mut Criterion) {
+    let lua = Lua::new();
+    call_overhead::setup(&lua).unwrap();
+
+    let mut group = c.benchmark_group("call_overhead");
+    for convention in call_overhead::Convention::ALL {
+        for args in call_overhead::ARG_COUNTS {
+            if convention == call_overhead::Convention::BatchedSoa && args == 0 {
+                continue;
+            }
+            group.bench_with_input(
+                BenchmarkId::new(convention.name(), args),
+                &args,
+                |b, &args| {
+                    b.iter_custom(|iters| {
+                        call_overhead::measure(&lua, convention, args, iters).unwrap()
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn iteration(c: &mut Criterion) {
+    let mut group = c.benchmark_group("iteration");
+    group.sample_size(20);
+    for strategy in iteration::Strategy::ALL {
+        let mut fixture = iteration::Fixture::new(10_000).unwrap();
+        group.bench_function(strategy.name(), |b| {
+            b.iter_custom(|frames| {
+                iteration::measure(&mut fixture, strategy, frames as u32)
+                    .unwrap()
+                    .elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, enhanced_script, call_overhead, iteration);
+criterion_main!(benches);
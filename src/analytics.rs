@@ -0,0 +1,496 @@
+//! Gameplay telemetry: `track` buffers named events with properties and
+//! a sink receives them in batches, for funnels and balancing.
+
+use crate::data::DataError;
+use crate::ecs::ScriptValue;
+use crate::kv::KvStore;
+use crate::rng::GameRng;
+#[cfg(feature = "http")]
+use crate::sandbox::SandboxConfig;
+use crate::sandbox::require_game_script;
+use mlua::{Lua, Result, UserData, UserDataMethods};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Events kept while a sink keeps failing; the oldest go first.
+const MAX_BUFFERED: usize = 10_000;
+
+/// The settings key the opt-out choice is saved under.
+const OPT_OUT_KEY: &str = "analytics.opt_out";
+
+/// Batches the HTTP worker may fall behind by before `send` fails.
+#[cfg(feature = "http")]
+const HTTP_QUEUE: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnalyticsEvent {
+    pub name: String,
+    pub properties: BTreeMap<String, ScriptValue>,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub session: String,
+    /// Order within the session, so gaps show what sampling or failures
+    /// dropped.
+    pub seq: u64,
+}
+
+/// Where batches go. A failed batch is kept and offered again at the next
+/// flush.
+pub trait AnalyticsSink {
+    fn send(&mut self, batch: &[AnalyticsEvent]) -> std::result::Result<(), DataError>;
+}
+
+/// Appends each event as a line of JSON.
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        FileSink {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl AnalyticsSink for FileSink {
+    fn send(&mut self, batch: &[AnalyticsEvent]) -> std::result::Result<(), DataError> {
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for event in batch {
+            let line =
+                serde_json::to_string(event).map_err(|e| DataError::Invalid(e.to_string()))?;
+            text.push_str(&line);
+            text.push('\n');
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(text.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// POSTs each batch as a JSON array from one background thread, so a
+/// slow collector never stalls a frame. A failed POST is reported by the
+/// next `send`; its events are lost, which the gap in `seq` shows.
+#[cfg(feature = "http")]
+pub struct HttpSink {
+    queue: std::sync::mpsc::SyncSender<String>,
+    failures: std::sync::mpsc::Receiver<String>,
+}
+
+#[cfg(feature = "http")]
+impl HttpSink {
+    /// Fails when the collector's host isn't in the sandbox allowlist.
+    pub fn new(url: &str, sandbox: &SandboxConfig) -> std::result::Result<Self, DataError> {
+        match crate::net::http::host(url) {
+            Some(host) if sandbox.allows_host(host) => {}
+            _ => {
+                return Err(DataError::Invalid(format!(
+                    "'{}' is not in the sandbox http allowlist",
+                    url
+                )));
+            }
+        }
+        let (queue, batches) = std::sync::mpsc::sync_channel::<String>(HTTP_QUEUE);
+        let (failed, failures) = std::sync::mpsc::channel();
+        // A redirect could leave the allowlist.
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .max_redirects(0)
+            .build()
+            .into();
+        let url = url.to_string();
+        std::thread::Builder::new()
+            .name("analytics".to_string())
+            .spawn(move || {
+                for body in batches {
+                    let sent = agent
+                        .post(&url)
+                        .header("Content-Type", "application/json")
+                        .send(body);
+                    if let Err(e) = sent {
+                        let _ = failed.send(format!("posting to {} failed: {}", url, e));
+                    }
+                }
+            })?;
+        Ok(HttpSink { queue, failures })
+    }
+}
+
+#[cfg(feature = "http")]
+impl AnalyticsSink for HttpSink {
+    fn send(&mut self, batch: &[AnalyticsEvent]) -> std::result::Result<(), DataError> {
+        if let Some(failure) = self.failures.try_iter().last() {
+            return Err(DataError::Invalid(failure));
+        }
+        let body = serde_json::to_string(batch).map_err(|e| DataError::Invalid(e.to_string()))?;
+        self.queue.try_send(body).map_err(|e| {
+            DataError::Invalid(match e {
+                std::sync::mpsc::TrySendError::Full(_) => {
+                    "the collector is falling behind".to_string()
+                }
+                std::sync::mpsc::TrySendError::Disconnected(_) => {
+                    "the analytics thread has stopped".to_string()
+                }
+            })
+        })
+    }
+}
+
+struct AnalyticsState {
+    sink: Box<dyn AnalyticsSink>,
+    buffer: VecDeque<AnalyticsEvent>,
+    session: String,
+    seq: u64,
+    rng: GameRng,
+    /// Whether this session was sampled in.
+    sampled: bool,
+    event_rates: BTreeMap<String, f64>,
+    opted_out: bool,
+    /// Where the opt-out choice is saved, if anywhere.
+    settings: Option<KvStore>,
+    /// Whether the last flush failed; full batches then wait for the
+    /// interval instead of retrying on every `track`.
+    failing: bool,
+    batch_size: usize,
+    flush_interval: f64,
+    since_flush: f64,
+}
+
+/// The event pipeline. Clones share one pipeline, as does the `analytics`
+/// Lua global.
+#[derive(Clone)]
+pub struct Analytics {
+    state: Rc<RefCell<AnalyticsState>>,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+impl Analytics {
+    /// Starts a session with a random id, flushing every 50 events or 30
+    /// seconds.
+    pub fn new(sink: impl AnalyticsSink + 'static) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        let mut rng = GameRng::new(nanos);
+        let session = format!("{:016x}", rng.next_u64());
+        Analytics {
+            state: Rc::new(RefCell::new(AnalyticsState {
+                sink: Box::new(sink),
+                buffer: VecDeque::new(),
+                session,
+                seq: 0,
+                rng,
+                sampled: true,
+                event_rates: BTreeMap::new(),
+                opted_out: false,
+                settings: None,
+                failing: false,
+                batch_size: 50,
+                flush_interval: 30.0,
+                since_flush: 0.0,
+            })),
+        }
+    }
+
+    pub fn session(&self) -> String {
+        self.state.borrow().session.clone()
+    }
+
+    pub fn set_batch_size(&self, events: usize) {
+        self.state.borrow_mut().batch_size = events.max(1);
+    }
+
+    pub fn set_flush_interval(&self, seconds: f64) {
+        self.state.borrow_mut().flush_interval = seconds;
+    }
+
+    /// Keeps a `rate` share of sessions, decided once per session so the
+    /// ones kept have every event a funnel needs.
+    pub fn set_sample_rate(&self, rate: f64) {
+        let mut state = self.state.borrow_mut();
+        state.sampled = state.rng.chance(rate);
+    }
+
+    /// Keeps a `rate` share of one event, for noisy ones like `"shot"`.
+    pub fn set_event_sample_rate(&self, name: &str, rate: f64) {
+        let mut state = self.state.borrow_mut();
+        state.event_rates.insert(name.to_string(), rate);
+    }
+
+    /// While opted out nothing is tracked, and what was buffered is
+    /// dropped unsent. The choice is saved when `persist_opt_out` gave a
+    /// store.
+    pub fn set_opt_out(&self, opted_out: bool) -> std::result::Result<(), DataError> {
+        let settings = {
+            let mut state = self.state.borrow_mut();
+            state.opted_out = opted_out;
+            if opted_out {
+                state.buffer.clear();
+            }
+            state.settings.clone()
+        };
+        match settings {
+            Some(settings) => settings.set(OPT_OUT_KEY, ScriptValue::Bool(opted_out)),
+            None => Ok(()),
+        }
+    }
+
+    /// Keeps the opt-out choice in `settings` from now on, taking the one
+    /// saved there, so it holds across runs.
+    pub fn persist_opt_out(&self, settings: KvStore) {
+        let saved = settings.get(OPT_OUT_KEY);
+        let mut state = self.state.borrow_mut();
+        if let Some(ScriptValue::Bool(opted_out)) = saved {
+            state.opted_out = opted_out;
+            if opted_out {
+                state.buffer.clear();
+            }
+        }
+        state.settings = Some(settings);
+    }
+
+    pub fn opted_out(&self) -> bool {
+        self.state.borrow().opted_out
+    }
+
+    pub fn buffered(&self) -> usize {
+        self.state.borrow().buffer.len()
+    }
+
+    /// Buffers the event, flushing once a batch is full. Returns whether it
+    /// was kept.
+    pub fn track(&self, name: &str, properties: BTreeMap<String, ScriptValue>) -> bool {
+        let full = {
+            let mut state = self.state.borrow_mut();
+            let state = &mut *state;
+            if state.opted_out || !state.sampled {
+                return false;
+            }
+            let seq = state.seq;
+            state.seq += 1;
+            if let Some(&rate) = state.event_rates.get(name)
+                && !state.rng.chance(rate)
+            {
+                return false;
+            }
+            if state.buffer.len() >= MAX_BUFFERED {
+                state.buffer.pop_front();
+            }
+            state.buffer.push_back(AnalyticsEvent {
+                name: name.to_string(),
+                properties,
+                timestamp: now_ms(),
+                session: state.session.clone(),
+                seq,
+            });
+            state.buffer.len() >= state.batch_size && !state.failing
+        };
+        if full {
+            self.flush_logged();
+        }
+        true
+    }
+
+    /// Call once a frame; flushes when the interval has passed.
+    pub fn update(&self, dt: f64) {
+        let due = {
+            let mut state = self.state.borrow_mut();
+            state.since_flush += dt;
+            state.since_flush >= state.flush_interval
+        };
+        if due {
+            self.flush_logged();
+        }
+    }
+
+    /// Hands everything buffered to the sink in batches.
+    pub fn flush(&self) -> std::result::Result<(), DataError> {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        state.since_flush = 0.0;
+        state.failing = false;
+        while !state.buffer.is_empty() {
+            let count = state.batch_size.min(state.buffer.len());
+            let batch: Vec<AnalyticsEvent> = state.buffer.range(..count).cloned().collect();
+            if let Err(e) = state.sink.send(&batch) {
+                state.failing = true;
+                return Err(e);
+            }
+            state.buffer.drain(..count);
+        }
+        Ok(())
+    }
+
+    fn flush_logged(&self) {
+        if let Err(e) = self.flush() {
+            log::warn!(target: "analytics", "flush failed, keeping events: {}", e);
+        }
+    }
+
+    /// Adds the `analytics` global: `track(name, properties)`, `flush()`,
+    /// `set_opt_out(opted_out)` and `opted_out()`, all as methods. Mods
+    /// can't change the opt-out, which is the player's to make.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("analytics", self.clone())
+    }
+}
+
+impl UserData for Analytics {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method(
+            "track",
+            |_, this, (name, properties): (String, Option<BTreeMap<String, ScriptValue>>)| {
+                Ok(this.track(&name, properties.unwrap_or_default()))
+            },
+        );
+        methods.add_method("flush", |_, this, ()| Ok(this.flush()?));
+        methods.add_method("set_opt_out", |lua, this, opted_out: bool| {
+            require_game_script(lua, "analytics:set_opt_out")?;
+            Ok(this.set_opt_out(opted_out)?)
+        });
+        methods.add_method("opted_out", |_, this, ()| Ok(this.opted_out()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batches_sampling_and_opt_out() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("ee_analytics_{}", std::process::id()));
+        let path = dir.join("events.jsonl");
+        let _ = std::fs::remove_file(&path);
+        let analytics = Analytics::new(FileSink::new(&path));
+        analytics.set_batch_size(2);
+        analytics.set_event_sample_rate("shot", 0.0);
+
+        let lua = Lua::new();
+        analytics.register_lua(&lua)?;
+        lua.load(
+            r#"
+            analytics:track("level_start", { level = "harbor" })
+            assert(not analytics:track("shot"))
+            analytics:track("level_complete", { level = "harbor", time = 93.5 })
+            analytics:track("menu_opened")
+        "#,
+        )
+        .exec()?;
+        // The first two went out as a full batch; the third waits.
+        let lines = || std::fs::read_to_string(&path).unwrap_or_default();
+        assert_eq!(lines().lines().count(), 2);
+        assert_eq!(analytics.buffered(), 1);
+        analytics.set_flush_interval(1.0);
+        analytics.update(1.0);
+        let events: Vec<serde_json::Value> = lines()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1]["properties"]["time"], 93.5);
+        // The dropped "shot" leaves a gap in the sequence.
+        let seqs: Vec<u64> = events.iter().map(|e| e["seq"].as_u64().unwrap()).collect();
+        assert_eq!(seqs, [0, 2, 3]);
+        assert!(events.iter().all(|e| e["session"] == analytics.session()));
+
+        lua.load("analytics:track('a'); analytics:set_opt_out(true); analytics:track('b')")
+            .exec()?;
+        assert_eq!(analytics.buffered(), 0);
+        analytics.set_opt_out(false).unwrap();
+        analytics.set_sample_rate(0.0);
+        assert!(!analytics.track("c", BTreeMap::new()));
+        let _ = std::fs::remove_dir_all(&dir);
+        Ok(())
+    }
+
+    struct Down(Rc<std::cell::Cell<usize>>);
+
+    impl AnalyticsSink for Down {
+        fn send(&mut self, _: &[AnalyticsEvent]) -> std::result::Result<(), DataError> {
+            self.0.set(self.0.get() + 1);
+            Err(DataError::Invalid("collector is down".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_failing_sink_waits_for_the_interval() {
+        let attempts = Rc::new(std::cell::Cell::new(0));
+        let analytics = Analytics::new(Down(attempts.clone()));
+        analytics.set_batch_size(1);
+        analytics.set_flush_interval(5.0);
+        for _ in 0..3 {
+            analytics.track("tick", BTreeMap::new());
+        }
+        assert_eq!((attempts.get(), analytics.buffered()), (1, 3));
+        analytics.update(5.0);
+        assert_eq!(attempts.get(), 2);
+    }
+
+    #[test]
+    fn test_opt_out_is_saved_and_kept_from_mods() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("ee_analytics_opt_{}", std::process::id()));
+        let path = dir.join("settings.json");
+        let _ = std::fs::remove_file(&path);
+        let analytics = Analytics::new(FileSink::new(dir.join("events.jsonl")));
+        analytics.persist_opt_out(KvStore::open(&path).unwrap());
+        analytics.set_opt_out(true).unwrap();
+        let next_run = Analytics::new(FileSink::new(dir.join("events.jsonl")));
+        next_run.persist_opt_out(KvStore::open(&path).unwrap());
+        assert!(next_run.opted_out());
+
+        let lua = Lua::new();
+        next_run.register_lua(&lua)?;
+        let manifest =
+            crate::sandbox::ModManifest::from_ron(r#"(name: "stats", capabilities: [network])"#)
+                .unwrap();
+        let env = crate::sandbox::mod_environment(&lua, &manifest)?;
+        let changed: bool = lua
+            .load("return pcall(analytics.set_opt_out, analytics, false)")
+            .set_environment(env)
+            .eval()?;
+        assert!(!changed && next_run.opted_out());
+        lua.load("analytics:set_opt_out(false)").exec()?;
+        assert!(!next_run.opted_out());
+        let _ = std::fs::remove_dir_all(&dir);
+        Ok(())
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_sink_checks_the_allowlist_and_reports_failures() {
+        let sandbox = SandboxConfig {
+            http_allowlist: vec!["127.0.0.1".to_string()],
+        };
+        assert!(HttpSink::new("https://collector.example/events", &sandbox).is_err());
+
+        // Nothing listens on a port just released.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut sink =
+            HttpSink::new(&format!("http://127.0.0.1:{}/events", port), &sandbox).unwrap();
+        assert!(sink.send(&[]).is_ok());
+        let reported = (0..200).any(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            sink.failures.try_recv().is_ok()
+        });
+        assert!(reported);
+    }
+}
@@ -0,0 +1,261 @@
+use mlua::{AnyUserData, Error, Lua, MultiValue, ObjectLike, Result, Table, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Version of the Lua API, bumped whenever a binding is renamed or changes
+/// meaning. Scripts read it as `api.version` and guard with
+/// `api.require(n)`.
+pub const API_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deprecation {
+    /// `Color.from_hex` for functions, `world:old_name` for world methods.
+    pub name: String,
+    pub replacement: String,
+    /// API version that deprecated it.
+    pub since: u32,
+}
+
+/// The first use of a deprecated binding this session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeprecatedUse {
+    pub name: String,
+    /// `source:line` of the calling script.
+    pub location: String,
+}
+
+#[derive(Default)]
+struct ApiState {
+    deprecations: BTreeMap<String, Deprecation>,
+    /// Old world method names to new ones.
+    world_aliases: BTreeMap<String, String>,
+    warned: BTreeSet<String>,
+    uses: Vec<DeprecatedUse>,
+}
+
+fn with_state<R>(lua: &Lua, f: impl FnOnce(&mut ApiState) -> R) -> R {
+    if lua.app_data_ref::<ApiState>().is_none() {
+        lua.set_app_data(ApiState::default());
+    }
+    let mut state = lua
+        .app_data_mut::<ApiState>()
+        .expect("api state was just set");
+    f(&mut state)
+}
+
+/// `source:line` of the nearest script frame, skipping native frames and
+/// mlua's own `__index` glue.
+fn caller(lua: &Lua) -> String {
+    for level in 1.. {
+        let found = lua.inspect_stack(level, |debug| {
+            let source = debug.source();
+            let name = source.source.as_deref().unwrap_or("?");
+            let name = name.trim_start_matches(['@', '=']);
+            if source.what == "C" || name.starts_with("__mlua") {
+                return None;
+            }
+            Some(format!("{}:{}", name, debug.current_line().unwrap_or(0)))
+        });
+        match found {
+            Some(Some(location)) => return location,
+            Some(None) => continue,
+            None => break,
+        }
+    }
+    "?".to_string()
+}
+
+/// Logs the first use of `name` this session; later uses stay quiet.
+fn warn_once(lua: &Lua, name: &str) {
+    let location = caller(lua);
+    with_state(lua, |state| {
+        if !state.warned.insert(name.to_string()) {
+            return;
+        }
+        if let Some(deprecation) = state.deprecations.get(name) {
+            log::warn!(
+                "{}: {} is deprecated since API {}, use {}",
+                location,
+                name,
+                deprecation.since,
+                deprecation.replacement
+            );
+        }
+        state.uses.push(DeprecatedUse {
+            name: name.to_string(),
+            location,
+        });
+    });
+}
+
+fn lookup(lua: &Lua, path: &str) -> Result<Value> {
+    let mut value = Value::Table(lua.globals());
+    for part in path.split('.') {
+        value = match value {
+            Value::Table(table) => table.get(part)?,
+            _ => return Ok(Value::Nil),
+        };
+    }
+    Ok(value)
+}
+
+/// Keeps `old`, a dotted global path such as `Color.from_hex`, calling
+/// `new` with a warning. `new` is looked up on each call, so it may be
+/// registered later.
+pub fn deprecate_function(lua: &Lua, old: &str, new: &str, since: u32) -> Result<()> {
+    let (parent, field) = match old.rsplit_once('.') {
+        Some((parent, field)) => (Some(parent), field),
+        None => (None, old),
+    };
+    let mut table = lua.globals();
+    for part in parent.into_iter().flat_map(|parent| parent.split('.')) {
+        table = match table.get::<Option<Table>>(part)? {
+            Some(inner) => inner,
+            None => {
+                let inner = lua.create_table()?;
+                table.set(part, &inner)?;
+                inner
+            }
+        };
+    }
+    let (name, target) = (old.to_string(), new.to_string());
+    let alias = lua.create_function(move |lua, args: MultiValue| {
+        warn_once(lua, &name);
+        match lookup(lua, &target)? {
+            Value::Function(function) => function.call::<MultiValue>(args),
+            _ => Err(Error::RuntimeError(format!(
+                "{} was replaced by {}, which does not exist",
+                name, target
+            ))),
+        }
+    })?;
+    table.set(field, alias)?;
+    record(lua, old, new, since);
+    Ok(())
+}
+
+/// Keeps `world:old(...)` working as `world:new(...)`, with a warning.
+pub fn deprecate_world_method(lua: &Lua, old: &str, new: &str, since: u32) {
+    let name = format!("world:{}", old);
+    record(lua, &name, &format!("world:{}", new), since);
+    with_state(lua, |state| {
+        state.world_aliases.insert(old.to_string(), new.to_string())
+    });
+}
+
+fn record(lua: &Lua, name: &str, replacement: &str, since: u32) {
+    let deprecation = Deprecation {
+        name: name.to_string(),
+        replacement: replacement.to_string(),
+        since,
+    };
+    with_state(lua, |state| {
+        state.deprecations.insert(name.to_string(), deprecation)
+    });
+}
+
+/// Resolves a world method missing from the bindings through the aliases;
+/// the world's `__index` falls back to this.
+pub(crate) fn world_alias(lua: &Lua, world: &AnyUserData, key: &str) -> Result<Value> {
+    let Some(new) = with_state(lua, |state| state.world_aliases.get(key).cloned()) else {
+        return Ok(Value::Nil);
+    };
+    warn_once(lua, &format!("world:{}", key));
+    world.get(new)
+}
+
+pub fn deprecations(lua: &Lua) -> Vec<Deprecation> {
+    with_state(lua, |state| state.deprecations.values().cloned().collect())
+}
+
+/// Deprecated bindings scripts have used this session, first use each.
+pub fn deprecated_uses(lua: &Lua) -> Vec<DeprecatedUse> {
+    with_state(lua, |state| state.uses.clone())
+}
+
+/// Adds the `api` global: `version`, `require(n)` failing on engines older
+/// than `n`, and `deprecated()` listing `{name, replacement, since}`.
+pub fn register(lua: &Lua) -> Result<()> {
+    let api = lua.create_table()?;
+    api.set("version", API_VERSION)?;
+    api.set(
+        "require",
+        lua.create_function(|_, version: u32| {
+            if version > API_VERSION {
+                return Err(Error::RuntimeError(format!(
+                    "script needs API {} but the engine provides {}",
+                    version, API_VERSION
+                )));
+            }
+            Ok(())
+        })?,
+    )?;
+    api.set(
+        "deprecated",
+        lua.create_function(|lua, ()| {
+            let list = lua.create_table()?;
+            for (i, deprecation) in deprecations(lua).into_iter().enumerate() {
+                let entry = lua.create_table()?;
+                entry.set("name", deprecation.name)?;
+                entry.set("replacement", deprecation.replacement)?;
+                entry.set("since", deprecation.since)?;
+                list.set(i + 1, entry)?;
+            }
+            Ok(list)
+        })?,
+    )?;
+    lua.globals().set("api", api)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::World;
+
+    #[test]
+    fn test_aliases_warn_once_with_caller() -> Result<()> {
+        let lua = Lua::new();
+        crate::register(&lua)?;
+        deprecate_function(&lua, "Color.from_hex", "Color.hex", 1)?;
+        deprecate_world_method(&lua, "create", "spawn", 1);
+
+        let mut world = World::new();
+        let alive: bool = lua.scope(|scope| {
+            let world = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r##"
+                local world = ...
+                api.require(1)
+                assert(not pcall(api.require, api.version + 1))
+                local red = Color.to_hex(Color.from_hex("#ff0000"))
+                assert(red == "#ff0000", red)
+                Color.from_hex("#00ff00")
+                local e = world:create({})
+                world:create({})
+                assert(world.missing == nil)
+                return world:is_alive(e)
+            "##,
+            )
+            .set_name("mods/old.lua")
+            .call(world)
+        })?;
+        assert!(alive);
+        assert_eq!(world.len(), 2);
+        let uses = deprecated_uses(&lua);
+        assert_eq!(
+            uses,
+            vec![
+                DeprecatedUse {
+                    name: "Color.from_hex".to_string(),
+                    location: "mods/old.lua:5".to_string(),
+                },
+                DeprecatedUse {
+                    name: "world:create".to_string(),
+                    location: "mods/old.lua:8".to_string(),
+                },
+            ]
+        );
+        let listed: usize = lua.load("return #api.deprecated()").eval()?;
+        assert_eq!(listed, 2);
+        Ok(())
+    }
+}
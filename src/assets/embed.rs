@@ -0,0 +1,84 @@
+//! Compiling an asset directory into the executable. A game's build
+//! script writes the file list:
+//!
+//! ```ignore
+//! // build.rs
+//! entity_engine::assets::embed::generate("assets", "assets.rs").unwrap();
+//! ```
+//!
+//! and the game mounts it, usually below any pack or loose directory:
+//!
+//! ```ignore
+//! vfs.mount("embedded", -10, entity_engine::embedded_assets!("assets.rs"));
+//! ```
+
+use super::walk;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The source of an expression listing every file below `dir` as
+/// `(path, include_bytes!(absolute path))`, sorted by path.
+pub fn listing(dir: impl AsRef<Path>) -> io::Result<(String, Vec<PathBuf>)> {
+    let dir = fs::canonicalize(dir)?;
+    let mut found = Vec::new();
+    walk(&dir, "", &mut found)?;
+    found.sort();
+    let mut source = String::from("{\n    const FILES: &[(&str, &[u8])] = &[\n");
+    for (name, path) in &found {
+        let _ = writeln!(
+            source,
+            "        ({:?}, include_bytes!({:?})),",
+            name,
+            path.to_string_lossy()
+        );
+    }
+    source.push_str("    ];\n    FILES\n}\n");
+    Ok((source, found.into_iter().map(|(_, path)| path).collect()))
+}
+
+/// For build scripts: writes the listing of `dir` to `file` under
+/// `OUT_DIR`, and asks cargo to rerun when the directory changes.
+pub fn generate(dir: impl AsRef<Path>, file: &str) -> io::Result<()> {
+    let out = std::env::var_os("OUT_DIR")
+        .ok_or_else(|| io::Error::other("OUT_DIR is only set for build scripts"))?;
+    let (source, files) = listing(&dir)?;
+    println!("cargo:rerun-if-changed={}", dir.as_ref().display());
+    for file in files {
+        println!("cargo:rerun-if-changed={}", file.display());
+    }
+    fs::write(Path::new(&out).join(file), source)
+}
+
+/// An `EmbeddedSource` over a listing written by `embed::generate`.
+#[macro_export]
+macro_rules! embedded_assets {
+    ($file:literal) => {
+        $crate::assets::EmbeddedSource::new(include!(concat!(env!("OUT_DIR"), "/", $file)))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_includes_every_file() {
+        let dir = std::env::temp_dir().join(format!("embed_{}", std::process::id()));
+        fs::create_dir_all(dir.join("scripts")).unwrap();
+        fs::write(dir.join("scripts/main.lua"), "").unwrap();
+        fs::write(dir.join("icon \"1\".png"), "").unwrap();
+
+        let (source, files) = listing(&dir).unwrap();
+        assert_eq!(files.len(), 2);
+        let main = fs::canonicalize(dir.join("scripts/main.lua")).unwrap();
+        assert!(source.contains(&format!(
+            "(\"scripts/main.lua\", include_bytes!({:?})),",
+            main.to_string_lossy()
+        )));
+        assert!(source.contains("(\"icon \\\"1\\\".png\", "));
+        assert!(source.find("icon").unwrap() < source.find("scripts/main.lua").unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,134 @@
+#[cfg(feature = "embed")]
+pub mod embed;
+mod pack;
+mod vfs;
+
+pub use pack::{PackBuilder, PackSource};
+pub use vfs::{EmbeddedSource, MemorySource, Vfs};
+
+use crate::data::DataError;
+use mlua::Lua;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Somewhere assets are read from by `/`-separated path, so loaders don't
+/// care whether files are loose on disk or shipped in a pack.
+pub trait AssetSource: Send + Sync {
+    fn read(&self, path: &str) -> Result<Vec<u8>, DataError>;
+    fn exists(&self, path: &str) -> bool;
+    /// Every asset path, sorted.
+    fn list(&self) -> Vec<String>;
+
+    fn read_to_string(&self, path: &str) -> Result<String, DataError> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|_| DataError::Invalid(format!("'{}' is not UTF-8", path)))
+    }
+}
+
+/// Lets a source be mounted while a handle to it is kept, e.g. to keep
+/// writing to a `MemorySource`.
+impl<T: AssetSource + ?Sized> AssetSource for Arc<T> {
+    fn read(&self, path: &str) -> Result<Vec<u8>, DataError> {
+        (**self).read(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        (**self).exists(path)
+    }
+
+    fn list(&self) -> Vec<String> {
+        (**self).list()
+    }
+}
+
+/// Loose files below a directory.
+#[derive(Debug, Clone)]
+pub struct DirSource {
+    root: PathBuf,
+}
+
+impl DirSource {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        DirSource { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Refuses paths climbing out of the root.
+    pub(crate) fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let escapes = path
+            .split(['/', '\\'])
+            .any(|part| part == ".." || part.contains(':'));
+        (!escapes && !path.starts_with('/')).then(|| self.root.join(path))
+    }
+}
+
+/// Files below `dir` as `prefix` plus their `/`-separated relative paths.
+pub(crate) fn walk(
+    dir: &Path,
+    prefix: &str,
+    found: &mut Vec<(String, PathBuf)>,
+) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = format!(
+            "{}{}",
+            prefix,
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        if path.is_dir() {
+            walk(&path, &format!("{}/", name), found)?;
+        } else {
+            found.push((name, path));
+        }
+    }
+    Ok(())
+}
+
+impl AssetSource for DirSource {
+    fn read(&self, path: &str) -> Result<Vec<u8>, DataError> {
+        let resolved = self
+            .resolve(path)
+            .ok_or_else(|| DataError::Invalid(format!("'{}' is outside the asset root", path)))?;
+        Ok(fs::read(resolved)?)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.resolve(path).is_some_and(|path| path.is_file())
+    }
+
+    fn list(&self) -> Vec<String> {
+        let mut found = Vec::new();
+        // An unreadable root lists as empty.
+        let _ = walk(&self.root, "", &mut found);
+        let mut names: Vec<String> = found.into_iter().map(|(name, _)| name).collect();
+        names.sort();
+        names
+    }
+}
+
+/// What `set_lua_source` installed.
+struct LuaSource(Arc<dyn AssetSource>);
+
+/// Makes `assets.load` and the Lua-side `load` methods (`dialogue:load`,
+/// `quests:load`, `timeline:load`, `loot:load`, `world:load_items`) read
+/// through `source`, usually the game's `Vfs`. Until one is set they read
+/// loose files.
+pub fn set_lua_source(lua: &Lua, source: Arc<dyn AssetSource>) {
+    lua.set_app_data(LuaSource(source));
+}
+
+pub(crate) fn lua_source(lua: &Lua) -> Option<Arc<dyn AssetSource>> {
+    lua.app_data_ref::<LuaSource>()
+        .map(|source| source.0.clone())
+}
+
+pub(crate) fn read_lua_asset(lua: &Lua, path: &str) -> Result<String, DataError> {
+    match lua_source(lua) {
+        Some(source) => source.read_to_string(path),
+        None => Ok(fs::read_to_string(path)?),
+    }
+}
@@ -0,0 +1,310 @@
+//! Pack archives: a header and index up front, then each file's bytes,
+//! zstd-compressed one by one so any file can be read without the rest.
+
+use super::{AssetSource, walk};
+use crate::content::content_hash;
+use crate::data::DataError;
+use crate::scene::binary::{compress_body, decompress_body};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+
+pub const MAGIC: &[u8; 4] = b"EEPK";
+const VERSION: u8 = 1;
+const FLAG_ZSTD: u8 = 1;
+
+#[derive(Debug, Clone, Copy)]
+struct PackEntry {
+    /// From the start of the archive.
+    offset: u64,
+    stored: u64,
+    flags: u8,
+    /// `content_hash` of the uncompressed bytes.
+    hash: u64,
+}
+
+/// Collects files for `write`, keyed by asset path.
+#[derive(Debug, Clone, Default)]
+pub struct PackBuilder {
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl PackBuilder {
+    pub fn new() -> Self {
+        PackBuilder::default()
+    }
+
+    pub fn add(&mut self, path: &str, bytes: Vec<u8>) {
+        self.files.insert(path.to_string(), bytes);
+    }
+
+    /// Every file below `dir`, at `prefix` plus its relative path.
+    pub fn add_dir(&mut self, prefix: &str, dir: impl AsRef<Path>) -> Result<usize, DataError> {
+        let mut found = Vec::new();
+        walk(dir.as_ref(), prefix, &mut found)?;
+        let added = found.len();
+        for (name, path) in found {
+            self.files.insert(name, fs::read(path)?);
+        }
+        Ok(added)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Files that don't shrink are stored as they are.
+    pub fn write(&self, compress: bool) -> Result<Vec<u8>, DataError> {
+        let mut bodies = Vec::with_capacity(self.files.len());
+        for bytes in self.files.values() {
+            let packed = if compress {
+                Some(compress_body(bytes)?).filter(|packed| packed.len() < bytes.len())
+            } else {
+                None
+            };
+            bodies.push(match packed {
+                Some(packed) => (FLAG_ZSTD, packed),
+                None => (0, bytes.clone()),
+            });
+        }
+
+        let index_len: usize = self
+            .files
+            .keys()
+            .map(|name| 4 + name.len() + 8 + 8 + 1 + 8)
+            .sum();
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&(self.files.len() as u32).to_le_bytes());
+        let mut offset = (out.len() + index_len) as u64;
+        for ((name, bytes), (flags, body)) in self.files.iter().zip(&bodies) {
+            out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&offset.to_le_bytes());
+            out.extend_from_slice(&(body.len() as u64).to_le_bytes());
+            out.push(*flags);
+            out.extend_from_slice(&content_hash(bytes).to_le_bytes());
+            offset += body.len() as u64;
+        }
+        for (_, body) in &bodies {
+            out.extend_from_slice(body);
+        }
+        Ok(out)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>, compress: bool) -> Result<(), DataError> {
+        Ok(fs::write(path, self.write(compress)?)?)
+    }
+}
+
+enum Storage {
+    Bytes(Vec<u8>),
+    File(Mutex<File>),
+}
+
+/// An `AssetSource` reading from a pack, either held in memory or read
+/// from disk on demand. Reads check each file against its stored hash.
+pub struct PackSource {
+    index: BTreeMap<String, PackEntry>,
+    storage: Storage,
+}
+
+fn truncated() -> DataError {
+    DataError::Invalid("pack is truncated".to_string())
+}
+
+/// Every name and body must fit in the `len` bytes of the archive, so a
+/// corrupt index can't ask for more than the file holds.
+fn read_index(
+    reader: &mut impl Read,
+    archive_len: u64,
+) -> Result<BTreeMap<String, PackEntry>, DataError> {
+    let mut header = [0; 9];
+    reader.read_exact(&mut header).map_err(|_| truncated())?;
+    if &header[..4] != MAGIC {
+        return Err(DataError::Invalid("not a pack archive".to_string()));
+    }
+    if header[4] != VERSION {
+        return Err(DataError::Invalid(format!(
+            "unsupported pack version {}",
+            header[4]
+        )));
+    }
+    let count = u32::from_le_bytes(header[5..9].try_into().expect("four bytes"));
+    let u64_at = |reader: &mut dyn Read| -> Result<u64, DataError> {
+        let mut bytes = [0; 8];
+        reader.read_exact(&mut bytes).map_err(|_| truncated())?;
+        Ok(u64::from_le_bytes(bytes))
+    };
+    let mut index = BTreeMap::new();
+    for _ in 0..count {
+        let mut len = [0; 4];
+        reader.read_exact(&mut len).map_err(|_| truncated())?;
+        let name_len = u32::from_le_bytes(len);
+        if u64::from(name_len) > archive_len {
+            return Err(truncated());
+        }
+        let mut name = vec![0; name_len as usize];
+        reader.read_exact(&mut name).map_err(|_| truncated())?;
+        let name = String::from_utf8(name)
+            .map_err(|_| DataError::Invalid("pack entry name is not UTF-8".to_string()))?;
+        let offset = u64_at(reader)?;
+        let stored = u64_at(reader)?;
+        let mut flags = [0; 1];
+        reader.read_exact(&mut flags).map_err(|_| truncated())?;
+        let hash = u64_at(reader)?;
+        if offset
+            .checked_add(stored)
+            .is_none_or(|end| end > archive_len)
+        {
+            return Err(truncated());
+        }
+        index.insert(
+            name,
+            PackEntry {
+                offset,
+                stored,
+                flags: flags[0],
+                hash,
+            },
+        );
+    }
+    Ok(index)
+}
+
+impl PackSource {
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, DataError> {
+        let index = read_index(&mut bytes.as_slice(), bytes.len() as u64)?;
+        Ok(PackSource {
+            index,
+            storage: Storage::Bytes(bytes),
+        })
+    }
+
+    /// Reads only the index now; files are read as they are asked for.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, DataError> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let index = read_index(&mut std::io::BufReader::new(&mut file), len)?;
+        Ok(PackSource {
+            index,
+            storage: Storage::File(Mutex::new(file)),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// The stored hash, for integrity manifests, without reading the file.
+    pub fn hash(&self, path: &str) -> Option<u64> {
+        self.index.get(path).map(|entry| entry.hash)
+    }
+}
+
+impl AssetSource for PackSource {
+    fn read(&self, path: &str) -> Result<Vec<u8>, DataError> {
+        let entry = self
+            .index
+            .get(path)
+            .ok_or_else(|| DataError::Invalid(format!("'{}' is not in the pack", path)))?;
+        let stored = match &self.storage {
+            Storage::Bytes(bytes) => {
+                let start = usize::try_from(entry.offset).map_err(|_| truncated())?;
+                let end = usize::try_from(entry.stored)
+                    .ok()
+                    .and_then(|stored| start.checked_add(stored))
+                    .ok_or_else(truncated)?;
+                bytes.get(start..end).ok_or_else(truncated)?.to_vec()
+            }
+            Storage::File(file) => {
+                let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+                file.seek(SeekFrom::Start(entry.offset))?;
+                let mut stored = vec![0; usize::try_from(entry.stored).map_err(|_| truncated())?];
+                file.read_exact(&mut stored).map_err(|_| truncated())?;
+                stored
+            }
+        };
+        let bytes = if entry.flags & FLAG_ZSTD != 0 {
+            decompress_body(&stored)?
+        } else {
+            stored
+        };
+        if content_hash(&bytes) != entry.hash {
+            return Err(DataError::Invalid(format!(
+                "'{}' is corrupt in the pack",
+                path
+            )));
+        }
+        Ok(bytes)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.index.contains_key(path)
+    }
+
+    fn list(&self) -> Vec<String> {
+        self.index.keys().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_round_trip() {
+        let dir = std::env::temp_dir().join(format!("pack_{}", std::process::id()));
+        fs::create_dir_all(dir.join("scripts")).unwrap();
+        fs::write(dir.join("scripts/main.lua"), "print('hi')\n".repeat(50)).unwrap();
+        fs::write(dir.join("level.ron"), "()").unwrap();
+
+        let mut builder = PackBuilder::new();
+        assert_eq!(builder.add_dir("", &dir).unwrap(), 2);
+        builder.add("empty.txt", Vec::new());
+        let compress = cfg!(feature = "zstd");
+        let bytes = builder.write(compress).unwrap();
+        let path = dir.join("game.pak");
+        builder.save(&path, compress).unwrap();
+
+        let in_memory = PackSource::from_bytes(bytes.clone()).unwrap();
+        let on_disk = PackSource::open(&path).unwrap();
+        for pack in [&in_memory as &dyn AssetSource, &on_disk] {
+            assert_eq!(pack.list(), ["empty.txt", "level.ron", "scripts/main.lua"]);
+            assert_eq!(pack.read_to_string("level.ron").unwrap(), "()");
+            assert_eq!(pack.read("scripts/main.lua").unwrap().len(), 600);
+            assert!(pack.read("missing").is_err());
+        }
+        if compress {
+            assert!(bytes.len() < 600);
+        }
+
+        let mut corrupt = bytes.clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xff;
+        let corrupt = PackSource::from_bytes(corrupt).unwrap();
+        assert!(corrupt.read("scripts/main.lua").is_err());
+
+        // An index pointing past the end is refused before anything is
+        // read. The first entry is `empty.txt`: its name length at 9, then
+        // the name, offset and stored size.
+        let mut huge_name = bytes.clone();
+        huge_name[9..13].fill(0xff);
+        assert!(PackSource::from_bytes(huge_name).is_err());
+        let mut huge_body = bytes;
+        huge_body[30..38].fill(0xff);
+        assert!(PackSource::from_bytes(huge_body).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+use super::{AssetSource, DirSource, PackSource};
+use crate::data::DataError;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::RwLock;
+
+/// Files held in memory, for generated assets and tests. Mount it as an
+/// `Arc` to keep writing to it after mounting.
+#[derive(Debug, Default)]
+pub struct MemorySource {
+    files: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        MemorySource::default()
+    }
+
+    pub fn insert(&self, path: &str, bytes: impl Into<Vec<u8>>) {
+        self.files
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(path.to_string(), bytes.into());
+    }
+
+    pub fn remove(&self, path: &str) -> Option<Vec<u8>> {
+        self.files
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(path)
+    }
+}
+
+impl AssetSource for MemorySource {
+    fn read(&self, path: &str) -> Result<Vec<u8>, DataError> {
+        let files = self.files.read().unwrap_or_else(|e| e.into_inner());
+        files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| DataError::Invalid(format!("'{}' is not in memory", path)))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        let files = self.files.read().unwrap_or_else(|e| e.into_inner());
+        files.contains_key(path)
+    }
+
+    fn list(&self) -> Vec<String> {
+        let files = self.files.read().unwrap_or_else(|e| e.into_inner());
+        files.keys().cloned().collect()
+    }
+}
+
+/// Files compiled into the executable, usually with `include_bytes!`:
+///
+/// ```ignore
+/// EmbeddedSource::new(&[("scripts/main.lua", include_bytes!("main.lua"))])
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddedSource {
+    files: &'static [(&'static str, &'static [u8])],
+}
+
+impl EmbeddedSource {
+    pub const fn new(files: &'static [(&'static str, &'static [u8])]) -> Self {
+        EmbeddedSource { files }
+    }
+
+    pub fn get(&self, path: &str) -> Option<&'static [u8]> {
+        self.files
+            .iter()
+            .find(|(name, _)| *name == path)
+            .map(|(_, bytes)| *bytes)
+    }
+}
+
+impl AssetSource for EmbeddedSource {
+    fn read(&self, path: &str) -> Result<Vec<u8>, DataError> {
+        self.get(path)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| DataError::Invalid(format!("'{}' is not embedded", path)))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.get(path).is_some()
+    }
+
+    fn list(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .files
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+struct Layer {
+    name: String,
+    priority: i32,
+    source: Box<dyn AssetSource>,
+}
+
+/// Asset I/O over mounted layers. A read goes to the highest-priority
+/// layer that has the path, with later mounts winning ties, so a loose
+/// directory mounted above a pack shadows its files. Paths are
+/// `/`-separated and relative; a leading `./` or `/` is ignored.
+#[derive(Default)]
+pub struct Vfs {
+    /// Highest priority first.
+    layers: Vec<Layer>,
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Vfs::default()
+    }
+
+    /// The usual setup under `root`: the pack at `pack` when it exists,
+    /// and in debug builds the loose directory above it, so edits show up
+    /// without repacking while release builds only read the pack. Release
+    /// builds without a pack fall back to the directory.
+    pub fn standard(root: impl AsRef<Path>, pack: impl AsRef<Path>) -> Result<Self, DataError> {
+        let mut vfs = Vfs::new();
+        let pack = pack.as_ref();
+        let has_pack = pack.is_file();
+        if has_pack {
+            vfs.mount("pack", 0, PackSource::open(pack)?);
+        }
+        if cfg!(debug_assertions) || !has_pack {
+            vfs.mount("dir", 10, DirSource::new(root.as_ref()));
+        }
+        Ok(vfs)
+    }
+
+    /// Replaces any layer already mounted under `name`.
+    pub fn mount(&mut self, name: &str, priority: i32, source: impl AssetSource + 'static) {
+        self.unmount(name);
+        let at = self
+            .layers
+            .iter()
+            .position(|layer| layer.priority <= priority)
+            .unwrap_or(self.layers.len());
+        self.layers.insert(
+            at,
+            Layer {
+                name: name.to_string(),
+                priority,
+                source: Box::new(source),
+            },
+        );
+    }
+
+    pub fn unmount(&mut self, name: &str) -> bool {
+        let before = self.layers.len();
+        self.layers.retain(|layer| layer.name != name);
+        self.layers.len() != before
+    }
+
+    /// Layer names, highest priority first.
+    pub fn layers(&self) -> Vec<&str> {
+        self.layers
+            .iter()
+            .map(|layer| layer.name.as_str())
+            .collect()
+    }
+
+    /// Which layer a read of `path` would come from.
+    pub fn layer_of(&self, path: &str) -> Option<&str> {
+        let path = normalize(path);
+        self.layers
+            .iter()
+            .find(|layer| layer.source.exists(path))
+            .map(|layer| layer.name.as_str())
+    }
+}
+
+impl AssetSource for Vfs {
+    fn read(&self, path: &str) -> Result<Vec<u8>, DataError> {
+        let path = normalize(path);
+        match self.layers.iter().find(|layer| layer.source.exists(path)) {
+            Some(layer) => layer.source.read(path),
+            None => Err(DataError::Invalid(format!(
+                "'{}' is not in any mounted layer",
+                path
+            ))),
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.layer_of(path).is_some()
+    }
+
+    fn list(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .layers
+            .iter()
+            .flat_map(|layer| layer.source.list())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::PackBuilder;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_layers_shadow_by_priority() {
+        let mut builder = PackBuilder::new();
+        builder.add("scripts/main.lua", b"return 'pack'".to_vec());
+        builder.add("scenes/arena.ron", b"()".to_vec());
+        let pack = PackSource::from_bytes(builder.write(false).unwrap()).unwrap();
+
+        let mut vfs = Vfs::new();
+        vfs.mount("pack", 0, pack);
+        vfs.mount(
+            "embedded",
+            -10,
+            EmbeddedSource::new(&[("Cargo.toml", include_bytes!("../../Cargo.toml"))]),
+        );
+        let loose = Arc::new(MemorySource::new());
+        vfs.mount("loose", 10, loose.clone());
+        loose.insert("scripts/main.lua", "return 'loose'");
+        assert_eq!(vfs.layers(), ["loose", "pack", "embedded"]);
+
+        assert_eq!(
+            vfs.read_to_string("./scripts/main.lua").unwrap(),
+            "return 'loose'"
+        );
+        assert_eq!(vfs.layer_of("scenes/arena.ron"), Some("pack"));
+        assert!(
+            vfs.read_to_string("Cargo.toml")
+                .unwrap()
+                .contains("[package]")
+        );
+        assert_eq!(
+            vfs.list(),
+            ["Cargo.toml", "scenes/arena.ron", "scripts/main.lua"]
+        );
+        assert!(vfs.read("missing.lua").is_err());
+
+        assert!(vfs.unmount("loose"));
+        assert_eq!(
+            vfs.read_to_string("scripts/main.lua").unwrap(),
+            "return 'pack'"
+        );
+    }
+
+    #[test]
+    fn test_lua_loaders_read_through_the_vfs() -> mlua::Result<()> {
+        let memory = MemorySource::new();
+        memory.insert(
+            "dialogue/hello.ron",
+            r#"{ "hello": (start: "hi", nodes: { "hi": Line(text: "hi") }) }"#,
+        );
+        let mut vfs = Vfs::new();
+        vfs.mount("memory", 0, memory);
+
+        let lua = mlua::Lua::new();
+        crate::assets::set_lua_source(&lua, std::sync::Arc::new(vfs));
+        let dialogue = crate::dialogue::Dialogue::new(Default::default());
+        dialogue.register_lua(&lua)?;
+        lua.load(r#"dialogue:load("dialogue/hello.ron")"#).exec()?;
+        assert!(dialogue.dialogues_mut().get("hello").is_some());
+        assert!(
+            lua.load(r#"dialogue:load("dialogue/missing.ron")"#)
+                .exec()
+                .is_err()
+        );
+        Ok(())
+    }
+}
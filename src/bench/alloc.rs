@@ -0,0 +1,167 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AllocStats {
+    pub allocations: u64,
+    pub deallocations: u64,
+    pub bytes_allocated: u64,
+    /// Highest number of live heap bytes above the starting level.
+    pub peak_bytes: u64,
+    /// Process-wide resident set high-water mark, where the platform reports it.
+    pub peak_rss_kb: Option<u64>,
+}
+
+impl std::fmt::Display for AllocStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} allocs / {} frees, {} bytes allocated, {} bytes peak",
+            self.allocations, self.deallocations, self.bytes_allocated, self.peak_bytes
+        )?;
+        if let Some(rss) = self.peak_rss_kb {
+            write!(f, ", peak RSS {} kB", rss)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `f` and reports the heap traffic it caused. Returns `None` for the
+/// stats unless the `alloc-tracking` feature installed the counting allocator.
+pub fn track<T>(f: impl FnOnce() -> T) -> (T, Option<AllocStats>) {
+    #[cfg(feature = "alloc-tracking")]
+    {
+        let start = counting::begin();
+        let value = f();
+        (value, Some(counting::end(start)))
+    }
+
+    #[cfg(not(feature = "alloc-tracking"))]
+    {
+        (f(), None)
+    }
+}
+
+pub fn peak_rss_kb() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmHWM:"))
+            .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+#[cfg(feature = "alloc-tracking")]
+mod counting {
+    use super::AllocStats;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+    static DEALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+    static BYTES_ALLOCATED: AtomicU64 = AtomicU64::new(0);
+    static LIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+    static PEAK_LIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+    pub struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc(layout) };
+            if !ptr.is_null() {
+                record_alloc(layout.size());
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) };
+            record_dealloc(layout.size());
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc_zeroed(layout) };
+            if !ptr.is_null() {
+                record_alloc(layout.size());
+            }
+            ptr
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+            if !new_ptr.is_null() {
+                record_dealloc(layout.size());
+                record_alloc(new_size);
+            }
+            new_ptr
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAllocator = CountingAllocator;
+
+    fn record_alloc(size: usize) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES_ALLOCATED.fetch_add(size as u64, Ordering::Relaxed);
+        let live = LIVE_BYTES.fetch_add(size as u64, Ordering::Relaxed) + size as u64;
+        PEAK_LIVE_BYTES.fetch_max(live, Ordering::Relaxed);
+    }
+
+    fn record_dealloc(size: usize) {
+        DEALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        LIVE_BYTES.fetch_sub(size as u64, Ordering::Relaxed);
+    }
+
+    pub struct Start {
+        allocations: u64,
+        deallocations: u64,
+        bytes_allocated: u64,
+        live_bytes: u64,
+    }
+
+    pub fn begin() -> Start {
+        let live_bytes = LIVE_BYTES.load(Ordering::Relaxed);
+        PEAK_LIVE_BYTES.store(live_bytes, Ordering::Relaxed);
+        Start {
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+            deallocations: DEALLOCATIONS.load(Ordering::Relaxed),
+            bytes_allocated: BYTES_ALLOCATED.load(Ordering::Relaxed),
+            live_bytes,
+        }
+    }
+
+    pub fn end(start: Start) -> AllocStats {
+        AllocStats {
+            allocations: ALLOCATIONS.load(Ordering::Relaxed) - start.allocations,
+            deallocations: DEALLOCATIONS.load(Ordering::Relaxed) - start.deallocations,
+            bytes_allocated: BYTES_ALLOCATED.load(Ordering::Relaxed) - start.bytes_allocated,
+            peak_bytes: PEAK_LIVE_BYTES
+                .load(Ordering::Relaxed)
+                .saturating_sub(start.live_bytes),
+            peak_rss_kb: super::peak_rss_kb(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_track_reports_only_with_feature() {
+        let (len, stats) = track(|| vec![0u8; 4096].len());
+        assert_eq!(len, 4096);
+
+        if cfg!(feature = "alloc-tracking") {
+            let stats = stats.unwrap();
+            assert!(stats.allocations >= 1);
+            assert!(stats.bytes_allocated >= 4096);
+        } else {
+            assert!(stats.is_none());
+        }
+    }
+}
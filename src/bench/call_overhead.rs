@@ -0,0 +1,258 @@
+use super::alloc::{self, AllocStats};
+use super::{Harness, WarmupReport};
+use mlua::{Function, Lua, Result, Table, Variadic};
+use std::time::{Duration, Instant};
+
+pub const ARG_COUNTS: [usize; 5] = [0, 1, 2, 4, 8];
+const BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Convention {
+    /// Lua calls a Rust function looked up from globals every iteration.
+    Global,
+    /// Lua calls a Rust function stored on a table with `obj:method(...)`.
+    TableMethod,
+    /// Rust holds a `Function` handle to a Lua closure and calls it directly.
+    CachedCall,
+    /// Lua passes whole argument columns to Rust once per batch.
+    BatchedSoa,
+}
+
+impl Convention {
+    pub const ALL: [Convention; 4] = [
+        Convention::Global,
+        Convention::TableMethod,
+        Convention::CachedCall,
+        Convention::BatchedSoa,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Convention::Global => "global function",
+            Convention::TableMethod => "table method",
+            Convention::CachedCall => "cached Function::call",
+            Convention::BatchedSoa => "batched SoA",
+        }
+    }
+}
+
+pub struct Cell {
+    pub convention: Convention,
+    pub args: usize,
+    /// Mean time per logical call (per element for batched calls).
+    pub per_call: Duration,
+    pub allocations: Option<AllocStats>,
+}
+
+fn arg_list(count: usize) -> String {
+    (1..=count)
+        .map(|i| format!("{}.5", i))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn setup(lua: &Lua) -> Result<()> {
+    let sum = lua.create_function(|_, args: Variadic<f64>| Ok(args.iter().sum::<f64>()))?;
+    lua.globals().set("bench_sum", sum)?;
+
+    let api = lua.create_table()?;
+    api.set(
+        "sum",
+        lua.create_function(|_, (_this, args): (Table, Variadic<f64>)| {
+            Ok(args.iter().sum::<f64>())
+        })?,
+    )?;
+    lua.globals().set("bench_api", api)?;
+
+    let batch = lua.create_function(|_, columns: Variadic<Table>| {
+        let columns: Vec<Vec<f64>> = columns
+            .iter()
+            .map(|column| column.sequence_values::<f64>().collect::<Result<_>>())
+            .collect::<Result<_>>()?;
+        let rows = columns.first().map_or(0, Vec::len);
+
+        let mut total = 0.0;
+        for row in 0..rows {
+            total += columns.iter().map(|column| column[row]).sum::<f64>();
+        }
+        Ok(total)
+    })?;
+    lua.globals().set("bench_batch", batch)?;
+
+    Ok(())
+}
+
+pub fn measure(
+    lua: &Lua,
+    convention: Convention,
+    args: usize,
+    iterations: u64,
+) -> Result<Duration> {
+    match convention {
+        Convention::Global => {
+            let script = format!(
+                "for i = 1, {} do bench_sum({}) end",
+                iterations,
+                arg_list(args)
+            );
+            let chunk = lua.load(script).into_function()?;
+            let start = Instant::now();
+            chunk.call::<()>(())?;
+            Ok(start.elapsed())
+        }
+        Convention::TableMethod => {
+            let script = format!(
+                "local api = bench_api for i = 1, {} do api:sum({}) end",
+                iterations,
+                arg_list(args)
+            );
+            let chunk = lua.load(script).into_function()?;
+            let start = Instant::now();
+            chunk.call::<()>(())?;
+            Ok(start.elapsed())
+        }
+        Convention::CachedCall => {
+            let callee: Function = lua
+                .load("return function(...) return select('#', ...) end")
+                .eval()?;
+            let values: Vec<f64> = (1..=args).map(|i| i as f64 + 0.5).collect();
+            let start = Instant::now();
+            for _ in 0..iterations {
+                callee.call::<f64>(Variadic::from_iter(values.iter().copied()))?;
+            }
+            Ok(start.elapsed())
+        }
+        Convention::BatchedSoa => {
+            let batches = (iterations as usize).div_ceil(BATCH_SIZE);
+            let script = format!(
+                r#"
+                local columns = {{}}
+                for c = 1, {args} do
+                    local column = table.create({batch}, c + 0.5)
+                    columns[c] = column
+                end
+                return function()
+                    for b = 1, {batches} do
+                        bench_batch(table.unpack(columns))
+                    end
+                end
+            "#,
+                args = args.max(1),
+                batch = BATCH_SIZE,
+                batches = batches,
+            );
+            let runner: Function = lua.load(script).eval()?;
+            let start = Instant::now();
+            runner.call::<()>(())?;
+            Ok(start
+                .elapsed()
+                .mul_f64(iterations as f64 / (batches * BATCH_SIZE) as f64))
+        }
+    }
+}
+
+fn matrix() -> impl Iterator<Item = (Convention, usize)> {
+    Convention::ALL.into_iter().flat_map(|convention| {
+        ARG_COUNTS
+            .into_iter()
+            // A batch with no columns has nothing to amortize the call over.
+            .filter(move |&args| !(convention == Convention::BatchedSoa && args == 0))
+            .map(move |args| (convention, args))
+    })
+}
+
+pub fn measure_all(harness: &Harness, iterations: u64) -> Result<(WarmupReport, Vec<Cell>)> {
+    let lua = Lua::new();
+    setup(&lua)?;
+
+    let warmup = harness.run_warmup(|_| {
+        for (convention, args) in matrix() {
+            measure(&lua, convention, args, 100)?;
+        }
+        Ok(())
+    })?;
+
+    let mut cells = Vec::new();
+    for (convention, args) in matrix() {
+        let (elapsed, allocations) = alloc::track(|| measure(&lua, convention, args, iterations));
+        cells.push(Cell {
+            convention,
+            args,
+            per_call: elapsed? / iterations.max(1) as u32,
+            allocations,
+        });
+    }
+
+    Ok((warmup, cells))
+}
+
+fn print_matrix(cells: &[Cell], column: impl Fn(&Cell) -> Option<String>) {
+    print!("{:<24}", "convention \\ args");
+    for args in ARG_COUNTS {
+        print!("{:>12}", args);
+    }
+    println!();
+
+    for convention in Convention::ALL {
+        print!("{:<24}", convention.name());
+        for args in ARG_COUNTS {
+            let value = cells
+                .iter()
+                .find(|cell| cell.convention == convention && cell.args == args)
+                .and_then(&column);
+            print!("{:>12}", value.as_deref().unwrap_or("-"));
+        }
+        println!();
+    }
+}
+
+pub fn print(cells: &[Cell], iterations: u64) {
+    print_matrix(cells, |cell| Some(format!("{:.1?}", cell.per_call)));
+
+    if cells.iter().any(|cell| cell.allocations.is_some()) {
+        println!();
+        println!("Allocations per call:");
+        print_matrix(cells, |cell| {
+            cell.allocations
+                .map(|stats| format!("{:.2}", stats.allocations as f64 / iterations.max(1) as f64))
+        });
+    }
+}
+
+pub fn run(harness: &Harness) -> Result<()> {
+    let iterations = 1_000_000;
+    println!(
+        "Measuring Lua/Rust call overhead ({} calls per cell)...",
+        iterations
+    );
+    let (warmup, cells) = measure_all(harness, iterations)?;
+    println!("{}", warmup);
+    print(&cells, iterations);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matrix_covers_every_convention() -> Result<()> {
+        let (warmup, cells) = measure_all(&Harness::new().warmup(1), 2_000)?;
+        assert_eq!(warmup.iterations, 1);
+        assert_eq!(cells.len(), Convention::ALL.len() * ARG_COUNTS.len() - 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_batched_call_sums_columns() -> Result<()> {
+        let lua = Lua::new();
+        setup(&lua)?;
+
+        let total: f64 = lua
+            .load("return bench_batch({1, 2, 3}, {10, 20, 30})")
+            .eval()?;
+        assert_eq!(total, 66.0);
+
+        Ok(())
+    }
+}
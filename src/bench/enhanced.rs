@@ -0,0 +1,216 @@
+use super::Harness;
+use super::alloc::{self, AllocStats};
+use mlua::{Error, FromLua, Function, Lua, Result};
+use std::time::Instant;
+
+pub struct BenchmarkResult {
+    pub result: f64,
+    pub operations: u32,
+    pub max_value: f64,
+    /// Filled in by the harness when allocation tracking is enabled.
+    pub allocations: Option<AllocStats>,
+}
+
+impl FromLua for BenchmarkResult {
+    fn from_lua(value: mlua::Value, _lua: &Lua) -> Result<Self> {
+        match value {
+            mlua::Value::Table(table) => {
+                let result: f64 =
+                    table
+                        .get("result")
+                        .map_err(|e| Error::FromLuaConversionError {
+                            from: "Table",
+                            to: "BenchmarkResult".to_string(),
+                            message: Some(format!("Failed to get 'result' field: {}", e)),
+                        })?;
+
+                let operations: u32 =
+                    table
+                        .get("operations")
+                        .map_err(|e| Error::FromLuaConversionError {
+                            from: "Table",
+                            to: "BenchmarkResult".to_string(),
+                            message: Some(format!("Failed to get 'operations' field: {}", e)),
+                        })?;
+
+                let max_value: f64 =
+                    table
+                        .get("max_value")
+                        .map_err(|e| Error::FromLuaConversionError {
+                            from: "Table",
+                            to: "BenchmarkResult".to_string(),
+                            message: Some(format!("Failed to get 'max_value' field: {}", e)),
+                        })?;
+
+                Ok(BenchmarkResult {
+                    result,
+                    operations,
+                    max_value,
+                    allocations: None,
+                })
+            }
+            _ => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "BenchmarkResult".to_string(),
+                message: Some(
+                    "Expected a table with result, operations, and max_value fields".to_string(),
+                ),
+            }),
+        }
+    }
+}
+
+const SCRIPT: &str = r#"
+    local sum = 0.0
+    local operations = 0
+    local max_value = 0.0
+    local pi = 3.14159265359
+    
+    for i = 1, {iterations} do
+        local angle = (i % 628) * 0.01
+        local radius = 10 + (i % 20)
+        
+        local len = length_fast(radius * 0.5, radius * 0.3)
+        local dist = distance(0, 0, len, angle)
+        local complex = complex_calc(angle, radius)
+        
+        local combined = len + dist + complex
+        sum = sum + combined
+        operations = operations + 3
+        
+        if combined > max_value then
+            max_value = combined
+        end
+        
+        if i % 1000 == 0 then
+            sum = sum * 1.0001
+        end
+    end
+    
+    return { 
+        result = sum,
+        operations = operations,
+        max_value = max_value
+    }
+"#;
+
+pub const ITERATIONS: u32 = 5_000_000;
+
+pub fn setup(lua: &Lua) -> Result<()> {
+    let length_fast: Function =
+        lua.create_function(|_, (x, y): (f64, f64)| Ok((x * x + y * y).sqrt()))?;
+
+    let distance: Function = lua.create_function(|_, (x1, y1, x2, y2): (f64, f64, f64, f64)| {
+        let dx = x2 - x1;
+        let dy = y2 - y1;
+        Ok((dx * dx + dy * dy).sqrt())
+    })?;
+
+    let complex_calc: Function = lua.create_function(|_, (angle, radius): (f64, f64)| {
+        let x = radius * angle.cos();
+        let y = radius * angle.sin();
+        let magnitude = (x * x + y * y).sqrt();
+
+        if magnitude > 10.0 {
+            Ok(magnitude * 1.5 + angle.tan().abs())
+        } else {
+            Ok(magnitude * 0.8 + angle.sin())
+        }
+    })?;
+    lua.globals().set("length_fast", length_fast)?;
+    lua.globals().set("distance", distance)?;
+    lua.globals().set("complex_calc", complex_calc)?;
+
+    Ok(())
+}
+
+pub fn script(iterations: u32) -> String {
+    SCRIPT.replace("{iterations}", &iterations.to_string())
+}
+
+pub fn run(harness: &Harness) -> Result<()> {
+    let lua = Lua::new();
+    setup(&lua)?;
+
+    let warmup = harness.run_warmup(|i| {
+        let angle = i as f64 * 0.001;
+        let _: f64 = lua.load("return length_fast(1, 1)").eval()?;
+        let _: f64 = lua.load("return distance(0, 0, 3, 4)").eval()?;
+        let _: f64 = lua
+            .load(format!("return complex_calc({}, 15)", angle))
+            .eval()?;
+        Ok(())
+    })?;
+
+    let script = script(ITERATIONS);
+
+    println!("Starting enhanced benchmark...");
+    println!("{}", warmup);
+    let start = Instant::now();
+    let (result, allocations) = alloc::track(|| lua.load(script).eval::<BenchmarkResult>());
+    let duration = start.elapsed();
+    let result = BenchmarkResult {
+        allocations,
+        ..result?
+    };
+
+    println!("Result: {}", result.result);
+    println!("Total operations: {}", result.operations);
+    println!("Maximum value encountered: {}", result.max_value);
+    println!("Total time: {:?}", duration);
+    println!("Time per iteration: {:?}", duration / ITERATIONS);
+    println!("Time per operation: {:?}", duration / result.operations);
+    println!(
+        "Iterations per second: {:.0}",
+        ITERATIONS as f64 / duration.as_secs_f64()
+    );
+    println!(
+        "Operations per second: {:.0}",
+        result.operations as f64 / duration.as_secs_f64()
+    );
+    if let Some(allocations) = result.allocations {
+        println!("Allocations: {}", allocations);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lua_functions() -> Result<()> {
+        let lua = Lua::new();
+
+        let length_fast: Function =
+            lua.create_function(|_, (x, y): (f64, f64)| Ok((x * x + y * y).sqrt()))?;
+
+        lua.globals().set("length_fast", length_fast)?;
+
+        let result: f64 = lua.load("return length_fast(3, 4)").eval()?;
+        assert!((result - 5.0).abs() < 1e-10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_benchmark_result_parsing() -> Result<()> {
+        let lua = Lua::new();
+
+        let script = r#"
+            return {
+                result = 42.5,
+                operations = 1000,
+                max_value = 99.9
+            }
+        "#;
+
+        let result: BenchmarkResult = lua.load(script).eval()?;
+        assert_eq!(result.result, 42.5);
+        assert_eq!(result.operations, 1000);
+        assert_eq!(result.max_value, 99.9);
+
+        Ok(())
+    }
+}
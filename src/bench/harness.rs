@@ -0,0 +1,158 @@
+use mlua::Result;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warmup {
+    Iterations(u64),
+    Duration(Duration),
+}
+
+impl From<u64> for Warmup {
+    fn from(iterations: u64) -> Self {
+        Warmup::Iterations(iterations)
+    }
+}
+
+impl From<Duration> for Warmup {
+    fn from(duration: Duration) -> Self {
+        Warmup::Duration(duration)
+    }
+}
+
+/// Parses `5000` as an iteration count and `250ms` / `2s` as a duration.
+impl FromStr for Warmup {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, String> {
+        let invalid = || {
+            format!(
+                "invalid warmup '{}' (expected iterations, or a duration like 500ms or 2s)",
+                value
+            )
+        };
+
+        if let Some(millis) = value.strip_suffix("ms") {
+            let millis = millis.parse().map_err(|_| invalid())?;
+            Ok(Warmup::Duration(Duration::from_millis(millis)))
+        } else if let Some(secs) = value.strip_suffix('s') {
+            let secs: f64 = secs.parse().map_err(|_| invalid())?;
+            Duration::try_from_secs_f64(secs)
+                .map(Warmup::Duration)
+                .map_err(|_| invalid())
+        } else {
+            value.parse().map(Warmup::Iterations).map_err(|_| invalid())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WarmupReport {
+    pub iterations: u64,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for WarmupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Warmup: {} iterations in {:?}",
+            self.iterations, self.elapsed
+        )
+    }
+}
+
+pub struct Harness {
+    warmup: Warmup,
+    /// Size overrides from the command line; scenarios fall back to their
+    /// own defaults.
+    pub entities: Option<usize>,
+    pub frames: Option<u32>,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Harness::new()
+    }
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        Harness {
+            warmup: Warmup::Iterations(0),
+            entities: None,
+            frames: None,
+        }
+    }
+
+    pub fn warmup(mut self, warmup: impl Into<Warmup>) -> Self {
+        self.warmup = warmup.into();
+        self
+    }
+
+    /// Calls `step` with the iteration index until the configured warmup
+    /// budget is spent, so the measured run starts with a hot JIT and caches.
+    pub fn run_warmup(&self, mut step: impl FnMut(u64) -> Result<()>) -> Result<WarmupReport> {
+        let start = Instant::now();
+        let mut iterations = 0;
+
+        match self.warmup {
+            Warmup::Iterations(count) => {
+                while iterations < count {
+                    step(iterations)?;
+                    iterations += 1;
+                }
+            }
+            Warmup::Duration(budget) => {
+                while start.elapsed() < budget {
+                    step(iterations)?;
+                    iterations += 1;
+                }
+            }
+        }
+
+        Ok(WarmupReport {
+            iterations,
+            elapsed: start.elapsed(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warmup_parsing() {
+        assert_eq!("5000".parse(), Ok(Warmup::Iterations(5000)));
+        assert_eq!(
+            "250ms".parse(),
+            Ok(Warmup::Duration(Duration::from_millis(250)))
+        );
+        assert_eq!(
+            "1.5s".parse(),
+            Ok(Warmup::Duration(Duration::from_millis(1500)))
+        );
+        assert!("soon".parse::<Warmup>().is_err());
+    }
+
+    #[test]
+    fn test_warmup_runs_configured_iterations() -> Result<()> {
+        let mut seen = Vec::new();
+        let report = Harness::new().warmup(3).run_warmup(|i| {
+            seen.push(i);
+            Ok(())
+        })?;
+        assert_eq!(report.iterations, 3);
+        assert_eq!(seen, vec![0, 1, 2]);
+
+        let report = Harness::new()
+            .warmup(Duration::from_millis(5))
+            .run_warmup(|_| Ok(()))?;
+        assert!(report.iterations > 0);
+        assert!(report.elapsed >= Duration::from_millis(5));
+
+        Ok(())
+    }
+}
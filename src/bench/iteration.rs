@@ -0,0 +1,245 @@
+use super::Harness;
+use super::alloc::{self, AllocStats};
+use mlua::{Function, Lua, Result, Table};
+use std::time::{Duration, Instant};
+
+const DT: f64 = 1.0 / 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Position {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Velocity {
+    x: f64,
+    y: f64,
+}
+
+fn spawn(count: usize) -> (Vec<Position>, Vec<Velocity>) {
+    let positions = (0..count)
+        .map(|i| Position {
+            x: i as f64,
+            y: (i % 100) as f64,
+        })
+        .collect();
+    let velocities = (0..count)
+        .map(|i| Velocity {
+            x: 1.0 + (i % 7) as f64,
+            y: -1.0 + (i % 3) as f64,
+        })
+        .collect();
+    (positions, velocities)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Plain Rust loop over the position/velocity columns.
+    RustQuery,
+    /// Rust iterates and calls a Lua function once per entity.
+    LuaPerEntity,
+    /// Columns are handed to Lua once per frame and updated in a Lua loop.
+    BatchedView,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 3] = [
+        Strategy::RustQuery,
+        Strategy::LuaPerEntity,
+        Strategy::BatchedView,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::RustQuery => "rust query",
+            Strategy::LuaPerEntity => "lua per-entity callback",
+            Strategy::BatchedView => "lua batched view",
+        }
+    }
+}
+
+pub struct Measurement {
+    pub strategy: Strategy,
+    pub elapsed: Duration,
+    pub entity_updates: u64,
+    pub allocations: Option<AllocStats>,
+}
+
+impl Measurement {
+    pub fn updates_per_second(&self) -> f64 {
+        self.entity_updates as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+const SCRIPTS: &str = r#"
+    function update_entity(x, y, vx, vy, dt)
+        return x + vx * dt, y + vy * dt
+    end
+
+    function update_batch(xs, ys, vxs, vys, n, dt)
+        for i = 1, n do
+            xs[i] = xs[i] + vxs[i] * dt
+            ys[i] = ys[i] + vys[i] * dt
+        end
+    end
+"#;
+
+fn run_rust(positions: &mut [Position], velocities: &[Velocity], frames: u32) {
+    for _ in 0..frames {
+        for (position, velocity) in positions.iter_mut().zip(velocities) {
+            position.x += velocity.x * DT;
+            position.y += velocity.y * DT;
+        }
+    }
+}
+
+fn run_per_entity(
+    lua: &Lua,
+    positions: &mut [Position],
+    velocities: &[Velocity],
+    frames: u32,
+) -> Result<()> {
+    let update: Function = lua.globals().get("update_entity")?;
+    for _ in 0..frames {
+        for (position, velocity) in positions.iter_mut().zip(velocities) {
+            let (x, y): (f64, f64) =
+                update.call((position.x, position.y, velocity.x, velocity.y, DT))?;
+            position.x = x;
+            position.y = y;
+        }
+    }
+    Ok(())
+}
+
+fn run_batched(
+    lua: &Lua,
+    positions: &mut [Position],
+    velocities: &[Velocity],
+    frames: u32,
+) -> Result<()> {
+    let update: Function = lua.globals().get("update_batch")?;
+    let vxs = lua.create_sequence_from(velocities.iter().map(|v| v.x))?;
+    let vys = lua.create_sequence_from(velocities.iter().map(|v| v.y))?;
+
+    for _ in 0..frames {
+        let xs = lua.create_sequence_from(positions.iter().map(|p| p.x))?;
+        let ys = lua.create_sequence_from(positions.iter().map(|p| p.y))?;
+        update.call::<()>((&xs, &ys, &vxs, &vys, positions.len(), DT))?;
+        read_back(&xs, &ys, positions)?;
+    }
+    Ok(())
+}
+
+fn read_back(xs: &Table, ys: &Table, positions: &mut [Position]) -> Result<()> {
+    for (i, position) in positions.iter_mut().enumerate() {
+        position.x = xs.raw_get(i + 1)?;
+        position.y = ys.raw_get(i + 1)?;
+    }
+    Ok(())
+}
+
+pub struct Fixture {
+    lua: Lua,
+    positions: Vec<Position>,
+    velocities: Vec<Velocity>,
+}
+
+impl Fixture {
+    pub fn new(entities: usize) -> Result<Self> {
+        let lua = Lua::new();
+        lua.load(SCRIPTS).exec()?;
+        let (positions, velocities) = spawn(entities);
+        Ok(Fixture {
+            lua,
+            positions,
+            velocities,
+        })
+    }
+
+    pub fn step(&mut self, strategy: Strategy, frames: u32) -> Result<()> {
+        match strategy {
+            Strategy::RustQuery => {
+                run_rust(&mut self.positions, &self.velocities, frames);
+                Ok(())
+            }
+            Strategy::LuaPerEntity => {
+                run_per_entity(&self.lua, &mut self.positions, &self.velocities, frames)
+            }
+            Strategy::BatchedView => {
+                run_batched(&self.lua, &mut self.positions, &self.velocities, frames)
+            }
+        }
+    }
+}
+
+pub fn measure(fixture: &mut Fixture, strategy: Strategy, frames: u32) -> Result<Measurement> {
+    let start = Instant::now();
+    let (outcome, allocations) = alloc::track(|| fixture.step(strategy, frames));
+    let elapsed = start.elapsed();
+    outcome?;
+    std::hint::black_box(&fixture.positions);
+
+    Ok(Measurement {
+        strategy,
+        elapsed,
+        entity_updates: fixture.positions.len() as u64 * frames as u64,
+        allocations,
+    })
+}
+
+pub fn run(harness: &Harness) -> Result<()> {
+    let (entities, frames) = (100_000, 20);
+    println!(
+        "Updating {} position/velocity pairs for {} frames...",
+        entities, frames
+    );
+
+    let mut measurements = Vec::new();
+    for strategy in Strategy::ALL {
+        let mut fixture = Fixture::new(entities)?;
+        let warmup = harness.run_warmup(|_| fixture.step(strategy, 1))?;
+        println!("{:<24} {}", strategy.name(), warmup);
+        measurements.push(measure(&mut fixture, strategy, frames)?);
+    }
+    let baseline = measurements[0].updates_per_second();
+
+    for measurement in &measurements {
+        println!(
+            "{:<24} {:>10.1?} {:>14.0} updates/s {:>8.1}x rust time",
+            measurement.strategy.name(),
+            measurement.elapsed,
+            measurement.updates_per_second(),
+            baseline / measurement.updates_per_second()
+        );
+        if let Some(allocations) = measurement.allocations {
+            println!("{:<24} {}", "", allocations);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strategies_agree() -> Result<()> {
+        let lua = Lua::new();
+        lua.load(SCRIPTS).exec()?;
+
+        let (mut expected, velocities) = spawn(64);
+        run_rust(&mut expected, &velocities, 3);
+
+        let (mut per_entity, _) = spawn(64);
+        run_per_entity(&lua, &mut per_entity, &velocities, 3)?;
+        assert_eq!(per_entity, expected);
+
+        let (mut batched, _) = spawn(64);
+        run_batched(&lua, &mut batched, &velocities, 3)?;
+        assert_eq!(batched, expected);
+
+        Ok(())
+    }
+}
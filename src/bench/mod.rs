@@ -0,0 +1,59 @@
+pub mod alloc;
+pub mod call_overhead;
+pub mod enhanced;
+mod harness;
+pub mod iteration;
+pub mod stress;
+
+pub use harness::{Harness, Warmup, WarmupReport};
+
+use mlua::{Error, Result};
+
+pub struct Scenario {
+    pub name: &'static str,
+    pub warmup: Warmup,
+    pub run: fn(&Harness) -> Result<()>,
+}
+
+pub const SCENARIOS: &[Scenario] = &[
+    Scenario {
+        name: "enhanced",
+        warmup: Warmup::Iterations(5000),
+        run: enhanced::run,
+    },
+    Scenario {
+        name: "call-overhead",
+        warmup: Warmup::Iterations(10),
+        run: call_overhead::run,
+    },
+    Scenario {
+        name: "iteration",
+        warmup: Warmup::Iterations(3),
+        run: iteration::run,
+    },
+    Scenario {
+        name: "stress",
+        warmup: Warmup::Iterations(5),
+        run: stress::run,
+    },
+];
+
+/// Runs a named scenario, using its default warmup unless the harness
+/// passed in has one.
+pub fn run(name: &str, warmup: Option<Warmup>, mut harness: Harness) -> Result<()> {
+    match SCENARIOS.iter().find(|scenario| scenario.name == name) {
+        Some(scenario) => {
+            harness = harness.warmup(warmup.unwrap_or(scenario.warmup));
+            (scenario.run)(&harness)
+        }
+        None => Err(Error::RuntimeError(format!(
+            "unknown benchmark scenario '{}' (available: {})",
+            name,
+            SCENARIOS
+                .iter()
+                .map(|scenario| scenario.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
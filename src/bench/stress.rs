@@ -0,0 +1,207 @@
+use super::Harness;
+use crate::ecs::{Schedule, ScriptValue, World};
+use crate::math::Vec2;
+use crate::physics::{Collider, Position, Shape, Velocity};
+use crate::rng::GameRng;
+use mlua::{Lua, Result};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const DT: f64 = 1.0 / 60.0;
+const RADIUS: f64 = 0.5;
+
+/// Every n-th entity also carries a script-driven `Brain`.
+const BRAIN_EVERY: usize = 10;
+
+/// Contacts found by the collision system in the last frame.
+#[derive(Debug, Default)]
+pub struct Contacts(pub usize);
+
+/// The world is a square sized so density stays the same at any count.
+fn bounds(entities: usize) -> f64 {
+    (entities as f64 * 4.0).sqrt().max(8.0)
+}
+
+pub fn populate(world: &mut World, entities: usize, seed: u64) -> Result<()> {
+    let size = bounds(entities);
+    let mut rng = GameRng::new(seed);
+    for i in 0..entities {
+        let entity = world.spawn();
+        world.insert(
+            entity,
+            Position(Vec2::new(rng.range(0.0, size), rng.range(0.0, size))),
+        )?;
+        world.insert(
+            entity,
+            Velocity(Vec2::new(rng.range(-4.0, 4.0), rng.range(-4.0, 4.0))),
+        )?;
+        world.insert(entity, Collider::new(Shape::Circle { radius: RADIUS }))?;
+        if i % BRAIN_EVERY == 0 {
+            world.set_script_component(entity, "Brain", ScriptValue::Number(0.0))?;
+        }
+    }
+    world.insert_resource(Contacts::default());
+    Ok(())
+}
+
+fn movement(world: &mut World, size: f64) {
+    world
+        .query::<(&mut Position, &mut Velocity)>()
+        .for_each(|_, (position, velocity)| {
+            position.0 = position.0 + velocity.0 * DT;
+            if !(0.0..=size).contains(&position.0.x) {
+                velocity.0.x = -velocity.0.x;
+                position.0.x = position.0.x.clamp(0.0, size);
+            }
+            if !(0.0..=size).contains(&position.0.y) {
+                velocity.0.y = -velocity.0.y;
+                position.0.y = position.0.y.clamp(0.0, size);
+            }
+        });
+}
+
+/// Uniform-grid broadphase over circle colliders, counting overlaps.
+fn collision(world: &mut World) {
+    let cell = RADIUS * 2.0;
+    let mut grid: HashMap<(i64, i64), Vec<Vec2>> = HashMap::new();
+    world
+        .query::<(&Position, &Collider)>()
+        .for_each(|_, (position, _)| {
+            let key = (
+                (position.0.x / cell).floor() as i64,
+                (position.0.y / cell).floor() as i64,
+            );
+            grid.entry(key).or_default().push(position.0);
+        });
+
+    let mut contacts = 0;
+    for (&(x, y), points) in &grid {
+        for (i, &a) in points.iter().enumerate() {
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let Some(other) = grid.get(&(x + dx, y + dy)) else {
+                        continue;
+                    };
+                    // Count each pair once: within a cell by index, across
+                    // cells only towards the "greater" neighbour.
+                    let same = dx == 0 && dy == 0;
+                    if !same && (dx, dy) < (0, 0) {
+                        continue;
+                    }
+                    let start = if same { i + 1 } else { 0 };
+                    contacts += other[start..]
+                        .iter()
+                        .filter(|&&b| (a - b).length() < RADIUS * 2.0)
+                        .count();
+                }
+            }
+        }
+    }
+    if let Some(counter) = world.resource_mut::<Contacts>() {
+        counter.0 = contacts;
+    }
+}
+
+const BRAIN: &str = r#"
+    return function(world)
+        for e in world:query({ "Brain" }) do
+            world:set(e, "Brain", world:get(e, "Brain") + 1)
+        end
+    end
+"#;
+
+pub fn schedule(lua: &Lua, entities: usize) -> Result<Schedule> {
+    let size = bounds(entities);
+    let mut schedule = Schedule::new();
+    schedule
+        .add_system("movement", move |world| {
+            movement(world, size);
+            Ok(())
+        })
+        .add_system("collision", |world| {
+            collision(world);
+            Ok(())
+        })
+        .add_lua_system("scripts", lua.load(BRAIN).eval()?);
+    Ok(schedule)
+}
+
+pub fn run(harness: &Harness) -> Result<()> {
+    let entities = harness.entities.unwrap_or(10_000);
+    let frames = harness.frames.unwrap_or(100).max(1);
+    println!(
+        "Stress: {} entities ({} scripted) for {} frames...",
+        entities,
+        entities.div_ceil(BRAIN_EVERY),
+        frames
+    );
+
+    let lua = Lua::new();
+    let mut world = World::new();
+    populate(&mut world, entities, 7)?;
+    let mut schedule = schedule(&lua, entities)?;
+
+    let warmup = harness.run_warmup(|_| schedule.run(&mut world, &lua))?;
+    println!("{}", warmup);
+
+    schedule.set_timing(true);
+    let start = Instant::now();
+    let mut worst = Duration::ZERO;
+    for _ in 0..frames {
+        let frame = Instant::now();
+        schedule.run(&mut world, &lua)?;
+        worst = worst.max(frame.elapsed());
+    }
+    let elapsed = start.elapsed();
+
+    for (name, total) in schedule.timings() {
+        println!(
+            "{:<12} {:>10.1?} total {:>10.1?}/frame {:>5.1}%",
+            name,
+            total,
+            total / frames,
+            100.0 * total.as_secs_f64() / elapsed.as_secs_f64()
+        );
+    }
+    println!(
+        "{:<12} {:>10.1?} total {:>10.1?}/frame {:>10.1?} worst, {} contacts last frame",
+        "frame",
+        elapsed,
+        elapsed / frames,
+        worst,
+        world.resource::<Contacts>().map_or(0, |c| c.0)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stress_frames_run_and_time_systems() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        populate(&mut world, 200, 1)?;
+        let mut schedule = schedule(&lua, 200)?;
+        schedule.set_timing(true);
+        for _ in 0..3 {
+            schedule.run(&mut world, &lua)?;
+        }
+
+        let brains: Vec<_> = world
+            .script_components("Brain")
+            .unwrap()
+            .entities()
+            .to_vec();
+        assert_eq!(brains.len(), 20);
+        assert_eq!(
+            world.script_component(brains[0], "Brain"),
+            Some(&ScriptValue::Number(3.0))
+        );
+        let timings = schedule.timings();
+        assert_eq!(timings.len(), 3);
+        assert!(timings.iter().all(|(_, elapsed)| !elapsed.is_zero()));
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+use crate::ecs::{Entity, World};
+use crate::math::Vec2;
+use crate::math::ease::Ease;
+use crate::physics::{Aabb, Position};
+use mlua::{LuaSerdeExt, UserData, UserDataFields, UserDataMethods, Value};
+use serde::{Deserialize, Serialize};
+
+/// A rectangle on screen in pixels, `y` growing downwards.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Viewport {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl Viewport {
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.x >= self.x
+            && point.x < self.x + self.w
+            && point.y >= self.y
+            && point.y < self.y + self.h
+    }
+
+    /// The usual split-screen layout of a `width` by `height` screen for
+    /// `players`: side by side for two, quarters for three or four, and
+    /// an even grid beyond that. Row by row from the top left.
+    pub fn split(width: f64, height: f64, players: usize) -> Vec<Viewport> {
+        let players = players.max(1);
+        let columns = match players {
+            1 => 1,
+            2..=4 => 2,
+            _ => (players as f64).sqrt().ceil() as usize,
+        };
+        let rows = players.div_ceil(columns);
+        let (w, h) = (width / columns as f64, height / rows as f64);
+        (0..players)
+            .map(|i| Viewport {
+                x: (i % columns) as f64 * w,
+                y: (i / columns) as f64 * h,
+                w,
+                h,
+            })
+            .collect()
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport {
+            x: 0.0,
+            y: 0.0,
+            w: 1280.0,
+            h: 720.0,
+        }
+    }
+}
+
+/// A decaying wobble added on top of the camera's center; its strength
+/// falls from `amplitude` to zero along `ease` over `duration` seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Shake {
+    pub amplitude: f64,
+    pub duration: f64,
+    /// Wobbles per second.
+    pub frequency: f64,
+    pub ease: Ease,
+    pub elapsed: f64,
+}
+
+impl Shake {
+    pub fn new(amplitude: f64, duration: f64) -> Self {
+        Shake {
+            amplitude,
+            duration,
+            frequency: 20.0,
+            ease: Ease::QuadOut,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    pub fn offset(&self) -> Vec2 {
+        if self.is_finished() {
+            return Vec2::ZERO;
+        }
+        let strength = self.amplitude * (1.0 - self.ease.apply(self.elapsed / self.duration));
+        let phase = self.elapsed * self.frequency * std::f64::consts::TAU;
+        // Off-ratio frequencies so the wobble doesn't trace a line.
+        Vec2::new(phase.sin(), (phase * 1.37 + 1.0).sin()) * strength
+    }
+}
+
+/// An orthographic camera showing the world around `center` in its
+/// `viewport`. At zoom 1 it fits `height` world units into the
+/// viewport's height; world `y` points up, screen `y` down.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Camera {
+    pub center: Vec2,
+    pub height: f64,
+    pub zoom: f64,
+    pub viewport: Viewport,
+    /// An entity whose `Position` the camera chases in `update_cameras`.
+    pub follow: Option<Entity>,
+    /// Seconds to close most of the gap to the target; 0 snaps to it.
+    pub damping: f64,
+    pub shake: Option<Shake>,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            center: Vec2::ZERO,
+            height: 10.0,
+            zoom: 1.0,
+            viewport: Viewport::default(),
+            follow: None,
+            damping: 0.0,
+            shake: None,
+        }
+    }
+}
+
+impl Camera {
+    /// Pixels per world unit.
+    pub fn scale(&self) -> f64 {
+        self.viewport.h * self.zoom / self.height
+    }
+
+    /// The center including any shake.
+    pub fn eye(&self) -> Vec2 {
+        self.center + self.shake.map(|s| s.offset()).unwrap_or_default()
+    }
+
+    pub fn world_to_screen(&self, point: Vec2) -> Vec2 {
+        let local = (point - self.eye()) * self.scale();
+        Vec2::new(
+            self.viewport.x + self.viewport.w / 2.0 + local.x,
+            self.viewport.y + self.viewport.h / 2.0 - local.y,
+        )
+    }
+
+    pub fn screen_to_world(&self, point: Vec2) -> Vec2 {
+        let local = Vec2::new(
+            point.x - self.viewport.x - self.viewport.w / 2.0,
+            self.viewport.y + self.viewport.h / 2.0 - point.y,
+        );
+        self.eye() + local * (1.0 / self.scale())
+    }
+
+    /// The world area the camera shows.
+    pub fn visible(&self) -> Aabb {
+        let half = Vec2::new(self.viewport.w, self.viewport.h) * (0.5 / self.scale());
+        Aabb::around(self.eye(), half)
+    }
+
+    /// Starts a shake, replacing any running one.
+    pub fn shake(&mut self, amplitude: f64, duration: f64) {
+        self.shake = Some(Shake::new(amplitude, duration));
+    }
+
+    pub fn update(&mut self, target: Option<Vec2>, dt: f64) {
+        if let Some(target) = target {
+            let t = if self.damping > 0.0 {
+                1.0 - (-dt / self.damping).exp()
+            } else {
+                1.0
+            };
+            self.center = self.center + (target - self.center) * t;
+        }
+        if let Some(shake) = &mut self.shake {
+            shake.elapsed += dt;
+            if shake.is_finished() {
+                self.shake = None;
+            }
+        }
+    }
+}
+
+pub fn register_components(world: &mut World) {
+    world.register_component::<Camera>("Camera");
+}
+
+/// The camera scripts get by default: the earliest spawned.
+pub fn main_camera(world: &World) -> Option<Entity> {
+    cameras(world).first().copied()
+}
+
+/// Every camera in draw order, earliest spawned first, so later cameras'
+/// viewports go on top.
+pub fn cameras(world: &World) -> Vec<Entity> {
+    let mut cameras = world.query::<&Camera>().entities();
+    cameras.sort();
+    cameras
+}
+
+/// The topmost camera whose viewport holds a screen point, for turning
+/// clicks into world positions under split-screen.
+pub fn camera_at(world: &World, point: Vec2) -> Option<Entity> {
+    cameras(world).into_iter().rev().find(|&entity| {
+        world
+            .get::<Camera>(entity)
+            .is_some_and(|camera| camera.viewport.contains(point))
+    })
+}
+
+/// Moves cameras after their targets and advances their shakes.
+pub fn update_cameras(world: &mut World, dt: f64) {
+    world.query::<&mut Camera>().for_each(|_, camera| {
+        let target = camera
+            .follow
+            .and_then(|entity| world.get::<Position>(entity).map(|p| p.0));
+        camera.update(target, dt);
+    });
+}
+
+impl UserData for Camera {
+    fn add_fields<F: UserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("x", |_, this| Ok(this.center.x));
+        fields.add_field_method_get("y", |_, this| Ok(this.center.y));
+        fields.add_field_method_get("zoom", |_, this| Ok(this.zoom));
+    }
+
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("world_to_screen", |lua, this, point: Value| {
+            lua.to_value(&this.world_to_screen(lua.from_value(point)?))
+        });
+        methods.add_method("screen_to_world", |lua, this, point: Value| {
+            lua.to_value(&this.screen_to_world(lua.from_value(point)?))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mlua::Lua;
+
+    #[test]
+    fn test_camera_converts_follows_and_shakes() -> mlua::Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        register_components(&mut world);
+        crate::physics::register_components(&mut world);
+        let player = world.spawn();
+        world.insert(player, Position(Vec2::new(4.0, 2.0)))?;
+        let (x, y): (f64, f64) = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world, player = ...
+                world:spawn({ Camera = { zoom = 2, follow = player, viewport = { x = 0, y = 0, w = 200, h = 100 } } })
+                local camera = world:camera()
+                local screen = camera:world_to_screen({ x = 1, y = 1 })
+                assert(camera:screen_to_world(screen).x == 1)
+                return screen.x, screen.y
+            "#,
+            )
+            .call((handle, player))
+        })?;
+        // 100 px / 10 units at zoom 2 is 20 px per unit, from the middle.
+        assert_eq!((x, y), (120.0, 30.0));
+
+        update_cameras(&mut world, 0.1);
+        let entity = main_camera(&world).unwrap();
+        assert_eq!(
+            world.get::<Camera>(entity).unwrap().center,
+            Vec2::new(4.0, 2.0)
+        );
+
+        let mut camera = *world.get::<Camera>(entity).unwrap();
+        camera.damping = 0.5;
+        camera.shake(1.0, 0.5);
+        camera.update(Some(Vec2::ZERO), 0.05);
+        assert!((camera.eye() - camera.center).length() > 0.0);
+        assert!(camera.center.x > 0.0 && camera.center.x < 4.0);
+        camera.update(None, 0.5);
+        assert!(camera.shake.is_none());
+        assert_eq!(camera.eye(), camera.center);
+        Ok(())
+    }
+}
@@ -0,0 +1,426 @@
+use crate::data::DataError;
+use crate::math::lerp;
+use mlua::{FromLua, IntoLua, Lua, LuaSerdeExt, Result, Value};
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// An sRGB color with straight alpha, every channel `0..=1`. Renderers
+/// that blend in linear space convert with `to_linear` at upload.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+/// Which space `Color::lerp` blends in. sRGB is what artists pick colors
+/// in; linear keeps brightness even; HSL and HSV walk the hue wheel the
+/// short way round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    Linear,
+    Hsl,
+    Hsv,
+}
+
+fn to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn from_linear(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Red, green and blue for hue `h` in degrees at full saturation and
+/// value, scaled into `min..max`.
+fn hue_to_rgb(h: f32, min: f32, max: f32) -> [f32; 3] {
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = (1.0 - (h % 2.0 - 1.0).abs()) * (max - min);
+    let (r, g, b) = match h as u32 {
+        0 => (max - min, x, 0.0),
+        1 => (x, max - min, 0.0),
+        2 => (0.0, max - min, x),
+        3 => (0.0, x, max - min),
+        4 => (x, 0.0, max - min),
+        _ => (max - min, 0.0, x),
+    };
+    [r + min, g + min, b + min]
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0.0, 0.0, 0.0);
+    pub const WHITE: Color = Color::rgb(1.0, 1.0, 1.0);
+    pub const RED: Color = Color::rgb(1.0, 0.0, 0.0);
+    pub const GREEN: Color = Color::rgb(0.0, 1.0, 0.0);
+    pub const BLUE: Color = Color::rgb(0.0, 0.0, 1.0);
+    pub const YELLOW: Color = Color::rgb(1.0, 1.0, 0.0);
+    pub const TRANSPARENT: Color = Color::rgba(0.0, 0.0, 0.0, 0.0);
+
+    pub const fn rgba(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Color { r, g, b, a }
+    }
+
+    pub const fn rgb(r: f32, g: f32, b: f32) -> Self {
+        Color::rgba(r, g, b, 1.0)
+    }
+
+    pub const fn with_alpha(self, a: f32) -> Self {
+        Color { a, ..self }
+    }
+
+    /// Parses `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`; the `#` is
+    /// optional.
+    pub fn hex(text: &str) -> std::result::Result<Self, DataError> {
+        let digits = text.strip_prefix('#').unwrap_or(text);
+        let invalid = || DataError::Invalid(format!("bad hex color {:?}", text));
+        if !digits.is_ascii() {
+            return Err(invalid());
+        }
+        let channels: Vec<u8> = match digits.len() {
+            3 | 4 => digits
+                .chars()
+                .map(|c| c.to_digit(16).map(|d| d as u8 * 17))
+                .collect::<Option<_>>()
+                .ok_or_else(invalid)?,
+            6 | 8 => (0..digits.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+                .collect::<Option<_>>()
+                .ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        };
+        let channel = |i: usize| channels.get(i).map_or(1.0, |&c| c as f32 / 255.0);
+        Ok(Color::rgba(channel(0), channel(1), channel(2), channel(3)))
+    }
+
+    /// `#rrggbb`, or `#rrggbbaa` when not opaque.
+    pub fn to_hex(&self) -> String {
+        let [r, g, b, a] = self
+            .to_array()
+            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        if a == 255 {
+            format!("#{:02x}{:02x}{:02x}", r, g, b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        }
+    }
+
+    pub fn to_array(&self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    /// The linear-light channels, alpha unchanged.
+    pub fn to_linear(&self) -> [f32; 4] {
+        [
+            to_linear(self.r),
+            to_linear(self.g),
+            to_linear(self.b),
+            self.a,
+        ]
+    }
+
+    pub fn from_linear([r, g, b, a]: [f32; 4]) -> Self {
+        Color::rgba(from_linear(r), from_linear(g), from_linear(b), a)
+    }
+
+    /// Hue in degrees, saturation and lightness `0..=1`.
+    pub fn hsl(h: f32, s: f32, l: f32) -> Self {
+        let chroma = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let min = l - chroma / 2.0;
+        let [r, g, b] = hue_to_rgb(h, min, min + chroma);
+        Color::rgb(r, g, b)
+    }
+
+    /// Hue in degrees, saturation and value `0..=1`.
+    pub fn hsv(h: f32, s: f32, v: f32) -> Self {
+        let [r, g, b] = hue_to_rgb(h, v - v * s, v);
+        Color::rgb(r, g, b)
+    }
+
+    fn hue(&self) -> (f32, f32, f32) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let chroma = max - min;
+        let h = if chroma == 0.0 {
+            0.0
+        } else if max == self.r {
+            60.0 * ((self.g - self.b) / chroma).rem_euclid(6.0)
+        } else if max == self.g {
+            60.0 * ((self.b - self.r) / chroma + 2.0)
+        } else {
+            60.0 * ((self.r - self.g) / chroma + 4.0)
+        };
+        (h, min, max)
+    }
+
+    pub fn to_hsl(&self) -> [f32; 3] {
+        let (h, min, max) = self.hue();
+        let l = (max + min) / 2.0;
+        let s = if max == min {
+            0.0
+        } else {
+            (max - min) / (1.0 - (2.0 * l - 1.0).abs())
+        };
+        [h, s, l]
+    }
+
+    pub fn to_hsv(&self) -> [f32; 3] {
+        let (h, min, max) = self.hue();
+        let s = if max == 0.0 { 0.0 } else { (max - min) / max };
+        [h, s, max]
+    }
+
+    pub fn lerp(a: Color, b: Color, t: f32, space: ColorSpace) -> Color {
+        let mix = |x: f32, y: f32| lerp(x as f64, y as f64, t as f64) as f32;
+        let mix_hue = |x: f32, y: f32| {
+            let delta = (y - x + 540.0).rem_euclid(360.0) - 180.0;
+            (x + delta * t).rem_euclid(360.0)
+        };
+        let alpha = mix(a.a, b.a);
+        match space {
+            ColorSpace::Srgb => Color::rgba(mix(a.r, b.r), mix(a.g, b.g), mix(a.b, b.b), alpha),
+            ColorSpace::Linear => {
+                let (la, lb) = (a.to_linear(), b.to_linear());
+                Color::from_linear(std::array::from_fn(|c| mix(la[c], lb[c]))).with_alpha(alpha)
+            }
+            ColorSpace::Hsl | ColorSpace::Hsv => {
+                let (ha, hb) = if space == ColorSpace::Hsl {
+                    (a.to_hsl(), b.to_hsl())
+                } else {
+                    (a.to_hsv(), b.to_hsv())
+                };
+                let (h, s, v) = (mix_hue(ha[0], hb[0]), mix(ha[1], hb[1]), mix(ha[2], hb[2]));
+                let color = if space == ColorSpace::Hsl {
+                    Color::hsl(h, s, v)
+                } else {
+                    Color::hsv(h, s, v)
+                };
+                color.with_alpha(alpha)
+            }
+        }
+    }
+}
+
+impl From<[f32; 4]> for Color {
+    fn from([r, g, b, a]: [f32; 4]) -> Self {
+        Color::rgba(r, g, b, a)
+    }
+}
+
+/// Serialized as an `(r, g, b, a)` tuple; reads that, three channels, a
+/// hex string or `{ r, g, b, a }`.
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(4)?;
+        for channel in self.to_array() {
+            tuple.serialize_element(&channel)?;
+        }
+        tuple.end()
+    }
+}
+
+struct ColorVisitor;
+
+impl<'de> Visitor<'de> for ColorVisitor {
+    type Value = Color;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a color as channels, a hex string or { r, g, b, a }")
+    }
+
+    fn visit_str<E: de::Error>(self, text: &str) -> std::result::Result<Color, E> {
+        Color::hex(text).map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Color, A::Error> {
+        let mut channels = Vec::new();
+        while let Some(channel) = seq.next_element::<f32>()? {
+            channels.push(channel);
+        }
+        match channels[..] {
+            [r, g, b] => Ok(Color::rgb(r, g, b)),
+            [r, g, b, a] => Ok(Color::rgba(r, g, b, a)),
+            _ => Err(de::Error::invalid_length(
+                channels.len(),
+                &"3 or 4 channels",
+            )),
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Color, A::Error> {
+        let mut color = Color::BLACK;
+        while let Some(key) = map.next_key::<String>()? {
+            let value = map.next_value::<f32>()?;
+            match key.as_str() {
+                "r" => color.r = value,
+                "g" => color.g = value,
+                "b" => color.b = value,
+                "a" => color.a = value,
+                _ => return Err(de::Error::unknown_field(&key, &["r", "g", "b", "a"])),
+            }
+        }
+        Ok(color)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Color, D::Error> {
+        deserializer.deserialize_any(ColorVisitor)
+    }
+}
+
+/// Scripts see colors as `{ r, g, b, a }` arrays, the same shape serde
+/// writes.
+impl IntoLua for Color {
+    fn into_lua(self, lua: &Lua) -> Result<Value> {
+        lua.to_value(&self)
+    }
+}
+
+impl FromLua for Color {
+    fn from_lua(value: Value, lua: &Lua) -> Result<Self> {
+        lua.from_value(value)
+    }
+}
+
+/// The `Color` global: `Color.hex("#ff8800")`, `Color.rgb(r, g, b, a?)`,
+/// `Color.hsl(h, s, l, a?)` and `Color.hsv(h, s, v, a?)` build colors;
+/// `Color.lerp(a, b, t, space?)` blends in "srgb", "linear", "hsl" or
+/// "hsv"; `Color.to_hex`, `Color.to_hsl` and `Color.to_hsv` convert back.
+pub fn register(lua: &Lua) -> Result<()> {
+    let color = lua.create_table()?;
+    color.set(
+        "hex",
+        lua.create_function(|_, text: String| Ok(Color::hex(&text)?))?,
+    )?;
+    color.set(
+        "rgb",
+        lua.create_function(|_, (r, g, b, a): (f32, f32, f32, Option<f32>)| {
+            Ok(Color::rgba(r, g, b, a.unwrap_or(1.0)))
+        })?,
+    )?;
+    color.set(
+        "hsl",
+        lua.create_function(|_, (h, s, l, a): (f32, f32, f32, Option<f32>)| {
+            Ok(Color::hsl(h, s, l).with_alpha(a.unwrap_or(1.0)))
+        })?,
+    )?;
+    color.set(
+        "hsv",
+        lua.create_function(|_, (h, s, v, a): (f32, f32, f32, Option<f32>)| {
+            Ok(Color::hsv(h, s, v).with_alpha(a.unwrap_or(1.0)))
+        })?,
+    )?;
+    color.set(
+        "lerp",
+        lua.create_function(
+            |lua, (a, b, t, space): (Color, Color, f32, Option<Value>)| {
+                let space = match space {
+                    None | Some(Value::Nil) => ColorSpace::default(),
+                    Some(space) => lua.from_value(space)?,
+                };
+                Ok(Color::lerp(a, b, t, space))
+            },
+        )?,
+    )?;
+    color.set(
+        "to_hex",
+        lua.create_function(|_, color: Color| Ok(color.to_hex()))?,
+    )?;
+    color.set(
+        "to_hsl",
+        lua.create_function(|_, color: Color| {
+            let [h, s, l] = color.to_hsl();
+            Ok((h, s, l))
+        })?,
+    )?;
+    color.set(
+        "to_hsv",
+        lua.create_function(|_, color: Color| {
+            let [h, s, v] = color.to_hsv();
+            Ok((h, s, v))
+        })?,
+    )?;
+    lua.globals().set("Color", color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data;
+
+    fn close(a: Color, b: Color) -> bool {
+        a.to_array()
+            .iter()
+            .zip(b.to_array())
+            .all(|(x, y)| (x - y).abs() < 1e-3)
+    }
+
+    #[test]
+    fn test_hex_conversions_and_lerp() {
+        let orange = Color::hex("#ff8800").unwrap();
+        assert_eq!(orange, Color::rgb(1.0, 136.0 / 255.0, 0.0));
+        assert_eq!(Color::hex("f80").unwrap(), orange);
+        assert_eq!(Color::hex("#ff880080").unwrap().to_hex(), "#ff880080");
+        assert!(Color::hex("#ff88").is_ok() && Color::hex("#ggg").is_err());
+        assert_eq!(orange.to_hex(), "#ff8800");
+
+        let [h, s, l] = orange.to_hsl();
+        assert!((h - 32.0).abs() < 0.1 && s == 1.0 && l == 0.5);
+        assert!(close(Color::hsl(h, s, l), orange));
+        let [h, s, v] = orange.to_hsv();
+        assert!(close(Color::hsv(h, s, v), orange));
+        assert!(close(Color::from_linear(orange.to_linear()), orange));
+
+        let (red, blue) = (Color::RED, Color::BLUE);
+        assert_eq!(
+            Color::lerp(red, blue, 0.5, ColorSpace::Srgb),
+            Color::rgb(0.5, 0.0, 0.5)
+        );
+        // Linear blending keeps the midpoint brighter.
+        assert!(Color::lerp(red, blue, 0.5, ColorSpace::Linear).r > 0.7);
+        // Red to blue the short way round the wheel passes magenta.
+        assert!(close(
+            Color::lerp(red, blue, 0.5, ColorSpace::Hsv),
+            Color::rgb(1.0, 0.0, 1.0)
+        ));
+
+        assert_eq!(
+            data::from_ron::<Color>("(1.0, 0.5, 0.0, 1.0)").unwrap(),
+            Color::rgb(1.0, 0.5, 0.0)
+        );
+        assert_eq!(data::from_ron::<Color>("\"#ff8800\"").unwrap(), orange);
+    }
+
+    #[test]
+    fn test_lua_color() -> Result<()> {
+        let lua = Lua::new();
+        register(&lua)?;
+        let hex: String = lua
+            .load(
+                r##"
+                local c = Color.hex("#ff8800")
+                assert(c[1] == 1 and c[4] == 1)
+                local mid = Color.lerp(Color.rgb(1, 0, 0), { r = 0, g = 0, b = 1, a = 1 }, 0.5, "hsv")
+                return Color.to_hex(mid)
+            "##,
+            )
+            .eval()?;
+        assert_eq!(hex, "#ff00ff");
+        Ok(())
+    }
+}
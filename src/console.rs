@@ -0,0 +1,362 @@
+use crate::ecs::World;
+use mlua::{Function, IntoLua, Lua, MultiValue, Result, Value};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+
+pub type RustCommand = Rc<dyn Fn(&mut World, &Lua, &[String]) -> Result<Option<String>>>;
+pub type OutputHook = Rc<dyn Fn(&str)>;
+
+#[derive(Clone)]
+pub enum CommandHandler {
+    Rust(RustCommand),
+    /// Called as `f(world, ...)` with each argument as a number when it
+    /// parses as one, otherwise a string. A returned string is printed.
+    Lua(Function),
+}
+
+#[derive(Clone)]
+struct ConsoleCommand {
+    help: String,
+    handler: CommandHandler,
+}
+
+struct ConsoleState {
+    commands: BTreeMap<String, ConsoleCommand>,
+    history: VecDeque<String>,
+    output: VecDeque<String>,
+    hooks: Vec<OutputHook>,
+    limit: usize,
+}
+
+/// A developer console: named commands registered from Rust or Lua, with
+/// history and completion. Output goes to the log and to `on_output`
+/// hooks; drawing it is up to the integrator. Clones share one console.
+#[derive(Clone)]
+pub struct Console {
+    state: Rc<RefCell<ConsoleState>>,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Console::new()
+    }
+}
+
+impl Console {
+    pub fn new() -> Self {
+        let console = Console {
+            state: Rc::new(RefCell::new(ConsoleState {
+                commands: BTreeMap::new(),
+                history: VecDeque::new(),
+                output: VecDeque::new(),
+                hooks: Vec::new(),
+                limit: 256,
+            })),
+        };
+        // Weak, so the console doesn't keep itself alive through its own command.
+        let help = Rc::downgrade(&console.state);
+        console.register_rust("help", "lists commands", move |_, _, _| {
+            let Some(state) = help.upgrade() else {
+                return Ok(None);
+            };
+            let lines: Vec<String> = state
+                .borrow()
+                .commands
+                .iter()
+                .map(|(name, command)| format!("{} - {}", name, command.help))
+                .collect();
+            Ok(Some(lines.join("\n")))
+        });
+        console
+    }
+
+    /// How many history entries and output lines are kept.
+    pub fn set_limit(&self, limit: usize) {
+        self.state.borrow_mut().limit = limit.max(1);
+    }
+
+    pub fn register(&self, name: &str, help: &str, handler: CommandHandler) {
+        self.state.borrow_mut().commands.insert(
+            name.to_string(),
+            ConsoleCommand {
+                help: help.to_string(),
+                handler,
+            },
+        );
+    }
+
+    pub fn register_rust(
+        &self,
+        name: &str,
+        help: &str,
+        handler: impl Fn(&mut World, &Lua, &[String]) -> Result<Option<String>> + 'static,
+    ) {
+        self.register(name, help, CommandHandler::Rust(Rc::new(handler)));
+    }
+
+    pub fn unregister(&self, name: &str) -> bool {
+        self.state.borrow_mut().commands.remove(name).is_some()
+    }
+
+    pub fn command_names(&self) -> Vec<String> {
+        self.state.borrow().commands.keys().cloned().collect()
+    }
+
+    pub fn on_output(&self, hook: impl Fn(&str) + 'static) {
+        self.state.borrow_mut().hooks.push(Rc::new(hook));
+    }
+
+    pub fn print(&self, text: &str) {
+        let hooks = {
+            let mut state = self.state.borrow_mut();
+            for line in text.lines() {
+                log::info!(target: "console", "{}", line);
+                state.output.push_back(line.to_string());
+            }
+            while state.output.len() > state.limit {
+                state.output.pop_front();
+            }
+            state.hooks.clone()
+        };
+        for hook in hooks {
+            hook(text);
+        }
+    }
+
+    pub fn output(&self) -> Vec<String> {
+        self.state.borrow().output.iter().cloned().collect()
+    }
+
+    pub fn clear_output(&self) {
+        self.state.borrow_mut().output.clear();
+    }
+
+    /// Oldest first.
+    pub fn history(&self) -> Vec<String> {
+        self.state.borrow().history.iter().cloned().collect()
+    }
+
+    /// Registered names starting with `prefix`, sorted.
+    pub fn complete(&self, prefix: &str) -> Vec<String> {
+        self.state
+            .borrow()
+            .commands
+            .range(prefix.to_string()..)
+            .map(|(name, _)| name)
+            .take_while(|name| name.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    /// Extends the command name in `line` as far as every match agrees,
+    /// adding a space once only one command is left.
+    pub fn complete_line(&self, line: &str) -> String {
+        if line.contains(char::is_whitespace) {
+            return line.to_string();
+        }
+        let matches = self.complete(line);
+        match matches.as_slice() {
+            [] => line.to_string(),
+            [only] => format!("{} ", only),
+            [first, rest @ ..] => {
+                let mut common = first.clone();
+                for name in rest {
+                    while !name.starts_with(&common) {
+                        common.pop();
+                    }
+                }
+                common
+            }
+        }
+    }
+
+    /// Runs one input line, recording it in the history. Errors are printed
+    /// and also returned.
+    pub fn execute(&self, world: &mut World, lua: &Lua, line: &str) -> Result<()> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+        {
+            let mut state = self.state.borrow_mut();
+            if state.history.back().map(String::as_str) != Some(line) {
+                state.history.push_back(line.to_string());
+            }
+            while state.history.len() > state.limit {
+                state.history.pop_front();
+            }
+        }
+        self.print(&format!("> {}", line));
+
+        let result = self.run(world, lua, line);
+        match &result {
+            Ok(Some(text)) => self.print(text),
+            Ok(None) => {}
+            Err(e) => self.print(&format!("error: {}", e)),
+        }
+        result.map(|_| ())
+    }
+
+    fn run(&self, world: &mut World, lua: &Lua, line: &str) -> Result<Option<String>> {
+        let args = parse_args(line)?;
+        let (name, args) = args.split_first().expect("line is not empty");
+        let command = self
+            .state
+            .borrow()
+            .commands
+            .get(name)
+            .cloned()
+            .ok_or_else(|| mlua::Error::runtime(format!("unknown command '{}'", name)))?;
+
+        let output = match command.handler {
+            CommandHandler::Rust(handler) => handler(world, lua, args)?,
+            CommandHandler::Lua(function) => lua.scope(|scope| {
+                let mut values = vec![Value::UserData(scope.create_userdata_ref_mut(world)?)];
+                for arg in args {
+                    values.push(match arg.parse::<f64>() {
+                        Ok(n) => Value::Number(n),
+                        Err(_) => arg.as_str().into_lua(lua)?,
+                    });
+                }
+                function.call::<Option<String>>(MultiValue::from_vec(values))
+            })?,
+        };
+        world.apply_commands(lua)?;
+        Ok(output)
+    }
+
+    /// Adds the `console` table: `register(name, handler, help)`,
+    /// `print(text)` and `complete(prefix)`.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        let console = lua.create_table()?;
+        let this = self.clone();
+        console.set(
+            "register",
+            lua.create_function(
+                move |_, (name, handler, help): (String, Function, Option<String>)| {
+                    this.register(
+                        &name,
+                        help.as_deref().unwrap_or_default(),
+                        CommandHandler::Lua(handler),
+                    );
+                    Ok(())
+                },
+            )?,
+        )?;
+        let this = self.clone();
+        console.set(
+            "print",
+            lua.create_function(move |_, text: String| {
+                this.print(&text);
+                Ok(())
+            })?,
+        )?;
+        let this = self.clone();
+        console.set(
+            "complete",
+            lua.create_function(move |_, prefix: String| Ok(this.complete(&prefix)))?,
+        )?;
+        lua.globals().set("console", console)
+    }
+}
+
+/// Splits on whitespace, keeping double-quoted runs together.
+fn parse_args(line: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut started = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    args.push(std::mem::take(&mut current));
+                    started = false;
+                }
+            }
+            c => {
+                current.push(c);
+                started = true;
+            }
+        }
+    }
+    if quoted {
+        return Err(mlua::Error::runtime("unterminated quote"));
+    }
+    if started {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::ScriptValue;
+
+    #[test]
+    fn test_lua_command_with_quoted_args() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let console = Console::new();
+        console.register_lua(&lua)?;
+        let drawn = Rc::new(RefCell::new(Vec::new()));
+        let sink = drawn.clone();
+        console.on_output(move |text| sink.borrow_mut().push(text.to_string()));
+
+        lua.load(
+            r#"
+            console.register("give", function(world, item, count)
+                local e = world:spawn({ Item = { name = item, count = count } })
+                return "gave " .. count .. " " .. item
+            end, "spawns an item")
+        "#,
+        )
+        .exec()?;
+
+        console.execute(&mut world, &lua, r#"give "iron sword" 3"#)?;
+        assert!(console.execute(&mut world, &lua, "take 1").is_err());
+
+        let item = world.entities().next().unwrap();
+        assert_eq!(
+            world.script_component(item, "Item"),
+            Some(&ScriptValue::Map(BTreeMap::from([
+                ("count".to_string(), ScriptValue::Number(3.0)),
+                (
+                    "name".to_string(),
+                    ScriptValue::String("iron sword".to_string())
+                ),
+            ])))
+        );
+        assert_eq!(
+            console.output(),
+            vec![
+                r#"> give "iron sword" 3"#,
+                "gave 3 iron sword",
+                "> take 1",
+                "error: runtime error: unknown command 'take'",
+            ]
+        );
+        assert_eq!(drawn.borrow().len(), 4);
+        assert_eq!(console.history().len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_completion() {
+        let console = Console::new();
+        for name in ["spawn", "spawn_wave", "speed"] {
+            console.register_rust(name, "", |_, _, _| Ok(None));
+        }
+        assert_eq!(console.complete("spawn"), vec!["spawn", "spawn_wave"]);
+        assert_eq!(console.complete_line("sp"), "sp");
+        assert_eq!(console.complete_line("spe"), "speed ");
+        assert_eq!(console.complete_line("spawn_"), "spawn_wave ");
+        assert_eq!(console.complete_line("h"), "help ");
+    }
+}
@@ -0,0 +1,178 @@
+use crate::data::{DataError, from_ron};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// FNV-1a over the bytes. Stable across builds and platforms, so hashes
+/// from different machines can be compared; not meant to resist tampering.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// One asset that differs between two manifests; `None` where a side
+/// doesn't have it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentMismatch {
+    pub name: String,
+    pub ours: Option<u64>,
+    pub theirs: Option<u64>,
+}
+
+/// Content hashes of loaded assets and scripts by name. Players exchange
+/// `combined` before a lockstep game starts and the full manifest when
+/// they disagree, to find out which files differ.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ContentManifest {
+    entries: BTreeMap<String, u64>,
+}
+
+impl ContentManifest {
+    pub fn new() -> Self {
+        ContentManifest::default()
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, DataError> {
+        from_ron(source)
+    }
+
+    pub fn to_ron(&self) -> Result<String, DataError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| DataError::Invalid(e.to_string()))
+    }
+
+    pub fn record(&mut self, name: &str, bytes: &[u8]) -> u64 {
+        let hash = content_hash(bytes);
+        self.entries.insert(name.to_string(), hash);
+        hash
+    }
+
+    pub fn record_file(&mut self, name: &str, path: impl AsRef<Path>) -> Result<u64, DataError> {
+        Ok(self.record(name, &fs::read(path)?))
+    }
+
+    /// Every file below `dir`, named `prefix` plus its `/`-separated path
+    /// relative to `dir`.
+    pub fn record_dir(&mut self, prefix: &str, dir: impl AsRef<Path>) -> Result<(), DataError> {
+        let dir = dir.as_ref();
+        if !dir.is_dir() {
+            return Ok(());
+        }
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = format!(
+                "{}{}",
+                prefix,
+                path.file_name().unwrap_or_default().to_string_lossy()
+            );
+            if path.is_dir() {
+                self.record_dir(&format!("{}/", name), &path)?;
+            } else {
+                self.record_file(&name, &path)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<u64> {
+        self.entries.remove(name)
+    }
+
+    pub fn extend(&mut self, other: ContentManifest) {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn get(&self, name: &str) -> Option<u64> {
+        self.entries.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.entries
+            .iter()
+            .map(|(name, &hash)| (name.as_str(), hash))
+    }
+
+    /// One hash over every name and hash, equal for equal manifests.
+    pub fn combined(&self) -> u64 {
+        let mut bytes = Vec::new();
+        for (name, hash) in &self.entries {
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.push(0);
+            bytes.extend_from_slice(&hash.to_le_bytes());
+        }
+        content_hash(&bytes)
+    }
+
+    pub fn diff(&self, theirs: &ContentManifest) -> Vec<ContentMismatch> {
+        let mut names: Vec<&String> = self.entries.keys().chain(theirs.entries.keys()).collect();
+        names.sort();
+        names.dedup();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let (ours, theirs) = (self.get(name), theirs.get(name));
+                (ours != theirs).then(|| ContentMismatch {
+                    name: name.clone(),
+                    ours,
+                    theirs,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifests_compare() {
+        let dir = std::env::temp_dir().join(format!("content_{}", std::process::id()));
+        fs::create_dir_all(dir.join("scripts")).unwrap();
+        fs::write(dir.join("scripts/ai.lua"), "return 1").unwrap();
+        fs::write(dir.join("level.ron"), "()").unwrap();
+
+        let mut ours = ContentManifest::new();
+        ours.record_dir("game/", &dir).unwrap();
+        assert_eq!(
+            ours.get("game/scripts/ai.lua"),
+            Some(content_hash(b"return 1"))
+        );
+        let mut theirs = ContentManifest::from_ron(&ours.to_ron().unwrap()).unwrap();
+        assert_eq!(ours.combined(), theirs.combined());
+
+        theirs.record("game/scripts/ai.lua", b"return 2");
+        theirs.record("extra.lua", b"");
+        assert_ne!(ours.combined(), theirs.combined());
+        let names: Vec<String> = ours.diff(&theirs).into_iter().map(|m| m.name).collect();
+        assert_eq!(names, ["extra.lua", "game/scripts/ai.lua"]);
+
+        let lua = mlua::Lua::new();
+        let mut world = crate::ecs::World::new();
+        world.insert_resource(ours.clone());
+        let (lua_hash, combined): (String, String) = lua
+            .scope(|scope| {
+                let world = scope.create_userdata_ref_mut(&mut world)?;
+                lua.load(
+                    "local manifest, combined = (...):content_manifest() \
+                     return manifest['game/level.ron'], combined",
+                )
+                .call(world)
+            })
+            .unwrap();
+        assert_eq!(lua_hash, format!("{:016x}", content_hash(b"()")));
+        assert_eq!(combined, format!("{:016x}", ours.combined()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,358 @@
+use crate::assets::read_lua_asset;
+use crate::color::{Color, ColorSpace};
+use crate::data::{self, DataError};
+use crate::math::{ease::Ease, lerp, smoothstep};
+use mlua::{Lua, LuaSerdeExt, Result, Table, UserData, UserDataMethods};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    Constant,
+    #[default]
+    Linear,
+    Cubic,
+    Ease(Ease),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CurveKey {
+    pub time: f64,
+    pub value: f64,
+    /// How the segment starting at this key blends into the next one.
+    #[serde(default)]
+    pub interp: Interpolation,
+}
+
+#[derive(Deserialize)]
+struct RawCurve {
+    keys: Vec<CurveKey>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawCurve")]
+pub struct Curve {
+    keys: Vec<CurveKey>,
+}
+
+impl TryFrom<RawCurve> for Curve {
+    type Error = DataError;
+
+    fn try_from(raw: RawCurve) -> std::result::Result<Self, DataError> {
+        Curve::new(raw.keys)
+    }
+}
+
+impl Curve {
+    pub fn new(mut keys: Vec<CurveKey>) -> std::result::Result<Self, DataError> {
+        if keys.is_empty() {
+            return Err(DataError::Invalid(
+                "curve needs at least one key".to_string(),
+            ));
+        }
+        if keys.iter().any(|key| !key.time.is_finite()) {
+            return Err(DataError::Invalid(
+                "curve key times must be finite".to_string(),
+            ));
+        }
+
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(Curve { keys })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        data::load_ron(path)
+    }
+
+    pub fn keys(&self) -> &[CurveKey] {
+        &self.keys
+    }
+
+    pub fn sample(&self, t: f64) -> f64 {
+        let (first, last) = (self.keys[0], self.keys[self.keys.len() - 1]);
+        if t <= first.time {
+            return first.value;
+        }
+        if t >= last.time {
+            return last.value;
+        }
+
+        let i = self.keys.partition_point(|key| key.time <= t) - 1;
+        let (a, b) = (self.keys[i], self.keys[i + 1]);
+        let span = b.time - a.time;
+        let u = (t - a.time) / span;
+
+        match a.interp {
+            Interpolation::Constant => a.value,
+            Interpolation::Linear => lerp(a.value, b.value, u),
+            Interpolation::Ease(ease) => lerp(a.value, b.value, ease.apply(u)),
+            Interpolation::Cubic => {
+                let (m_a, m_b) = (self.tangent(i), self.tangent(i + 1));
+                let (u2, u3) = (u * u, u * u * u);
+                (2.0 * u3 - 3.0 * u2 + 1.0) * a.value
+                    + (u3 - 2.0 * u2 + u) * span * m_a
+                    + (-2.0 * u3 + 3.0 * u2) * b.value
+                    + (u3 - u2) * span * m_b
+            }
+        }
+    }
+
+    fn tangent(&self, i: usize) -> f64 {
+        let prev = self.keys[i.saturating_sub(1)];
+        let next = self.keys[(i + 1).min(self.keys.len() - 1)];
+        if next.time == prev.time {
+            0.0
+        } else {
+            (next.value - prev.value) / (next.time - prev.time)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColorStop {
+    pub time: f64,
+    pub color: Color,
+}
+
+#[derive(Deserialize)]
+struct RawGradient {
+    stops: Vec<ColorStop>,
+    #[serde(default)]
+    interp: Interpolation,
+    #[serde(default)]
+    space: ColorSpace,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawGradient")]
+pub struct Gradient {
+    stops: Vec<ColorStop>,
+    interp: Interpolation,
+    /// What neighbouring stops blend in; sRGB unless set.
+    space: ColorSpace,
+}
+
+impl TryFrom<RawGradient> for Gradient {
+    type Error = DataError;
+
+    fn try_from(raw: RawGradient) -> std::result::Result<Self, DataError> {
+        Ok(Gradient::new(raw.stops, raw.interp)?.in_space(raw.space))
+    }
+}
+
+impl Gradient {
+    pub fn new(
+        mut stops: Vec<ColorStop>,
+        interp: Interpolation,
+    ) -> std::result::Result<Self, DataError> {
+        if stops.is_empty() {
+            return Err(DataError::Invalid(
+                "gradient needs at least one stop".to_string(),
+            ));
+        }
+        if stops.iter().any(|stop| !stop.time.is_finite()) {
+            return Err(DataError::Invalid(
+                "gradient stop times must be finite".to_string(),
+            ));
+        }
+
+        stops.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(Gradient {
+            stops,
+            interp,
+            space: ColorSpace::Srgb,
+        })
+    }
+
+    pub fn in_space(self, space: ColorSpace) -> Self {
+        Gradient { space, ..self }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        data::load_ron(path)
+    }
+
+    pub fn stops(&self) -> &[ColorStop] {
+        &self.stops
+    }
+
+    pub fn sample(&self, t: f64) -> Color {
+        let (first, last) = (self.stops[0], self.stops[self.stops.len() - 1]);
+        if t <= first.time {
+            return first.color;
+        }
+        if t >= last.time {
+            return last.color;
+        }
+
+        let i = self.stops.partition_point(|stop| stop.time <= t) - 1;
+        let (a, b) = (self.stops[i], self.stops[i + 1]);
+        let u = (t - a.time) / (b.time - a.time);
+        let u = match self.interp {
+            Interpolation::Constant => 0.0,
+            Interpolation::Linear => u,
+            Interpolation::Cubic => smoothstep(0.0, 1.0, u),
+            Interpolation::Ease(ease) => ease.apply(u),
+        };
+
+        Color::lerp(a.color, b.color, u as f32, self.space)
+    }
+}
+
+impl UserData for Curve {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("sample", |_, this, t: f64| Ok(this.sample(t)));
+    }
+}
+
+impl UserData for Gradient {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("sample", |_, this, t: f64| {
+            let Color { r, g, b, a } = this.sample(t);
+            Ok((r, g, b, a))
+        });
+    }
+}
+
+pub fn register(lua: &Lua) -> Result<()> {
+    let curve = lua.create_table()?;
+    curve.set(
+        "new",
+        lua.create_function(|lua, def: Table| lua.from_value::<Curve>(mlua::Value::Table(def)))?,
+    )?;
+    curve.set(
+        "load",
+        lua.create_function(|lua, path: String| {
+            Ok(data::from_ron::<Curve>(&read_lua_asset(lua, &path)?)?)
+        })?,
+    )?;
+    lua.globals().set("curve", curve)?;
+
+    let gradient = lua.create_table()?;
+    gradient.set(
+        "new",
+        lua.create_function(|lua, def: Table| lua.from_value::<Gradient>(mlua::Value::Table(def)))?,
+    )?;
+    gradient.set(
+        "load",
+        lua.create_function(|lua, path: String| {
+            Ok(data::from_ron::<Gradient>(&read_lua_asset(lua, &path)?)?)
+        })?,
+    )?;
+    lua.globals().set("gradient", gradient)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f64, value: f64, interp: Interpolation) -> CurveKey {
+        CurveKey {
+            time,
+            value,
+            interp,
+        }
+    }
+
+    #[test]
+    fn test_curve_sampling_modes() {
+        let curve = Curve::new(vec![
+            key(1.0, 10.0, Interpolation::Constant),
+            key(0.0, 0.0, Interpolation::Linear),
+            key(2.0, 20.0, Interpolation::Cubic),
+            key(3.0, 30.0, Interpolation::Linear),
+        ])
+        .unwrap();
+
+        assert_eq!(curve.sample(-1.0), 0.0);
+        assert_eq!(curve.sample(0.5), 5.0);
+        assert_eq!(curve.sample(1.5), 10.0);
+        assert!((curve.sample(2.5) - 25.0).abs() < 1e-10);
+        assert_eq!(curve.sample(4.0), 30.0);
+
+        assert!(Curve::new(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_curve_and_gradient_from_ron() {
+        let curve: Curve = data::from_ron(
+            "(keys: [(time: 0.0, value: 0.0, interp: ease(quad_in)), (time: 1.0, value: 1.0)])",
+        )
+        .unwrap();
+        assert_eq!(curve.sample(0.5), 0.25);
+
+        let gradient: Gradient = data::from_ron(
+            "(stops: [(time: 0.0, color: (0.0, 0.0, 0.0, 1.0)), (time: 1.0, color: (1.0, 0.5, 0.0, 1.0))])",
+        )
+        .unwrap();
+        assert_eq!(gradient.sample(0.5), Color::rgb(0.5, 0.25, 0.0));
+
+        assert!(data::from_ron::<Curve>("(keys: [])").is_err());
+    }
+
+    #[test]
+    fn test_lua_curve_sample() -> Result<()> {
+        let lua = Lua::new();
+        register(&lua)?;
+
+        let value: f64 = lua
+            .load(
+                r#"
+                local c = curve.new({ keys = {
+                    { time = 0, value = 0 },
+                    { time = 2, value = 4 },
+                } })
+                return c:sample(1)
+            "#,
+            )
+            .eval()?;
+        assert_eq!(value, 2.0);
+
+        let (r, _, _, a): (f64, f64, f64, f64) = lua
+            .load(
+                r#"
+                local g = gradient.new({ interp = "constant", stops = {
+                    { time = 0, color = { 1, 0, 0, 1 } },
+                    { time = 1, color = { 0, 0, 1, 1 } },
+                } })
+                return g:sample(0.9)
+            "#,
+            )
+            .eval()?;
+        assert_eq!((r, a), (1.0, 1.0));
+
+        assert!(lua.load("curve.new({ keys = {} })").exec().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lua_loads_read_through_the_asset_source() -> Result<()> {
+        use crate::assets::{PackBuilder, PackSource};
+
+        let mut builder = PackBuilder::new();
+        builder.add(
+            "curves/fade.ron",
+            b"(keys: [(time: 0.0, value: 0.0), (time: 1.0, value: 2.0)])".to_vec(),
+        );
+        builder.add(
+            "curves/sky.ron",
+            b"(stops: [(time: 0.0, color: (0.0, 0.0, 0.0, 1.0))])".to_vec(),
+        );
+        let pack = PackSource::from_bytes(builder.write(false).unwrap()).unwrap();
+
+        let lua = Lua::new();
+        register(&lua)?;
+        crate::assets::set_lua_source(&lua, std::sync::Arc::new(pack));
+        let value: f64 = lua
+            .load(r#"return curve.load("curves/fade.ron"):sample(0.5)"#)
+            .eval()?;
+        assert_eq!(value, 1.0);
+        lua.load(r#"gradient.load("curves/sky.ron")"#).exec()?;
+        assert!(lua.load(r#"curve.load("Cargo.toml")"#).exec().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,565 @@
+use crate::console::Console;
+use crate::data::{DataError, load_ron};
+use mlua::{Function, IntoLua, Lua, Result, Table, Value};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CvarValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl CvarValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            CvarValue::Bool(_) => "bool",
+            CvarValue::Int(_) => "int",
+            CvarValue::Float(_) => "float",
+            CvarValue::String(_) => "string",
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match *self {
+            CvarValue::Int(n) => Some(n as f64),
+            CvarValue::Float(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Converts `value` to this value's type where that loses nothing, e.g.
+    /// a whole float into an int.
+    fn coerce(&self, value: CvarValue) -> Option<CvarValue> {
+        match (self, value) {
+            (CvarValue::Bool(_), v @ CvarValue::Bool(_))
+            | (CvarValue::Int(_), v @ CvarValue::Int(_))
+            | (CvarValue::Float(_), v @ CvarValue::Float(_))
+            | (CvarValue::String(_), v @ CvarValue::String(_)) => Some(v),
+            (CvarValue::Float(_), CvarValue::Int(n)) => Some(CvarValue::Float(n as f64)),
+            (CvarValue::Int(_), CvarValue::Float(n))
+                if n.fract() == 0.0 && (i64::MIN as f64..i64::MAX as f64).contains(&n) =>
+            {
+                Some(CvarValue::Int(n as i64))
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses console input as this value's type.
+    fn parse(&self, text: &str) -> Option<CvarValue> {
+        match self {
+            CvarValue::Bool(_) => match text {
+                "1" | "true" | "on" => Some(CvarValue::Bool(true)),
+                "0" | "false" | "off" => Some(CvarValue::Bool(false)),
+                _ => None,
+            },
+            CvarValue::Int(_) => text.parse().ok().map(CvarValue::Int),
+            CvarValue::Float(_) => text.parse().ok().map(CvarValue::Float),
+            CvarValue::String(_) => Some(CvarValue::String(text.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for CvarValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CvarValue::Bool(b) => write!(f, "{}", b),
+            CvarValue::Int(n) => write!(f, "{}", n),
+            CvarValue::Float(n) => write!(f, "{}", n),
+            CvarValue::String(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+impl IntoLua for CvarValue {
+    fn into_lua(self, lua: &Lua) -> Result<Value> {
+        match self {
+            CvarValue::Bool(b) => Ok(Value::Boolean(b)),
+            CvarValue::Int(n) => Ok(Value::Number(n as f64)),
+            CvarValue::Float(n) => Ok(Value::Number(n)),
+            CvarValue::String(s) => s.into_lua(lua),
+        }
+    }
+}
+
+impl mlua::FromLua for CvarValue {
+    fn from_lua(value: Value, _lua: &Lua) -> Result<Self> {
+        match value {
+            Value::Boolean(b) => Ok(CvarValue::Bool(b)),
+            Value::Integer(n) => Ok(CvarValue::Int(n)),
+            Value::Number(n) => Ok(CvarValue::Float(n)),
+            Value::String(s) => Ok(CvarValue::String(s.to_str()?.to_string())),
+            _ => Err(mlua::Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "CvarValue".to_string(),
+                message: Some("Expected a boolean, number or string".to_string()),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CvarError {
+    Unknown(String),
+    WrongType {
+        name: String,
+        expected: &'static str,
+        found: String,
+    },
+    OutOfRange {
+        name: String,
+        value: f64,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    CheatsDisabled(String),
+}
+
+impl fmt::Display for CvarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CvarError::Unknown(name) => write!(f, "unknown cvar '{}'", name),
+            CvarError::WrongType {
+                name,
+                expected,
+                found,
+            } => write!(f, "cvar '{}' is a {}, got {}", name, expected, found),
+            CvarError::OutOfRange {
+                name,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "{} is outside the range of cvar '{}' ({}..={})",
+                value,
+                name,
+                min.map_or(String::new(), |n| n.to_string()),
+                max.map_or(String::new(), |n| n.to_string())
+            ),
+            CvarError::CheatsDisabled(name) => {
+                write!(f, "cvar '{}' is a cheat and cheats are disabled", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CvarError {}
+
+impl From<CvarError> for mlua::Error {
+    fn from(e: CvarError) -> Self {
+        mlua::Error::external(e)
+    }
+}
+
+/// The definition a system registers; the default also fixes the type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cvar {
+    pub default: CvarValue,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub help: String,
+    /// Only settable while cheats are enabled.
+    pub cheat: bool,
+}
+
+impl Cvar {
+    pub fn new(default: CvarValue) -> Self {
+        Cvar {
+            default,
+            min: None,
+            max: None,
+            help: String::new(),
+            cheat: false,
+        }
+    }
+
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        self.min = Some(min);
+        self.max = Some(max);
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = help.to_string();
+        self
+    }
+
+    pub fn cheat(mut self) -> Self {
+        self.cheat = true;
+        self
+    }
+}
+
+pub type RustCvarCallback = Rc<dyn Fn(&str, &CvarValue)>;
+
+#[derive(Clone)]
+pub enum CvarCallback {
+    Rust(RustCvarCallback),
+    /// Called as `f(name, value)`.
+    Lua(Function),
+}
+
+struct Entry {
+    def: Cvar,
+    value: CvarValue,
+    callbacks: Vec<CvarCallback>,
+}
+
+#[derive(Default)]
+struct CvarState {
+    vars: BTreeMap<String, Entry>,
+    /// Values from a config file for cvars that aren't registered yet.
+    pending: BTreeMap<String, CvarValue>,
+    cheats: bool,
+}
+
+/// Engine-wide named settings like `physics.gravity`. Clones share the
+/// same variables, so the console and Lua see what systems registered.
+#[derive(Clone, Default)]
+pub struct Cvars {
+    state: Rc<RefCell<CvarState>>,
+}
+
+impl Cvars {
+    pub fn new() -> Self {
+        Cvars::default()
+    }
+
+    /// Registers `name`, taking a value loaded from config earlier if it
+    /// fits. Re-registering keeps the current value and callbacks.
+    pub fn register(&self, name: &str, def: Cvar) -> std::result::Result<(), CvarError> {
+        let pending = self.state.borrow_mut().pending.remove(name);
+        {
+            let mut state = self.state.borrow_mut();
+            match state.vars.get_mut(name) {
+                Some(entry) => entry.def = def,
+                None => {
+                    state.vars.insert(
+                        name.to_string(),
+                        Entry {
+                            value: def.default.clone(),
+                            def,
+                            callbacks: Vec::new(),
+                        },
+                    );
+                }
+            }
+        }
+        match pending {
+            Some(value) => self.set_unchecked(name, value),
+            None => Ok(()),
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.state.borrow().vars.keys().cloned().collect()
+    }
+
+    pub fn def(&self, name: &str) -> Option<Cvar> {
+        self.state.borrow().vars.get(name).map(|e| e.def.clone())
+    }
+
+    pub fn get(&self, name: &str) -> Option<CvarValue> {
+        self.state.borrow().vars.get(name).map(|e| e.value.clone())
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        match self.get(name)? {
+            CvarValue::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn get_f64(&self, name: &str) -> Option<f64> {
+        self.get(name)?.as_f64()
+    }
+
+    pub fn get_str(&self, name: &str) -> Option<String> {
+        match self.get(name)? {
+            CvarValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn set_cheats(&self, enabled: bool) {
+        self.state.borrow_mut().cheats = enabled;
+    }
+
+    pub fn cheats(&self) -> bool {
+        self.state.borrow().cheats
+    }
+
+    /// Sets `name` and runs its change callbacks if the value changed.
+    pub fn set(&self, name: &str, value: CvarValue) -> std::result::Result<(), CvarError> {
+        {
+            let state = self.state.borrow();
+            let entry = state
+                .vars
+                .get(name)
+                .ok_or_else(|| CvarError::Unknown(name.to_string()))?;
+            if entry.def.cheat && !state.cheats {
+                return Err(CvarError::CheatsDisabled(name.to_string()));
+            }
+        }
+        self.set_unchecked(name, value)
+    }
+
+    /// Sets `name` from console text, parsed as the cvar's type.
+    pub fn set_str(&self, name: &str, text: &str) -> std::result::Result<(), CvarError> {
+        let def = self
+            .def(name)
+            .ok_or_else(|| CvarError::Unknown(name.to_string()))?;
+        let value = def
+            .default
+            .parse(text)
+            .ok_or_else(|| CvarError::WrongType {
+                name: name.to_string(),
+                expected: def.default.type_name(),
+                found: format!("'{}'", text),
+            })?;
+        self.set(name, value)
+    }
+
+    /// Set skipping the cheat check, for config files and registration.
+    fn set_unchecked(&self, name: &str, value: CvarValue) -> std::result::Result<(), CvarError> {
+        let (value, callbacks) = {
+            let mut state = self.state.borrow_mut();
+            let entry = state
+                .vars
+                .get_mut(name)
+                .ok_or_else(|| CvarError::Unknown(name.to_string()))?;
+            let found = value.type_name();
+            let value = entry
+                .def
+                .default
+                .coerce(value)
+                .ok_or_else(|| CvarError::WrongType {
+                    name: name.to_string(),
+                    expected: entry.def.default.type_name(),
+                    found: found.to_string(),
+                })?;
+            if let Some(n) = value.as_f64() {
+                let (min, max) = (entry.def.min, entry.def.max);
+                // NaN would slip past both bounds, and no cvar wants infinity.
+                if !n.is_finite()
+                    || min.is_some_and(|min| n < min)
+                    || max.is_some_and(|max| n > max)
+                {
+                    return Err(CvarError::OutOfRange {
+                        name: name.to_string(),
+                        value: n,
+                        min,
+                        max,
+                    });
+                }
+            }
+            if entry.value == value {
+                return Ok(());
+            }
+            entry.value = value.clone();
+            (value, entry.callbacks.clone())
+        };
+
+        for callback in callbacks {
+            match callback {
+                CvarCallback::Rust(f) => f(name, &value),
+                CvarCallback::Lua(f) => {
+                    if let Err(e) = f.call::<()>((name, value.clone())) {
+                        log::error!(target: "cvar", "on_change for '{}' failed: {}", name, e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn on_change(
+        &self,
+        name: &str,
+        callback: CvarCallback,
+    ) -> std::result::Result<(), CvarError> {
+        self.state
+            .borrow_mut()
+            .vars
+            .get_mut(name)
+            .ok_or_else(|| CvarError::Unknown(name.to_string()))?
+            .callbacks
+            .push(callback);
+        Ok(())
+    }
+
+    /// Applies a RON map of `name: value`. Cvars that aren't registered yet
+    /// pick their value up when they are.
+    pub fn load_config(&self, path: impl AsRef<Path>) -> std::result::Result<(), DataError> {
+        let values: BTreeMap<String, CvarValue> = load_ron(path)?;
+        self.apply_config(values)
+            .map_err(|e| DataError::Invalid(e.to_string()))
+    }
+
+    pub fn apply_config(
+        &self,
+        values: BTreeMap<String, CvarValue>,
+    ) -> std::result::Result<(), CvarError> {
+        for (name, value) in values {
+            if self.state.borrow().vars.contains_key(&name) {
+                self.set_unchecked(&name, value)?;
+            } else {
+                self.state.borrow_mut().pending.insert(name, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds `set <name> <value>`, `get <name>` and `cvars [prefix]`.
+    pub fn register_console(&self, console: &Console) {
+        let cvars = self.clone();
+        console.register_rust("set", "set <cvar> <value>", move |_, _, args| {
+            let [name, value] = args else {
+                return Err(mlua::Error::runtime("usage: set <cvar> <value>"));
+            };
+            cvars.set_str(name, value)?;
+            Ok(Some(format!("{} = {}", name, cvars.get(name).unwrap())))
+        });
+
+        let cvars = self.clone();
+        console.register_rust("get", "get <cvar>", move |_, _, args| {
+            let [name] = args else {
+                return Err(mlua::Error::runtime("usage: get <cvar>"));
+            };
+            let value = cvars
+                .get(name)
+                .ok_or_else(|| CvarError::Unknown(name.clone()))?;
+            Ok(Some(format!("{} = {}", name, value)))
+        });
+
+        let cvars = self.clone();
+        console.register_rust("cvars", "lists cvars [prefix]", move |_, _, args| {
+            let prefix = args.first().map_or("", String::as_str);
+            let state = cvars.state.borrow();
+            let lines: Vec<String> = state
+                .vars
+                .iter()
+                .filter(|(name, _)| name.starts_with(prefix))
+                .map(|(name, entry)| format!("{} = {}  {}", name, entry.value, entry.def.help))
+                .collect();
+            Ok(Some(lines.join("\n")))
+        });
+    }
+
+    /// Adds the `cvar` table: `get(name)`, `set(name, value)`,
+    /// `register(name, default, { min, max, help, cheat })` and
+    /// `on_change(name, f)`.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        let table = lua.create_table()?;
+        let cvars = self.clone();
+        table.set(
+            "get",
+            lua.create_function(move |_, name: String| Ok(cvars.get(&name)))?,
+        )?;
+        let cvars = self.clone();
+        table.set(
+            "set",
+            lua.create_function(move |_, (name, value): (String, CvarValue)| {
+                Ok(cvars.set(&name, value)?)
+            })?,
+        )?;
+        let cvars = self.clone();
+        table.set(
+            "register",
+            lua.create_function(
+                move |_, (name, default, options): (String, CvarValue, Option<Table>)| {
+                    let mut def = Cvar::new(default);
+                    if let Some(options) = options {
+                        def.min = options.get("min")?;
+                        def.max = options.get("max")?;
+                        def.help = options.get::<Option<String>>("help")?.unwrap_or_default();
+                        def.cheat = options.get::<Option<bool>>("cheat")?.unwrap_or(false);
+                    }
+                    Ok(cvars.register(&name, def)?)
+                },
+            )?,
+        )?;
+        let cvars = self.clone();
+        table.set(
+            "on_change",
+            lua.create_function(move |_, (name, f): (String, Function)| {
+                Ok(cvars.on_change(&name, CvarCallback::Lua(f))?)
+            })?,
+        )?;
+        lua.globals().set("cvar", table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::World;
+
+    #[test]
+    fn test_cvars_from_config_console_and_lua() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let console = Console::new();
+        let cvars = Cvars::new();
+        cvars.register_console(&console);
+        cvars.register_lua(&lua)?;
+
+        cvars.apply_config(BTreeMap::from([(
+            "physics.gravity".to_string(),
+            CvarValue::Int(20),
+        )]))?;
+        cvars.register(
+            "physics.gravity",
+            Cvar::new(CvarValue::Float(9.8)).with_range(0.0, 100.0),
+        )?;
+        cvars.register(
+            "debug.draw_colliders",
+            Cvar::new(CvarValue::Bool(false)).cheat(),
+        )?;
+        assert_eq!(cvars.get_f64("physics.gravity"), Some(20.0));
+
+        lua.load(
+            r#"
+            changes = 0
+            cvar.on_change("physics.gravity", function(name, value) changes = changes + 1 end)
+            cvar.set("physics.gravity", 3)
+            assert(not pcall(cvar.set, "physics.gravity", 500))
+            assert(not pcall(cvar.set, "physics.gravity", "high"))
+        "#,
+        )
+        .exec()?;
+        assert_eq!(cvars.get_f64("physics.gravity"), Some(3.0));
+
+        console.execute(&mut world, &lua, "set physics.gravity 4.5")?;
+        assert_eq!(lua.globals().get::<u32>("changes")?, 2);
+        for text in ["NaN", "inf"] {
+            assert!(matches!(
+                cvars.set_str("physics.gravity", text),
+                Err(CvarError::OutOfRange { .. })
+            ));
+        }
+        cvars.register("net.rate", Cvar::new(CvarValue::Int(30)))?;
+        assert!(cvars.set("net.rate", CvarValue::Float(1e300)).is_err());
+        assert!(cvars.set("net.rate", CvarValue::Float(f64::NAN)).is_err());
+        assert_eq!(cvars.get_f64("physics.gravity"), Some(4.5));
+
+        assert!(
+            console
+                .execute(&mut world, &lua, "set debug.draw_colliders on")
+                .is_err()
+        );
+        cvars.set_cheats(true);
+        console.execute(&mut world, &lua, "set debug.draw_colliders on")?;
+        assert_eq!(cvars.get_bool("debug.draw_colliders"), Some(true));
+        Ok(())
+    }
+}
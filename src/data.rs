@@ -0,0 +1,57 @@
+use crate::assets::AssetSource;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum DataError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Invalid(String),
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataError::Io(e) => write!(f, "failed to read data file: {}", e),
+            DataError::Parse(e) => write!(f, "failed to parse data file: {}", e),
+            DataError::Invalid(message) => write!(f, "invalid data: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+impl From<std::io::Error> for DataError {
+    fn from(e: std::io::Error) -> Self {
+        DataError::Io(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for DataError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        DataError::Parse(e)
+    }
+}
+
+impl From<DataError> for mlua::Error {
+    fn from(e: DataError) -> Self {
+        mlua::Error::external(e)
+    }
+}
+
+pub fn from_ron<T: DeserializeOwned>(source: &str) -> Result<T, DataError> {
+    Ok(ron::from_str(source)?)
+}
+
+pub fn load_ron<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, DataError> {
+    from_ron(&std::fs::read_to_string(path)?)
+}
+
+/// `load_ron` through an asset source such as the `Vfs`.
+pub fn load_ron_from<T: DeserializeOwned>(
+    source: &dyn AssetSource,
+    path: &str,
+) -> Result<T, DataError> {
+    from_ron(&source.read_to_string(path)?)
+}
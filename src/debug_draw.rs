@@ -0,0 +1,74 @@
+use crate::color::Color;
+use crate::math::Vec2;
+
+pub const RED: Color = Color::rgb(1.0, 0.2, 0.2);
+pub const GREEN: Color = Color::rgb(0.2, 1.0, 0.2);
+pub const BLUE: Color = Color::rgb(0.3, 0.5, 1.0);
+pub const YELLOW: Color = Color::rgb(1.0, 0.9, 0.2);
+pub const WHITE: Color = Color::WHITE;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugShape {
+    Line {
+        from: Vec2,
+        to: Vec2,
+    },
+    Circle {
+        center: Vec2,
+        radius: f64,
+    },
+    /// Axis-aligned, in world space.
+    Rect {
+        min: Vec2,
+        max: Vec2,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DebugItem {
+    pub shape: DebugShape,
+    pub color: Color,
+}
+
+/// Immediate-mode debug geometry in world space. Anything may add shapes
+/// during a frame; the renderer draws and clears them. Kept as a world
+/// resource.
+#[derive(Debug, Clone, Default)]
+pub struct DebugDraw {
+    items: Vec<DebugItem>,
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        DebugDraw::default()
+    }
+
+    pub fn push(&mut self, shape: DebugShape, color: Color) {
+        self.items.push(DebugItem { shape, color });
+    }
+
+    pub fn line(&mut self, from: Vec2, to: Vec2, color: Color) {
+        self.push(DebugShape::Line { from, to }, color);
+    }
+
+    pub fn circle(&mut self, center: Vec2, radius: f64, color: Color) {
+        self.push(DebugShape::Circle { center, radius }, color);
+    }
+
+    pub fn rect(&mut self, min: Vec2, max: Vec2, color: Color) {
+        self.push(DebugShape::Rect { min, max }, color);
+    }
+
+    pub fn items(&self) -> &[DebugItem] {
+        &self.items
+    }
+
+    /// Takes this frame's shapes, leaving the layer empty for the next.
+    pub fn drain(&mut self) -> Vec<DebugItem> {
+        std::mem::take(&mut self.items)
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
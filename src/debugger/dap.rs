@@ -0,0 +1,247 @@
+//! The wire side of the Debug Adapter Protocol: `Content-Length` framed
+//! JSON over TCP, read on a background thread and handed to the game
+//! thread, which writes responses and events back itself.
+
+use serde_json::{Value as Json, json};
+use std::cell::Cell;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Request {
+    pub seq: i64,
+    pub command: String,
+    pub arguments: Json,
+}
+
+type Client = Arc<Mutex<Option<TcpStream>>>;
+
+/// Accepts one debug client at a time. A client that goes away counts as
+/// a `disconnect` request, so the debugger never waits on a dead socket.
+pub struct DapServer {
+    addr: SocketAddr,
+    requests: Receiver<Request>,
+    client: Client,
+    seq: Cell<i64>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Longest header line and body a client may send; requests are small.
+const MAX_HEADER: u64 = 1024;
+const MAX_MESSAGE: usize = 4 << 20;
+
+/// One framed message, or `None` at the end of the stream.
+pub(crate) fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.by_ref().take(MAX_HEADER).read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.ends_with('\n') {
+            return Err(io::Error::other("header line too long"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::other("message without Content-Length"))?;
+    if length > MAX_MESSAGE {
+        return Err(io::Error::other(format!(
+            "message of {} bytes is too large",
+            length
+        )));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(io::Error::other)
+}
+
+pub(crate) fn write_message(writer: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+fn serve_client(stream: TcpStream, requests: &Sender<Request>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    while let Some(message) = read_message(&mut reader)? {
+        if message["type"] != "request" {
+            continue;
+        }
+        let request = Request {
+            seq: message["seq"].as_i64().unwrap_or(0),
+            command: message["command"].as_str().unwrap_or("").to_string(),
+            arguments: message["arguments"].clone(),
+        };
+        if requests.send(request).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+impl DapServer {
+    pub fn listen(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        // The accept loop checks `stop` between polls; a blocking accept
+        // would keep `Drop` waiting for a client that may never come.
+        listener.set_nonblocking(true)?;
+        let (sender, requests) = mpsc::channel();
+        let client: Client = Arc::new(Mutex::new(None));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (shared, stopping) = (client.clone(), stop.clone());
+        let thread = thread::Builder::new()
+            .name("dap".to_string())
+            .spawn(move || {
+                while !stopping.load(Ordering::Relaxed) {
+                    let stream = match listener.accept() {
+                        Ok((stream, _)) => stream,
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            thread::sleep(Duration::from_millis(20));
+                            continue;
+                        }
+                        Err(e) => {
+                            log::warn!("debug adapter listener: {}", e);
+                            continue;
+                        }
+                    };
+                    let attached = stream.set_nonblocking(false).and_then(|()| {
+                        *shared.lock().unwrap_or_else(|e| e.into_inner()) =
+                            Some(stream.try_clone()?);
+                        Ok(())
+                    });
+                    if let Err(e) = attached.and_then(|()| serve_client(stream, &sender)) {
+                        log::warn!("debug client: {}", e);
+                    }
+                    *shared.lock().unwrap_or_else(|e| e.into_inner()) = None;
+                    let _ = sender.send(Request {
+                        seq: 0,
+                        command: "disconnect".to_string(),
+                        arguments: Json::Null,
+                    });
+                }
+            })?;
+        Ok(DapServer {
+            addr,
+            requests,
+            client,
+            seq: Cell::new(0),
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub(crate) fn try_recv(&self) -> Option<Request> {
+        self.requests.try_recv().ok()
+    }
+
+    /// Blocks for the next request; `None` once the server has stopped.
+    pub(crate) fn recv(&self) -> Option<Request> {
+        self.requests.recv().ok()
+    }
+
+    fn send(&self, mut message: Json) {
+        let seq = self.seq.get() + 1;
+        self.seq.set(seq);
+        message["seq"] = json!(seq);
+        let mut client = self.client.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(stream) = client.as_mut()
+            && let Err(e) = write_message(stream, &message)
+        {
+            log::warn!("debug client: {}", e);
+        }
+    }
+
+    pub(crate) fn respond(&self, request: &Request, body: Json) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "command": request.command,
+            "success": true,
+            "body": body,
+        }));
+    }
+
+    pub(crate) fn fail(&self, request: &Request, message: &str) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "command": request.command,
+            "success": false,
+            "message": message,
+        }));
+    }
+
+    pub(crate) fn event(&self, event: &str, body: Json) {
+        self.send(json!({"type": "event", "event": event, "body": body}));
+    }
+}
+
+impl Drop for DapServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(stream) = self.client.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_are_framed_and_bounded() {
+        let mut wire = Vec::new();
+        write_message(&mut wire, &json!({"command": "threads"})).unwrap();
+        write_message(&mut wire, &json!({"command": "pause"})).unwrap();
+        let mut reader = &wire[..];
+        assert_eq!(
+            read_message(&mut reader).unwrap().unwrap()["command"],
+            "threads"
+        );
+        assert_eq!(
+            read_message(&mut reader).unwrap().unwrap()["command"],
+            "pause"
+        );
+        assert!(read_message(&mut reader).unwrap().is_none());
+
+        let huge = format!("Content-Length: {}\r\n\r\n", MAX_MESSAGE + 1);
+        assert!(read_message(&mut huge.as_bytes()).is_err());
+        let long_header = format!("X-Padding: {}\r\n\r\n", "a".repeat(MAX_HEADER as usize));
+        assert!(read_message(&mut long_header.as_bytes()).is_err());
+        assert!(read_message(&mut &b"\r\n{}"[..]).is_err());
+    }
+
+    #[test]
+    fn test_header_case_and_truncated_bodies() {
+        let wire = "content-length: 2\r\nContent-Type: json\r\n\r\n{}";
+        assert_eq!(read_message(&mut wire.as_bytes()).unwrap(), Some(json!({})));
+        let truncated = "Content-Length: 10\r\n\r\n{}";
+        assert!(read_message(&mut truncated.as_bytes()).is_err());
+        assert!(read_message(&mut &b""[..]).unwrap().is_none());
+    }
+}
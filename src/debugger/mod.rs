@@ -0,0 +1,11 @@
+#[cfg(feature = "dap")]
+mod dap;
+#[cfg(feature = "dap")]
+mod session;
+pub(crate) mod watch;
+
+#[cfg(feature = "dap")]
+pub use dap::DapServer;
+#[cfg(feature = "dap")]
+pub use session::ScriptDebugger;
+pub use watch::{DataWatch, WatchAction, WatchCondition, WatchHit};
@@ -0,0 +1,738 @@
+use super::dap::{DapServer, Request};
+use mlua::{Compiler, Lua, MultiValue, Result, Value, WeakLua, ffi};
+use serde_json::{Value as Json, json};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::{CStr, c_int};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+/// Wraps `error` and `assert` so a raise can stop before the stack
+/// unwinds; Luau only reports errors to a hook inside coroutines, and
+/// then only caught ones. Levels are bumped past the wrapper so messages
+/// still point at the script.
+const ERROR_WRAPPERS: &str = r##"
+local raw_error, pause = error, ...
+function error(message, level)
+    pause(message)
+    level = level or 1
+    raw_error(message, if level > 0 then level + 1 else 0)
+end
+function assert(ok, ...)
+    if not ok then
+        local message = if select("#", ...) > 0 then (...) else "assertion failed!"
+        pause(message)
+        raw_error(message, 2)
+    end
+    return ok, ...
+end
+"##;
+
+/// How many instructions run between checks for a `pause` request.
+const POLL_INTERVAL: u32 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Run,
+    Pause,
+    StepIn,
+    /// Stack depths the step started at.
+    StepOver(c_int),
+    StepOut(c_int),
+}
+
+struct Frame {
+    level: c_int,
+    name: String,
+    chunk: String,
+    line: c_int,
+}
+
+/// A DAP `variablesReference` points at one of these, less one.
+enum Handle {
+    Locals(c_int),
+    Value(Value),
+}
+
+/// The stopped thread and what the client has been shown of it; only
+/// valid until execution resumes.
+struct Stop<'a> {
+    lua: &'a Lua,
+    thread: *mut ffi::lua_State,
+    depth: c_int,
+    frames: Vec<Frame>,
+    handles: Vec<Handle>,
+}
+
+enum Flow {
+    Stay,
+    Resume,
+}
+
+struct Session {
+    lua: WeakLua,
+    server: DapServer,
+    connected: bool,
+    source_root: PathBuf,
+    /// Lines by script path relative to the source root.
+    breakpoints: BTreeMap<String, BTreeSet<c_int>>,
+    pause_on_error: bool,
+    mode: Mode,
+    /// Source and line last seen at each stack depth, so a line only
+    /// counts once however many instructions it has.
+    lines: Vec<(usize, c_int)>,
+    countdown: u32,
+}
+
+/// Debugs the scripts of one Lua state for a DAP client such as VS Code,
+/// connecting with `debugServer` set to the server's port. Breakpoints,
+/// stepping and locals work through Luau's single-step hook, which runs
+/// every script in the interpreter with the JIT off and full debug info,
+/// so attach only in development builds and before loading scripts. The
+/// hook only runs while a client is connected. With the `error` exception
+/// filter on, `error` and `assert` stop before raising unless a `pcall`
+/// will catch them.
+#[derive(Clone)]
+pub struct ScriptDebugger {
+    session: Rc<RefCell<Session>>,
+}
+
+fn chunk_name(source: *const std::ffi::c_char) -> String {
+    if source.is_null() {
+        return "?".to_string();
+    }
+    let source = unsafe { CStr::from_ptr(source) }.to_string_lossy();
+    let source = source.trim_start_matches(['@', '=']);
+    source.trim_start_matches("./").to_string()
+}
+
+/// Engine glue such as the wrappers above and mlua's own chunks.
+fn hidden(chunk: &str) -> bool {
+    chunk.starts_with("__")
+}
+
+/// Whether a `pcall` or `xpcall` on `thread` will catch an error raised
+/// now.
+fn caught(thread: *mut ffi::lua_State) -> bool {
+    let mut ar: ffi::lua_Debug = unsafe { std::mem::zeroed() };
+    (0..)
+        .map_while(|level| {
+            let found = unsafe { ffi::lua_getinfo(thread, level, c"sn".as_ptr(), &mut ar) };
+            (found != 0).then(|| {
+                let what = unsafe { CStr::from_ptr(ar.what) };
+                let name = (!ar.name.is_null()).then(|| unsafe { CStr::from_ptr(ar.name) });
+                what == c"C" && matches!(name, Some(name) if name == c"pcall" || name == c"xpcall")
+            })
+        })
+        .any(|protected| protected)
+}
+
+/// Script frames on `thread`, innermost first.
+fn frames(thread: *mut ffi::lua_State) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut ar: ffi::lua_Debug = unsafe { std::mem::zeroed() };
+    for level in 0.. {
+        if unsafe { ffi::lua_getinfo(thread, level, c"sln".as_ptr(), &mut ar) } == 0 {
+            break;
+        }
+        let what = unsafe { CStr::from_ptr(ar.what) };
+        let chunk = chunk_name(ar.source);
+        if what == c"C" || hidden(&chunk) {
+            continue;
+        }
+        let name = match (ar.name.is_null(), what == c"main") {
+            (false, _) => unsafe { CStr::from_ptr(ar.name) }
+                .to_string_lossy()
+                .into_owned(),
+            (true, true) => "<main>".to_string(),
+            (true, false) => "<anonymous>".to_string(),
+        };
+        frames.push(Frame {
+            level,
+            name,
+            chunk,
+            line: ar.currentline,
+        });
+    }
+    frames
+}
+
+/// Named locals of the frame at `level`, moved onto the state mlua reads
+/// results from. That state gains a frame for the call when it is the
+/// stopped thread itself.
+fn locals(lua: &Lua, thread: *mut ffi::lua_State, level: c_int) -> Result<Vec<(String, Value)>> {
+    let mut names = Vec::new();
+    let values: MultiValue = unsafe {
+        lua.exec_raw((), |state| {
+            let level = if state == thread { level + 1 } else { level };
+            for n in 1.. {
+                if ffi::lua_checkstack(state, 2) == 0 {
+                    break;
+                }
+                let name = ffi::lua_getlocal(thread, level, n);
+                if name.is_null() {
+                    break;
+                }
+                names.push(CStr::from_ptr(name).to_string_lossy().into_owned());
+                ffi::lua_xmove(thread, state, 1);
+            }
+        })
+    }?;
+    Ok(names
+        .into_iter()
+        .zip(values)
+        // Luau names compiler temporaries `(for index)` and the like.
+        .filter(|(name, _)| !name.starts_with('('))
+        .collect())
+}
+
+/// A short rendering that never runs metamethods.
+fn preview(value: &Value) -> String {
+    match value {
+        Value::Nil => "nil".to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => format!("{:?}", s.to_string_lossy()),
+        Value::Table(t) => format!("table ({} items)", t.raw_len()),
+        other => other.type_name().to_string(),
+    }
+}
+
+impl Stop<'_> {
+    fn variable(&mut self, name: String, value: Value) -> Json {
+        let reference = match &value {
+            Value::Table(_) => {
+                self.handles.push(Handle::Value(value.clone()));
+                self.handles.len()
+            }
+            _ => 0,
+        };
+        json!({
+            "name": name,
+            "value": preview(&value),
+            "type": value.type_name(),
+            "variablesReference": reference,
+        })
+    }
+
+    fn variables(&mut self, reference: usize) -> Result<Vec<Json>> {
+        let entries = match reference.checked_sub(1).and_then(|i| self.handles.get(i)) {
+            Some(Handle::Locals(level)) => locals(self.lua, self.thread, *level)?,
+            Some(Handle::Value(Value::Table(table))) => {
+                let mut entries = Vec::new();
+                for pair in table.pairs::<Value, Value>() {
+                    let (key, value) = pair?;
+                    let name = match &key {
+                        Value::String(s) => s.to_string_lossy(),
+                        other => format!("[{}]", preview(other)),
+                    };
+                    entries.push((key.as_i64(), name, value));
+                }
+                entries.sort_by(|a, b| (a.0.is_none(), a.0, &a.1).cmp(&(b.0.is_none(), b.0, &b.1)));
+                entries
+                    .into_iter()
+                    .map(|(_, name, value)| (name, value))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        Ok(entries
+            .into_iter()
+            .map(|(name, value)| self.variable(name, value))
+            .collect())
+    }
+}
+
+impl Session {
+    /// Turns the single-step hook on or off for the running thread and
+    /// the main one, which new threads copy it from.
+    fn set_stepping(&self, enabled: bool) {
+        let Some(lua) = self.lua.try_upgrade() else {
+            return;
+        };
+        let enabled = enabled as c_int;
+        let stepping = unsafe {
+            lua.exec_raw::<()>((), |state| {
+                ffi::lua_singlestep(state, enabled);
+                ffi::lua_singlestep(ffi::lua_mainthread(state), enabled);
+            })
+        };
+        if let Err(e) = stepping {
+            log::warn!("script debugger: {}", e);
+        }
+    }
+
+    /// Whether the hook has to follow lines at all.
+    fn tracking(&self) -> bool {
+        self.mode != Mode::Run || !self.breakpoints.is_empty()
+    }
+
+    fn has_breakpoint(&self, chunk: &str, line: c_int) -> bool {
+        self.breakpoints.iter().any(|(path, lines)| {
+            lines.contains(&line)
+                && (path == chunk
+                    || path
+                        .strip_suffix(chunk)
+                        .is_some_and(|parent| parent.ends_with('/')))
+        })
+    }
+
+    /// A client path as a `/`-separated path under the source root.
+    fn script_path(&self, path: &str) -> String {
+        let path = Path::new(path);
+        let relative = path.strip_prefix(&self.source_root).unwrap_or(path);
+        relative
+            .components()
+            .filter_map(|part| match part {
+                Component::Normal(part) => Some(part.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn handle(&mut self, request: &Request, stop: Option<&mut Stop<'_>>) -> Flow {
+        let args = &request.arguments;
+        match request.command.as_str() {
+            "initialize" => {
+                self.connected = true;
+                self.set_stepping(true);
+                self.server.respond(
+                    request,
+                    json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "exceptionBreakpointFilters": [
+                            {"filter": "error", "label": "Script errors", "default": true},
+                        ],
+                    }),
+                );
+                self.server.event("initialized", json!({}));
+            }
+            "launch" | "attach" | "configurationDone" => self.server.respond(request, json!({})),
+            "setBreakpoints" => {
+                let source = &args["source"];
+                let path = source["path"].as_str().or(source["name"].as_str());
+                let Some(path) = path.map(|path| self.script_path(path)) else {
+                    self.server.fail(request, "breakpoints need a source path");
+                    return Flow::Stay;
+                };
+                let lines: BTreeSet<c_int> = args["breakpoints"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|breakpoint| breakpoint["line"].as_i64())
+                    .map(|line| line as c_int)
+                    .collect();
+                let verified: Vec<Json> = lines
+                    .iter()
+                    .map(|line| json!({"verified": true, "line": line}))
+                    .collect();
+                if lines.is_empty() {
+                    self.breakpoints.remove(&path);
+                } else {
+                    self.breakpoints.insert(path, lines);
+                }
+                self.server
+                    .respond(request, json!({"breakpoints": verified}));
+            }
+            "setExceptionBreakpoints" => {
+                self.pause_on_error = args["filters"]
+                    .as_array()
+                    .is_some_and(|filters| filters.iter().any(|filter| filter == "error"));
+                self.server.respond(request, json!({}));
+            }
+            "threads" => self
+                .server
+                .respond(request, json!({"threads": [{"id": 1, "name": "main"}]})),
+            "pause" => {
+                self.mode = Mode::Pause;
+                self.server.respond(request, json!({}));
+            }
+            "disconnect" => {
+                self.connected = false;
+                self.set_stepping(false);
+                self.breakpoints.clear();
+                self.mode = Mode::Run;
+                self.server.respond(request, json!({}));
+                return Flow::Resume;
+            }
+            command => {
+                let Some(stop) = stop else {
+                    let message = format!("'{}' needs the scripts to be stopped", command);
+                    self.server.fail(request, &message);
+                    return Flow::Stay;
+                };
+                return self.handle_stopped(request, stop);
+            }
+        }
+        Flow::Stay
+    }
+
+    fn handle_stopped(&mut self, request: &Request, stop: &mut Stop<'_>) -> Flow {
+        let args = &request.arguments;
+        let resume = match request.command.as_str() {
+            "continue" => Mode::Run,
+            "next" => Mode::StepOver(stop.depth),
+            "stepIn" => Mode::StepIn,
+            "stepOut" => Mode::StepOut(stop.depth),
+            "stackTrace" => {
+                let frames: Vec<Json> = stop
+                    .frames
+                    .iter()
+                    .enumerate()
+                    .map(|(id, frame)| {
+                        json!({
+                            "id": id,
+                            "name": frame.name,
+                            "source": {
+                                "name": frame.chunk,
+                                "path": self.source_root.join(&frame.chunk),
+                            },
+                            "line": frame.line,
+                            "column": 1,
+                        })
+                    })
+                    .collect();
+                let total = frames.len();
+                self.server.respond(
+                    request,
+                    json!({"stackFrames": frames, "totalFrames": total}),
+                );
+                return Flow::Stay;
+            }
+            "scopes" => {
+                let frame = args["frameId"].as_u64().unwrap_or(0) as usize;
+                let Some(level) = stop.frames.get(frame).map(|frame| frame.level) else {
+                    self.server.fail(request, "no such frame");
+                    return Flow::Stay;
+                };
+                stop.handles.push(Handle::Locals(level));
+                let reference = stop.handles.len();
+                self.server.respond(
+                    request,
+                    json!({"scopes": [
+                        {"name": "Locals", "variablesReference": reference, "expensive": false},
+                    ]}),
+                );
+                return Flow::Stay;
+            }
+            "variables" => {
+                let reference = args["variablesReference"].as_u64().unwrap_or(0) as usize;
+                match stop.variables(reference) {
+                    Ok(variables) => self
+                        .server
+                        .respond(request, json!({"variables": variables})),
+                    Err(e) => self.server.fail(request, &e.to_string()),
+                }
+                return Flow::Stay;
+            }
+            command => {
+                self.server
+                    .fail(request, &format!("unsupported request '{}'", command));
+                return Flow::Stay;
+            }
+        };
+        self.mode = resume;
+        let body = match resume {
+            Mode::Run => json!({"allThreadsContinued": true}),
+            _ => json!({}),
+        };
+        self.server.respond(request, body);
+        Flow::Resume
+    }
+
+    /// Reports the stop and serves the client until it resumes.
+    fn stop(&mut self, lua: &Lua, thread: *mut ffi::lua_State, reason: &str, text: Option<String>) {
+        let mut stop = Stop {
+            lua,
+            thread,
+            depth: unsafe { ffi::lua_stackdepth(thread) },
+            frames: frames(thread),
+            handles: Vec::new(),
+        };
+        self.mode = Mode::Run;
+        self.server.event(
+            "stopped",
+            json!({"reason": reason, "threadId": 1, "allThreadsStopped": true, "text": text}),
+        );
+        while let Some(request) = self.server.recv() {
+            if let Flow::Resume = self.handle(&request, Some(&mut stop)) {
+                break;
+            }
+        }
+    }
+
+    fn poll(&mut self) {
+        while let Some(request) = self.server.try_recv() {
+            self.handle(&request, None);
+        }
+    }
+
+    fn step(&mut self, lua: &Lua, thread: *mut ffi::lua_State) {
+        self.countdown = self.countdown.saturating_sub(1);
+        if self.countdown == 0 {
+            self.countdown = POLL_INTERVAL;
+            self.poll();
+        }
+        if !self.connected || !self.tracking() {
+            return;
+        }
+        let mut ar: ffi::lua_Debug = unsafe { std::mem::zeroed() };
+        if unsafe { ffi::lua_getinfo(thread, 0, c"sl".as_ptr(), &mut ar) } == 0 {
+            return;
+        }
+        let depth = unsafe { ffi::lua_stackdepth(thread) };
+        let slot = depth.max(1) as usize - 1;
+        let seen = (ar.source as usize, ar.currentline);
+        self.lines.truncate(slot + 1);
+        self.lines.resize(slot + 1, (0, -1));
+        if self.lines[slot] == seen {
+            return;
+        }
+        self.lines[slot] = seen;
+        let chunk = chunk_name(ar.source);
+        if hidden(&chunk) {
+            return;
+        }
+        let reason = match self.mode {
+            Mode::Pause => Some("pause"),
+            Mode::StepIn => Some("step"),
+            Mode::StepOver(from) if depth <= from => Some("step"),
+            Mode::StepOut(from) if depth < from => Some("step"),
+            _ => None,
+        }
+        .or_else(|| {
+            self.has_breakpoint(&chunk, ar.currentline)
+                .then_some("breakpoint")
+        });
+        if let Some(reason) = reason {
+            self.stop(lua, thread, reason, None);
+        }
+    }
+}
+
+unsafe extern "C-unwind" fn debug_step(thread: *mut ffi::lua_State, _: *mut ffi::lua_Debug) {
+    let lua = unsafe { Lua::get_or_init_from_ptr(thread) };
+    let Some(debugger) = lua.app_data_ref::<ScriptDebugger>().map(|d| d.clone()) else {
+        return;
+    };
+    // Nothing may unwind into the VM.
+    let _ = catch_unwind(AssertUnwindSafe(|| {
+        if let Ok(mut session) = debugger.session.try_borrow_mut() {
+            session.step(lua, thread);
+        }
+    }));
+}
+
+impl ScriptDebugger {
+    /// Hooks `lua` up to the clients of `server`.
+    pub fn attach(lua: &Lua, server: DapServer) -> Result<Self> {
+        let debugger = ScriptDebugger {
+            session: Rc::new(RefCell::new(Session {
+                lua: lua.weak(),
+                server,
+                connected: false,
+                source_root: PathBuf::new(),
+                breakpoints: BTreeMap::new(),
+                pause_on_error: true,
+                mode: Mode::Run,
+                lines: Vec::new(),
+                countdown: POLL_INTERVAL,
+            })),
+        };
+        lua.set_app_data(debugger.clone());
+        // Local names only survive at debug level 2, and Luau won't read
+        // locals of natively compiled functions.
+        lua.set_compiler(Compiler::new().set_debug_level(2));
+        lua.enable_jit(false);
+        unsafe {
+            lua.exec_raw::<()>((), |state| {
+                (*ffi::lua_callbacks(state)).debugstep = Some(debug_step);
+            })?;
+        }
+
+        let session = debugger.session.clone();
+        let pause = lua.create_function(move |lua, message: Value| {
+            let Ok(mut session) = session.try_borrow_mut() else {
+                return Ok(());
+            };
+            if session.connected && session.pause_on_error {
+                let mut thread = std::ptr::null_mut();
+                unsafe { lua.exec_raw::<()>((), |state| thread = state)? };
+                if caught(thread) {
+                    return Ok(());
+                }
+                let text = match &message {
+                    Value::String(s) => s.to_string_lossy(),
+                    other => preview(other),
+                };
+                session.stop(lua, thread, "exception", Some(text));
+            }
+            Ok(())
+        })?;
+        lua.load(ERROR_WRAPPERS)
+            .set_name("=__debugger")
+            .call::<()>(pause)?;
+        Ok(debugger)
+    }
+
+    /// Where client paths are taken relative to, matching script chunk
+    /// names such as `scripts/ai.lua`. The working directory by default.
+    pub fn set_source_root(&self, root: impl Into<PathBuf>) {
+        self.session.borrow_mut().source_root = root.into();
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.session.borrow().connected
+    }
+
+    /// Handles requests that arrived while no script was running; call
+    /// once a frame.
+    pub fn poll(&self) {
+        if let Ok(mut session) = self.session.try_borrow_mut() {
+            session.poll();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::dap::{read_message, write_message};
+    use std::io::BufReader;
+    use std::net::TcpStream;
+    use std::sync::mpsc;
+
+    struct Client {
+        stream: TcpStream,
+        reader: BufReader<TcpStream>,
+        seq: i64,
+    }
+
+    impl Client {
+        fn send(&mut self, command: &str, arguments: Json) -> Json {
+            self.seq += 1;
+            let request = json!({
+                "seq": self.seq, "type": "request", "command": command, "arguments": arguments,
+            });
+            write_message(&mut self.stream, &request).unwrap();
+            loop {
+                let message = read_message(&mut self.reader).unwrap().unwrap();
+                if message["type"] == "response" && message["request_seq"] == self.seq {
+                    assert_eq!(message["success"], true, "{}", message);
+                    return message["body"].clone();
+                }
+            }
+        }
+
+        fn stopped(&mut self) -> Json {
+            loop {
+                let message = read_message(&mut self.reader).unwrap().unwrap();
+                if message["event"] == "stopped" {
+                    return message["body"].clone();
+                }
+            }
+        }
+
+        fn local(&mut self, name: &str) -> String {
+            let frames = self.send("stackTrace", json!({"threadId": 1}));
+            let frame = frames["stackFrames"][0]["id"].clone();
+            let scopes = self.send("scopes", json!({"frameId": frame}));
+            let reference = scopes["scopes"][0]["variablesReference"].clone();
+            let variables = self.send("variables", json!({"variablesReference": reference}));
+            variables["variables"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|v| v["name"] == name)
+                .map(|v| v["value"].as_str().unwrap().to_string())
+                .unwrap_or_default()
+        }
+    }
+
+    #[test]
+    fn test_breakpoints_steps_and_errors() -> Result<()> {
+        let lua = Lua::new();
+        let server = DapServer::listen("127.0.0.1:0").unwrap();
+        let addr = server.local_addr();
+        let debugger = ScriptDebugger::attach(&lua, server)?;
+        debugger.set_source_root("/game");
+
+        let (configured, ready) = mpsc::channel();
+        let client = std::thread::spawn(move || {
+            let stream = TcpStream::connect(addr).unwrap();
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            let mut client = Client {
+                stream,
+                reader,
+                seq: 0,
+            };
+            client.send("initialize", json!({"adapterID": "entity-engine"}));
+            client.send(
+                "setBreakpoints",
+                json!({"source": {"path": "/game/scripts/ai.lua"}, "breakpoints": [{"line": 3}]}),
+            );
+            client.send("setExceptionBreakpoints", json!({"filters": ["error"]}));
+            client.send("configurationDone", json!({}));
+            configured.send(()).unwrap();
+
+            assert_eq!(client.stopped()["reason"], "breakpoint");
+            let frames = client.send("stackTrace", json!({"threadId": 1}));
+            assert_eq!(frames["stackFrames"][0]["name"], "think");
+            assert_eq!(frames["stackFrames"][0]["line"], 3);
+            assert_eq!(frames["stackFrames"][1]["line"], 8);
+            assert_eq!(client.local("hp"), "7");
+            assert_eq!(client.local("target"), "\"orc\"");
+
+            client.send("next", json!({"threadId": 1}));
+            assert_eq!(client.stopped()["reason"], "step");
+            assert_eq!(client.local("hp"), "6");
+            client.send("continue", json!({"threadId": 1}));
+
+            let stopped = client.stopped();
+            assert_eq!(stopped["reason"], "exception");
+            assert_eq!(stopped["text"], "out of range");
+            let frames = client.send("stackTrace", json!({"threadId": 1}));
+            assert_eq!(frames["stackFrames"][0]["name"], "fail");
+            assert_eq!(frames["stackFrames"][0]["line"], 17);
+            client.send("continue", json!({"threadId": 1}));
+        });
+
+        while ready.try_recv().is_err() {
+            debugger.poll();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert!(debugger.is_connected());
+        lua.load(
+            r#"
+            local function think(target, hp)
+                hp = hp - 1
+                return target .. hp
+            end
+
+            function run()
+                local said = think("orc", 7)
+                assert(said == "orc6", said)
+                local ok, e = pcall(function()
+                    error("out of range", 0)
+                end)
+                return e
+            end
+
+            function fail(n)
+                assert(n > 0, "out of range")
+            end
+            "#,
+        )
+        .set_name("@scripts/ai.lua")
+        .exec()?;
+        let message: String = lua.load("return run()").eval()?;
+        // Caught by the pcall, so it never stops.
+        assert_eq!(message, "out of range");
+        assert!(lua.load("fail(0)").exec().is_err());
+        client.join().unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,514 @@
+use crate::console::Console;
+use crate::ecs::{Entity, ScriptValue, World};
+use crate::time::TimeScale;
+use mlua::{Function, Lua, LuaSerdeExt, Result, Table, UserData, UserDataMethods, Value};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchAction {
+    /// Logs the change with a traceback or the system that made it.
+    Log,
+    /// Logs, then pauses the `TimeScale` resource.
+    Pause,
+}
+
+#[derive(Clone)]
+pub enum WatchCondition {
+    /// Any change.
+    Changed,
+    Equals(ScriptValue),
+    /// Called as `f(new, old)`; a truthy result matches.
+    Lua(Function),
+}
+
+struct Watch {
+    id: u32,
+    entity: Entity,
+    component: String,
+    /// Fields below the component, `["current"]` for `Health.current`.
+    path: Vec<String>,
+    condition: WatchCondition,
+    action: WatchAction,
+    /// The value last seen, `None` until it is first read.
+    last: Option<Option<ScriptValue>>,
+}
+
+impl Watch {
+    fn field(&self) -> String {
+        std::iter::once(self.component.as_str())
+            .chain(self.path.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    fn read(&self, world: &World, lua: &Lua) -> Result<Option<ScriptValue>> {
+        let value = world.get_by_name(lua, self.entity, &self.component)?;
+        if value.is_nil() {
+            return Ok(None);
+        }
+        let mut value: ScriptValue = lua.from_value(value)?;
+        for key in &self.path {
+            value = match value {
+                ScriptValue::Map(mut fields) => match fields.remove(key) {
+                    Some(field) => field,
+                    None => return Ok(None),
+                },
+                ScriptValue::List(mut items) => {
+                    let index = key
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&i| i >= 1 && i <= items.len());
+                    match index {
+                        Some(i) => items.swap_remove(i - 1),
+                        None => return Ok(None),
+                    }
+                }
+                _ => return Ok(None),
+            };
+        }
+        Ok(Some(value))
+    }
+}
+
+/// A watched field changing to a matching value. Sent as a world event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchHit {
+    pub watch: u32,
+    pub entity: Entity,
+    /// `Health.current`.
+    pub field: String,
+    pub old: Option<ScriptValue>,
+    pub new: Option<ScriptValue>,
+    /// The script traceback for writes through `world:set`, otherwise the
+    /// system that ran when the change was noticed.
+    pub source: String,
+}
+
+#[derive(Default)]
+struct WatchState {
+    watches: Vec<Watch>,
+    next_id: u32,
+}
+
+/// Data breakpoints on component fields, for finding out who set a value.
+/// Script writes through `world:set` and `world:remove` are checked as
+/// they happen, with the writer's traceback; anything else, such as a Rust
+/// system, is caught by comparing values around each system the schedule
+/// runs. Lives in the Lua state's app data once registered; clones share
+/// the watches.
+#[derive(Clone, Default)]
+pub struct DataWatch {
+    state: Rc<RefCell<WatchState>>,
+}
+
+/// Where a check came from, for `WatchHit::source`.
+enum Source<'a> {
+    Script,
+    System(&'a str),
+    Outside,
+}
+
+impl DataWatch {
+    pub fn new() -> Self {
+        DataWatch::default()
+    }
+
+    /// `field` is a component name, optionally followed by dotted field
+    /// names or 1-based list indices.
+    pub fn watch(
+        &self,
+        entity: Entity,
+        field: &str,
+        condition: WatchCondition,
+        action: WatchAction,
+    ) -> u32 {
+        let mut parts = field.split('.').map(str::to_string);
+        let component = parts.next().unwrap_or_default();
+        let mut state = self.state.borrow_mut();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.watches.push(Watch {
+            id,
+            entity,
+            component,
+            path: parts.collect(),
+            condition,
+            action,
+            last: None,
+        });
+        id
+    }
+
+    pub fn unwatch(&self, id: u32) -> bool {
+        let mut state = self.state.borrow_mut();
+        let before = state.watches.len();
+        state.watches.retain(|watch| watch.id != id);
+        state.watches.len() != before
+    }
+
+    /// Ids with their entity and field, in the order they were added.
+    pub fn watches(&self) -> Vec<(u32, Entity, String)> {
+        let state = self.state.borrow();
+        state
+            .watches
+            .iter()
+            .map(|watch| (watch.id, watch.entity, watch.field()))
+            .collect()
+    }
+
+    /// Stores the watch in `lua`'s app data, where the world bindings and
+    /// schedule find it, and adds the `debugger` global with
+    /// `watch(entity, field, match?, action?)` returning an id, where
+    /// `match` is a function or a value to compare with and `action` is
+    /// `"log"` or `"pause"`, plus `unwatch(id)`.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.set_app_data(self.clone());
+        lua.globals().set("debugger", self.clone())
+    }
+
+    /// Adds `watch <entity> <field> [value] [pause]`, `unwatch <id>` and
+    /// `watches`. Entities are given by name, bits or `3v0`.
+    pub fn register_console(&self, console: &Console) {
+        let watch = self.clone();
+        console.register_rust(
+            "watch",
+            "watch <entity> <field> [value] [pause]",
+            move |world, _, args| {
+                let (entity, field, rest) = match args {
+                    [entity, field, rest @ ..] => (entity, field, rest),
+                    _ => {
+                        return Err(mlua::Error::runtime(
+                            "usage: watch <entity> <field> [value] [pause]",
+                        ));
+                    }
+                };
+                let entity = parse_entity(world, entity)
+                    .ok_or_else(|| mlua::Error::runtime(format!("no entity '{}'", entity)))?;
+                let (action, rest) = match rest {
+                    [value @ .., last] if last == "pause" => (WatchAction::Pause, value),
+                    rest => (WatchAction::Log, rest),
+                };
+                let condition = match rest {
+                    [] => WatchCondition::Changed,
+                    [value] => WatchCondition::Equals(parse_value(value)),
+                    _ => return Err(mlua::Error::runtime("watch takes one value")),
+                };
+                let id = watch.watch(entity, field, condition, action);
+                Ok(Some(format!("watch {} on {} {}", id, entity, field)))
+            },
+        );
+        let watch = self.clone();
+        console.register_rust("unwatch", "unwatch <id>", move |_, _, args| {
+            let id = match args {
+                [id] => id.parse::<u32>().ok(),
+                _ => None,
+            };
+            let id = id.ok_or_else(|| mlua::Error::runtime("usage: unwatch <id>"))?;
+            let removed = watch.unwatch(id);
+            Ok(Some(
+                if removed { "removed" } else { "no such watch" }.into(),
+            ))
+        });
+        let watch = self.clone();
+        console.register_rust("watches", "lists data watches", move |_, _, _| {
+            let lines: Vec<String> = watch
+                .watches()
+                .into_iter()
+                .map(|(id, entity, field)| format!("{}: {} {}", id, entity, field))
+                .collect();
+            Ok(Some(lines.join("\n")))
+        });
+    }
+
+    /// Compares watched values, optionally only one entity's component,
+    /// with what was last seen and reports matching changes. Unseen values
+    /// only become the baseline.
+    fn check(
+        &self,
+        world: &mut World,
+        lua: &Lua,
+        only: Option<(Entity, &str)>,
+        source: Source<'_>,
+    ) -> Result<()> {
+        let mut changes = Vec::new();
+        {
+            let mut state = self.state.borrow_mut();
+            state.watches.retain_mut(|watch| {
+                if let Some((entity, component)) = only
+                    && (watch.entity != entity || watch.component != component)
+                {
+                    return true;
+                }
+                // A value that can't be read now won't be on the next
+                // check either, so the watch is dropped with a warning.
+                let new = match watch.read(world, lua) {
+                    Ok(new) => new,
+                    Err(e) => {
+                        log::warn!(target: "watch", "dropping watch {} on {}: {}", watch.id, watch.field(), e);
+                        return false;
+                    }
+                };
+                match watch.last.replace(new.clone()) {
+                    Some(old) if old != new => {
+                        let hit = WatchHit {
+                            watch: watch.id,
+                            entity: watch.entity,
+                            field: watch.field(),
+                            old,
+                            new,
+                            source: String::new(),
+                        };
+                        changes.push((hit, watch.condition.clone(), watch.action));
+                    }
+                    _ => {}
+                }
+                true
+            });
+        }
+        // Conditions run with the state released, so they may add watches.
+        for (mut hit, condition, action) in changes {
+            let matched = match condition {
+                WatchCondition::Changed => true,
+                WatchCondition::Equals(value) => hit.new.as_ref() == Some(&value),
+                WatchCondition::Lua(function) => {
+                    let to_lua = |value: &Option<ScriptValue>| {
+                        value.as_ref().map(|v| lua.to_value(v)).transpose()
+                    };
+                    match function.call::<Value>((to_lua(&hit.new)?, to_lua(&hit.old)?)) {
+                        Ok(result) => !matches!(result, Value::Nil | Value::Boolean(false)),
+                        Err(e) => {
+                            log::warn!(target: "watch", "watch {} condition failed: {}", hit.watch, e);
+                            false
+                        }
+                    }
+                }
+            };
+            if !matched {
+                continue;
+            }
+            hit.source = match source {
+                Source::Script => traceback(lua)?,
+                Source::System(name) => format!("system '{}'", name),
+                Source::Outside => "outside the schedule".to_string(),
+            };
+            let show = |value: &Option<ScriptValue>| match value {
+                Some(value) => serde_json::to_string(value).unwrap_or_default(),
+                None => "nothing".to_string(),
+            };
+            log::warn!(
+                target: "watch",
+                "watch {}: {} of {} changed from {} to {} in {}",
+                hit.watch,
+                hit.field,
+                hit.entity,
+                show(&hit.old),
+                show(&hit.new),
+                hit.source
+            );
+            if action == WatchAction::Pause
+                && let Some(scale) = world.resource::<TimeScale>()
+            {
+                scale.pause();
+            }
+            world.send_event(hit);
+        }
+        Ok(())
+    }
+}
+
+fn traceback(lua: &Lua) -> Result<String> {
+    let debug: Table = lua.globals().get("debug")?;
+    let traceback: Function = debug.get("traceback")?;
+    let trace: String = traceback.call(())?;
+    Ok(trace.trim_end().to_string())
+}
+
+fn parse_entity(world: &World, text: &str) -> Option<Entity> {
+    let entity = match text.split_once('v') {
+        Some((index, generation)) if index.parse::<u32>().is_ok() => {
+            let (index, generation) = (index.parse::<u64>().ok()?, generation.parse::<u64>().ok()?);
+            Entity::from_bits(generation << 32 | index)
+        }
+        _ => match text.parse::<u64>() {
+            Ok(bits) => Entity::from_bits(bits),
+            Err(_) => world.find(text),
+        },
+    };
+    entity.filter(|&entity| world.is_alive(entity))
+}
+
+fn parse_value(text: &str) -> ScriptValue {
+    match text {
+        "true" => ScriptValue::Bool(true),
+        "false" => ScriptValue::Bool(false),
+        text => match text.parse::<f64>() {
+            Ok(n) => ScriptValue::Number(n),
+            Err(_) => ScriptValue::String(text.to_string()),
+        },
+    }
+}
+
+fn registered(lua: &Lua) -> Option<DataWatch> {
+    let watch = lua.app_data_ref::<DataWatch>()?;
+    (!watch.state.borrow().watches.is_empty()).then(|| watch.clone())
+}
+
+/// Before a script write to `component` on `entity`, so the change is
+/// measured from the value it replaces.
+pub(crate) fn before_write(
+    world: &mut World,
+    lua: &Lua,
+    entity: Entity,
+    component: &str,
+) -> Result<()> {
+    match registered(lua) {
+        Some(watch) => watch.check(world, lua, Some((entity, component)), Source::Outside),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn after_write(
+    world: &mut World,
+    lua: &Lua,
+    entity: Entity,
+    component: &str,
+) -> Result<()> {
+    match registered(lua) {
+        Some(watch) => watch.check(world, lua, Some((entity, component)), Source::Script),
+        None => Ok(()),
+    }
+}
+
+/// Around each system the schedule runs: `None` before, to pick up
+/// changes made between systems, and the system's name after.
+pub(crate) fn around_system(world: &mut World, lua: &Lua, system: Option<&str>) -> Result<()> {
+    let Some(watch) = registered(lua) else {
+        return Ok(());
+    };
+    let source = match system {
+        Some(name) => Source::System(name),
+        None => Source::Outside,
+    };
+    watch.check(world, lua, None, source)
+}
+
+impl UserData for DataWatch {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method(
+            "watch",
+            |lua, this, (entity, field, matcher, action): (Entity, String, Value, Option<String>)| {
+                let condition = match matcher {
+                    Value::Nil => WatchCondition::Changed,
+                    Value::Function(function) => WatchCondition::Lua(function),
+                    value => WatchCondition::Equals(lua.from_value(value)?),
+                };
+                let action = match action.as_deref() {
+                    None | Some("log") => WatchAction::Log,
+                    Some("pause") => WatchAction::Pause,
+                    Some(other) => {
+                        return Err(mlua::Error::runtime(format!(
+                            "unknown watch action '{}', expected \"log\" or \"pause\"",
+                            other
+                        )));
+                    }
+                };
+                Ok(this.watch(entity, &field, condition, action))
+            },
+        );
+        methods.add_method("unwatch", |_, this, id: u32| Ok(this.unwatch(id)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::Schedule;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct Health {
+        current: f64,
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    struct Unreadable;
+
+    impl Serialize for Unreadable {
+        fn serialize<S: serde::Serializer>(&self, _: S) -> std::result::Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("unreadable"))
+        }
+    }
+
+    #[test]
+    fn test_watch_reports_script_and_system_writes() -> Result<()> {
+        let lua = Lua::new();
+        let watch = DataWatch::new();
+        watch.register_lua(&lua)?;
+        let mut world = World::new();
+        world.register_component::<Health>("Health");
+        world.insert_resource(TimeScale::default());
+        let hero = world.spawn();
+        world.insert(hero, Health { current: 10.0 })?;
+        world.set_name(hero, "hero".to_string())?;
+
+        let console = Console::new();
+        watch.register_console(&console);
+        console.execute(&mut world, &lua, "watch hero Health.current -3 pause")?;
+
+        let mut schedule = Schedule::new();
+        schedule.add_system("poison", |world| {
+            let hero = world.find("hero").unwrap();
+            world.get_mut::<Health>(hero).unwrap().current = -3.0;
+            Ok(())
+        });
+        schedule.register_lua(&lua)?;
+        lua.globals().set("world_hero", hero)?;
+        lua.load(
+            r#"
+            schedule:add_system("Update", "heal", function(world)
+                local hero = world:find("hero")
+                world:set(hero, "Health", { current = 5 })
+            end)
+            debugger:watch(world_hero, "Health", function(new, old)
+                return new.current > old.current
+            end)
+            "#,
+        )
+        .set_name("@scripts/heal.lua")
+        .exec()?;
+        schedule.run(&mut world, &lua)?;
+
+        let hits = world.drain_events::<WatchHit>();
+        assert_eq!(hits.len(), 2, "{:?}", hits);
+        assert_eq!(hits[0].watch, 1);
+        assert_eq!(hits[0].field, "Health.current");
+        assert_eq!(hits[0].new, Some(ScriptValue::Number(-3.0)));
+        assert_eq!(hits[0].source, "system 'poison'");
+        assert!(world.resource::<TimeScale>().unwrap().is_paused());
+        assert_eq!(hits[1].watch, 2);
+        assert!(
+            hits[1].source.contains("scripts/heal.lua:4"),
+            "{}",
+            hits[1].source
+        );
+
+        assert!(lua.load("return debugger:unwatch(2)").eval::<bool>()?);
+        console.execute(&mut world, &lua, "unwatch 1")?;
+        assert!(watch.watches().is_empty());
+
+        // A watch that can't be read is dropped instead of failing every
+        // check after it.
+        world.register_component::<Unreadable>("Unreadable");
+        world.insert(hero, Unreadable)?;
+        watch.watch(
+            hero,
+            "Unreadable",
+            WatchCondition::Changed,
+            WatchAction::Log,
+        );
+        around_system(&mut world, &lua, None)?;
+        assert!(watch.watches().is_empty());
+        Ok(())
+    }
+}
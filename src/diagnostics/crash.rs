@@ -0,0 +1,308 @@
+use super::WorldMetrics;
+use crate::content::content_hash;
+use crate::ecs::{Schedule, World, panic as ecs_panic};
+use log::{Log, Metadata, Record};
+use std::backtrace::Backtrace;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Default)]
+struct CrashState {
+    dir: PathBuf,
+    log_len: usize,
+    log: VecDeque<String>,
+    scene: Option<String>,
+    scripts: BTreeMap<String, u64>,
+    tick: u32,
+    /// Milliseconds each system took in the last recorded frame.
+    systems: Vec<(String, f64)>,
+    last_totals: BTreeMap<String, Duration>,
+    world: String,
+}
+
+/// Keeps enough about the running game to explain a crash: recent log
+/// lines, the last frame's timings, the active scene, loaded scripts and
+/// a world summary. After `install`, a panic that no schedule catches
+/// writes all of it to a fresh directory under the crash directory before
+/// the previous hook runs. Clones share one record.
+#[derive(Clone)]
+pub struct CrashReporter {
+    state: Arc<Mutex<CrashState>>,
+}
+
+impl CrashReporter {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        CrashReporter {
+            state: Arc::new(Mutex::new(CrashState {
+                dir: dir.into(),
+                log_len: 200,
+                ..CrashState::default()
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CrashState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// How many log lines to keep, 200 by default.
+    pub fn set_log_len(&self, len: usize) {
+        let mut state = self.lock();
+        state.log_len = len;
+        while state.log.len() > len {
+            state.log.pop_front();
+        }
+    }
+
+    pub fn record_line(&self, line: impl Into<String>) {
+        let mut state = self.lock();
+        state.log.push_back(line.into());
+        while state.log.len() > state.log_len {
+            state.log.pop_front();
+        }
+    }
+
+    pub fn set_scene(&self, name: &str) {
+        self.lock().scene = Some(name.to_string());
+    }
+
+    /// Notes a loaded script by its chunk name and a hash of its source.
+    pub fn record_script(&self, name: &str, source: &[u8]) {
+        self.lock()
+            .scripts
+            .insert(name.to_string(), content_hash(source));
+    }
+
+    pub fn forget_script(&self, name: &str) {
+        self.lock().scripts.remove(name);
+    }
+
+    /// Snapshots the frame that just ran. System times need
+    /// `Schedule::set_timing(true)`; the world summary uses `WorldMetrics`
+    /// when `Diagnostics` has published them.
+    pub fn record_frame(&self, world: &World, schedule: &Schedule) {
+        let summary = match world.resource::<WorldMetrics>() {
+            Some(metrics) => {
+                let mut summary = format!("{}\n", metrics);
+                for component in &metrics.components {
+                    let _ = writeln!(
+                        summary,
+                        "  {}: {} ({} bytes)",
+                        component.name, component.count, component.bytes
+                    );
+                }
+                summary
+            }
+            None => format!("{} entities\n", world.len()),
+        };
+        let mut state = self.lock();
+        state.tick = world.tick();
+        state.world = summary;
+        state.systems.clear();
+        for (name, total) in schedule.timings() {
+            let last = state.last_totals.insert(name.to_string(), total);
+            let frame = total.saturating_sub(last.unwrap_or(total));
+            state
+                .systems
+                .push((name.to_string(), frame.as_secs_f64() * 1000.0));
+        }
+    }
+
+    /// A logger keeping lines for the report, passing records on to
+    /// `inner` when given. Install it with `log::set_boxed_logger`.
+    pub fn logger(&self, inner: Option<Box<dyn Log>>) -> CrashLog {
+        CrashLog {
+            reporter: self.clone(),
+            inner,
+        }
+    }
+
+    /// Writes the bundle now and returns its directory.
+    pub fn write_bundle(&self, message: &str, backtrace: &str) -> io::Result<PathBuf> {
+        let state = self.lock();
+        write_bundle(&state, message, backtrace)
+    }
+
+    /// Chains onto the current panic hook. Call once.
+    pub fn install(&self) {
+        let state = self.state.clone();
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if !ecs_panic::catching() {
+                // The panic may have come from inside a reporter method.
+                let state = match state.try_lock() {
+                    Ok(state) => Some(state),
+                    Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+                    Err(TryLockError::WouldBlock) => None,
+                };
+                if let Some(state) = state {
+                    let message = ecs_panic::describe(info);
+                    let backtrace = Backtrace::force_capture().to_string();
+                    match write_bundle(&state, &message, &backtrace) {
+                        Ok(dir) => eprintln!("crash report written to {}", dir.display()),
+                        Err(e) => eprintln!("failed to write crash report: {}", e),
+                    }
+                }
+            }
+            previous(info);
+        }));
+    }
+}
+
+fn write_bundle(state: &CrashState, message: &str, backtrace: &str) -> io::Result<PathBuf> {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let dir = unique_dir(&state.dir, since_epoch.as_millis())?;
+
+    let thread = std::thread::current();
+    fs::write(
+        dir.join("panic.txt"),
+        format!(
+            "{}\nthread: {}\n\n{}\n",
+            message,
+            thread.name().unwrap_or("<unnamed>"),
+            backtrace
+        ),
+    )?;
+
+    let mut log = String::new();
+    for line in &state.log {
+        let _ = writeln!(log, "{}", line);
+    }
+    fs::write(dir.join("log.txt"), log)?;
+
+    let mut frame = format!(
+        "scene: {}\ntick: {}\n",
+        state.scene.as_deref().unwrap_or("<none>"),
+        state.tick
+    );
+    for (system, ms) in &state.systems {
+        let _ = writeln!(frame, "  {}: {:.3} ms", system, ms);
+    }
+    fs::write(dir.join("frame.txt"), frame)?;
+
+    let mut scripts = String::new();
+    for (name, hash) in &state.scripts {
+        let _ = writeln!(scripts, "{:016x} {}", hash, name);
+    }
+    fs::write(dir.join("scripts.txt"), scripts)?;
+    fs::write(dir.join("world.txt"), &state.world)?;
+    Ok(dir)
+}
+
+/// `crash-<millis>`, suffixed when several reports land in one millisecond.
+fn unique_dir(parent: &Path, millis: u128) -> io::Result<PathBuf> {
+    fs::create_dir_all(parent)?;
+    let mut attempt = 0;
+    loop {
+        let name = match attempt {
+            0 => format!("crash-{}", millis),
+            n => format!("crash-{}-{}", millis, n),
+        };
+        let dir = parent.join(name);
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// See `CrashReporter::logger`.
+pub struct CrashLog {
+    reporter: CrashReporter,
+    inner: Option<Box<dyn Log>>,
+}
+
+impl Log for CrashLog {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.inner
+            .as_ref()
+            .is_none_or(|inner| inner.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        self.reporter.record_line(format!(
+            "[{}] {}: {}",
+            record.level(),
+            record.target(),
+            record.args()
+        ));
+        if let Some(inner) = &self.inner {
+            inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(inner) = &self.inner {
+            inner.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostics;
+    use mlua::Lua;
+
+    #[test]
+    fn test_bundle_contents() -> mlua::Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.register_component::<u32>("Level");
+        let hero = world.spawn();
+        world.insert(hero, 3u32)?;
+        Diagnostics::new().update(&mut world);
+        let mut schedule = Schedule::new();
+        schedule.set_timing(true);
+        schedule.add_system("physics", |_| Ok(()));
+        schedule.run(&mut world, &lua)?;
+
+        let dir = std::env::temp_dir().join(format!("crash_{}", std::process::id()));
+        let reporter = CrashReporter::new(&dir);
+        reporter.set_log_len(2);
+        let logger = reporter.logger(None);
+        for line in ["loading", "ready", "fighting"] {
+            logger.log(
+                &Record::builder()
+                    .level(log::Level::Info)
+                    .target("game")
+                    .args(format_args!("{}", line))
+                    .build(),
+            );
+        }
+        reporter.set_scene("arena");
+        reporter.record_script("scripts/ai.lua", b"return 1");
+        reporter.record_frame(&world, &schedule);
+
+        let first = reporter.write_bundle("boom at ai.rs:3", "<backtrace>")?;
+        let second = reporter.write_bundle("boom again", "")?;
+        assert_ne!(first, second);
+        let read = |name: &str| fs::read_to_string(first.join(name)).unwrap();
+        assert!(read("panic.txt").starts_with("boom at ai.rs:3\n"));
+        assert_eq!(
+            read("log.txt"),
+            "[INFO] game: ready\n[INFO] game: fighting\n"
+        );
+        let frame = read("frame.txt");
+        assert!(frame.starts_with("scene: arena\n"));
+        assert!(frame.contains("  physics: "));
+        assert_eq!(
+            read("scripts.txt"),
+            format!("{:016x} scripts/ai.lua\n", content_hash(b"return 1"))
+        );
+        assert!(read("world.txt").contains("  Level: 1 ("));
+        fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+use super::Metric;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const PREFIX: &str = "entity_engine_";
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+/// How long one scrape may take, from accept to the last byte written.
+const DEADLINE: Duration = Duration::from_secs(5);
+/// Request line and headers past this are refused.
+const MAX_REQUEST: usize = 8 * 1024;
+/// Scrapes answered at once; more are turned away.
+const MAX_CONNECTIONS: usize = 8;
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn number(value: f64) -> String {
+    match value {
+        v if v.is_nan() => "NaN".to_string(),
+        v if v == f64::INFINITY => "+Inf".to_string(),
+        v if v == f64::NEG_INFINITY => "-Inf".to_string(),
+        v => v.to_string(),
+    }
+}
+
+/// Renders metrics in the OpenMetrics text format, every value a gauge
+/// and every name prefixed with `entity_engine_`. Samples sharing a name
+/// are grouped under one family in the order the names first appear.
+pub fn render_openmetrics(metrics: &[Metric]) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for metric in metrics {
+        if !names.contains(&metric.name) {
+            names.push(metric.name);
+        }
+    }
+    let mut out = String::new();
+    for name in names {
+        out.push_str(&format!("# TYPE {}{} gauge\n", PREFIX, name));
+        for metric in metrics.iter().filter(|m| m.name == name) {
+            let labels = match &metric.label {
+                Some((key, value)) => format!("{{{}=\"{}\"}}", key, escape(value)),
+                None => String::new(),
+            };
+            out.push_str(&format!(
+                "{}{}{} {}\n",
+                PREFIX,
+                name,
+                labels,
+                number(metric.value)
+            ));
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+/// Holds the latest rendered metrics for scraping. The game loop calls
+/// `publish` after `Diagnostics::update`; `serve` answers `GET /metrics`
+/// from background threads with whatever was published last, so scrapes
+/// never touch the world. Clones share one page.
+#[derive(Clone)]
+pub struct MetricsExporter {
+    page: Arc<Mutex<String>>,
+}
+
+impl Default for MetricsExporter {
+    fn default() -> Self {
+        MetricsExporter {
+            page: Arc::new(Mutex::new(render_openmetrics(&[]))),
+        }
+    }
+}
+
+impl MetricsExporter {
+    pub fn new() -> Self {
+        MetricsExporter::default()
+    }
+
+    pub fn publish(&self, metrics: &[Metric]) {
+        *self.page.lock().unwrap_or_else(|e| e.into_inner()) = render_openmetrics(metrics);
+    }
+
+    pub fn page(&self) -> String {
+        self.page.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn serve(&self, addr: impl ToSocketAddrs) -> io::Result<MetricsServer> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        // Polled so dropping the server can stop the thread.
+        listener.set_nonblocking(true)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let page = self.page.clone();
+        let stopping = stop.clone();
+        let active = Arc::new(AtomicUsize::new(0));
+        let thread = thread::Builder::new()
+            .name("metrics".to_string())
+            .spawn(move || {
+                while !stopping.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            if active.load(Ordering::Relaxed) >= MAX_CONNECTIONS {
+                                log::warn!("metrics: too many scrapes at once");
+                                continue;
+                            }
+                            active.fetch_add(1, Ordering::Relaxed);
+                            let page = page.clone();
+                            let done = active.clone();
+                            let spawned = thread::Builder::new()
+                                .name("metrics-scrape".to_string())
+                                .spawn(move || {
+                                    if let Err(e) = respond(stream, &page) {
+                                        log::warn!("metrics request failed: {}", e);
+                                    }
+                                    done.fetch_sub(1, Ordering::Relaxed);
+                                });
+                            if let Err(e) = spawned {
+                                active.fetch_sub(1, Ordering::Relaxed);
+                                log::warn!("metrics: {}", e);
+                            }
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            thread::sleep(Duration::from_millis(20));
+                        }
+                        Err(e) => log::warn!("metrics listener: {}", e),
+                    }
+                }
+            })?;
+        Ok(MetricsServer {
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Time left before `deadline`, or an error once it has passed.
+fn remaining(deadline: Instant) -> io::Result<Duration> {
+    deadline
+        .checked_duration_since(Instant::now())
+        .filter(|left| !left.is_zero())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "scrape took too long"))
+}
+
+fn respond(mut stream: TcpStream, page: &Mutex<String>) -> io::Result<()> {
+    let deadline = Instant::now() + DEADLINE;
+    stream.set_nonblocking(false)?;
+    // Read up to the blank line ending the headers; nothing in them
+    // changes the answer.
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") && !request.ends_with(b"\n\n") {
+        if request.len() >= MAX_REQUEST {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request too large",
+            ));
+        }
+        stream.set_read_timeout(Some(remaining(deadline)?))?;
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    let request_line =
+        String::from_utf8_lossy(request.split(|&b| b == b'\n').next().unwrap_or(&[])).into_owned();
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            CONTENT_TYPE,
+            page.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        ),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+    stream.set_write_timeout(Some(remaining(deadline)?))?;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// The listening endpoint; stops when dropped.
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::{Diagnostics, WorldMetrics};
+    use crate::ecs::World;
+    use std::io::Read;
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_scrape_published_metrics() {
+        let mut world = World::new();
+        world.register_component::<u32>("Level \"1\"");
+        let entity = world.spawn();
+        world.insert(entity, 7u32).unwrap();
+        Diagnostics::new().update(&mut world);
+
+        let exporter = MetricsExporter::new();
+        let server = exporter.serve("127.0.0.1:0").unwrap();
+        assert!(get(server.local_addr(), "/metrics").ends_with("\r\n\r\n# EOF\n"));
+
+        let metrics = world.resource::<WorldMetrics>().unwrap();
+        exporter.publish(&metrics.metrics());
+        let response = get(server.local_addr(), "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains(CONTENT_TYPE));
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        assert!(
+            body.starts_with("# TYPE entity_engine_entities gauge\nentity_engine_entities 1\n")
+        );
+        assert!(body.contains("entity_engine_component_count{component=\"Level \\\"1\\\"\"} 1\n"));
+        assert_eq!(
+            body.matches("# TYPE entity_engine_component_bytes").count(),
+            1
+        );
+        assert!(body.ends_with("# EOF\n"));
+
+        assert!(get(server.local_addr(), "/").starts_with("HTTP/1.1 404"));
+
+        // A client that never finishes its request doesn't hold up others,
+        // and one that sends too much is cut off.
+        let _idle = TcpStream::connect(server.local_addr()).unwrap();
+        assert!(get(server.local_addr(), "/metrics").starts_with("HTTP/1.1 200 OK"));
+        let mut flood = TcpStream::connect(server.local_addr()).unwrap();
+        let _ = flood.write_all(&[b'x'; 2 * MAX_REQUEST]);
+        let mut response = String::new();
+        let _ = flood.read_to_string(&mut response);
+        assert!(response.is_empty());
+        drop(server);
+    }
+}
@@ -0,0 +1,355 @@
+mod crash;
+#[cfg(feature = "metrics")]
+mod exporter;
+mod profiler;
+
+pub use crash::{CrashLog, CrashReporter};
+#[cfg(feature = "metrics")]
+pub use exporter::{MetricsExporter, MetricsServer, render_openmetrics};
+pub use profiler::{FunctionProfile, LuaProfiler, ProfileReport, add_interrupt};
+
+use crate::ecs::{Entity, Events, ScriptEvents, World};
+use crate::net::NetStats;
+use mlua::{Lua, WeakLua};
+use serde::Serialize;
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+type EventDepth = Box<dyn Fn(&World) -> usize>;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComponentMetrics {
+    pub name: String,
+    pub count: usize,
+    /// Bytes reserved by the column, see `SparseSet::memory`.
+    pub bytes: usize,
+}
+
+/// One frame's numbers. `Diagnostics::update` keeps the latest as a world
+/// resource, where the overlay, logs and exporters read it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct WorldMetrics {
+    pub frame: u64,
+    pub entities: usize,
+    /// Distinct sets of components among live entities, the empty set
+    /// included.
+    pub archetypes: usize,
+    /// Rust components by registered name (type name when unregistered),
+    /// then script components, each sorted.
+    pub components: Vec<ComponentMetrics>,
+    /// Queued events by tracked or script event name.
+    pub events: BTreeMap<String, usize>,
+    /// Bytes in use by each tracked Lua state.
+    pub lua_memory: BTreeMap<String, usize>,
+    /// The world's `NetStats`, when it runs an RPC layer.
+    pub net: Option<NetStats>,
+}
+
+/// A single labelled value, the shape metrics exporters want.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub name: &'static str,
+    pub label: Option<(&'static str, String)>,
+    pub value: f64,
+}
+
+impl Metric {
+    fn new(name: &'static str, value: usize) -> Self {
+        Metric {
+            name,
+            label: None,
+            value: value as f64,
+        }
+    }
+
+    fn labelled(name: &'static str, key: &'static str, label: &str, value: usize) -> Self {
+        Metric {
+            label: Some((key, label.to_string())),
+            ..Metric::new(name, value)
+        }
+    }
+
+    fn rate(name: &'static str, channel: &str, value: f64) -> Self {
+        Metric {
+            name,
+            label: Some(("channel", channel.to_string())),
+            value,
+        }
+    }
+}
+
+impl WorldMetrics {
+    pub fn component_bytes(&self) -> usize {
+        self.components.iter().map(|c| c.bytes).sum()
+    }
+
+    pub fn metrics(&self) -> Vec<Metric> {
+        let mut metrics = vec![
+            Metric::new("entities", self.entities),
+            Metric::new("archetypes", self.archetypes),
+        ];
+        for component in &self.components {
+            metrics.push(Metric::labelled(
+                "component_count",
+                "component",
+                &component.name,
+                component.count,
+            ));
+            metrics.push(Metric::labelled(
+                "component_bytes",
+                "component",
+                &component.name,
+                component.bytes,
+            ));
+        }
+        for (name, &depth) in &self.events {
+            metrics.push(Metric::labelled("event_queue_depth", "event", name, depth));
+        }
+        for (name, &bytes) in &self.lua_memory {
+            metrics.push(Metric::labelled("lua_memory_bytes", "state", name, bytes));
+        }
+        if let Some(net) = &self.net {
+            for (channel, stats) in &net.channels {
+                metrics.extend([
+                    Metric::rate("net_bytes_sent_per_sec", channel, stats.bytes_sent_per_sec),
+                    Metric::rate(
+                        "net_bytes_received_per_sec",
+                        channel,
+                        stats.bytes_received_per_sec,
+                    ),
+                    Metric::rate(
+                        "net_packets_sent_per_sec",
+                        channel,
+                        stats.packets_sent_per_sec,
+                    ),
+                    Metric::rate(
+                        "net_packets_received_per_sec",
+                        channel,
+                        stats.packets_received_per_sec,
+                    ),
+                ]);
+            }
+            metrics.push(Metric {
+                value: net.packet_loss,
+                ..Metric::new("net_packet_loss", 0)
+            });
+            if let Some(rtt) = net.rtt_ms {
+                metrics.push(Metric {
+                    value: rtt,
+                    ..Metric::new("net_rtt_ms", 0)
+                });
+            }
+            metrics.push(Metric::new("net_pending", net.pending));
+            metrics.push(Metric::new("net_snapshot_bytes", net.snapshot_bytes));
+        }
+        metrics
+    }
+}
+
+impl fmt::Display for WorldMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "frame {}: {} entities, {} archetypes, {} bytes in {} components",
+            self.frame,
+            self.entities,
+            self.archetypes,
+            self.component_bytes(),
+            self.components.len()
+        )?;
+        let queued: usize = self.events.values().sum();
+        if queued > 0 {
+            write!(f, ", {} events queued", queued)?;
+        }
+        for (name, bytes) in &self.lua_memory {
+            write!(f, ", lua {}: {} bytes", name, bytes)?;
+        }
+        if let Some(net) = &self.net {
+            let sent: f64 = net.channels.values().map(|c| c.bytes_sent_per_sec).sum();
+            write!(f, ", net {:.0} B/s out", sent)?;
+            if let Some(rtt) = net.rtt_ms {
+                write!(f, ", rtt {:.0} ms", rtt)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collects `WorldMetrics` once a frame. Typed event queues and Lua states
+/// aren't discoverable from the world, so they are tracked by name; script
+/// events are always counted. Lua states are held weakly and dropped from
+/// the report once closed.
+#[derive(Default)]
+pub struct Diagnostics {
+    frame: u64,
+    events: Vec<(String, EventDepth)>,
+    lua: Vec<(String, WeakLua)>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Diagnostics::default()
+    }
+
+    pub fn track_events<E: Any + Send + Sync>(&mut self, name: &str) {
+        let depth: EventDepth = Box::new(|world| world.events::<E>().map_or(0, Events::len));
+        self.events.push((name.to_string(), depth));
+    }
+
+    pub fn track_lua(&mut self, name: &str, lua: &Lua) {
+        self.lua.push((name.to_string(), lua.weak()));
+    }
+
+    /// Walks every column, so costs grow with entities times component
+    /// types. Call between schedule runs, not from inside a system.
+    pub fn collect(&self, world: &World) -> WorldMetrics {
+        let mut rust = Vec::new();
+        for (type_id, storage) in world.storages() {
+            let storage = storage.borrow();
+            let name = world
+                .registry()
+                .name_of_id(type_id)
+                .unwrap_or(storage.type_name());
+            let metrics = ComponentMetrics {
+                name: name.to_string(),
+                count: storage.entities().len(),
+                bytes: storage.memory(),
+            };
+            rust.push((metrics, storage.entities().to_vec()));
+        }
+        let mut scripts: Vec<(ComponentMetrics, Vec<Entity>)> = world
+            .script_component_names()
+            .filter_map(|name| {
+                let storage = world.script_components(name)?;
+                let metrics = ComponentMetrics {
+                    name: name.to_string(),
+                    count: storage.len(),
+                    bytes: storage.memory(),
+                };
+                Some((metrics, storage.entities().to_vec()))
+            })
+            .collect();
+        rust.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+        scripts.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+
+        let mut signatures: HashMap<Entity, Vec<usize>> = HashMap::new();
+        let mut components = Vec::new();
+        for (column, (metrics, entities)) in rust.into_iter().chain(scripts).enumerate() {
+            for entity in entities {
+                signatures.entry(entity).or_default().push(column);
+            }
+            components.push(metrics);
+        }
+        let mut archetypes: BTreeSet<&[usize]> = signatures.values().map(Vec::as_slice).collect();
+        if signatures.len() < world.len() {
+            archetypes.insert(&[]);
+        }
+
+        let mut events: BTreeMap<String, usize> = self
+            .events
+            .iter()
+            .map(|(name, depth)| (name.clone(), depth(world)))
+            .collect();
+        if let Some(script) = world.resource::<ScriptEvents>() {
+            for (name, depth) in script.pending() {
+                events.insert(name.to_string(), depth);
+            }
+        }
+        let lua_memory = self
+            .lua
+            .iter()
+            .filter_map(|(name, lua)| Some((name.clone(), lua.try_upgrade()?.used_memory())))
+            .collect();
+
+        WorldMetrics {
+            frame: self.frame,
+            entities: world.len(),
+            archetypes: archetypes.len(),
+            components,
+            events,
+            lua_memory,
+            net: world.resource::<NetStats>().cloned(),
+        }
+    }
+
+    /// Collects this frame's metrics into the world's `WorldMetrics`
+    /// resource and logs them at debug level.
+    pub fn update(&mut self, world: &mut World) {
+        self.lua.retain(|(_, lua)| lua.try_upgrade().is_some());
+        let metrics = self.collect(world);
+        log::debug!("{}", metrics);
+        world.insert_resource(metrics);
+        self.frame += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::ScriptValue;
+
+    #[derive(Debug)]
+    struct Hit;
+
+    #[test]
+    fn test_collects_world_metrics() {
+        let mut world = World::new();
+        world.register_component::<u32>("Level");
+        let a = world.spawn();
+        let b = world.spawn();
+        let c = world.spawn();
+        world.spawn();
+        world.insert(a, 1u32).unwrap();
+        world.insert(b, 2u32).unwrap();
+        world.insert(c, 0.5f32).unwrap();
+        world
+            .set_script_component(b, "Tag", ScriptValue::Bool(true))
+            .unwrap();
+        world.send_event(Hit);
+        world.send_event(Hit);
+        world.send_script_event("opened", ScriptValue::Bool(true));
+
+        let lua = Lua::new();
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.track_events::<Hit>("hit");
+        diagnostics.track_lua("main", &lua);
+        diagnostics.update(&mut world);
+
+        let metrics = world.resource::<WorldMetrics>().unwrap();
+        assert_eq!((metrics.entities, metrics.archetypes), (4, 4));
+        let names: Vec<&str> = metrics.components.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["Level", "f32", "Tag"]);
+        assert_eq!(metrics.components[0].count, 2);
+        assert!(metrics.components[0].bytes >= 2 * size_of::<u32>());
+        assert_eq!(metrics.events["hit"], 2);
+        assert_eq!(metrics.events["opened"], 1);
+        assert!(metrics.lua_memory["main"] > 0);
+        assert!(metrics.metrics().contains(&Metric {
+            name: "event_queue_depth",
+            label: Some(("event", "hit".to_string())),
+            value: 2.0,
+        }));
+
+        drop(lua);
+        diagnostics.update(&mut world);
+        let metrics = world.resource::<WorldMetrics>().unwrap();
+        assert_eq!(metrics.frame, 1);
+        assert!(metrics.lua_memory.is_empty());
+        assert!(metrics.net.is_none());
+
+        world.insert_resource(NetStats {
+            rtt_ms: Some(50.0),
+            ..NetStats::default()
+        });
+        diagnostics.update(&mut world);
+        let metrics = world.resource::<WorldMetrics>().unwrap();
+        assert!(metrics.to_string().ends_with("rtt 50 ms"), "{}", metrics);
+        assert!(
+            metrics
+                .metrics()
+                .iter()
+                .any(|m| m.name == "net_rtt_ms" && m.value == 50.0)
+        );
+    }
+}
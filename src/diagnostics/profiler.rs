@@ -0,0 +1,354 @@
+use mlua::{Lua, Result, UserData, UserDataMethods, VmState};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Samples attributed to one script function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionProfile {
+    /// `<anonymous>` when Lua can't name it, `<main>` for chunk bodies.
+    pub name: String,
+    /// `source:line` where the function is defined.
+    pub location: String,
+    /// Samples taken while it was running itself.
+    pub self_samples: u64,
+    /// Samples taken while it was anywhere on the stack.
+    pub total_samples: u64,
+}
+
+/// Functions by self samples, most first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProfileReport {
+    pub interval: Duration,
+    pub samples: u64,
+    pub functions: Vec<FunctionProfile>,
+}
+
+impl ProfileReport {
+    /// Estimated time from a sample count, saturating at `Duration::MAX`.
+    pub fn time(&self, samples: u64) -> Duration {
+        Duration::try_from_secs_f64(self.interval.as_secs_f64() * samples as f64)
+            .unwrap_or(Duration::MAX)
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} samples every {:?}", self.samples, self.interval)?;
+        for function in &self.functions {
+            writeln!(
+                f,
+                "{:>8.2?} self {:>8.2?} total  {} ({})",
+                self.time(function.self_samples),
+                self.time(function.total_samples),
+                function.name,
+                function.location
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct ProfilerState {
+    enabled: bool,
+    interval: Duration,
+    next_sample: Option<Instant>,
+    samples: u64,
+    /// By location, since names depend on the call site.
+    functions: HashMap<String, FunctionProfile>,
+}
+
+/// Samples script code through Luau's interrupt, which runs at calls and
+/// loop back edges. Once `interval` has passed the running stack is
+/// recorded, so a function's samples times the interval estimates its
+/// time. Off until `start`.
+#[derive(Clone)]
+pub struct LuaProfiler {
+    state: Rc<RefCell<ProfilerState>>,
+}
+
+impl Default for LuaProfiler {
+    fn default() -> Self {
+        LuaProfiler {
+            state: Rc::new(RefCell::new(ProfilerState {
+                interval: Duration::from_millis(1),
+                ..ProfilerState::default()
+            })),
+        }
+    }
+}
+
+/// Name and location of the function at `level`, or `None` past the top
+/// of the stack. Native frames and mlua's glue come back as `Some(None)`.
+fn frame(lua: &Lua, level: usize) -> Option<Option<(String, String)>> {
+    lua.inspect_stack(level, |debug| {
+        let source = debug.source();
+        let chunk = source.source.as_deref().unwrap_or("?");
+        let chunk = chunk.trim_start_matches(['@', '=']);
+        if source.what == "C" || chunk.starts_with("__mlua") {
+            return None;
+        }
+        let name = match (debug.names().name, source.what) {
+            (Some(name), _) => name.into_owned(),
+            (None, "main") => "<main>".to_string(),
+            (None, _) => "<anonymous>".to_string(),
+        };
+        let location = format!("{}:{}", chunk, source.line_defined.unwrap_or(0));
+        Some((name, location))
+    })
+}
+
+type Interrupt = Rc<dyn Fn(&Lua) -> Result<VmState>>;
+
+#[derive(Default)]
+struct Interrupts(Vec<Interrupt>);
+
+/// Adds `f` to the interrupts run at Luau's calls and loop back edges,
+/// after any added before it. Only one interrupt can be installed and
+/// mlua can't read it back, so anything sharing the state should come
+/// through here: a direct `Lua::set_interrupt` replaces the whole chain.
+/// The first that doesn't return `VmState::Continue` decides.
+pub fn add_interrupt(lua: &Lua, f: impl Fn(&Lua) -> Result<VmState> + 'static) {
+    if lua.app_data_ref::<Interrupts>().is_none() {
+        lua.set_app_data(Interrupts::default());
+        lua.set_interrupt(|lua| {
+            let chain = match lua.app_data_ref::<Interrupts>() {
+                Some(interrupts) => interrupts.0.clone(),
+                None => return Ok(VmState::Continue),
+            };
+            for interrupt in chain {
+                match interrupt(lua)? {
+                    VmState::Continue => {}
+                    state => return Ok(state),
+                }
+            }
+            Ok(VmState::Continue)
+        });
+    }
+    lua.app_data_mut::<Interrupts>()
+        .expect("interrupts were just set")
+        .0
+        .push(Rc::new(f));
+}
+
+impl LuaProfiler {
+    pub fn new() -> Self {
+        LuaProfiler::default()
+    }
+
+    /// Installs the interrupt and the `profiler` global with `start()`,
+    /// `stop()`, `reset()` and `report()`, the last listing
+    /// `{name, location, self_ms, total_ms}`.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        let profiler = self.clone();
+        add_interrupt(lua, move |lua| {
+            profiler.sample(lua);
+            Ok(VmState::Continue)
+        });
+        lua.globals().set("profiler", self.clone())
+    }
+
+    pub fn start(&self) {
+        let mut state = self.state.borrow_mut();
+        state.enabled = true;
+        state.next_sample = None;
+    }
+
+    pub fn stop(&self) {
+        self.state.borrow_mut().enabled = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.borrow().enabled
+    }
+
+    /// Time between samples, 1ms by default. Zero samples at every
+    /// interrupt.
+    pub fn set_interval(&self, interval: Duration) {
+        self.state.borrow_mut().interval = interval;
+    }
+
+    pub fn reset(&self) {
+        let mut state = self.state.borrow_mut();
+        state.samples = 0;
+        state.functions.clear();
+    }
+
+    pub fn report(&self) -> ProfileReport {
+        let state = self.state.borrow();
+        let mut functions: Vec<FunctionProfile> = state.functions.values().cloned().collect();
+        functions.sort_by(|a, b| {
+            b.self_samples
+                .cmp(&a.self_samples)
+                .then(b.total_samples.cmp(&a.total_samples))
+                .then_with(|| a.location.cmp(&b.location))
+        });
+        ProfileReport {
+            interval: state.interval,
+            samples: state.samples,
+            functions,
+        }
+    }
+
+    fn sample(&self, lua: &Lua) {
+        let now = Instant::now();
+        {
+            let mut state = self.state.borrow_mut();
+            if !state.enabled || state.next_sample.is_some_and(|next| now < next) {
+                return;
+            }
+            state.next_sample = Some(now + state.interval);
+        }
+
+        let mut stack = Vec::new();
+        for level in 0.. {
+            match frame(lua, level) {
+                Some(Some(found)) => stack.push(found),
+                Some(None) => continue,
+                None => break,
+            }
+        }
+        let Some((_, running)) = stack.first().cloned() else {
+            return;
+        };
+        let mut state = self.state.borrow_mut();
+        state.samples += 1;
+        let mut seen = Vec::new();
+        for (name, location) in stack {
+            // Recursion counts once toward the total.
+            if seen.contains(&location) {
+                continue;
+            }
+            let entry =
+                state
+                    .functions
+                    .entry(location.clone())
+                    .or_insert_with(|| FunctionProfile {
+                        name,
+                        location: location.clone(),
+                        self_samples: 0,
+                        total_samples: 0,
+                    });
+            entry.total_samples += 1;
+            if location == running {
+                entry.self_samples += 1;
+            }
+            seen.push(location);
+        }
+    }
+}
+
+impl UserData for LuaProfiler {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("start", |_, this, ()| {
+            this.start();
+            Ok(())
+        });
+        methods.add_method("stop", |_, this, ()| {
+            this.stop();
+            Ok(())
+        });
+        methods.add_method("reset", |_, this, ()| {
+            this.reset();
+            Ok(())
+        });
+        methods.add_method("report", |lua, this, ()| {
+            let report = this.report();
+            let list = lua.create_table()?;
+            for (i, function) in report.functions.iter().enumerate() {
+                let entry = lua.create_table()?;
+                entry.set("name", function.name.as_str())?;
+                entry.set("location", function.location.as_str())?;
+                entry.set(
+                    "self_ms",
+                    report.time(function.self_samples).as_secs_f64() * 1000.0,
+                )?;
+                entry.set(
+                    "total_ms",
+                    report.time(function.total_samples).as_secs_f64() * 1000.0,
+                )?;
+                list.set(i + 1, entry)?;
+            }
+            Ok(list)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_name_script_functions() -> Result<()> {
+        let lua = Lua::new();
+        let profiler = LuaProfiler::new();
+        profiler.set_interval(Duration::ZERO);
+        let earlier = Rc::new(std::cell::Cell::new(0));
+        let count = earlier.clone();
+        add_interrupt(&lua, move |_| {
+            count.set(count.get() + 1);
+            Ok(VmState::Continue)
+        });
+        profiler.register_lua(&lua)?;
+        lua.load(
+            r#"
+            function slow(n)
+                local total = 0
+                for i = 1, n do total = total + math.sqrt(i) end
+                return total
+            end
+            function update()
+                return slow(20000) + slow(20000)
+            end
+            "#,
+        )
+        .set_name("@scripts/ai.lua")
+        .exec()?;
+
+        lua.load("update()").exec()?;
+        assert_eq!(profiler.report().samples, 0);
+
+        lua.load("profiler:start() update() profiler:stop()")
+            .exec()?;
+        assert!(!profiler.is_running());
+        let report = profiler.report();
+        let hottest = &report.functions[0];
+        assert_eq!(hottest.name, "slow");
+        assert_eq!(hottest.location, "scripts/ai.lua:2");
+        let update = report
+            .functions
+            .iter()
+            .find(|f| f.location == "scripts/ai.lua:7")
+            .unwrap();
+        assert!(update.total_samples >= hottest.total_samples);
+        assert!(report.to_string().contains("slow (scripts/ai.lua:2)"));
+
+        let listed: String = lua.load("return profiler:report()[1].location").eval()?;
+        assert_eq!(listed, "scripts/ai.lua:2");
+        lua.load("profiler:reset()").exec()?;
+        assert!(profiler.report().functions.is_empty());
+
+        // The interrupt added first still runs, and huge counts saturate.
+        assert!(earlier.get() > 0);
+        let report = ProfileReport {
+            interval: Duration::from_secs(1),
+            ..report
+        };
+        assert_eq!(
+            report.time(u64::from(u32::MAX) + 2),
+            Duration::from_secs(u64::from(u32::MAX) + 2)
+        );
+        assert_eq!(
+            ProfileReport {
+                interval: Duration::MAX,
+                ..report
+            }
+            .time(2),
+            Duration::MAX
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,675 @@
+//! Branching conversations as node graphs loaded from data. A `Dialogue`
+//! walks one conversation at a time: lines wait for `advance`, choices for
+//! `choose`, and every step goes out as a `DialogueEvent` and a
+//! `"dialogue"` script event for the UI to draw.
+
+use crate::assets::read_lua_asset;
+use crate::data::{self, DataError};
+use crate::ecs::{Entity, World};
+use crate::i18n::{Localization, TrArg};
+use crate::loot::Condition;
+use mlua::{AnyUserData, Error, Function, Lua, LuaSerdeExt, Result, UserData, UserDataMethods};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Script event every `DialogueEvent` is also sent under, as a table with
+/// a `kind` field.
+pub const DIALOGUE_EVENT: &str = "dialogue";
+
+/// Branch nodes a conversation may pass through in one step, which also
+/// catches branches that loop back on themselves.
+const MAX_BRANCHES: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DialogueChoice {
+    /// A localization key, shown as is without a locale.
+    pub text: String,
+    pub next: Option<String>,
+    /// Offered only while this holds.
+    #[serde(default)]
+    pub when: Option<Condition>,
+    /// Called when the choice is picked.
+    #[serde(default)]
+    pub hook: Option<String>,
+}
+
+/// Texts and speakers are localization keys. `next: None` ends the
+/// conversation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DialogueNode {
+    Line {
+        #[serde(default)]
+        speaker: Option<String>,
+        text: String,
+        #[serde(default)]
+        next: Option<String>,
+        /// Called as the line is shown.
+        #[serde(default)]
+        hook: Option<String>,
+    },
+    Choice {
+        #[serde(default)]
+        speaker: Option<String>,
+        #[serde(default)]
+        text: Option<String>,
+        choices: Vec<DialogueChoice>,
+    },
+    /// Goes to the first target whose condition holds, else `otherwise`.
+    Branch {
+        branches: Vec<(Condition, String)>,
+        #[serde(default)]
+        otherwise: Option<String>,
+    },
+}
+
+impl DialogueNode {
+    fn targets(&self) -> Vec<&str> {
+        match self {
+            DialogueNode::Line { next, .. } => next.iter().map(String::as_str).collect(),
+            DialogueNode::Choice { choices, .. } => {
+                choices.iter().filter_map(|c| c.next.as_deref()).collect()
+            }
+            DialogueNode::Branch {
+                branches,
+                otherwise,
+            } => branches
+                .iter()
+                .map(|(_, next)| next.as_str())
+                .chain(otherwise.as_deref())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DialogueGraph {
+    pub start: String,
+    pub nodes: BTreeMap<String, DialogueNode>,
+}
+
+/// Conversations by name, loaded from a RON map such as
+/// `{ "intro": (start: "hello", nodes: { "hello": Line(text: "intro.hello") }) }`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Dialogues {
+    graphs: BTreeMap<String, DialogueGraph>,
+}
+
+impl Dialogues {
+    pub fn new() -> Self {
+        Dialogues::default()
+    }
+
+    pub fn from_ron(source: &str) -> std::result::Result<Self, DataError> {
+        let dialogues: Dialogues = data::from_ron(source)?;
+        dialogues.validate()?;
+        Ok(dialogues)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        Dialogues::from_ron(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn insert(&mut self, name: &str, graph: DialogueGraph) {
+        self.graphs.insert(name.to_string(), graph);
+    }
+
+    pub fn extend(&mut self, other: Dialogues) {
+        self.graphs.extend(other.graphs);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DialogueGraph> {
+        self.graphs.get(name)
+    }
+
+    /// Checks that every start node and link exists.
+    pub fn validate(&self) -> std::result::Result<(), DataError> {
+        for (name, graph) in &self.graphs {
+            let links = graph
+                .nodes
+                .values()
+                .flat_map(DialogueNode::targets)
+                .chain([graph.start.as_str()]);
+            for target in links {
+                if !graph.nodes.contains_key(target) {
+                    return Err(DataError::Invalid(format!(
+                        "dialogue '{}' refers to missing node '{}'",
+                        name, target
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DialogueEvent {
+    Started {
+        dialogue: String,
+    },
+    Line {
+        dialogue: String,
+        node: String,
+    },
+    /// A choice node was reached; `choices` are the offered texts.
+    Choices {
+        dialogue: String,
+        node: String,
+        choices: Vec<String>,
+    },
+    /// `choice` indexes the offered choices, from 0.
+    Chosen {
+        dialogue: String,
+        node: String,
+        choice: usize,
+    },
+    Ended {
+        dialogue: String,
+    },
+}
+
+/// What to show right now, localized.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DialogueView {
+    pub dialogue: String,
+    pub node: String,
+    pub speaker: Option<String>,
+    pub text: Option<String>,
+    /// Empty on a line.
+    pub choices: Vec<String>,
+}
+
+pub type RustDialogueHook = Rc<dyn Fn(&mut World, &Lua, Option<Entity>) -> Result<()>>;
+
+#[derive(Clone)]
+enum Hook {
+    Rust(RustDialogueHook),
+    /// Called as `hook(world, entity)`.
+    Lua(Function),
+}
+
+struct Cursor {
+    dialogue: String,
+    node: String,
+    entity: Option<Entity>,
+    /// Indices of the choices offered at a choice node.
+    offered: Vec<usize>,
+}
+
+#[derive(Default)]
+struct DialogueState {
+    dialogues: Dialogues,
+    localization: Option<Localization>,
+    hooks: BTreeMap<String, Hook>,
+    cursor: Option<Cursor>,
+}
+
+impl DialogueState {
+    fn tr(&self, key: &str) -> String {
+        match &self.localization {
+            Some(localization) => localization.tr_args(key, &[] as &[(&str, TrArg)]),
+            None => key.to_string(),
+        }
+    }
+}
+
+/// Runs conversations from `Dialogues`. Clones share one cursor, as does
+/// the `dialogue` Lua global.
+#[derive(Clone, Default)]
+pub struct Dialogue {
+    state: Rc<RefCell<DialogueState>>,
+}
+
+impl Dialogue {
+    pub fn new(dialogues: Dialogues) -> Self {
+        let dialogue = Dialogue::default();
+        dialogue.state.borrow_mut().dialogues = dialogues;
+        dialogue
+    }
+
+    pub fn dialogues_mut(&self) -> std::cell::RefMut<'_, Dialogues> {
+        std::cell::RefMut::map(self.state.borrow_mut(), |state| &mut state.dialogues)
+    }
+
+    /// Translates texts and speakers through `localization`.
+    pub fn set_localization(&self, localization: Localization) {
+        self.state.borrow_mut().localization = Some(localization);
+    }
+
+    pub fn on(
+        &self,
+        hook: &str,
+        f: impl Fn(&mut World, &Lua, Option<Entity>) -> Result<()> + 'static,
+    ) {
+        let mut state = self.state.borrow_mut();
+        state.hooks.insert(hook.to_string(), Hook::Rust(Rc::new(f)));
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state.borrow().cursor.is_some()
+    }
+
+    /// Starts `name`, ending any conversation in progress. `entity` is
+    /// what conditions check and hooks receive, usually the one spoken to.
+    pub fn start(
+        &self,
+        world: &mut World,
+        lua: &Lua,
+        name: &str,
+        entity: Option<Entity>,
+    ) -> Result<()> {
+        let start = {
+            let state = self.state.borrow();
+            let graph = state
+                .dialogues
+                .get(name)
+                .ok_or_else(|| Error::runtime(format!("no dialogue named '{}'", name)))?;
+            graph.start.clone()
+        };
+        self.stop(world)?;
+        self.state.borrow_mut().cursor = Some(Cursor {
+            dialogue: name.to_string(),
+            node: start.clone(),
+            entity,
+            offered: Vec::new(),
+        });
+        world.emit(
+            DIALOGUE_EVENT,
+            DialogueEvent::Started {
+                dialogue: name.to_string(),
+            },
+        )?;
+        self.enter(world, lua, Some(start))
+    }
+
+    pub fn current(&self) -> Option<DialogueView> {
+        let state = self.state.borrow();
+        let cursor = state.cursor.as_ref()?;
+        let node = state
+            .dialogues
+            .get(&cursor.dialogue)?
+            .nodes
+            .get(&cursor.node)?;
+        let (speaker, text, choices) = match node {
+            DialogueNode::Line { speaker, text, .. } => (speaker, Some(text), Vec::new()),
+            DialogueNode::Choice {
+                speaker,
+                text,
+                choices,
+            } => (
+                speaker,
+                text.as_ref(),
+                cursor
+                    .offered
+                    .iter()
+                    .filter_map(|&i| choices.get(i))
+                    .map(|choice| state.tr(&choice.text))
+                    .collect(),
+            ),
+            DialogueNode::Branch { .. } => return None,
+        };
+        Some(DialogueView {
+            dialogue: cursor.dialogue.clone(),
+            node: cursor.node.clone(),
+            speaker: speaker.as_deref().map(|key| state.tr(key)),
+            text: text.map(|key| state.tr(key)),
+            choices,
+        })
+    }
+
+    /// Moves past the current line.
+    pub fn advance(&self, world: &mut World, lua: &Lua) -> Result<()> {
+        let next = {
+            let state = self.state.borrow();
+            let Some(cursor) = &state.cursor else {
+                return Err(Error::runtime("no dialogue is running"));
+            };
+            match self.node(&state, cursor) {
+                Some(DialogueNode::Line { next, .. }) => next.clone(),
+                _ => return Err(Error::runtime("the dialogue is waiting for a choice")),
+            }
+        };
+        self.enter(world, lua, next)
+    }
+
+    /// Picks one of the offered choices, counted from 0.
+    pub fn choose(&self, world: &mut World, lua: &Lua, choice: usize) -> Result<()> {
+        let (dialogue, node, picked, entity) = {
+            let state = self.state.borrow();
+            let Some(cursor) = &state.cursor else {
+                return Err(Error::runtime("no dialogue is running"));
+            };
+            let Some(DialogueNode::Choice { choices, .. }) = self.node(&state, cursor) else {
+                return Err(Error::runtime("the dialogue is not at a choice"));
+            };
+            let picked = cursor
+                .offered
+                .get(choice)
+                .and_then(|&i| choices.get(i).cloned())
+                .ok_or_else(|| Error::runtime(format!("no choice {}", choice)))?;
+            (
+                cursor.dialogue.clone(),
+                cursor.node.clone(),
+                picked,
+                cursor.entity,
+            )
+        };
+        world.emit(
+            DIALOGUE_EVENT,
+            DialogueEvent::Chosen {
+                dialogue,
+                node,
+                choice,
+            },
+        )?;
+        if let Some(hook) = &picked.hook {
+            self.call_hook(world, lua, hook, entity)?;
+        }
+        self.enter(world, lua, picked.next)
+    }
+
+    /// Ends the conversation in progress, if any.
+    pub fn stop(&self, world: &mut World) -> Result<()> {
+        let ended = self.state.borrow_mut().cursor.take();
+        match ended {
+            Some(cursor) => world.emit(
+                DIALOGUE_EVENT,
+                DialogueEvent::Ended {
+                    dialogue: cursor.dialogue,
+                },
+            ),
+            None => Ok(()),
+        }
+    }
+
+    fn node<'a>(&self, state: &'a DialogueState, cursor: &Cursor) -> Option<&'a DialogueNode> {
+        state
+            .dialogues
+            .get(&cursor.dialogue)?
+            .nodes
+            .get(&cursor.node)
+    }
+
+    /// Moves the cursor to `target`, through any branches, and announces
+    /// where it lands; `None`, or a choice with nothing to offer, ends the
+    /// conversation.
+    fn enter(&self, world: &mut World, lua: &Lua, mut target: Option<String>) -> Result<()> {
+        for _ in 0..MAX_BRANCHES {
+            let Some(name) = target else {
+                return self.stop(world);
+            };
+            let (event, hook, entity) = {
+                let mut state = self.state.borrow_mut();
+                let state = &mut *state;
+                let Some(cursor) = state.cursor.as_mut() else {
+                    return Ok(());
+                };
+                let node = state
+                    .dialogues
+                    .get(&cursor.dialogue)
+                    .and_then(|graph| graph.nodes.get(&name))
+                    .ok_or_else(|| Error::runtime(format!("no dialogue node '{}'", name)))?;
+                cursor.node = name.clone();
+                cursor.offered.clear();
+                let dialogue = cursor.dialogue.clone();
+                match node {
+                    DialogueNode::Branch {
+                        branches,
+                        otherwise,
+                    } => {
+                        target = branches
+                            .iter()
+                            .find(|(condition, _)| condition.check(world, cursor.entity))
+                            .map(|(_, next)| next.clone())
+                            .or_else(|| otherwise.clone());
+                        continue;
+                    }
+                    DialogueNode::Line { hook, .. } => (
+                        DialogueEvent::Line {
+                            dialogue,
+                            node: name,
+                        },
+                        hook.clone(),
+                        cursor.entity,
+                    ),
+                    DialogueNode::Choice { choices, .. } => {
+                        cursor.offered = choices
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, choice)| {
+                                choice
+                                    .when
+                                    .as_ref()
+                                    .is_none_or(|when| when.check(world, cursor.entity))
+                            })
+                            .map(|(i, _)| i)
+                            .collect();
+                        if cursor.offered.is_empty() {
+                            log::warn!(target: "dialogue", "no choice at '{}' is available", name);
+                            target = None;
+                            continue;
+                        }
+                        let choices = cursor
+                            .offered
+                            .iter()
+                            .map(|&i| choices[i].text.clone())
+                            .collect();
+                        (
+                            DialogueEvent::Choices {
+                                dialogue,
+                                node: name,
+                                choices,
+                            },
+                            None,
+                            cursor.entity,
+                        )
+                    }
+                }
+            };
+            if let Some(hook) = hook {
+                self.call_hook(world, lua, &hook, entity)?;
+            }
+            return world.emit(DIALOGUE_EVENT, event);
+        }
+        self.stop(world)?;
+        Err(Error::runtime(format!(
+            "dialogue passed more than {} branches in one step",
+            MAX_BRANCHES
+        )))
+    }
+
+    fn call_hook(
+        &self,
+        world: &mut World,
+        lua: &Lua,
+        name: &str,
+        entity: Option<Entity>,
+    ) -> Result<()> {
+        let hook = self.state.borrow().hooks.get(name).cloned();
+        match hook {
+            Some(Hook::Rust(hook)) => hook(world, lua, entity),
+            Some(Hook::Lua(hook)) => lua.scope(|scope| {
+                let handle = scope.create_userdata_ref_mut(&mut *world)?;
+                hook.call::<()>((handle, entity))
+            }),
+            None => {
+                log::warn!(target: "dialogue", "no dialogue hook named '{}'", name);
+                Ok(())
+            }
+        }
+    }
+
+    /// Adds the `dialogue` global: `start(world, name, entity?)`,
+    /// `current()` as `{ dialogue, node, speaker, text, choices }` or nil,
+    /// `advance(world)`, `choose(world, index)` counting from 1,
+    /// `stop(world)`, `active()`, `on(hook, function(world, entity))` and
+    /// `load(path)`, all as methods.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("dialogue", self.clone())
+    }
+}
+
+impl UserData for Dialogue {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method(
+            "start",
+            |lua, this, (world, name, entity): (AnyUserData, String, Option<Entity>)| {
+                world
+                    .borrow_mut_scoped::<World, _>(|world| this.start(world, lua, &name, entity))?
+            },
+        );
+        methods.add_method("current", |lua, this, ()| {
+            this.current().map(|view| lua.to_value(&view)).transpose()
+        });
+        methods.add_method("advance", |lua, this, world: AnyUserData| {
+            world.borrow_mut_scoped::<World, _>(|world| this.advance(world, lua))?
+        });
+        methods.add_method(
+            "choose",
+            |lua, this, (world, index): (AnyUserData, usize)| {
+                let choice = index
+                    .checked_sub(1)
+                    .ok_or_else(|| Error::runtime("choices count from 1"))?;
+                world.borrow_mut_scoped::<World, _>(|world| this.choose(world, lua, choice))?
+            },
+        );
+        methods.add_method("stop", |_, this, world: AnyUserData| {
+            world.borrow_mut_scoped::<World, _>(|world| this.stop(world))?
+        });
+        methods.add_method("active", |_, this, ()| Ok(this.is_active()));
+        methods.add_method("on", |_, this, (name, hook): (String, Function)| {
+            this.state.borrow_mut().hooks.insert(name, Hook::Lua(hook));
+            Ok(())
+        });
+        methods.add_method("load", |lua, this, path: String| {
+            let loaded = Dialogues::from_ron(&read_lua_asset(lua, &path)?)?;
+            this.dialogues_mut().extend(loaded);
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::ScriptValue;
+
+    const GUARD: &str = r#"{
+        "gate": (
+            start: "greet",
+            nodes: {
+                "greet": Line(speaker: Some("guard.name"), text: "gate.halt", next: Some("ask")),
+                "ask": Choice(choices: [
+                    (text: "gate.leave", next: None),
+                    (text: "gate.bribe", next: Some("paid"), hook: Some("pay"),
+                        when: Some(Field(component: "Wallet", field: "gold", min: Some(10)))),
+                ]),
+                "paid": Branch(branches: [(Has("Paid"), "thanks")]),
+                "thanks": Line(text: "gate.thanks", hook: Some("open_gate")),
+            },
+        ),
+    }"#;
+
+    #[test]
+    fn test_walks_a_graph_with_choices_and_hooks() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("ee_dialogue_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("en.ron"),
+            r#"{ "guard.name": "Guard", "gate.halt": "Halt!", "gate.bribe": "Here's 10 gold." }"#,
+        )
+        .unwrap();
+        assert!(Dialogues::from_ron(r#"{ "x": (start: "nowhere", nodes: {}) }"#).is_err());
+
+        let lua = Lua::new();
+        let mut world = World::new();
+        let player = world.spawn();
+        world.set_script_component(
+            player,
+            "Wallet",
+            ScriptValue::Map(BTreeMap::from([(
+                "gold".to_string(),
+                ScriptValue::Number(12.0),
+            )])),
+        )?;
+        let dialogue = Dialogue::new(Dialogues::from_ron(GUARD)?);
+        dialogue.set_localization(Localization::new(&dir, "en")?);
+        dialogue.on("pay", |world, _, entity| {
+            world.set_script_component(entity.unwrap(), "Paid", ScriptValue::Bool(true))?;
+            Ok(())
+        });
+        dialogue.register_lua(&lua)?;
+
+        let opened: bool = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world, player = ...
+                opened = false
+                dialogue:on("open_gate", function(world, entity) opened = entity == player end)
+                dialogue:start(world, "gate", player)
+                local line = dialogue:current()
+                assert(line.speaker == "Guard" and line.text == "Halt!", line.text)
+                dialogue:advance(world)
+                local ask = dialogue:current()
+                assert(#ask.choices == 2 and ask.choices[2] == "Here's 10 gold.")
+                assert(not pcall(dialogue.advance, dialogue, world))
+                dialogue:choose(world, 2)
+                assert(dialogue:current().text == "gate.thanks")
+                dialogue:advance(world)
+                assert(not dialogue:active() and dialogue:current() == nil)
+                return opened
+            "#,
+            )
+            .call((handle, player))
+        })?;
+        assert!(opened);
+
+        let kinds: Vec<String> = world
+            .drain_script_events(DIALOGUE_EVENT)
+            .into_iter()
+            .filter_map(|event| match event {
+                ScriptValue::Map(fields) => match fields.get("kind") {
+                    Some(ScriptValue::String(kind)) => Some(kind.clone()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            ["started", "line", "choices", "chosen", "line", "ended"]
+        );
+
+        // Too poor to bribe, only leaving is offered.
+        world.set_script_component(
+            player,
+            "Wallet",
+            ScriptValue::Map(BTreeMap::from([(
+                "gold".to_string(),
+                ScriptValue::Number(3.0),
+            )])),
+        )?;
+        dialogue.start(&mut world, &lua, "gate", Some(player))?;
+        dialogue.advance(&mut world, &lua)?;
+        assert_eq!(dialogue.current().unwrap().choices, ["gate.leave"]);
+        dialogue.choose(&mut world, &lua, 0)?;
+        assert!(!dialogue.is_active());
+
+        // Nothing to offer ends the conversation instead of waiting forever.
+        dialogue.dialogues_mut().extend(Dialogues::from_ron(
+            r#"{ "vip": (start: "door", nodes: {
+                "door": Choice(choices: [(text: "enter", next: None, when: Some(Has("Badge")))]),
+            }) }"#,
+        )?);
+        dialogue.start(&mut world, &lua, "vip", Some(player))?;
+        assert!(!dialogue.is_active() && dialogue.current().is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+        Ok(())
+    }
+}
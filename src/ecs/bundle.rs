@@ -0,0 +1,152 @@
+use super::{Component, EcsError, Entity, World};
+
+/// A set of components inserted together. Implemented for tuples; wrap a
+/// single component as `(component,)`.
+pub trait Bundle: 'static {
+    /// Grows every column the bundle touches by `additional` rows.
+    fn reserve(world: &mut World, additional: usize);
+
+    fn insert_into(self, world: &mut World, entity: Entity) -> Result<(), EcsError>;
+}
+
+macro_rules! impl_bundle {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<$($name: Component),+> Bundle for ($($name,)+) {
+            fn reserve(world: &mut World, additional: usize) {
+                $(world.column_mut::<$name>().reserve(additional);)+
+            }
+
+            fn insert_into(self, world: &mut World, entity: Entity) -> Result<(), EcsError> {
+                let ($($name,)+) = self;
+                $(world.insert(entity, $name)?;)+
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+impl_bundle!(A, B, C, D, E, F);
+impl_bundle!(A, B, C, D, E, F, G);
+impl_bundle!(A, B, C, D, E, F, G, H);
+
+impl World {
+    pub fn spawn_bundle<B: Bundle>(&mut self, bundle: B) -> Entity {
+        let entity = self.spawn();
+        bundle
+            .insert_into(self, entity)
+            .expect("freshly spawned entity is alive");
+        entity
+    }
+
+    /// Spawns one entity per bundle, reserving entity slots and column rows
+    /// up front from the iterator's size hint.
+    pub fn spawn_batch<B, I>(&mut self, bundles: I) -> Vec<Entity>
+    where
+        B: Bundle,
+        I: IntoIterator<Item = B>,
+    {
+        let bundles = bundles.into_iter();
+        let expected = bundles.size_hint().0;
+        self.reserve_entities(expected);
+        B::reserve(self, expected);
+
+        let mut spawned = Vec::with_capacity(expected);
+        for bundle in bundles {
+            spawned.push(self.spawn_bundle(bundle));
+        }
+        spawned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mlua::{Lua, Result};
+
+    #[derive(Debug, PartialEq)]
+    struct Position(f64);
+    #[derive(Debug, PartialEq)]
+    struct Lifetime(u32);
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct Health(f32);
+
+    #[test]
+    fn test_spawn_batch_inserts_every_component() {
+        let mut world = World::new();
+        let particles = world.spawn_batch((0..1000).map(|i| (Position(i as f64), Lifetime(60))));
+
+        assert_eq!(particles.len(), 1000);
+        assert_eq!(
+            world.query::<(&Position, &Lifetime)>().entities().len(),
+            1000
+        );
+        assert_eq!(
+            *world.get::<Position>(particles[10]).unwrap(),
+            Position(10.0)
+        );
+    }
+
+    #[test]
+    fn test_lua_spawn_batch_with_overrides() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+
+        let spawned: Vec<Entity> = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world = ...
+                return world:spawn_batch(3, { Bullet = true, Speed = 10 }, function(i)
+                    return { Speed = i * 2, Index = i }
+                end)
+            "#,
+            )
+            .call(handle)
+        })?;
+
+        assert_eq!(spawned.len(), 3);
+        let last = spawned[2];
+        assert_eq!(
+            world.script_component(last, "Speed"),
+            Some(&crate::ecs::ScriptValue::Number(6.0))
+        );
+        assert!(world.script_component(last, "Bullet").is_some());
+        assert!(world.script_component(last, "Index").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lua_spawn_batch_failure_leaves_no_entities() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.register_component::<Health>("Health");
+
+        lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            let spawn = |count: &str| {
+                lua.load(format!(
+                    r#"
+                    local world = ...
+                    world:spawn_batch({}, {{ Health = 10 }}, function(i)
+                        if i == 3 then return {{ Health = "full" }} end
+                    end)
+                "#,
+                    count
+                ))
+                .call::<()>(&handle)
+            };
+            assert!(spawn("5").is_err());
+            assert!(spawn("math.maxinteger").is_err());
+            spawn("2")?;
+            Ok(())
+        })?;
+        assert_eq!(world.len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use super::{ComponentInfo, EcsError, Entity, World};
+use mlua::{Function, Lua, LuaSerdeExt, Result};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// Source entity to its copy, for every entity a clone produced.
+pub type EntityMap = BTreeMap<Entity, Entity>;
+
+pub type RustFixup = Rc<dyn Fn(&mut World, &EntityMap) -> Result<()>>;
+
+#[derive(Clone)]
+pub enum CloneFixup {
+    Rust(RustFixup),
+    /// Called as `f(world, map)` with `map[old] == new`.
+    Lua(Function),
+}
+
+impl World {
+    /// Copies every registered Rust component, script component and name of
+    /// `entity`, and with `with_children` its whole subtree, re-parented to
+    /// match. Unregistered Rust components are not copied. Entity
+    /// references in the copies are tracked like the originals'.
+    ///
+    /// The copy of `entity` keeps the original's parent.
+    pub fn clone_entity(
+        &mut self,
+        lua: &Lua,
+        entity: Entity,
+        with_children: bool,
+    ) -> Result<EntityMap> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::NoSuchEntity(entity).into());
+        }
+        let mut sources = vec![entity];
+        if with_children {
+            sources.extend(self.descendants(entity));
+        }
+
+        let mut map = EntityMap::new();
+        // Like `spawn`, a clone that fails partway leaves no copies behind.
+        if let Err(e) = self.copy_entities(lua, &sources, &mut map) {
+            for &copy in map.values() {
+                self.despawn(copy);
+            }
+            return Err(e);
+        }
+
+        for fixup in self.clone_fixups() {
+            match fixup {
+                CloneFixup::Rust(f) => f(self, &map)?,
+                CloneFixup::Lua(f) => {
+                    let table = lua.create_table_from(map.iter().map(|(k, v)| (*k, *v)))?;
+                    lua.scope(|scope| {
+                        let handle = scope.create_userdata_ref_mut(&mut *self)?;
+                        f.call::<()>((handle, table))
+                    })?;
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    /// Spawns a copy of each source, recorded in `map`, then re-parents them.
+    fn copy_entities(&mut self, lua: &Lua, sources: &[Entity], map: &mut EntityMap) -> Result<()> {
+        let components: Vec<ComponentInfo> = self.registry().iter().cloned().collect();
+        let script_names: Vec<String> = self.script_component_names().map(str::to_string).collect();
+
+        for &source in sources {
+            let copy = self.spawn();
+            map.insert(source, copy);
+
+            for info in &components {
+                if let Some(value) = (info.get)(self, source, lua)? {
+                    if self.registry().schema(&info.name).is_some() {
+                        self.track_refs(copy, &info.name, &lua.from_value(value.clone())?);
+                    }
+                    (info.set)(self, copy, lua, value)?;
+                }
+            }
+            for name in &script_names {
+                if let Some(value) = self.script_component(source, name).cloned() {
+                    self.track_refs(copy, name, &value);
+                    self.set_script_component(copy, name, value)?;
+                }
+            }
+            if let Some(name) = self.name(source).map(str::to_string) {
+                self.set_name(copy, name)?;
+            }
+        }
+
+        for &source in sources {
+            let parent = self
+                .parent(source)
+                .map(|p| map.get(&p).copied().unwrap_or(p));
+            if parent.is_some() {
+                self.set_parent(map[&source], parent)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Health(u32);
+
+    #[test]
+    fn test_clone_copies_subtree_and_fixes_references() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.register_component::<Health>("Health");
+
+        let knight = world.spawn();
+        let sword = world.spawn();
+        world.insert(knight, Health(20))?;
+        world.set_name(knight, "Knight")?;
+        world.set_parent(sword, Some(knight))?;
+
+        let (copy, map): (Entity, mlua::Table) = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world, knight, sword = ...
+                world:set(knight, "Weapon", sword)
+                world:on_clone(function(world, map)
+                    for _, new in map do
+                        local target = world:get(new, "Weapon")
+                        if target and map[target] then
+                            world:set(new, "Weapon", map[target])
+                        end
+                    end
+                end)
+                return world:clone(knight, { children = true })
+            "#,
+            )
+            .call((handle, knight, sword))
+        })?;
+        let sword_copy: Entity = map.get(sword)?;
+
+        assert_eq!(*world.get::<Health>(copy).unwrap(), Health(20));
+        assert_eq!(world.children(copy), vec![sword_copy]);
+        assert_eq!(world.find_all("Knight").len(), 2);
+        assert_eq!(
+            world.get_by_name(&lua, copy, "Weapon")?,
+            mlua::Value::Number(sword_copy.to_bits() as f64)
+        );
+        assert_eq!(world.children(knight), vec![sword]);
+
+        world.set_name_policy(crate::ecs::NamePolicy::Unique);
+        let before = world.len();
+        assert!(world.clone_entity(&lua, knight, true).is_err());
+        assert_eq!(world.len(), before);
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+use super::World;
+use mlua::{Lua, Result};
+
+pub type Command = Box<dyn FnOnce(&mut World, &Lua) -> Result<()>>;
+
+/// World changes deferred until the current system or script returns, so
+/// they never run while a query or Lua call is borrowing the world.
+#[derive(Default)]
+pub struct CommandBuffer {
+    commands: Vec<Command>,
+}
+
+impl CommandBuffer {
+    pub fn push(&mut self, command: impl FnOnce(&mut World, &Lua) -> Result<()> + 'static) {
+        self.commands.push(Box::new(command));
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<Command> {
+        std::mem::take(&mut self.commands)
+    }
+}
@@ -0,0 +1,236 @@
+use super::storage::ComponentTicks;
+use super::{ComponentRegistry, EcsError, Entity, NAME_COMPONENT, World};
+use mlua::{Error, FromLua, Lua, Result, Table, Value};
+use std::any::TypeId;
+
+/// A query over components addressed by name, as written from Lua:
+/// `{ "Position", "Velocity", without = { "Frozen" }, changed = { "Position" } }`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuerySpec {
+    pub with: Vec<String>,
+    pub without: Vec<String>,
+    /// Required components that also must have changed since the previous tick.
+    pub changed: Vec<String>,
+}
+
+impl QuerySpec {
+    /// Cache key; specs listing the same names in the same order share one
+    /// compiled query.
+    pub fn signature(&self) -> String {
+        format!(
+            "{}|{}|{}",
+            self.with.join(","),
+            self.without.join(","),
+            self.changed.join(",")
+        )
+    }
+}
+
+fn names_field(table: &Table, field: &str) -> Result<Vec<String>> {
+    table
+        .get::<Option<Vec<String>>>(field)
+        .map(Option::unwrap_or_default)
+        .map_err(|e| Error::FromLuaConversionError {
+            from: "Table",
+            to: "QuerySpec".to_string(),
+            message: Some(format!("Failed to get '{}' field: {}", field, e)),
+        })
+}
+
+impl FromLua for QuerySpec {
+    fn from_lua(value: Value, _lua: &Lua) -> Result<Self> {
+        match value {
+            Value::Table(table) => Ok(QuerySpec {
+                with: table
+                    .sequence_values()
+                    .collect::<Result<_>>()
+                    .map_err(|e| Error::FromLuaConversionError {
+                        from: "Table",
+                        to: "QuerySpec".to_string(),
+                        message: Some(format!("Failed to get component names: {}", e)),
+                    })?,
+                without: names_field(&table, "without")?,
+                changed: names_field(&table, "changed")?,
+            }),
+            _ => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "QuerySpec".to_string(),
+                message: Some("Expected a table".to_string()),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Column {
+    Rust(TypeId),
+    Script(String),
+    Name,
+}
+
+impl Column {
+    fn resolve(registry: &ComponentRegistry, name: &str) -> Column {
+        if name == NAME_COMPONENT {
+            return Column::Name;
+        }
+        match registry.get(name) {
+            Some(info) => Column::Rust(info.type_id),
+            None => Column::Script(name.to_string()),
+        }
+    }
+
+    /// `None` when the column has never been created, so nothing can match.
+    fn entities(&self, world: &World) -> Option<Vec<Entity>> {
+        match self {
+            Column::Rust(type_id) => {
+                Some(world.storage_by_id(*type_id)?.borrow().entities().to_vec())
+            }
+            Column::Script(name) => Some(world.script_components(name)?.entities().to_vec()),
+            Column::Name => Some(
+                world
+                    .entities()
+                    .filter(|&e| world.name(e).is_some())
+                    .collect(),
+            ),
+        }
+    }
+
+    fn contains(&self, world: &World, entity: Entity) -> bool {
+        match self {
+            Column::Rust(type_id) => world
+                .storage_by_id(*type_id)
+                .is_some_and(|cell| cell.borrow().contains(entity)),
+            Column::Script(name) => world
+                .script_components(name)
+                .is_some_and(|storage| storage.contains(entity)),
+            Column::Name => world.name(entity).is_some(),
+        }
+    }
+
+    fn ticks(&self, world: &World, entity: Entity) -> Option<ComponentTicks> {
+        match self {
+            Column::Rust(type_id) => world.storage_by_id(*type_id)?.borrow().ticks(entity),
+            Column::Script(name) => world.script_components(name)?.ticks(entity),
+            Column::Name => None,
+        }
+    }
+}
+
+/// A `QuerySpec` with its names resolved against the world's registry.
+#[derive(Debug, Clone)]
+pub struct DynamicQuery {
+    with: Vec<Column>,
+    without: Vec<Column>,
+    changed: Vec<Column>,
+}
+
+impl DynamicQuery {
+    pub fn compile(
+        registry: &ComponentRegistry,
+        spec: &QuerySpec,
+    ) -> std::result::Result<Self, EcsError> {
+        if spec.with.is_empty() && spec.changed.is_empty() {
+            return Err(EcsError::InvalidQuery(
+                "a query needs at least one required component".to_string(),
+            ));
+        }
+        if spec.changed.iter().any(|name| name == NAME_COMPONENT) {
+            return Err(EcsError::InvalidQuery(format!(
+                "'{}' does not track changes",
+                NAME_COMPONENT
+            )));
+        }
+
+        let resolve = |names: &[String]| -> Vec<Column> {
+            names
+                .iter()
+                .map(|name| Column::resolve(registry, name))
+                .collect()
+        };
+        Ok(DynamicQuery {
+            with: resolve(&spec.with),
+            without: resolve(&spec.without),
+            changed: resolve(&spec.changed),
+        })
+    }
+
+    /// Matching entities. A component counts as changed if it was written
+    /// during the current or the previous tick.
+    pub fn run(&self, world: &World) -> Vec<Entity> {
+        let since = world.tick().saturating_sub(1);
+
+        let mut candidates: Option<Vec<Entity>> = None;
+        for column in self.with.iter().chain(&self.changed) {
+            let Some(entities) = column.entities(world) else {
+                return Vec::new();
+            };
+            if candidates
+                .as_ref()
+                .is_none_or(|best| entities.len() < best.len())
+            {
+                candidates = Some(entities);
+            }
+        }
+
+        candidates
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|&entity| {
+                self.with
+                    .iter()
+                    .all(|column| column.contains(world, entity))
+                    && self.changed.iter().all(|column| {
+                        column
+                            .ticks(world, entity)
+                            .is_some_and(|ticks| ticks.changed >= since)
+                    })
+                    && !self
+                        .without
+                        .iter()
+                        .any(|column| column.contains(world, entity))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::ScriptValue;
+
+    #[test]
+    fn test_dynamic_query_filters() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.register_component::<f64>("Speed");
+
+        let (a, b, c) = (world.spawn(), world.spawn(), world.spawn());
+        for entity in [a, b, c] {
+            world.insert(entity, 1.0f64)?;
+            world.set_script_component(entity, "Position", ScriptValue::Number(0.0))?;
+        }
+        world.set_script_component(b, "Frozen", ScriptValue::Bool(true))?;
+
+        let spec: QuerySpec = lua
+            .load("return { 'Position', 'Speed', without = { 'Frozen' } }")
+            .eval()?;
+        let query = DynamicQuery::compile(world.registry(), &spec)?;
+        assert_eq!(query.run(&world), vec![a, c]);
+
+        world.advance_tick();
+        world.advance_tick();
+        world.set_script_component(c, "Position", ScriptValue::Number(4.0))?;
+
+        let changed = DynamicQuery::compile(
+            world.registry(),
+            &QuerySpec {
+                changed: vec!["Position".to_string()],
+                ..QuerySpec::default()
+            },
+        )?;
+        assert_eq!(changed.run(&world), vec![c]);
+
+        assert!(DynamicQuery::compile(world.registry(), &QuerySpec::default()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use mlua::{Error, FromLua, IntoLua, Lua, Result, Value};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// Generations wrap at 21 bits so `to_bits` stays exactly representable as a
+/// Lua number.
+const GENERATION_MASK: u32 = (1 << 21) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn to_bits(&self) -> u64 {
+        (self.generation as u64) << 32 | self.index as u64
+    }
+
+    pub fn from_bits(bits: u64) -> Option<Entity> {
+        let generation = (bits >> 32) as u32;
+        if generation > GENERATION_MASK {
+            return None;
+        }
+        Some(Entity {
+            index: bits as u32,
+            generation,
+        })
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+impl IntoLua for Entity {
+    fn into_lua(self, _lua: &Lua) -> Result<Value> {
+        Ok(Value::Number(self.to_bits() as f64))
+    }
+}
+
+impl FromLua for Entity {
+    fn from_lua(value: Value, _lua: &Lua) -> Result<Self> {
+        let bits = match value {
+            Value::Integer(i) if i >= 0 => Some(i as u64),
+            Value::Number(n) if n >= 0.0 && n.fract() == 0.0 && n < (1u64 << 53) as f64 => {
+                Some(n as u64)
+            }
+            _ => None,
+        };
+
+        bits.and_then(Entity::from_bits)
+            .ok_or_else(|| Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "Entity".to_string(),
+                message: Some("Expected an entity id".to_string()),
+            })
+    }
+}
+
+/// Serialized as its bits, so entities inside components look the same to
+/// serde as they do to Lua.
+impl Serialize for Entity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.to_bits())
+    }
+}
+
+struct EntityVisitor;
+
+impl Visitor<'_> for EntityVisitor {
+    type Value = Entity;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an entity id")
+    }
+
+    fn visit_u64<E: de::Error>(self, bits: u64) -> std::result::Result<Entity, E> {
+        Entity::from_bits(bits).ok_or_else(|| E::custom("entity generation out of range"))
+    }
+
+    fn visit_i64<E: de::Error>(self, bits: i64) -> std::result::Result<Entity, E> {
+        let bits = u64::try_from(bits).map_err(|_| E::custom("negative entity id"))?;
+        self.visit_u64(bits)
+    }
+
+    fn visit_f64<E: de::Error>(self, bits: f64) -> std::result::Result<Entity, E> {
+        if bits < 0.0 || bits.fract() != 0.0 || bits >= (1u64 << 53) as f64 {
+            return Err(E::custom("entity ids are non-negative integers"));
+        }
+        self.visit_u64(bits as u64)
+    }
+}
+
+impl<'de> Deserialize<'de> for Entity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_any(EntityVisitor)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Entities {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl Entities {
+    pub fn alloc(&mut self) -> Entity {
+        self.len += 1;
+        if let Some(index) = self.free.pop() {
+            self.alive[index as usize] = true;
+            return Entity {
+                index,
+                generation: self.generations[index as usize],
+            };
+        }
+
+        let index = self.generations.len() as u32;
+        self.generations.push(0);
+        self.alive.push(true);
+        Entity {
+            index,
+            generation: 0,
+        }
+    }
+
+    /// Makes room for `additional` new indices beyond the free list.
+    pub fn reserve(&mut self, additional: usize) {
+        let fresh = additional.saturating_sub(self.free.len());
+        self.generations.reserve(fresh);
+        self.alive.reserve(fresh);
+    }
+
+    pub fn free(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] = (self.generations[index] + 1) & GENERATION_MASK;
+        self.free.push(entity.index);
+        self.len -= 1;
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        index < self.alive.len()
+            && self.alive[index]
+            && self.generations[index] == entity.generation
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Generations by index and the free list, which together describe
+    /// the allocator exactly: every index not on the free list is alive.
+    pub(crate) fn parts(&self) -> (&[u32], &[u32]) {
+        (&self.generations, &self.free)
+    }
+
+    pub(crate) fn from_parts(generations: Vec<u32>, free: Vec<u32>) -> Self {
+        let mut alive = vec![true; generations.len()];
+        for &index in &free {
+            alive[index as usize] = false;
+        }
+        Entities {
+            len: generations.len() - free.len(),
+            generations,
+            alive,
+            free,
+        }
+    }
+
+    /// Live entities in index order.
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive
+            .iter()
+            .enumerate()
+            .filter(|(_, alive)| **alive)
+            .map(|(index, _)| Entity {
+                index: index as u32,
+                generation: self.generations[index],
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recycled_entities_get_new_generation() {
+        let mut entities = Entities::default();
+        let a = entities.alloc();
+        let b = entities.alloc();
+        assert!(entities.free(a));
+        assert!(!entities.free(a));
+
+        let c = entities.alloc();
+        assert_eq!(c.index(), a.index());
+        assert_ne!(c, a);
+        assert!(!entities.is_alive(a));
+        assert!(entities.is_alive(c));
+        assert_eq!(entities.iter().collect::<Vec<_>>(), vec![c, b]);
+    }
+
+    #[test]
+    fn test_entity_round_trips_through_lua() -> Result<()> {
+        let lua = Lua::new();
+        let entity = Entity {
+            index: 7,
+            generation: GENERATION_MASK,
+        };
+
+        let back: Entity = lua.load("return ...").call(entity)?;
+        assert_eq!(back, entity);
+        assert!(lua.load("return 1.5").eval::<Entity>().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+use super::{ScriptValue, World};
+use serde::Serialize;
+use std::any::Any;
+use std::collections::BTreeMap;
+
+/// Queue of events of one type, stored as a world resource.
+#[derive(Debug, Clone)]
+pub struct Events<E> {
+    queue: Vec<E>,
+}
+
+impl<E> Default for Events<E> {
+    fn default() -> Self {
+        Events { queue: Vec::new() }
+    }
+}
+
+impl<E> Events<E> {
+    pub fn send(&mut self, event: E) {
+        self.queue.push(event);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &E> {
+        self.queue.iter()
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = E> + '_ {
+        self.queue.drain(..)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+/// Events addressed by name, the form scripts send and receive.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptEvents {
+    by_name: BTreeMap<String, Vec<ScriptValue>>,
+    sent: BTreeMap<String, u64>,
+}
+
+impl ScriptEvents {
+    pub fn send(&mut self, name: &str, payload: ScriptValue) {
+        self.by_name
+            .entry(name.to_string())
+            .or_default()
+            .push(payload);
+        *self.sent.entry(name.to_string()).or_default() += 1;
+    }
+
+    pub fn drain(&mut self, name: &str) -> Vec<ScriptValue> {
+        self.by_name.remove(name).unwrap_or_default()
+    }
+
+    /// Events waiting under `name`, left for whoever drains them.
+    pub fn peek(&self, name: &str) -> &[ScriptValue] {
+        self.by_name.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// How many events were ever sent under `name`, drained or not, so
+    /// observers can tell which queued ones they haven't seen.
+    pub fn sent(&self, name: &str) -> u64 {
+        self.sent.get(name).copied().unwrap_or(0)
+    }
+
+    /// Events under `name` sent since the observer had seen `seen` of
+    /// them, as far as they're still queued, moving `seen` up to now. For
+    /// observers that look rather than drain: run them after the systems
+    /// sending and before any draining in the same frame, since drained
+    /// events are missed. A `seen` past `sent`, as after the resource was
+    /// replaced, starts over.
+    pub fn unseen(&self, name: &str, seen: &mut u64) -> &[ScriptValue] {
+        let queued = self.peek(name);
+        let sent = self.sent(name);
+        if *seen > sent {
+            *seen = 0;
+        }
+        let new = (sent - *seen).min(queued.len() as u64) as usize;
+        *seen = sent;
+        &queued[queued.len() - new..]
+    }
+
+    /// Each name with events waiting, and how many.
+    pub fn pending(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.by_name
+            .iter()
+            .filter(|(_, queue)| !queue.is_empty())
+            .map(|(name, queue)| (name.as_str(), queue.len()))
+    }
+}
+
+impl World {
+    pub fn send_script_event(&mut self, name: &str, payload: ScriptValue) {
+        if self.resource::<ScriptEvents>().is_none() {
+            self.insert_resource(ScriptEvents::default());
+        }
+        self.resource_mut::<ScriptEvents>()
+            .expect("events resource was just inserted")
+            .send(name, payload);
+    }
+
+    pub fn drain_script_events(&mut self, name: &str) -> Vec<ScriptValue> {
+        self.resource_mut::<ScriptEvents>()
+            .map(|events| events.drain(name))
+            .unwrap_or_default()
+    }
+
+    pub fn send_event<E: Any + Send + Sync>(&mut self, event: E) {
+        if self.resource::<Events<E>>().is_none() {
+            self.insert_resource(Events::<E>::default());
+        }
+        self.resource_mut::<Events<E>>()
+            .expect("events resource was just inserted")
+            .send(event);
+    }
+
+    /// Sends `event` typed, for Rust systems, and as its serialized form
+    /// under `name`, for scripts. `None` fields should be skipped when
+    /// serializing; scripts have no value for them.
+    pub fn emit<E: Serialize + Any + Send + Sync>(
+        &mut self,
+        name: &str,
+        event: E,
+    ) -> mlua::Result<()> {
+        let json = serde_json::to_value(&event).map_err(mlua::Error::external)?;
+        let payload: ScriptValue = serde_json::from_value(json).map_err(mlua::Error::external)?;
+        self.send_script_event(name, payload);
+        self.send_event(event);
+        Ok(())
+    }
+
+    pub fn events<E: Any + Send + Sync>(&self) -> Option<&Events<E>> {
+        self.resource::<Events<E>>()
+    }
+
+    pub fn drain_events<E: Any + Send + Sync>(&mut self) -> Vec<E> {
+        self.resource_mut::<Events<E>>()
+            .map(|events| events.drain().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unseen_counts_from_the_last_look() {
+        let mut events = ScriptEvents::default();
+        let mut seen = 0;
+        events.send("hit", ScriptValue::Number(1.0));
+        events.send("hit", ScriptValue::Number(2.0));
+        assert_eq!(events.unseen("hit", &mut seen).len(), 2);
+        events.send("hit", ScriptValue::Number(3.0));
+        assert_eq!(events.unseen("hit", &mut seen), [ScriptValue::Number(3.0)]);
+        assert!(events.unseen("hit", &mut seen).is_empty());
+
+        // A fresh resource doesn't underflow an old count.
+        let mut replaced = ScriptEvents::default();
+        replaced.send("hit", ScriptValue::Number(4.0));
+        assert_eq!(replaced.unseen("hit", &mut seen).len(), 1);
+        assert_eq!(seen, 1);
+    }
+
+    #[test]
+    fn test_peek_leaves_events_and_sent_outlives_drain() {
+        let mut events = ScriptEvents::default();
+        assert!(events.peek("hit").is_empty());
+        assert_eq!(events.sent("hit"), 0);
+        events.send("hit", ScriptValue::Number(1.0));
+        events.send("hit", ScriptValue::Number(2.0));
+        assert_eq!(events.peek("hit").len(), 2);
+        assert_eq!(events.peek("hit").len(), 2);
+        assert_eq!(events.drain("hit").len(), 2);
+        assert!(events.peek("hit").is_empty());
+        assert_eq!(events.sent("hit"), 2);
+        assert_eq!(events.pending().count(), 0);
+    }
+
+    #[test]
+    fn test_emit_sends_typed_and_script_events() -> mlua::Result<()> {
+        #[derive(Debug, PartialEq, Serialize)]
+        struct Door {
+            open: bool,
+        }
+
+        let mut world = World::new();
+        world.emit("door", Door { open: true })?;
+        assert_eq!(world.drain_events::<Door>(), [Door { open: true }]);
+        assert_eq!(
+            world.drain_script_events("door"),
+            [ScriptValue::Map(BTreeMap::from([(
+                "open".to_string(),
+                ScriptValue::Bool(true)
+            )]))]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use super::{EcsError, Entity, World};
+
+/// Set through `World::set_parent`, which keeps `Children` in sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parent(Entity);
+
+impl Parent {
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Children(Vec<Entity>);
+
+impl Children {
+    pub fn as_slice(&self) -> &[Entity] {
+        &self.0
+    }
+}
+
+impl World {
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        self.get::<Parent>(entity).map(|parent| parent.get())
+    }
+
+    pub fn children(&self, entity: Entity) -> Vec<Entity> {
+        self.get::<Children>(entity)
+            .map_or_else(Vec::new, |children| children.0.clone())
+    }
+
+    /// Every entity below `entity`, parents before their children.
+    pub fn descendants(&self, entity: Entity) -> Vec<Entity> {
+        let mut found = Vec::new();
+        let mut stack = self.children(entity);
+        stack.reverse();
+        while let Some(next) = stack.pop() {
+            found.push(next);
+            let mut children = self.children(next);
+            children.reverse();
+            stack.extend(children);
+        }
+        found
+    }
+
+    /// Moves `child` under `parent`, or makes it a root with `None`.
+    pub fn set_parent(&mut self, child: Entity, parent: Option<Entity>) -> Result<(), EcsError> {
+        for entity in std::iter::once(child).chain(parent) {
+            if !self.is_alive(entity) {
+                return Err(EcsError::NoSuchEntity(entity));
+            }
+        }
+        if let Some(parent) = parent
+            && (parent == child || self.descendants(child).contains(&parent))
+        {
+            return Err(EcsError::InvalidComponent {
+                component: "Parent".to_string(),
+                message: format!(
+                    "{} cannot be parented to its own descendant {}",
+                    child, parent
+                ),
+            });
+        }
+
+        self.detach(child);
+        if let Some(parent) = parent {
+            self.insert(child, Parent(parent))?;
+            let added = self
+                .get_mut::<Children>(parent)
+                .map(|mut children| children.0.push(child))
+                .is_some();
+            if !added {
+                self.insert(parent, Children(vec![child]))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn detach(&mut self, child: Entity) {
+        let Some(Parent(old)) = self.remove::<Parent>(child) else {
+            return;
+        };
+        let now_empty = match self.get_mut::<Children>(old) {
+            Some(mut children) => {
+                children.0.retain(|&e| e != child);
+                children.0.is_empty()
+            }
+            None => false,
+        };
+        if now_empty {
+            self.remove::<Children>(old);
+        }
+    }
+
+    /// Removes `entity` from its parent and turns its children into roots.
+    pub(crate) fn unlink(&mut self, entity: Entity) {
+        self.detach(entity);
+        if let Some(Children(children)) = self.remove::<Children>(entity) {
+            for child in children {
+                self.remove::<Parent>(child);
+            }
+        }
+    }
+
+    /// Despawns `entity` and everything below it, returning how many
+    /// entities were removed.
+    pub fn despawn_recursive(&mut self, entity: Entity) -> usize {
+        let mut doomed = self.descendants(entity);
+        doomed.insert(0, entity);
+        doomed
+            .into_iter()
+            .rev()
+            .filter(|&e| self.despawn(e))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hierarchy_stays_consistent() {
+        let mut world = World::new();
+        let (root, arm, hand) = (world.spawn(), world.spawn(), world.spawn());
+        world.set_parent(arm, Some(root)).unwrap();
+        world.set_parent(hand, Some(arm)).unwrap();
+
+        assert_eq!(world.descendants(root), vec![arm, hand]);
+        assert!(world.set_parent(root, Some(hand)).is_err());
+
+        world.despawn(arm);
+        assert!(world.children(root).is_empty());
+        assert_eq!(world.parent(hand), None);
+
+        world.set_parent(hand, Some(root)).unwrap();
+        assert_eq!(world.despawn_recursive(root), 2);
+        assert!(world.is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+use super::commands::CommandBuffer;
+use super::{Component, ComponentRegistry, Entity, World};
+use mlua::{Function, Lua, Result};
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Identifies a component column for hooks: a Rust type or a script
+/// component name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ComponentKey {
+    Rust(TypeId),
+    Script(String),
+}
+
+impl ComponentKey {
+    pub fn of<T: Component>() -> Self {
+        ComponentKey::Rust(TypeId::of::<T>())
+    }
+
+    /// Resolves a name the way scripts see it: registered Rust components
+    /// first, otherwise a script component.
+    pub fn named(registry: &ComponentRegistry, name: &str) -> Self {
+        match registry.get(name) {
+            Some(info) => ComponentKey::Rust(info.type_id),
+            None => ComponentKey::Script(name.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    Added,
+    /// Fired after the component is gone; the entity may be despawned too.
+    Removed,
+}
+
+pub type RustHook = Rc<dyn Fn(&mut World, Entity) -> Result<()>>;
+
+#[derive(Clone)]
+pub enum Hook {
+    Rust(RustHook),
+    /// Called as `f(world, entity)`.
+    Lua(Function),
+}
+
+impl Hook {
+    fn call(&self, world: &mut World, lua: &Lua, entity: Entity) -> Result<()> {
+        match self {
+            Hook::Rust(hook) => hook(world, entity),
+            Hook::Lua(function) => lua.scope(|scope| {
+                let handle = scope.create_userdata_ref_mut(world)?;
+                function.call::<()>((handle, entity))
+            }),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Hooks {
+    by_key: HashMap<ComponentKey, Vec<(HookEvent, Hook)>>,
+}
+
+impl Hooks {
+    pub(crate) fn add(&mut self, key: ComponentKey, event: HookEvent, hook: Hook) {
+        self.by_key.entry(key).or_default().push((event, hook));
+    }
+
+    /// Queues every hook registered for `key` and `event`.
+    pub(crate) fn fire(
+        &self,
+        commands: &mut CommandBuffer,
+        key: &ComponentKey,
+        event: HookEvent,
+        entity: Entity,
+    ) {
+        let Some(hooks) = self.by_key.get(key) else {
+            return;
+        };
+        for (on, hook) in hooks {
+            if *on == event {
+                let hook = hook.clone();
+                commands.push(move |world, lua| hook.call(world, lua, entity));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::ScriptValue;
+
+    struct Collider;
+
+    #[derive(Default)]
+    struct Grid(Vec<Entity>);
+
+    #[test]
+    fn test_hooks_run_when_commands_apply() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.insert_resource(Grid::default());
+        world.on_add::<Collider>(|world, entity| {
+            world.resource_mut::<Grid>().unwrap().0.push(entity);
+            Ok(())
+        });
+        world.on_remove::<Collider>(|world, entity| {
+            world
+                .resource_mut::<Grid>()
+                .unwrap()
+                .0
+                .retain(|&e| e != entity);
+            Ok(())
+        });
+
+        let entity = world.spawn();
+        world.insert(entity, Collider)?;
+        world.insert(entity, Collider)?;
+        assert!(world.resource::<Grid>().unwrap().0.is_empty());
+
+        world.apply_commands(&lua)?;
+        assert_eq!(world.resource::<Grid>().unwrap().0, vec![entity]);
+
+        world.despawn(entity);
+        world.apply_commands(&lua)?;
+        assert!(world.resource::<Grid>().unwrap().0.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lua_hook_on_script_component() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let entity = world.spawn();
+
+        lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world, e = ...
+                world:on_add("Enemy", function(world, e) world:set(e, "Tracked", true) end)
+                world:set(e, "Enemy", { hp = 3 })
+            "#,
+            )
+            .call::<()>((handle, entity))
+        })?;
+        world.apply_commands(&lua)?;
+
+        assert_eq!(
+            world.script_component(entity, "Tracked"),
+            Some(&ScriptValue::Bool(true))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,715 @@
+use super::{
+    CloneFixup, ComponentKey, ComponentSchema, EcsError, Entity, Hook, HookEvent, NAME_COMPONENT,
+    QuerySpec, RefBroken, ScriptValue, World,
+};
+use crate::debugger::watch;
+use crate::sandbox::{Capability, require_capability};
+use crate::tilemap::{TileMap, TileMapHandle};
+use crate::time::Time;
+use mlua::{
+    AnyUserData, FromLua, Function, IntoLua, Lua, LuaSerdeExt, MetaMethod, Result, Table, UserData,
+    UserDataMethods, Value,
+};
+
+/// Most entity slots `world:spawn_batch` reserves before spawning.
+const MAX_BATCH_RESERVE: usize = 1 << 16;
+
+/// Lua's 1-based inventory slots as indices.
+fn slot_index(slot: usize) -> Result<usize> {
+    slot.checked_sub(1)
+        .ok_or_else(|| mlua::Error::RuntimeError("inventory slots start at 1".to_string()))
+}
+
+fn define_items(world: &mut World, defs: crate::gameplay::ItemDefs) {
+    match world.resource_mut::<crate::gameplay::ItemDefs>() {
+        Some(existing) => existing.extend(defs),
+        None => {
+            world.insert_resource(defs);
+        }
+    }
+}
+
+fn to_script_value(lua: &Lua, component: &str, value: Value) -> Result<ScriptValue> {
+    lua.from_value(value).map_err(|e| {
+        EcsError::InvalidComponent {
+            component: component.to_string(),
+            message: e.to_string(),
+        }
+        .into()
+    })
+}
+
+impl World {
+    /// Reads a registered Rust component or a script component by name.
+    pub fn get_by_name(&self, lua: &Lua, entity: Entity, name: &str) -> Result<Value> {
+        if name == NAME_COMPONENT {
+            return self.name(entity).into_lua(lua);
+        }
+        if let Some(info) = self.registry().get(name) {
+            return Ok((info.get)(self, entity, lua)?.unwrap_or(Value::Nil));
+        }
+        match self.script_component(entity, name) {
+            Some(value) => lua.to_value(value),
+            None => Ok(Value::Nil),
+        }
+    }
+
+    /// Writes a component by name; `nil` removes it.
+    pub fn set_by_name(
+        &mut self,
+        lua: &Lua,
+        entity: Entity,
+        name: &str,
+        value: Value,
+    ) -> Result<()> {
+        if value.is_nil() {
+            self.remove_by_name(entity, name);
+            return Ok(());
+        }
+        if !self.is_alive(entity) {
+            return Err(EcsError::NoSuchEntity(entity).into());
+        }
+        if name == NAME_COMPONENT {
+            self.set_name(entity, String::from_lua(value, lua)?)?;
+            return Ok(());
+        }
+
+        let value = match self.registry().schema(name) {
+            Some(schema) => {
+                let mut checked = to_script_value(lua, name, value)?;
+                schema.apply(self, name, &mut checked)?;
+                self.track_refs(entity, name, &checked);
+                lua.to_value(&checked)?
+            }
+            None => value,
+        };
+        if let Some(info) = self.registry().get(name) {
+            return (info.set)(self, entity, lua, value);
+        }
+
+        let value = to_script_value(lua, name, value)?;
+        self.set_script_component(entity, name, value)?;
+        Ok(())
+    }
+
+    pub fn remove_by_name(&mut self, entity: Entity, name: &str) -> bool {
+        if name == NAME_COMPONENT {
+            return self.remove_name(entity).is_some();
+        }
+        if let Some(info) = self.registry().get(name) {
+            return (info.remove)(self, entity);
+        }
+        self.remove_script_component(entity, name).is_some()
+    }
+
+    pub fn has_by_name(&self, entity: Entity, name: &str) -> bool {
+        if name == NAME_COMPONENT {
+            return self.name(entity).is_some();
+        }
+        match self.registry().get(name) {
+            Some(info) => (info.has)(self, entity),
+            None => self.script_component(entity, name).is_some(),
+        }
+    }
+}
+
+/// Lua sees a world only through a handle lent for the duration of a call
+/// (see `Engine::with_world`), so scripts can never outlive the world they
+/// were given.
+impl UserData for World {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        // Only reached for names that aren't methods.
+        methods.add_meta_function(
+            MetaMethod::Index,
+            |lua, (world, key): (AnyUserData, String)| crate::api::world_alias(lua, &world, &key),
+        );
+        methods.add_method_mut("spawn", |lua, this, components: Option<Table>| {
+            require_capability(lua, Capability::SpawnEntities, "world:spawn")?;
+            let entity = this.spawn();
+            let Some(components) = components else {
+                return Ok(entity);
+            };
+            let result = components.pairs::<String, Value>().try_for_each(|pair| {
+                let (name, value) = pair?;
+                this.set_by_name(lua, entity, &name, value)
+            });
+            // A component failing validation leaves no half-built entity.
+            if let Err(e) = result {
+                this.despawn(entity);
+                return Err(e);
+            }
+            Ok(entity)
+        });
+
+        methods.add_method_mut(
+            "define_component",
+            |_, this, (name, schema): (String, ComponentSchema)| {
+                this.set_schema(&name, schema);
+                Ok(())
+            },
+        );
+
+        // `world:spawn_batch(n, prefab, overrides)`: `overrides(i)` may
+        // return a table of components replacing or adding to the prefab's.
+        methods.add_method_mut(
+            "spawn_batch",
+            |lua, this, (count, prefab, overrides): (usize, Table, Option<Function>)| {
+                require_capability(lua, Capability::SpawnEntities, "world:spawn_batch")?;
+                let prefab = prefab
+                    .pairs::<String, Value>()
+                    .collect::<Result<Vec<_>>>()?;
+                // `count` comes from the script, so only reserve up to a bound.
+                let reserve = count.min(MAX_BATCH_RESERVE);
+                this.reserve_entities(reserve);
+
+                let mut spawned = Vec::with_capacity(reserve);
+                let result = (1..=count).try_for_each(|i| {
+                    let overrides = match &overrides {
+                        Some(f) => f.call::<Option<Table>>(i)?,
+                        None => None,
+                    };
+                    let entity = this.spawn();
+                    spawned.push(entity);
+                    for (name, value) in &prefab {
+                        let value = match &overrides {
+                            Some(overrides) => match overrides.get::<Value>(name.as_str())? {
+                                Value::Nil => value.clone(),
+                                replaced => replaced,
+                            },
+                            None => value.clone(),
+                        };
+                        this.set_by_name(lua, entity, name, value)?;
+                    }
+                    if let Some(overrides) = overrides {
+                        for pair in overrides.pairs::<String, Value>() {
+                            let (name, value) = pair?;
+                            if !prefab.iter().any(|(existing, _)| *existing == name) {
+                                this.set_by_name(lua, entity, &name, value)?;
+                            }
+                        }
+                    }
+                    Ok(())
+                });
+                // Like `spawn`, a failing batch leaves none of its entities.
+                if let Err(e) = result {
+                    for entity in spawned {
+                        this.despawn(entity);
+                    }
+                    return Err(e);
+                }
+                lua.create_sequence_from(spawned)
+            },
+        );
+
+        methods.add_method_mut(
+            "despawn",
+            |_, this, entity: Entity| Ok(this.despawn(entity)),
+        );
+
+        methods.add_method("is_alive", |_, this, entity: Entity| {
+            Ok(this.is_alive(entity))
+        });
+
+        methods.add_method("get", |lua, this, (entity, name): (Entity, String)| {
+            this.get_by_name(lua, entity, &name)
+        });
+
+        methods.add_method_mut(
+            "set",
+            |lua, this, (entity, name, value): (Entity, String, Value)| {
+                watch::before_write(this, lua, entity, &name)?;
+                this.set_by_name(lua, entity, &name, value)?;
+                watch::after_write(this, lua, entity, &name)
+            },
+        );
+
+        methods.add_method("has", |_, this, (entity, name): (Entity, String)| {
+            Ok(this.has_by_name(entity, &name))
+        });
+
+        methods.add_method_mut("remove", |lua, this, (entity, name): (Entity, String)| {
+            watch::before_write(this, lua, entity, &name)?;
+            let removed = this.remove_by_name(entity, &name);
+            watch::after_write(this, lua, entity, &name)?;
+            Ok(removed)
+        });
+
+        methods.add_method("find", |_, this, name: String| Ok(this.find(&name)));
+
+        methods.add_method("find_all", |lua, this, name: String| {
+            lua.create_sequence_from(this.find_all(&name).iter().copied())
+        });
+
+        // `for e in world:query{ "Position", without = { "Frozen" } } do`
+        methods.add_method_mut("query", |lua, this, spec: QuerySpec| {
+            let mut matches = this.query_by_spec(&spec)?.into_iter();
+            lua.create_function_mut(move |_, ()| Ok(matches.next()))
+        });
+
+        methods.add_method_mut("on_add", |_, this, (name, hook): (String, Function)| {
+            let key = ComponentKey::named(this.registry(), &name);
+            this.add_hook(key, HookEvent::Added, Hook::Lua(hook));
+            Ok(())
+        });
+
+        methods.add_method_mut("on_remove", |_, this, (name, hook): (String, Function)| {
+            let key = ComponentKey::named(this.registry(), &name);
+            this.add_hook(key, HookEvent::Removed, Hook::Lua(hook));
+            Ok(())
+        });
+
+        // Returns the copy of `entity` and a table mapping every original
+        // to its copy.
+        methods.add_method_mut(
+            "clone",
+            |lua, this, (entity, options): (Entity, Option<Table>)| {
+                require_capability(lua, Capability::SpawnEntities, "world:clone")?;
+                let children = match options {
+                    Some(options) => options.get::<Option<bool>>("children")?.unwrap_or(false),
+                    None => false,
+                };
+                let map = this.clone_entity(lua, entity, children)?;
+                let table = lua.create_table_from(map.iter().map(|(k, v)| (*k, *v)))?;
+                Ok((map[&entity], table))
+            },
+        );
+
+        methods.add_method_mut("on_clone", |_, this, fixup: Function| {
+            this.on_clone(CloneFixup::Lua(fixup));
+            Ok(())
+        });
+
+        methods.add_method_mut(
+            "set_parent",
+            |_, this, (child, parent): (Entity, Option<Entity>)| {
+                this.set_parent(child, parent)?;
+                Ok(())
+            },
+        );
+
+        methods.add_method("parent", |_, this, entity: Entity| Ok(this.parent(entity)));
+
+        methods.add_method("children", |lua, this, entity: Entity| {
+            lua.create_sequence_from(this.children(entity))
+        });
+
+        methods.add_function("tilemap", |_, (world, entity): (AnyUserData, Entity)| {
+            if world.borrow_scoped::<World, _>(|world| world.get::<TileMap>(entity).is_none())? {
+                return Err(mlua::Error::runtime(format!("{} has no tile map", entity)));
+            }
+            Ok(TileMapHandle { world, entity })
+        });
+
+        // Drains `RefBroken` events as `{ owner, component, field, target }`.
+        methods.add_method_mut("broken_refs", |lua, this, ()| {
+            let events = this.drain_events::<RefBroken>();
+            lua.create_sequence_from(
+                events
+                    .into_iter()
+                    .map(|event| {
+                        let table = lua.create_table()?;
+                        table.set("owner", event.owner)?;
+                        table.set("component", event.component)?;
+                        table.set("field", event.field)?;
+                        table.set("target", event.target)?;
+                        Ok::<_, mlua::Error>(table)
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        });
+
+        methods.add_method_mut(
+            "emit",
+            |_, this, (name, payload): (String, Option<ScriptValue>)| {
+                this.send_script_event(&name, payload.unwrap_or(ScriptValue::Bool(true)));
+                Ok(())
+            },
+        );
+
+        // Drains and returns every payload sent under `name` so far.
+        methods.add_method_mut("poll", |lua, this, name: String| {
+            lua.create_sequence_from(this.drain_script_events(&name))
+        });
+
+        // `{ real, unscaled, simulated }`, each `{ delta, elapsed }`; nil
+        // before the world's first `time::run_fixed`.
+        methods.add_method("time", |_, this, ()| Ok(this.resource::<Time>().cloned()));
+
+        // `{ day, hour, phase, weather, ambient }` from the world's
+        // `environment::Environment`; nil without one.
+        methods.add_method("environment", |lua, this, ()| {
+            this.resource::<crate::environment::Environment>()
+                .map(|environment| lua.to_value(&environment.snapshot()))
+                .transpose()
+        });
+        methods.add_method_mut("set_weather", |_, this, name: String| {
+            crate::environment::set_weather(this, &name)
+        });
+
+        // Step the world's `edit::History`; false with nothing to step.
+        methods.add_method_mut("undo", |lua, this, ()| crate::edit::undo(this, lua));
+        methods.add_method_mut("redo", |lua, this, ()| crate::edit::redo(this, lua));
+
+        // Whether this end may change `entity`; see `net::has_authority`.
+        methods.add_method("has_authority", |_, this, entity: Entity| {
+            Ok(crate::net::has_authority(this, entity))
+        });
+
+        // "server" or "client" and the client's id; nil when offline.
+        methods.add_method("net_role", |_, this, ()| {
+            Ok(match this.resource::<crate::net::NetRole>() {
+                Some(crate::net::NetRole::Server) => (Some("server"), None),
+                Some(crate::net::NetRole::Client(id)) => (Some("client"), Some(id.0)),
+                None => (None, None),
+            })
+        });
+
+        methods.add_method("is_headless", |_, this, ()| {
+            Ok(this.resource::<crate::engine::Headless>().is_some())
+        });
+
+        // A copy of a camera for coordinate conversion; the main one by default.
+        methods.add_method("camera", |_, this, entity: Option<Entity>| {
+            let entity = entity.or_else(|| crate::camera::main_camera(this));
+            Ok(entity.and_then(|entity| this.get::<crate::camera::Camera>(entity).map(|c| *c)))
+        });
+
+        methods.add_method(
+            "shake_camera",
+            |_, this, (amplitude, duration, entity): (f64, f64, Option<Entity>)| {
+                let entity = entity.or_else(|| crate::camera::main_camera(this));
+                if let Some(mut camera) =
+                    entity.and_then(|entity| this.get_mut::<crate::camera::Camera>(entity))
+                {
+                    camera.shake(amplitude, duration);
+                }
+                Ok(())
+            },
+        );
+
+        // Stats: `modifier` is `{ stat, kind = "add" | "multiply", value,
+        // source, duration? }`; `stat` is nil without a `Stats` component.
+        methods.add_method("stat", |_, this, (entity, name): (Entity, String)| {
+            Ok(crate::gameplay::stat(this, entity, &name))
+        });
+        methods.add_method_mut(
+            "set_base_stat",
+            |_, this, (entity, name, value): (Entity, String, f64)| {
+                Ok(crate::gameplay::set_base_stat(this, entity, &name, value)?)
+            },
+        );
+        methods.add_method_mut(
+            "add_modifier",
+            |lua, this, (entity, modifier): (Entity, Value)| {
+                let modifier = lua.from_value(modifier)?;
+                Ok(crate::gameplay::add_modifier(this, entity, modifier)?)
+            },
+        );
+        methods.add_method_mut(
+            "remove_modifiers",
+            |_, this, (entity, source): (Entity, String)| {
+                Ok(crate::gameplay::remove_modifiers(this, entity, &source)?)
+            },
+        );
+
+        // Drains `StatChanged` events as `{ entity, stat, old, new }`.
+        methods.add_method_mut("stat_changes", |lua, this, ()| {
+            let events = this.drain_events::<crate::gameplay::StatChanged>();
+            lua.create_sequence_from(
+                events
+                    .into_iter()
+                    .map(|event| {
+                        let table = lua.create_table()?;
+                        table.set("entity", event.entity)?;
+                        table.set("stat", event.stat)?;
+                        table.set("old", event.old)?;
+                        table.set("new", event.new)?;
+                        Ok::<_, mlua::Error>(table)
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        });
+
+        // Inventories, with 1-based slots. Item definitions come from
+        // `world:load_items(path)` or `world:define_items({ potion = { max_stack = 5 } })`.
+        methods.add_method_mut("load_items", |lua, this, path: String| {
+            let source = crate::assets::read_lua_asset(lua, &path)?;
+            let loaded = crate::gameplay::ItemDefs::from_ron(&source)?;
+            define_items(this, loaded);
+            Ok(())
+        });
+        methods.add_method_mut("define_items", |lua, this, defs: Value| {
+            define_items(this, lua.from_value(defs)?);
+            Ok(())
+        });
+        methods.add_method_mut(
+            "give",
+            |_, this, (entity, item, count): (Entity, String, Option<u32>)| {
+                Ok(crate::gameplay::give(
+                    this,
+                    entity,
+                    &item,
+                    count.unwrap_or(1),
+                )?)
+            },
+        );
+        methods.add_method_mut(
+            "take",
+            |_, this, (entity, item, count): (Entity, String, Option<u32>)| {
+                Ok(crate::gameplay::take(
+                    this,
+                    entity,
+                    &item,
+                    count.unwrap_or(1),
+                )?)
+            },
+        );
+        methods.add_method("item_count", |_, this, (entity, item): (Entity, String)| {
+            Ok(this
+                .get::<crate::gameplay::Inventory>(entity)
+                .map_or(0, |inventory| inventory.count(&item)))
+        });
+        methods.add_method_mut(
+            "move_item",
+            |_,
+             this,
+             (from, from_slot, to, to_slot, count): (Entity, usize, Entity, usize, Option<u32>)| {
+                Ok(crate::gameplay::move_item(
+                    this,
+                    (from, slot_index(from_slot)?),
+                    (to, slot_index(to_slot)?),
+                    count,
+                )?)
+            },
+        );
+        methods.add_method_mut(
+            "split_stack",
+            |_, this, (entity, slot, count): (Entity, usize, u32)| {
+                Ok(crate::gameplay::split_stack(this, entity, slot_index(slot)?, count)? + 1)
+            },
+        );
+
+        // Drains `InventoryChanged` events as `{ entity, slot, old, new }`,
+        // stacks being `{ item, count }` or nil.
+        methods.add_method_mut("inventory_changes", |lua, this, ()| {
+            let events = this.drain_events::<crate::gameplay::InventoryChanged>();
+            lua.create_sequence_from(
+                events
+                    .into_iter()
+                    .map(|event| {
+                        let table = lua.create_table()?;
+                        table.set("entity", event.entity)?;
+                        table.set("slot", event.slot + 1)?;
+                        table.set("old", event.old.map(|s| lua.to_value(&s)).transpose()?)?;
+                        table.set("new", event.new.map(|s| lua.to_value(&s)).transpose()?)?;
+                        Ok::<_, mlua::Error>(table)
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        });
+
+        // Queues damage for the `DamagePipeline`; `options` is `{ source, kind }`.
+        methods.add_method_mut(
+            "damage",
+            |_, this, (target, amount, options): (Entity, f64, Option<Table>)| {
+                let mut damage = crate::gameplay::Damage::new(target, amount);
+                if let Some(options) = options {
+                    damage.source = options.get("source")?;
+                    damage.kind = options.get::<Option<String>>("kind")?.unwrap_or_default();
+                }
+                this.send_event(damage);
+                Ok(())
+            },
+        );
+
+        // Drain `DamageApplied` as `{ target, source, amount, kind, health }`
+        // and `Died` as `{ entity, source }`.
+        methods.add_method_mut("damage_applied", |lua, this, ()| {
+            let events = this.drain_events::<crate::gameplay::DamageApplied>();
+            lua.create_sequence_from(
+                events
+                    .into_iter()
+                    .map(|event| {
+                        let table = lua.create_table()?;
+                        table.set("target", event.target)?;
+                        table.set("source", event.source)?;
+                        table.set("amount", event.amount)?;
+                        table.set("kind", event.kind)?;
+                        table.set("health", event.health)?;
+                        Ok::<_, mlua::Error>(table)
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        });
+        methods.add_method_mut("deaths", |lua, this, ()| {
+            let events = this.drain_events::<crate::gameplay::Died>();
+            lua.create_sequence_from(
+                events
+                    .into_iter()
+                    .map(|event| {
+                        let table = lua.create_table()?;
+                        table.set("entity", event.entity)?;
+                        table.set("source", event.source)?;
+                        Ok::<_, mlua::Error>(table)
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        });
+
+        // Drains `AbilityEvent`s as `{ entity, ability, kind, reason }`, `kind`
+        // one of "started", "cast", "channel_tick", "channel_ended",
+        // "interrupted" or "failed" with the reason.
+        methods.add_method_mut("ability_events", |lua, this, ()| {
+            use crate::gameplay::AbilityEventKind;
+            let events = this.drain_events::<crate::gameplay::AbilityEvent>();
+            lua.create_sequence_from(
+                events
+                    .into_iter()
+                    .map(|event| {
+                        let table = lua.create_table()?;
+                        table.set("entity", event.entity)?;
+                        table.set("ability", event.ability)?;
+                        let kind = match &event.kind {
+                            AbilityEventKind::Started => "started",
+                            AbilityEventKind::Cast => "cast",
+                            AbilityEventKind::ChannelTick => "channel_tick",
+                            AbilityEventKind::ChannelEnded => "channel_ended",
+                            AbilityEventKind::Interrupted => "interrupted",
+                            AbilityEventKind::Failed(reason) => {
+                                table.set("reason", reason.to_string())?;
+                                "failed"
+                            }
+                        };
+                        table.set("kind", kind)?;
+                        Ok::<_, mlua::Error>(table)
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        });
+
+        // The world's `ContentManifest` as `{ [name] = hash }` plus the combined
+        // hash, hashes as hex strings since Lua numbers can't hold 64 bits.
+        methods.add_method("content_manifest", |lua, this, ()| {
+            let Some(manifest) = this.resource::<crate::content::ContentManifest>() else {
+                return Ok((None, None));
+            };
+            let table = lua.create_table()?;
+            for (name, hash) in manifest.iter() {
+                table.set(name, format!("{:016x}", hash))?;
+            }
+            Ok((Some(table), Some(format!("{:016x}", manifest.combined()))))
+        });
+
+        // The topmost collider at a world point; `mask` defaults to every layer.
+        methods.add_method("pick", |_, this, (x, y, mask): (f64, f64, Option<u32>)| {
+            let mask = mask.unwrap_or(crate::picking::ALL_LAYERS);
+            Ok(crate::picking::pick(
+                this,
+                crate::math::Vec2::new(x, y),
+                mask,
+            ))
+        });
+
+        // `{ entity, x, y, nx, ny, distance }` for the nearest hit, or nil.
+        // `filter.mask` is a number or layer names such as "enemies|walls";
+        // `filter.exclude` an entity or list of entities.
+        methods.add_method(
+            "raycast",
+            |lua, this, (ox, oy, dx, dy, max, filter): (f64, f64, f64, f64, f64, Option<Table>)| {
+                use crate::physics::{CollisionLayers, QueryFilter};
+                let mut query = QueryFilter::default();
+                if let Some(filter) = filter {
+                    query.mask = match filter.get::<Value>("mask")? {
+                        Value::Nil => query.mask,
+                        Value::String(spec) => this
+                            .resource::<CollisionLayers>()
+                            .cloned()
+                            .unwrap_or_default()
+                            .mask(&spec.to_str()?)?,
+                        other => u32::from_lua(other, lua)?,
+                    };
+                    query.exclude = match filter.get::<Value>("exclude")? {
+                        Value::Nil => Vec::new(),
+                        Value::Table(list) => list.sequence_values().collect::<Result<_>>()?,
+                        other => vec![Entity::from_lua(other, lua)?],
+                    };
+                }
+                let origin = crate::math::Vec2::new(ox, oy);
+                let direction = crate::math::Vec2::new(dx, dy);
+                let Some(hit) = crate::physics::raycast(this, origin, direction, max, &query)
+                else {
+                    return Ok(None);
+                };
+                let result = lua.create_table()?;
+                result.set("entity", hit.entity)?;
+                result.set("x", hit.point.x)?;
+                result.set("y", hit.point.y)?;
+                result.set("nx", hit.normal.x)?;
+                result.set("ny", hit.normal.y)?;
+                result.set("distance", hit.distance)?;
+                Ok(Some(result))
+            },
+        );
+
+        methods.add_method("len", |_, this, ()| Ok(this.len()));
+
+        methods.add_method("entities", |lua, this, ()| {
+            lua.create_sequence_from(this.entities())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Position {
+        x: f64,
+        y: f64,
+    }
+
+    #[test]
+    fn test_lua_reads_and_writes_components() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.register_component::<Position>("Position");
+
+        let entity: Entity = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world = ...
+                local e = world:spawn({ Position = { x = 1, y = 2 }, Health = { hp = 3 } })
+                local pos = world:get(e, "Position")
+                world:set(e, "Position", { x = pos.x + 10, y = pos.y })
+                assert(world:get(e, "Health").hp == 3)
+                assert(world:has(e, "Health") and not world:has(e, "Missing"))
+                local player = world:spawn({ Name = "Player" })
+                assert(world:find("Player") == player and world:get(player, "Name") == "Player")
+                assert(world:find("Nobody") == nil and #world:find_all("Player") == 1)
+                for found in world:query({ "Position", without = { "Name" } }) do
+                    assert(found == e)
+                end
+                return e
+            "#,
+            )
+            .call(handle)
+        })?;
+
+        assert_eq!(
+            *world.get::<Position>(entity).unwrap(),
+            Position { x: 11.0, y: 2.0 }
+        );
+
+        let bad = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load("local world, e = ... world:set(e, 'Position', { x = 'nope' })")
+                .call::<()>((handle, entity))
+        });
+        assert!(bad.unwrap_err().to_string().contains("Position"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+mod bundle;
+mod clone;
+mod commands;
+mod dynamic;
+mod entity;
+mod events;
+mod hierarchy;
+mod hooks;
+mod lua;
+mod name;
+pub(crate) mod panic;
+pub mod query;
+mod refs;
+mod registry;
+mod schedule;
+mod schema;
+mod split;
+pub mod storage;
+mod value;
+mod watchdog;
+mod world;
+
+pub use bundle::Bundle;
+pub use clone::{CloneFixup, EntityMap, RustFixup};
+pub use commands::{Command, CommandBuffer};
+pub use dynamic::{DynamicQuery, QuerySpec};
+pub use entity::{Entities, Entity};
+pub use events::{Events, ScriptEvents};
+pub use hierarchy::{Children, Parent};
+pub use hooks::{ComponentKey, Hook, HookEvent, RustHook};
+pub use name::{NAME_COMPONENT, NameIndex, NamePolicy};
+pub use panic::{PanicPolicy, SystemFailed};
+pub use query::{Query, QueryParam, With, Without};
+pub use refs::{BrokenRef, RefBroken};
+pub use registry::{ComponentInfo, ComponentRegistry};
+pub use schedule::{
+    DEFAULT_STAGE, ExclusiveSystem, RustSystem, Schedule, ScheduleHandle, System, SystemFn,
+    SystemOrder,
+};
+pub use schema::{ComponentSchema, FieldSchema};
+pub use split::{ColumnMut, SplitColumns};
+pub use value::ScriptValue;
+pub use watchdog::{FrameOverrun, FrameWatchdog, SystemTiming};
+pub use world::{Component, EcsError, World};
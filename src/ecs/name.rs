@@ -0,0 +1,127 @@
+use super::{EcsError, Entity};
+use std::collections::HashMap;
+
+/// Component name under which scripts and scene files address entity names.
+pub const NAME_COMPONENT: &str = "Name";
+
+/// What happens when an entity is given a name another entity already has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NamePolicy {
+    #[default]
+    AllowDuplicates,
+    /// Reject the new name with `EcsError::DuplicateName`.
+    Unique,
+    /// Append ` (n)` with the first free `n`.
+    MakeUnique,
+}
+
+/// Entity names kept in both directions so lookups by name don't scan the
+/// world.
+#[derive(Debug, Clone, Default)]
+pub struct NameIndex {
+    policy: NamePolicy,
+    by_entity: HashMap<Entity, String>,
+    by_name: HashMap<String, Vec<Entity>>,
+}
+
+impl NameIndex {
+    pub fn policy(&self) -> NamePolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: NamePolicy) {
+        self.policy = policy;
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&str> {
+        self.by_entity.get(&entity).map(String::as_str)
+    }
+
+    /// The first entity given `name` that still holds it.
+    pub fn find(&self, name: &str) -> Option<Entity> {
+        self.find_all(name).first().copied()
+    }
+
+    pub fn find_all(&self, name: &str) -> &[Entity] {
+        self.by_name.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_entity.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_entity.is_empty()
+    }
+
+    fn is_taken(&self, name: &str, by: Entity) -> bool {
+        self.find_all(name).iter().any(|&other| other != by)
+    }
+
+    /// Names `entity`, returning the name actually assigned.
+    pub(crate) fn set(&mut self, entity: Entity, name: String) -> Result<String, EcsError> {
+        if self.get(entity) == Some(name.as_str()) {
+            return Ok(name);
+        }
+        let name = match self.policy {
+            NamePolicy::AllowDuplicates => name,
+            NamePolicy::Unique if self.is_taken(&name, entity) => {
+                return Err(EcsError::DuplicateName(name));
+            }
+            NamePolicy::Unique => name,
+            NamePolicy::MakeUnique if self.is_taken(&name, entity) => (1..)
+                .map(|n| format!("{} ({})", name, n))
+                .find(|candidate| !self.is_taken(candidate, entity))
+                .expect("ran out of name suffixes"),
+            NamePolicy::MakeUnique => name,
+        };
+
+        self.remove(entity);
+        self.by_name.entry(name.clone()).or_default().push(entity);
+        self.by_entity.insert(entity, name.clone());
+        Ok(name)
+    }
+
+    pub(crate) fn remove(&mut self, entity: Entity) -> Option<String> {
+        let name = self.by_entity.remove(&entity)?;
+        if let Some(entities) = self.by_name.get_mut(&name) {
+            entities.retain(|&other| other != entity);
+            if entities.is_empty() {
+                self.by_name.remove(&name);
+            }
+        }
+        Some(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::Entities;
+
+    #[test]
+    fn test_name_policies() {
+        let mut entities = Entities::default();
+        let (a, b, c) = (entities.alloc(), entities.alloc(), entities.alloc());
+
+        let mut names = NameIndex::default();
+        names.set(a, "Orc".to_string()).unwrap();
+        names.set(b, "Orc".to_string()).unwrap();
+        assert_eq!(names.find_all("Orc"), &[a, b]);
+
+        names.set_policy(NamePolicy::Unique);
+        assert_eq!(
+            names.set(c, "Orc".to_string()),
+            Err(EcsError::DuplicateName("Orc".to_string()))
+        );
+        assert_eq!(names.set(a, "Orc".to_string()).unwrap(), "Orc");
+
+        names.set_policy(NamePolicy::MakeUnique);
+        assert_eq!(names.set(c, "Orc".to_string()).unwrap(), "Orc (1)");
+
+        assert_eq!(names.remove(a), Some("Orc".to_string()));
+        assert_eq!(names.find("Orc"), Some(b));
+        assert_eq!(names.find("Orc (1)"), Some(c));
+        assert_eq!(names.len(), 2);
+    }
+}
@@ -0,0 +1,124 @@
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::sync::Once;
+
+/// Sent when a system panicked and the schedule carried on without it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemFailed {
+    pub system: String,
+    pub message: String,
+    pub backtrace: String,
+    /// Whether the system was disabled as a result.
+    pub disabled: bool,
+}
+
+/// What a schedule does when one of its Rust systems panics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PanicPolicy {
+    /// Let the panic unwind out of `Schedule::run`.
+    Propagate,
+    /// Print and log the panic, send `SystemFailed` and keep running the
+    /// system on later frames.
+    #[default]
+    Report,
+    /// As `Report`, but skip the system until it is re-enabled.
+    Disable,
+}
+
+thread_local! {
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    static CAUGHT: RefCell<Option<(String, String)>> = const { RefCell::new(None) };
+}
+
+static INSTALL_HOOK: Once = Once::new();
+
+/// The panic's message and, when known, where it happened.
+pub(crate) fn describe(info: &PanicHookInfo<'_>) -> String {
+    let message = match info.payload().downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match info.payload().downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown panic".to_string(),
+        },
+    };
+    match info.location() {
+        Some(location) => format!("{} at {}", message, location),
+        None => message,
+    }
+}
+
+/// Whether a panic on this thread will be caught and reported by a
+/// schedule rather than take the program down.
+pub(crate) fn catching() -> bool {
+    CATCHING.get()
+}
+
+/// Wraps the current panic hook so panics inside `catch` also record
+/// their message and backtrace. They still print as usual, so a caught
+/// panic is as hard to miss as one that ends the program.
+fn install_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.get() {
+                let message = describe(info);
+                let backtrace = Backtrace::force_capture().to_string();
+                CAUGHT.set(Some((message, backtrace)));
+            }
+            previous(info);
+        }));
+    });
+}
+
+/// Runs `f`, turning a panic into its message and backtrace.
+pub(crate) fn catch<R>(f: impl FnOnce() -> R) -> Result<R, (String, String)> {
+    install_hook();
+    let was_catching = CATCHING.replace(true);
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.set(was_catching);
+    result.map_err(|_| {
+        CAUGHT
+            .take()
+            .unwrap_or_else(|| ("unknown panic".to_string(), String::new()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::{Schedule, World};
+    use mlua::{Lua, Result};
+
+    #[test]
+    fn test_panicking_system_is_reported_and_disabled() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let mut schedule = Schedule::new();
+        schedule.add_system("plugin", |_| panic!("plugin exploded"));
+        schedule.add_system("after", |world| {
+            world.spawn();
+            Ok(())
+        });
+
+        schedule.run(&mut world, &lua)?;
+        let failed = world.drain_events::<SystemFailed>();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].system, "plugin");
+        assert!(
+            failed[0]
+                .message
+                .starts_with("plugin exploded at src/ecs/panic.rs")
+        );
+        assert!(!failed[0].backtrace.is_empty() && !failed[0].disabled);
+        assert_eq!(world.len(), 1);
+
+        schedule.set_panic_policy(PanicPolicy::Disable);
+        schedule.run(&mut world, &lua)?;
+        schedule.run(&mut world, &lua)?;
+        assert_eq!(world.drain_events::<SystemFailed>().len(), 1);
+        assert!(schedule.is_disabled("plugin"));
+        assert_eq!(world.len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+use super::storage::{AnyStorage, SparseSet};
+use super::{Component, Entity, World};
+use std::cell::{Ref, RefMut};
+use std::marker::PhantomData;
+
+/// One element of a query tuple: a component borrow or a filter.
+pub trait QueryParam {
+    type Fetch<'w>;
+    type Item<'f>;
+
+    /// Borrows the columns this parameter reads. `None` means the query
+    /// cannot match anything (a required component has no storage yet).
+    fn fetch(world: &World) -> Option<Self::Fetch<'_>>;
+
+    /// Entities this parameter requires, used to pick the smallest column
+    /// to drive iteration. `None` for optional parameters and exclusions.
+    fn candidates<'a>(fetch: &'a Self::Fetch<'_>) -> Option<&'a [Entity]>;
+
+    fn get<'f>(fetch: &'f mut Self::Fetch<'_>, entity: Entity, tick: u32)
+    -> Option<Self::Item<'f>>;
+}
+
+fn column<T: Component>(world: &World) -> Option<Ref<'_, SparseSet<T>>> {
+    let cell = world.storage_cell::<T>()?;
+    Ref::filter_map(cell.borrow(), |storage| {
+        storage.as_any().downcast_ref::<SparseSet<T>>()
+    })
+    .ok()
+}
+
+fn column_mut<T: Component>(world: &World) -> Option<RefMut<'_, SparseSet<T>>> {
+    let cell = world.storage_cell::<T>()?;
+    RefMut::filter_map(cell.borrow_mut(), |storage| {
+        storage.as_any_mut().downcast_mut::<SparseSet<T>>()
+    })
+    .ok()
+}
+
+impl<T: Component> QueryParam for &T {
+    type Fetch<'w> = Ref<'w, SparseSet<T>>;
+    type Item<'f> = &'f T;
+
+    fn fetch(world: &World) -> Option<Self::Fetch<'_>> {
+        column::<T>(world)
+    }
+
+    fn candidates<'a>(fetch: &'a Self::Fetch<'_>) -> Option<&'a [Entity]> {
+        Some(fetch.entities())
+    }
+
+    fn get<'f>(fetch: &'f mut Self::Fetch<'_>, entity: Entity, _tick: u32) -> Option<&'f T> {
+        fetch.get(entity)
+    }
+}
+
+impl<T: Component> QueryParam for &mut T {
+    type Fetch<'w> = RefMut<'w, SparseSet<T>>;
+    type Item<'f> = &'f mut T;
+
+    fn fetch(world: &World) -> Option<Self::Fetch<'_>> {
+        column_mut::<T>(world)
+    }
+
+    fn candidates<'a>(fetch: &'a Self::Fetch<'_>) -> Option<&'a [Entity]> {
+        Some(fetch.entities())
+    }
+
+    fn get<'f>(fetch: &'f mut Self::Fetch<'_>, entity: Entity, tick: u32) -> Option<&'f mut T> {
+        fetch.get_mut(entity, tick)
+    }
+}
+
+impl<T: Component> QueryParam for Option<&T> {
+    type Fetch<'w> = Option<Ref<'w, SparseSet<T>>>;
+    type Item<'f> = Option<&'f T>;
+
+    fn fetch(world: &World) -> Option<Self::Fetch<'_>> {
+        Some(column::<T>(world))
+    }
+
+    fn candidates<'a>(_fetch: &'a Self::Fetch<'_>) -> Option<&'a [Entity]> {
+        None
+    }
+
+    fn get<'f>(
+        fetch: &'f mut Self::Fetch<'_>,
+        entity: Entity,
+        _tick: u32,
+    ) -> Option<Option<&'f T>> {
+        Some(fetch.as_ref().and_then(|column| column.get(entity)))
+    }
+}
+
+/// Filter: entity must have `T`, without borrowing its value.
+pub struct With<T>(PhantomData<T>);
+
+/// Filter: entity must not have `T`.
+pub struct Without<T>(PhantomData<T>);
+
+impl<T: Component> QueryParam for With<T> {
+    type Fetch<'w> = Ref<'w, Box<dyn AnyStorage>>;
+    type Item<'f> = ();
+
+    fn fetch(world: &World) -> Option<Self::Fetch<'_>> {
+        Some(world.storage_cell::<T>()?.borrow())
+    }
+
+    fn candidates<'a>(fetch: &'a Self::Fetch<'_>) -> Option<&'a [Entity]> {
+        Some(fetch.entities())
+    }
+
+    fn get(fetch: &mut Self::Fetch<'_>, entity: Entity, _tick: u32) -> Option<()> {
+        fetch.contains(entity).then_some(())
+    }
+}
+
+impl<T: Component> QueryParam for Without<T> {
+    type Fetch<'w> = Option<Ref<'w, Box<dyn AnyStorage>>>;
+    type Item<'f> = ();
+
+    fn fetch(world: &World) -> Option<Self::Fetch<'_>> {
+        Some(world.storage_cell::<T>().map(|cell| cell.borrow()))
+    }
+
+    fn candidates<'a>(_fetch: &'a Self::Fetch<'_>) -> Option<&'a [Entity]> {
+        None
+    }
+
+    fn get(fetch: &mut Self::Fetch<'_>, entity: Entity, _tick: u32) -> Option<()> {
+        match fetch {
+            Some(storage) if storage.contains(entity) => None,
+            _ => Some(()),
+        }
+    }
+}
+
+macro_rules! impl_query_tuple {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<$($name: QueryParam),+> QueryParam for ($($name,)+) {
+            type Fetch<'w> = ($($name::Fetch<'w>,)+);
+            type Item<'f> = ($($name::Item<'f>,)+);
+
+            fn fetch(world: &World) -> Option<Self::Fetch<'_>> {
+                Some(($($name::fetch(world)?,)+))
+            }
+
+            fn candidates<'a>(fetch: &'a Self::Fetch<'_>) -> Option<&'a [Entity]> {
+                let ($($name,)+) = fetch;
+                [$($name::candidates($name)),+]
+                    .into_iter()
+                    .flatten()
+                    .min_by_key(|entities| entities.len())
+            }
+
+            fn get<'f>(
+                fetch: &'f mut Self::Fetch<'_>,
+                entity: Entity,
+                tick: u32,
+            ) -> Option<Self::Item<'f>> {
+                let ($($name,)+) = fetch;
+                Some(($($name::get($name, entity, tick)?,)+))
+            }
+        }
+    };
+}
+
+impl_query_tuple!(A);
+impl_query_tuple!(A, B);
+impl_query_tuple!(A, B, C);
+impl_query_tuple!(A, B, C, D);
+impl_query_tuple!(A, B, C, D, E);
+impl_query_tuple!(A, B, C, D, E, F);
+impl_query_tuple!(A, B, C, D, E, F, G);
+impl_query_tuple!(A, B, C, D, E, F, G, H);
+
+/// Borrowed columns for one query; created by `World::query`.
+pub struct Query<'w, Q: QueryParam> {
+    world: &'w World,
+    fetch: Option<Q::Fetch<'w>>,
+}
+
+impl<'w, Q: QueryParam> Query<'w, Q> {
+    pub(crate) fn new(world: &'w World) -> Self {
+        Query {
+            world,
+            fetch: Q::fetch(world),
+        }
+    }
+
+    /// Matching entities, in the order `for_each` visits them.
+    pub fn entities(&mut self) -> Vec<Entity> {
+        let tick = self.world.tick();
+        let Some(fetch) = self.fetch.as_mut() else {
+            return Vec::new();
+        };
+
+        let candidates = match Q::candidates(fetch) {
+            Some(entities) => entities.to_vec(),
+            None => self.world.entities().collect(),
+        };
+        candidates
+            .into_iter()
+            .filter(|&entity| Q::get(fetch, entity, tick).is_some())
+            .collect()
+    }
+
+    pub fn for_each(&mut self, mut f: impl FnMut(Entity, Q::Item<'_>)) {
+        let tick = self.world.tick();
+        let Some(fetch) = self.fetch.as_mut() else {
+            return;
+        };
+
+        let candidates = match Q::candidates(fetch) {
+            Some(entities) => entities.to_vec(),
+            None => self.world.entities().collect(),
+        };
+        for entity in candidates {
+            if let Some(item) = Q::get(fetch, entity, tick) {
+                f(entity, item);
+            }
+        }
+    }
+
+    pub fn get(&mut self, entity: Entity) -> Option<Q::Item<'_>> {
+        let tick = self.world.tick();
+        Q::get(self.fetch.as_mut()?, entity, tick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(f64);
+    #[derive(Debug, PartialEq)]
+    struct Velocity(f64);
+    struct Frozen;
+
+    #[test]
+    fn test_query_filters_and_mutation() {
+        let mut world = World::new();
+        let moving = world.spawn();
+        world.insert(moving, Position(0.0)).unwrap();
+        world.insert(moving, Velocity(2.0)).unwrap();
+
+        let frozen = world.spawn();
+        world.insert(frozen, Position(5.0)).unwrap();
+        world.insert(frozen, Velocity(1.0)).unwrap();
+        world.insert(frozen, Frozen).unwrap();
+
+        let still = world.spawn();
+        world.insert(still, Position(9.0)).unwrap();
+
+        world
+            .query::<(&mut Position, &Velocity, Without<Frozen>)>()
+            .for_each(|_, (position, velocity, ())| position.0 += velocity.0);
+
+        assert_eq!(*world.get::<Position>(moving).unwrap(), Position(2.0));
+        assert_eq!(*world.get::<Position>(frozen).unwrap(), Position(5.0));
+
+        let mut with_velocity = world.query::<(&Position, Option<&Velocity>)>();
+        assert_eq!(with_velocity.entities().len(), 3);
+        assert_eq!(with_velocity.get(still), Some((&Position(9.0), None)));
+
+        assert!(world.query::<&String>().entities().is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+use super::{Entity, ScriptValue, World};
+use mlua::{FromLua, Lua, Result, Value};
+use std::collections::HashMap;
+
+/// What happens to a tracked entity-reference field when its target
+/// despawns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrokenRef {
+    /// Remove the field from the component.
+    Clear,
+    /// Leave the field alone and send a `RefBroken` event.
+    Notify,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefBroken {
+    pub owner: Entity,
+    pub component: String,
+    pub field: String,
+    pub target: Entity,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RefSlot {
+    owner: Entity,
+    component: String,
+    field: String,
+    policy: BrokenRef,
+}
+
+/// Reverse index from an entity to the component fields pointing at it.
+/// Entries are checked when used rather than removed when fields change.
+#[derive(Debug, Default)]
+pub(crate) struct RefIndex {
+    by_target: HashMap<Entity, Vec<RefSlot>>,
+}
+
+impl RefIndex {
+    fn track(&mut self, target: Entity, slot: RefSlot) {
+        let slots = self.by_target.entry(target).or_default();
+        if !slots.contains(&slot) {
+            slots.push(slot);
+        }
+    }
+
+    fn take(&mut self, target: Entity) -> Vec<RefSlot> {
+        self.by_target.remove(&target).unwrap_or_default()
+    }
+}
+
+impl World {
+    /// Records the `ref` fields of a just-written component.
+    pub(crate) fn track_refs(&mut self, owner: Entity, component: &str, value: &ScriptValue) {
+        let (Some(schema), ScriptValue::Map(fields)) = (self.registry().schema(component), value)
+        else {
+            return;
+        };
+
+        let mut tracked = Vec::new();
+        for (field, field_schema) in &schema.fields {
+            let (Some(policy), Some(ScriptValue::Number(bits))) =
+                (field_schema.entity_ref, fields.get(field))
+            else {
+                continue;
+            };
+            if let Some(target) = Entity::from_bits(*bits as u64) {
+                let slot = RefSlot {
+                    owner,
+                    component: component.to_string(),
+                    field: field.clone(),
+                    policy,
+                };
+                tracked.push((target, slot));
+            }
+        }
+        for (target, slot) in tracked {
+            self.refs_mut().track(target, slot);
+        }
+    }
+
+    /// Queues cleanup for every field that pointed at `target`.
+    pub(crate) fn queue_broken_refs(&mut self, target: Entity) {
+        for slot in self.refs_mut().take(target) {
+            self.commands()
+                .push(move |world, lua| world.break_ref(lua, slot, target));
+        }
+    }
+
+    fn break_ref(&mut self, lua: &Lua, slot: RefSlot, target: Entity) -> Result<()> {
+        if !self.is_alive(slot.owner) {
+            return Ok(());
+        }
+        let Value::Table(component) = self.get_by_name(lua, slot.owner, &slot.component)? else {
+            return Ok(());
+        };
+        let current = component.get::<Value>(slot.field.as_str())?;
+        if Entity::from_lua(current, lua).ok() != Some(target) {
+            return Ok(());
+        }
+
+        match slot.policy {
+            BrokenRef::Clear => {
+                component.set(slot.field.as_str(), Value::Nil)?;
+                self.set_by_name(lua, slot.owner, &slot.component, Value::Table(component))
+            }
+            BrokenRef::Notify => {
+                self.send_event(RefBroken {
+                    owner: slot.owner,
+                    component: slot.component,
+                    field: slot.field,
+                    target,
+                });
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::Events;
+
+    #[test]
+    fn test_despawned_targets_clear_or_notify() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+
+        let (follower, target) = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world = ...
+                world:define_component("Follow", { target = { ref = "clear" }, speed = { default = 1 } })
+                world:define_component("Attack", { target = { ref = "notify" } })
+
+                local target = world:spawn()
+                local follower = world:spawn({ Follow = { target = target }, Attack = { target = target } })
+                return follower, target
+            "#,
+            )
+            .call::<(Entity, Entity)>(handle)
+        })?;
+
+        // A copy points at the same target and is cleaned up with it.
+        let copy = world.clone_entity(&lua, follower, false)?[&follower];
+        world.despawn(target);
+        world.apply_commands(&lua)?;
+
+        for owner in [follower, copy] {
+            let ScriptValue::Map(follow) = world.script_component(owner, "Follow").unwrap() else {
+                panic!("Follow should still be a table");
+            };
+            assert!(!follow.contains_key("target"));
+            assert_eq!(follow["speed"], ScriptValue::Number(1.0));
+        }
+
+        let mut broken = world.drain_events::<RefBroken>();
+        broken.sort_by_key(|event| event.owner);
+        assert_eq!(broken.len(), 2);
+        assert_eq!((broken[0].owner, broken[0].target), (follower, target));
+        assert_eq!(broken[1].owner, copy);
+        assert_eq!(broken[0].component, "Attack");
+        assert!(world.events::<RefBroken>().is_some_and(Events::is_empty));
+
+        Ok(())
+    }
+}
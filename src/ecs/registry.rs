@@ -0,0 +1,158 @@
+use super::{Component, ComponentSchema, EcsError, Entity, World};
+use mlua::{Lua, LuaSerdeExt, Result, Value};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap};
+
+type GetFn = fn(&World, Entity, &Lua) -> Result<Option<Value>>;
+type SetFn = fn(&mut World, Entity, &Lua, Value) -> Result<()>;
+type RemoveFn = fn(&mut World, Entity) -> bool;
+type HasFn = fn(&World, Entity) -> bool;
+
+/// Type-erased accessors that let scripts read and write a Rust component
+/// by its registered name.
+#[derive(Clone)]
+pub struct ComponentInfo {
+    pub name: String,
+    pub type_id: TypeId,
+    pub get: GetFn,
+    pub set: SetFn,
+    pub remove: RemoveFn,
+    pub has: HasFn,
+}
+
+fn get_component<T: Component + Serialize>(
+    world: &World,
+    entity: Entity,
+    lua: &Lua,
+) -> Result<Option<Value>> {
+    match world.get::<T>(entity) {
+        Some(component) => Ok(Some(lua.to_value(&*component)?)),
+        None => Ok(None),
+    }
+}
+
+fn set_component<T: Component + DeserializeOwned>(
+    world: &mut World,
+    entity: Entity,
+    lua: &Lua,
+    value: Value,
+) -> Result<()> {
+    let component: T = lua.from_value(value).map_err(|e| {
+        let name = world
+            .registry()
+            .name_of::<T>()
+            .unwrap_or(std::any::type_name::<T>());
+        EcsError::InvalidComponent {
+            component: name.to_string(),
+            message: e.to_string(),
+        }
+    })?;
+    world.insert(entity, component)?;
+    Ok(())
+}
+
+fn remove_component<T: Component>(world: &mut World, entity: Entity) -> bool {
+    world.remove::<T>(entity).is_some()
+}
+
+fn has_component<T: Component>(world: &World, entity: Entity) -> bool {
+    world.has::<T>(entity)
+}
+
+#[derive(Clone, Default)]
+pub struct ComponentRegistry {
+    by_name: BTreeMap<String, ComponentInfo>,
+    by_type: HashMap<TypeId, String>,
+    schemas: BTreeMap<String, ComponentSchema>,
+}
+
+impl ComponentRegistry {
+    pub fn register<T: Component + Serialize + DeserializeOwned>(&mut self, name: &str) {
+        let info = ComponentInfo {
+            name: name.to_string(),
+            type_id: TypeId::of::<T>(),
+            get: get_component::<T>,
+            set: set_component::<T>,
+            remove: remove_component::<T>,
+            has: has_component::<T>,
+        };
+        self.by_type.insert(info.type_id, info.name.clone());
+        self.by_name.insert(info.name.clone(), info);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ComponentInfo> {
+        self.by_name.get(name)
+    }
+
+    pub fn name_of<T: 'static>(&self) -> Option<&str> {
+        self.name_of_id(TypeId::of::<T>())
+    }
+
+    pub fn name_of_id(&self, type_id: TypeId) -> Option<&str> {
+        self.by_type.get(&type_id).map(String::as_str)
+    }
+
+    /// Applies to the Rust or script component named `name`.
+    pub fn set_schema(&mut self, name: &str, schema: ComponentSchema) {
+        self.schemas.insert(name.to_string(), schema);
+    }
+
+    pub fn schema(&self, name: &str) -> Option<&ComponentSchema> {
+        self.schemas.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ComponentInfo> {
+        self.by_name.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Health {
+        hp: f64,
+    }
+
+    #[test]
+    fn test_accessors_by_name() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.register_component::<Health>("Health");
+        let entity = world.spawn();
+        let info = world.registry().get("Health").cloned().unwrap();
+        assert_eq!(world.registry().name_of::<Health>(), Some("Health"));
+        assert!(world.registry().get("Mana").is_none());
+
+        (info.set)(
+            &mut world,
+            entity,
+            &lua,
+            lua.load("return { hp = 3 }").eval()?,
+        )?;
+        assert!((info.has)(&world, entity));
+        assert_eq!(
+            world.get::<Health>(entity).as_deref(),
+            Some(&Health { hp: 3.0 })
+        );
+        let value = (info.get)(&world, entity, &lua)?.unwrap();
+        assert_eq!(lua.from_value::<Health>(value)?, Health { hp: 3.0 });
+
+        let error = (info.set)(&mut world, entity, &lua, Value::Boolean(true))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("invalid value for component 'Health'"),
+            "{}",
+            error
+        );
+        assert!((info.remove)(&mut world, entity));
+        assert!(!(info.remove)(&mut world, entity));
+        assert!((info.get)(&world, entity, &lua)?.is_none());
+        Ok(())
+    }
+}
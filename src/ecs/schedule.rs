@@ -0,0 +1,759 @@
+use super::World;
+use super::panic::{self, PanicPolicy, SystemFailed};
+use super::watchdog::{self, FrameWatchdog, SystemTiming};
+use crate::debugger::watch;
+use mlua::{Error, FromLua, Function, Lua, Result, UserData, UserDataMethods, Value};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub type RustSystem = Box<dyn FnMut(&mut World) -> Result<()>>;
+pub type ExclusiveSystem = Box<dyn FnMut(&mut World, &Lua) -> Result<()>>;
+
+pub enum SystemFn {
+    Rust(RustSystem),
+    /// Called with a handle to the world the schedule is running against.
+    Lua(Function),
+    /// Runs at a sync point: every command queued by earlier systems has
+    /// been applied before it starts, and its own commands are applied
+    /// before the next system.
+    Exclusive(ExclusiveSystem),
+}
+
+pub struct System {
+    pub name: String,
+    pub stage: String,
+    pub order: SystemOrder,
+    pub run: SystemFn,
+    /// Total time spent in this system while timing is enabled.
+    pub elapsed: Duration,
+    /// Set after a panic under `PanicPolicy::Disable`; skipped by `run`.
+    pub disabled: bool,
+}
+
+pub const DEFAULT_STAGE: &str = "Update";
+
+/// Where a system runs relative to others in its stage. `after` and
+/// `before` name systems or sets; a name that matches nothing fails the
+/// schedule's `resolve`, since it is usually a typo.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SystemOrder {
+    pub after: Vec<String>,
+    pub before: Vec<String>,
+    /// Sets this system belongs to, so others can order against all of
+    /// them at once.
+    pub sets: Vec<String>,
+}
+
+impl SystemOrder {
+    pub fn new() -> Self {
+        SystemOrder::default()
+    }
+
+    pub fn after(mut self, name: &str) -> Self {
+        self.after.push(name.to_string());
+        self
+    }
+
+    pub fn before(mut self, name: &str) -> Self {
+        self.before.push(name.to_string());
+        self
+    }
+
+    pub fn in_set(mut self, set: &str) -> Self {
+        self.sets.push(set.to_string());
+        self
+    }
+}
+
+impl FromLua for SystemOrder {
+    fn from_lua(value: Value, lua: &Lua) -> Result<Self> {
+        let table = match value {
+            Value::Nil => return Ok(SystemOrder::default()),
+            Value::Table(table) => table,
+            other => {
+                return Err(Error::FromLuaConversionError {
+                    from: other.type_name(),
+                    to: "SystemOrder".to_string(),
+                    message: Some("expected a table".to_string()),
+                });
+            }
+        };
+        // Each constraint is a system name or a list of them.
+        let names = |key: &str| {
+            let names = match table.get::<Value>(key) {
+                Ok(Value::Nil) => Ok(Vec::new()),
+                Ok(Value::Table(list)) => list.sequence_values().collect(),
+                Ok(name) => String::from_lua(name, lua).map(|name| vec![name]),
+                Err(e) => Err(e),
+            };
+            names.map_err(|e| Error::FromLuaConversionError {
+                from: "table",
+                to: "SystemOrder".to_string(),
+                message: Some(format!("Failed to get '{}' field: {}", key, e)),
+            })
+        };
+        Ok(SystemOrder {
+            after: names("after")?,
+            before: names("before")?,
+            sets: names("in_set")?,
+        })
+    }
+}
+
+enum ScheduleEdit {
+    Add {
+        stage: String,
+        name: String,
+        function: Function,
+        order: SystemOrder,
+    },
+    Remove(String),
+}
+
+/// The `schedule` Lua global. Edits are queued and applied at the start of
+/// the schedule's next run, so scripts can add and remove systems from
+/// inside a running system.
+#[derive(Clone)]
+pub struct ScheduleHandle {
+    edits: Rc<RefCell<Vec<ScheduleEdit>>>,
+}
+
+impl UserData for ScheduleHandle {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method(
+            "add_system",
+            |_, this, (stage, name, function, order): (String, String, Function, SystemOrder)| {
+                this.edits.borrow_mut().push(ScheduleEdit::Add {
+                    stage,
+                    name,
+                    function,
+                    order,
+                });
+                Ok(())
+            },
+        );
+        methods.add_method("remove_system", |_, this, name: String| {
+            this.edits.borrow_mut().push(ScheduleEdit::Remove(name));
+            Ok(())
+        });
+    }
+}
+
+/// Systems grouped into stages that run in the order they were added,
+/// starting with `Update`. Within a stage systems run in insertion order
+/// unless a `SystemOrder` says otherwise. A schedule owns no world; it is
+/// run against whichever world it is paired with.
+pub struct Schedule {
+    systems: Vec<System>,
+    stages: Vec<String>,
+    /// Indices into `systems` in run order; `None` until resolved.
+    order: Option<Vec<usize>>,
+    edits: Rc<RefCell<Vec<ScheduleEdit>>>,
+    timing: bool,
+    panic_policy: PanicPolicy,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule {
+            systems: Vec::new(),
+            stages: vec![DEFAULT_STAGE.to_string()],
+            order: None,
+            edits: Rc::default(),
+            timing: false,
+            panic_policy: PanicPolicy::default(),
+        }
+    }
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Schedule::default()
+    }
+
+    /// Adds a stage that runs after every existing one.
+    pub fn add_stage(&mut self, name: &str) -> &mut Self {
+        if !self.stages.iter().any(|stage| stage == name) {
+            self.stages.push(name.to_string());
+        }
+        self
+    }
+
+    pub fn stages(&self) -> &[String] {
+        &self.stages
+    }
+
+    fn push(&mut self, name: &str, run: SystemFn) -> &mut Self {
+        self.systems.push(System {
+            name: name.to_string(),
+            stage: DEFAULT_STAGE.to_string(),
+            order: SystemOrder::default(),
+            run,
+            elapsed: Duration::ZERO,
+            disabled: false,
+        });
+        self.order = None;
+        self
+    }
+
+    pub fn add_system(
+        &mut self,
+        name: &str,
+        system: impl FnMut(&mut World) -> Result<()> + 'static,
+    ) -> &mut Self {
+        self.push(name, SystemFn::Rust(Box::new(system)))
+    }
+
+    pub fn add_exclusive_system(
+        &mut self,
+        name: &str,
+        system: impl FnMut(&mut World, &Lua) -> Result<()> + 'static,
+    ) -> &mut Self {
+        self.push(name, SystemFn::Exclusive(Box::new(system)))
+    }
+
+    /// Applies queued commands at this point in the schedule.
+    pub fn add_sync_point(&mut self, name: &str) -> &mut Self {
+        self.add_exclusive_system(name, |_, _| Ok(()))
+    }
+
+    pub fn add_lua_system(&mut self, name: &str, system: Function) -> &mut Self {
+        self.push(name, SystemFn::Lua(system))
+    }
+
+    /// Sets the stage and ordering of every system called `name`. Checked
+    /// when the schedule next resolves.
+    pub fn configure(&mut self, name: &str, stage: &str, order: SystemOrder) -> &mut Self {
+        for system in self.systems.iter_mut().filter(|s| s.name == name) {
+            system.stage = stage.to_string();
+            system.order = order.clone();
+        }
+        self.order = None;
+        self
+    }
+
+    /// Removes every system called `name`. Constraints that ordered other
+    /// systems around it go too, unless a set by that name remains.
+    pub fn remove_system(&mut self, name: &str) -> bool {
+        let before = self.systems.len();
+        self.systems.retain(|system| system.name != name);
+        if !self
+            .systems
+            .iter()
+            .any(|s| s.order.sets.iter().any(|set| set == name))
+        {
+            for system in &mut self.systems {
+                system.order.after.retain(|other| other != name);
+                system.order.before.retain(|other| other != name);
+            }
+        }
+        self.order = None;
+        self.systems.len() != before
+    }
+
+    /// Names in run order once resolved, otherwise in insertion order.
+    pub fn system_names(&self) -> impl Iterator<Item = &str> {
+        self.run_order()
+            .into_iter()
+            .map(|i| self.systems[i].name.as_str())
+    }
+
+    fn run_order(&self) -> Vec<usize> {
+        match &self.order {
+            Some(order) => order.clone(),
+            None => (0..self.systems.len()).collect(),
+        }
+    }
+
+    /// Validates every stage and ordering constraint and fixes the run
+    /// order. `run` does this itself whenever systems have changed.
+    pub fn resolve(&mut self) -> Result<()> {
+        if self.order.is_some() {
+            return Ok(());
+        }
+        let stage_of = |system: &System| {
+            self.stages
+                .iter()
+                .position(|stage| *stage == system.stage)
+                .ok_or_else(|| {
+                    Error::runtime(format!(
+                        "system '{}' is in unknown stage '{}'",
+                        system.name, system.stage
+                    ))
+                })
+        };
+        let stages = self
+            .systems
+            .iter()
+            .map(stage_of)
+            .collect::<Result<Vec<_>>>()?;
+
+        // Edges point from the system that runs first.
+        let mut edges: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); self.systems.len()];
+        for (i, system) in self.systems.iter().enumerate() {
+            let constraints = system
+                .order
+                .after
+                .iter()
+                .map(|name| (name, true))
+                .chain(system.order.before.iter().map(|name| (name, false)));
+            for (name, after) in constraints {
+                let known = self
+                    .systems
+                    .iter()
+                    .any(|other| other.name == *name || other.order.sets.contains(name));
+                if !known {
+                    return Err(Error::runtime(format!(
+                        "system '{}' is ordered {} '{}', which is no system or set",
+                        system.name,
+                        if after { "after" } else { "before" },
+                        name
+                    )));
+                }
+                for (j, other) in self.systems.iter().enumerate() {
+                    if i == j || (other.name != *name && !other.order.sets.contains(name)) {
+                        continue;
+                    }
+                    let (first, then) = if after { (j, i) } else { (i, j) };
+                    match stages[first].cmp(&stages[then]) {
+                        Ordering::Less => {}
+                        Ordering::Equal => {
+                            edges[first].insert(then);
+                        }
+                        Ordering::Greater => {
+                            return Err(Error::runtime(format!(
+                                "'{}' must run before '{}', but its stage '{}' runs after '{}'",
+                                self.systems[first].name,
+                                self.systems[then].name,
+                                self.systems[first].stage,
+                                self.systems[then].stage
+                            )));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Kahn's algorithm, always taking the earliest stage and then the
+        // earliest added system that is ready, so unconstrained systems
+        // keep their insertion order.
+        let mut incoming = vec![0; self.systems.len()];
+        for targets in &edges {
+            for &j in targets {
+                incoming[j] += 1;
+            }
+        }
+        let mut ready: BTreeSet<(usize, usize)> = (0..self.systems.len())
+            .filter(|&i| incoming[i] == 0)
+            .map(|i| (stages[i], i))
+            .collect();
+        let mut order = Vec::with_capacity(self.systems.len());
+        while let Some((_, i)) = ready.pop_first() {
+            order.push(i);
+            for &j in &edges[i] {
+                incoming[j] -= 1;
+                if incoming[j] == 0 {
+                    ready.insert((stages[j], j));
+                }
+            }
+        }
+        if order.len() < self.systems.len() {
+            let error = Error::runtime(format!(
+                "system order has a cycle: {}",
+                self.find_cycle(&edges, &incoming)
+            ));
+            log::error!(target: "schedule", "{}", error);
+            return Err(error);
+        }
+        self.order = Some(order);
+        Ok(())
+    }
+
+    /// Names one cycle among the systems Kahn's algorithm couldn't place,
+    /// as `a -> b -> a`. Every such system has an unplaced predecessor, so
+    /// walking predecessors must come back around.
+    fn find_cycle(&self, edges: &[BTreeSet<usize>], incoming: &[usize]) -> String {
+        let stuck = |i: usize| incoming[i] > 0;
+        let mut path = vec![(0..incoming.len()).find(|&i| stuck(i)).expect("a cycle")];
+        loop {
+            let last = *path.last().expect("not empty");
+            let previous = (0..edges.len())
+                .find(|&i| stuck(i) && edges[i].contains(&last))
+                .expect("unplaced systems have unplaced predecessors");
+            if let Some(start) = path.iter().position(|&i| i == previous) {
+                let mut cycle = path.split_off(start);
+                cycle.reverse();
+                // Start at the earliest added system so the report is stable.
+                let first = cycle.iter().enumerate().min_by_key(|&(_, &i)| i);
+                let first = first.expect("not empty").0;
+                cycle.rotate_left(first);
+                cycle.push(*cycle.first().expect("not empty"));
+                let names: Vec<&str> = cycle
+                    .into_iter()
+                    .map(|i| self.systems[i].name.as_str())
+                    .collect();
+                return names.join(" -> ");
+            }
+            path.push(previous);
+        }
+    }
+
+    /// A handle for scripts to edit this schedule; see `register_lua`.
+    pub fn lua_handle(&self) -> ScheduleHandle {
+        ScheduleHandle {
+            edits: self.edits.clone(),
+        }
+    }
+
+    /// Adds the `schedule` global: `add_system(stage, name, f, order?)`
+    /// with `order` as `{ after = ..., before = ..., in_set = ... }`, and
+    /// `remove_system(name)`.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("schedule", self.lua_handle())
+    }
+
+    /// Applies edits queued from Lua. An edit that leaves the schedule
+    /// unresolvable is undone and its error returned; later edits are
+    /// still applied.
+    fn apply_edits(&mut self) -> Result<()> {
+        let edits = std::mem::take(&mut *self.edits.borrow_mut());
+        let mut first_error = None;
+        for edit in edits {
+            match edit {
+                ScheduleEdit::Add {
+                    stage,
+                    name,
+                    function,
+                    order,
+                } => {
+                    self.push(&name, SystemFn::Lua(function));
+                    let system = self.systems.last_mut().expect("just pushed");
+                    system.stage = stage;
+                    system.order = order;
+                    if let Err(e) = self.resolve() {
+                        self.systems.pop();
+                        self.order = None;
+                        first_error.get_or_insert(Error::runtime(format!(
+                            "failed to add system '{}': {}",
+                            name, e
+                        )));
+                    }
+                }
+                ScheduleEdit::Remove(name) => {
+                    self.remove_system(&name);
+                }
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Accumulates per-system run times, read back with `timings`.
+    pub fn set_timing(&mut self, enabled: bool) {
+        self.timing = enabled;
+    }
+
+    pub fn timings(&self) -> Vec<(&str, Duration)> {
+        self.run_order()
+            .into_iter()
+            .map(|i| (self.systems[i].name.as_str(), self.systems[i].elapsed))
+            .collect()
+    }
+
+    /// Applies to Rust and exclusive systems; Lua systems report errors
+    /// rather than panicking.
+    pub fn set_panic_policy(&mut self, policy: PanicPolicy) {
+        self.panic_policy = policy;
+    }
+
+    /// Re-enables a system disabled after a panic.
+    pub fn enable_system(&mut self, name: &str) -> bool {
+        let mut found = false;
+        for system in self.systems.iter_mut().filter(|s| s.name == name) {
+            system.disabled = false;
+            found = true;
+        }
+        found
+    }
+
+    pub fn is_disabled(&self, name: &str) -> bool {
+        self.systems.iter().any(|s| s.name == name && s.disabled)
+    }
+
+    pub fn reset_timings(&mut self) {
+        for system in &mut self.systems {
+            system.elapsed = Duration::ZERO;
+        }
+    }
+
+    /// Applies queued script edits, then runs every system in order.
+    /// Commands are applied at sync points and once more after the last
+    /// system. With a `FrameWatchdog` resource,
+    /// the run is also timed against its budget. A queued edit that fails
+    /// doesn't hold up the frame; its error is returned once the systems
+    /// have run.
+    pub fn run(&mut self, world: &mut World, lua: &Lua) -> Result<()> {
+        let frame_start = world
+            .resource::<FrameWatchdog>()
+            .is_some()
+            .then(Instant::now);
+        let mut frame_timings = Vec::new();
+
+        let edited = self.apply_edits();
+        self.resolve()?;
+        let order = self.order.clone().expect("resolved above");
+
+        let policy = self.panic_policy;
+        for i in order {
+            let system = &mut self.systems[i];
+            if system.disabled {
+                continue;
+            }
+            watch::around_system(world, lua, None)?;
+            let start = (self.timing || frame_start.is_some()).then(Instant::now);
+            let isolate = |world: &mut World, f: &mut dyn FnMut(&mut World) -> Result<()>| {
+                if policy == PanicPolicy::Propagate {
+                    return f(world).map(|()| false);
+                }
+                match panic::catch(|| f(world)) {
+                    Ok(result) => result.map(|()| false),
+                    Err((message, backtrace)) => {
+                        let disabled = policy == PanicPolicy::Disable;
+                        log::error!(
+                            target: "schedule",
+                            "system '{}' panicked{}: {}",
+                            system.name,
+                            if disabled { " and was disabled" } else { "" },
+                            message
+                        );
+                        world.send_event(SystemFailed {
+                            system: system.name.clone(),
+                            message,
+                            backtrace,
+                            disabled,
+                        });
+                        Ok(disabled)
+                    }
+                }
+            };
+            let disable = match &mut system.run {
+                SystemFn::Rust(run) => isolate(world, &mut |world| run(world))?,
+                SystemFn::Lua(function) => {
+                    lua.scope(|scope| {
+                        let handle = scope.create_userdata_ref_mut(&mut *world)?;
+                        function.call::<()>(handle)
+                    })?;
+                    false
+                }
+                SystemFn::Exclusive(run) => {
+                    world.apply_commands(lua)?;
+                    let disable = isolate(world, &mut |world| run(world, lua))?;
+                    world.apply_commands(lua)?;
+                    disable
+                }
+            };
+            system.disabled = disable;
+            watch::around_system(world, lua, Some(&system.name))?;
+            if let Some(start) = start {
+                let elapsed = start.elapsed();
+                if self.timing {
+                    system.elapsed += elapsed;
+                }
+                if frame_start.is_some() {
+                    frame_timings.push(SystemTiming {
+                        system: system.name.clone(),
+                        elapsed,
+                        script: match &system.run {
+                            SystemFn::Lua(function) => Some(watchdog::script_location(function)),
+                            _ => None,
+                        },
+                    });
+                }
+            }
+        }
+        world.apply_commands(lua)?;
+        if let Some(frame_start) = frame_start {
+            watchdog::check_frame(world, frame_start.elapsed(), frame_timings);
+        }
+        world.advance_tick();
+        edited
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rust_and_lua_systems_run_in_order() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.insert_resource(Vec::<String>::new());
+
+        let mut schedule = Schedule::new();
+        schedule.add_system("first", |world| {
+            world
+                .resource_mut::<Vec<String>>()
+                .unwrap()
+                .push("rust".to_string());
+            Ok(())
+        });
+        schedule.add_lua_system(
+            "second",
+            lua.load("return function(world) world:spawn() end")
+                .eval()?,
+        );
+
+        schedule.run(&mut world, &lua)?;
+        assert_eq!(world.resource::<Vec<String>>().unwrap(), &["rust"]);
+        assert_eq!(world.len(), 1);
+        assert_eq!(world.tick(), 1);
+
+        assert!(schedule.remove_system("second"));
+        assert_eq!(schedule.system_names().collect::<Vec<_>>(), vec!["first"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lua_adds_ordered_systems() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let mut schedule = Schedule::new();
+        schedule.add_stage("Late");
+        schedule.register_lua(&lua)?;
+        schedule.add_system("movement", |_| Ok(()));
+        schedule.add_system("render", |_| Ok(()));
+
+        lua.load(
+            r#"
+            schedule:add_system("Update", "enemy_ai", function(world) end, { after = "movement", before = { "render" } })
+            schedule:add_system("Update", "first", function(world) end, { before = "movement" })
+            schedule:add_system("Late", "cleanup", function(world)
+                schedule:remove_system("first")
+            end)
+        "#,
+        )
+        .exec()?;
+        schedule.run(&mut world, &lua)?;
+        assert_eq!(
+            schedule.system_names().collect::<Vec<_>>(),
+            ["first", "movement", "enemy_ai", "render", "cleanup"]
+        );
+
+        lua.load(r#"schedule:add_system("Update", "loop", function() end, { after = "render", before = "movement" })"#)
+            .exec()?;
+        let error = schedule.run(&mut world, &lua).unwrap_err().to_string();
+        assert!(error.contains("failed to add system 'loop'"), "{}", error);
+        schedule.run(&mut world, &lua)?;
+        assert_eq!(
+            schedule.system_names().collect::<Vec<_>>(),
+            ["movement", "enemy_ai", "render", "cleanup"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_removing_a_dependency_keeps_the_schedule_running() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let mut schedule = Schedule::new();
+        schedule.register_lua(&lua)?;
+        schedule
+            .add_system("input", |_| Ok(()))
+            .add_system("movement", |world| {
+                *world.resource_mut::<u32>().unwrap() += 1;
+                Ok(())
+            })
+            .configure("movement", DEFAULT_STAGE, SystemOrder::new().after("input"));
+        world.insert_resource(0u32);
+
+        lua.load(r#"schedule:remove_system("input")"#).exec()?;
+        schedule.run(&mut world, &lua)?;
+        schedule.run(&mut world, &lua)?;
+        assert_eq!(schedule.system_names().collect::<Vec<_>>(), ["movement"]);
+
+        lua.load(r#"schedule:add_system("Update", "late", function() end, { after = "missing" })"#)
+            .exec()?;
+        assert!(schedule.run(&mut world, &lua).is_err());
+        assert_eq!(world.resource::<u32>(), Some(&3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sets_and_cycle_diagnostics() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system("integrate", |_| Ok(()))
+            .add_system("render", |_| Ok(()))
+            .add_system("collide", |_| Ok(()))
+            .add_system("input", |_| Ok(()))
+            .configure(
+                "integrate",
+                DEFAULT_STAGE,
+                SystemOrder::new().in_set("physics"),
+            )
+            .configure(
+                "collide",
+                DEFAULT_STAGE,
+                SystemOrder::new().in_set("physics").after("integrate"),
+            )
+            .configure("render", DEFAULT_STAGE, SystemOrder::new().after("physics"))
+            .configure("input", DEFAULT_STAGE, SystemOrder::new().before("physics"));
+        schedule.run(&mut world, &lua)?;
+        assert_eq!(
+            schedule.system_names().collect::<Vec<_>>(),
+            ["input", "integrate", "collide", "render"]
+        );
+
+        schedule.configure(
+            "input",
+            DEFAULT_STAGE,
+            SystemOrder::new().before("physics").after("render"),
+        );
+        let error = schedule.run(&mut world, &lua).unwrap_err().to_string();
+        assert!(
+            error.contains("cycle: integrate -> render -> input -> integrate"),
+            "{}",
+            error
+        );
+
+        schedule.configure("input", DEFAULT_STAGE, SystemOrder::new().before("phyiscs"));
+        let error = schedule.run(&mut world, &lua).unwrap_err().to_string();
+        assert!(
+            error.contains("system 'input' is ordered before 'phyiscs', which is no system or set"),
+            "{}",
+            error
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_exclusive_systems_see_applied_commands() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+
+        let mut schedule = Schedule::new();
+        schedule.add_system("queue", |world| {
+            world.commands().push(|world, _| {
+                world.spawn();
+                Ok(())
+            });
+            assert!(world.is_empty());
+            Ok(())
+        });
+        schedule.add_exclusive_system("count", |world, _| {
+            world.insert_resource(world.len());
+            Ok(())
+        });
+
+        schedule.run(&mut world, &lua)?;
+        assert_eq!(world.resource::<usize>(), Some(&1));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+use super::{BrokenRef, EcsError, Entity, ScriptValue, World};
+use mlua::{Error, FromLua, Lua, LuaSerdeExt, Result, Table, Value};
+use std::collections::BTreeMap;
+
+/// Rules for one field of a table-shaped component.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldSchema {
+    /// Filled in when the field is missing.
+    pub default: Option<ScriptValue>,
+    pub required: bool,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// The field must hold a live entity; implies `required`.
+    pub entity: bool,
+    /// An optional entity reference the world keeps track of, handled as
+    /// given when the target despawns.
+    pub entity_ref: Option<BrokenRef>,
+}
+
+impl FieldSchema {
+    fn check(&self, world: &World, value: &ScriptValue) -> std::result::Result<(), String> {
+        if self.min.is_some() || self.max.is_some() {
+            let ScriptValue::Number(n) = value else {
+                return Err("expected a number".to_string());
+            };
+            if self.min.is_some_and(|min| *n < min) || self.max.is_some_and(|max| *n > max) {
+                return Err(match (self.min, self.max) {
+                    (Some(min), Some(max)) => format!("{} is outside {}..={}", n, min, max),
+                    (Some(min), None) => format!("{} is below the minimum {}", n, min),
+                    (_, max) => format!("{} is above the maximum {}", n, max.unwrap_or_default()),
+                });
+            }
+        }
+        if self.entity || self.entity_ref.is_some() {
+            let alive = match value {
+                ScriptValue::Number(n) if *n >= 0.0 && n.fract() == 0.0 => {
+                    Entity::from_bits(*n as u64).is_some_and(|e| world.is_alive(e))
+                }
+                _ => false,
+            };
+            if !alive {
+                return Err("expected a live entity".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Defaults and validators applied whenever a component is set by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComponentSchema {
+    pub fields: BTreeMap<String, FieldSchema>,
+}
+
+impl ComponentSchema {
+    pub fn new() -> Self {
+        ComponentSchema::default()
+    }
+
+    pub fn with_field(mut self, name: &str, field: FieldSchema) -> Self {
+        self.fields.insert(name.to_string(), field);
+        self
+    }
+
+    /// Fills defaults into `value` and checks every field, naming the first
+    /// field that fails.
+    pub fn apply(
+        &self,
+        world: &World,
+        component: &str,
+        value: &mut ScriptValue,
+    ) -> std::result::Result<(), EcsError> {
+        let ScriptValue::Map(fields) = value else {
+            return Err(EcsError::InvalidComponent {
+                component: component.to_string(),
+                message: "expected a table".to_string(),
+            });
+        };
+
+        for (name, schema) in &self.fields {
+            let invalid = |message: String| EcsError::InvalidField {
+                component: component.to_string(),
+                field: name.clone(),
+                message,
+            };
+            match fields.get(name) {
+                Some(field) => schema.check(world, field).map_err(invalid)?,
+                None => match &schema.default {
+                    Some(default) => {
+                        fields.insert(name.clone(), default.clone());
+                    }
+                    None if schema.required || schema.entity => {
+                        return Err(invalid("missing required field".to_string()));
+                    }
+                    None => {}
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+fn field<T: FromLua>(table: &Table, name: &str) -> Result<T> {
+    table.get(name).map_err(|e| Error::FromLuaConversionError {
+        from: "Table",
+        to: "FieldSchema".to_string(),
+        message: Some(format!("Failed to get '{}' field: {}", name, e)),
+    })
+}
+
+impl FromLua for FieldSchema {
+    fn from_lua(value: Value, lua: &Lua) -> Result<Self> {
+        match value {
+            Value::Table(table) => {
+                let default: Value = field(&table, "default")?;
+                Ok(FieldSchema {
+                    default: match default {
+                        Value::Nil => None,
+                        value => Some(lua.from_value(value)?),
+                    },
+                    required: field::<Option<bool>>(&table, "required")?.unwrap_or(false),
+                    min: field(&table, "min")?,
+                    max: field(&table, "max")?,
+                    entity: field::<Option<bool>>(&table, "entity")?.unwrap_or(false),
+                    entity_ref: match field::<Option<String>>(&table, "ref")?.as_deref() {
+                        None => None,
+                        Some("clear") => Some(BrokenRef::Clear),
+                        Some("notify") => Some(BrokenRef::Notify),
+                        Some(other) => {
+                            return Err(Error::FromLuaConversionError {
+                                from: "Table",
+                                to: "FieldSchema".to_string(),
+                                message: Some(format!(
+                                    "Unknown 'ref' policy '{}', expected 'clear' or 'notify'",
+                                    other
+                                )),
+                            });
+                        }
+                    },
+                })
+            }
+            _ => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "FieldSchema".to_string(),
+                message: Some("Expected a table".to_string()),
+            }),
+        }
+    }
+}
+
+impl FromLua for ComponentSchema {
+    fn from_lua(value: Value, lua: &Lua) -> Result<Self> {
+        Ok(ComponentSchema {
+            fields: BTreeMap::from_lua(value, lua)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Health {
+        hp: f64,
+        max: f64,
+    }
+
+    #[test]
+    fn test_schema_defaults_and_validation() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.register_component::<Health>("Health");
+        world.set_schema(
+            "Health",
+            ComponentSchema::new()
+                .with_field(
+                    "hp",
+                    FieldSchema {
+                        min: Some(0.0),
+                        required: true,
+                        ..FieldSchema::default()
+                    },
+                )
+                .with_field(
+                    "max",
+                    FieldSchema {
+                        default: Some(ScriptValue::Number(100.0)),
+                        ..FieldSchema::default()
+                    },
+                ),
+        );
+
+        let errors: Vec<String> = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world = ...
+                world:define_component("Follow", { target = { entity = true } })
+                local e = world:spawn({ Health = { hp = 5 } })
+                assert(world:get(e, "Health").max == 100)
+
+                local errors = {}
+                for _, bad in { { Health = { hp = -1 } }, { Follow = { target = 12345 } } } do
+                    local ok, err = pcall(world.spawn, world, bad)
+                    assert(not ok)
+                    table.insert(errors, tostring(err))
+                end
+                world:set(e, "Follow", { target = e })
+                return errors
+            "#,
+            )
+            .call(handle)
+        })?;
+
+        assert!(errors[0].contains("Health.hp"), "{}", errors[0]);
+        assert!(errors[1].contains("Follow.target"), "{}", errors[1]);
+        Ok(())
+    }
+}
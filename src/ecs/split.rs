@@ -0,0 +1,143 @@
+use super::storage::{ComponentTicks, SparseSet};
+use super::{Component, Entity, World};
+use std::any::TypeId;
+
+/// One column borrowed out of a world by `World::split`. Values can be
+/// read and changed, stamped with the world's tick, but components can't
+/// be added or removed, since that has to run the world's hooks.
+pub struct ColumnMut<'w, T> {
+    column: &'w mut SparseSet<T>,
+    tick: u32,
+}
+
+impl<T> ColumnMut<'_, T> {
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.column.contains(entity)
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.column.get(entity)
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.column.get_mut(entity, self.tick)
+    }
+
+    pub fn ticks(&self, entity: Entity) -> Option<ComponentTicks> {
+        self.column.ticks(entity)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.column.iter()
+    }
+
+    /// Marks every component changed.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.column.iter_mut(self.tick)
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        self.column.entities()
+    }
+
+    pub fn len(&self) -> usize {
+        self.column.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.column.is_empty()
+    }
+}
+
+/// A tuple of distinct component types whose columns can be borrowed from
+/// a world at the same time.
+pub trait SplitColumns {
+    type Columns<'w>;
+
+    fn split(world: &mut World) -> Self::Columns<'_>;
+}
+
+macro_rules! impl_split_columns {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<$($name: Component),+> SplitColumns for ($($name,)+) {
+            type Columns<'w> = ($(ColumnMut<'w, $name>,)+);
+
+            fn split(world: &mut World) -> Self::Columns<'_> {
+                $(world.column_mut::<$name>();)+
+                let tick = world.tick();
+                let [$($name),+] = world.storages_disjoint_mut([$(&TypeId::of::<$name>()),+]);
+                ($(
+                    ColumnMut {
+                        column: $name
+                            .get_mut()
+                            .as_any_mut()
+                            .downcast_mut::<SparseSet<$name>>()
+                            .expect("storage registered under the wrong type"),
+                        tick,
+                    },
+                )+)
+            }
+        }
+    };
+}
+
+impl_split_columns!(A);
+impl_split_columns!(A, B);
+impl_split_columns!(A, B, C);
+impl_split_columns!(A, B, C, D);
+impl_split_columns!(A, B, C, D, E);
+impl_split_columns!(A, B, C, D, E, F);
+
+impl World {
+    /// Mutable access to several component columns at once. The columns are
+    /// `Send`, so each can be handed to its own thread; missing columns are
+    /// created empty. Changes are marked at the current tick.
+    ///
+    /// Panics if the same component type appears twice.
+    pub fn split<S: SplitColumns>(&mut self) -> S::Columns<'_> {
+        S::split(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(f64);
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[test]
+    fn test_split_columns_across_threads() {
+        let mut world = World::new();
+        for i in 0..100 {
+            let entity = world.spawn();
+            world.insert(entity, Position(i as f64)).unwrap();
+            world.insert(entity, Health(10)).unwrap();
+        }
+
+        world.advance_tick();
+        let (mut positions, mut health) = world.split::<(Position, Health)>();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for (_, position) in positions.iter_mut() {
+                    position.0 *= 2.0;
+                }
+            });
+            scope.spawn(|| {
+                let first = health.entities()[0];
+                health.get_mut(first).unwrap().0 -= 1;
+            });
+        });
+
+        let first = world.entities().next().unwrap();
+        let last = world.entities().last().unwrap();
+        assert_eq!(*world.get::<Position>(last).unwrap(), Position(198.0));
+        assert_eq!(*world.get::<Health>(first).unwrap(), Health(9));
+        assert_eq!(world.ticks::<Position>(last).unwrap().changed, 1);
+        assert_eq!(world.ticks::<Health>(first).unwrap().changed, 1);
+        assert_eq!(world.ticks::<Health>(last).unwrap().changed, 0);
+    }
+}
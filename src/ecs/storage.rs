@@ -0,0 +1,211 @@
+use super::Entity;
+use std::any::Any;
+
+const EMPTY: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ComponentTicks {
+    pub added: u32,
+    pub changed: u32,
+}
+
+/// Dense component column indexed through a sparse entity-index table.
+#[derive(Debug, Clone)]
+pub struct SparseSet<T> {
+    sparse: Vec<u32>,
+    entities: Vec<Entity>,
+    values: Vec<T>,
+    ticks: Vec<ComponentTicks>,
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        SparseSet {
+            sparse: Vec::new(),
+            entities: Vec::new(),
+            values: Vec::new(),
+            ticks: Vec::new(),
+        }
+    }
+}
+
+impl<T> SparseSet<T> {
+    fn dense_index(&self, entity: Entity) -> Option<usize> {
+        let slot = *self.sparse.get(entity.index() as usize)?;
+        if slot == EMPTY || self.entities[slot as usize] != entity {
+            return None;
+        }
+        Some(slot as usize)
+    }
+
+    pub fn insert(&mut self, entity: Entity, value: T, tick: u32) -> Option<T> {
+        if let Some(i) = self.dense_index(entity) {
+            self.ticks[i].changed = tick;
+            return Some(std::mem::replace(&mut self.values[i], value));
+        }
+
+        let index = entity.index() as usize;
+        if index >= self.sparse.len() {
+            self.sparse.resize(index + 1, EMPTY);
+        }
+        // A stale generation may still occupy the slot; drop it first.
+        if self.sparse[index] != EMPTY {
+            let stale = self.entities[self.sparse[index] as usize];
+            self.remove(stale);
+        }
+
+        self.sparse[index] = self.values.len() as u32;
+        self.entities.push(entity);
+        self.values.push(value);
+        self.ticks.push(ComponentTicks {
+            added: tick,
+            changed: tick,
+        });
+        None
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.entities.reserve(additional);
+        self.values.reserve(additional);
+        self.ticks.reserve(additional);
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let i = self.dense_index(entity)?;
+        let last = self.entities.len() - 1;
+        if i != last {
+            let moved = self.entities[last];
+            self.sparse[moved.index() as usize] = i as u32;
+        }
+        self.sparse[entity.index() as usize] = EMPTY;
+        self.entities.swap_remove(i);
+        self.ticks.swap_remove(i);
+        Some(self.values.swap_remove(i))
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.dense_index(entity).is_some()
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.dense_index(entity).map(|i| &self.values[i])
+    }
+
+    /// Mutable access marks the component changed at `tick`.
+    pub fn get_mut(&mut self, entity: Entity, tick: u32) -> Option<&mut T> {
+        let i = self.dense_index(entity)?;
+        self.ticks[i].changed = tick;
+        Some(&mut self.values[i])
+    }
+
+    /// Marks every component changed at `tick`.
+    pub fn iter_mut(&mut self, tick: u32) -> impl Iterator<Item = (Entity, &mut T)> {
+        for ticks in &mut self.ticks {
+            ticks.changed = tick;
+        }
+        self.entities.iter().copied().zip(self.values.iter_mut())
+    }
+
+    pub fn ticks(&self, entity: Entity) -> Option<ComponentTicks> {
+        self.dense_index(entity).map(|i| self.ticks[i])
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.entities.iter().copied().zip(self.values.iter())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Bytes reserved by the column itself; heap data owned by the values
+    /// (strings, vectors) isn't counted.
+    pub fn memory(&self) -> usize {
+        self.sparse.capacity() * size_of::<u32>()
+            + self.entities.capacity() * size_of::<Entity>()
+            + self.values.capacity() * size_of::<T>()
+            + self.ticks.capacity() * size_of::<ComponentTicks>()
+    }
+}
+
+/// Type-erased view of a `SparseSet<T>` so the world can manage columns
+/// without knowing their component types.
+pub trait AnyStorage: Any + Send + Sync {
+    fn remove_entity(&mut self, entity: Entity) -> bool;
+    fn contains(&self, entity: Entity) -> bool;
+    fn entities(&self) -> &[Entity];
+    fn ticks(&self, entity: Entity) -> Option<ComponentTicks>;
+    fn type_name(&self) -> &'static str;
+    fn memory(&self) -> usize;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Send + Sync + 'static> AnyStorage for SparseSet<T> {
+    fn remove_entity(&mut self, entity: Entity) -> bool {
+        self.remove(entity).is_some()
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        SparseSet::contains(self, entity)
+    }
+
+    fn entities(&self) -> &[Entity] {
+        SparseSet::entities(self)
+    }
+
+    fn ticks(&self, entity: Entity) -> Option<ComponentTicks> {
+        SparseSet::ticks(self, entity)
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+
+    fn memory(&self) -> usize {
+        SparseSet::memory(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::Entities;
+
+    #[test]
+    fn test_sparse_set_swap_remove_keeps_lookup() {
+        let mut entities = Entities::default();
+        let (a, b, c) = (entities.alloc(), entities.alloc(), entities.alloc());
+
+        let mut set = SparseSet::default();
+        set.insert(a, "a", 0);
+        set.insert(b, "b", 0);
+        set.insert(c, "c", 0);
+
+        assert_eq!(set.remove(a), Some("a"));
+        assert_eq!(set.get(c), Some(&"c"));
+        assert_eq!(set.get(b), Some(&"b"));
+        assert!(!set.contains(a));
+        assert_eq!(set.len(), 2);
+
+        *set.get_mut(b, 5).unwrap() = "B";
+        assert_eq!(set.ticks(b).unwrap().changed, 5);
+        assert_eq!(set.insert(b, "bb", 6), Some("B"));
+    }
+}
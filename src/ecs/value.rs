@@ -0,0 +1,64 @@
+use mlua::{FromLua, IntoLua, Lua, LuaSerdeExt, Result, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Plain-data component value owned by the world, used for components that
+/// are defined from Lua rather than as Rust types.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ScriptValue {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    List(Vec<ScriptValue>),
+    Map(BTreeMap<String, ScriptValue>),
+}
+
+impl IntoLua for ScriptValue {
+    fn into_lua(self, lua: &Lua) -> Result<Value> {
+        lua.to_value(&self)
+    }
+}
+
+impl FromLua for ScriptValue {
+    fn from_lua(value: Value, lua: &Lua) -> Result<Self> {
+        lua.from_value(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_value_round_trip() -> Result<()> {
+        let lua = Lua::new();
+        let value: ScriptValue = lua
+            .load("return { hp = 10, name = 'orc', tags = { 'a', 'b' }, boss = false }")
+            .eval()?;
+
+        let ScriptValue::Map(fields) = &value else {
+            panic!("expected a map, got {:?}", value);
+        };
+        assert_eq!(fields["hp"], ScriptValue::Number(10.0));
+        assert_eq!(
+            fields["tags"],
+            ScriptValue::List(vec![
+                ScriptValue::String("a".to_string()),
+                ScriptValue::String("b".to_string())
+            ])
+        );
+
+        let name: String = lua
+            .load("local v = ... return v.name")
+            .call(value.clone())?;
+        assert_eq!(name, "orc");
+
+        assert!(
+            lua.load("return function() end")
+                .eval::<ScriptValue>()
+                .is_err()
+        );
+        Ok(())
+    }
+}
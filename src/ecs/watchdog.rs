@@ -0,0 +1,138 @@
+use super::World;
+use mlua::Function;
+use std::time::Duration;
+
+/// Time one system took during a single frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemTiming {
+    pub system: String,
+    pub elapsed: Duration,
+    /// `source:line` of the function behind a Lua system.
+    pub script: Option<String>,
+}
+
+/// Sent when a frame went over the watchdog's budget.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameOverrun {
+    pub tick: u32,
+    pub elapsed: Duration,
+    pub budget: Duration,
+    /// Slowest system first.
+    pub systems: Vec<SystemTiming>,
+}
+
+/// Insert as a resource to check every schedule run against `budget`.
+/// Overruns are always logged; `send_events` also sends them as
+/// `FrameOverrun` events with the per-system breakdown.
+#[derive(Debug, Clone)]
+pub struct FrameWatchdog {
+    pub budget: Duration,
+    pub send_events: bool,
+    pub overruns: u64,
+    pub worst: Duration,
+}
+
+impl FrameWatchdog {
+    pub fn new(budget: Duration) -> Self {
+        FrameWatchdog {
+            budget,
+            send_events: false,
+            overruns: 0,
+            worst: Duration::ZERO,
+        }
+    }
+
+    pub fn with_events(mut self) -> Self {
+        self.send_events = true;
+        self
+    }
+}
+
+pub(crate) fn script_location(function: &Function) -> String {
+    let info = function.info();
+    format!(
+        "{}:{}",
+        info.short_src.as_deref().unwrap_or("?"),
+        info.line_defined.unwrap_or(0)
+    )
+}
+
+/// Records the frame against the world's watchdog, if it has one.
+pub(crate) fn check_frame(world: &mut World, elapsed: Duration, mut systems: Vec<SystemTiming>) {
+    let tick = world.tick();
+    let Some(watchdog) = world.resource_mut::<FrameWatchdog>() else {
+        return;
+    };
+    watchdog.worst = watchdog.worst.max(elapsed);
+    if elapsed <= watchdog.budget {
+        return;
+    }
+    watchdog.overruns += 1;
+    let (budget, send_events) = (watchdog.budget, watchdog.send_events);
+
+    systems.sort_by_key(|timing| std::cmp::Reverse(timing.elapsed));
+    let breakdown: Vec<String> = systems
+        .iter()
+        .take(3)
+        .map(|timing| match &timing.script {
+            Some(script) => format!("{} ({}) {:.1?}", timing.system, script, timing.elapsed),
+            None => format!("{} {:.1?}", timing.system, timing.elapsed),
+        })
+        .collect();
+    log::warn!(
+        target: "watchdog",
+        "frame {} took {:.1?}, over the {:.1?} budget; slowest: {}",
+        tick,
+        elapsed,
+        budget,
+        breakdown.join(", ")
+    );
+
+    if send_events {
+        world.send_event(FrameOverrun {
+            tick,
+            elapsed,
+            budget,
+            systems,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::Schedule;
+    use mlua::{Lua, Result};
+
+    #[test]
+    fn test_overrun_reports_slow_lua_system() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.insert_resource(FrameWatchdog::new(Duration::from_millis(5)).with_events());
+
+        let mut schedule = Schedule::new();
+        schedule.add_system("fast", |_| Ok(()));
+        schedule.add_lua_system(
+            "ai",
+            lua.load(
+                r#"
+                return function(world)
+                    local start = os.clock()
+                    while os.clock() - start < 0.02 do end
+                end
+            "#,
+            )
+            .set_name("=ai.lua")
+            .eval()?,
+        );
+        schedule.run(&mut world, &lua)?;
+
+        let overruns = world.drain_events::<FrameOverrun>();
+        assert_eq!(overruns.len(), 1);
+        let slowest = &overruns[0].systems[0];
+        assert_eq!(slowest.system, "ai");
+        assert_eq!(slowest.script.as_deref(), Some("ai.lua:2"));
+        assert_eq!(world.resource::<FrameWatchdog>().unwrap().overruns, 1);
+        Ok(())
+    }
+}
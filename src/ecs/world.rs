@@ -0,0 +1,506 @@
+use super::clone::CloneFixup;
+use super::commands::CommandBuffer;
+use super::dynamic::{DynamicQuery, QuerySpec};
+use super::hooks::{ComponentKey, Hook, HookEvent, Hooks};
+use super::name::{NameIndex, NamePolicy};
+use super::query::{Query, QueryParam};
+use super::refs::RefIndex;
+use super::registry::ComponentRegistry;
+use super::storage::{AnyStorage, ComponentTicks, SparseSet};
+use super::{ComponentSchema, Entities, Entity, ScriptValue};
+use mlua::Lua;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+pub trait Component: Send + Sync + 'static {}
+
+impl<T: Send + Sync + 'static> Component for T {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EcsError {
+    NoSuchEntity(Entity),
+    UnknownComponent(String),
+    InvalidComponent {
+        component: String,
+        message: String,
+    },
+    DuplicateName(String),
+    InvalidQuery(String),
+    InvalidField {
+        component: String,
+        field: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for EcsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EcsError::NoSuchEntity(entity) => write!(f, "entity {} does not exist", entity),
+            EcsError::UnknownComponent(name) => write!(f, "unknown component '{}'", name),
+            EcsError::InvalidComponent { component, message } => {
+                write!(
+                    f,
+                    "invalid value for component '{}': {}",
+                    component, message
+                )
+            }
+            EcsError::InvalidField {
+                component,
+                field,
+                message,
+            } => write!(f, "invalid field '{}.{}': {}", component, field, message),
+            EcsError::InvalidQuery(message) => write!(f, "invalid query: {}", message),
+            EcsError::DuplicateName(name) => write!(f, "an entity named '{}' already exists", name),
+        }
+    }
+}
+
+impl std::error::Error for EcsError {}
+
+impl From<EcsError> for mlua::Error {
+    fn from(e: EcsError) -> Self {
+        mlua::Error::external(e)
+    }
+}
+
+/// Everything that iterates the world does so in an order fixed by the
+/// calls made on it, never by hashing: entities by index, Rust columns in
+/// the order they were first inserted into, script components by name,
+/// queries by their driving column's storage order and events by send
+/// order. Two worlds fed the same calls therefore run hooks, systems and
+/// queries identically, which lockstep and replays rely on.
+#[derive(Default)]
+pub struct World {
+    entities: Entities,
+    storages: HashMap<TypeId, RefCell<Box<dyn AnyStorage>>>,
+    /// Keys of `storages` in creation order, for iterating them.
+    column_order: Vec<TypeId>,
+    script_components: BTreeMap<String, SparseSet<ScriptValue>>,
+    resources: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    registry: ComponentRegistry,
+    names: NameIndex,
+    query_cache: HashMap<String, DynamicQuery>,
+    hooks: Hooks,
+    commands: CommandBuffer,
+    clone_fixups: Vec<CloneFixup>,
+    refs: RefIndex,
+    tick: u32,
+}
+
+impl World {
+    pub fn new() -> Self {
+        World::default()
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        self.entities.alloc()
+    }
+
+    pub fn reserve_entities(&mut self, additional: usize) {
+        self.entities.reserve(additional);
+    }
+
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        self.unlink(entity);
+        self.queue_broken_refs(entity);
+        self.entities.free(entity);
+        for type_id in &self.column_order {
+            let storage = self.storages.get_mut(type_id).expect("column exists");
+            if storage.get_mut().remove_entity(entity) {
+                let key = ComponentKey::Rust(*type_id);
+                self.hooks
+                    .fire(&mut self.commands, &key, HookEvent::Removed, entity);
+            }
+        }
+        for (name, storage) in &mut self.script_components {
+            if storage.remove(entity).is_some() {
+                let key = ComponentKey::Script(name.clone());
+                self.hooks
+                    .fire(&mut self.commands, &key, HookEvent::Removed, entity);
+            }
+        }
+        self.names.remove(entity);
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.is_alive(entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities.iter()
+    }
+
+    /// Current change-detection tick; advanced once per schedule run.
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub fn advance_tick(&mut self) {
+        self.tick = self.tick.wrapping_add(1);
+    }
+
+    pub(crate) fn allocator(&self) -> &Entities {
+        &self.entities
+    }
+
+    /// Swaps in a recorded allocator and tick, so a snapshot's entities
+    /// can be filled in under their old ids. The world must be empty.
+    pub(crate) fn restore_allocator(&mut self, entities: Entities, tick: u32) {
+        assert!(self.is_empty(), "restoring entities into a non-empty world");
+        self.entities = entities;
+        self.tick = tick;
+    }
+
+    pub(crate) fn storage_cell<T: Component>(&self) -> Option<&RefCell<Box<dyn AnyStorage>>> {
+        self.storage_by_id(TypeId::of::<T>())
+    }
+
+    pub(crate) fn storage_by_id(&self, type_id: TypeId) -> Option<&RefCell<Box<dyn AnyStorage>>> {
+        self.storages.get(&type_id)
+    }
+
+    pub(crate) fn storages(&self) -> impl Iterator<Item = (TypeId, &RefCell<Box<dyn AnyStorage>>)> {
+        self.column_order
+            .iter()
+            .map(|type_id| (*type_id, &self.storages[type_id]))
+    }
+
+    pub(crate) fn column_mut<T: Component>(&mut self) -> &mut SparseSet<T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| {
+                self.column_order.push(TypeId::of::<T>());
+                RefCell::new(Box::new(SparseSet::<T>::default()))
+            })
+            .get_mut()
+            .as_any_mut()
+            .downcast_mut()
+            .expect("storage registered under the wrong type")
+    }
+
+    /// Panics if `ids` repeats a type or names a column that doesn't exist.
+    pub(crate) fn storages_disjoint_mut<const N: usize>(
+        &mut self,
+        ids: [&TypeId; N],
+    ) -> [&mut RefCell<Box<dyn AnyStorage>>; N] {
+        self.storages
+            .get_disjoint_mut(ids)
+            .map(|storage| storage.expect("column exists"))
+    }
+
+    pub fn insert<T: Component>(
+        &mut self,
+        entity: Entity,
+        component: T,
+    ) -> Result<Option<T>, EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::NoSuchEntity(entity));
+        }
+        let tick = self.tick;
+        let old = self.column_mut::<T>().insert(entity, component, tick);
+        if old.is_none() {
+            self.fire(ComponentKey::of::<T>(), HookEvent::Added, entity);
+        }
+        Ok(old)
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        let removed = self
+            .storages
+            .get_mut(&TypeId::of::<T>())?
+            .get_mut()
+            .as_any_mut()
+            .downcast_mut::<SparseSet<T>>()?
+            .remove(entity)?;
+        self.fire(ComponentKey::of::<T>(), HookEvent::Removed, entity);
+        Some(removed)
+    }
+
+    pub fn has<T: Component>(&self, entity: Entity) -> bool {
+        self.storage_cell::<T>()
+            .is_some_and(|cell| cell.borrow().contains(entity))
+    }
+
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<Ref<'_, T>> {
+        let cell = self.storage_cell::<T>()?;
+        Ref::filter_map(cell.borrow(), |storage| {
+            storage.as_any().downcast_ref::<SparseSet<T>>()?.get(entity)
+        })
+        .ok()
+    }
+
+    /// Mutable access through a shared world, marking the component changed.
+    pub fn get_mut<T: Component>(&self, entity: Entity) -> Option<RefMut<'_, T>> {
+        let cell = self.storage_cell::<T>()?;
+        let tick = self.tick;
+        RefMut::filter_map(cell.borrow_mut(), |storage| {
+            storage
+                .as_any_mut()
+                .downcast_mut::<SparseSet<T>>()?
+                .get_mut(entity, tick)
+        })
+        .ok()
+    }
+
+    pub fn ticks<T: Component>(&self, entity: Entity) -> Option<ComponentTicks> {
+        self.storage_cell::<T>()?.borrow().ticks(entity)
+    }
+
+    pub fn query<Q: QueryParam>(&self) -> Query<'_, Q> {
+        Query::new(self)
+    }
+
+    /// Runs a by-name query, compiling it on first use and reusing the
+    /// compiled form for every later spec with the same signature.
+    pub fn query_by_spec(&mut self, spec: &QuerySpec) -> Result<Vec<Entity>, EcsError> {
+        let signature = spec.signature();
+        if !self.query_cache.contains_key(&signature) {
+            let query = DynamicQuery::compile(&self.registry, spec)?;
+            self.query_cache.insert(signature.clone(), query);
+        }
+        Ok(self.query_cache[&signature].run(self))
+    }
+
+    pub fn set_script_component(
+        &mut self,
+        entity: Entity,
+        name: &str,
+        value: ScriptValue,
+    ) -> Result<Option<ScriptValue>, EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::NoSuchEntity(entity));
+        }
+        let tick = self.tick;
+        let old = self
+            .script_components
+            .entry(name.to_string())
+            .or_default()
+            .insert(entity, value, tick);
+        if old.is_none() {
+            self.fire(
+                ComponentKey::Script(name.to_string()),
+                HookEvent::Added,
+                entity,
+            );
+        }
+        Ok(old)
+    }
+
+    pub fn script_component(&self, entity: Entity, name: &str) -> Option<&ScriptValue> {
+        self.script_components.get(name)?.get(entity)
+    }
+
+    pub fn remove_script_component(&mut self, entity: Entity, name: &str) -> Option<ScriptValue> {
+        let removed = self.script_components.get_mut(name)?.remove(entity)?;
+        self.fire(
+            ComponentKey::Script(name.to_string()),
+            HookEvent::Removed,
+            entity,
+        );
+        Some(removed)
+    }
+
+    pub fn script_component_names(&self) -> impl Iterator<Item = &str> {
+        self.script_components.keys().map(String::as_str)
+    }
+
+    pub fn script_components(&self, name: &str) -> Option<&SparseSet<ScriptValue>> {
+        self.script_components.get(name)
+    }
+
+    /// Exposes a Rust component to scripts under `name`.
+    pub fn register_component<T>(&mut self, name: &str)
+    where
+        T: Component + Serialize + DeserializeOwned,
+    {
+        self.registry.register::<T>(name);
+        self.query_cache.clear();
+    }
+
+    pub fn set_schema(&mut self, name: &str, schema: ComponentSchema) {
+        self.registry.set_schema(name, schema);
+    }
+
+    pub fn registry(&self) -> &ComponentRegistry {
+        &self.registry
+    }
+
+    pub fn set_registry(&mut self, registry: ComponentRegistry) {
+        self.registry = registry;
+        self.query_cache.clear();
+    }
+
+    /// Names `entity`, returning the name assigned under the current policy.
+    pub fn set_name(
+        &mut self,
+        entity: Entity,
+        name: impl Into<String>,
+    ) -> Result<String, EcsError> {
+        if !self.is_alive(entity) {
+            return Err(EcsError::NoSuchEntity(entity));
+        }
+        self.names.set(entity, name.into())
+    }
+
+    pub fn name(&self, entity: Entity) -> Option<&str> {
+        self.names.get(entity)
+    }
+
+    pub fn remove_name(&mut self, entity: Entity) -> Option<String> {
+        self.names.remove(entity)
+    }
+
+    pub fn find(&self, name: &str) -> Option<Entity> {
+        self.names.find(name)
+    }
+
+    pub fn find_all(&self, name: &str) -> &[Entity] {
+        self.names.find_all(name)
+    }
+
+    pub fn names(&self) -> &NameIndex {
+        &self.names
+    }
+
+    /// Applies to names set from now on; existing duplicates are kept.
+    pub fn set_name_policy(&mut self, policy: NamePolicy) {
+        self.names.set_policy(policy);
+    }
+
+    fn fire(&mut self, key: ComponentKey, event: HookEvent, entity: Entity) {
+        self.hooks.fire(&mut self.commands, &key, event, entity);
+    }
+
+    pub fn add_hook(&mut self, key: ComponentKey, event: HookEvent, hook: Hook) {
+        self.hooks.add(key, event, hook);
+    }
+
+    /// Runs `hook` whenever `T` is added to an entity that didn't have it.
+    pub fn on_add<T: Component>(
+        &mut self,
+        hook: impl Fn(&mut World, Entity) -> mlua::Result<()> + 'static,
+    ) {
+        self.add_hook(
+            ComponentKey::of::<T>(),
+            HookEvent::Added,
+            Hook::Rust(Rc::new(hook)),
+        );
+    }
+
+    pub fn on_remove<T: Component>(
+        &mut self,
+        hook: impl Fn(&mut World, Entity) -> mlua::Result<()> + 'static,
+    ) {
+        self.add_hook(
+            ComponentKey::of::<T>(),
+            HookEvent::Removed,
+            Hook::Rust(Rc::new(hook)),
+        );
+    }
+
+    /// Called after every `clone_entity` with the old-to-new entity map, so
+    /// components holding entity references can be pointed at the copies.
+    pub fn on_clone(&mut self, fixup: CloneFixup) {
+        self.clone_fixups.push(fixup);
+    }
+
+    pub(crate) fn clone_fixups(&self) -> Vec<CloneFixup> {
+        self.clone_fixups.clone()
+    }
+
+    pub(crate) fn refs_mut(&mut self) -> &mut RefIndex {
+        &mut self.refs
+    }
+
+    pub fn commands(&mut self) -> &mut CommandBuffer {
+        &mut self.commands
+    }
+
+    /// Drains the command buffer, including commands queued by the commands
+    /// themselves.
+    pub fn apply_commands(&mut self, lua: &Lua) -> mlua::Result<()> {
+        loop {
+            let commands = self.commands.take();
+            if commands.is_empty() {
+                return Ok(());
+            }
+            for command in commands {
+                command(self, lua)?;
+            }
+        }
+    }
+
+    pub fn insert_resource<R: Any + Send + Sync>(&mut self, resource: R) -> Option<R> {
+        self.resources
+            .insert(TypeId::of::<R>(), Box::new(resource))
+            .and_then(|old| old.downcast().ok().map(|old| *old))
+    }
+
+    pub fn resource<R: Any + Send + Sync>(&self) -> Option<&R> {
+        self.resources.get(&TypeId::of::<R>())?.downcast_ref()
+    }
+
+    pub fn resource_mut<R: Any + Send + Sync>(&mut self) -> Option<&mut R> {
+        self.resources.get_mut(&TypeId::of::<R>())?.downcast_mut()
+    }
+
+    pub fn remove_resource<R: Any + Send + Sync>(&mut self) -> Option<R> {
+        self.resources
+            .remove(&TypeId::of::<R>())
+            .and_then(|old| old.downcast().ok().map(|old| *old))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_despawn_removes_components() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.insert(entity, 5u32).unwrap();
+        world
+            .set_script_component(entity, "Health", ScriptValue::Number(3.0))
+            .unwrap();
+
+        assert!(world.despawn(entity));
+        assert!(!world.has::<u32>(entity));
+        assert!(world.script_component(entity, "Health").is_none());
+        assert_eq!(
+            world.insert(entity, 1u32),
+            Err(EcsError::NoSuchEntity(entity))
+        );
+
+        let recycled = world.spawn();
+        assert!(world.get::<u32>(recycled).is_none());
+    }
+
+    #[test]
+    fn test_resources_are_typed() {
+        let mut world = World::new();
+        assert_eq!(world.insert_resource(1.5f64), None);
+        *world.resource_mut::<f64>().unwrap() += 1.0;
+        assert_eq!(world.resource::<f64>(), Some(&2.5));
+        assert_eq!(world.remove_resource::<f64>(), Some(2.5));
+        assert!(world.resource::<f64>().is_none());
+    }
+}
@@ -0,0 +1,342 @@
+use crate::console::Console;
+use crate::ecs::{Entity, ScriptValue, World};
+use crate::scene::{Scene, SceneEntity};
+use mlua::{Lua, LuaSerdeExt, Result, Value};
+use std::collections::HashMap;
+
+/// One reversible change to the world. Entities are stored as first
+/// recorded; `History` maps them to whatever entity currently stands in
+/// for them after an undo respawned them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditOp {
+    Spawn {
+        entity: Entity,
+        components: SceneEntity,
+    },
+    Despawn {
+        entity: Entity,
+        components: SceneEntity,
+    },
+    Set {
+        entity: Entity,
+        component: String,
+        before: Option<ScriptValue>,
+        after: Option<ScriptValue>,
+    },
+}
+
+/// Undo/redo stack for editor changes, kept as a world resource. Changes
+/// made through `edit::spawn`, `edit::despawn` and `edit::set` are
+/// recorded while `edit_mode` is on; components go through the registry,
+/// so anything a scene can hold can be undone.
+#[derive(Debug, Clone)]
+pub struct History {
+    pub edit_mode: bool,
+    /// Oldest undo entries are dropped past this many.
+    pub limit: usize,
+    undo: Vec<EditOp>,
+    redo: Vec<EditOp>,
+    /// Recorded entity to the live entity standing in for it.
+    live: HashMap<Entity, Entity>,
+    recorded: HashMap<Entity, Entity>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            edit_mode: true,
+            limit: 256,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            live: HashMap::new(),
+            recorded: HashMap::new(),
+        }
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.live.clear();
+        self.recorded.clear();
+    }
+
+    /// Pushes an operation that was already applied, dropping the redo
+    /// stack.
+    pub fn record(&mut self, mut op: EditOp) {
+        if !self.edit_mode {
+            return;
+        }
+        let entity = match &mut op {
+            EditOp::Spawn { entity, .. }
+            | EditOp::Despawn { entity, .. }
+            | EditOp::Set { entity, .. } => entity,
+        };
+        *entity = self.recorded.get(entity).copied().unwrap_or(*entity);
+        self.undo.push(op);
+        if self.undo.len() > self.limit.max(1) {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    fn resolve(&self, entity: Entity) -> Entity {
+        self.live.get(&entity).copied().unwrap_or(entity)
+    }
+
+    fn respawn(
+        &mut self,
+        world: &mut World,
+        lua: &Lua,
+        entity: Entity,
+        components: &SceneEntity,
+    ) -> Result<()> {
+        let spawned = Scene {
+            entities: vec![components.clone()],
+        }
+        .spawn(world, lua)?[0];
+        if let Some(old) = self.live.insert(entity, spawned) {
+            self.recorded.remove(&old);
+        }
+        self.recorded.insert(spawned, entity);
+        Ok(())
+    }
+
+    fn apply(&mut self, world: &mut World, lua: &Lua, op: &EditOp, forward: bool) -> Result<()> {
+        match (op, forward) {
+            (EditOp::Spawn { entity, components }, true)
+            | (EditOp::Despawn { entity, components }, false) => {
+                self.respawn(world, lua, *entity, components)
+            }
+            (EditOp::Spawn { entity, .. }, false) | (EditOp::Despawn { entity, .. }, true) => {
+                world.despawn(self.resolve(*entity));
+                Ok(())
+            }
+            (
+                EditOp::Set {
+                    entity,
+                    component,
+                    before,
+                    after,
+                },
+                forward,
+            ) => {
+                let value = if forward { after } else { before };
+                let value = match value {
+                    Some(value) => lua.to_value(value)?,
+                    None => Value::Nil,
+                };
+                world.set_by_name(lua, self.resolve(*entity), component, value)
+            }
+        }
+    }
+
+    /// Reverts the latest change. Returns false with nothing to undo.
+    pub fn undo(&mut self, world: &mut World, lua: &Lua) -> Result<bool> {
+        let Some(op) = self.undo.pop() else {
+            return Ok(false);
+        };
+        if let Err(e) = self.apply(world, lua, &op, false) {
+            self.undo.push(op);
+            return Err(e);
+        }
+        self.redo.push(op);
+        Ok(true)
+    }
+
+    pub fn redo(&mut self, world: &mut World, lua: &Lua) -> Result<bool> {
+        let Some(op) = self.redo.pop() else {
+            return Ok(false);
+        };
+        if let Err(e) = self.apply(world, lua, &op, true) {
+            self.redo.push(op);
+            return Err(e);
+        }
+        self.undo.push(op);
+        Ok(true)
+    }
+}
+
+fn record(world: &mut World, op: EditOp) {
+    if let Some(history) = world.resource_mut::<History>() {
+        history.record(op);
+    }
+}
+
+/// Spawns an entity from named components, recording it in the world's
+/// `History`.
+pub fn spawn(world: &mut World, lua: &Lua, components: SceneEntity) -> Result<Entity> {
+    let entity = Scene {
+        entities: vec![components.clone()],
+    }
+    .spawn(world, lua)?[0];
+    record(world, EditOp::Spawn { entity, components });
+    Ok(entity)
+}
+
+pub fn despawn(world: &mut World, lua: &Lua, entity: Entity) -> Result<bool> {
+    if !world.is_alive(entity) {
+        return Ok(false);
+    }
+    let components = Scene::capture_entity(world, lua, entity)?;
+    world.despawn(entity);
+    record(world, EditOp::Despawn { entity, components });
+    Ok(true)
+}
+
+/// Sets a component by name; `None` removes it.
+pub fn set(
+    world: &mut World,
+    lua: &Lua,
+    entity: Entity,
+    component: &str,
+    value: Option<ScriptValue>,
+) -> Result<()> {
+    let before = lua.from_value(world.get_by_name(lua, entity, component)?)?;
+    let lua_value = match &value {
+        Some(value) => lua.to_value(value)?,
+        None => Value::Nil,
+    };
+    world.set_by_name(lua, entity, component, lua_value)?;
+    record(
+        world,
+        EditOp::Set {
+            entity,
+            component: component.to_string(),
+            before,
+            after: value,
+        },
+    );
+    Ok(())
+}
+
+/// Runs `undo` or `redo` on the world's `History`, if it has one.
+pub fn undo(world: &mut World, lua: &Lua) -> Result<bool> {
+    with_history(world, |history, world| history.undo(world, lua))
+}
+
+pub fn redo(world: &mut World, lua: &Lua) -> Result<bool> {
+    with_history(world, |history, world| history.redo(world, lua))
+}
+
+fn with_history(
+    world: &mut World,
+    f: impl FnOnce(&mut History, &mut World) -> Result<bool>,
+) -> Result<bool> {
+    let Some(mut history) = world.remove_resource::<History>() else {
+        return Ok(false);
+    };
+    let result = f(&mut history, world);
+    world.insert_resource(history);
+    result
+}
+
+/// Adds the `undo` and `redo` console commands.
+pub fn register_console(console: &Console) {
+    console.register_rust("undo", "Revert the last edit", |world, lua, _| {
+        let done = undo(world, lua)?;
+        Ok(Some(if done { "undone" } else { "nothing to undo" }.into()))
+    });
+    console.register_rust("redo", "Reapply the last undone edit", |world, lua, _| {
+        let done = redo(world, lua)?;
+        Ok(Some(if done { "redone" } else { "nothing to redo" }.into()))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Health {
+        hp: f64,
+    }
+
+    #[test]
+    fn test_undo_redo_spawn_set_despawn() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.register_component::<Health>("Health");
+        world.insert_resource(History::default());
+
+        let mut components = SceneEntity::new();
+        components.insert("Tag".to_string(), ScriptValue::String("crate".to_string()));
+        let entity = spawn(&mut world, &lua, components)?;
+        set(
+            &mut world,
+            &lua,
+            entity,
+            "Health",
+            Some(lua.from_value(lua.to_value(&Health { hp: 5.0 })?)?),
+        )?;
+        despawn(&mut world, &lua, entity)?;
+        assert_eq!(world.len(), 0);
+
+        // Undoing the despawn brings back a new entity with both components.
+        assert!(undo(&mut world, &lua)?);
+        let revived = world.entities().next().unwrap();
+        assert_eq!(
+            world.get::<Health>(revived).as_deref(),
+            Some(&Health { hp: 5.0 })
+        );
+        assert!(undo(&mut world, &lua)?);
+        assert!(world.get::<Health>(revived).is_none());
+        assert!(undo(&mut world, &lua)?);
+        assert_eq!(world.len(), 0);
+        assert!(!undo(&mut world, &lua)?);
+
+        for _ in 0..3 {
+            assert!(redo(&mut world, &lua)?);
+        }
+        assert_eq!(world.len(), 0);
+        assert!(!redo(&mut world, &lua)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_console_and_lua_undo() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.insert_resource(History::new());
+        let entity = world.spawn();
+        set(
+            &mut world,
+            &lua,
+            entity,
+            "Tag",
+            Some(ScriptValue::Bool(true)),
+        )?;
+
+        let console = Console::new();
+        register_console(&console);
+        console.execute(&mut world, &lua, "undo")?;
+        assert_eq!(console.output().last().map(String::as_str), Some("undone"));
+        assert!(world.script_component(entity, "Tag").is_none());
+
+        let redone: bool = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load("local world = ... return world:redo()")
+                .call(handle)
+        })?;
+        assert!(redone);
+        assert_eq!(
+            world.script_component(entity, "Tag"),
+            Some(&ScriptValue::Bool(true))
+        );
+        Ok(())
+    }
+}
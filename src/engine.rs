@@ -0,0 +1,478 @@
+use crate::assets::AssetSource;
+use crate::ecs::{Component, ComponentRegistry, ComponentSchema, Schedule, World};
+use mlua::{AnyUserData, Error, FromLuaMulti, Function, Lua, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WorldId(u32);
+
+impl fmt::Display for WorldId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "world#{}", self.0)
+    }
+}
+
+/// Sent to every world when an exit is requested, so systems get one more
+/// frame to react before shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppExit {
+    pub code: i32,
+}
+
+/// A resource in every world of a headless engine, so systems that only
+/// matter with a window, like rendering and UI, can skip themselves.
+/// Scripts check it with `world:is_headless()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Headless;
+
+/// Removes every `--headless` from command-line arguments, returning
+/// whether there was one.
+pub fn take_headless_flag(args: &mut Vec<String>) -> bool {
+    let before = args.len();
+    args.retain(|arg| arg != "--headless");
+    args.len() != before
+}
+
+pub type ShutdownHook = Box<dyn FnOnce(&mut Engine) -> Result<()>>;
+
+/// Shared with the `engine` Lua table so scripts can request exit too.
+#[derive(Default)]
+struct ShutdownState {
+    requested: Option<AppExit>,
+    /// The exit has been announced to the worlds.
+    announced: bool,
+    lua_hooks: Vec<Function>,
+}
+
+struct WorldSlot {
+    name: String,
+    world: World,
+    schedule: Schedule,
+}
+
+/// Owns the Lua state and any number of independent worlds, each driven by
+/// its own schedule.
+pub struct Engine {
+    lua: Lua,
+    registry: ComponentRegistry,
+    worlds: BTreeMap<WorldId, WorldSlot>,
+    next_world: u32,
+    shutdown: Rc<RefCell<ShutdownState>>,
+    shutdown_hooks: Vec<ShutdownHook>,
+    headless: bool,
+}
+
+impl Engine {
+    pub fn new() -> Result<Self> {
+        let lua = Lua::new();
+        crate::register(&lua)?;
+        let shutdown = Rc::new(RefCell::new(ShutdownState::default()));
+
+        let table = lua.create_table()?;
+        let state = shutdown.clone();
+        table.set(
+            "request_exit",
+            lua.create_function(move |_, code: Option<i32>| {
+                let mut state = state.borrow_mut();
+                state.requested.get_or_insert(AppExit {
+                    code: code.unwrap_or(0),
+                });
+                Ok(())
+            })?,
+        )?;
+        let state = shutdown.clone();
+        table.set(
+            "on_shutdown",
+            lua.create_function(move |_, hook: Function| {
+                state.borrow_mut().lua_hooks.push(hook);
+                Ok(())
+            })?,
+        )?;
+        lua.globals().set("engine", table)?;
+
+        Ok(Engine {
+            lua,
+            registry: ComponentRegistry::default(),
+            worlds: BTreeMap::new(),
+            next_world: 0,
+            shutdown,
+            shutdown_hooks: Vec::new(),
+            headless: !cfg!(feature = "client"),
+        })
+    }
+
+    pub fn lua(&self) -> &Lua {
+        &self.lua
+    }
+
+    /// Exposes a Rust component to scripts in every world, existing and future.
+    pub fn register_component<T>(&mut self, name: &str)
+    where
+        T: Component + Serialize + DeserializeOwned,
+    {
+        self.registry.register::<T>(name);
+        for slot in self.worlds.values_mut() {
+            slot.world.register_component::<T>(name);
+        }
+    }
+
+    pub fn set_schema(&mut self, name: &str, schema: ComponentSchema) {
+        self.registry.set_schema(name, schema.clone());
+        for slot in self.worlds.values_mut() {
+            slot.world.set_schema(name, schema.clone());
+        }
+    }
+
+    /// Builds without the `client` feature are always headless.
+    pub fn set_headless(&mut self, headless: bool) {
+        self.headless = headless || !cfg!(feature = "client");
+        for slot in self.worlds.values_mut() {
+            set_headless(&mut slot.world, self.headless);
+        }
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.headless
+    }
+
+    pub fn create_world(&mut self, name: &str) -> WorldId {
+        let id = WorldId(self.next_world);
+        self.next_world += 1;
+
+        let mut world = World::new();
+        world.set_registry(self.registry.clone());
+        set_headless(&mut world, self.headless);
+        self.worlds.insert(
+            id,
+            WorldSlot {
+                name: name.to_string(),
+                world,
+                schedule: Schedule::new(),
+            },
+        );
+        id
+    }
+
+    pub fn destroy_world(&mut self, id: WorldId) -> Option<World> {
+        self.worlds.remove(&id).map(|slot| slot.world)
+    }
+
+    pub fn find_world(&self, name: &str) -> Option<WorldId> {
+        self.worlds
+            .iter()
+            .find(|(_, slot)| slot.name == name)
+            .map(|(id, _)| *id)
+    }
+
+    pub fn world_ids(&self) -> impl Iterator<Item = WorldId> + '_ {
+        self.worlds.keys().copied()
+    }
+
+    pub fn world_name(&self, id: WorldId) -> Option<&str> {
+        self.worlds.get(&id).map(|slot| slot.name.as_str())
+    }
+
+    pub fn world(&self, id: WorldId) -> Option<&World> {
+        self.worlds.get(&id).map(|slot| &slot.world)
+    }
+
+    pub fn world_mut(&mut self, id: WorldId) -> Option<&mut World> {
+        self.worlds.get_mut(&id).map(|slot| &mut slot.world)
+    }
+
+    pub fn schedule_mut(&mut self, id: WorldId) -> Option<&mut Schedule> {
+        self.worlds.get_mut(&id).map(|slot| &mut slot.schedule)
+    }
+
+    fn slot_mut(&mut self, id: WorldId) -> Result<&mut WorldSlot> {
+        self.worlds
+            .get_mut(&id)
+            .ok_or_else(|| Error::RuntimeError(format!("{} does not exist", id)))
+    }
+
+    /// Runs one world's schedule against that world.
+    pub fn update_world(&mut self, id: WorldId) -> Result<()> {
+        let Engine { lua, worlds, .. } = self;
+        let slot = worlds
+            .get_mut(&id)
+            .ok_or_else(|| Error::RuntimeError(format!("{} does not exist", id)))?;
+        slot.schedule.run(&mut slot.world, lua)
+    }
+
+    /// Runs every world's schedule once, in creation order. A pending exit
+    /// request is first sent to every world as an `AppExit` event.
+    pub fn update(&mut self) -> Result<()> {
+        let exit = {
+            let mut state = self.shutdown.borrow_mut();
+            match state.requested {
+                Some(exit) if !state.announced => {
+                    state.announced = true;
+                    Some(exit)
+                }
+                _ => None,
+            }
+        };
+        if let Some(exit) = exit {
+            for slot in self.worlds.values_mut() {
+                slot.world.send_event(exit);
+            }
+        }
+
+        let ids: Vec<WorldId> = self.world_ids().collect();
+        for id in ids {
+            self.update_world(id)?;
+        }
+        Ok(())
+    }
+
+    /// Asks the engine to stop; the first request's code wins. Scripts can
+    /// do the same with `engine.request_exit(code)`.
+    pub fn request_exit(&mut self, code: i32) {
+        self.shutdown
+            .borrow_mut()
+            .requested
+            .get_or_insert(AppExit { code });
+    }
+
+    pub fn exit_requested(&self) -> Option<AppExit> {
+        self.shutdown.borrow().requested
+    }
+
+    /// Registers cleanup to run in `shutdown`, e.g. flushing saves or
+    /// closing network sessions. Hooks run in reverse registration order,
+    /// so later plugins clean up before the ones they depend on.
+    pub fn on_shutdown(&mut self, hook: impl FnOnce(&mut Engine) -> Result<()> + 'static) {
+        self.shutdown_hooks.push(Box::new(hook));
+    }
+
+    /// Runs Rust shutdown hooks, then `engine.on_shutdown` Lua hooks, then
+    /// drops every world. Every hook runs even if an earlier one fails; the
+    /// first error is returned.
+    pub fn shutdown(&mut self) -> Result<()> {
+        let mut first_error = None;
+        let mut record = |result: Result<()>| {
+            if let Err(e) = result {
+                log::error!(target: "engine", "shutdown hook failed: {}", e);
+                first_error.get_or_insert(e);
+            }
+        };
+
+        while let Some(hook) = self.shutdown_hooks.pop() {
+            record(hook(self));
+        }
+        let lua_hooks = std::mem::take(&mut self.shutdown.borrow_mut().lua_hooks);
+        for hook in lua_hooks.into_iter().rev() {
+            record(hook.call::<()>(()));
+        }
+        self.worlds.clear();
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Updates until an exit is requested, gives the worlds one more frame
+    /// to see the `AppExit` event, shuts down and returns the exit code.
+    pub fn run(&mut self) -> Result<i32> {
+        while self.exit_requested().is_none() {
+            self.update()?;
+        }
+        self.update()?;
+        let code = self.exit_requested().map_or(0, |exit| exit.code);
+        self.shutdown()?;
+        Ok(code)
+    }
+
+    /// Lends a Lua handle for one world to `f`; the handle is invalidated
+    /// when `f` returns, after which queued commands are applied.
+    pub fn with_world<R>(
+        &mut self,
+        id: WorldId,
+        f: impl FnOnce(&Lua, AnyUserData) -> Result<R>,
+    ) -> Result<R> {
+        let lua = self.lua.clone();
+        let world = &mut self.slot_mut(id)?.world;
+        let result = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut *world)?;
+            f(&lua, handle)
+        })?;
+        world.apply_commands(&lua)?;
+        Ok(result)
+    }
+
+    /// Runs a chunk with the world handle passed as `...`.
+    pub fn run_script<R: FromLuaMulti>(&mut self, id: WorldId, source: &str) -> Result<R> {
+        self.with_world(id, |lua, world| lua.load(source).call(world))
+    }
+
+    /// `run_script` for the script at `path` in `assets`, named after it
+    /// in tracebacks.
+    pub fn run_script_asset<R: FromLuaMulti>(
+        &mut self,
+        id: WorldId,
+        assets: &dyn AssetSource,
+        path: &str,
+    ) -> Result<R> {
+        let source = assets.read(path)?;
+        self.with_world(id, |lua, world| {
+            lua.load(source).set_name(format!("@{}", path)).call(world)
+        })
+    }
+}
+
+fn set_headless(world: &mut World, headless: bool) {
+    if headless {
+        world.insert_resource(Headless);
+    } else {
+        world.remove_resource::<Headless>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Score(u32);
+
+    #[test]
+    fn test_worlds_are_independent() -> Result<()> {
+        let mut engine = Engine::new()?;
+        engine.register_component::<Score>("Score");
+        let game = engine.create_world("game");
+        let ui = engine.create_world("ui");
+
+        engine.run_script::<()>(game, "local world = ... world:spawn({ Score = 5 })")?;
+        assert_eq!(engine.world(game).unwrap().len(), 1);
+        assert_eq!(engine.world(ui).unwrap().len(), 0);
+
+        let score_system = engine
+            .lua()
+            .load(
+                r#"
+                return function(world)
+                    for _, e in world:entities() do
+                        world:set(e, "Score", world:get(e, "Score") + 1)
+                    end
+                end
+            "#,
+            )
+            .eval()?;
+        engine
+            .schedule_mut(game)
+            .unwrap()
+            .add_lua_system("score", score_system);
+        engine
+            .schedule_mut(ui)
+            .unwrap()
+            .add_system("spawn", |world| {
+                world.spawn();
+                Ok(())
+            });
+
+        engine.update()?;
+        engine.update()?;
+
+        let world = engine.world(game).unwrap();
+        let entity = world.entities().next().unwrap();
+        assert_eq!(*world.get::<Score>(entity).unwrap(), Score(7));
+        assert_eq!(engine.world(ui).unwrap().len(), 2);
+        assert_eq!(engine.find_world("ui"), Some(ui));
+
+        assert!(engine.destroy_world(ui).is_some());
+        assert!(engine.update_world(ui).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_exit_runs_shutdown_hooks_in_reverse() -> Result<()> {
+        let mut engine = Engine::new()?;
+        let id = engine.create_world("main");
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        for name in ["saves", "network"] {
+            let log = log.clone();
+            engine.on_shutdown(move |engine| {
+                assert!(engine.world(id).is_some());
+                log.borrow_mut().push(name.to_string());
+                Ok(())
+            });
+        }
+        let seen = log.clone();
+        engine
+            .schedule_mut(id)
+            .unwrap()
+            .add_system("quit", move |world| {
+                if world.tick() == 2 {
+                    world.spawn();
+                }
+                if !world.drain_events::<AppExit>().is_empty() {
+                    seen.borrow_mut()
+                        .push(format!("exit at tick {}", world.tick()));
+                }
+                Ok(())
+            });
+        engine.run_script::<()>(
+            id,
+            r#"
+            local world = ...
+            engine.on_shutdown(function() shut_down = true end)
+        "#,
+        )?;
+        let quit = engine
+            .lua()
+            .load("return function(world) if world:len() > 0 then engine.request_exit(3) end end")
+            .eval()?;
+        engine
+            .schedule_mut(id)
+            .unwrap()
+            .add_lua_system("quit_from_lua", quit);
+
+        assert_eq!(engine.run()?, 3);
+        assert_eq!(*log.borrow(), vec!["exit at tick 3", "network", "saves"]);
+        assert!(engine.lua().globals().get::<bool>("shut_down")?);
+        assert!(engine.world(id).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_world_handle_expires_after_call() -> Result<()> {
+        let mut engine = Engine::new()?;
+        let id = engine.create_world("main");
+
+        engine.run_script::<()>(id, "stashed = ...")?;
+        let result: Result<()> = engine.lua().load("stashed:spawn()").exec();
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_headless_reaches_every_world() -> Result<()> {
+        let mut args = vec!["--headless".to_string(), "level.ron".to_string()];
+        assert!(take_headless_flag(&mut args));
+        assert_eq!(args, ["level.ron"]);
+
+        let mut engine = Engine::new()?;
+        let before = engine.create_world("before");
+        engine.set_headless(true);
+        let after = engine.create_world("after");
+        for id in [before, after] {
+            assert!(engine.run_script::<bool>(id, "return (...):is_headless()")?);
+        }
+        engine.set_headless(false);
+        assert_eq!(
+            engine.run_script::<bool>(after, "return (...):is_headless()")?,
+            !cfg!(feature = "client")
+        );
+        Ok(())
+    }
+}
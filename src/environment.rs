@@ -0,0 +1,456 @@
+//! Time of day, ambient values and weather as one world resource. The
+//! `Environment` is ticked by `environment_system`, announces sunrise,
+//! sunset, new days and weather changes as `EnvironmentEvent`s, and is
+//! sampled by whatever draws or plays the world, e.g. `ambient("light")`.
+
+use crate::curve::Curve;
+use crate::data::{self, DataError};
+use crate::ecs::World;
+use crate::rng::GameRng;
+use crate::time::FixedTimestep;
+use mlua::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Script event every `EnvironmentEvent` is also sent under, as a table
+/// with a `kind` field.
+pub const ENVIRONMENT_EVENT: &str = "environment";
+
+fn default_day_length() -> f64 {
+    600.0
+}
+
+fn default_duration() -> (f64, f64) {
+    (120.0, 300.0)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WeatherDef {
+    /// Seconds the weather lasts, picked between the two.
+    #[serde(default = "default_duration")]
+    pub duration: (f64, f64),
+    /// Weights of what comes next; empty keeps the weather.
+    #[serde(default)]
+    pub next: BTreeMap<String, f64>,
+    /// Multiplies ambient values, e.g. `{ "light": 0.6 }` under rain.
+    #[serde(default)]
+    pub scale: BTreeMap<String, f64>,
+    /// Added after scaling, for values only weather has, e.g. `"rain"`.
+    #[serde(default)]
+    pub add: BTreeMap<String, f64>,
+}
+
+/// Loaded from RON such as
+/// `(day_length: 1200, ambient: { "light": (keys: [(time: 0, value: 0.1), (time: 12, value: 1)]) },
+///   weather: { "clear": (next: { "rain": 1 }), "rain": (scale: { "light": 0.6 }, next: { "clear": 1 }) })`.
+/// Hours run from 0 to 24.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentConfig {
+    /// Real seconds in a game day.
+    #[serde(default = "default_day_length")]
+    pub day_length: f64,
+    pub start_hour: f64,
+    pub sunrise: f64,
+    pub sunset: f64,
+    /// Ambient values by hour.
+    pub ambient: BTreeMap<String, Curve>,
+    pub weather: BTreeMap<String, WeatherDef>,
+    /// Defaults to the first weather by name.
+    pub start_weather: Option<String>,
+    /// Seconds one weather blends into the next.
+    pub transition: f64,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        EnvironmentConfig {
+            day_length: default_day_length(),
+            start_hour: 8.0,
+            sunrise: 6.0,
+            sunset: 18.0,
+            ambient: BTreeMap::new(),
+            weather: BTreeMap::new(),
+            start_weather: None,
+            transition: 10.0,
+        }
+    }
+}
+
+impl EnvironmentConfig {
+    pub fn from_ron(source: &str) -> std::result::Result<Self, DataError> {
+        let config: EnvironmentConfig = data::from_ron(source)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        EnvironmentConfig::from_ron(&std::fs::read_to_string(path)?)
+    }
+
+    /// Checks the day length and that every weather leads somewhere known.
+    pub fn validate(&self) -> std::result::Result<(), DataError> {
+        if self.day_length <= 0.0 {
+            return Err(DataError::Invalid(
+                "day_length must be positive".to_string(),
+            ));
+        }
+        let known = |name: &String| self.weather.contains_key(name);
+        for (name, def) in &self.weather {
+            if let Some(next) = def.next.keys().find(|next| !known(next)) {
+                return Err(DataError::Invalid(format!(
+                    "weather '{}' leads to missing weather '{}'",
+                    name, next
+                )));
+            }
+        }
+        match &self.start_weather {
+            Some(start) if !known(start) => Err(DataError::Invalid(format!(
+                "no weather named '{}' to start with",
+                start
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DayPhase {
+    Day,
+    Night,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EnvironmentEvent {
+    SunriseStarted {
+        day: u32,
+    },
+    SunsetStarted {
+        day: u32,
+    },
+    /// Midnight passed.
+    DayStarted {
+        day: u32,
+    },
+    WeatherChanged {
+        from: String,
+        to: String,
+    },
+}
+
+/// What `world:environment()` returns to scripts.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EnvironmentSnapshot {
+    pub day: u32,
+    pub hour: f64,
+    pub phase: DayPhase,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weather: Option<String>,
+    pub ambient: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Environment {
+    config: EnvironmentConfig,
+    day: u32,
+    hour: f64,
+    weather: Option<String>,
+    /// The weather blending out, and how far the blend has come from 0 to 1.
+    previous: Option<(String, f64)>,
+    weather_left: f64,
+    rng: GameRng,
+}
+
+impl Environment {
+    pub fn new(config: EnvironmentConfig, seed: u64) -> Self {
+        let weather = config
+            .start_weather
+            .clone()
+            .or_else(|| config.weather.keys().next().cloned());
+        let mut environment = Environment {
+            hour: config.start_hour.rem_euclid(24.0),
+            config,
+            day: 0,
+            weather,
+            previous: None,
+            weather_left: 0.0,
+            rng: GameRng::new(seed),
+        };
+        environment.weather_left = environment.roll_duration();
+        environment
+    }
+
+    pub fn config(&self) -> &EnvironmentConfig {
+        &self.config
+    }
+
+    pub fn day(&self) -> u32 {
+        self.day
+    }
+
+    pub fn hour(&self) -> f64 {
+        self.hour
+    }
+
+    /// Moves the clock without announcing what it skips.
+    pub fn set_hour(&mut self, hour: f64) {
+        self.hour = hour.rem_euclid(24.0);
+    }
+
+    pub fn phase(&self) -> DayPhase {
+        let (sunrise, sunset) = (self.config.sunrise, self.config.sunset);
+        let day = match sunrise <= sunset {
+            true => self.hour >= sunrise && self.hour < sunset,
+            false => self.hour >= sunrise || self.hour < sunset,
+        };
+        if day { DayPhase::Day } else { DayPhase::Night }
+    }
+
+    pub fn weather(&self) -> Option<&str> {
+        self.weather.as_deref()
+    }
+
+    /// Blends into `name` over the configured transition and gives it a
+    /// fresh duration.
+    pub fn set_weather(&mut self, name: &str) -> Result<Option<EnvironmentEvent>> {
+        if !self.config.weather.contains_key(name) {
+            return Err(Error::runtime(format!("no weather named '{}'", name)));
+        }
+        Ok(self.change_weather(name.to_string()))
+    }
+
+    fn change_weather(&mut self, to: String) -> Option<EnvironmentEvent> {
+        self.weather_left = self.roll_duration_of(&to);
+        let from = self.weather.replace(to.clone())?;
+        if from == to {
+            return None;
+        }
+        self.previous = Some((from.clone(), 0.0));
+        Some(EnvironmentEvent::WeatherChanged { from, to })
+    }
+
+    fn roll_duration(&mut self) -> f64 {
+        match self.weather.clone() {
+            Some(weather) => self.roll_duration_of(&weather),
+            None => f64::INFINITY,
+        }
+    }
+
+    fn roll_duration_of(&mut self, weather: &str) -> f64 {
+        let (min, max) = self
+            .config
+            .weather
+            .get(weather)
+            .map_or((f64::INFINITY, f64::INFINITY), |def| def.duration);
+        match max > min {
+            true => self.rng.range(min, max),
+            false => min,
+        }
+    }
+
+    /// The ambient value now: its curve at this hour, changed by the
+    /// weather and blended across a weather change. Unknown names are 0.
+    pub fn ambient(&self, name: &str) -> f64 {
+        let base = self
+            .config
+            .ambient
+            .get(name)
+            .map_or(0.0, |curve| curve.sample(self.hour));
+        let under = |weather: Option<&str>| {
+            let def = weather.and_then(|weather| self.config.weather.get(weather));
+            let scale = def.and_then(|def| def.scale.get(name)).copied();
+            let add = def.and_then(|def| def.add.get(name)).copied();
+            base * scale.unwrap_or(1.0) + add.unwrap_or(0.0)
+        };
+        let now = under(self.weather());
+        match &self.previous {
+            Some((previous, blend)) => {
+                let before = under(Some(previous));
+                before + (now - before) * blend
+            }
+            None => now,
+        }
+    }
+
+    /// Every ambient value any curve or weather names.
+    pub fn ambient_values(&self) -> BTreeMap<String, f64> {
+        let weather_names = self
+            .config
+            .weather
+            .values()
+            .flat_map(|def| def.scale.keys().chain(def.add.keys()));
+        self.config
+            .ambient
+            .keys()
+            .chain(weather_names)
+            .map(|name| (name.clone(), self.ambient(name)))
+            .collect()
+    }
+
+    pub fn snapshot(&self) -> EnvironmentSnapshot {
+        EnvironmentSnapshot {
+            day: self.day,
+            hour: self.hour,
+            phase: self.phase(),
+            weather: self.weather.clone(),
+            ambient: self.ambient_values(),
+        }
+    }
+
+    /// Advances the clock and weather by `dt` seconds, returning what
+    /// happened in order.
+    pub fn update(&mut self, dt: f64) -> Vec<EnvironmentEvent> {
+        let mut crossed = Vec::new();
+        let to = self.hour + dt * 24.0 / self.config.day_length;
+        let marks = [(self.config.sunrise, 0), (self.config.sunset, 1), (24.0, 2)];
+        for (mark, which) in marks {
+            let mark = mark.rem_euclid(24.0);
+            for at in [mark, mark + 24.0] {
+                if at > self.hour && at <= to {
+                    crossed.push((at, which));
+                }
+            }
+        }
+        crossed.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut events = Vec::new();
+        for (at, which) in crossed {
+            let day = self.day + (at >= 24.0) as u32;
+            events.push(match which {
+                0 => EnvironmentEvent::SunriseStarted { day },
+                1 => EnvironmentEvent::SunsetStarted { day },
+                _ => EnvironmentEvent::DayStarted { day },
+            });
+        }
+        self.day += (to / 24.0).floor() as u32;
+        self.hour = to.rem_euclid(24.0);
+
+        if let Some((_, blend)) = &mut self.previous {
+            *blend += match self.config.transition > 0.0 {
+                true => dt / self.config.transition,
+                false => 1.0,
+            };
+            if *blend >= 1.0 {
+                self.previous = None;
+            }
+        }
+        self.weather_left -= dt;
+        if self.weather_left <= 0.0
+            && let Some(current) = self.weather.clone()
+            && let Some(def) = self.config.weather.get(&current)
+        {
+            let next: Vec<(&String, &f64)> = def.next.iter().collect();
+            let weights: Vec<f64> = next.iter().map(|(_, weight)| **weight).collect();
+            let to = self.rng.weighted(&weights).map(|i| next[i].0.clone());
+            events.extend(self.change_weather(to.unwrap_or(current)));
+        }
+        events
+    }
+}
+
+/// Advances the world's `Environment`, if it has one, and sends what
+/// happened as events.
+pub fn update_environment(world: &mut World, dt: f64) -> Result<()> {
+    let events = match world.resource_mut::<Environment>() {
+        Some(environment) => environment.update(dt),
+        None => return Ok(()),
+    };
+    for event in events {
+        world.emit(ENVIRONMENT_EVENT, event)?;
+    }
+    Ok(())
+}
+
+/// Runs `update_environment` each schedule run with the world's
+/// `FixedTimestep` step as `dt`, so the clock keeps pace with physics.
+pub fn environment_system() -> impl FnMut(&mut World) -> Result<()> {
+    |world| {
+        let dt = world
+            .resource::<FixedTimestep>()
+            .map_or(0.0, |fixed| fixed.step);
+        update_environment(world, dt)
+    }
+}
+
+/// Changes the weather through `world:set_weather(name)`.
+pub fn set_weather(world: &mut World, name: &str) -> Result<()> {
+    let environment = world
+        .resource_mut::<Environment>()
+        .ok_or_else(|| Error::runtime("the world has no environment"))?;
+    if let Some(event) = environment.set_weather(name)? {
+        world.emit(ENVIRONMENT_EVENT, event)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"(
+        day_length: 24,
+        start_hour: 5,
+        ambient: { "light": (keys: [(time: 0, value: 0), (time: 12, value: 1), (time: 24, value: 0)]) },
+        weather: {
+            "clear": (duration: (3, 3), next: { "rain": 1 }),
+            "rain": (duration: (100, 100), scale: { "light": 0.5 }, add: { "rain": 1 }, next: { "clear": 1 }),
+        },
+        transition: 2,
+    )"#;
+
+    #[test]
+    fn test_day_cycle_weather_and_events() -> Result<()> {
+        let config = EnvironmentConfig::from_ron(CONFIG)?;
+        assert!(EnvironmentConfig::from_ron("(weather: { \"a\": (next: { \"b\": 1 }) })").is_err());
+        let mut world = World::new();
+        world.insert_resource(Environment::new(config, 1));
+        let environment = |world: &World| world.resource::<Environment>().unwrap().clone();
+        assert_eq!(environment(&world).phase(), DayPhase::Night);
+
+        // An hour a second: sunrise at 6, the weather turns at 8.
+        update_environment(&mut world, 1.0)?;
+        assert_eq!(environment(&world).phase(), DayPhase::Day);
+        update_environment(&mut world, 2.0)?;
+        assert_eq!(environment(&world).weather(), Some("rain"));
+        // Half way into rain at 9: light 0.75 blending from 1x to 0.5x.
+        update_environment(&mut world, 1.0)?;
+        assert_eq!(environment(&world).ambient("light"), 0.75 * 0.75);
+        assert_eq!(environment(&world).ambient("rain"), 0.5);
+        update_environment(&mut world, 16.0)?;
+        assert_eq!(environment(&world).day(), 1);
+        assert_eq!(
+            world.drain_events::<EnvironmentEvent>(),
+            [
+                EnvironmentEvent::SunriseStarted { day: 0 },
+                EnvironmentEvent::WeatherChanged {
+                    from: "clear".to_string(),
+                    to: "rain".to_string()
+                },
+                EnvironmentEvent::SunsetStarted { day: 0 },
+                EnvironmentEvent::DayStarted { day: 1 },
+            ]
+        );
+
+        set_weather(&mut world, "clear")?;
+        assert!(set_weather(&mut world, "snow").is_err());
+        let lua = mlua::Lua::new();
+        let hour: f64 = lua.scope(|scope| {
+            let world = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world = ...
+                local environment = world:environment()
+                assert(environment.weather == "clear" and environment.phase == "night")
+                assert(environment.ambient.rain == 1 and environment.day == 1)
+                return environment.hour
+            "#,
+            )
+            .call(world)
+        })?;
+        assert_eq!(hour, 1.0);
+        assert_eq!(world.drain_script_events(ENVIRONMENT_EVENT).len(), 5);
+        Ok(())
+    }
+}
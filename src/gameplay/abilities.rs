@@ -0,0 +1,496 @@
+use super::{Stats, set_base_stat};
+use crate::ecs::{Entity, World};
+use mlua::{Lua, Result, UserData, UserDataMethods};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// One ability an entity knows. Times are seconds; a zero `cast_time`
+/// casts on the spot and a non-zero `channel` keeps it going afterwards,
+/// ticking every `tick` seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ability {
+    #[serde(default)]
+    pub cast_time: f64,
+    #[serde(default)]
+    pub cooldown: f64,
+    #[serde(default)]
+    pub channel: f64,
+    #[serde(default)]
+    pub tick: f64,
+    /// Paid out of this stat's base when the cast completes, e.g. "mana".
+    #[serde(default)]
+    pub resource: Option<String>,
+    #[serde(default)]
+    pub cost: f64,
+    /// Cooldown left.
+    #[serde(default)]
+    pub ready_in: f64,
+}
+
+impl Ability {
+    pub fn instant(cooldown: f64) -> Self {
+        Ability {
+            cast_time: 0.0,
+            cooldown,
+            channel: 0.0,
+            tick: 0.0,
+            resource: None,
+            cost: 0.0,
+            ready_in: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum CastState {
+    #[default]
+    Idle,
+    Casting {
+        ability: String,
+        elapsed: f64,
+    },
+    Channeling {
+        ability: String,
+        elapsed: f64,
+        ticks: u32,
+    },
+}
+
+/// Known abilities and what the entity is doing with them. One cast or
+/// channel at a time.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Abilities {
+    pub known: BTreeMap<String, Ability>,
+    pub state: CastState,
+}
+
+impl Abilities {
+    pub fn with(mut self, name: &str, ability: Ability) -> Self {
+        self.known.insert(name.to_string(), ability);
+        self
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.state != CastState::Idle
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CastError {
+    Unknown(String),
+    OnCooldown(f64),
+    Busy,
+    /// Short of the named resource.
+    CantAfford(String),
+}
+
+impl fmt::Display for CastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CastError::Unknown(name) => write!(f, "unknown ability '{}'", name),
+            CastError::OnCooldown(left) => write!(f, "on cooldown for {:.2}s", left),
+            CastError::Busy => write!(f, "already casting"),
+            CastError::CantAfford(resource) => write!(f, "not enough {}", resource),
+        }
+    }
+}
+
+impl std::error::Error for CastError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AbilityEventKind {
+    /// A cast with a cast time began.
+    Started,
+    /// The cast went off: costs paid, cooldown running.
+    Cast,
+    ChannelTick,
+    ChannelEnded,
+    Interrupted,
+    /// A queued cast couldn't start.
+    Failed(CastError),
+}
+
+/// Sent for animation and audio to hook: a wind-up on `Started`, the
+/// effect on `Cast`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AbilityEvent {
+    pub entity: Entity,
+    pub ability: String,
+    pub kind: AbilityEventKind,
+}
+
+fn send(world: &mut World, entity: Entity, ability: &str, kind: AbilityEventKind) {
+    world.send_event(AbilityEvent {
+        entity,
+        ability: ability.to_string(),
+        kind,
+    });
+}
+
+/// The base value costs are checked against and paid from, so modifiers
+/// can't let through a cast the base can't pay for.
+fn resource_base(world: &World, entity: Entity, resource: &str) -> f64 {
+    world
+        .get::<Stats>(entity)
+        .map_or(0.0, |stats| stats.base(resource))
+}
+
+fn affordable(
+    world: &World,
+    entity: Entity,
+    ability: &Ability,
+) -> std::result::Result<(), CastError> {
+    match &ability.resource {
+        Some(resource) if resource_base(world, entity, resource) < ability.cost => {
+            Err(CastError::CantAfford(resource.clone()))
+        }
+        _ => Ok(()),
+    }
+}
+
+type QueuedEvent = (Entity, String, AbilityEventKind);
+
+/// Moves a channel along by `dt`, queuing the ticks it passes and its end.
+fn advance_channel(
+    entity: Entity,
+    abilities: &mut Abilities,
+    dt: f64,
+    events: &mut Vec<QueuedEvent>,
+) {
+    let CastState::Channeling {
+        ability,
+        elapsed,
+        ticks,
+    } = &mut abilities.state
+    else {
+        return;
+    };
+    let Some(def) = abilities.known.get(ability) else {
+        abilities.state = CastState::Idle;
+        return;
+    };
+    *elapsed += dt;
+    let due = if def.tick > 0.0 {
+        ((elapsed.min(def.channel) / def.tick).floor() as u32).saturating_sub(*ticks)
+    } else {
+        0
+    };
+    *ticks += due;
+    for _ in 0..due {
+        events.push((entity, ability.clone(), AbilityEventKind::ChannelTick));
+    }
+    if *elapsed >= def.channel {
+        events.push((entity, ability.clone(), AbilityEventKind::ChannelEnded));
+        abilities.state = CastState::Idle;
+    }
+}
+
+/// Pays for and fires the ability, then starts its channel if it has one.
+/// `late` is how long before the end of the update the cast finished, so
+/// the cooldown and channel start that far along.
+fn complete(world: &mut World, entity: Entity, name: &str, late: f64) {
+    let Some(ability) = world
+        .get::<Abilities>(entity)
+        .and_then(|a| a.known.get(name).cloned())
+    else {
+        return;
+    };
+    if affordable(world, entity, &ability).is_err() {
+        if let Some(mut abilities) = world.get_mut::<Abilities>(entity) {
+            abilities.state = CastState::Idle;
+        }
+        send(world, entity, name, AbilityEventKind::Interrupted);
+        return;
+    }
+    if let Some(resource) = &ability.resource {
+        let base = resource_base(world, entity, resource);
+        set_base_stat(world, entity, resource, base - ability.cost).expect("entity is alive");
+    }
+    let mut events = Vec::new();
+    if let Some(mut abilities) = world.get_mut::<Abilities>(entity) {
+        abilities.state = if ability.channel > 0.0 {
+            CastState::Channeling {
+                ability: name.to_string(),
+                elapsed: 0.0,
+                ticks: 0,
+            }
+        } else {
+            CastState::Idle
+        };
+        if let Some(known) = abilities.known.get_mut(name) {
+            known.ready_in = (ability.cooldown - late).max(0.0);
+        }
+        advance_channel(entity, &mut abilities, late, &mut events);
+    }
+    send(world, entity, name, AbilityEventKind::Cast);
+    for (entity, ability, kind) in events {
+        send(world, entity, &ability, kind);
+    }
+}
+
+/// Starts casting `name`, going off at once when it has no cast time.
+pub fn try_cast(
+    world: &mut World,
+    entity: Entity,
+    name: &str,
+) -> std::result::Result<(), CastError> {
+    let (ability, busy) = {
+        let abilities = world
+            .get::<Abilities>(entity)
+            .ok_or_else(|| CastError::Unknown(name.to_string()))?;
+        let ability = abilities
+            .known
+            .get(name)
+            .cloned()
+            .ok_or_else(|| CastError::Unknown(name.to_string()))?;
+        (ability, abilities.is_busy())
+    };
+    if busy {
+        return Err(CastError::Busy);
+    }
+    if ability.ready_in > 0.0 {
+        return Err(CastError::OnCooldown(ability.ready_in));
+    }
+    affordable(world, entity, &ability)?;
+    if ability.cast_time <= 0.0 {
+        complete(world, entity, name, 0.0);
+        return Ok(());
+    }
+    let mut abilities = world.get_mut::<Abilities>(entity).expect("read above");
+    abilities.state = CastState::Casting {
+        ability: name.to_string(),
+        elapsed: 0.0,
+    };
+    drop(abilities);
+    send(world, entity, name, AbilityEventKind::Started);
+    Ok(())
+}
+
+/// Stops a cast or channel; false when there was none.
+pub fn interrupt(world: &mut World, entity: Entity) -> bool {
+    let Some(mut abilities) = world.get_mut::<Abilities>(entity) else {
+        return false;
+    };
+    let name = match std::mem::take(&mut abilities.state) {
+        CastState::Idle => return false,
+        CastState::Casting { ability, .. } | CastState::Channeling { ability, .. } => ability,
+    };
+    drop(abilities);
+    send(world, entity, &name, AbilityEventKind::Interrupted);
+    true
+}
+
+/// Counts cooldowns down and moves casts and channels along by `dt`.
+pub fn update_abilities(world: &mut World, dt: f64) {
+    let mut finished = Vec::new();
+    let mut events = Vec::new();
+    world
+        .query::<&mut Abilities>()
+        .for_each(|entity, abilities| {
+            for ability in abilities.known.values_mut() {
+                ability.ready_in = (ability.ready_in - dt).max(0.0);
+            }
+            if let CastState::Casting { ability, elapsed } = &mut abilities.state {
+                *elapsed += dt;
+                let cast_time = abilities.known.get(ability).map_or(0.0, |a| a.cast_time);
+                if *elapsed >= cast_time {
+                    let late = (*elapsed - cast_time).min(dt);
+                    finished.push((entity, ability.clone(), late));
+                }
+            }
+            advance_channel(entity, abilities, dt, &mut events);
+        });
+    for (entity, ability, kind) in events {
+        send(world, entity, &ability, kind);
+    }
+    for (entity, ability, late) in finished {
+        complete(world, entity, &ability, late);
+    }
+}
+
+/// Queues casts from scripts as `abilities:try_cast(entity, "fireball")`
+/// and `abilities:interrupt(entity)`; `apply` carries them out, then
+/// ticks every `Abilities` by `dt`. Casts that can't start come back as
+/// `Failed` events. Clones share one queue.
+#[derive(Clone, Default)]
+pub struct AbilitySystem {
+    pending: Rc<RefCell<Vec<Request>>>,
+}
+
+enum Request {
+    Cast(Entity, String),
+    Interrupt(Entity),
+}
+
+impl AbilitySystem {
+    pub fn new() -> Self {
+        AbilitySystem::default()
+    }
+
+    pub fn apply(&self, world: &mut World, dt: f64) {
+        let pending = std::mem::take(&mut *self.pending.borrow_mut());
+        for request in pending {
+            match request {
+                Request::Cast(entity, name) => {
+                    if let Err(e) = try_cast(world, entity, &name) {
+                        send(world, entity, &name, AbilityEventKind::Failed(e));
+                    }
+                }
+                Request::Interrupt(entity) => {
+                    interrupt(world, entity);
+                }
+            }
+        }
+        update_abilities(world, dt);
+    }
+
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("abilities", self.clone())
+    }
+}
+
+impl UserData for AbilitySystem {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("try_cast", |_, this, (entity, name): (Entity, String)| {
+            this.pending.borrow_mut().push(Request::Cast(entity, name));
+            Ok(())
+        });
+        methods.add_method("interrupt", |_, this, entity: Entity| {
+            this.pending.borrow_mut().push(Request::Interrupt(entity));
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameplay::{Modifier, add_modifier, stat};
+
+    fn kinds(world: &mut World) -> Vec<AbilityEventKind> {
+        world
+            .drain_events::<AbilityEvent>()
+            .into_iter()
+            .map(|e| e.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_cast_channel_cooldown_and_cost() -> Result<()> {
+        let lua = Lua::new();
+        let system = AbilitySystem::new();
+        system.register_lua(&lua)?;
+        let mut world = World::new();
+        let mage = world.spawn();
+        let fireball = Ability {
+            cast_time: 1.0,
+            cooldown: 5.0,
+            channel: 2.0,
+            tick: 0.5,
+            resource: Some("mana".to_string()),
+            cost: 30.0,
+            ..Ability::instant(0.0)
+        };
+        world
+            .insert(mage, Abilities::default().with("fireball", fireball))
+            .unwrap();
+        world.insert(mage, Stats::new().with("mana", 50.0)).unwrap();
+
+        lua.load("local mage = ... abilities:try_cast(mage, 'fireball')")
+            .call::<()>(mage)?;
+        system.apply(&mut world, 0.0);
+        assert_eq!(kinds(&mut world), vec![AbilityEventKind::Started]);
+        system.apply(&mut world, 1.0);
+        assert_eq!(kinds(&mut world), vec![AbilityEventKind::Cast]);
+        assert_eq!(stat(&world, mage, "mana"), Some(20.0));
+
+        system.apply(&mut world, 1.2);
+        assert_eq!(kinds(&mut world), vec![AbilityEventKind::ChannelTick; 2]);
+        system.apply(&mut world, 1.0);
+        assert_eq!(
+            kinds(&mut world),
+            vec![
+                AbilityEventKind::ChannelTick,
+                AbilityEventKind::ChannelTick,
+                AbilityEventKind::ChannelEnded
+            ]
+        );
+
+        assert!(matches!(
+            try_cast(&mut world, mage, "fireball"),
+            Err(CastError::OnCooldown(_))
+        ));
+        system.apply(&mut world, 5.0);
+        assert_eq!(
+            try_cast(&mut world, mage, "fireball"),
+            Err(CastError::CantAfford("mana".to_string()))
+        );
+        world
+            .get_mut::<Stats>(mage)
+            .unwrap()
+            .base
+            .insert("mana".to_string(), 100.0);
+        try_cast(&mut world, mage, "fireball").unwrap();
+        assert_eq!(try_cast(&mut world, mage, "fireball"), Err(CastError::Busy));
+        lua.load("local mage = ... abilities:interrupt(mage)")
+            .call::<()>(mage)?;
+        system.apply(&mut world, 0.1);
+        assert_eq!(
+            kinds(&mut world),
+            vec![AbilityEventKind::Started, AbilityEventKind::Interrupted]
+        );
+        assert_eq!(stat(&world, mage, "mana"), Some(100.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_costs_use_base_and_overshoot_carries_over() {
+        let mut world = World::new();
+        let mage = world.spawn();
+        let beam = Ability {
+            cast_time: 1.0,
+            cooldown: 5.0,
+            channel: 2.0,
+            tick: 0.5,
+            resource: Some("mana".to_string()),
+            cost: 30.0,
+            ..Ability::instant(0.0)
+        };
+        world
+            .insert(mage, Abilities::default().with("beam", beam))
+            .unwrap();
+        world.insert(mage, Stats::new().with("mana", 20.0)).unwrap();
+
+        // A buff can't pay for what the base can't.
+        add_modifier(&mut world, mage, Modifier::add("mana", 50.0, "potion")).unwrap();
+        assert_eq!(
+            try_cast(&mut world, mage, "beam"),
+            Err(CastError::CantAfford("mana".to_string()))
+        );
+
+        set_base_stat(&mut world, mage, "mana", 40.0).unwrap();
+        try_cast(&mut world, mage, "beam").unwrap();
+        kinds(&mut world);
+        // Finishing half a second into the update leaves the channel and
+        // cooldown half a second along.
+        update_abilities(&mut world, 1.5);
+        assert_eq!(
+            kinds(&mut world),
+            vec![AbilityEventKind::Cast, AbilityEventKind::ChannelTick]
+        );
+        let abilities = world.get::<Abilities>(mage).unwrap();
+        assert_eq!(abilities.known["beam"].ready_in, 4.5);
+        assert!(matches!(
+            abilities.state,
+            CastState::Channeling {
+                elapsed: 0.5,
+                ticks: 1,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,469 @@
+use super::stats::stat;
+use crate::data::{self, DataError};
+use crate::ecs::{ScriptEvents, ScriptValue, World};
+use crate::kv::KvStore;
+use crate::platform::Platform;
+use mlua::{Lua, Result, UserData, UserDataMethods};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::rc::Rc;
+
+/// Script event sent with `{ id = ... }` for every unlock.
+pub const UNLOCKED_EVENT: &str = "achievement_unlocked";
+/// Key progress is kept under in the `KvStore`.
+const STORE_KEY: &str = "achievements";
+
+pub(super) fn one() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AchievementCondition {
+    /// The script event was sent `count` times, counting only payloads
+    /// whose fields equal everything in `with`.
+    Event {
+        event: String,
+        #[serde(default = "one")]
+        count: f64,
+        #[serde(default)]
+        with: BTreeMap<String, ScriptValue>,
+    },
+    /// A stat kept by `Achievements` itself, such as total gold earned.
+    Stat {
+        stat: String,
+        at_least: f64,
+    },
+    /// A stat in the `Stats` of the entity with this name.
+    EntityStat {
+        entity: String,
+        stat: String,
+        at_least: f64,
+    },
+    All(Vec<AchievementCondition>),
+    Any(Vec<AchievementCondition>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AchievementDef {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Left out of menus until unlocked.
+    #[serde(default)]
+    pub hidden: bool,
+    pub condition: AchievementCondition,
+}
+
+/// Achievements by id; a RON map such as
+/// `{ "first_blood": (name: "First Blood", condition: Event(event: "kill")) }`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AchievementDefs {
+    defs: BTreeMap<String, AchievementDef>,
+}
+
+impl AchievementDefs {
+    pub fn new() -> Self {
+        AchievementDefs::default()
+    }
+
+    pub fn from_ron(source: &str) -> std::result::Result<Self, DataError> {
+        data::from_ron(source)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        data::load_ron(path)
+    }
+
+    pub fn insert(&mut self, id: &str, def: AchievementDef) {
+        self.defs.insert(id.to_string(), def);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&AchievementDef> {
+        self.defs.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &AchievementDef)> {
+        self.defs.iter().map(|(id, def)| (id.as_str(), def))
+    }
+}
+
+/// Sent once for each achievement as it unlocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AchievementUnlocked {
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct Progress {
+    unlocked: BTreeSet<String>,
+    stats: BTreeMap<String, f64>,
+    /// Matching events counted by each `Event` condition, keyed by
+    /// achievement and the condition's place in it.
+    counters: BTreeMap<String, f64>,
+}
+
+struct AchievementState {
+    defs: AchievementDefs,
+    progress: Progress,
+    /// `ScriptEvents::sent` for each name as of the last update.
+    seen: BTreeMap<String, u64>,
+    store: Option<KvStore>,
+    platform: Option<Platform>,
+    dirty: bool,
+}
+
+/// Tracks progress towards every achievement and unlocks them as their
+/// conditions are met, so games describe achievements in data instead of
+/// checking for them in scripts. Clones share one tracker, as does the
+/// `achievements` Lua global.
+#[derive(Clone)]
+pub struct Achievements {
+    state: Rc<RefCell<AchievementState>>,
+}
+
+impl AchievementCondition {
+    /// Calls `f` with every `Event` condition and its counter key.
+    fn each_event(&self, key: &mut String, f: &mut impl FnMut(&str, &AchievementCondition)) {
+        match self {
+            AchievementCondition::Event { .. } => f(key, self),
+            AchievementCondition::All(conditions) | AchievementCondition::Any(conditions) => {
+                for (i, condition) in conditions.iter().enumerate() {
+                    let len = key.len();
+                    key.push_str(&format!(".{}", i));
+                    condition.each_event(key, f);
+                    key.truncate(len);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// How close the condition is to being met, from 0 to 1.
+    fn progress(&self, world: &World, progress: &Progress, key: &mut String) -> f64 {
+        let ratio = |value: f64, target: f64| match target > 0.0 {
+            true => (value / target).clamp(0.0, 1.0),
+            false => 1.0,
+        };
+        match self {
+            AchievementCondition::Event { count, .. } => ratio(
+                progress.counters.get(key.as_str()).copied().unwrap_or(0.0),
+                *count,
+            ),
+            AchievementCondition::Stat { stat, at_least } => {
+                ratio(progress.stats.get(stat).copied().unwrap_or(0.0), *at_least)
+            }
+            AchievementCondition::EntityStat {
+                entity,
+                stat: name,
+                at_least,
+            } => {
+                let value = world.find(entity).and_then(|e| stat(world, e, name));
+                value.map_or(0.0, |value| ratio(value, *at_least))
+            }
+            AchievementCondition::All(conditions) | AchievementCondition::Any(conditions) => {
+                let parts = conditions.iter().enumerate().map(|(i, condition)| {
+                    let len = key.len();
+                    key.push_str(&format!(".{}", i));
+                    let part = condition.progress(world, progress, key);
+                    key.truncate(len);
+                    part
+                });
+                let parts: Vec<f64> = parts.collect();
+                match self {
+                    AchievementCondition::All(_) if parts.is_empty() => 1.0,
+                    AchievementCondition::All(_) => parts.iter().sum::<f64>() / parts.len() as f64,
+                    _ => parts.into_iter().fold(0.0, f64::max),
+                }
+            }
+        }
+    }
+}
+
+pub(super) fn matches(payload: &ScriptValue, with: &BTreeMap<String, ScriptValue>) -> bool {
+    with.is_empty()
+        || matches!(payload, ScriptValue::Map(fields)
+            if with.iter().all(|(name, value)| fields.get(name) == Some(value)))
+}
+
+impl Achievements {
+    pub fn new(defs: AchievementDefs) -> Self {
+        Achievements {
+            state: Rc::new(RefCell::new(AchievementState {
+                defs,
+                progress: Progress::default(),
+                seen: BTreeMap::new(),
+                store: None,
+                platform: None,
+                dirty: false,
+            })),
+        }
+    }
+
+    /// Loads progress saved in `store` and keeps it there from now on,
+    /// written at the end of any `update` that changed it.
+    pub fn persist_to(&self, store: KvStore) -> std::result::Result<(), DataError> {
+        let mut state = self.state.borrow_mut();
+        if let Some(saved) = store.get(STORE_KEY) {
+            let json =
+                serde_json::to_value(saved).map_err(|e| DataError::Invalid(e.to_string()))?;
+            state.progress =
+                serde_json::from_value(json).map_err(|e| DataError::Invalid(e.to_string()))?;
+        }
+        state.store = Some(store);
+        Ok(())
+    }
+
+    /// Mirrors unlocks to the platform, starting with the ones already
+    /// unlocked, so its list catches up with progress saved offline.
+    pub fn set_platform(&self, platform: Platform) {
+        let mut state = self.state.borrow_mut();
+        for id in &state.progress.unlocked {
+            if let Err(e) = platform.services().unlock_achievement(id) {
+                log::warn!(target: "achievements", "platform unlock of '{}' failed: {}", id, e);
+            }
+        }
+        state.platform = Some(platform);
+    }
+
+    pub fn add_stat(&self, name: &str, by: f64) {
+        let mut state = self.state.borrow_mut();
+        *state.progress.stats.entry(name.to_string()).or_default() += by;
+        state.dirty = true;
+    }
+
+    pub fn set_stat(&self, name: &str, value: f64) {
+        let mut state = self.state.borrow_mut();
+        state.progress.stats.insert(name.to_string(), value);
+        state.dirty = true;
+    }
+
+    pub fn stat(&self, name: &str) -> f64 {
+        let state = self.state.borrow();
+        state.progress.stats.get(name).copied().unwrap_or(0.0)
+    }
+
+    pub fn is_unlocked(&self, id: &str) -> bool {
+        self.state.borrow().progress.unlocked.contains(id)
+    }
+
+    pub fn unlocked(&self) -> Vec<String> {
+        let state = self.state.borrow();
+        state.progress.unlocked.iter().cloned().collect()
+    }
+
+    /// From 0 to 1; `None` for an unknown id.
+    pub fn progress(&self, world: &World, id: &str) -> Option<f64> {
+        let state = self.state.borrow();
+        if state.progress.unlocked.contains(id) {
+            return Some(1.0);
+        }
+        let def = state.defs.get(id)?;
+        Some(
+            def.condition
+                .progress(world, &state.progress, &mut id.to_string()),
+        )
+    }
+
+    /// Counts script events sent since the last update, as
+    /// `ScriptEvents::unseen` finds them, then unlocks every achievement
+    /// whose condition is met.
+    pub fn update(&self, world: &mut World) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        let mut fresh: BTreeMap<String, Vec<ScriptValue>> = BTreeMap::new();
+        if let Some(events) = world.resource::<ScriptEvents>() {
+            for (id, def) in state.defs.iter() {
+                if state.progress.unlocked.contains(id) {
+                    continue;
+                }
+                def.condition
+                    .each_event(&mut id.to_string(), &mut |_, condition| {
+                        if let AchievementCondition::Event { event, .. } = condition
+                            && !fresh.contains_key(event)
+                        {
+                            let seen = state.seen.entry(event.clone()).or_default();
+                            fresh.insert(event.clone(), events.unseen(event, seen).to_vec());
+                        }
+                    });
+            }
+        }
+        for (name, new) in &fresh {
+            if new.is_empty() {
+                continue;
+            }
+            for (id, def) in state.defs.iter() {
+                if state.progress.unlocked.contains(id) {
+                    continue;
+                }
+                def.condition
+                    .each_event(&mut id.to_string(), &mut |key, condition| {
+                        if let AchievementCondition::Event { event, with, .. } = condition
+                            && event == name
+                        {
+                            let hits = new.iter().filter(|payload| matches(payload, with)).count();
+                            if hits > 0 {
+                                *state.progress.counters.entry(key.to_string()).or_default() +=
+                                    hits as f64;
+                                state.dirty = true;
+                            }
+                        }
+                    });
+            }
+        }
+
+        let mut unlocked = Vec::new();
+        for (id, def) in state.defs.iter() {
+            if !state.progress.unlocked.contains(id)
+                && def
+                    .condition
+                    .progress(world, &state.progress, &mut id.to_string())
+                    >= 1.0
+            {
+                unlocked.push(id.to_string());
+            }
+        }
+        for id in unlocked {
+            let prefix = format!("{}.", id);
+            state
+                .progress
+                .counters
+                .retain(|key, _| *key != id && !key.starts_with(&prefix));
+            state.progress.unlocked.insert(id.clone());
+            state.dirty = true;
+            if let Some(platform) = &state.platform
+                && let Err(e) = platform.services().unlock_achievement(&id)
+            {
+                log::warn!(target: "achievements", "platform unlock of '{}' failed: {}", id, e);
+            }
+            log::info!(target: "achievements", "unlocked '{}'", id);
+            world.send_script_event(
+                UNLOCKED_EVENT,
+                ScriptValue::Map(BTreeMap::from([(
+                    "id".to_string(),
+                    ScriptValue::String(id.clone()),
+                )])),
+            );
+            world.send_event(AchievementUnlocked { id });
+        }
+
+        if state.dirty
+            && let Some(store) = &state.store
+        {
+            let json = serde_json::to_value(&state.progress)
+                .map_err(|e| DataError::Invalid(e.to_string()))?;
+            let saved: ScriptValue =
+                serde_json::from_value(json).map_err(|e| DataError::Invalid(e.to_string()))?;
+            store.set(STORE_KEY, saved)?;
+        }
+        state.dirty = false;
+        Ok(())
+    }
+
+    /// An exclusive system updating the tracker once per schedule run.
+    pub fn into_system(self) -> impl FnMut(&mut World, &Lua) -> Result<()> {
+        move |world, _| self.update(world)
+    }
+
+    /// Adds the `achievements` global: `add_stat(name, by)`,
+    /// `set_stat(name, value)`, `stat(name)`, `is_unlocked(id)` and
+    /// `unlocked()`, all as methods.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("achievements", self.clone())
+    }
+}
+
+impl UserData for Achievements {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("add_stat", |_, this, (name, by): (String, Option<f64>)| {
+            this.add_stat(&name, by.unwrap_or(1.0));
+            Ok(())
+        });
+        methods.add_method("set_stat", |_, this, (name, value): (String, f64)| {
+            this.set_stat(&name, value);
+            Ok(())
+        });
+        methods.add_method("stat", |_, this, name: String| Ok(this.stat(&name)));
+        methods.add_method("is_unlocked", |_, this, id: String| {
+            Ok(this.is_unlocked(&id))
+        });
+        methods.add_method("unlocked", |_, this, ()| Ok(this.unlocked()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameplay::Stats;
+    use crate::platform::NullPlatform;
+
+    #[test]
+    fn test_conditions_unlock_and_persist() -> Result<()> {
+        let defs = AchievementDefs::from_ron(
+            r#"{
+                "first_blood": (condition: Event(event: "kill")),
+                "giant_slayer": (condition: All([
+                    Event(event: "kill", count: 2, with: { "kind": "giant" }),
+                    Stat(stat: "gold", at_least: 100),
+                ])),
+                "strong": (condition: EntityStat(entity: "hero", stat: "strength", at_least: 10)),
+            }"#,
+        )?;
+        let dir = std::env::temp_dir().join(format!("ee_achievements_{}", std::process::id()));
+        let path = dir.join("progress.json");
+        let _ = std::fs::remove_file(&path);
+
+        let mut world = World::new();
+        let hero = world.spawn();
+        world.set_name(hero, "hero")?;
+        world.insert(hero, Stats::new().with("strength", 4.0))?;
+        let achievements = Achievements::new(defs.clone());
+        achievements.persist_to(KvStore::open(&path)?)?;
+        let platform = Platform::new(NullPlatform::new());
+        achievements.set_platform(platform.clone());
+        let lua = Lua::new();
+        achievements.register_lua(&lua)?;
+
+        let giant = || {
+            ScriptValue::Map(BTreeMap::from([(
+                "kind".to_string(),
+                ScriptValue::String("giant".to_string()),
+            )]))
+        };
+        world.send_script_event("kill", giant());
+        achievements.update(&mut world)?;
+        assert_eq!(achievements.unlocked(), ["first_blood"]);
+        assert!(platform.services().is_achievement_unlocked("first_blood"));
+        assert_eq!(achievements.progress(&world, "giant_slayer"), Some(0.25));
+
+        // Seen events aren't counted twice, even though nobody drained them.
+        lua.load("achievements:add_stat('gold', 150)").exec()?;
+        achievements.update(&mut world)?;
+        assert!(!achievements.is_unlocked("giant_slayer"));
+        world.drain_script_events("kill");
+        world.send_script_event("kill", giant());
+        achievements.update(&mut world)?;
+        assert!(achievements.is_unlocked("giant_slayer"));
+
+        crate::gameplay::set_base_stat(&mut world, hero, "strength", 12.0)?;
+        achievements.update(&mut world)?;
+        let unlocks: Vec<String> = world
+            .drain_events::<AchievementUnlocked>()
+            .into_iter()
+            .map(|event| event.id)
+            .collect();
+        assert_eq!(unlocks, ["first_blood", "giant_slayer", "strong"]);
+        assert_eq!(world.drain_script_events(UNLOCKED_EVENT).len(), 3);
+
+        let reloaded = Achievements::new(defs);
+        reloaded.persist_to(KvStore::open(&path)?)?;
+        assert_eq!(reloaded.unlocked().len(), 3);
+        assert_eq!(reloaded.stat("gold"), 150.0);
+        let _ = std::fs::remove_dir_all(&dir);
+        Ok(())
+    }
+}
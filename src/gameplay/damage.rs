@@ -0,0 +1,269 @@
+use crate::ecs::{Entity, World};
+use mlua::{Function, Lua, Result, Table, Value};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    pub current: f64,
+    pub max: f64,
+}
+
+impl Health {
+    pub fn new(max: f64) -> Self {
+        Health { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+/// Damage on its way to a target, queued with `world.send_event` and
+/// worked through by `DamagePipeline::process`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Damage {
+    pub target: Entity,
+    pub source: Option<Entity>,
+    pub amount: f64,
+    /// Free-form, e.g. "fire"; mitigation stages key resistances off it.
+    pub kind: String,
+}
+
+impl Damage {
+    pub fn new(target: Entity, amount: f64) -> Self {
+        Damage {
+            target,
+            source: None,
+            amount,
+            kind: String::new(),
+        }
+    }
+}
+
+/// Damage after mitigation, as taken off the target's health.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DamageApplied {
+    pub target: Entity,
+    pub source: Option<Entity>,
+    pub amount: f64,
+    pub kind: String,
+    /// Health left afterwards.
+    pub health: f64,
+}
+
+/// The target's health reached zero from this hit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Died {
+    pub entity: Entity,
+    pub source: Option<Entity>,
+}
+
+pub type MitigateFn = Rc<dyn Fn(&World, &mut Damage) -> bool>;
+
+#[derive(Clone)]
+enum MitigateHook {
+    Rust(MitigateFn),
+    /// Called as `f(world, damage)` with `damage` a table of `target`,
+    /// `source`, `amount` and `kind`; edits to `amount` and `kind` are
+    /// kept, and returning false cancels the hit.
+    Lua(Function),
+}
+
+/// Turns queued `Damage` into health changes through mitigation stages,
+/// so armor, shields and invulnerability frames each add a stage instead
+/// of wrapping one another. Stages run lowest `order` first, ties in the
+/// order they were added; the first to cancel stops the hit. Clones
+/// share one list.
+#[derive(Clone, Default)]
+pub struct DamagePipeline {
+    stages: Rc<RefCell<Vec<(i32, MitigateHook)>>>,
+}
+
+impl DamagePipeline {
+    pub fn new() -> Self {
+        DamagePipeline::default()
+    }
+
+    fn add(&self, order: i32, hook: MitigateHook) {
+        let mut stages = self.stages.borrow_mut();
+        let at = stages.partition_point(|(existing, _)| *existing <= order);
+        stages.insert(at, (order, hook));
+    }
+
+    pub fn on_mitigate(&self, order: i32, hook: impl Fn(&World, &mut Damage) -> bool + 'static) {
+        self.add(order, MitigateHook::Rust(Rc::new(hook)));
+    }
+
+    /// Runs one hit through every stage and applies what is left. Targets
+    /// without `Health` and cancelled hits give `None`; the dead take no
+    /// more damage.
+    pub fn deal(
+        &self,
+        world: &mut World,
+        lua: &Lua,
+        mut damage: Damage,
+    ) -> Result<Option<DamageApplied>> {
+        if world
+            .get::<Health>(damage.target)
+            .is_none_or(|h| h.is_dead())
+        {
+            return Ok(None);
+        }
+        let stages = self.stages.borrow().clone();
+        for (_, stage) in stages {
+            let keep = match stage {
+                MitigateHook::Rust(hook) => hook(world, &mut damage),
+                MitigateHook::Lua(function) => {
+                    let table = to_table(lua, &damage)?;
+                    let keep = lua.scope(|scope| {
+                        let handle = scope.create_userdata_ref_mut(&mut *world)?;
+                        function.call::<Value>((handle, &table))
+                    })?;
+                    damage.amount = table.get("amount")?;
+                    damage.kind = table.get("kind")?;
+                    !matches!(keep, Value::Boolean(false))
+                }
+            };
+            if !keep {
+                return Ok(None);
+            }
+        }
+        let Some(mut health) = world.get_mut::<Health>(damage.target) else {
+            // A stage despawned it.
+            return Ok(None);
+        };
+        let amount = damage.amount.max(0.0);
+        health.current -= amount;
+        let applied = DamageApplied {
+            target: damage.target,
+            source: damage.source,
+            amount,
+            kind: damage.kind,
+            health: health.current,
+        };
+        let died = health.is_dead();
+        drop(health);
+        world.send_event(applied.clone());
+        if died {
+            world.send_event(Died {
+                entity: applied.target,
+                source: applied.source,
+            });
+        }
+        Ok(Some(applied))
+    }
+
+    /// Deals every queued `Damage`, including any that stages queue.
+    pub fn process(&self, world: &mut World, lua: &Lua) -> Result<usize> {
+        let mut applied = 0;
+        loop {
+            let queued = world.drain_events::<Damage>();
+            if queued.is_empty() {
+                return Ok(applied);
+            }
+            for damage in queued {
+                applied += usize::from(self.deal(world, lua, damage)?.is_some());
+            }
+        }
+    }
+
+    /// Adds `damage.on_mitigate(f, order?)`, order defaulting to 0.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        let damage = lua.create_table()?;
+        let this = self.clone();
+        damage.set(
+            "on_mitigate",
+            lua.create_function(move |_, (hook, order): (Function, Option<i32>)| {
+                this.add(order.unwrap_or(0), MitigateHook::Lua(hook));
+                Ok(())
+            })?,
+        )?;
+        lua.globals().set("damage", damage)
+    }
+}
+
+fn to_table(lua: &Lua, damage: &Damage) -> Result<Table> {
+    let table = lua.create_table()?;
+    table.set("target", damage.target)?;
+    table.set("source", damage.source)?;
+    table.set("amount", damage.amount)?;
+    table.set("kind", damage.kind.as_str())?;
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameplay::stat;
+
+    #[test]
+    fn test_stages_compose() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let pipeline = DamagePipeline::new();
+        pipeline.register_lua(&lua)?;
+        // Armor flattens, then the shield halves what is left.
+        pipeline.on_mitigate(10, |_, damage| {
+            damage.amount *= 0.5;
+            true
+        });
+        pipeline.on_mitigate(0, |world, damage| {
+            damage.amount -= stat(world, damage.target, "armor").unwrap_or(0.0);
+            true
+        });
+        lua.load(
+            r#"
+            damage.on_mitigate(function(world, hit)
+                if hit.kind == "fire" and world:has(hit.target, "FireImmune") then
+                    return false
+                end
+            end, -1)
+        "#,
+        )
+        .exec()?;
+
+        let knight = world.spawn();
+        world.insert(knight, Health::new(20.0)).unwrap();
+        crate::gameplay::set_base_stat(&mut world, knight, "armor", 2.0).unwrap();
+        lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load("local world, knight = ... world:damage(knight, 12)")
+                .call::<()>((handle, knight))
+        })?;
+        world.send_event(Damage {
+            kind: "fire".to_string(),
+            ..Damage::new(knight, 100.0)
+        });
+        world
+            .set_script_component(knight, "FireImmune", crate::ecs::ScriptValue::Bool(true))
+            .unwrap();
+        assert_eq!(pipeline.process(&mut world, &lua)?, 1);
+        assert_eq!(world.get::<Health>(knight).unwrap().current, 15.0);
+
+        world.remove_script_component(knight, "FireImmune");
+        world.send_event(Damage {
+            source: Some(knight),
+            ..Damage::new(knight, 100.0)
+        });
+        pipeline.process(&mut world, &lua)?;
+        let applied = world.drain_events::<DamageApplied>();
+        assert_eq!(
+            applied.iter().map(|a| a.amount).collect::<Vec<_>>(),
+            vec![5.0, 49.0]
+        );
+        let deaths = world.drain_events::<Died>();
+        assert_eq!(
+            deaths,
+            vec![Died {
+                entity: knight,
+                source: Some(knight)
+            }]
+        );
+        // The dead take no more damage.
+        world.send_event(Damage::new(knight, 1.0));
+        assert_eq!(pipeline.process(&mut world, &lua)?, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,539 @@
+use crate::data::{self, DataError};
+use crate::ecs::{Entity, World};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+fn default_stack() -> u32 {
+    99
+}
+
+/// What every stack of one item shares, loaded from an item asset.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemDef {
+    #[serde(default = "default_stack")]
+    pub max_stack: u32,
+    /// Matched against slots' `accepts`, e.g. "helmet" or "potion".
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Item definitions by name, kept as a world resource; a RON map such as
+/// `{ "potion": (max_stack: 10, tags: ["consumable"]) }`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ItemDefs {
+    items: BTreeMap<String, ItemDef>,
+}
+
+impl ItemDefs {
+    pub fn new() -> Self {
+        ItemDefs::default()
+    }
+
+    pub fn from_ron(source: &str) -> std::result::Result<Self, DataError> {
+        data::from_ron(source)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        data::load_ron(path)
+    }
+
+    pub fn insert(&mut self, name: &str, def: ItemDef) {
+        self.items.insert(name.to_string(), def);
+    }
+
+    pub fn extend(&mut self, other: ItemDefs) {
+        self.items.extend(other.items);
+    }
+
+    pub fn get(&self, name: &str) -> Result<&ItemDef, InventoryError> {
+        self.items
+            .get(name)
+            .ok_or_else(|| InventoryError::UnknownItem(name.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub item: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Slot {
+    /// Left out when empty, since scripts can't hold a nil field.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack: Option<ItemStack>,
+    /// Tags an item needs one of to go here; anything when empty.
+    pub accepts: Vec<String>,
+}
+
+impl Slot {
+    pub fn accepts(&self, def: &ItemDef) -> bool {
+        self.accepts.is_empty() || def.tags.iter().any(|tag| self.accepts.contains(tag))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InventoryError {
+    UnknownItem(String),
+    NoSuchSlot(usize),
+    EmptySlot(usize),
+    NotEnough {
+        item: String,
+        wanted: u32,
+        have: u32,
+    },
+    /// Not everything fits.
+    NoRoom(String),
+    /// The slot's `accepts` rules the item out.
+    Rejected {
+        slot: usize,
+        item: String,
+    },
+    /// A different item is in the way and can't be swapped.
+    Occupied(usize),
+    NoInventory(Entity),
+}
+
+impl fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InventoryError::UnknownItem(item) => write!(f, "unknown item '{}'", item),
+            InventoryError::NoSuchSlot(slot) => write!(f, "no slot {}", slot),
+            InventoryError::EmptySlot(slot) => write!(f, "slot {} is empty", slot),
+            InventoryError::NotEnough { item, wanted, have } => {
+                write!(f, "wanted {} '{}' but there are {}", wanted, item, have)
+            }
+            InventoryError::NoRoom(item) => write!(f, "no room for '{}'", item),
+            InventoryError::Rejected { slot, item } => {
+                write!(f, "slot {} doesn't take '{}'", slot, item)
+            }
+            InventoryError::Occupied(slot) => write!(f, "slot {} holds something else", slot),
+            InventoryError::NoInventory(entity) => write!(f, "{} has no inventory", entity),
+        }
+    }
+}
+
+impl std::error::Error for InventoryError {}
+
+impl From<InventoryError> for mlua::Error {
+    fn from(e: InventoryError) -> Self {
+        mlua::Error::external(e)
+    }
+}
+
+/// Moves `count` (the whole stack when unset) from one slot to another:
+/// into an empty slot, onto the same item, or swapping with a different
+/// item when moving a whole stack. `from_index` and `to_index` are only
+/// for errors.
+fn transfer(
+    defs: &ItemDefs,
+    from: &mut Slot,
+    from_index: usize,
+    to: &mut Slot,
+    to_index: usize,
+    count: Option<u32>,
+) -> Result<(), InventoryError> {
+    let stack = from
+        .stack
+        .clone()
+        .ok_or(InventoryError::EmptySlot(from_index))?;
+    let count = count.unwrap_or(stack.count);
+    if count == 0 || count > stack.count {
+        return Err(InventoryError::NotEnough {
+            item: stack.item,
+            wanted: count,
+            have: stack.count,
+        });
+    }
+    let def = defs.get(&stack.item)?;
+    if !to.accepts(def) {
+        return Err(InventoryError::Rejected {
+            slot: to_index,
+            item: stack.item,
+        });
+    }
+    let left = stack.count - count;
+    match &mut to.stack {
+        None if count > def.max_stack => return Err(InventoryError::NoRoom(stack.item)),
+        None => {
+            to.stack = Some(ItemStack {
+                item: stack.item.clone(),
+                count,
+            })
+        }
+        Some(there) if there.item == stack.item => {
+            if there.count + count > def.max_stack {
+                return Err(InventoryError::NoRoom(stack.item));
+            }
+            there.count += count;
+        }
+        Some(there) => {
+            if left > 0 || !from.accepts(defs.get(&there.item)?) {
+                return Err(InventoryError::Occupied(to_index));
+            }
+            std::mem::swap(&mut from.stack, &mut to.stack);
+            return Ok(());
+        }
+    }
+    from.stack = (left > 0).then_some(ItemStack {
+        item: stack.item,
+        count: left,
+    });
+    Ok(())
+}
+
+/// Slots of item stacks. Every operation either happens completely or
+/// fails leaving the inventory as it was.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Inventory {
+    pub slots: Vec<Slot>,
+}
+
+impl Inventory {
+    pub fn new(slots: usize) -> Self {
+        Inventory {
+            slots: vec![Slot::default(); slots],
+        }
+    }
+
+    pub fn count(&self, item: &str) -> u32 {
+        self.slots
+            .iter()
+            .filter_map(|slot| slot.stack.as_ref())
+            .filter(|stack| stack.item == item)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    pub fn first_empty(&self) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.stack.is_none())
+    }
+
+    fn slot(&self, index: usize) -> Result<&Slot, InventoryError> {
+        self.slots
+            .get(index)
+            .ok_or(InventoryError::NoSuchSlot(index))
+    }
+
+    /// Tops up existing stacks first, then fills empty slots.
+    pub fn add(&mut self, defs: &ItemDefs, item: &str, count: u32) -> Result<(), InventoryError> {
+        let def = defs.get(item)?;
+        let mut slots = self.slots.clone();
+        let mut left = count;
+        for slot in &mut slots {
+            if let Some(stack) = slot.stack.as_mut().filter(|s| s.item == item) {
+                let moved = left.min(def.max_stack.saturating_sub(stack.count));
+                stack.count += moved;
+                left -= moved;
+            }
+        }
+        for slot in &mut slots {
+            if left > 0 && slot.stack.is_none() && slot.accepts(def) && def.max_stack > 0 {
+                let moved = left.min(def.max_stack);
+                slot.stack = Some(ItemStack {
+                    item: item.to_string(),
+                    count: moved,
+                });
+                left -= moved;
+            }
+        }
+        if left > 0 {
+            return Err(InventoryError::NoRoom(item.to_string()));
+        }
+        self.slots = slots;
+        Ok(())
+    }
+
+    /// Takes from the last stacks first, so the front of the bag stays full.
+    pub fn remove(&mut self, item: &str, count: u32) -> Result<(), InventoryError> {
+        let have = self.count(item);
+        if have < count {
+            return Err(InventoryError::NotEnough {
+                item: item.to_string(),
+                wanted: count,
+                have,
+            });
+        }
+        let mut left = count;
+        for slot in self.slots.iter_mut().rev() {
+            if let Some(stack) = slot.stack.as_mut().filter(|s| s.item == item) {
+                let taken = left.min(stack.count);
+                stack.count -= taken;
+                left -= taken;
+                if stack.count == 0 {
+                    slot.stack = None;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves, merges or swaps between two slots; see `transfer`.
+    pub fn move_items(
+        &mut self,
+        defs: &ItemDefs,
+        from: usize,
+        to: usize,
+        count: Option<u32>,
+    ) -> Result<(), InventoryError> {
+        let (mut a, mut b) = (self.slot(from)?.clone(), self.slot(to)?.clone());
+        if from == to {
+            return Ok(());
+        }
+        transfer(defs, &mut a, from, &mut b, to, count)?;
+        self.slots[from] = a;
+        self.slots[to] = b;
+        Ok(())
+    }
+
+    /// Moves `count` off a stack into the first empty slot, returning it.
+    pub fn split(
+        &mut self,
+        defs: &ItemDefs,
+        from: usize,
+        count: u32,
+    ) -> Result<usize, InventoryError> {
+        let item = self
+            .slot(from)?
+            .stack
+            .as_ref()
+            .ok_or(InventoryError::EmptySlot(from))?
+            .item
+            .clone();
+        let def = defs.get(&item)?;
+        let to = self
+            .slots
+            .iter()
+            .position(|slot| slot.stack.is_none() && slot.accepts(def))
+            .ok_or(InventoryError::NoRoom(item))?;
+        self.move_items(defs, from, to, Some(count))?;
+        Ok(to)
+    }
+}
+
+/// One slot's contents changing, sent by the world-level functions here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InventoryChanged {
+    pub entity: Entity,
+    pub slot: usize,
+    pub old: Option<ItemStack>,
+    pub new: Option<ItemStack>,
+}
+
+/// Runs `change` on the entities' inventories with the world's
+/// `ItemDefs`, then sends an `InventoryChanged` for every slot that
+/// differs. Nothing is written back when `change` fails.
+fn change_inventories<R>(
+    world: &mut World,
+    entities: &[Entity],
+    change: impl FnOnce(&ItemDefs, &mut [Inventory]) -> Result<R, InventoryError>,
+) -> Result<R, InventoryError> {
+    let before = entities
+        .iter()
+        .map(|&entity| {
+            world
+                .get::<Inventory>(entity)
+                .map(|inventory| inventory.clone())
+                .ok_or(InventoryError::NoInventory(entity))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut after = before.clone();
+    let empty = ItemDefs::default();
+    let result = change(world.resource::<ItemDefs>().unwrap_or(&empty), &mut after)?;
+    let mut changes = Vec::new();
+    for ((&entity, old), new) in entities.iter().zip(&before).zip(after) {
+        for (slot, (old, new)) in old.slots.iter().zip(&new.slots).enumerate() {
+            if old.stack != new.stack {
+                changes.push(InventoryChanged {
+                    entity,
+                    slot,
+                    old: old.stack.clone(),
+                    new: new.stack.clone(),
+                });
+            }
+        }
+        world
+            .insert(entity, new)
+            .map_err(|_| InventoryError::NoInventory(entity))?;
+    }
+    for change in changes {
+        world.send_event(change);
+    }
+    Ok(result)
+}
+
+pub fn give(
+    world: &mut World,
+    entity: Entity,
+    item: &str,
+    count: u32,
+) -> Result<(), InventoryError> {
+    change_inventories(world, &[entity], |defs, inventories| {
+        inventories[0].add(defs, item, count)
+    })
+}
+
+pub fn take(
+    world: &mut World,
+    entity: Entity,
+    item: &str,
+    count: u32,
+) -> Result<(), InventoryError> {
+    change_inventories(world, &[entity], |_, inventories| {
+        inventories[0].remove(item, count)
+    })
+}
+
+/// Moves between slots of one inventory or two; see `Inventory::move_items`.
+pub fn move_item(
+    world: &mut World,
+    (from, from_slot): (Entity, usize),
+    (to, to_slot): (Entity, usize),
+    count: Option<u32>,
+) -> Result<(), InventoryError> {
+    if from == to {
+        return change_inventories(world, &[from], |defs, inventories| {
+            inventories[0].move_items(defs, from_slot, to_slot, count)
+        });
+    }
+    change_inventories(world, &[from, to], |defs, inventories| {
+        let [source, target] = inventories else {
+            unreachable!("two entities given");
+        };
+        let mut a = source.slot(from_slot)?.clone();
+        let mut b = target.slot(to_slot)?.clone();
+        transfer(defs, &mut a, from_slot, &mut b, to_slot, count)?;
+        source.slots[from_slot] = a;
+        target.slots[to_slot] = b;
+        Ok(())
+    })
+}
+
+pub fn split_stack(
+    world: &mut World,
+    entity: Entity,
+    slot: usize,
+    count: u32,
+) -> Result<usize, InventoryError> {
+    change_inventories(world, &[entity], |defs, inventories| {
+        inventories[0].split(defs, slot, count)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defs() -> ItemDefs {
+        ItemDefs::from_ron(
+            r#"{
+                "potion": (max_stack: 5, tags: ["consumable"]),
+                "helmet": (max_stack: 1, tags: ["head"]),
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_stacking_is_all_or_nothing() {
+        let defs = defs();
+        let mut bag = Inventory::new(3);
+        bag.slots[2].accepts = vec!["head".to_string()];
+        bag.add(&defs, "potion", 7).unwrap();
+        assert_eq!(bag.slots[0].stack.as_ref().unwrap().count, 5);
+        assert_eq!(bag.slots[1].stack.as_ref().unwrap().count, 2);
+        // Only the helmet slot is free and potions don't go there.
+        let before = bag.clone();
+        assert_eq!(
+            bag.add(&defs, "potion", 4),
+            Err(InventoryError::NoRoom("potion".to_string()))
+        );
+        assert_eq!(bag, before);
+        assert!(bag.remove("potion", 8).is_err());
+
+        bag.add(&defs, "potion", 3).unwrap();
+        assert_eq!(bag.count("potion"), 10);
+        bag.remove("potion", 6).unwrap();
+        assert_eq!(bag.count("potion"), 4);
+        assert!(bag.slots[1].stack.is_none());
+
+        assert_eq!(bag.split(&defs, 0, 1).unwrap(), 1);
+        bag.move_items(&defs, 1, 0, None).unwrap();
+        assert_eq!(bag.count("potion"), 4);
+        assert!(matches!(
+            bag.move_items(&defs, 0, 2, None),
+            Err(InventoryError::Rejected { slot: 2, .. })
+        ));
+        assert!(matches!(
+            bag.move_items(&defs, 0, 1, Some(6)),
+            Err(InventoryError::NotEnough { .. })
+        ));
+    }
+
+    #[test]
+    fn test_moves_between_entities_send_events() {
+        let mut world = World::new();
+        world.insert_resource(defs());
+        let (hero, chest) = (world.spawn(), world.spawn());
+        world.insert(hero, Inventory::new(2)).unwrap();
+        world.insert(chest, Inventory::new(1)).unwrap();
+        give(&mut world, chest, "helmet", 1).unwrap();
+        give(&mut world, hero, "potion", 3).unwrap();
+        world.drain_events::<InventoryChanged>();
+
+        move_item(&mut world, (chest, 0), (hero, 1), Some(2)).unwrap_err();
+        move_item(&mut world, (chest, 0), (hero, 1), None).unwrap();
+        let events = world.drain_events::<InventoryChanged>();
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].entity, events[0].new.clone()), (chest, None));
+        assert_eq!(events[1].new.as_ref().unwrap().item, "helmet");
+        assert_eq!(world.get::<Inventory>(hero).unwrap().count("helmet"), 1);
+        assert!(take(&mut world, chest, "helmet", 1).is_err());
+    }
+
+    #[test]
+    fn test_lua_inventory() -> mlua::Result<()> {
+        let lua = mlua::Lua::new();
+        let mut world = World::new();
+        world.register_component::<Inventory>("Inventory");
+        lua.scope(|scope| {
+            let world = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world = ...
+                world:define_items({ arrow = { max_stack = 20 } })
+                local hero = world:spawn({ Inventory = { slots = { {}, {}, {} } } })
+                world:give(hero, "arrow", 25)
+                assert(world:item_count(hero, "arrow") == 25)
+                assert(world:split_stack(hero, 1, 5) == 3)
+                world:move_item(hero, 3, hero, 2)
+                assert(world:get(hero, "Inventory").slots[2].stack.count == 10)
+                assert(not pcall(world.take, world, hero, "arrow", 26))
+                local changes = world:inventory_changes()
+                assert(#changes == 6 and changes[1].slot == 1 and changes[1].old == nil)
+                local ok, err = pcall(world.split_stack, world, hero, 0, 1)
+                assert(not ok and tostring(err):find("start at 1"), tostring(err))
+            "#,
+            )
+            .call::<()>(world)
+        })?;
+
+        // Empty slots survive a capture.
+        let scene = crate::scene::Scene::capture(&world, &lua)?;
+        let mut loaded = World::new();
+        loaded.register_component::<Inventory>("Inventory");
+        let spawned = scene.spawn(&mut loaded, &lua)?;
+        let hero = world.entities().next().unwrap();
+        assert_eq!(
+            loaded.get::<Inventory>(spawned[0]).unwrap().slots,
+            world.get::<Inventory>(hero).unwrap().slots
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+mod abilities;
+mod achievements;
+mod damage;
+mod inventory;
+mod quests;
+mod stats;
+
+pub use abilities::{
+    Abilities, Ability, AbilityEvent, AbilityEventKind, AbilitySystem, CastError, CastState,
+    interrupt, try_cast, update_abilities,
+};
+pub use achievements::{
+    AchievementCondition, AchievementDef, AchievementDefs, AchievementUnlocked, Achievements,
+    UNLOCKED_EVENT,
+};
+pub use damage::{Damage, DamageApplied, DamagePipeline, Died, Health, MitigateFn};
+pub use inventory::{
+    Inventory, InventoryChanged, InventoryError, ItemDef, ItemDefs, ItemStack, Slot, give,
+    move_item, split_stack, take,
+};
+pub use quests::{
+    Objective, ObjectiveKind, QUEST_EVENT, QuestDef, QuestDefs, QuestEvent, QuestLog,
+    QuestProgress, QuestStage, Quests, RustObjectiveHook,
+};
+pub use stats::{
+    Modifier, ModifierKind, StatChanged, Stats, add_modifier, remove_modifiers, set_base_stat,
+    stat, update_stats, with_modifier_source,
+};
+
+use crate::ecs::World;
+
+pub fn register_components(world: &mut World) {
+    world.register_component::<Stats>("Stats");
+    world.register_component::<Inventory>("Inventory");
+    world.register_component::<Health>("Health");
+    world.register_component::<Abilities>("Abilities");
+    world.register_component::<QuestLog>("QuestLog");
+}
@@ -0,0 +1,664 @@
+use super::achievements::{matches, one};
+use super::inventory::{Inventory, give};
+use crate::assets::read_lua_asset;
+use crate::data::{self, DataError};
+use crate::ecs::{Entity, ScriptEvents, ScriptValue, World};
+use crate::loot::{Condition, Loot};
+use crate::rng::GameRng;
+use mlua::{AnyUserData, Error, Function, Lua, LuaSerdeExt, Result, UserData, UserDataMethods};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::rc::Rc;
+
+/// Script event every `QuestEvent` is also sent under, as a table with a
+/// `kind` field.
+pub const QUEST_EVENT: &str = "quest";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ObjectiveKind {
+    /// Counts script events whose payload fields equal everything in
+    /// `with`, e.g. `kill` events with `{ kind = "Goblin" }`.
+    Event {
+        event: String,
+        #[serde(default)]
+        with: BTreeMap<String, ScriptValue>,
+    },
+    /// Met while the condition holds for the quest holder.
+    Holds(Condition),
+    /// Asks the hook registered under `kind` for the progress count.
+    Custom {
+        kind: String,
+        #[serde(default)]
+        params: BTreeMap<String, ScriptValue>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Objective {
+    #[serde(default)]
+    pub description: String,
+    pub kind: ObjectiveKind,
+    #[serde(default = "one")]
+    pub count: f64,
+}
+
+/// Done once every objective is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuestStage {
+    #[serde(default)]
+    pub description: String,
+    pub objectives: Vec<Objective>,
+    /// Loot tables rolled for the holder when the stage is done.
+    #[serde(default)]
+    pub rewards: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuestDef {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub stages: Vec<QuestStage>,
+    /// Loot tables rolled for the holder on completion.
+    #[serde(default)]
+    pub rewards: Vec<String>,
+}
+
+/// Quests by id; a RON map such as
+/// `{ "cull": (stages: [(objectives: [(kind: Event(event: "kill"), count: 5)])]) }`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct QuestDefs {
+    defs: BTreeMap<String, QuestDef>,
+}
+
+impl QuestDefs {
+    pub fn new() -> Self {
+        QuestDefs::default()
+    }
+
+    pub fn from_ron(source: &str) -> std::result::Result<Self, DataError> {
+        data::from_ron(source)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        data::load_ron(path)
+    }
+
+    pub fn insert(&mut self, id: &str, def: QuestDef) {
+        self.defs.insert(id.to_string(), def);
+    }
+
+    pub fn extend(&mut self, other: QuestDefs) {
+        self.defs.extend(other.defs);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&QuestDef> {
+        self.defs.get(id)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuestProgress {
+    pub stage: usize,
+    /// Progress of each objective in the current stage.
+    pub counts: Vec<f64>,
+}
+
+/// The quests an entity, usually the player, has taken on. A registered
+/// component, so quest progress is saved with the scene.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuestLog {
+    pub active: BTreeMap<String, QuestProgress>,
+    pub completed: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QuestEvent {
+    Started {
+        quest: String,
+        entity: Entity,
+    },
+    Progress {
+        quest: String,
+        entity: Entity,
+        objective: usize,
+        count: f64,
+    },
+    StageCompleted {
+        quest: String,
+        entity: Entity,
+        stage: usize,
+    },
+    Completed {
+        quest: String,
+        entity: Entity,
+    },
+    Abandoned {
+        quest: String,
+        entity: Entity,
+    },
+    /// What the reward tables dropped. Drops were given as items to a
+    /// holder with an `Inventory`.
+    Rewarded {
+        quest: String,
+        entity: Entity,
+        drops: Vec<String>,
+    },
+}
+
+pub type RustObjectiveHook =
+    Rc<dyn Fn(&mut World, &Lua, Entity, &BTreeMap<String, ScriptValue>) -> Result<f64>>;
+
+#[derive(Clone)]
+enum ObjectiveHook {
+    Rust(RustObjectiveHook),
+    /// Called as `hook(world, entity, params)`.
+    Lua(Function),
+}
+
+struct QuestState {
+    defs: QuestDefs,
+    hooks: BTreeMap<String, ObjectiveHook>,
+    loot: Option<(Loot, GameRng)>,
+    /// `ScriptEvents::sent` for each name as of the last update.
+    seen: BTreeMap<String, u64>,
+}
+
+/// Starts quests and advances them as their objectives are met. Clones
+/// share one set of definitions and hooks, as does the `quests` Lua global.
+#[derive(Clone)]
+pub struct Quests {
+    state: Rc<RefCell<QuestState>>,
+}
+
+impl Quests {
+    pub fn new(defs: QuestDefs) -> Self {
+        Quests {
+            state: Rc::new(RefCell::new(QuestState {
+                defs,
+                hooks: BTreeMap::new(),
+                loot: None,
+                seen: BTreeMap::new(),
+            })),
+        }
+    }
+
+    pub fn defs_mut(&self) -> std::cell::RefMut<'_, QuestDefs> {
+        std::cell::RefMut::map(self.state.borrow_mut(), |state| &mut state.defs)
+    }
+
+    /// Rolls reward tables from `loot`; without it rewards are skipped.
+    pub fn set_loot(&self, loot: Loot, rng: GameRng) {
+        self.state.borrow_mut().loot = Some((loot, rng));
+    }
+
+    /// Handles `Custom` objectives of this kind. `f` returns the progress
+    /// count, which is kept as is rather than added to.
+    pub fn on_objective(
+        &self,
+        kind: &str,
+        f: impl Fn(&mut World, &Lua, Entity, &BTreeMap<String, ScriptValue>) -> Result<f64> + 'static,
+    ) {
+        let mut state = self.state.borrow_mut();
+        state
+            .hooks
+            .insert(kind.to_string(), ObjectiveHook::Rust(Rc::new(f)));
+    }
+
+    /// Gives `entity` the quest, adding a `QuestLog` if needed. Returns
+    /// false if it is already active or completed.
+    pub fn start(&self, world: &mut World, entity: Entity, quest: &str) -> Result<bool> {
+        let objectives = {
+            let state = self.state.borrow();
+            let def = state
+                .defs
+                .get(quest)
+                .ok_or_else(|| Error::runtime(format!("no quest named '{}'", quest)))?;
+            def.stages.first().map_or(0, |stage| stage.objectives.len())
+        };
+        if !world.has::<QuestLog>(entity) {
+            world.insert(entity, QuestLog::default())?;
+        }
+        {
+            let mut log = world.get_mut::<QuestLog>(entity).expect("inserted");
+            if log.active.contains_key(quest) || log.completed.contains(quest) {
+                return Ok(false);
+            }
+            let progress = QuestProgress {
+                stage: 0,
+                counts: vec![0.0; objectives],
+            };
+            log.active.insert(quest.to_string(), progress);
+        }
+        world.emit(
+            QUEST_EVENT,
+            QuestEvent::Started {
+                quest: quest.to_string(),
+                entity,
+            },
+        )?;
+        Ok(true)
+    }
+
+    /// Drops an active quest and its progress. Returns whether it was
+    /// active.
+    pub fn abandon(&self, world: &mut World, entity: Entity, quest: &str) -> Result<bool> {
+        let removed = world
+            .get_mut::<QuestLog>(entity)
+            .is_some_and(|mut log| log.active.remove(quest).is_some());
+        if removed {
+            world.emit(
+                QUEST_EVENT,
+                QuestEvent::Abandoned {
+                    quest: quest.to_string(),
+                    entity,
+                },
+            )?;
+        }
+        Ok(removed)
+    }
+
+    /// Checks every active quest's current stage against the script
+    /// events `ScriptEvents::unseen` finds, completing at most one stage
+    /// per quest. Event objectives count events from anyone, so filter on
+    /// a payload field to tell holders apart. A custom objective whose hook
+    /// fails is logged and keeps its count.
+    pub fn update(&self, world: &mut World, lua: &Lua) -> Result<()> {
+        let mut holders: Vec<(Entity, QuestLog)> = Vec::new();
+        world
+            .query::<&QuestLog>()
+            .for_each(|entity, log| holders.push((entity, log.clone())));
+        let fresh = self.fresh_events(world, &holders);
+
+        for (entity, mut log) in holders {
+            let mut events = Vec::new();
+            let mut rewards = Vec::new();
+            for (quest, progress) in &mut log.active {
+                let Some(def) = self.state.borrow().defs.get(quest).cloned() else {
+                    continue;
+                };
+                let Some(stage) = def.stages.get(progress.stage) else {
+                    continue;
+                };
+                progress.counts.resize(stage.objectives.len(), 0.0);
+                for (i, objective) in stage.objectives.iter().enumerate() {
+                    let count = match &objective.kind {
+                        ObjectiveKind::Event { event, with } => {
+                            let new = fresh.get(event).map_or(&[][..], Vec::as_slice);
+                            let hits = new.iter().filter(|payload| matches(payload, with)).count();
+                            progress.counts[i] + hits as f64
+                        }
+                        ObjectiveKind::Holds(condition) => {
+                            match condition.check(world, Some(entity)) {
+                                true => objective.count,
+                                false => 0.0,
+                            }
+                        }
+                        ObjectiveKind::Custom { kind, params } => {
+                            match self.call_hook(world, lua, kind, entity, params) {
+                                Ok(count) => count,
+                                Err(e) => {
+                                    log::error!(
+                                        target: "quests",
+                                        "objective '{}' of '{}' failed: {}",
+                                        kind,
+                                        quest,
+                                        e
+                                    );
+                                    continue;
+                                }
+                            }
+                        }
+                    };
+                    let count = count.min(objective.count);
+                    if count != progress.counts[i] {
+                        progress.counts[i] = count;
+                        events.push(QuestEvent::Progress {
+                            quest: quest.clone(),
+                            entity,
+                            objective: i,
+                            count,
+                        });
+                    }
+                }
+                let done = stage
+                    .objectives
+                    .iter()
+                    .zip(&progress.counts)
+                    .all(|(objective, &count)| count >= objective.count);
+                if !done {
+                    continue;
+                }
+                events.push(QuestEvent::StageCompleted {
+                    quest: quest.clone(),
+                    entity,
+                    stage: progress.stage,
+                });
+                rewards.push((quest.clone(), stage.rewards.clone()));
+                progress.stage += 1;
+                match def.stages.get(progress.stage) {
+                    Some(next) => progress.counts = vec![0.0; next.objectives.len()],
+                    None => {
+                        rewards.push((quest.clone(), def.rewards.clone()));
+                        events.push(QuestEvent::Completed {
+                            quest: quest.clone(),
+                            entity,
+                        });
+                    }
+                }
+            }
+            for event in &events {
+                if let QuestEvent::Completed { quest, .. } = event {
+                    log.active.remove(quest);
+                    log.completed.insert(quest.clone());
+                }
+            }
+            if let Some(mut stored) = world.get_mut::<QuestLog>(entity) {
+                *stored = log;
+            }
+            for event in events {
+                world.emit(QUEST_EVENT, event)?;
+            }
+            for (quest, tables) in rewards {
+                self.reward(world, entity, &quest, &tables)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The events new since the last update, for every event objective in
+    /// a current stage.
+    fn fresh_events(
+        &self,
+        world: &World,
+        holders: &[(Entity, QuestLog)],
+    ) -> BTreeMap<String, Vec<ScriptValue>> {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        let mut fresh = BTreeMap::new();
+        let Some(events) = world.resource::<ScriptEvents>() else {
+            return fresh;
+        };
+        let stages = holders
+            .iter()
+            .flat_map(|(_, log)| &log.active)
+            .filter_map(|(quest, progress)| state.defs.get(quest)?.stages.get(progress.stage));
+        for objective in stages.flat_map(|stage| &stage.objectives) {
+            if let ObjectiveKind::Event { event, .. } = &objective.kind
+                && !fresh.contains_key(event)
+            {
+                let seen = state.seen.entry(event.clone()).or_default();
+                fresh.insert(event.clone(), events.unseen(event, seen).to_vec());
+            }
+        }
+        fresh
+    }
+
+    fn call_hook(
+        &self,
+        world: &mut World,
+        lua: &Lua,
+        kind: &str,
+        entity: Entity,
+        params: &BTreeMap<String, ScriptValue>,
+    ) -> Result<f64> {
+        let hook = self.state.borrow().hooks.get(kind).cloned();
+        match hook {
+            Some(ObjectiveHook::Rust(hook)) => hook(world, lua, entity, params),
+            Some(ObjectiveHook::Lua(hook)) => lua.scope(|scope| {
+                let handle = scope.create_userdata_ref_mut(&mut *world)?;
+                hook.call::<f64>((handle, entity, lua.to_value(params)?))
+            }),
+            None => Err(Error::runtime(format!(
+                "no objective hook for kind '{}'",
+                kind
+            ))),
+        }
+    }
+
+    fn reward(
+        &self,
+        world: &mut World,
+        entity: Entity,
+        quest: &str,
+        tables: &[String],
+    ) -> Result<()> {
+        if tables.is_empty() {
+            return Ok(());
+        }
+        let mut drops = Vec::new();
+        {
+            let mut state = self.state.borrow_mut();
+            let Some((loot, rng)) = &mut state.loot else {
+                log::warn!(target: "quests", "no loot tables for the rewards of '{}'", quest);
+                return Ok(());
+            };
+            for table in tables {
+                drops.extend(loot.tables().roll(table, world, Some(entity), rng)?);
+            }
+        }
+        if world.has::<Inventory>(entity) {
+            for item in &drops {
+                if let Err(e) = give(world, entity, item, 1) {
+                    log::warn!(target: "quests", "reward '{}' for '{}': {}", item, quest, e);
+                }
+            }
+        }
+        world.emit(
+            QUEST_EVENT,
+            QuestEvent::Rewarded {
+                quest: quest.to_string(),
+                entity,
+                drops,
+            },
+        )
+    }
+
+    /// An exclusive system updating quests once per schedule run.
+    pub fn into_system(self) -> impl FnMut(&mut World, &Lua) -> Result<()> {
+        move |world, lua| self.update(world, lua)
+    }
+
+    /// Adds the `quests` global: `start(world, entity, id)`,
+    /// `abandon(world, entity, id)`, `log(world, entity)` as
+    /// `{ active = { [id] = { stage, counts } }, completed }` or nil,
+    /// `objective(kind, function(world, entity, params))` and `load(path)`,
+    /// all as methods. Stages and objectives count from 0, as in events.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("quests", self.clone())
+    }
+}
+
+impl UserData for Quests {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method(
+            "start",
+            |_, this, (world, entity, id): (AnyUserData, Entity, String)| {
+                world.borrow_mut_scoped::<World, _>(|world| this.start(world, entity, &id))?
+            },
+        );
+        methods.add_method(
+            "abandon",
+            |_, this, (world, entity, id): (AnyUserData, Entity, String)| {
+                world.borrow_mut_scoped::<World, _>(|world| this.abandon(world, entity, &id))?
+            },
+        );
+        methods.add_method("log", |lua, _, (world, entity): (AnyUserData, Entity)| {
+            let log = world.borrow_scoped::<World, _>(|world| {
+                world.get::<QuestLog>(entity).map(|log| log.clone())
+            })?;
+            log.map(|log| lua.to_value(&log)).transpose()
+        });
+        methods.add_method("objective", |_, this, (kind, hook): (String, Function)| {
+            let mut state = this.state.borrow_mut();
+            state.hooks.insert(kind, ObjectiveHook::Lua(hook));
+            Ok(())
+        });
+        methods.add_method("load", |lua, this, path: String| {
+            let loaded = QuestDefs::from_ron(&read_lua_asset(lua, &path)?)?;
+            this.defs_mut().extend(loaded);
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameplay::{ItemDefs, register_components};
+    use crate::loot::LootTables;
+
+    const QUESTS: &str = r#"{
+        "cull": (
+            name: "Cull the Goblins",
+            stages: [
+                (objectives: [
+                    (kind: Event(event: "kill", with: { "kind": "Goblin" }), count: 5),
+                ]),
+                (
+                    objectives: [(kind: Custom(kind: "visit", params: { "place": "camp" }))],
+                    rewards: ["camp_chest"],
+                ),
+            ],
+            rewards: ["bounty"],
+        ),
+    }"#;
+
+    fn kill(kind: &str) -> ScriptValue {
+        ScriptValue::Map(BTreeMap::from([(
+            "kind".to_string(),
+            ScriptValue::String(kind.to_string()),
+        )]))
+    }
+
+    #[test]
+    fn test_stages_rewards_and_custom_objectives() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        register_components(&mut world);
+        world.insert_resource(ItemDefs::from_ron(
+            r#"{ "gold": (max_stack: 99), "map": () }"#,
+        )?);
+        let player = world.spawn();
+        world.insert(player, Inventory::new(4))?;
+
+        let quests = Quests::new(QuestDefs::from_ron(QUESTS)?);
+        let tables = LootTables::from_ron(
+            r#"{
+                "camp_chest": (always: [(drop: Prefab("map"))]),
+                "bounty": (always: [(drop: Prefab("gold"), count: (10, 10))]),
+            }"#,
+        )?;
+        quests.set_loot(Loot::new(tables), GameRng::new(7));
+        quests.register_lua(&lua)?;
+        lua.load(
+            r#"
+            visited = false
+            quests:objective("visit", function(world, entity, params)
+                return (visited and params.place == "camp") and 1 or 0
+            end)
+        "#,
+        )
+        .exec()?;
+
+        assert!(quests.start(&mut world, player, "cull")?);
+        assert!(!quests.start(&mut world, player, "cull")?);
+        for kind in ["Goblin", "Wolf", "Goblin", "Goblin", "Goblin"] {
+            world.send_script_event("kill", kill(kind));
+        }
+        quests.update(&mut world, &lua)?;
+        let counts = |world: &World| world.get::<QuestLog>(player).unwrap().active["cull"].clone();
+        assert_eq!(counts(&world).counts, [4.0]);
+        // Already counted events are not counted again.
+        world.send_script_event("kill", kill("Goblin"));
+        quests.update(&mut world, &lua)?;
+        assert_eq!(counts(&world).stage, 1);
+
+        quests.update(&mut world, &lua)?;
+        assert_eq!(counts(&world).counts, [0.0]);
+        lua.globals().set("visited", true)?;
+        quests.update(&mut world, &lua)?;
+
+        let log = world.get::<QuestLog>(player).unwrap().clone();
+        assert!(log.active.is_empty() && log.completed.contains("cull"));
+        assert_eq!(world.get::<Inventory>(player).unwrap().count("map"), 1);
+        assert_eq!(world.get::<Inventory>(player).unwrap().count("gold"), 10);
+        let kinds: Vec<&str> = world
+            .events::<QuestEvent>()
+            .unwrap()
+            .iter()
+            .map(|event| match event {
+                QuestEvent::Started { .. } => "started",
+                QuestEvent::Progress { .. } => "progress",
+                QuestEvent::StageCompleted { .. } => "stage",
+                QuestEvent::Completed { .. } => "completed",
+                QuestEvent::Abandoned { .. } => "abandoned",
+                QuestEvent::Rewarded { .. } => "rewarded",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "started",
+                "progress",
+                "progress",
+                "stage",
+                "progress",
+                "stage",
+                "completed",
+                "rewarded",
+                "rewarded"
+            ]
+        );
+
+        // The log survives a scene round trip, as a save would do it.
+        let scene = crate::scene::Scene::capture(&world, &lua)?;
+        let mut loaded = World::new();
+        register_components(&mut loaded);
+        let spawned = scene.spawn(&mut loaded, &lua)?;
+        assert!(
+            loaded
+                .get::<QuestLog>(spawned[0])
+                .unwrap()
+                .completed
+                .contains("cull")
+        );
+        assert!(!quests.start(&mut loaded, spawned[0], "cull")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failing_objective_hook_is_skipped() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        register_components(&mut world);
+        let player = world.spawn();
+        let quests = Quests::new(QuestDefs::from_ron(
+            r#"{
+                "chores": (stages: [(objectives: [
+                    (kind: Custom(kind: "broken")),
+                    (kind: Custom(kind: "fine")),
+                ])]),
+            }"#,
+        )?);
+        quests.register_lua(&lua)?;
+        lua.load(
+            r#"
+            quests:objective("broken", function() error("oops") end)
+            quests:objective("fine", function() return 1 end)
+        "#,
+        )
+        .exec()?;
+        quests.start(&mut world, player, "chores")?;
+        quests.update(&mut world, &lua)?;
+        let log = world.get::<QuestLog>(player).unwrap().clone();
+        assert_eq!(log.active["chores"].counts, [0.0, 1.0]);
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+use crate::ecs::{EcsError, Entity, World};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModifierKind {
+    /// Added to the base.
+    Add,
+    /// Multiplies the base plus every `Add`.
+    Multiply,
+}
+
+/// One change to a stat, tagged with what applied it so everything from
+/// one buff, item or aura can be removed together.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Modifier {
+    pub stat: String,
+    pub kind: ModifierKind,
+    pub value: f64,
+    pub source: String,
+    /// Seconds left; permanent until removed when unset.
+    #[serde(default)]
+    pub duration: Option<f64>,
+}
+
+impl Modifier {
+    pub fn add(stat: &str, value: f64, source: &str) -> Self {
+        Modifier {
+            stat: stat.to_string(),
+            kind: ModifierKind::Add,
+            value,
+            source: source.to_string(),
+            duration: None,
+        }
+    }
+
+    pub fn multiply(stat: &str, value: f64, source: &str) -> Self {
+        Modifier {
+            kind: ModifierKind::Multiply,
+            ..Modifier::add(stat, value, source)
+        }
+    }
+
+    pub fn lasting(self, seconds: f64) -> Self {
+        Modifier {
+            duration: Some(seconds),
+            ..self
+        }
+    }
+}
+
+/// Base values and the modifiers on top of them. A stat's value is
+/// `(base + adds) * multiplies`; stats with no base count from zero.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stats {
+    pub base: BTreeMap<String, f64>,
+    pub modifiers: Vec<Modifier>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats::default()
+    }
+
+    pub fn with(mut self, stat: &str, base: f64) -> Self {
+        self.base.insert(stat.to_string(), base);
+        self
+    }
+
+    pub fn base(&self, stat: &str) -> f64 {
+        self.base.get(stat).copied().unwrap_or(0.0)
+    }
+
+    pub fn get(&self, stat: &str) -> f64 {
+        let (mut add, mut multiply) = (0.0, 1.0);
+        for modifier in self.modifiers.iter().filter(|m| m.stat == stat) {
+            match modifier.kind {
+                ModifierKind::Add => add += modifier.value,
+                ModifierKind::Multiply => multiply *= modifier.value,
+            }
+        }
+        (self.base(stat) + add) * multiply
+    }
+
+    /// Every stat with a base or a modifier.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .base
+            .keys()
+            .map(String::as_str)
+            .chain(self.modifiers.iter().map(|m| m.stat.as_str()))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    pub fn has_source(&self, source: &str) -> bool {
+        self.modifiers.iter().any(|m| m.source == source)
+    }
+}
+
+/// Sent whenever a stat's value changes through the functions here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatChanged {
+    pub entity: Entity,
+    pub stat: String,
+    pub old: f64,
+    pub new: f64,
+}
+
+/// Applies `change` to the entity's `Stats`, adding the component if
+/// missing, and sends a `StatChanged` for each stat whose value moved.
+fn change_stats<R>(
+    world: &mut World,
+    entity: Entity,
+    change: impl FnOnce(&mut Stats) -> R,
+) -> Result<R, EcsError> {
+    if !world.has::<Stats>(entity) {
+        world.insert(entity, Stats::default())?;
+    }
+    let (result, changes) = {
+        let mut stats = world.get_mut::<Stats>(entity).expect("inserted above");
+        let before: Vec<(String, f64)> = stats
+            .names()
+            .into_iter()
+            .map(|name| (name.to_string(), stats.get(name)))
+            .collect();
+        let result = change(&mut stats);
+        let mut names: Vec<String> = stats.names().into_iter().map(String::from).collect();
+        names.extend(before.iter().map(|(name, _)| name.clone()));
+        names.sort();
+        names.dedup();
+        let changes: Vec<StatChanged> = names
+            .into_iter()
+            .filter_map(|stat| {
+                let old = before
+                    .iter()
+                    .find(|(name, _)| *name == stat)
+                    .map_or(0.0, |&(_, value)| value);
+                let new = stats.get(&stat);
+                (old != new).then_some(StatChanged {
+                    entity,
+                    stat,
+                    old,
+                    new,
+                })
+            })
+            .collect();
+        (result, changes)
+    };
+    for change in changes {
+        world.send_event(change);
+    }
+    Ok(result)
+}
+
+/// The stat's current value, or `None` without a `Stats` component.
+pub fn stat(world: &World, entity: Entity, stat: &str) -> Option<f64> {
+    world.get::<Stats>(entity).map(|stats| stats.get(stat))
+}
+
+pub fn set_base_stat(
+    world: &mut World,
+    entity: Entity,
+    stat: &str,
+    value: f64,
+) -> Result<(), EcsError> {
+    change_stats(world, entity, |stats| {
+        stats.base.insert(stat.to_string(), value);
+    })
+}
+
+pub fn add_modifier(world: &mut World, entity: Entity, modifier: Modifier) -> Result<(), EcsError> {
+    change_stats(world, entity, |stats| stats.modifiers.push(modifier))
+}
+
+/// Removes every modifier from `source`, returning how many went.
+pub fn remove_modifiers(
+    world: &mut World,
+    entity: Entity,
+    source: &str,
+) -> Result<usize, EcsError> {
+    if !world.is_alive(entity) {
+        return Err(EcsError::NoSuchEntity(entity));
+    }
+    if !world
+        .get::<Stats>(entity)
+        .is_some_and(|s| s.has_source(source))
+    {
+        return Ok(0);
+    }
+    change_stats(world, entity, |stats| {
+        let before = stats.modifiers.len();
+        stats.modifiers.retain(|m| m.source != source);
+        before - stats.modifiers.len()
+    })
+}
+
+/// Entities carrying at least one modifier from `source`, e.g. everyone
+/// inside an aura.
+pub fn with_modifier_source(world: &World, source: &str) -> Vec<Entity> {
+    let mut found = Vec::new();
+    world.query::<&Stats>().for_each(|entity, stats| {
+        if stats.has_source(source) {
+            found.push(entity);
+        }
+    });
+    found
+}
+
+/// Counts timed modifiers down by `dt` and drops the ones that ran out.
+pub fn update_stats(world: &mut World, dt: f64) {
+    let mut expiring = Vec::new();
+    world.query::<&mut Stats>().for_each(|entity, stats| {
+        let mut expired = false;
+        for modifier in &mut stats.modifiers {
+            if let Some(left) = &mut modifier.duration {
+                *left -= dt;
+                expired |= *left <= 0.0;
+            }
+        }
+        if expired {
+            expiring.push(entity);
+        }
+    });
+    for entity in expiring {
+        change_stats(world, entity, |stats| {
+            stats
+                .modifiers
+                .retain(|m| m.duration.is_none_or(|left| left > 0.0));
+        })
+        .expect("queried alive");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modifiers_stack_expire_and_report() {
+        let mut world = World::new();
+        let hero = world.spawn();
+        world
+            .insert(hero, Stats::new().with("strength", 10.0))
+            .unwrap();
+        add_modifier(&mut world, hero, Modifier::add("strength", 5.0, "ring")).unwrap();
+        add_modifier(
+            &mut world,
+            hero,
+            Modifier::multiply("strength", 2.0, "rage").lasting(3.0),
+        )
+        .unwrap();
+        add_modifier(&mut world, hero, Modifier::add("speed", 1.0, "ring")).unwrap();
+        assert_eq!(stat(&world, hero, "strength"), Some(30.0));
+        assert_eq!(with_modifier_source(&world, "rage"), vec![hero]);
+
+        let changes = world.drain_events::<StatChanged>();
+        assert_eq!(changes.len(), 3);
+        assert_eq!((changes[1].old, changes[1].new), (15.0, 30.0));
+
+        update_stats(&mut world, 2.0);
+        assert!(world.drain_events::<StatChanged>().is_empty());
+        update_stats(&mut world, 1.5);
+        assert_eq!(stat(&world, hero, "strength"), Some(15.0));
+        let changes = world.drain_events::<StatChanged>();
+        assert_eq!(
+            (changes[0].stat.as_str(), changes[0].new),
+            ("strength", 15.0)
+        );
+
+        assert_eq!(remove_modifiers(&mut world, hero, "ring").unwrap(), 2);
+        assert_eq!(stat(&world, hero, "speed"), Some(0.0));
+        assert_eq!(world.drain_events::<StatChanged>().len(), 2);
+    }
+
+    #[test]
+    fn test_lua_stats() -> mlua::Result<()> {
+        let lua = mlua::Lua::new();
+        let mut world = World::new();
+        let (value, changes): (f64, usize) = lua.scope(|scope| {
+            let world = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world = ...
+                local hero = world:spawn({})
+                assert(world:stat(hero, "armor") == nil)
+                world:set_base_stat(hero, "armor", 4)
+                world:add_modifier(hero, { stat = "armor", kind = "multiply", value = 1.5, source = "shield" })
+                local changes = world:stat_changes()
+                assert(changes[2].old == 4 and changes[2].new == 6)
+                world:remove_modifiers(hero, "shield")
+                return world:stat(hero, "armor"), #world:stat_changes()
+            "#,
+            )
+            .call(world)
+        })?;
+        assert_eq!((value, changes), (4.0, 1));
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+use crate::debug_draw::{self, DebugDraw};
+use crate::ecs::{Entity, World};
+use crate::math::{Transform, Vec2};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GizmoMode {
+    #[default]
+    Translate,
+    Rotate,
+    Scale,
+}
+
+/// The part of a gizmo under the pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoHandle {
+    /// The local X axis arrow.
+    X,
+    Y,
+    /// The square at the origin: free translation or uniform scale.
+    Center,
+    /// The rotation ring.
+    Ring,
+}
+
+/// One finished drag: the selected entity's transform before and after,
+/// ready for an undo history.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransformEdit {
+    pub entity: Entity,
+    pub before: Transform,
+    pub after: Transform,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Drag {
+    handle: GizmoHandle,
+    start_pointer: Vec2,
+    start: Transform,
+}
+
+/// Editor-style handles on the selected entity's `Transform`. Feed it
+/// pointer input in world space; it edits the transform while dragging
+/// and draws itself into the debug-draw layer.
+#[derive(Debug, Clone)]
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    pub selected: Option<Entity>,
+    /// Handle length in world units.
+    pub size: f64,
+    drag: Option<Drag>,
+}
+
+impl Default for Gizmo {
+    fn default() -> Self {
+        Gizmo {
+            mode: GizmoMode::Translate,
+            selected: None,
+            size: 1.0,
+            drag: None,
+        }
+    }
+}
+
+fn distance_to_segment(point: Vec2, from: Vec2, to: Vec2) -> f64 {
+    let along = to - from;
+    let t = ((point - from).dot(along) / along.dot(along).max(f64::EPSILON)).clamp(0.0, 1.0);
+    (point - (from + along * t)).length()
+}
+
+impl Gizmo {
+    pub fn new() -> Self {
+        Gizmo::default()
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    fn transform(&self, world: &World) -> Option<(Entity, Transform)> {
+        let entity = self.selected?;
+        Some((entity, *world.get::<Transform>(entity)?))
+    }
+
+    /// The handle at `point`, if any, for the current mode.
+    pub fn hit(&self, world: &World, point: Vec2) -> Option<GizmoHandle> {
+        let (_, transform) = self.transform(world)?;
+        let origin = transform.translation;
+        let tolerance = self.size * 0.1;
+        if self.mode == GizmoMode::Rotate {
+            let ring = ((point - origin).length() - self.size).abs();
+            return (ring <= tolerance).then_some(GizmoHandle::Ring);
+        }
+        let local = point - origin;
+        if local.x.abs().max(local.y.abs()) <= self.size * 0.15 {
+            return Some(GizmoHandle::Center);
+        }
+        let (x, y) = transform.axes();
+        [(GizmoHandle::X, x), (GizmoHandle::Y, y)]
+            .into_iter()
+            .map(|(handle, axis)| {
+                (
+                    handle,
+                    distance_to_segment(point, origin, origin + axis * self.size),
+                )
+            })
+            .filter(|&(_, distance)| distance <= tolerance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(handle, _)| handle)
+    }
+
+    /// Starts dragging the handle under `point`. Returns whether one was hit.
+    pub fn pointer_down(&mut self, world: &World, point: Vec2) -> bool {
+        let Some(handle) = self.hit(world, point) else {
+            return false;
+        };
+        let (_, start) = self.transform(world).expect("hit needs a transform");
+        self.drag = Some(Drag {
+            handle,
+            start_pointer: point,
+            start,
+        });
+        true
+    }
+
+    /// Applies the drag so far to the selected transform.
+    pub fn pointer_move(&mut self, world: &mut World, point: Vec2) {
+        let (Some(drag), Some(entity)) = (self.drag, self.selected) else {
+            return;
+        };
+        let edited = self.apply(drag, point);
+        if let Some(mut transform) = world.get_mut::<Transform>(entity) {
+            *transform = edited;
+        }
+    }
+
+    /// Ends the drag, returning the edit it made.
+    pub fn pointer_up(&mut self, world: &World) -> Option<TransformEdit> {
+        let drag = self.drag.take()?;
+        let (entity, after) = self.transform(world)?;
+        (after != drag.start).then_some(TransformEdit {
+            entity,
+            before: drag.start,
+            after,
+        })
+    }
+
+    /// Puts the transform back as it was when the drag started.
+    pub fn cancel(&mut self, world: &mut World) {
+        if let (Some(drag), Some(entity)) = (self.drag.take(), self.selected)
+            && let Some(mut transform) = world.get_mut::<Transform>(entity)
+        {
+            *transform = drag.start;
+        }
+    }
+
+    fn apply(&self, drag: Drag, point: Vec2) -> Transform {
+        let start = drag.start;
+        let origin = start.translation;
+        let (x, y) = start.axes();
+        let delta = point - drag.start_pointer;
+        let mut edited = start;
+        match (self.mode, drag.handle) {
+            (GizmoMode::Translate, GizmoHandle::X) => {
+                edited.translation = origin + x * delta.dot(x)
+            }
+            (GizmoMode::Translate, GizmoHandle::Y) => {
+                edited.translation = origin + y * delta.dot(y)
+            }
+            (GizmoMode::Translate, _) => edited.translation = origin + delta,
+            (GizmoMode::Rotate, _) => {
+                let from = drag.start_pointer - origin;
+                let to = point - origin;
+                edited.rotation = start.rotation + from.cross(to).atan2(from.dot(to));
+            }
+            (GizmoMode::Scale, handle) => {
+                let ratio = |axis: Option<Vec2>| {
+                    let (from, to) = match axis {
+                        Some(axis) => (
+                            (drag.start_pointer - origin).dot(axis),
+                            (point - origin).dot(axis),
+                        ),
+                        None => (
+                            (drag.start_pointer - origin).length(),
+                            (point - origin).length(),
+                        ),
+                    };
+                    if from.abs() < f64::EPSILON {
+                        1.0
+                    } else {
+                        to / from
+                    }
+                };
+                match handle {
+                    GizmoHandle::X => edited.scale.x = start.scale.x * ratio(Some(x)),
+                    GizmoHandle::Y => edited.scale.y = start.scale.y * ratio(Some(y)),
+                    _ => edited.scale = start.scale * ratio(None),
+                }
+            }
+        }
+        edited
+    }
+
+    /// Adds the handles for the current mode, highlighting the one being
+    /// dragged.
+    pub fn draw(&self, world: &World, draw: &mut DebugDraw) {
+        let Some((_, transform)) = self.transform(world) else {
+            return;
+        };
+        let origin = transform.translation;
+        let active = self.drag.map(|drag| drag.handle);
+        let color = |handle, color| {
+            if active == Some(handle) {
+                debug_draw::YELLOW
+            } else {
+                color
+            }
+        };
+        if self.mode == GizmoMode::Rotate {
+            draw.circle(
+                origin,
+                self.size,
+                color(GizmoHandle::Ring, debug_draw::BLUE),
+            );
+            return;
+        }
+        let (x, y) = transform.axes();
+        for (handle, axis, base) in [
+            (GizmoHandle::X, x, debug_draw::RED),
+            (GizmoHandle::Y, y, debug_draw::GREEN),
+        ] {
+            let tip = origin + axis * self.size;
+            let color = color(handle, base);
+            draw.line(origin, tip, color);
+            let side = axis.rotate(std::f64::consts::FRAC_PI_2) * (self.size * 0.08);
+            let back = tip - axis * (self.size * 0.15);
+            if self.mode == GizmoMode::Translate {
+                draw.line(tip, back + side, color);
+                draw.line(tip, back - side, color);
+            } else {
+                let half = Vec2::new(self.size * 0.06, self.size * 0.06);
+                draw.rect(tip - half, tip + half, color);
+            }
+        }
+        let half = Vec2::new(self.size * 0.15, self.size * 0.15);
+        draw.rect(
+            origin - half,
+            origin + half,
+            color(GizmoHandle::Center, debug_draw::WHITE),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    fn near(a: Vec2, b: Vec2) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    #[test]
+    fn test_drag_handles_edit_transform() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world
+            .insert(entity, Transform::from_translation(Vec2::new(2.0, 0.0)))
+            .unwrap();
+        let mut gizmo = Gizmo::new();
+        gizmo.selected = Some(entity);
+
+        // Dragging the X arrow diagonally only moves along X.
+        assert!(gizmo.pointer_down(&world, Vec2::new(2.9, 0.0)));
+        gizmo.pointer_move(&mut world, Vec2::new(4.9, 3.0));
+        let edit = gizmo.pointer_up(&world).unwrap();
+        assert!(near(edit.after.translation, Vec2::new(4.0, 0.0)));
+        assert_eq!(edit.before.translation, Vec2::new(2.0, 0.0));
+
+        gizmo.mode = GizmoMode::Rotate;
+        assert!(!gizmo.pointer_down(&world, Vec2::new(4.0, 0.0)));
+        assert!(gizmo.pointer_down(&world, Vec2::new(5.0, 0.0)));
+        gizmo.pointer_move(&mut world, Vec2::new(4.0, 1.0));
+        gizmo.pointer_up(&world);
+        assert!((world.get::<Transform>(entity).unwrap().rotation - FRAC_PI_2).abs() < 1e-9);
+
+        // The X handle now points up; stretching it doubles scale.x.
+        gizmo.mode = GizmoMode::Scale;
+        assert_eq!(gizmo.hit(&world, Vec2::new(4.0, 1.0)), Some(GizmoHandle::X));
+        assert!(gizmo.pointer_down(&world, Vec2::new(4.0, 1.0)));
+        gizmo.pointer_move(&mut world, Vec2::new(4.0, 2.0));
+        gizmo.cancel(&mut world);
+        assert_eq!(
+            world.get::<Transform>(entity).unwrap().scale,
+            Vec2::new(1.0, 1.0)
+        );
+        assert!(gizmo.pointer_down(&world, Vec2::new(4.0, 1.0)));
+        gizmo.pointer_move(&mut world, Vec2::new(4.0, 2.0));
+        gizmo.pointer_up(&world);
+        assert_eq!(
+            world.get::<Transform>(entity).unwrap().scale,
+            Vec2::new(2.0, 1.0)
+        );
+
+        let mut draw = DebugDraw::new();
+        gizmo.draw(&world, &mut draw);
+        assert_eq!(draw.items().len(), 5);
+        assert!(near(
+            match draw.items()[0].shape {
+                crate::debug_draw::DebugShape::Line { to, .. } => to,
+                _ => Vec2::ZERO,
+            },
+            Vec2::new(4.0, 1.0)
+        ));
+    }
+}
@@ -0,0 +1,324 @@
+use crate::data::{DataError, from_ron};
+use mlua::{Lua, Result, Table, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// A translated string, or one per plural category (`zero`, `one`, `two`,
+/// `few`, `many`, `other`) picked by the `count` argument.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Message {
+    Text(String),
+    Plural(BTreeMap<String, String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrArg {
+    Number(f64),
+    Text(String),
+}
+
+impl From<f64> for TrArg {
+    fn from(n: f64) -> Self {
+        TrArg::Number(n)
+    }
+}
+
+impl From<i64> for TrArg {
+    fn from(n: i64) -> Self {
+        TrArg::Number(n as f64)
+    }
+}
+
+impl From<&str> for TrArg {
+    fn from(s: &str) -> Self {
+        TrArg::Text(s.to_string())
+    }
+}
+
+impl From<String> for TrArg {
+    fn from(s: String) -> Self {
+        TrArg::Text(s)
+    }
+}
+
+impl TrArg {
+    fn render(&self) -> String {
+        match self {
+            TrArg::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => format!("{}", *n as i64),
+            TrArg::Number(n) => n.to_string(),
+            TrArg::Text(s) => s.clone(),
+        }
+    }
+}
+
+/// The CLDR plural category of `n` for `language`, covering the common
+/// rule families; unknown languages use the English rule.
+pub fn plural_category(language: &str, n: f64) -> &'static str {
+    let base = language.split(['-', '_']).next().unwrap_or(language);
+    let integer = n.fract() == 0.0;
+    let i = n.abs() as u64;
+    match base {
+        "ja" | "zh" | "ko" | "th" | "vi" | "id" => "other",
+        "fr" | "pt" if i < 2 => "one",
+        "fr" | "pt" => "other",
+        "ru" | "uk" | "be" | "pl" | "cs" | "sk" if !integer => "other",
+        "cs" | "sk" => match i {
+            1 => "one",
+            2..=4 => "few",
+            _ => "many",
+        },
+        "pl" if i == 1 => "one",
+        "ru" | "uk" | "be" if i % 10 == 1 && i % 100 != 11 => "one",
+        "ru" | "uk" | "be" | "pl"
+            if (2..=4).contains(&(i % 10)) && !(12..=14).contains(&(i % 100)) =>
+        {
+            "few"
+        }
+        "ru" | "uk" | "be" | "pl" => "many",
+        _ if integer && i == 1 => "one",
+        _ => "other",
+    }
+}
+
+/// One language's messages, loaded from a RON map of key to `Message`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Locale {
+    pub language: String,
+    messages: BTreeMap<String, Message>,
+}
+
+impl Locale {
+    pub fn new(language: &str) -> Self {
+        Locale {
+            language: language.to_string(),
+            messages: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_ron(language: &str, source: &str) -> std::result::Result<Self, DataError> {
+        Ok(Locale {
+            language: language.to_string(),
+            messages: from_ron(source)?,
+        })
+    }
+
+    /// Loads `path`, naming the language after the file stem, e.g. `fr.ron`.
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        let path = path.as_ref();
+        let language = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| DataError::Invalid(format!("no language in '{}'", path.display())))?;
+        Locale::from_ron(language, &std::fs::read_to_string(path)?)
+    }
+
+    pub fn insert(&mut self, key: &str, message: Message) {
+        self.messages.insert(key.to_string(), message);
+    }
+
+    pub fn tr(&self, key: &str) -> String {
+        self.tr_args(key, &[])
+    }
+
+    /// Looks up `key` and replaces each `{name}` with its argument. A
+    /// missing key comes back as the key itself, so untranslated strings
+    /// stand out without breaking the UI.
+    pub fn tr_args(&self, key: &str, args: &[(&str, TrArg)]) -> String {
+        let template = match self.messages.get(key) {
+            None => return key.to_string(),
+            Some(Message::Text(text)) => text.as_str(),
+            Some(Message::Plural(forms)) => {
+                let count = args.iter().find_map(|(name, arg)| match arg {
+                    TrArg::Number(n) if *name == "count" => Some(*n),
+                    _ => None,
+                });
+                let category = plural_category(&self.language, count.unwrap_or(0.0));
+                match forms.get(category).or_else(|| forms.get("other")) {
+                    Some(form) => form.as_str(),
+                    None => return key.to_string(),
+                }
+            }
+        };
+        interpolate(template, args)
+    }
+}
+
+fn interpolate(template: &str, args: &[(&str, TrArg)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+        let name = &after[..end];
+        match args.iter().find(|(arg, _)| *arg == name) {
+            Some((_, value)) => out.push_str(&value.render()),
+            None => out.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The active locale, loaded from `<dir>/<language>.ron`. Kept as a world
+/// resource; clones, including the Lua `tr` global, share the active
+/// locale, so a language switch or reload shows up everywhere.
+#[derive(Debug, Clone)]
+pub struct Localization {
+    dir: PathBuf,
+    locale: Arc<RwLock<Locale>>,
+    modified: Option<SystemTime>,
+}
+
+impl Localization {
+    pub fn new(dir: impl AsRef<Path>, language: &str) -> std::result::Result<Self, DataError> {
+        let mut localization = Localization {
+            dir: dir.as_ref().to_path_buf(),
+            locale: Arc::new(RwLock::new(Locale::new(language))),
+            modified: None,
+        };
+        localization.set_language(language)?;
+        Ok(localization)
+    }
+
+    fn path(&self, language: &str) -> PathBuf {
+        self.dir.join(format!("{}.ron", language))
+    }
+
+    pub fn language(&self) -> String {
+        self.locale().language.clone()
+    }
+
+    fn locale(&self) -> std::sync::RwLockReadGuard<'_, Locale> {
+        self.locale.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Switches to `language`, keeping the current locale if it fails to load.
+    pub fn set_language(&mut self, language: &str) -> std::result::Result<(), DataError> {
+        let path = self.path(language);
+        let locale = Locale::load(&path)?;
+        self.modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        *self.locale.write().unwrap_or_else(|e| e.into_inner()) = locale;
+        Ok(())
+    }
+
+    /// Reloads the active locale if its file changed since it was loaded.
+    pub fn reload_if_changed(&mut self) -> std::result::Result<bool, DataError> {
+        let path = self.path(&self.language());
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == self.modified {
+            return Ok(false);
+        }
+        self.set_language(&self.language())?;
+        Ok(true)
+    }
+
+    pub fn tr(&self, key: &str) -> String {
+        self.locale().tr(key)
+    }
+
+    pub fn tr_args(&self, key: &str, args: &[(&str, TrArg)]) -> String {
+        self.locale().tr_args(key, args)
+    }
+
+    /// Adds `tr(key, args?)`, with `args` a table of names to strings or
+    /// numbers.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        let locale = self.locale.clone();
+        lua.globals().set(
+            "tr",
+            lua.create_function(move |_, (key, args): (String, Option<Table>)| {
+                let mut values = Vec::new();
+                if let Some(args) = args {
+                    for pair in args.pairs::<String, Value>() {
+                        let (name, value) = pair?;
+                        let value = match value {
+                            Value::Integer(n) => TrArg::Number(n as f64),
+                            Value::Number(n) => TrArg::Number(n),
+                            other => TrArg::Text(other.to_string()?),
+                        };
+                        values.push((name, value));
+                    }
+                }
+                let args: Vec<(&str, TrArg)> = values
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.clone()))
+                    .collect();
+                let locale = locale.read().unwrap_or_else(|e| e.into_inner());
+                Ok(locale.tr_args(&key, &args))
+            })?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plurals_and_interpolation() -> std::result::Result<(), DataError> {
+        let en = Locale::from_ron(
+            "en",
+            r#"{
+                "menu.start": "Start",
+                "greeting": "Hello, {name}!",
+                "items": { "one": "{count} item", "other": "{count} items" },
+            }"#,
+        )?;
+        assert_eq!(en.tr("menu.start"), "Start");
+        assert_eq!(en.tr("menu.quit"), "menu.quit");
+        assert_eq!(
+            en.tr_args("greeting", &[("name", "Ada".into())]),
+            "Hello, Ada!"
+        );
+        assert_eq!(en.tr_args("items", &[("count", 1.into())]), "1 item");
+        assert_eq!(en.tr_args("items", &[("count", 5.into())]), "5 items");
+
+        assert_eq!(plural_category("ru", 21.0), "one");
+        assert_eq!(plural_category("ru", 3.0), "few");
+        assert_eq!(plural_category("ru", 12.0), "many");
+        assert_eq!(plural_category("fr", 0.0), "one");
+        assert_eq!(plural_category("ja", 1.0), "other");
+        Ok(())
+    }
+
+    #[test]
+    fn test_switch_and_reload_from_lua() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("i18n_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("en.ron"), r#"{ "menu.start": "Start" }"#).unwrap();
+        std::fs::write(dir.join("de.ron"), r#"{ "menu.start": "Los" }"#).unwrap();
+
+        let lua = Lua::new();
+        let mut localization = Localization::new(&dir, "en")?;
+        localization.register_lua(&lua)?;
+        let tr = || lua.load(r#"return tr("menu.start")"#).eval::<String>();
+        assert_eq!(tr()?, "Start");
+
+        localization.set_language("de")?;
+        assert_eq!(tr()?, "Los");
+        assert!(localization.set_language("xx").is_err());
+        assert_eq!(localization.language(), "de");
+
+        assert!(!localization.reload_if_changed()?);
+        std::fs::write(dir.join("de.ron"), r#"{ "menu.start": "Spiel starten" }"#).unwrap();
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(dir.join("de.ron"))
+            .and_then(|file| file.set_modified(later))
+            .unwrap();
+        assert!(localization.reload_if_changed()?);
+        assert_eq!(tr()?, "Spiel starten");
+        std::fs::remove_dir_all(&dir).ok();
+        Ok(())
+    }
+}
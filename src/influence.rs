@@ -0,0 +1,372 @@
+//! Grid heatmaps for AI. Systems deposit threat, smell or noise where it
+//! happens, each layer decays and spreads to neighbouring cells over time,
+//! and scripts sample it, e.g. `influence:sample("threat", pos)`.
+
+use crate::ecs::World;
+use crate::math::Vec2;
+use crate::time::FixedTimestep;
+use mlua::{Error, Lua, LuaSerdeExt, Result, UserData, UserDataMethods, Value};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+struct Layer {
+    /// Share of the value lost per second, from 0 to 1.
+    decay: f64,
+    /// How fast cells even out with their neighbours, per second.
+    spread: f64,
+    values: Vec<f64>,
+    /// The layer's clock as of each row's last update, so rows the budget
+    /// delays catch up on the time they missed.
+    row_updated: Vec<f64>,
+    clock: f64,
+}
+
+struct InfluenceState {
+    origin: Vec2,
+    cell_size: f64,
+    width: usize,
+    height: usize,
+    layers: BTreeMap<String, Layer>,
+    /// Cells updated per `update` across all layers; `None` is all of them.
+    budget: Option<usize>,
+    /// Where the next update picks up, as a layer index and row.
+    cursor: (usize, usize),
+}
+
+/// A grid of influence layers over the area from `origin`. Clones share
+/// one map, as does the `influence` Lua global, so systems and scripts
+/// see the same values.
+#[derive(Clone)]
+pub struct InfluenceMap {
+    state: Rc<RefCell<InfluenceState>>,
+}
+
+fn no_layer(name: &str) -> Error {
+    Error::runtime(format!("no influence layer named '{}'", name))
+}
+
+impl InfluenceState {
+    fn layer(&self, name: &str) -> Result<&Layer> {
+        self.layers.get(name).ok_or_else(|| no_layer(name))
+    }
+
+    /// `point` in fractional cell coordinates.
+    fn grid(&self, point: Vec2) -> (f64, f64) {
+        (
+            (point.x - self.origin.x) / self.cell_size,
+            (point.y - self.origin.y) / self.cell_size,
+        )
+    }
+
+    fn cell(&self, point: Vec2) -> Option<(usize, usize)> {
+        let (x, y) = self.grid(point);
+        let (x, y) = (x.floor(), y.floor());
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    fn center(&self, (x, y): (usize, usize)) -> Vec2 {
+        Vec2::new(
+            self.origin.x + (x as f64 + 0.5) * self.cell_size,
+            self.origin.y + (y as f64 + 0.5) * self.cell_size,
+        )
+    }
+
+    /// Decays and spreads one row by the time since it was last updated.
+    /// Neighbours are read as they are, some rows ahead and some behind.
+    fn update_row(&mut self, layer: &str, y: usize) {
+        let (width, height) = (self.width, self.height);
+        let Some(layer) = self.layers.get_mut(layer) else {
+            return;
+        };
+        let dt = layer.clock - layer.row_updated[y];
+        layer.row_updated[y] = layer.clock;
+        if dt <= 0.0 {
+            return;
+        }
+        let keep = (1.0 - layer.decay).powf(dt);
+        let even = 1.0 - (-layer.spread * dt).exp();
+        let row: Vec<f64> = (0..width)
+            .map(|x| {
+                let at = |x: usize, y: usize| layer.values[y * width + x];
+                let neighbours = [
+                    (x > 0).then(|| at(x - 1, y)),
+                    (x + 1 < width).then(|| at(x + 1, y)),
+                    (y > 0).then(|| at(x, y - 1)),
+                    (y + 1 < height).then(|| at(x, y + 1)),
+                ];
+                let (sum, count) = neighbours
+                    .into_iter()
+                    .flatten()
+                    .fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+                let value = at(x, y);
+                let average = if count > 0 { sum / count as f64 } else { value };
+                (value + (average - value) * even) * keep
+            })
+            .collect();
+        layer.values[y * width..(y + 1) * width].copy_from_slice(&row);
+    }
+}
+
+impl InfluenceMap {
+    pub fn new(origin: Vec2, cell_size: f64, width: usize, height: usize) -> Self {
+        InfluenceMap {
+            state: Rc::new(RefCell::new(InfluenceState {
+                origin,
+                cell_size,
+                width,
+                height,
+                layers: BTreeMap::new(),
+                budget: None,
+                cursor: (0, 0),
+            })),
+        }
+    }
+
+    /// Adds an empty layer, replacing any of the same name. `decay` is the
+    /// share lost per second, so 0.5 halves values every second, and
+    /// `spread` how fast cells even out with their neighbours.
+    pub fn add_layer(&self, name: &str, decay: f64, spread: f64) {
+        let mut state = self.state.borrow_mut();
+        let layer = Layer {
+            decay: decay.clamp(0.0, 1.0),
+            spread,
+            values: vec![0.0; state.width * state.height],
+            row_updated: vec![0.0; state.height],
+            clock: 0.0,
+        };
+        state.layers.insert(name.to_string(), layer);
+    }
+
+    pub fn layers(&self) -> Vec<String> {
+        self.state.borrow().layers.keys().cloned().collect()
+    }
+
+    /// Caps the cells `update` touches per call, so big maps spread their
+    /// cost over several frames.
+    pub fn set_budget(&self, cells: Option<usize>) {
+        self.state.borrow_mut().budget = cells;
+    }
+
+    /// Adds `amount` to the cell holding `point` and, within `radius`, to
+    /// the cells around it, falling off linearly with distance. Points
+    /// outside the grid are ignored.
+    pub fn deposit(&self, layer: &str, point: Vec2, amount: f64, radius: f64) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        state.layer(layer)?;
+        let Some((cx, cy)) = state.cell(point) else {
+            return Ok(());
+        };
+        let reach = (radius.max(0.0) / state.cell_size).ceil() as usize;
+        let mut deposits = Vec::new();
+        let rows = cy.saturating_sub(reach)..=cy.saturating_add(reach).min(state.height - 1);
+        for y in rows {
+            let columns = cx.saturating_sub(reach)..=cx.saturating_add(reach).min(state.width - 1);
+            for x in columns {
+                let cell = (x, y);
+                let weight = match cell == (cx, cy) {
+                    true => 1.0,
+                    false => 1.0 - (state.center(cell) - point).length() / radius,
+                };
+                if weight > 0.0 {
+                    deposits.push((cell.1 * state.width + cell.0, weight));
+                }
+            }
+        }
+        let layer = state.layers.get_mut(layer).expect("checked above");
+        for (i, weight) in deposits {
+            layer.values[i] += amount * weight;
+        }
+        Ok(())
+    }
+
+    /// The value at `point`, blended bilinearly between cell centers; 0
+    /// outside the grid.
+    pub fn sample(&self, layer: &str, point: Vec2) -> Result<f64> {
+        let state = self.state.borrow();
+        let values = &state.layer(layer)?.values;
+        if state.cell(point).is_none() {
+            return Ok(0.0);
+        }
+        let (gx, gy) = state.grid(point);
+        let (gx, gy) = (gx - 0.5, gy - 0.5);
+        let clamp = |v: f64, len: usize| v.clamp(0.0, len as f64 - 1.0);
+        let (fx, fy) = (clamp(gx, state.width), clamp(gy, state.height));
+        let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+        let (x1, y1) = (
+            (x0 + 1).min(state.width - 1),
+            (y0 + 1).min(state.height - 1),
+        );
+        let (tx, ty) = (fx - x0 as f64, fy - y0 as f64);
+        let at = |x: usize, y: usize| values[y * state.width + x];
+        let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * tx;
+        let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * tx;
+        Ok(top + (bottom - top) * ty)
+    }
+
+    /// The center and value of the strongest cell within `radius` of
+    /// `point`, e.g. where a noise came from; `None` if all are 0 or less.
+    pub fn strongest(&self, layer: &str, point: Vec2, radius: f64) -> Result<Option<(Vec2, f64)>> {
+        let state = self.state.borrow();
+        let values = &state.layer(layer)?.values;
+        let mut best: Option<(Vec2, f64)> = None;
+        for y in 0..state.height {
+            for x in 0..state.width {
+                let center = state.center((x, y));
+                let value = values[y * state.width + x];
+                if value > 0.0
+                    && (center - point).length() <= radius
+                    && best.is_none_or(|(_, top)| value > top)
+                {
+                    best = Some((center, value));
+                }
+            }
+        }
+        Ok(best)
+    }
+
+    pub fn clear(&self, layer: &str) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        let layer = state.layers.get_mut(layer).ok_or_else(|| no_layer(layer))?;
+        layer.values.fill(0.0);
+        Ok(())
+    }
+
+    /// Advances every layer by `dt`, decaying and spreading as many rows as
+    /// the budget allows and resuming where the last call stopped.
+    pub fn update(&self, dt: f64) {
+        let mut state = self.state.borrow_mut();
+        for layer in state.layers.values_mut() {
+            layer.clock += dt;
+        }
+        let names: Vec<String> = state.layers.keys().cloned().collect();
+        if names.is_empty() || state.width == 0 || state.height == 0 {
+            return;
+        }
+        let rows = names.len() * state.height;
+        let budget = state
+            .budget
+            .map_or(rows, |cells| cells.div_ceil(state.width).max(1));
+        let (mut layer, mut row) = state.cursor;
+        layer = layer.min(names.len() - 1);
+        for _ in 0..budget.min(rows) {
+            state.update_row(&names[layer], row);
+            row += 1;
+            if row == state.height {
+                row = 0;
+                layer = (layer + 1) % names.len();
+            }
+        }
+        state.cursor = (layer, row);
+    }
+
+    /// A system for the schedule, advancing by the fixed step.
+    pub fn into_system(self) -> impl FnMut(&mut World) -> Result<()> {
+        move |world| {
+            let dt = world
+                .resource::<FixedTimestep>()
+                .map_or(0.0, |fixed| fixed.step);
+            self.update(dt);
+            Ok(())
+        }
+    }
+
+    /// Adds the `influence` global: `sample(layer, pos)`,
+    /// `deposit(layer, pos, amount, radius?)`, `strongest(layer, pos, radius)`
+    /// returning `pos, value` or nil, and `clear(layer)`, all as methods.
+    /// Positions are `{ x, y }` tables.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("influence", self.clone())
+    }
+}
+
+impl UserData for InfluenceMap {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("sample", |lua, this, (layer, point): (String, Value)| {
+            this.sample(&layer, lua.from_value(point)?)
+        });
+        methods.add_method(
+            "deposit",
+            |lua, this, (layer, point, amount, radius): (String, Value, f64, Option<f64>)| {
+                this.deposit(
+                    &layer,
+                    lua.from_value(point)?,
+                    amount,
+                    radius.unwrap_or(0.0),
+                )
+            },
+        );
+        methods.add_method(
+            "strongest",
+            |lua, this, (layer, point, radius): (String, Value, f64)| match this.strongest(
+                &layer,
+                lua.from_value(point)?,
+                radius,
+            )? {
+                Some((at, value)) => Ok((lua.to_value(&at)?, Some(value))),
+                None => Ok((Value::Nil, None)),
+            },
+        );
+        methods.add_method("clear", |_, this, layer: String| this.clear(&layer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decay_spread_budget_and_sampling() -> Result<()> {
+        let map = InfluenceMap::new(Vec2::ZERO, 1.0, 8, 8);
+        map.add_layer("threat", 0.5, 1.0);
+        map.add_layer("noise", 0.0, 0.0);
+        map.deposit("threat", Vec2::new(4.5, 4.5), 10.0, 0.0)?;
+        assert_eq!(map.sample("threat", Vec2::new(4.5, 4.5))?, 10.0);
+        // Half way between two centers, half the value.
+        assert_eq!(map.sample("threat", Vec2::new(4.0, 4.5))?, 5.0);
+        assert!(map.sample("smell", Vec2::ZERO).is_err());
+
+        map.update(1.0);
+        let peak = map.sample("threat", Vec2::new(4.5, 4.5))?;
+        let beside = map.sample("threat", Vec2::new(5.5, 4.5))?;
+        assert!(peak < 5.0 && beside > 0.0);
+
+        let smoke = InfluenceMap::new(Vec2::ZERO, 1.0, 2, 2);
+        smoke.add_layer("smoke", 0.5, 0.0);
+        smoke.deposit("smoke", Vec2::new(0.5, 0.5), 8.0, 0.0)?;
+        smoke.update(1.0);
+        assert_eq!(smoke.sample("smoke", Vec2::new(0.5, 0.5))?, 4.0);
+        // Only the cells on the grid are visited, however far it reaches.
+        smoke.deposit("smoke", Vec2::new(0.5, 0.5), 1.0, 1e15)?;
+        assert_eq!(smoke.sample("smoke", Vec2::new(0.5, 0.5))?, 5.0);
+
+        // Four rows a call: rows the budget skipped catch up later.
+        map.set_budget(Some(32));
+        map.deposit("noise", Vec2::new(1.5, 1.5), 3.0, 2.0)?;
+        map.update(1.0);
+        let far = map.sample("threat", Vec2::new(4.5, 7.5))?;
+        for _ in 0..3 {
+            map.update(0.0);
+        }
+        assert!(map.sample("threat", Vec2::new(4.5, 7.5))? != far);
+        assert_eq!(map.sample("noise", Vec2::new(1.5, 1.5))?, 3.0);
+
+        let lua = Lua::new();
+        map.register_lua(&lua)?;
+        lua.load(
+            r#"
+            assert(influence:sample("noise", { x = 2.5, y = 1.5 }) == 1.5)
+            local at, value = influence:strongest("noise", { x = 3, y = 3 }, 4)
+            assert(at.x == 1.5 and at.y == 1.5 and value == 3)
+            assert(influence:strongest("noise", { x = 7.5, y = 7.5 }, 1) == nil)
+            influence:clear("noise")
+            assert(influence:sample("noise", { x = 1.5, y = 1.5 }) == 0)
+        "#,
+        )
+        .exec()
+    }
+}
@@ -0,0 +1,168 @@
+use crate::data::DataError;
+use crate::ecs::ScriptValue;
+use mlua::{Lua, Result, UserData, UserDataMethods};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+struct KvState {
+    path: PathBuf,
+    values: BTreeMap<String, ScriptValue>,
+}
+
+/// Persistent key-value store kept as a JSON file, for settings and save
+/// progress. Every change is written straight away, atomically, so a
+/// crash never leaves a half-written file, and only kept once it is on
+/// disk. Clones share one store.
+#[derive(Clone)]
+pub struct KvStore {
+    state: Rc<RefCell<KvState>>,
+}
+
+impl KvStore {
+    /// Opens the store at `path`; a missing file is an empty store.
+    pub fn open(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        let path = path.as_ref().to_path_buf();
+        let values = match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                serde_json::from_str(&text).map_err(|e| DataError::Invalid(e.to_string()))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(KvStore {
+            state: Rc::new(RefCell::new(KvState { path, values })),
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<ScriptValue> {
+        self.state.borrow().values.get(key).cloned()
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        self.state.borrow().values.keys().cloned().collect()
+    }
+
+    pub fn set(&self, key: &str, value: ScriptValue) -> std::result::Result<(), DataError> {
+        let mut values = self.state.borrow().values.clone();
+        values.insert(key.to_string(), value);
+        self.save(values)
+    }
+
+    pub fn remove(&self, key: &str) -> std::result::Result<Option<ScriptValue>, DataError> {
+        let mut values = self.state.borrow().values.clone();
+        let old = values.remove(key);
+        if old.is_some() {
+            self.save(values)?;
+        }
+        Ok(old)
+    }
+
+    /// Writes `values` to a temporary file beside the store, syncs it and
+    /// renames it over the old one, then makes them the store's contents.
+    fn save(&self, values: BTreeMap<String, ScriptValue>) -> std::result::Result<(), DataError> {
+        let mut state = self.state.borrow_mut();
+        let text =
+            serde_json::to_string_pretty(&values).map_err(|e| DataError::Invalid(e.to_string()))?;
+        if let Some(dir) = state
+            .path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut temp = state.path.clone().into_os_string();
+        temp.push(".tmp");
+        let mut file = std::fs::File::create(&temp)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&temp, &state.path)?;
+        state.values = values;
+        Ok(())
+    }
+
+    /// Adds the `storage` global: `storage:get(key, default?)`,
+    /// `storage:set(key, value)` (nil removes) and `storage:keys()`.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("storage", self.clone())
+    }
+}
+
+impl UserData for KvStore {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method(
+            "get",
+            |_, this, (key, default): (String, Option<ScriptValue>)| Ok(this.get(&key).or(default)),
+        );
+        methods.add_method(
+            "set",
+            |_, this, (key, value): (String, Option<ScriptValue>)| {
+                match value {
+                    Some(value) => this.set(&key, value)?,
+                    None => {
+                        this.remove(&key)?;
+                    }
+                }
+                Ok(())
+            },
+        );
+        methods.add_method("keys", |_, this, ()| Ok(this.keys()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_persist_across_opens() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("kv_{}", std::process::id()));
+        let path = dir.join("save.json");
+        let lua = Lua::new();
+        KvStore::open(&path)?.register_lua(&lua)?;
+        lua.load(
+            r#"
+            storage:set("highscore", 123)
+            storage:set("settings", { volume = 0.5, name = "p1" })
+            storage:set("temp", true)
+            storage:set("temp", nil)
+        "#,
+        )
+        .exec()?;
+
+        let reopened = KvStore::open(&path)?;
+        assert_eq!(reopened.keys(), vec!["highscore", "settings"]);
+        assert_eq!(reopened.get("highscore"), Some(ScriptValue::Number(123.0)));
+        let lua = Lua::new();
+        reopened.register_lua(&lua)?;
+        let (volume, missing): (f64, String) = lua
+            .load(r#"return storage:get("settings").volume, storage:get("missing", "none")"#)
+            .eval()?;
+        assert_eq!((volume, missing.as_str()), (0.5, "none"));
+        assert!(!dir.join("save.json.tmp").exists());
+        std::fs::remove_dir_all(&dir).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_save_keeps_old_values() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("kv_fail_{}", std::process::id()));
+        let path = dir.join("save.json");
+        let store = KvStore::open(&path)?;
+        store.set("highscore", ScriptValue::Number(1.0))?;
+        // A directory where the temporary file goes makes the next save fail.
+        std::fs::create_dir_all(dir.join("save.json.tmp")).map_err(mlua::Error::external)?;
+        assert!(store.set("highscore", ScriptValue::Number(2.0)).is_err());
+        assert!(store.remove("highscore").is_err());
+        assert_eq!(store.get("highscore"), Some(ScriptValue::Number(1.0)));
+        assert_eq!(
+            KvStore::open(&path)?.get("highscore"),
+            Some(ScriptValue::Number(1.0))
+        );
+        std::fs::remove_dir_all(&dir).ok();
+        Ok(())
+    }
+}
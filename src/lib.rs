@@ -0,0 +1,63 @@
+pub mod analytics;
+pub mod api;
+pub mod assets;
+pub mod bench;
+pub mod camera;
+pub mod color;
+pub mod console;
+pub mod content;
+pub mod curve;
+pub mod cvar;
+pub mod data;
+#[cfg(feature = "client")]
+pub mod debug_draw;
+pub mod debugger;
+pub mod diagnostics;
+pub mod dialogue;
+pub mod ecs;
+pub mod edit;
+pub mod engine;
+pub mod environment;
+pub mod gameplay;
+#[cfg(feature = "client")]
+pub mod gizmos;
+pub mod i18n;
+pub mod influence;
+pub mod kv;
+pub mod loot;
+pub mod math;
+pub mod mods;
+pub mod nav;
+pub mod net;
+pub mod physics;
+pub mod picking;
+pub mod platform;
+#[cfg(feature = "client")]
+pub mod render;
+pub mod replay;
+pub mod rng;
+pub mod sandbox;
+pub mod scene;
+#[cfg(feature = "client")]
+pub mod sprite;
+pub mod streaming;
+pub mod tasks;
+pub mod testing;
+#[cfg(feature = "client")]
+pub mod text;
+pub mod tilemap;
+pub mod time;
+pub mod timeline;
+#[cfg(feature = "client")]
+pub mod ui;
+
+use mlua::{Lua, Result};
+
+pub fn register(lua: &Lua) -> Result<()> {
+    api::register(lua)?;
+    math::register(lua)?;
+    curve::register(lua)?;
+    color::register(lua)?;
+    rng::register(lua)?;
+    Ok(())
+}
@@ -0,0 +1,399 @@
+use crate::assets::read_lua_asset;
+use crate::data::{self, DataError};
+use crate::ecs::{Entity, ScriptValue, World};
+use crate::rng::GameRng;
+use mlua::{AnyUserData, Lua, LuaSerdeExt, Result, UserData, UserDataMethods, Value};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::rc::Rc;
+
+/// How deep tables may nest before a roll gives up, which also catches
+/// tables that include themselves.
+const MAX_DEPTH: usize = 16;
+
+/// A check an entry must pass to be in the running. `Has` and `Field`
+/// look at the entity the roll is for; without one they fail.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Condition {
+    /// Some entity has this name, e.g. a boss still alive.
+    Exists(String),
+    /// The entity has the named component, Rust or script-defined.
+    Has(String),
+    /// A number in the entity's script component, within the bounds.
+    Field {
+        component: String,
+        field: String,
+        #[serde(default)]
+        min: Option<f64>,
+        #[serde(default)]
+        max: Option<f64>,
+    },
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    pub fn check(&self, world: &World, entity: Option<Entity>) -> bool {
+        match self {
+            Condition::Exists(name) => world.find(name).is_some(),
+            Condition::Has(component) => entity.is_some_and(|entity| {
+                world
+                    .registry()
+                    .get(component)
+                    .map(|info| (info.has)(world, entity))
+                    .unwrap_or_else(|| world.script_component(entity, component).is_some())
+            }),
+            Condition::Field {
+                component,
+                field,
+                min,
+                max,
+            } => {
+                let value = entity
+                    .and_then(|entity| world.script_component(entity, component))
+                    .and_then(|value| match value {
+                        ScriptValue::Map(fields) => fields.get(field),
+                        _ => None,
+                    });
+                match value {
+                    Some(&ScriptValue::Number(n)) => {
+                        min.is_none_or(|min| n >= min) && max.is_none_or(|max| n <= max)
+                    }
+                    _ => false,
+                }
+            }
+            Condition::All(conditions) => conditions.iter().all(|c| c.check(world, entity)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.check(world, entity)),
+            Condition::Not(condition) => !condition.check(world, entity),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LootDrop {
+    /// A prefab name to spawn.
+    Prefab(String),
+    /// Rolls another table once per count.
+    Table(String),
+    Nothing,
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn single() -> (u32, u32) {
+    (1, 1)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LootEntry {
+    #[serde(default = "one")]
+    pub weight: f64,
+    pub drop: LootDrop,
+    /// How many of the drop, inclusive.
+    #[serde(default = "single")]
+    pub count: (u32, u32),
+    #[serde(default)]
+    pub when: Option<Condition>,
+}
+
+/// Picks `rolls` entries by weight, skipping any whose condition fails.
+/// `always` entries drop every time their condition passes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LootTable {
+    #[serde(default = "single")]
+    pub rolls: (u32, u32),
+    #[serde(default)]
+    pub always: Vec<LootEntry>,
+    #[serde(default)]
+    pub entries: Vec<LootEntry>,
+}
+
+fn count(rng: &mut GameRng, (min, max): (u32, u32)) -> u32 {
+    if max <= min {
+        min
+    } else {
+        min + rng.below((max - min) as u64 + 1) as u32
+    }
+}
+
+/// Named loot tables, loaded from a RON map such as `tables/loot.ron` or
+/// defined from Lua.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct LootTables {
+    tables: BTreeMap<String, LootTable>,
+}
+
+impl LootTables {
+    pub fn new() -> Self {
+        LootTables::default()
+    }
+
+    pub fn from_ron(source: &str) -> std::result::Result<Self, DataError> {
+        let tables: LootTables = data::from_ron(source)?;
+        tables.validate()?;
+        Ok(tables)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        LootTables::from_ron(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn insert(&mut self, name: &str, table: LootTable) {
+        self.tables.insert(name.to_string(), table);
+    }
+
+    /// Adds every table from `other`, replacing same-named ones.
+    pub fn extend(&mut self, other: LootTables) {
+        self.tables.extend(other.tables);
+    }
+
+    /// Like `extend`, but leaves the tables untouched unless the merged
+    /// set still validates.
+    pub fn try_extend(&mut self, other: LootTables) -> std::result::Result<(), DataError> {
+        let mut merged = self.clone();
+        merged.extend(other);
+        merged.validate()?;
+        *self = merged;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LootTable> {
+        self.tables.get(name)
+    }
+
+    /// Checks that every nested table exists.
+    pub fn validate(&self) -> std::result::Result<(), DataError> {
+        for (name, table) in &self.tables {
+            for entry in table.always.iter().chain(&table.entries) {
+                if let LootDrop::Table(inner) = &entry.drop
+                    && !self.tables.contains_key(inner)
+                {
+                    return Err(DataError::Invalid(format!(
+                        "loot table '{}' refers to missing table '{}'",
+                        name, inner
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The prefab names that drop from `name`, nested tables expanded.
+    /// `entity` is what conditions check, usually whatever died.
+    pub fn roll(
+        &self,
+        name: &str,
+        world: &World,
+        entity: Option<Entity>,
+        rng: &mut GameRng,
+    ) -> std::result::Result<Vec<String>, DataError> {
+        let mut drops = Vec::new();
+        self.roll_into(name, world, entity, rng, 0, &mut drops)?;
+        Ok(drops)
+    }
+
+    fn roll_into(
+        &self,
+        name: &str,
+        world: &World,
+        entity: Option<Entity>,
+        rng: &mut GameRng,
+        depth: usize,
+        drops: &mut Vec<String>,
+    ) -> std::result::Result<(), DataError> {
+        if depth > MAX_DEPTH {
+            return Err(DataError::Invalid(format!(
+                "loot table '{}' nests more than {} deep",
+                name, MAX_DEPTH
+            )));
+        }
+        let table = self
+            .tables
+            .get(name)
+            .ok_or_else(|| DataError::Invalid(format!("no loot table named '{}'", name)))?;
+        let passes = |entry: &LootEntry| {
+            entry
+                .when
+                .as_ref()
+                .is_none_or(|condition| condition.check(world, entity))
+        };
+        let mut picked: Vec<&LootEntry> = table.always.iter().filter(|e| passes(e)).collect();
+        let candidates: Vec<&LootEntry> = table.entries.iter().filter(|e| passes(e)).collect();
+        let weights: Vec<f64> = candidates.iter().map(|e| e.weight).collect();
+        for _ in 0..count(rng, table.rolls) {
+            if let Some(i) = rng.weighted(&weights) {
+                picked.push(candidates[i]);
+            }
+        }
+        for entry in picked {
+            for _ in 0..count(rng, entry.count) {
+                match &entry.drop {
+                    LootDrop::Prefab(prefab) => drops.push(prefab.clone()),
+                    LootDrop::Table(inner) => {
+                        self.roll_into(inner, world, entity, rng, depth + 1, drops)?
+                    }
+                    LootDrop::Nothing => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `loot` global. `loot:roll(name, rng, world?, entity?)` returns an
+/// array of prefab names; without a world, conditions see an empty one.
+/// `loot:load(path)` adds a RON file's tables and
+/// `loot:define(name, table)` one table shaped like the RON, e.g.
+/// `{ rolls = { 1, 2 }, entries = { { weight = 3, drop = { Prefab = "coin" } } } }`.
+/// Clones share one set of tables.
+#[derive(Clone, Default)]
+pub struct Loot {
+    tables: Rc<RefCell<LootTables>>,
+}
+
+impl Loot {
+    pub fn new(tables: LootTables) -> Self {
+        Loot {
+            tables: Rc::new(RefCell::new(tables)),
+        }
+    }
+
+    pub fn tables(&self) -> std::cell::Ref<'_, LootTables> {
+        self.tables.borrow()
+    }
+
+    pub fn tables_mut(&self) -> std::cell::RefMut<'_, LootTables> {
+        self.tables.borrow_mut()
+    }
+
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("loot", self.clone())
+    }
+}
+
+impl UserData for Loot {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method(
+            "roll",
+            |_,
+             this,
+             (name, rng, world, entity): (
+                String,
+                AnyUserData,
+                Option<AnyUserData>,
+                Option<Entity>,
+            )| {
+                let mut rng = rng.borrow_mut::<GameRng>()?;
+                let tables = this.tables.borrow();
+                match world {
+                    Some(world) => Ok(world.borrow_scoped::<World, _>(|world| {
+                        tables.roll(&name, world, entity, &mut rng)
+                    })??),
+                    None => Ok(tables.roll(&name, &World::new(), None, &mut rng)?),
+                }
+            },
+        );
+        // Both validate against the tables already known, so a file may
+        // nest tables loaded before it; nothing changes if that fails.
+        methods.add_method("load", |lua, this, path: String| {
+            let loaded: LootTables = data::from_ron(&read_lua_asset(lua, &path)?)?;
+            Ok(this.tables.borrow_mut().try_extend(loaded)?)
+        });
+        methods.add_method("define", |lua, this, (name, table): (String, Value)| {
+            let mut defined = LootTables::new();
+            defined.insert(&name, lua.from_value(table)?);
+            Ok(this.tables.borrow_mut().try_extend(defined)?)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLES: &str = r#"{
+        "goblin_drops": (
+            rolls: (2, 2),
+            always: [(drop: Prefab("goblin_ear"))],
+            entries: [
+                (weight: 3, drop: Prefab("coin"), count: (1, 3)),
+                (weight: 1, drop: Table("gems"), when: Some(Has("Elite"))),
+            ],
+        ),
+        "gems": (entries: [(drop: Prefab("ruby")), (drop: Prefab("emerald"))]),
+    }"#;
+
+    #[test]
+    fn test_nested_tables_and_conditions() {
+        let tables = LootTables::from_ron(TABLES).unwrap();
+        let mut world = World::new();
+        let goblin = world.spawn();
+        let elite = world.spawn();
+        world
+            .set_script_component(elite, "Elite", ScriptValue::Bool(true))
+            .unwrap();
+        let mut rng = GameRng::new(1);
+
+        for _ in 0..50 {
+            let drops = tables
+                .roll("goblin_drops", &world, Some(goblin), &mut rng)
+                .unwrap();
+            assert_eq!(drops[0], "goblin_ear");
+            assert!(drops[1..].iter().all(|d| d == "coin"));
+            assert!((3..=7).contains(&drops.len()));
+        }
+        let gems = (0..50)
+            .flat_map(|_| {
+                tables
+                    .roll("goblin_drops", &world, Some(elite), &mut rng)
+                    .unwrap()
+            })
+            .filter(|d| d == "ruby" || d == "emerald")
+            .count();
+        assert!(gems > 0);
+
+        assert!(tables.roll("boss", &world, None, &mut rng).is_err());
+        assert!(LootTables::from_ron(r#"{ "a": (entries: [(drop: Table("b"))]) }"#).is_err());
+    }
+
+    #[test]
+    fn test_lua_loot_roll() -> Result<()> {
+        let lua = Lua::new();
+        crate::rng::register(&lua)?;
+        let loot = Loot::new(LootTables::from_ron(TABLES).unwrap());
+        loot.register_lua(&lua)?;
+        let mut world = World::new();
+        let count: usize = lua.scope(|scope| {
+            let world = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world = ...
+                loot:define("chest", { entries = { { drop = { Prefab = "sword" } } } })
+                local r = rng.new(4)
+                assert(loot:roll("chest", r)[1] == "sword")
+                local ok = pcall(loot.define, loot, "chest", { entries = { { drop = { Table = "nope" } } } })
+                assert(not ok)
+                assert(loot:roll("chest", r)[1] == "sword")
+                local goblin = world:spawn({})
+                return #loot:roll("goblin_drops", r, world, goblin)
+            "#,
+            )
+            .call(world)
+        })?;
+        assert!(count >= 3);
+
+        let mut tables = loot.tables().clone();
+        let nested = data::from_ron(r#"{ "boss": (entries: [(drop: Table("gems"))]) }"#).unwrap();
+        tables.try_extend(nested).unwrap();
+        let broken = data::from_ron(r#"{ "gems": (entries: [(drop: Table("nope"))]) }"#).unwrap();
+        assert!(tables.try_extend(broken).is_err());
+        assert_eq!(tables.get("gems"), loot.tables().get("gems"));
+        Ok(())
+    }
+}
@@ -1,189 +1,292 @@
-use mlua::{Error, FromLua, Function, Lua, Result};
-use std::time::Instant;
+use entity_engine::assets::PackBuilder;
+use entity_engine::bench::{self, Harness, Warmup};
+use entity_engine::data::load_ron;
+use entity_engine::ecs::{Schedule, World};
+use entity_engine::engine::{Headless, take_headless_flag};
+use entity_engine::replay::Replay;
+use entity_engine::scene::{self, Scene, ScenePatch};
+use entity_engine::testing;
+use mlua::{Error, Result};
 
-struct BenchmarkResult {
-    result: f64,
-    operations: u32,
-    max_value: f64,
+const USAGE: &str = "usage: EntityEngine bench [scenario] [--warmup <iterations|duration>]
+                          [--entities <n>] [--frames <n>]
+       EntityEngine scene diff <from.ron> <to.ron> [--ron]
+       EntityEngine scene patch <scene.ron> <patch.ron>
+       EntityEngine scene convert <input> <output> [--zstd]
+       EntityEngine test [path...] [--filter <name>]
+       EntityEngine atlas <name> <out-dir> <image.png...> [--size <pixels>] [--root <dir>]
+       EntityEngine pack <output.pak> <dir...> [--store]
+       EntityEngine replay play <file> [--seek <frame>] [--script <setup.lua>] [--out <scene>]
+                                [--headless]
+Replays are always headless in builds without the client feature.";
+
+/// Parses `value` as the count `arg` takes, rejecting ones that don't fit.
+fn count<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| Error::RuntimeError(format!("{} expects a count, got '{}'", arg, value)))
 }
 
-impl FromLua for BenchmarkResult {
-    fn from_lua(value: mlua::Value, _lua: &Lua) -> Result<Self> {
-        match value {
-            mlua::Value::Table(table) => {
-                let result: f64 =
-                    table
-                        .get("result")
-                        .map_err(|e| Error::FromLuaConversionError {
-                            from: "Table",
-                            to: "BenchmarkResult".to_string(),
-                            message: Some(format!("Failed to get 'result' field: {}", e)),
-                        })?;
-
-                let operations: u32 =
-                    table
-                        .get("operations")
-                        .map_err(|e| Error::FromLuaConversionError {
-                            from: "Table",
-                            to: "BenchmarkResult".to_string(),
-                            message: Some(format!("Failed to get 'operations' field: {}", e)),
-                        })?;
-
-                let max_value: f64 =
-                    table
-                        .get("max_value")
-                        .map_err(|e| Error::FromLuaConversionError {
-                            from: "Table",
-                            to: "BenchmarkResult".to_string(),
-                            message: Some(format!("Failed to get 'max_value' field: {}", e)),
-                        })?;
-
-                Ok(BenchmarkResult {
-                    result,
-                    operations,
-                    max_value,
-                })
+fn run_bench(args: &[String]) -> Result<()> {
+    let mut scenario = "enhanced";
+    let mut warmup = None;
+    let mut harness = Harness::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| Error::RuntimeError(format!("{} needs a value ({})", arg, USAGE)))
+        };
+        match arg.as_str() {
+            "--warmup" => {
+                warmup = Some(value()?.parse::<Warmup>().map_err(Error::RuntimeError)?);
             }
-            _ => Err(Error::FromLuaConversionError {
-                from: value.type_name(),
-                to: "BenchmarkResult".to_string(),
-                message: Some(
-                    "Expected a table with result, operations, and max_value fields".to_string(),
-                ),
-            }),
+            "--entities" => harness.entities = Some(count(arg, value()?)?),
+            "--frames" => harness.frames = Some(count(arg, value()?)?),
+            name => scenario = name,
         }
     }
-}
 
-fn main() -> Result<()> {
-    let lua = Lua::new();
+    bench::run(scenario, warmup, harness)
+}
 
-    let length_fast: Function =
-        lua.create_function(|_, (x, y): (f64, f64)| Ok((x * x + y * y).sqrt()))?;
+fn to_pretty_ron<T: serde::Serialize>(value: &T) -> Result<String> {
+    ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()).map_err(Error::external)
+}
 
-    let distance: Function = lua.create_function(|_, (x1, y1, x2, y2): (f64, f64, f64, f64)| {
-        let dx = x2 - x1;
-        let dy = y2 - y1;
-        Ok((dx * dx + dy * dy).sqrt())
-    })?;
+fn run_scene(args: &[String]) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["diff", from, to, rest @ ..] => {
+            let patch = scene::diff(&Scene::load(from)?, &Scene::load(to)?);
+            match rest {
+                ["--ron"] => println!("{}", to_pretty_ron(&patch)?),
+                _ => print!("{}", patch),
+            }
+            Ok(())
+        }
+        ["patch", base, patch] => {
+            let patch: ScenePatch = load_ron(patch)?;
+            let patched = scene::apply_patch(&Scene::load(base)?, &patch)?;
+            println!("{}", to_pretty_ron(&patched)?);
+            Ok(())
+        }
+        // Writes RON for a `.ron` output and the binary format otherwise.
+        ["convert", input, output, rest @ ..] => {
+            let scene = Scene::load(input)?;
+            let bytes = if output.ends_with(".ron") {
+                scene.to_ron()?.into_bytes()
+            } else {
+                scene.to_binary(rest == ["--zstd"])?
+            };
+            std::fs::write(output, bytes).map_err(Error::external)
+        }
+        _ => Err(Error::RuntimeError(USAGE.to_string())),
+    }
+}
 
-    let complex_calc: Function = lua.create_function(|_, (angle, radius): (f64, f64)| {
-        let x = radius * angle.cos();
-        let y = radius * angle.sin();
-        let magnitude = (x * x + y * y).sqrt();
+/// Runs `*_test.lua` files under each path (default: the current
+/// directory) and fails if any test does.
+fn run_tests(args: &[String]) -> Result<()> {
+    let mut roots = Vec::new();
+    let mut filter = None;
 
-        if magnitude > 10.0 {
-            Ok(magnitude * 1.5 + angle.tan().abs())
-        } else {
-            Ok(magnitude * 0.8 + angle.sin())
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--filter" => {
+                filter = Some(args.next().ok_or_else(|| {
+                    Error::RuntimeError(format!("--filter needs a value ({})", USAGE))
+                })?);
+            }
+            path => roots.push(path.to_string()),
         }
-    })?;
-    lua.globals().set("length_fast", length_fast)?;
-    lua.globals().set("distance", distance)?;
-    lua.globals().set("complex_calc", complex_calc)?;
-
-    for i in 0..5000 {
-        let angle = i as f64 * 0.001;
-        let _: f64 = lua.load("return length_fast(1, 1)").eval()?;
-        let _: f64 = lua.load("return distance(0, 0, 3, 4)").eval()?;
-        let _: f64 = lua
-            .load(&format!("return complex_calc({}, 15)", angle))
-            .eval()?;
     }
+    if roots.is_empty() {
+        roots.push(".".to_string());
+    }
+
+    let mut files = Vec::new();
+    for root in &roots {
+        files.extend(testing::discover(root).map_err(Error::external)?);
+    }
+    let report = testing::run(&files, filter.map(String::as_str)).map_err(Error::external)?;
+    println!("{}", report);
+    match report.failed() {
+        0 => Ok(()),
+        failed => Err(Error::RuntimeError(format!(
+            "{} script test(s) failed",
+            failed
+        ))),
+    }
+}
 
-    let script: &'static str = r#"
-        local sum = 0.0
-        local operations = 0
-        local max_value = 0.0
-        local pi = 3.14159265359
-        
-        for i = 1, 5000000 do
-            local angle = (i % 628) * 0.01
-            local radius = 10 + (i % 20)
-            
-            local len = length_fast(radius * 0.5, radius * 0.3)
-            local dist = distance(0, 0, len, angle)
-            local complex = complex_calc(angle, radius)
-            
-            local combined = len + dist + complex
-            sum = sum + combined
-            operations = operations + 3
-            
-            if combined > max_value then
-                max_value = combined
-            end
-            
-            if i % 1000 == 0 then
-                sum = sum * 1.0001
-            end
-        end
-        
-        return { 
-            result = sum,
-            operations = operations,
-            max_value = max_value
+/// Packs images into `<out-dir>/<name>_<page>.png` with a
+/// `<name>.atlas.ron` manifest for remapping sprites at load. Regions are
+/// named by their path under `--root` (the current directory by default).
+#[cfg(feature = "png")]
+fn run_atlas(args: &[String]) -> Result<()> {
+    use entity_engine::render::AtlasBuilder;
+
+    let mut builder = AtlasBuilder::default();
+    let mut root = ".";
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--size" => {
+                let value = args.next().ok_or_else(|| {
+                    Error::RuntimeError(format!("--size needs a value ({})", USAGE))
+                })?;
+                builder.max_size = value.parse().map_err(|_| {
+                    Error::RuntimeError(format!("--size expects pixels, got '{}'", value))
+                })?;
+            }
+            "--root" => {
+                root = args.next().ok_or_else(|| {
+                    Error::RuntimeError(format!("--root needs a directory ({})", USAGE))
+                })?;
+            }
+            arg => positional.push(arg),
         }
-    "#;
-
-    println!("Starting enhanced benchmark...");
-    let start = Instant::now();
-    let result: BenchmarkResult = lua.load(script).eval()?;
-    let duration = start.elapsed();
-
-    println!("Result: {}", result.result);
-    println!("Total operations: {}", result.operations);
-    println!("Maximum value encountered: {}", result.max_value);
-    println!("Total time: {:?}", duration);
-    println!("Time per iteration: {:?}", duration / 5_000_000);
-    println!("Time per operation: {:?}", duration / result.operations);
-    println!(
-        "Iterations per second: {:.0}",
-        5_000_000.0 / duration.as_secs_f64()
-    );
+    }
+    let [name, out, images @ ..] = positional.as_slice() else {
+        return Err(Error::RuntimeError(USAGE.to_string()));
+    };
+    for image in images {
+        builder.add_file(root, image)?;
+    }
+    let atlas = builder.build(name)?;
+    atlas.save(out)?;
     println!(
-        "Operations per second: {:.0}",
-        result.operations as f64 / duration.as_secs_f64()
+        "packed {} image(s) into {} page(s)",
+        atlas.regions.len(),
+        atlas.page_count
     );
-
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_lua_functions() -> Result<()> {
-        let lua = Lua::new();
-
-        let length_fast: Function =
-            lua.create_function(|_, (x, y): (f64, f64)| Ok((x * x + y * y).sqrt()))?;
-
-        lua.globals().set("length_fast", length_fast)?;
-
-        let result: f64 = lua.load("return length_fast(3, 4)").eval()?;
-        assert!((result - 5.0).abs() < 1e-10);
+#[cfg(not(feature = "png"))]
+fn run_atlas(_: &[String]) -> Result<()> {
+    Err(Error::RuntimeError(
+        "atlas needs a build with the `png` feature".to_string(),
+    ))
+}
 
-        Ok(())
+/// Bundles each directory into one archive, its files named after the
+/// directory (`scripts/main.lua`), compressed unless `--store` is given
+/// or the build has no zstd.
+fn run_pack(args: &[String]) -> Result<()> {
+    let store = !cfg!(feature = "zstd") || args.iter().any(|arg| arg == "--store");
+    let positional: Vec<&String> = args.iter().filter(|arg| *arg != "--store").collect();
+    let [output, dirs @ ..] = positional.as_slice() else {
+        return Err(Error::RuntimeError(USAGE.to_string()));
+    };
+    if dirs.is_empty() {
+        return Err(Error::RuntimeError(USAGE.to_string()));
     }
+    let mut builder = PackBuilder::new();
+    for dir in dirs {
+        let path = std::path::Path::new(dir.as_str());
+        let prefix = match path.file_name() {
+            Some(name) => format!("{}/", name.to_string_lossy()),
+            None => String::new(),
+        };
+        builder.add_dir(&prefix, path)?;
+    }
+    builder.save(output, !store)?;
+    println!("packed {} file(s) into {}", builder.len(), output);
+    Ok(())
+}
 
-    #[test]
-    fn test_benchmark_result_parsing() -> Result<()> {
-        let lua = Lua::new();
-
-        let script = r#"
-            return {
-                result = 42.5,
-                operations = 1000,
-                max_value = 99.9
+/// Seeks a replay and reports the world there. The setup script, the
+/// replay's own unless `--script` is given, is called with the world and
+/// adds systems through the `schedule` global; it may return the input
+/// function, called as `input(world, frame, recorded)`.
+fn run_replay(args: &[String]) -> Result<()> {
+    let mut args = args.to_vec();
+    let headless = take_headless_flag(&mut args) || !cfg!(feature = "client");
+    let [command, path, rest @ ..] = args.as_slice() else {
+        return Err(Error::RuntimeError(USAGE.to_string()));
+    };
+    if command != "play" {
+        return Err(Error::RuntimeError(USAGE.to_string()));
+    }
+    let replay = Replay::load(path)?;
+    let mut seek = replay.frames();
+    let mut script = replay.script.clone();
+    let mut out = None;
+    let mut args = rest.iter();
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| Error::RuntimeError(format!("{} needs a value ({})", arg, USAGE)))?;
+        match arg.as_str() {
+            "--seek" => {
+                seek = value.parse().map_err(|_| {
+                    Error::RuntimeError(format!("--seek expects a frame, got '{}'", value))
+                })?;
             }
-        "#;
+            "--script" => script = Some(value.clone()),
+            "--out" => out = Some(value),
+            _ => return Err(Error::RuntimeError(USAGE.to_string())),
+        }
+    }
+
+    let lua = mlua::Lua::new();
+    entity_engine::register(&lua)?;
+    let mut world = World::new();
+    if headless {
+        world.insert_resource(Headless);
+    }
+    let mut schedule = Schedule::new();
+    schedule.register_lua(&lua)?;
+    let input: Option<mlua::Function> = match &script {
+        Some(script) => {
+            let source = std::fs::read_to_string(script).map_err(Error::external)?;
+            lua.scope(|scope| {
+                let handle = scope.create_userdata_ref_mut(&mut world)?;
+                lua.load(source).set_name(script.as_str()).call(handle)
+            })?
+        }
+        None => None,
+    };
+    let from = replay.seek(&mut world, &mut schedule, &lua, seek, input.as_ref())?;
+    println!(
+        "frame {} of {} (from keyframe {}): {} entities, hash {:016x}",
+        seek,
+        replay.frames(),
+        from,
+        world.len(),
+        testing::world_hash(&world, &lua)?
+    );
+    if let Some(out) = out {
+        let scene = Scene::capture(&world, &lua)?;
+        let bytes = if out.ends_with(".ron") {
+            scene.to_ron()?.into_bytes()
+        } else {
+            scene.to_binary(false)?
+        };
+        std::fs::write(out, bytes).map_err(Error::external)?;
+    }
+    Ok(())
+}
 
-        let result: BenchmarkResult = lua.load(script).eval()?;
-        assert_eq!(result.result, 42.5);
-        assert_eq!(result.operations, 1000);
-        assert_eq!(result.max_value, 99.9);
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
 
-        Ok(())
+    match args.first().map(String::as_str) {
+        None => run_bench(&[]),
+        Some("bench") => run_bench(&args[1..]),
+        Some("scene") => run_scene(&args[1..]),
+        Some("test") => run_tests(&args[1..]),
+        Some("atlas") => run_atlas(&args[1..]),
+        Some("pack") => run_pack(&args[1..]),
+        Some("replay") => run_replay(&args[1..]),
+        Some(command) => Err(Error::RuntimeError(format!(
+            "unknown command '{}' ({})",
+            command, USAGE
+        ))),
     }
 }
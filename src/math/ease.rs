@@ -0,0 +1,279 @@
+use mlua::{Lua, Result};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ease {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+    BounceIn,
+    BounceOut,
+    BounceInOut,
+    CubicBezier(f64, f64, f64, f64),
+}
+
+impl Ease {
+    pub const NAMED: [Ease; 13] = [
+        Ease::Linear,
+        Ease::QuadIn,
+        Ease::QuadOut,
+        Ease::QuadInOut,
+        Ease::CubicIn,
+        Ease::CubicOut,
+        Ease::CubicInOut,
+        Ease::ElasticIn,
+        Ease::ElasticOut,
+        Ease::ElasticInOut,
+        Ease::BounceIn,
+        Ease::BounceOut,
+        Ease::BounceInOut,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Ease::Linear => "linear",
+            Ease::QuadIn => "quad_in",
+            Ease::QuadOut => "quad_out",
+            Ease::QuadInOut => "quad_in_out",
+            Ease::CubicIn => "cubic_in",
+            Ease::CubicOut => "cubic_out",
+            Ease::CubicInOut => "cubic_in_out",
+            Ease::ElasticIn => "elastic_in",
+            Ease::ElasticOut => "elastic_out",
+            Ease::ElasticInOut => "elastic_in_out",
+            Ease::BounceIn => "bounce_in",
+            Ease::BounceOut => "bounce_out",
+            Ease::BounceInOut => "bounce_in_out",
+            Ease::CubicBezier(..) => "bezier",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Ease> {
+        Ease::NAMED.into_iter().find(|ease| ease.name() == name)
+    }
+
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match *self {
+            Ease::Linear => t,
+            Ease::QuadIn => quad_in(t),
+            Ease::QuadOut => quad_out(t),
+            Ease::QuadInOut => quad_in_out(t),
+            Ease::CubicIn => cubic_in(t),
+            Ease::CubicOut => cubic_out(t),
+            Ease::CubicInOut => cubic_in_out(t),
+            Ease::ElasticIn => elastic_in(t),
+            Ease::ElasticOut => elastic_out(t),
+            Ease::ElasticInOut => elastic_in_out(t),
+            Ease::BounceIn => bounce_in(t),
+            Ease::BounceOut => bounce_out(t),
+            Ease::BounceInOut => bounce_in_out(t),
+            Ease::CubicBezier(x1, y1, x2, y2) => cubic_bezier(x1, y1, x2, y2, t),
+        }
+    }
+}
+
+pub fn quad_in(t: f64) -> f64 {
+    t * t
+}
+
+pub fn quad_out(t: f64) -> f64 {
+    1.0 - (1.0 - t) * (1.0 - t)
+}
+
+pub fn quad_in_out(t: f64) -> f64 {
+    if t < 0.5 {
+        2.0 * t * t
+    } else {
+        1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+    }
+}
+
+pub fn cubic_in(t: f64) -> f64 {
+    t * t * t
+}
+
+pub fn cubic_out(t: f64) -> f64 {
+    1.0 - (1.0 - t).powi(3)
+}
+
+pub fn cubic_in_out(t: f64) -> f64 {
+    if t < 0.5 {
+        4.0 * t * t * t
+    } else {
+        1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+    }
+}
+
+pub fn elastic_in(t: f64) -> f64 {
+    if t <= 0.0 || t >= 1.0 {
+        return t.clamp(0.0, 1.0);
+    }
+    let c4 = 2.0 * PI / 3.0;
+    -(2f64.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * c4).sin()
+}
+
+pub fn elastic_out(t: f64) -> f64 {
+    if t <= 0.0 || t >= 1.0 {
+        return t.clamp(0.0, 1.0);
+    }
+    let c4 = 2.0 * PI / 3.0;
+    2f64.powf(-10.0 * t) * ((t * 10.0 - 0.75) * c4).sin() + 1.0
+}
+
+pub fn elastic_in_out(t: f64) -> f64 {
+    if t <= 0.0 || t >= 1.0 {
+        return t.clamp(0.0, 1.0);
+    }
+    let c5 = 2.0 * PI / 4.5;
+    if t < 0.5 {
+        -(2f64.powf(20.0 * t - 10.0) * ((20.0 * t - 11.125) * c5).sin()) / 2.0
+    } else {
+        2f64.powf(-20.0 * t + 10.0) * ((20.0 * t - 11.125) * c5).sin() / 2.0 + 1.0
+    }
+}
+
+pub fn bounce_out(t: f64) -> f64 {
+    let n1 = 7.5625;
+    let d1 = 2.75;
+    if t < 1.0 / d1 {
+        n1 * t * t
+    } else if t < 2.0 / d1 {
+        let t = t - 1.5 / d1;
+        n1 * t * t + 0.75
+    } else if t < 2.5 / d1 {
+        let t = t - 2.25 / d1;
+        n1 * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / d1;
+        n1 * t * t + 0.984375
+    }
+}
+
+pub fn bounce_in(t: f64) -> f64 {
+    1.0 - bounce_out(1.0 - t)
+}
+
+pub fn bounce_in_out(t: f64) -> f64 {
+    if t < 0.5 {
+        (1.0 - bounce_out(1.0 - 2.0 * t)) / 2.0
+    } else {
+        (1.0 + bounce_out(2.0 * t - 1.0)) / 2.0
+    }
+}
+
+/// CSS-style `cubic-bezier(x1, y1, x2, y2)` timing curve evaluated at `t`.
+pub fn cubic_bezier(x1: f64, y1: f64, x2: f64, y2: f64, t: f64) -> f64 {
+    let bezier = |a: f64, b: f64, s: f64| {
+        let inv = 1.0 - s;
+        3.0 * inv * inv * s * a + 3.0 * inv * s * s * b + s * s * s
+    };
+    let bezier_slope = |a: f64, b: f64, s: f64| {
+        let inv = 1.0 - s;
+        3.0 * inv * inv * a + 6.0 * inv * s * (b - a) + 3.0 * s * s * (1.0 - b)
+    };
+
+    let t = t.clamp(0.0, 1.0);
+    let mut s = t;
+    for _ in 0..8 {
+        let slope = bezier_slope(x1, x2, s);
+        if slope.abs() < 1e-6 {
+            break;
+        }
+        s -= (bezier(x1, x2, s) - t) / slope;
+    }
+
+    if !(0.0..=1.0).contains(&s) || (bezier(x1, x2, s) - t).abs() > 1e-6 {
+        let (mut lo, mut hi) = (0.0, 1.0);
+        s = t;
+        for _ in 0..32 {
+            if bezier(x1, x2, s) < t {
+                lo = s;
+            } else {
+                hi = s;
+            }
+            s = (lo + hi) / 2.0;
+        }
+    }
+
+    bezier(y1, y2, s)
+}
+
+pub fn register(lua: &Lua) -> Result<()> {
+    let ease = lua.create_table()?;
+
+    for kind in Ease::NAMED {
+        ease.set(
+            kind.name(),
+            lua.create_function(move |_, t: f64| Ok(kind.apply(t)))?,
+        )?;
+    }
+
+    ease.set(
+        "bezier",
+        lua.create_function(|_, (x1, y1, x2, y2, t): (f64, f64, f64, f64, f64)| {
+            Ok(cubic_bezier(x1, y1, x2, y2, t))
+        })?,
+    )?;
+
+    ease.set(
+        "apply",
+        lua.create_function(|_, (name, t): (String, f64)| match Ease::from_name(&name) {
+            Some(kind) => Ok(kind.apply(t)),
+            None => Err(mlua::Error::RuntimeError(format!(
+                "unknown easing function '{}'",
+                name
+            ))),
+        })?,
+    )?;
+
+    lua.globals().set("ease", ease)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_easing_endpoints() {
+        for kind in Ease::NAMED {
+            assert!(kind.apply(0.0).abs() < 1e-10, "{} at 0", kind.name());
+            assert!((kind.apply(1.0) - 1.0).abs() < 1e-10, "{} at 1", kind.name());
+        }
+    }
+
+    #[test]
+    fn test_cubic_bezier_matches_linear() {
+        for i in 0..=10 {
+            let t = i as f64 / 10.0;
+            assert!((cubic_bezier(0.25, 0.25, 0.75, 0.75, t) - t).abs() < 1e-6);
+        }
+        assert!(cubic_bezier(0.42, 0.0, 0.58, 1.0, 0.25) < 0.25);
+    }
+
+    #[test]
+    fn test_lua_ease_table() -> Result<()> {
+        let lua = Lua::new();
+        register(&lua)?;
+
+        let result: f64 = lua.load("return ease.quad_in(0.5)").eval()?;
+        assert!((result - 0.25).abs() < 1e-10);
+
+        let result: f64 = lua.load("return ease.apply('cubic_in', 0.5)").eval()?;
+        assert!((result - 0.125).abs() < 1e-10);
+
+        assert!(lua.load("return ease.apply('nope', 0.5)").exec().is_err());
+
+        Ok(())
+    }
+}
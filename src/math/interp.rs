@@ -0,0 +1,36 @@
+use super::Vec2;
+
+pub fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+pub fn inverse_lerp(a: f64, b: f64, value: f64) -> f64 {
+    if a == b { 0.0 } else { (value - a) / (b - a) }
+}
+
+pub fn remap(value: f64, from_min: f64, from_max: f64, to_min: f64, to_max: f64) -> f64 {
+    lerp(to_min, to_max, inverse_lerp(from_min, from_max, value))
+}
+
+pub fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = inverse_lerp(edge0, edge1, x).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Spherical interpolation between two directions, falling back to a plain
+/// lerp when they are (nearly) parallel.
+pub fn slerp(a: Vec2, b: Vec2, t: f64) -> Vec2 {
+    let (len_a, len_b) = (a.length(), b.length());
+    if len_a == 0.0 || len_b == 0.0 {
+        return a + (b - a) * t;
+    }
+
+    let cos_omega = (a.dot(b) / (len_a * len_b)).clamp(-1.0, 1.0);
+    let omega = cos_omega.acos();
+    let sin_omega = omega.sin();
+    if sin_omega.abs() < 1e-9 {
+        return a + (b - a) * t;
+    }
+
+    a * (((1.0 - t) * omega).sin() / sin_omega) + b * ((t * omega).sin() / sin_omega)
+}
@@ -0,0 +1,94 @@
+pub mod ease;
+mod interp;
+pub mod noise;
+mod transform;
+mod vec;
+#[cfg(feature = "3d")]
+mod vec3;
+
+pub use interp::{inverse_lerp, lerp, remap, slerp, smoothstep};
+pub use transform::Transform;
+#[cfg(feature = "3d")]
+pub use transform::Transform3;
+pub use vec::Vec2;
+#[cfg(feature = "3d")]
+pub use vec3::{Quat, Vec3};
+
+use mlua::{Lua, Result, Table};
+
+pub fn register(lua: &Lua) -> Result<()> {
+    ease::register(lua)?;
+    noise::register(lua)?;
+
+    let math: Table = lua.globals().get("math")?;
+
+    // Luau ships math.lerp as a fastcall builtin; only fill it in when missing.
+    if math.get::<mlua::Value>("lerp")?.is_nil() {
+        math.set(
+            "lerp",
+            lua.create_function(|_, (a, b, t): (f64, f64, f64)| Ok(lerp(a, b, t)))?,
+        )?;
+    }
+
+    math.set(
+        "inverse_lerp",
+        lua.create_function(|_, (a, b, value): (f64, f64, f64)| Ok(inverse_lerp(a, b, value)))?,
+    )?;
+
+    math.set(
+        "remap",
+        lua.create_function(
+            |_, (value, from_min, from_max, to_min, to_max): (f64, f64, f64, f64, f64)| {
+                Ok(remap(value, from_min, from_max, to_min, to_max))
+            },
+        )?,
+    )?;
+
+    math.set(
+        "smoothstep",
+        lua.create_function(|_, (edge0, edge1, x): (f64, f64, f64)| {
+            Ok(smoothstep(edge0, edge1, x))
+        })?,
+    )?;
+
+    math.set(
+        "slerp",
+        lua.create_function(|_, (ax, ay, bx, by, t): (f64, f64, f64, f64, f64)| {
+            let v = slerp(Vec2::new(ax, ay), Vec2::new(bx, by), t);
+            Ok((v.x, v.y))
+        })?,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolation_helpers() {
+        assert_eq!(lerp(2.0, 4.0, 0.5), 3.0);
+        assert_eq!(remap(5.0, 0.0, 10.0, 100.0, 200.0), 150.0);
+        assert_eq!(smoothstep(0.0, 1.0, -1.0), 0.0);
+        assert_eq!(smoothstep(0.0, 1.0, 0.5), 0.5);
+
+        let v = slerp(Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0), 0.5);
+        let expected = std::f64::consts::FRAC_1_SQRT_2;
+        assert!((v.x - expected).abs() < 1e-10 && (v.y - expected).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_lua_math_helpers() -> Result<()> {
+        let lua = Lua::new();
+        register(&lua)?;
+
+        let result: f64 = lua.load("return math.remap(0.5, 0, 1, 10, 20)").eval()?;
+        assert_eq!(result, 15.0);
+
+        let (x, y): (f64, f64) = lua.load("return math.slerp(1, 0, 0, 1, 1)").eval()?;
+        assert!(x.abs() < 1e-10 && (y - 1.0).abs() < 1e-10);
+
+        Ok(())
+    }
+}
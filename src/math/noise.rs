@@ -0,0 +1,443 @@
+use super::lerp;
+use crate::rng::GameRng;
+use mlua::{Error, FromLua, Lua, Result, UserData, UserDataMethods, Value};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const GRAD3: [[f64; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fbm {
+    pub octaves: u32,
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub gain: f64,
+}
+
+impl Default for Fbm {
+    fn default() -> Self {
+        Fbm {
+            octaves: 4,
+            frequency: 1.0,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl Fbm {
+    /// Sums `octaves` layers of `sample`, normalized back into `[-1, 1]`.
+    fn accumulate(&self, mut sample: impl FnMut(f64) -> f64) -> f64 {
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        let mut total_amplitude = 0.0;
+        let mut frequency = self.frequency;
+
+        for _ in 0..self.octaves.max(1) {
+            sum += sample(frequency) * amplitude;
+            total_amplitude += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+
+        sum / total_amplitude
+    }
+}
+
+/// More octaves than this add nothing visible, so scripts can't stall a
+/// frame asking for a billion.
+const MAX_LUA_OCTAVES: u32 = 16;
+
+impl FromLua for Fbm {
+    fn from_lua(value: Value, _lua: &Lua) -> Result<Self> {
+        match value {
+            Value::Nil => Ok(Fbm::default()),
+            Value::Table(table) => {
+                let defaults = Fbm::default();
+                let field = |name: &str| {
+                    table
+                        .get::<Option<f64>>(name)
+                        .map_err(|e| Error::FromLuaConversionError {
+                            from: "Table",
+                            to: "Fbm".to_string(),
+                            message: Some(format!("Failed to get '{}' field: {}", name, e)),
+                        })
+                };
+
+                let octaves = field("octaves")?.map_or(defaults.octaves, |v| v as u32);
+                if octaves > MAX_LUA_OCTAVES {
+                    return Err(Error::FromLuaConversionError {
+                        from: "Table",
+                        to: "Fbm".to_string(),
+                        message: Some(format!(
+                            "octaves must be at most {}, got {}",
+                            MAX_LUA_OCTAVES, octaves
+                        )),
+                    });
+                }
+
+                Ok(Fbm {
+                    octaves,
+                    frequency: field("frequency")?.unwrap_or(defaults.frequency),
+                    lacunarity: field("lacunarity")?.unwrap_or(defaults.lacunarity),
+                    gain: field("gain")?.unwrap_or(defaults.gain),
+                })
+            }
+            _ => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "Fbm".to_string(),
+                message: Some("Expected an options table or nil".to_string()),
+            }),
+        }
+    }
+}
+
+/// Seeded Perlin and simplex noise. Outputs are roughly in `[-1, 1]`.
+#[derive(Clone)]
+pub struct Noise {
+    perm: [u8; 512],
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        let mut rng = GameRng::new(seed);
+        rng.shuffle(&mut table);
+
+        Noise {
+            perm: std::array::from_fn(|i| table[i & 255]),
+        }
+    }
+
+    fn hash(&self, i: i64) -> usize {
+        self.perm[(i & 255) as usize] as usize
+    }
+
+    pub fn perlin1(&self, x: f64) -> f64 {
+        let xi = x.floor() as i64;
+        let xf = x - x.floor();
+        let u = fade(xf);
+
+        let grad = |h: usize, d: f64| if h & 1 == 0 { d } else { -d };
+        let a = grad(self.hash(xi), xf);
+        let b = grad(self.hash(xi + 1), xf - 1.0);
+
+        // The 1D gradient range is [-0.5, 0.5]; rescale to match 2D/3D.
+        2.0 * lerp(a, b, u)
+    }
+
+    pub fn perlin2(&self, x: f64, y: f64) -> f64 {
+        let (xi, yi) = (x.floor() as i64, y.floor() as i64);
+        let (xf, yf) = (x - x.floor(), y - y.floor());
+        let (u, v) = (fade(xf), fade(yf));
+
+        let corner = |dx: i64, dy: i64| {
+            let h = self.hash(xi + dx + self.hash(yi + dy) as i64);
+            let g = GRAD3[h % 12];
+            g[0] * (xf - dx as f64) + g[1] * (yf - dy as f64)
+        };
+
+        lerp(
+            lerp(corner(0, 0), corner(1, 0), u),
+            lerp(corner(0, 1), corner(1, 1), u),
+            v,
+        )
+    }
+
+    pub fn perlin3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (xi, yi, zi) = (x.floor() as i64, y.floor() as i64, z.floor() as i64);
+        let (xf, yf, zf) = (x - x.floor(), y - y.floor(), z - z.floor());
+        let (u, v, w) = (fade(xf), fade(yf), fade(zf));
+
+        let corner = |dx: i64, dy: i64, dz: i64| {
+            let h = self.hash(xi + dx + self.hash(yi + dy + self.hash(zi + dz) as i64) as i64);
+            let g = GRAD3[h % 12];
+            g[0] * (xf - dx as f64) + g[1] * (yf - dy as f64) + g[2] * (zf - dz as f64)
+        };
+
+        lerp(
+            lerp(
+                lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+                lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+                v,
+            ),
+            lerp(
+                lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+                lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+                v,
+            ),
+            w,
+        )
+    }
+
+    pub fn simplex2(&self, x: f64, y: f64) -> f64 {
+        let f2 = 0.5 * (3f64.sqrt() - 1.0);
+        let g2 = (3.0 - 3f64.sqrt()) / 6.0;
+
+        let s = (x + y) * f2;
+        let (i, j) = ((x + s).floor() as i64, (y + s).floor() as i64);
+        let t = (i + j) as f64 * g2;
+        let (x0, y0) = (x - (i as f64 - t), y - (j as f64 - t));
+
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let offsets = [
+            (x0, y0, 0, 0),
+            (x0 - i1 as f64 + g2, y0 - j1 as f64 + g2, i1, j1),
+            (x0 - 1.0 + 2.0 * g2, y0 - 1.0 + 2.0 * g2, 1, 1),
+        ];
+
+        let mut total = 0.0;
+        for (dx, dy, oi, oj) in offsets {
+            let falloff = 0.5 - dx * dx - dy * dy;
+            if falloff > 0.0 {
+                let h = self.hash(i + oi + self.hash(j + oj) as i64);
+                let g = GRAD3[h % 12];
+                total += falloff.powi(4) * (g[0] * dx + g[1] * dy);
+            }
+        }
+
+        70.0 * total
+    }
+
+    pub fn simplex3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let f3 = 1.0 / 3.0;
+        let g3 = 1.0 / 6.0;
+
+        let s = (x + y + z) * f3;
+        let (i, j, k) = (
+            (x + s).floor() as i64,
+            (y + s).floor() as i64,
+            (z + s).floor() as i64,
+        );
+        let t = (i + j + k) as f64 * g3;
+        let (x0, y0, z0) = (x - (i as f64 - t), y - (j as f64 - t), z - (k as f64 - t));
+
+        let ((i1, j1, k1), (i2, j2, k2)) = if x0 >= y0 {
+            if y0 >= z0 {
+                ((1, 0, 0), (1, 1, 0))
+            } else if x0 >= z0 {
+                ((1, 0, 0), (1, 0, 1))
+            } else {
+                ((0, 0, 1), (1, 0, 1))
+            }
+        } else if y0 < z0 {
+            ((0, 0, 1), (0, 1, 1))
+        } else if x0 < z0 {
+            ((0, 1, 0), (0, 1, 1))
+        } else {
+            ((0, 1, 0), (1, 1, 0))
+        };
+
+        let corners = [
+            (0, 0, 0, 0.0),
+            (i1, j1, k1, g3),
+            (i2, j2, k2, 2.0 * g3),
+            (1, 1, 1, 3.0 * g3),
+        ];
+
+        let mut total = 0.0;
+        for (oi, oj, ok, offset) in corners {
+            let dx = x0 - oi as f64 + offset;
+            let dy = y0 - oj as f64 + offset;
+            let dz = z0 - ok as f64 + offset;
+            let falloff = 0.6 - dx * dx - dy * dy - dz * dz;
+            if falloff > 0.0 {
+                let h = self.hash(i + oi + self.hash(j + oj + self.hash(k + ok) as i64) as i64);
+                let g = GRAD3[h % 12];
+                total += falloff.powi(4) * (g[0] * dx + g[1] * dy + g[2] * dz);
+            }
+        }
+
+        32.0 * total
+    }
+
+    pub fn fbm1(&self, x: f64, fbm: &Fbm) -> f64 {
+        fbm.accumulate(|f| self.perlin1(x * f))
+    }
+
+    pub fn fbm2(&self, x: f64, y: f64, fbm: &Fbm) -> f64 {
+        fbm.accumulate(|f| self.simplex2(x * f, y * f))
+    }
+
+    pub fn fbm3(&self, x: f64, y: f64, z: f64, fbm: &Fbm) -> f64 {
+        fbm.accumulate(|f| self.simplex3(x * f, y * f, z * f))
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+impl UserData for Noise {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("perlin1", |_, this, x: f64| Ok(this.perlin1(x)));
+        methods.add_method("perlin2", |_, this, (x, y): (f64, f64)| {
+            Ok(this.perlin2(x, y))
+        });
+        methods.add_method("perlin3", |_, this, (x, y, z): (f64, f64, f64)| {
+            Ok(this.perlin3(x, y, z))
+        });
+        methods.add_method("simplex2", |_, this, (x, y): (f64, f64)| {
+            Ok(this.simplex2(x, y))
+        });
+        methods.add_method("simplex3", |_, this, (x, y, z): (f64, f64, f64)| {
+            Ok(this.simplex3(x, y, z))
+        });
+        methods.add_method("fbm1", |_, this, (x, fbm): (f64, Fbm)| {
+            Ok(this.fbm1(x, &fbm))
+        });
+        methods.add_method("fbm2", |_, this, (x, y, fbm): (f64, f64, Fbm)| {
+            Ok(this.fbm2(x, y, &fbm))
+        });
+        methods.add_method("fbm3", |_, this, (x, y, z, fbm): (f64, f64, f64, Fbm)| {
+            Ok(this.fbm3(x, y, z, &fbm))
+        });
+    }
+}
+
+pub fn register(lua: &Lua) -> Result<()> {
+    let shared = Rc::new(RefCell::new(Noise::new(0)));
+    let noise = lua.create_table()?;
+
+    noise.set(
+        "new",
+        lua.create_function(|_, seed: Option<u64>| Ok(Noise::new(seed.unwrap_or(0))))?,
+    )?;
+
+    let state = shared.clone();
+    noise.set(
+        "seed",
+        lua.create_function(move |_, seed: u64| {
+            *state.borrow_mut() = Noise::new(seed);
+            Ok(())
+        })?,
+    )?;
+
+    let state = shared.clone();
+    noise.set(
+        "perlin1",
+        lua.create_function(move |_, x: f64| Ok(state.borrow().perlin1(x)))?,
+    )?;
+    let state = shared.clone();
+    noise.set(
+        "perlin2",
+        lua.create_function(move |_, (x, y): (f64, f64)| Ok(state.borrow().perlin2(x, y)))?,
+    )?;
+    let state = shared.clone();
+    noise.set(
+        "perlin3",
+        lua.create_function(move |_, (x, y, z): (f64, f64, f64)| {
+            Ok(state.borrow().perlin3(x, y, z))
+        })?,
+    )?;
+    let state = shared.clone();
+    noise.set(
+        "simplex2",
+        lua.create_function(move |_, (x, y): (f64, f64)| Ok(state.borrow().simplex2(x, y)))?,
+    )?;
+    let state = shared.clone();
+    noise.set(
+        "simplex3",
+        lua.create_function(move |_, (x, y, z): (f64, f64, f64)| {
+            Ok(state.borrow().simplex3(x, y, z))
+        })?,
+    )?;
+    let state = shared.clone();
+    noise.set(
+        "fbm1",
+        lua.create_function(move |_, (x, fbm): (f64, Fbm)| Ok(state.borrow().fbm1(x, &fbm)))?,
+    )?;
+    let state = shared.clone();
+    noise.set(
+        "fbm2",
+        lua.create_function(move |_, (x, y, fbm): (f64, f64, Fbm)| {
+            Ok(state.borrow().fbm2(x, y, &fbm))
+        })?,
+    )?;
+    let state = shared;
+    noise.set(
+        "fbm3",
+        lua.create_function(move |_, (x, y, z, fbm): (f64, f64, f64, Fbm)| {
+            Ok(state.borrow().fbm3(x, y, z, &fbm))
+        })?,
+    )?;
+
+    lua.globals().set("noise", noise)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_is_seeded_and_bounded() {
+        let a = Noise::new(1);
+        let b = Noise::new(1);
+        let c = Noise::new(2);
+
+        let mut differs = false;
+        for i in 0..200 {
+            let (x, y, z) = (i as f64 * 0.37, i as f64 * 0.11, i as f64 * 0.07);
+            assert_eq!(a.simplex2(x, y), b.simplex2(x, y));
+            differs |= a.perlin2(x, y) != c.perlin2(x, y);
+
+            for value in [
+                a.perlin1(x),
+                a.perlin2(x, y),
+                a.perlin3(x, y, z),
+                a.simplex2(x, y),
+                a.simplex3(x, y, z),
+                a.fbm2(x, y, &Fbm::default()),
+            ] {
+                assert!((-1.01..=1.01).contains(&value), "{} out of range", value);
+            }
+        }
+        assert!(differs);
+    }
+
+    #[test]
+    fn test_perlin_is_zero_on_lattice() {
+        let noise = Noise::new(3);
+        assert_eq!(noise.perlin2(4.0, 7.0), 0.0);
+        assert_eq!(noise.perlin3(1.0, 2.0, 3.0), 0.0);
+    }
+
+    #[test]
+    fn test_lua_noise_table() -> Result<()> {
+        let lua = Lua::new();
+        register(&lua)?;
+
+        let shared: f64 = lua
+            .load("noise.seed(5) return noise.fbm2(1.3, 2.7, {octaves = 4})")
+            .eval()?;
+        let instance: f64 = lua
+            .load("return noise.new(5):fbm2(1.3, 2.7, {octaves = 4})")
+            .eval()?;
+        assert_eq!(shared, instance);
+        assert_eq!(shared, Noise::new(5).fbm2(1.3, 2.7, &Fbm::default()));
+
+        assert!(lua.load("return noise.fbm2(1, 2, 'bad')").exec().is_err());
+        assert!(
+            lua.load("return noise.fbm2(1, 2, {octaves = 1e9})")
+                .exec()
+                .is_err()
+        );
+        lua.load("return noise.fbm2(1, 2, {octaves = 16})").exec()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use super::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Placement of an entity: scaled, then rotated (radians, counter-clockwise),
+/// then translated.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transform {
+    pub translation: Vec2,
+    pub rotation: f64,
+    pub scale: Vec2,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: Vec2::ZERO,
+            rotation: 0.0,
+            scale: Vec2::new(1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vec2) -> Self {
+        Transform {
+            translation,
+            ..Transform::default()
+        }
+    }
+
+    /// The local X and Y axes in world space, ignoring scale.
+    pub fn axes(&self) -> (Vec2, Vec2) {
+        (
+            Vec2::new(1.0, 0.0).rotate(self.rotation),
+            Vec2::new(0.0, 1.0).rotate(self.rotation),
+        )
+    }
+
+    pub fn transform_point(&self, point: Vec2) -> Vec2 {
+        let scaled = Vec2::new(point.x * self.scale.x, point.y * self.scale.y);
+        self.translation + scaled.rotate(self.rotation)
+    }
+}
+
+/// `Transform` for 3D entities: scaled, then rotated, then translated.
+#[cfg(feature = "3d")]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transform3 {
+    pub translation: super::Vec3,
+    pub rotation: super::Quat,
+    pub scale: super::Vec3,
+}
+
+#[cfg(feature = "3d")]
+impl Default for Transform3 {
+    fn default() -> Self {
+        Transform3 {
+            translation: super::Vec3::ZERO,
+            rotation: super::Quat::IDENTITY,
+            scale: super::Vec3::ONE,
+        }
+    }
+}
+
+#[cfg(feature = "3d")]
+impl Transform3 {
+    pub fn from_translation(translation: super::Vec3) -> Self {
+        Transform3 {
+            translation,
+            ..Transform3::default()
+        }
+    }
+
+    pub fn transform_point(&self, point: super::Vec3) -> super::Vec3 {
+        self.translation + self.rotation.rotate(point.scale(self.scale))
+    }
+}
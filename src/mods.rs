@@ -0,0 +1,824 @@
+use crate::assets::DirSource;
+use crate::content::ContentManifest;
+use crate::data::{DataError, from_ron};
+use crate::ecs::{Entity, ScriptValue, World};
+use crate::sandbox::{ModManifest, mod_environment, release_mod_environment};
+use crate::scene::{SceneEntity, spawn_entity};
+use mlua::{Function, Lua, Result, Table};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE: &str = "mod.ron";
+
+#[derive(Debug)]
+pub enum ModError {
+    Data {
+        path: PathBuf,
+        error: Box<DataError>,
+    },
+    Duplicate(String),
+    /// Names can't hold ':', which separates them from asset paths.
+    InvalidName(String),
+    UnknownMod(String),
+    MissingDependency {
+        name: String,
+        dependency: String,
+    },
+    /// Mods that depend on each other in a loop.
+    Cycle(Vec<String>),
+    Script {
+        name: String,
+        error: mlua::Error,
+    },
+}
+
+impl fmt::Display for ModError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModError::Data { path, error } => write!(f, "{}: {}", path.display(), error),
+            ModError::Duplicate(name) => write!(f, "two mods are named '{}'", name),
+            ModError::InvalidName(name) => write!(f, "'{}' is not a valid mod name", name),
+            ModError::UnknownMod(name) => write!(f, "no mod named '{}'", name),
+            ModError::MissingDependency { name, dependency } => {
+                write!(
+                    f,
+                    "mod '{}' needs '{}', which is not enabled",
+                    name, dependency
+                )
+            }
+            ModError::Cycle(names) => write!(f, "mods depend on each other: {}", names.join(", ")),
+            ModError::Script { name, error } => write!(f, "mod '{}' failed: {}", name, error),
+        }
+    }
+}
+
+impl std::error::Error for ModError {}
+
+impl From<ModError> for mlua::Error {
+    fn from(e: ModError) -> Self {
+        mlua::Error::external(e)
+    }
+}
+
+/// A discovered mod folder: `mod.ron`, then optional `scripts/`,
+/// `prefabs/` and `assets/` directories, plus `patches/` and `overrides/`
+/// for changing the base game and other mods.
+#[derive(Debug, Clone)]
+pub struct ModInfo {
+    pub manifest: ModManifest,
+    pub dir: PathBuf,
+    pub enabled: bool,
+}
+
+impl ModInfo {
+    /// Reads `dir/mod.ron`; a manifest without a name takes the folder's.
+    pub fn load(dir: impl Into<PathBuf>) -> std::result::Result<Self, ModError> {
+        let dir = dir.into();
+        let path = dir.join(MANIFEST_FILE);
+        let data = |error: DataError| ModError::Data {
+            path: path.clone(),
+            error: Box::new(error),
+        };
+        let source = fs::read_to_string(&path).map_err(|e| data(e.into()))?;
+        let mut manifest: ModManifest = from_ron(&source).map_err(data)?;
+        if manifest.name.is_empty() {
+            manifest.name = dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+        }
+        Ok(ModInfo {
+            manifest,
+            dir,
+            enabled: true,
+        })
+    }
+}
+
+/// What a running mod holds on to until it is unloaded.
+struct LoadedMod {
+    env: Table,
+    prefabs: BTreeMap<String, SceneEntity>,
+    /// Partial prefabs merged over others, keyed by the prefab they patch.
+    patches: BTreeMap<String, SceneEntity>,
+    /// Paths under `overrides/` replacing base-game assets.
+    overrides: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OverrideKind {
+    /// The file was replaced outright.
+    Asset,
+    /// Fields were merged in.
+    Prefab,
+}
+
+/// Mods that replaced or patched one asset, in load order; the last one's
+/// file or fields win.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetOverride {
+    pub asset: String,
+    pub kind: OverrideKind,
+    pub mods: Vec<String>,
+}
+
+impl AssetOverride {
+    pub fn winner(&self) -> &str {
+        self.mods.last().map_or("", String::as_str)
+    }
+}
+
+/// Overlays `patch` field by field: maps merge recursively, anything else
+/// replaces what was there.
+fn merge_value(base: &mut ScriptValue, patch: &ScriptValue) {
+    match (base, patch) {
+        (ScriptValue::Map(base), ScriptValue::Map(patch)) => {
+            for (field, value) in patch {
+                match base.get_mut(field) {
+                    Some(existing) => merge_value(existing, value),
+                    None => {
+                        base.insert(field.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, patch) => *base = patch.clone(),
+    }
+}
+
+/// Discovers mods, orders them by their dependencies and loads the
+/// enabled ones, each into its own sandboxed environment. Prefabs and
+/// assets are namespaced as `mod:name`. Enabling or disabling marks the
+/// change; `apply` carries it out without a restart.
+#[derive(Default)]
+pub struct ModManager {
+    mods: BTreeMap<String, ModInfo>,
+    base_prefabs: BTreeMap<String, SceneEntity>,
+    base_assets: Option<PathBuf>,
+    loaded: BTreeMap<String, LoadedMod>,
+    /// Load order of what is currently loaded.
+    order: Vec<String>,
+}
+
+impl ModManager {
+    pub fn new() -> Self {
+        ModManager::default()
+    }
+
+    /// Every folder under `root` holding a `mod.ron`.
+    pub fn discover(&mut self, root: impl AsRef<Path>) -> std::result::Result<usize, ModError> {
+        let root = root.as_ref();
+        let data = |error: std::io::Error| ModError::Data {
+            path: root.to_path_buf(),
+            error: Box::new(error.into()),
+        };
+        let mut dirs: Vec<PathBuf> = fs::read_dir(root)
+            .map_err(data)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<_>>()
+            .map_err(data)?;
+        dirs.retain(|dir| dir.join(MANIFEST_FILE).is_file());
+        dirs.sort();
+        let found = dirs.len();
+        for dir in dirs {
+            self.add(ModInfo::load(dir)?)?;
+        }
+        Ok(found)
+    }
+
+    /// The game's own prefabs, which mods patch through
+    /// `patches/<prefab>.ron`.
+    pub fn set_base_prefabs(&mut self, prefabs: BTreeMap<String, SceneEntity>) {
+        self.base_prefabs = prefabs;
+    }
+
+    /// The game's asset directory, which mods override through
+    /// `overrides/<path>`.
+    pub fn set_base_assets(&mut self, dir: impl Into<PathBuf>) {
+        self.base_assets = Some(dir.into());
+    }
+
+    pub fn add(&mut self, info: ModInfo) -> std::result::Result<(), ModError> {
+        let name = info.manifest.name.clone();
+        if name.is_empty() || name.contains(':') {
+            return Err(ModError::InvalidName(name));
+        }
+        if self.mods.contains_key(&name) {
+            return Err(ModError::Duplicate(name));
+        }
+        self.mods.insert(name, info);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ModInfo> {
+        self.mods.get(name)
+    }
+
+    pub fn mods(&self) -> impl Iterator<Item = &ModInfo> {
+        self.mods.values()
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> std::result::Result<(), ModError> {
+        let info = self
+            .mods
+            .get_mut(name)
+            .ok_or_else(|| ModError::UnknownMod(name.to_string()))?;
+        info.enabled = enabled;
+        Ok(())
+    }
+
+    pub fn is_loaded(&self, name: &str) -> bool {
+        self.loaded.contains_key(name)
+    }
+
+    /// Mods as loaded by the last `apply`.
+    pub fn load_order(&self) -> &[String] {
+        &self.order
+    }
+
+    /// Orders the enabled mods so each loads after its dependencies and
+    /// any enabled `load_after` mods, otherwise alphabetically.
+    pub fn resolve(&self) -> std::result::Result<Vec<String>, ModError> {
+        let enabled: BTreeSet<&str> = self
+            .mods
+            .values()
+            .filter(|info| info.enabled)
+            .map(|info| info.manifest.name.as_str())
+            .collect();
+        let mut after: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for &name in &enabled {
+            let manifest = &self.mods[name].manifest;
+            let mut first = BTreeSet::new();
+            for dependency in &manifest.dependencies {
+                if !enabled.contains(dependency.as_str()) {
+                    return Err(ModError::MissingDependency {
+                        name: name.to_string(),
+                        dependency: dependency.clone(),
+                    });
+                }
+                first.insert(dependency.as_str());
+            }
+            first.extend(
+                manifest
+                    .load_after
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|other| enabled.contains(other)),
+            );
+            after.insert(name, first);
+        }
+        let mut order: Vec<String> = Vec::with_capacity(enabled.len());
+        while order.len() < enabled.len() {
+            let next = after
+                .iter()
+                .find(|(_, first)| first.is_empty())
+                .map(|(&name, _)| name);
+            let Some(next) = next else {
+                return Err(ModError::Cycle(
+                    after.keys().map(|name| name.to_string()).collect(),
+                ));
+            };
+            after.remove(next);
+            for first in after.values_mut() {
+                first.remove(next);
+            }
+            order.push(next.to_string());
+        }
+        Ok(order)
+    }
+
+    /// Unloads mods that are no longer enabled, or whose place in the
+    /// order moved, then loads the rest in order. A mod's scripts run
+    /// once on load, sorted by file name and given the world; a global
+    /// `on_unload(world)` they define runs when it unloads.
+    pub fn apply(&mut self, world: &mut World, lua: &Lua) -> std::result::Result<(), ModError> {
+        let order = self.resolve()?;
+        let keep = order
+            .iter()
+            .zip(&self.order)
+            .take_while(|(new, old)| new == old)
+            .count();
+        // Every mod gets unloaded even if one of them fails to.
+        let mut unloaded = Ok(());
+        for name in self.order.split_off(keep).into_iter().rev() {
+            let result = self.unload(world, lua, &name);
+            if unloaded.is_ok() {
+                unloaded = result;
+            }
+        }
+        unloaded?;
+        for name in &order[keep..] {
+            let loaded = self.load(world, lua, name)?;
+            self.loaded.insert(name.clone(), loaded);
+            self.order.push(name.clone());
+        }
+        for conflict in self.overrides().iter().filter(|o| o.mods.len() > 1) {
+            log::info!(
+                "{} is overridden by {}; {} wins",
+                conflict.asset,
+                conflict.mods.join(", "),
+                conflict.winner()
+            );
+        }
+        Ok(())
+    }
+
+    fn load(
+        &self,
+        world: &mut World,
+        lua: &Lua,
+        name: &str,
+    ) -> std::result::Result<LoadedMod, ModError> {
+        let info = &self.mods[name];
+        let script = |error| ModError::Script {
+            name: name.to_string(),
+            error,
+        };
+        let prefabs = read_prefabs(&info.dir.join("prefabs"), "")?;
+        let mut patches = read_prefabs(&info.dir.join("patches"), "")?;
+        let patch_dirs = DirSource::new(info.dir.join("patches"));
+        for owner in self.mods.keys() {
+            let Some(dir) = patch_dirs.resolve(owner) else {
+                continue;
+            };
+            patches.extend(read_prefabs(&dir, &format!("{}:", owner))?);
+        }
+        let mut overrides = BTreeSet::new();
+        files_under(&info.dir.join("overrides"), "", &mut overrides)?;
+        let scripts = read_dir_sorted(&info.dir.join("scripts"), "lua")?;
+        let env = mod_environment(lua, &info.manifest).map_err(script)?;
+        let run = |world: &mut World| {
+            for path in &scripts {
+                let source = fs::read(path).map_err(|e| ModError::Data {
+                    path: path.clone(),
+                    error: Box::new(e.into()),
+                })?;
+                let chunk = format!(
+                    "{}/{}",
+                    name,
+                    path.strip_prefix(&info.dir).unwrap_or(path).display()
+                );
+                lua.scope(|scope| {
+                    let world = scope.create_userdata_ref_mut(&mut *world)?;
+                    lua.load(source)
+                        .set_name(format!("@{}", chunk))
+                        .set_environment(env.clone())
+                        .call::<()>(world)
+                })
+                .map_err(script)?;
+            }
+            Ok(())
+        };
+        // A mod that fails halfway gets to undo what its earlier scripts did.
+        if let Err(error) = run(world) {
+            if let Err(unload) = call_unload_hook(world, lua, &env) {
+                log::error!("mod '{}' failed to unload: {}", name, unload);
+            }
+            release_mod_environment(lua, name);
+            return Err(error);
+        }
+        Ok(LoadedMod {
+            env,
+            prefabs,
+            patches,
+            overrides,
+        })
+    }
+
+    fn unload(
+        &mut self,
+        world: &mut World,
+        lua: &Lua,
+        name: &str,
+    ) -> std::result::Result<(), ModError> {
+        let Some(loaded) = self.loaded.remove(name) else {
+            return Ok(());
+        };
+        let result = call_unload_hook(world, lua, &loaded.env);
+        release_mod_environment(lua, name);
+        result.map_err(|error| ModError::Script {
+            name: name.to_string(),
+            error,
+        })
+    }
+
+    /// `mod:prefab` from a loaded mod's `prefabs/prefab.ron`, or a base
+    /// prefab by its plain name, with the patches of every mod loaded
+    /// after its owner merged in.
+    pub fn prefab(&self, name: &str) -> Option<SceneEntity> {
+        let (mut prefab, patchers) = match name.split_once(':') {
+            Some((owner, prefab)) => {
+                let prefab = self.loaded.get(owner)?.prefabs.get(prefab)?.clone();
+                let position = self.order.iter().position(|name| name == owner)?;
+                (prefab, &self.order[position + 1..])
+            }
+            None => (self.base_prefabs.get(name)?.clone(), &self.order[..]),
+        };
+        for patcher in patchers {
+            let Some(patch) = self.loaded[patcher].patches.get(name) else {
+                continue;
+            };
+            for (component, value) in patch {
+                match prefab.get_mut(component) {
+                    Some(existing) => merge_value(existing, value),
+                    None => {
+                        prefab.insert(component.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        Some(prefab)
+    }
+
+    pub fn prefab_names(&self) -> Vec<String> {
+        self.order
+            .iter()
+            .flat_map(|owner| {
+                self.loaded[owner]
+                    .prefabs
+                    .keys()
+                    .map(move |prefab| format!("{}:{}", owner, prefab))
+            })
+            .collect()
+    }
+
+    pub fn spawn_prefab(&self, world: &mut World, lua: &Lua, name: &str) -> Result<Entity> {
+        let prefab = self
+            .prefab(name)
+            .ok_or_else(|| mlua::Error::RuntimeError(format!("no prefab named '{}'", name)))?;
+        spawn_entity(world, lua, &prefab)
+    }
+
+    /// Where an asset lives on disk: `mod:path` in a loaded mod's
+    /// `assets/`, or a base-game path from the last mod overriding it,
+    /// falling back to the base asset directory.
+    pub fn asset_path(&self, name: &str) -> Option<PathBuf> {
+        let path = match name.split_once(':') {
+            Some((owner, path)) => {
+                if !self.loaded.contains_key(owner) {
+                    return None;
+                }
+                DirSource::new(self.mods[owner].dir.join("assets")).resolve(path)?
+            }
+            None => match self
+                .order
+                .iter()
+                .rev()
+                .find(|owner| self.loaded[*owner].overrides.contains(name))
+            {
+                Some(owner) => {
+                    DirSource::new(self.mods[owner].dir.join("overrides")).resolve(name)?
+                }
+                None => DirSource::new(self.base_assets.as_ref()?).resolve(name)?,
+            },
+        };
+        path.is_file().then_some(path)
+    }
+
+    /// Hashes of the base assets and every file of each loaded mod, the
+    /// latter named `mod:path`.
+    pub fn content_manifest(&self) -> std::result::Result<ContentManifest, DataError> {
+        let mut manifest = ContentManifest::new();
+        if let Some(base) = &self.base_assets {
+            manifest.record_dir("", base)?;
+        }
+        for name in &self.order {
+            manifest.record_dir(&format!("{}:", name), &self.mods[name].dir)?;
+        }
+        Ok(manifest)
+    }
+
+    /// Every asset and prefab a loaded mod replaced or patched, sorted by
+    /// name. Entries with more than one mod are conflicts.
+    pub fn overrides(&self) -> Vec<AssetOverride> {
+        let mut found: BTreeMap<(String, OverrideKind), Vec<String>> = BTreeMap::new();
+        for owner in &self.order {
+            let loaded = &self.loaded[owner];
+            for asset in &loaded.overrides {
+                found
+                    .entry((asset.clone(), OverrideKind::Asset))
+                    .or_default()
+                    .push(owner.clone());
+            }
+            for prefab in loaded.patches.keys() {
+                // Patches only count against prefabs they can reach.
+                let applies = match prefab.split_once(':') {
+                    Some((target, stem)) => {
+                        let before = |name: &str| self.order.iter().position(|n| n == name);
+                        self.loaded
+                            .get(target)
+                            .is_some_and(|t| t.prefabs.contains_key(stem))
+                            && before(target) < before(owner)
+                    }
+                    None => self.base_prefabs.contains_key(prefab),
+                };
+                if applies {
+                    found
+                        .entry((prefab.clone(), OverrideKind::Prefab))
+                        .or_default()
+                        .push(owner.clone());
+                }
+            }
+        }
+        found
+            .into_iter()
+            .map(|((asset, kind), mods)| AssetOverride { asset, kind, mods })
+            .collect()
+    }
+}
+
+/// Runs the `on_unload(world)` a mod's scripts defined, if any.
+fn call_unload_hook(world: &mut World, lua: &Lua, env: &Table) -> Result<()> {
+    let hook: Option<Function> = env.raw_get("on_unload").ok().flatten();
+    let Some(hook) = hook else {
+        return Ok(());
+    };
+    lua.scope(|scope| {
+        let world = scope.create_userdata_ref_mut(&mut *world)?;
+        hook.call::<()>(world)
+    })
+}
+
+/// Every `.ron` prefab directly in `dir`, named by file stem after
+/// `prefix`.
+fn read_prefabs(
+    dir: &Path,
+    prefix: &str,
+) -> std::result::Result<BTreeMap<String, SceneEntity>, ModError> {
+    read_dir_sorted(dir, "ron")?
+        .into_iter()
+        .map(|path| {
+            let data = |error: DataError| ModError::Data {
+                path: path.clone(),
+                error: Box::new(error),
+            };
+            let source = fs::read_to_string(&path).map_err(|e| data(e.into()))?;
+            let prefab: SceneEntity = from_ron(&source).map_err(data)?;
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            Ok((format!("{}{}", prefix, stem), prefab))
+        })
+        .collect()
+}
+
+/// Paths of files below `dir`, `/`-separated and relative to it.
+fn files_under(
+    dir: &Path,
+    prefix: &str,
+    found: &mut BTreeSet<String>,
+) -> std::result::Result<(), ModError> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let data = |error: std::io::Error| ModError::Data {
+        path: dir.to_path_buf(),
+        error: Box::new(error.into()),
+    };
+    for entry in fs::read_dir(dir).map_err(data)? {
+        let path = entry.map_err(data)?.path();
+        let name = format!(
+            "{}{}",
+            prefix,
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        if path.is_dir() {
+            files_under(&path, &format!("{}/", name), found)?;
+        } else {
+            found.insert(name);
+        }
+    }
+    Ok(())
+}
+
+/// Files in `dir` with the extension, sorted; none if `dir` is missing.
+fn read_dir_sorted(dir: &Path, extension: &str) -> std::result::Result<Vec<PathBuf>, ModError> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let data = |error: std::io::Error| ModError::Data {
+        path: dir.to_path_buf(),
+        error: Box::new(error.into()),
+    };
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).map_err(data)? {
+        let path = entry.map_err(data)?.path();
+        if path.extension().is_some_and(|ext| ext == extension) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::ScriptValue;
+
+    fn write(path: PathBuf, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_discover_order_and_toggle() -> mlua::Result<()> {
+        let root = std::env::temp_dir().join(format!("mods_{}", std::process::id()));
+        write(
+            root.join("core/mod.ron"),
+            "(capabilities: [spawn_entities])",
+        );
+        write(
+            root.join("core/scripts/init.lua"),
+            "local world = ... loaded = (loaded or 0) + 1 world:spawn({ Core = true })",
+        );
+        write(root.join("core/prefabs/crate.ron"), "{ \"Loot\": 3.0 }");
+        write(root.join("core/assets/crate.png"), "png");
+        write(
+            root.join("extras/mod.ron"),
+            "(name: \"extras\", dependencies: [\"core\"])",
+        );
+        write(
+            root.join("extras/scripts/main.lua"),
+            "function on_unload(world) unloaded = true end",
+        );
+        write(
+            root.join("aaa/mod.ron"),
+            "(load_after: [\"extras\", \"missing\"])",
+        );
+
+        let lua = Lua::new();
+        let mut world = World::new();
+        let mut mods = ModManager::new();
+        assert_eq!(mods.discover(&root)?, 3);
+        mods.apply(&mut world, &lua)?;
+        assert_eq!(mods.load_order(), ["core", "extras", "aaa"]);
+        assert_eq!(mods.prefab_names(), ["core:crate"]);
+        let spawned = mods.spawn_prefab(&mut world, &lua, "core:crate")?;
+        assert_eq!(
+            world.script_component(spawned, "Loot"),
+            Some(&ScriptValue::Number(3.0))
+        );
+        assert_eq!(
+            mods.asset_path("core:crate.png"),
+            Some(root.join("core/assets/crate.png"))
+        );
+        assert!(mods.asset_path("extras:crate.png").is_none());
+        assert!(mods.asset_path("core:../mod.ron").is_none());
+        // Mod scripts run in their own environments.
+        assert!(lua.globals().get::<Option<f64>>("loaded")?.is_none());
+
+        mods.set_enabled("core", false)?;
+        assert!(matches!(
+            mods.apply(&mut world, &lua),
+            Err(ModError::MissingDependency { .. })
+        ));
+        mods.set_enabled("extras", false)?;
+        mods.apply(&mut world, &lua)?;
+        assert_eq!(mods.load_order(), ["aaa"]);
+        assert!(!mods.is_loaded("extras") && mods.prefab("core:crate").is_none());
+
+        mods.set_enabled("core", true)?;
+        mods.apply(&mut world, &lua)?;
+        assert_eq!(mods.load_order(), ["aaa", "core"]);
+        assert_eq!(world.len(), 3);
+        fs::remove_dir_all(&root).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_load_unloads() -> mlua::Result<()> {
+        let root = std::env::temp_dir().join(format!("mods_broken_{}", std::process::id()));
+        write(
+            root.join("broken/mod.ron"),
+            "(capabilities: [spawn_entities])",
+        );
+        write(
+            root.join("broken/scripts/a.lua"),
+            "local world = ... local e = world:spawn({}) \
+             function on_unload(world) world:despawn(e) end",
+        );
+        write(root.join("broken/scripts/b.lua"), "error('boom')");
+
+        let lua = Lua::new();
+        let mut world = World::new();
+        let mut mods = ModManager::new();
+        mods.discover(&root)?;
+        assert!(matches!(
+            mods.apply(&mut world, &lua),
+            Err(ModError::Script { .. })
+        ));
+        assert!(!mods.is_loaded("broken"));
+        assert_eq!(world.len(), 0);
+
+        let mut bad = mods.get("broken").unwrap().clone();
+        bad.manifest.name = "a:b".to_string();
+        assert!(matches!(mods.add(bad), Err(ModError::InvalidName(_))));
+        fs::remove_dir_all(&root).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_unload_still_unloads_the_rest() -> mlua::Result<()> {
+        let root = std::env::temp_dir().join(format!("mods_unload_{}", std::process::id()));
+        for name in ["alpha", "beta"] {
+            write(
+                root.join(name).join("mod.ron"),
+                "(capabilities: [spawn_entities])",
+            );
+        }
+        write(
+            root.join("alpha/scripts/init.lua"),
+            "local world = ... local e = world:spawn({}) \
+             function on_unload(world) world:despawn(e) end",
+        );
+        write(
+            root.join("beta/scripts/init.lua"),
+            "local world = ... world:spawn({}) function on_unload() error('stuck') end",
+        );
+
+        let lua = Lua::new();
+        let mut world = World::new();
+        let mut mods = ModManager::new();
+        mods.discover(&root)?;
+        mods.apply(&mut world, &lua)?;
+        assert_eq!(mods.load_order(), ["alpha", "beta"]);
+
+        mods.set_enabled("alpha", false)?;
+        assert!(matches!(
+            mods.apply(&mut world, &lua),
+            Err(ModError::Script { .. })
+        ));
+        assert!(!mods.is_loaded("alpha") && !mods.is_loaded("beta"));
+        assert_eq!(world.len(), 1);
+
+        mods.apply(&mut world, &lua)?;
+        assert_eq!(mods.load_order(), ["beta"]);
+        assert_eq!(world.len(), 2);
+        fs::remove_dir_all(&root).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_overrides_and_patches() -> mlua::Result<()> {
+        let root = std::env::temp_dir().join(format!("mod_overrides_{}", std::process::id()));
+        write(root.join("base/textures/orc.png"), "base");
+        write(root.join("base/music.ogg"), "base");
+        write(root.join("mods/hd/mod.ron"), "()");
+        write(root.join("mods/hd/overrides/textures/orc.png"), "hd");
+        write(
+            root.join("mods/hd/patches/orc.ron"),
+            "{ \"Stats\": { \"hp\": 20.0 }, \"Glow\": true }",
+        );
+        write(root.join("mods/zz/mod.ron"), "(load_after: [\"hd\"])");
+        write(root.join("mods/zz/overrides/textures/orc.png"), "zz");
+        write(
+            root.join("mods/zz/patches/orc.ron"),
+            "{ \"Stats\": { \"speed\": 2.0 } }",
+        );
+        write(root.join("mods/zz/patches/hd/missing.ron"), "{}");
+
+        let mut mods = ModManager::new();
+        mods.set_base_assets(root.join("base"));
+        let orc = ScriptValue::Map(BTreeMap::from([
+            ("hp".to_string(), ScriptValue::Number(10.0)),
+            ("speed".to_string(), ScriptValue::Number(1.0)),
+        ]));
+        mods.set_base_prefabs(BTreeMap::from([(
+            "orc".to_string(),
+            SceneEntity::from([("Stats".to_string(), orc)]),
+        )]));
+        mods.discover(root.join("mods"))?;
+        mods.apply(&mut World::new(), &Lua::new())?;
+
+        let read = |name: &str| fs::read_to_string(mods.asset_path(name).unwrap()).unwrap();
+        assert_eq!(read("textures/orc.png"), "zz");
+        assert_eq!(read("music.ogg"), "base");
+        assert!(mods.asset_path("../mods/hd/mod.ron").is_none());
+        assert!(mods.asset_path("/etc/hostname").is_none());
+        let orc = mods.prefab("orc").unwrap();
+        assert_eq!(orc["Glow"], ScriptValue::Bool(true));
+        let ScriptValue::Map(stats) = &orc["Stats"] else {
+            panic!("stats should stay a map");
+        };
+        assert_eq!(
+            (&stats["hp"], &stats["speed"]),
+            (&ScriptValue::Number(20.0), &ScriptValue::Number(2.0))
+        );
+
+        let report = mods.overrides();
+        assert_eq!(report.len(), 2);
+        assert_eq!(
+            (report[0].asset.as_str(), report[0].kind, report[0].winner()),
+            ("orc", OverrideKind::Prefab, "zz")
+        );
+        assert_eq!(report[1].mods, ["hd", "zz"]);
+
+        let manifest = mods.content_manifest().unwrap();
+        assert!(manifest.get("music.ogg").is_some());
+        assert!(manifest.get("zz:overrides/textures/orc.png").is_some());
+        assert_eq!(manifest.len(), 9);
+        fs::remove_dir_all(&root).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+use crate::data::{DataError, load_ron};
+use crate::ecs::World;
+use crate::math::Vec2;
+use crate::physics::{Collider, Position};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::path::Path;
+
+/// A walkability grid baked from static colliders. Cells are blocked when
+/// an agent of `agent_radius` standing at the cell center would overlap a
+/// collider.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NavGrid {
+    pub origin: Vec2,
+    pub cell_size: f64,
+    pub width: usize,
+    pub height: usize,
+    pub agent_radius: f64,
+    blocked: Vec<bool>,
+}
+
+impl NavGrid {
+    pub fn new(
+        origin: Vec2,
+        cell_size: f64,
+        width: usize,
+        height: usize,
+        agent_radius: f64,
+    ) -> Self {
+        NavGrid {
+            origin,
+            cell_size,
+            width,
+            height,
+            agent_radius,
+            blocked: vec![false; width * height],
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DataError> {
+        let grid: NavGrid = load_ron(path)?;
+        if grid.blocked.len() != grid.width * grid.height {
+            return Err(DataError::Invalid(format!(
+                "nav grid has {} cells, expected {}x{}",
+                grid.blocked.len(),
+                grid.width,
+                grid.height
+            )));
+        }
+        Ok(grid)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), DataError> {
+        let text = ron::to_string(self).map_err(|e| DataError::Invalid(e.to_string()))?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    pub fn cell(&self, point: Vec2) -> Option<(usize, usize)> {
+        let x = ((point.x - self.origin.x) / self.cell_size).floor();
+        let y = ((point.y - self.origin.y) / self.cell_size).floor();
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    pub fn center(&self, (x, y): (usize, usize)) -> Vec2 {
+        Vec2::new(
+            self.origin.x + (x as f64 + 0.5) * self.cell_size,
+            self.origin.y + (y as f64 + 0.5) * self.cell_size,
+        )
+    }
+
+    pub fn is_walkable(&self, (x, y): (usize, usize)) -> bool {
+        x < self.width && y < self.height && !self.blocked[y * self.width + x]
+    }
+
+    pub fn set_blocked(&mut self, (x, y): (usize, usize), blocked: bool) {
+        if x < self.width && y < self.height {
+            self.blocked[y * self.width + x] = blocked;
+        }
+    }
+
+    /// Rasterizes every static collider in `world` into the whole grid.
+    pub fn bake(&mut self, world: &World) {
+        self.rebake(
+            world,
+            self.origin,
+            self.origin + Vec2::new(self.width as f64, self.height as f64) * self.cell_size,
+        );
+    }
+
+    /// Re-rasterizes only the cells between `min` and `max`, e.g. after a
+    /// wall in that area was destroyed.
+    pub fn rebake(&mut self, world: &World, min: Vec2, max: Vec2) {
+        let clamp = |point: Vec2| {
+            let x = ((point.x - self.origin.x) / self.cell_size).floor();
+            let y = ((point.y - self.origin.y) / self.cell_size).floor();
+            (
+                x.clamp(0.0, self.width as f64 - 1.0) as usize,
+                y.clamp(0.0, self.height as f64 - 1.0) as usize,
+            )
+        };
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+        let (x0, y0) = clamp(min);
+        let (x1, y1) = clamp(max);
+
+        // Whole cells are rebaked, so colliders are gathered for their
+        // bounds rather than the area asked for.
+        let min = self.origin + Vec2::new(x0 as f64, y0 as f64) * self.cell_size;
+        let max = self.origin + Vec2::new(x1 as f64 + 1.0, y1 as f64 + 1.0) * self.cell_size;
+        let inflate = Vec2::new(self.agent_radius, self.agent_radius);
+        let mut colliders = Vec::new();
+        world
+            .query::<(&Position, &Collider)>()
+            .for_each(|_, (position, collider)| {
+                let reach = collider.shape.half_extents() + inflate;
+                let lo = position.0 - reach;
+                let hi = position.0 + reach;
+                let overlaps = lo.x <= max.x && hi.x >= min.x && lo.y <= max.y && hi.y >= min.y;
+                if collider.is_static && overlaps {
+                    colliders.push((position.0, collider.shape));
+                }
+            });
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let center = self.center((x, y));
+                let blocked = colliders
+                    .iter()
+                    .any(|(at, shape)| shape.distance(*at, center) < self.agent_radius);
+                self.set_blocked((x, y), blocked);
+            }
+        }
+    }
+
+    /// A* over the grid with diagonal moves that never cut a blocked
+    /// corner. Returns the cell centers from start to goal.
+    pub fn find_path(&self, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+        let start = self.cell(from).filter(|&c| self.is_walkable(c))?;
+        let goal = self.cell(to).filter(|&c| self.is_walkable(c))?;
+
+        let heuristic = |(x, y): (usize, usize)| {
+            let dx = x.abs_diff(goal.0) as f64;
+            let dy = y.abs_diff(goal.1) as f64;
+            dx.max(dy) + (std::f64::consts::SQRT_2 - 1.0) * dx.min(dy)
+        };
+        // Costs are kept in fixed point so they can be ordered in the heap.
+        let key = |cost: f64| (cost * 1000.0).round() as u64;
+
+        let mut open = BinaryHeap::from([Reverse((key(heuristic(start)), start))]);
+        let mut best: HashMap<(usize, usize), f64> = HashMap::from([(start, 0.0)]);
+        let mut came_from = HashMap::new();
+        while let Some(Reverse((_, cell))) = open.pop() {
+            if cell == goal {
+                let mut path = vec![self.center(cell)];
+                let mut at = cell;
+                while let Some(&prev) = came_from.get(&at) {
+                    path.push(self.center(prev));
+                    at = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let cost = best[&cell];
+            for (dx, dy) in [
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ] {
+                let (Some(nx), Some(ny)) =
+                    (cell.0.checked_add_signed(dx), cell.1.checked_add_signed(dy))
+                else {
+                    continue;
+                };
+                let next = (nx, ny);
+                if !self.is_walkable(next)
+                    || (dx != 0 && dy != 0)
+                        && !(self.is_walkable((nx, cell.1)) && self.is_walkable((cell.0, ny)))
+                {
+                    continue;
+                }
+                let step = if dx != 0 && dy != 0 {
+                    std::f64::consts::SQRT_2
+                } else {
+                    1.0
+                };
+                let next_cost = cost + step;
+                if best.get(&next).is_none_or(|&old| next_cost < old) {
+                    best.insert(next, next_cost);
+                    came_from.insert(next, cell);
+                    open.push(Reverse((key(next_cost + heuristic(next)), next)));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::Shape;
+
+    #[test]
+    fn test_bake_inflates_and_rebakes() {
+        let mut world = World::new();
+        let wall = world.spawn();
+        world.insert(wall, Position(Vec2::new(5.0, 3.0))).unwrap();
+        world
+            .insert(
+                wall,
+                Collider {
+                    is_static: true,
+                    ..Collider::new(Shape::Box {
+                        half_width: 0.5,
+                        half_height: 3.0,
+                    })
+                },
+            )
+            .unwrap();
+
+        let mut grid = NavGrid::new(Vec2::ZERO, 1.0, 10, 8, 0.6);
+        grid.bake(&world);
+        // Cells whose centers come within the agent radius of the wall are blocked.
+        assert!(!grid.is_walkable((5, 2)));
+        assert!(!grid.is_walkable((4, 2)));
+        assert!(grid.is_walkable((3, 2)));
+
+        let path = grid
+            .find_path(Vec2::new(1.5, 1.5), Vec2::new(8.5, 1.5))
+            .unwrap();
+        assert!(path.iter().all(|p| p.y > 6.0 || p.x < 4.0 || p.x > 6.0));
+
+        // The wall reaches the cell's center but not the corner rebaked.
+        assert!(!grid.is_walkable((5, 6)));
+        grid.rebake(&world, Vec2::new(5.2, 6.8), Vec2::new(5.3, 6.9));
+        assert!(!grid.is_walkable((5, 6)));
+
+        world.despawn(wall);
+        grid.rebake(&world, Vec2::new(3.0, 0.0), Vec2::new(7.0, 8.0));
+        assert_eq!(
+            grid.find_path(Vec2::new(1.5, 1.5), Vec2::new(8.5, 1.5))
+                .unwrap()
+                .len(),
+            8
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use super::ClientId;
+use crate::ecs::{Entity, World};
+use serde::{Deserialize, Serialize};
+
+/// Who may change an entity. Entities without the component belong to the
+/// server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Authority {
+    #[default]
+    Server,
+    /// Simulated by its owner, typically the player's own character; the
+    /// server and other clients apply what the owner sends.
+    Client(ClientId),
+    /// Anyone may change it; the server settles conflicts.
+    Shared,
+}
+
+/// Which end of the connection a world runs, and the address transports
+/// send to. A world without one is offline and has authority over
+/// everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum NetRole {
+    Server,
+    Client(ClientId),
+}
+
+/// Registers `Authority` under that name.
+pub fn register_components(world: &mut World) {
+    world.register_component::<Authority>("Authority");
+}
+
+pub fn authority(world: &World, entity: Entity) -> Authority {
+    world
+        .get::<Authority>(entity)
+        .map(|a| *a)
+        .unwrap_or_default()
+}
+
+/// Whether `role` may change `entity`.
+pub fn role_has_authority(world: &World, role: NetRole, entity: Entity) -> bool {
+    match (role, authority(world, entity)) {
+        (_, Authority::Shared) => true,
+        (NetRole::Server, Authority::Server) => true,
+        (NetRole::Client(client), Authority::Client(owner)) => client == owner,
+        _ => false,
+    }
+}
+
+/// Whether this world may change `entity`, so systems only simulate what
+/// is theirs and the prediction layer only predicts owned entities.
+pub fn has_authority(world: &World, entity: Entity) -> bool {
+    match world.resource::<NetRole>() {
+        Some(&role) => role_has_authority(world, role, entity),
+        None => true,
+    }
+}
+
+/// Whether the replication layer should apply a change to `entity` that
+/// arrived from `sender`. Servers take changes only from entities'
+/// authorities; clients take the server's word for everything they don't
+/// own themselves.
+pub fn accepts_update(world: &World, sender: NetRole, entity: Entity) -> bool {
+    match (world.resource::<NetRole>(), sender) {
+        (Some(&NetRole::Client(me)), NetRole::Server) => {
+            authority(world, entity) != Authority::Client(me)
+        }
+        _ => role_has_authority(world, sender, entity),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mlua::Lua;
+
+    #[test]
+    fn test_authority_by_role() -> mlua::Result<()> {
+        let mut world = World::new();
+        register_components(&mut world);
+        let (me, other) = (ClientId(2), ClientId(3));
+        let rock = world.spawn();
+        let hero = world.spawn();
+        world.insert(hero, Authority::Client(me))?;
+        let door = world.spawn();
+        world.insert(door, Authority::Shared)?;
+        assert!(has_authority(&world, rock));
+
+        world.insert_resource(NetRole::Client(me));
+        let lua = Lua::new();
+        let owned: (bool, bool, bool, String, u32) = lua.scope(|scope| {
+            let world = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world, rock, hero, door = ...
+                local role, id = world:net_role()
+                return world:has_authority(rock), world:has_authority(hero),
+                    world:has_authority(door), role, id
+            "#,
+            )
+            .call((world, rock, hero, door))
+        })?;
+        assert_eq!(owned, (false, true, true, "client".to_string(), 2));
+        assert!(accepts_update(&world, NetRole::Server, rock));
+        assert!(!accepts_update(&world, NetRole::Server, hero));
+
+        world.insert_resource(NetRole::Server);
+        assert!(has_authority(&world, rock) && !has_authority(&world, hero));
+        assert!(accepts_update(&world, NetRole::Client(me), hero));
+        assert!(!accepts_update(&world, NetRole::Client(other), hero));
+        assert!(!accepts_update(&world, NetRole::Client(me), rock));
+        assert!(accepts_update(&world, NetRole::Client(other), door));
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+use crate::sandbox::SandboxConfig;
+use crate::tasks::{AsyncJob, AsyncValue, LuaAsync};
+use mlua::{Error, IntoLua, Lua, Result, Table};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// The host part of an http(s) URL.
+pub fn host(url: &str) -> Option<&str> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next()?,
+        None => host.split(':').next()?,
+    };
+    (!host.is_empty()).then_some(host)
+}
+
+struct Response {
+    status: u16,
+    body: String,
+    headers: BTreeMap<String, String>,
+}
+
+fn request(
+    agent: &ureq::Agent,
+    method: &str,
+    url: &str,
+    headers: &BTreeMap<String, String>,
+    body: Option<String>,
+) -> std::result::Result<Response, String> {
+    let fail = |e: ureq::Error| format!("{} {} failed: {}", method, url, e);
+    let mut response = match body {
+        None => {
+            let mut request = agent.get(url);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            request.call()
+        }
+        Some(body) => {
+            let mut request = agent.post(url);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            request.send(body)
+        }
+    }
+    .map_err(fail)?;
+    Ok(Response {
+        status: response.status().as_u16(),
+        headers: response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect(),
+        body: response.body_mut().read_to_string().map_err(fail)?,
+    })
+}
+
+/// Adds `net.http.get(url, headers?)` and `net.http.post(url, body,
+/// headers?)`. Both return futures resolving to `{ status, body, headers }`;
+/// error statuses still resolve, transport failures raise from `await`.
+/// URLs whose host isn't in the sandbox allowlist are refused up front.
+/// Redirects aren't followed, so they resolve like any other status.
+pub fn register_lua(lua: &Lua, bridge: &LuaAsync, sandbox: &SandboxConfig) -> Result<()> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .max_redirects(0)
+        .build()
+        .into();
+    let sandbox = Arc::new(sandbox.clone());
+
+    let start = move |method: &'static str| {
+        let agent = agent.clone();
+        let sandbox = sandbox.clone();
+        move |url: String, headers: Option<BTreeMap<String, String>>, body: Option<String>| {
+            match host(&url) {
+                Some(host) if sandbox.allows_host(host) => {}
+                Some(host) => {
+                    return Err(Error::runtime(format!(
+                        "'{}' is not in the sandbox http allowlist",
+                        host
+                    )));
+                }
+                None => return Err(Error::runtime(format!("invalid http url '{}'", url))),
+            }
+            let agent = agent.clone();
+            Ok(Box::new(move || {
+                let response = request(&agent, method, &url, &headers.unwrap_or_default(), body)?;
+                Ok(Box::new(move |lua: &Lua| {
+                    let table = lua.create_table()?;
+                    table.set("status", response.status)?;
+                    table.set("body", response.body)?;
+                    table.set("headers", response.headers)?;
+                    table.into_lua(lua)
+                }) as AsyncValue)
+            }) as AsyncJob)
+        }
+    };
+
+    let http = lua.create_table()?;
+    let get = start("GET");
+    http.set(
+        "get",
+        bridge.create_function(lua, move |_, (url, headers)| get(url, headers, None))?,
+    )?;
+    let post = start("POST");
+    http.set(
+        "post",
+        bridge.create_function(lua, move |_, (url, body, headers): (String, String, _)| {
+            post(url, headers, Some(body))
+        })?,
+    )?;
+
+    let net = match lua.globals().get::<Option<Table>>("net")? {
+        Some(net) => net,
+        None => {
+            let net = lua.create_table()?;
+            lua.globals().set("net", net.clone())?;
+            net
+        }
+    };
+    net.set("http", http)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::World;
+    use crate::tasks::TaskPool;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_get_through_allowlist() -> Result<()> {
+        assert_eq!(
+            host("https://user@api.example.com:443/scores?top=10"),
+            Some("api.example.com")
+        );
+        assert_eq!(host("ftp://example.com"), None);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    if read == 0 {
+                        break;
+                    }
+                }
+                let response: &[u8] = if request.starts_with(b"GET /moved") {
+                    b"HTTP/1.1 302 Found\r\nLocation: http://evil.example.com/\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                } else {
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\nConnection: close\r\n\r\n[1,2,3] "
+                };
+                stream.write_all(response).unwrap();
+            }
+        });
+
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.insert_resource(TaskPool::new(1));
+        let bridge = LuaAsync::new();
+        bridge.register_lua(&lua)?;
+        let sandbox = SandboxConfig {
+            http_allowlist: vec!["127.0.0.1".to_string()],
+        };
+        register_lua(&lua, &bridge, &sandbox)?;
+
+        assert!(
+            lua.load(r#"net.http.get("https://evil.example.com/")"#)
+                .exec()
+                .is_err()
+        );
+        lua.load(format!(
+            r#"
+            async(function()
+                local response = await(net.http.get("http://127.0.0.1:{}/scores"))
+                status, body = response.status, response.body
+                moved = await(net.http.get("http://127.0.0.1:{}/moved")).status
+            end)
+        "#,
+            port, port
+        ))
+        .exec()?;
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while bridge.waiting() > 0 {
+            bridge.update(&mut world, &lua)?;
+            assert!(Instant::now() < deadline, "request never completed");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        server.join().unwrap();
+        assert_eq!(lua.globals().get::<u16>("status")?, 200);
+        assert_eq!(lua.globals().get::<String>("body")?, "[1,2,3] ");
+        assert_eq!(lua.globals().get::<u16>("moved")?, 302);
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+use super::ClientId;
+use crate::ecs::{Entity, World};
+use crate::math::Vec2;
+use crate::physics::Position;
+use serde::{Deserialize, Serialize};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Relevant to every client wherever it is, e.g. match state or teams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct AlwaysRelevant;
+
+/// The zone an entity is in, for `InterestMode::Zone`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterestZone(pub String);
+
+/// Registers `AlwaysRelevant` and `InterestZone` under those names.
+pub fn register_components(world: &mut World) {
+    world.register_component::<AlwaysRelevant>("AlwaysRelevant");
+    world.register_component::<InterestZone>("InterestZone");
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterestMode {
+    /// Entities come into view within `enter` of the client's viewer and
+    /// leave it only past `exit`, so one hovering at the edge isn't sent
+    /// and withdrawn every other frame.
+    Distance { enter: f64, exit: f64 },
+    /// Entities whose `InterestZone` is one the client watches.
+    Zone,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ClientInterest {
+    /// Seen from its position, and always relevant to its client.
+    viewer: Option<Entity>,
+    zones: BTreeSet<String>,
+    /// Each relevant entity with the updates it has gone unqualified.
+    relevant: BTreeMap<Entity, u32>,
+}
+
+/// What changed for one client in an `update`, in entity order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterestDelta {
+    pub client: ClientId,
+    pub entered: Vec<Entity>,
+    pub left: Vec<Entity>,
+}
+
+/// Decides which entities each client is sent. The replication layer calls
+/// `update` once per network tick, spawns `entered` entities on the client
+/// and despawns `left` ones, and only sends changes for `relevant` ones.
+#[derive(Debug, Clone)]
+pub struct InterestManager {
+    mode: InterestMode,
+    linger: u32,
+    clients: BTreeMap<ClientId, ClientInterest>,
+}
+
+impl InterestManager {
+    pub fn new(mode: InterestMode) -> Self {
+        InterestManager {
+            mode,
+            linger: 0,
+            clients: BTreeMap::new(),
+        }
+    }
+
+    /// How many updates an entity stays relevant after it stops
+    /// qualifying, 0 by default. Smooths zone borders the way the exit
+    /// radius does distances.
+    pub fn set_linger(&mut self, updates: u32) {
+        self.linger = updates;
+    }
+
+    pub fn add_client(&mut self, client: ClientId, viewer: Option<Entity>) {
+        self.clients.entry(client).or_default().viewer = viewer;
+    }
+
+    pub fn remove_client(&mut self, client: ClientId) -> bool {
+        self.clients.remove(&client).is_some()
+    }
+
+    pub fn set_viewer(&mut self, client: ClientId, viewer: Option<Entity>) {
+        if let Some(interest) = self.clients.get_mut(&client) {
+            interest.viewer = viewer;
+        }
+    }
+
+    pub fn watch_zone(&mut self, client: ClientId, zone: &str) {
+        if let Some(interest) = self.clients.get_mut(&client) {
+            interest.zones.insert(zone.to_string());
+        }
+    }
+
+    pub fn unwatch_zone(&mut self, client: ClientId, zone: &str) {
+        if let Some(interest) = self.clients.get_mut(&client) {
+            interest.zones.remove(zone);
+        }
+    }
+
+    pub fn clients(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.clients.keys().copied()
+    }
+
+    pub fn relevant(&self, client: ClientId) -> impl Iterator<Item = Entity> + '_ {
+        self.clients
+            .get(&client)
+            .into_iter()
+            .flat_map(|interest| interest.relevant.keys().copied())
+    }
+
+    pub fn is_relevant(&self, client: ClientId, entity: Entity) -> bool {
+        self.clients
+            .get(&client)
+            .is_some_and(|interest| interest.relevant.contains_key(&entity))
+    }
+
+    /// Recomputes every client's relevant set, returning a delta for each
+    /// client whose set changed. Despawned entities always leave.
+    pub fn update(&mut self, world: &World) -> Vec<InterestDelta> {
+        let always = world.query::<&AlwaysRelevant>().entities();
+        let mut positions = Vec::new();
+        let mut zones = Vec::new();
+        match self.mode {
+            InterestMode::Distance { .. } => world
+                .query::<&Position>()
+                .for_each(|entity, position| positions.push((entity, position.0))),
+            InterestMode::Zone => world
+                .query::<&InterestZone>()
+                .for_each(|entity, zone| zones.push((entity, zone.0.clone()))),
+        }
+
+        let mut deltas = Vec::new();
+        for (&client, interest) in &mut self.clients {
+            let origin: Option<Vec2> = interest
+                .viewer
+                .and_then(|viewer| world.get::<Position>(viewer).map(|p| p.0));
+            let mut qualifying: BTreeSet<Entity> = always.iter().copied().collect();
+            qualifying.extend(interest.viewer.filter(|&viewer| world.is_alive(viewer)));
+            match (&self.mode, origin) {
+                (InterestMode::Distance { enter, exit }, Some(origin)) => {
+                    for &(entity, position) in &positions {
+                        let radius = match interest.relevant.contains_key(&entity) {
+                            true => exit.max(*enter),
+                            false => *enter,
+                        };
+                        if (position - origin).length() <= radius {
+                            qualifying.insert(entity);
+                        }
+                    }
+                }
+                (InterestMode::Distance { .. }, None) => {}
+                (InterestMode::Zone, _) => {
+                    for (entity, zone) in &zones {
+                        if interest.zones.contains(zone) {
+                            qualifying.insert(*entity);
+                        }
+                    }
+                }
+            }
+
+            let mut delta = InterestDelta {
+                client,
+                entered: Vec::new(),
+                left: Vec::new(),
+            };
+            let linger = self.linger;
+            interest.relevant.retain(|&entity, missed| {
+                if !world.is_alive(entity) {
+                    delta.left.push(entity);
+                    return false;
+                }
+                if qualifying.contains(&entity) {
+                    *missed = 0;
+                    return true;
+                }
+                *missed += 1;
+                let keep = *missed <= linger;
+                if !keep {
+                    delta.left.push(entity);
+                }
+                keep
+            });
+            for entity in qualifying {
+                if let Entry::Vacant(slot) = interest.relevant.entry(entity) {
+                    slot.insert(0);
+                    delta.entered.push(entity);
+                }
+            }
+            if !delta.entered.is_empty() || !delta.left.is_empty() {
+                deltas.push(delta);
+            }
+        }
+        deltas
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_hysteresis_and_zones() {
+        let mut world = World::new();
+        let viewer = world.spawn();
+        world.insert(viewer, Position(Vec2::new(0.0, 0.0))).unwrap();
+        let walker = world.spawn();
+        world
+            .insert(walker, Position(Vec2::new(15.0, 0.0)))
+            .unwrap();
+        let flag = world.spawn();
+        world.insert(flag, AlwaysRelevant).unwrap();
+
+        let client = ClientId(1);
+        let mut interest = InterestManager::new(InterestMode::Distance {
+            enter: 10.0,
+            exit: 20.0,
+        });
+        interest.add_client(client, Some(viewer));
+        let changes = |deltas: Vec<InterestDelta>| -> (Vec<Entity>, Vec<Entity>) {
+            deltas
+                .into_iter()
+                .map(|delta| (delta.entered, delta.left))
+                .next()
+                .unwrap_or_default()
+        };
+        assert_eq!(
+            changes(interest.update(&world)),
+            (vec![viewer, flag], vec![])
+        );
+
+        // Crossing the enter radius brings it in; drifting back out past it,
+        // but not past the exit radius, keeps it.
+        for (x, expected) in [(9.0, (vec![walker], vec![])), (15.0, (vec![], vec![]))] {
+            world.get_mut::<Position>(walker).unwrap().0 = Vec2::new(x, 0.0);
+            assert_eq!(changes(interest.update(&world)), expected);
+        }
+        world.get_mut::<Position>(walker).unwrap().0 = Vec2::new(25.0, 0.0);
+        assert_eq!(changes(interest.update(&world)), (vec![], vec![walker]));
+        assert!(!interest.is_relevant(client, walker));
+
+        world.despawn(flag);
+        assert_eq!(changes(interest.update(&world)), (vec![], vec![flag]));
+        assert!(interest.update(&world).is_empty());
+
+        let mut zoned = InterestManager::new(InterestMode::Zone);
+        zoned.set_linger(1);
+        zoned.add_client(client, None);
+        zoned.watch_zone(client, "harbor");
+        world
+            .insert(walker, InterestZone("harbor".to_string()))
+            .unwrap();
+        assert_eq!(changes(zoned.update(&world)), (vec![walker], vec![]));
+        world
+            .insert(walker, InterestZone("market".to_string()))
+            .unwrap();
+        assert!(zoned.update(&world).is_empty());
+        assert_eq!(changes(zoned.update(&world)), (vec![], vec![walker]));
+    }
+}
@@ -0,0 +1,607 @@
+//! Matchmaking lobbies behind the `LobbyBackend` trait, so game code can be
+//! written against `MemoryLobbies` before a real service exists. A lobby
+//! starts once every player is ready and the host calls `start`; each
+//! player then loads in and calls `confirm_start`, and the game begins
+//! with `LobbyEvent::Started` once all of them have.
+
+use super::ClientId;
+use crate::ecs::{ScriptValue, World};
+use mlua::{Lua, LuaSerdeExt, Result, UserData, UserDataMethods};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::rc::Rc;
+
+/// Script event every `LobbyEvent` is also sent under, as a table with a
+/// `kind` field.
+pub const LOBBY_EVENT: &str = "lobby";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct LobbyId(pub u64);
+
+impl fmt::Display for LobbyId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "lobby#{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LobbyState {
+    #[default]
+    Open,
+    /// The host has started; waiting for every player to confirm.
+    Starting,
+    InGame,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LobbyPlayer {
+    pub id: ClientId,
+    pub ready: bool,
+    pub metadata: BTreeMap<String, ScriptValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LobbyInfo {
+    pub id: LobbyId,
+    pub name: String,
+    pub host: ClientId,
+    pub max_players: usize,
+    pub state: LobbyState,
+    /// In joining order, the host first.
+    pub players: Vec<LobbyPlayer>,
+    pub metadata: BTreeMap<String, ScriptValue>,
+}
+
+impl LobbyInfo {
+    pub fn player(&self, id: ClientId) -> Option<&LobbyPlayer> {
+        self.players.iter().find(|player| player.id == id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LobbyEvent {
+    Created {
+        lobby: LobbyId,
+    },
+    Joined {
+        lobby: LobbyId,
+        player: ClientId,
+    },
+    Left {
+        lobby: LobbyId,
+        player: ClientId,
+    },
+    /// The host left and `host` took over.
+    HostChanged {
+        lobby: LobbyId,
+        host: ClientId,
+    },
+    /// A player's metadata, or the lobby's when `player` is `None`.
+    MetadataChanged {
+        lobby: LobbyId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        player: Option<ClientId>,
+        key: String,
+    },
+    ReadyChanged {
+        lobby: LobbyId,
+        player: ClientId,
+        ready: bool,
+    },
+    /// Load the game, then call `confirm_start`.
+    Starting {
+        lobby: LobbyId,
+    },
+    Started {
+        lobby: LobbyId,
+        players: Vec<ClientId>,
+    },
+    /// The last player left.
+    Closed {
+        lobby: LobbyId,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LobbyError {
+    NotFound(LobbyId),
+    Full(LobbyId),
+    NotMember(LobbyId),
+    AlreadyMember(LobbyId),
+    /// Only the host may do that.
+    NotHost(LobbyId),
+    /// The lobby isn't in the state the call needs.
+    WrongState(LobbyId, LobbyState),
+    NotReady(LobbyId, ClientId),
+}
+
+impl fmt::Display for LobbyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LobbyError::NotFound(id) => write!(f, "{} not found", id),
+            LobbyError::Full(id) => write!(f, "{} is full", id),
+            LobbyError::NotMember(id) => write!(f, "not in {}", id),
+            LobbyError::AlreadyMember(id) => write!(f, "already in {}", id),
+            LobbyError::NotHost(id) => write!(f, "only the host of {} can do that", id),
+            LobbyError::WrongState(id, state) => write!(f, "{} is {:?}", id, state),
+            LobbyError::NotReady(id, player) => write!(f, "{} in {} is not ready", player, id),
+        }
+    }
+}
+
+impl std::error::Error for LobbyError {}
+
+impl From<LobbyError> for mlua::Error {
+    fn from(e: LobbyError) -> Self {
+        mlua::Error::external(e)
+    }
+}
+
+pub type LobbyResult<T> = std::result::Result<T, LobbyError>;
+
+/// One player's connection to a matchmaking service. Calls act as
+/// `local()`; events about every lobby queue up until `poll`.
+pub trait LobbyBackend {
+    fn local(&self) -> ClientId;
+    /// Creates a lobby hosted by the local player, who joins it.
+    fn create(&mut self, name: &str, max_players: usize) -> LobbyResult<LobbyId>;
+    fn join(&mut self, lobby: LobbyId) -> LobbyResult<()>;
+    fn leave(&mut self, lobby: LobbyId) -> LobbyResult<()>;
+    /// Lobbies that are open to join.
+    fn list(&self) -> Vec<LobbyInfo>;
+    fn lobby(&self, lobby: LobbyId) -> Option<LobbyInfo>;
+    /// Sets the local player's metadata, e.g. a chosen character.
+    fn set_player_metadata(
+        &mut self,
+        lobby: LobbyId,
+        key: &str,
+        value: ScriptValue,
+    ) -> LobbyResult<()>;
+    /// Host only, e.g. the map or game mode.
+    fn set_lobby_metadata(
+        &mut self,
+        lobby: LobbyId,
+        key: &str,
+        value: ScriptValue,
+    ) -> LobbyResult<()>;
+    fn set_ready(&mut self, lobby: LobbyId, ready: bool) -> LobbyResult<()>;
+    /// Host only, once every player is ready.
+    fn start(&mut self, lobby: LobbyId) -> LobbyResult<()>;
+    fn confirm_start(&mut self, lobby: LobbyId) -> LobbyResult<()>;
+    fn poll(&mut self) -> Vec<LobbyEvent>;
+}
+
+struct MemoryLobby {
+    info: LobbyInfo,
+    confirmed: BTreeSet<ClientId>,
+}
+
+#[derive(Default)]
+struct ServiceState {
+    lobbies: BTreeMap<LobbyId, MemoryLobby>,
+    next_id: u64,
+    /// Every connection's undelivered events.
+    queues: BTreeMap<ClientId, Vec<LobbyEvent>>,
+}
+
+impl ServiceState {
+    fn broadcast(&mut self, event: LobbyEvent) {
+        for queue in self.queues.values_mut() {
+            queue.push(event.clone());
+        }
+    }
+
+    fn member(&mut self, lobby: LobbyId, player: ClientId) -> LobbyResult<&mut MemoryLobby> {
+        let entry = self
+            .lobbies
+            .get_mut(&lobby)
+            .ok_or(LobbyError::NotFound(lobby))?;
+        match entry.info.player(player) {
+            Some(_) => Ok(entry),
+            None => Err(LobbyError::NotMember(lobby)),
+        }
+    }
+
+    fn host(&mut self, lobby: LobbyId, player: ClientId) -> LobbyResult<&mut MemoryLobby> {
+        let entry = self.member(lobby, player)?;
+        match entry.info.host == player {
+            true => Ok(entry),
+            false => Err(LobbyError::NotHost(lobby)),
+        }
+    }
+
+    /// Starts the game once every remaining player has confirmed.
+    fn check_started(&mut self, lobby: LobbyId) {
+        let Some(entry) = self.lobbies.get_mut(&lobby) else {
+            return;
+        };
+        let players: Vec<ClientId> = entry.info.players.iter().map(|p| p.id).collect();
+        if entry.info.state == LobbyState::Starting
+            && players.iter().all(|p| entry.confirmed.contains(p))
+        {
+            entry.info.state = LobbyState::InGame;
+            self.broadcast(LobbyEvent::Started { lobby, players });
+        }
+    }
+}
+
+/// An in-process matchmaking service; `connect` gives each player a
+/// backend. Clones share one service.
+#[derive(Clone, Default)]
+pub struct MemoryLobbies {
+    state: Rc<RefCell<ServiceState>>,
+}
+
+impl MemoryLobbies {
+    pub fn new() -> Self {
+        MemoryLobbies::default()
+    }
+
+    pub fn connect(&self, player: ClientId) -> MemoryLobbyConnection {
+        self.state.borrow_mut().queues.entry(player).or_default();
+        MemoryLobbyConnection {
+            service: self.clone(),
+            player,
+        }
+    }
+}
+
+pub struct MemoryLobbyConnection {
+    service: MemoryLobbies,
+    player: ClientId,
+}
+
+impl MemoryLobbyConnection {
+    fn state(&self) -> std::cell::RefMut<'_, ServiceState> {
+        self.service.state.borrow_mut()
+    }
+}
+
+impl LobbyBackend for MemoryLobbyConnection {
+    fn local(&self) -> ClientId {
+        self.player
+    }
+
+    fn create(&mut self, name: &str, max_players: usize) -> LobbyResult<LobbyId> {
+        let mut state = self.state();
+        state.next_id += 1;
+        let id = LobbyId(state.next_id);
+        let info = LobbyInfo {
+            id,
+            name: name.to_string(),
+            host: self.player,
+            max_players: max_players.max(1),
+            state: LobbyState::Open,
+            players: Vec::new(),
+            metadata: BTreeMap::new(),
+        };
+        let confirmed = BTreeSet::new();
+        state.lobbies.insert(id, MemoryLobby { info, confirmed });
+        state.broadcast(LobbyEvent::Created { lobby: id });
+        drop(state);
+        self.join(id)?;
+        Ok(id)
+    }
+
+    fn join(&mut self, lobby: LobbyId) -> LobbyResult<()> {
+        let player = self.player;
+        let mut state = self.state();
+        let entry = state
+            .lobbies
+            .get_mut(&lobby)
+            .ok_or(LobbyError::NotFound(lobby))?;
+        let info = &mut entry.info;
+        if info.player(player).is_some() {
+            return Err(LobbyError::AlreadyMember(lobby));
+        }
+        if info.state != LobbyState::Open {
+            return Err(LobbyError::WrongState(lobby, info.state));
+        }
+        if info.players.len() >= info.max_players {
+            return Err(LobbyError::Full(lobby));
+        }
+        info.players.push(LobbyPlayer {
+            id: player,
+            ready: false,
+            metadata: BTreeMap::new(),
+        });
+        state.broadcast(LobbyEvent::Joined { lobby, player });
+        Ok(())
+    }
+
+    fn leave(&mut self, lobby: LobbyId) -> LobbyResult<()> {
+        let player = self.player;
+        let mut state = self.state();
+        let entry = state.member(lobby, player)?;
+        entry.info.players.retain(|p| p.id != player);
+        entry.confirmed.remove(&player);
+        let new_host = match entry.info.players.first() {
+            Some(next) if entry.info.host == player => {
+                entry.info.host = next.id;
+                Some(next.id)
+            }
+            _ => None,
+        };
+        let empty = entry.info.players.is_empty();
+        state.broadcast(LobbyEvent::Left { lobby, player });
+        if let Some(host) = new_host {
+            state.broadcast(LobbyEvent::HostChanged { lobby, host });
+        }
+        if empty {
+            state.lobbies.remove(&lobby);
+            state.broadcast(LobbyEvent::Closed { lobby });
+        } else {
+            state.check_started(lobby);
+        }
+        Ok(())
+    }
+
+    fn list(&self) -> Vec<LobbyInfo> {
+        self.service
+            .state
+            .borrow()
+            .lobbies
+            .values()
+            .filter(|entry| entry.info.state == LobbyState::Open)
+            .map(|entry| entry.info.clone())
+            .collect()
+    }
+
+    fn lobby(&self, lobby: LobbyId) -> Option<LobbyInfo> {
+        let state = self.service.state.borrow();
+        state.lobbies.get(&lobby).map(|entry| entry.info.clone())
+    }
+
+    fn set_player_metadata(
+        &mut self,
+        lobby: LobbyId,
+        key: &str,
+        value: ScriptValue,
+    ) -> LobbyResult<()> {
+        let player = self.player;
+        let mut state = self.state();
+        let entry = state.member(lobby, player)?;
+        let slot = entry.info.players.iter_mut().find(|p| p.id == player);
+        slot.expect("member")
+            .metadata
+            .insert(key.to_string(), value);
+        state.broadcast(LobbyEvent::MetadataChanged {
+            lobby,
+            player: Some(player),
+            key: key.to_string(),
+        });
+        Ok(())
+    }
+
+    fn set_lobby_metadata(
+        &mut self,
+        lobby: LobbyId,
+        key: &str,
+        value: ScriptValue,
+    ) -> LobbyResult<()> {
+        let mut state = self.state();
+        let entry = state.host(lobby, self.player)?;
+        entry.info.metadata.insert(key.to_string(), value);
+        state.broadcast(LobbyEvent::MetadataChanged {
+            lobby,
+            player: None,
+            key: key.to_string(),
+        });
+        Ok(())
+    }
+
+    fn set_ready(&mut self, lobby: LobbyId, ready: bool) -> LobbyResult<()> {
+        let player = self.player;
+        let mut state = self.state();
+        let entry = state.member(lobby, player)?;
+        if entry.info.state != LobbyState::Open {
+            return Err(LobbyError::WrongState(lobby, entry.info.state));
+        }
+        let slot = entry.info.players.iter_mut().find(|p| p.id == player);
+        slot.expect("member").ready = ready;
+        state.broadcast(LobbyEvent::ReadyChanged {
+            lobby,
+            player,
+            ready,
+        });
+        Ok(())
+    }
+
+    fn start(&mut self, lobby: LobbyId) -> LobbyResult<()> {
+        let mut state = self.state();
+        let entry = state.host(lobby, self.player)?;
+        if entry.info.state != LobbyState::Open {
+            return Err(LobbyError::WrongState(lobby, entry.info.state));
+        }
+        if let Some(waiting) = entry.info.players.iter().find(|p| !p.ready) {
+            return Err(LobbyError::NotReady(lobby, waiting.id));
+        }
+        entry.info.state = LobbyState::Starting;
+        state.broadcast(LobbyEvent::Starting { lobby });
+        Ok(())
+    }
+
+    fn confirm_start(&mut self, lobby: LobbyId) -> LobbyResult<()> {
+        let player = self.player;
+        let mut state = self.state();
+        let entry = state.member(lobby, player)?;
+        if entry.info.state != LobbyState::Starting {
+            return Err(LobbyError::WrongState(lobby, entry.info.state));
+        }
+        entry.confirmed.insert(player);
+        state.check_started(lobby);
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Vec<LobbyEvent> {
+        let player = self.player;
+        let mut state = self.state();
+        state
+            .queues
+            .get_mut(&player)
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
+
+/// A backend wrapped for games: `update` puts what happened on the event
+/// bus, as `LobbyEvent`s and `"lobby"` script events. Clones share one
+/// backend, as does the `lobby` Lua global.
+#[derive(Clone)]
+pub struct Lobby {
+    backend: Rc<RefCell<Box<dyn LobbyBackend>>>,
+}
+
+impl Lobby {
+    pub fn new(backend: impl LobbyBackend + 'static) -> Self {
+        Lobby {
+            backend: Rc::new(RefCell::new(Box::new(backend))),
+        }
+    }
+
+    pub fn backend(&self) -> std::cell::RefMut<'_, Box<dyn LobbyBackend>> {
+        self.backend.borrow_mut()
+    }
+
+    /// Call once a frame.
+    pub fn update(&self, world: &mut World) -> Result<()> {
+        let events = self.backend.borrow_mut().poll();
+        for event in events {
+            world.emit(LOBBY_EVENT, event)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the `lobby` global: `create(name, max_players)`, `join(id)`,
+    /// `leave(id)`, `list()`, `get(id)`, `set_metadata(id, key, value)`,
+    /// `set_lobby_metadata(id, key, value)`, `set_ready(id, ready)`,
+    /// `start(id)`, `confirm_start(id)` and `me()`, all as methods.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("lobby", self.clone())
+    }
+}
+
+impl UserData for Lobby {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("me", |_, this, ()| Ok(this.backend().local().0));
+        methods.add_method("create", |_, this, (name, max_players): (String, usize)| {
+            Ok(this.backend().create(&name, max_players)?.0)
+        });
+        methods.add_method("join", |_, this, id: u64| {
+            Ok(this.backend().join(LobbyId(id))?)
+        });
+        methods.add_method("leave", |_, this, id: u64| {
+            Ok(this.backend().leave(LobbyId(id))?)
+        });
+        methods.add_method("list", |lua, this, ()| lua.to_value(&this.backend().list()));
+        methods.add_method("get", |lua, this, id: u64| {
+            let info = this.backend().lobby(LobbyId(id));
+            info.map(|info| lua.to_value(&info)).transpose()
+        });
+        methods.add_method(
+            "set_metadata",
+            |_, this, (id, key, value): (u64, String, ScriptValue)| {
+                Ok(this
+                    .backend()
+                    .set_player_metadata(LobbyId(id), &key, value)?)
+            },
+        );
+        methods.add_method(
+            "set_lobby_metadata",
+            |_, this, (id, key, value): (u64, String, ScriptValue)| {
+                Ok(this
+                    .backend()
+                    .set_lobby_metadata(LobbyId(id), &key, value)?)
+            },
+        );
+        methods.add_method("set_ready", |_, this, (id, ready): (u64, bool)| {
+            Ok(this.backend().set_ready(LobbyId(id), ready)?)
+        });
+        methods.add_method("start", |_, this, id: u64| {
+            Ok(this.backend().start(LobbyId(id))?)
+        });
+        methods.add_method("confirm_start", |_, this, id: u64| {
+            Ok(this.backend().confirm_start(LobbyId(id))?)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lobby_handshake() -> Result<()> {
+        let service = MemoryLobbies::new();
+        let host = Lobby::new(service.connect(ClientId(1)));
+        let mut guest = service.connect(ClientId(2));
+
+        let lua = Lua::new();
+        host.register_lua(&lua)?;
+        let id: u64 = lua
+            .load(
+                r#"
+                local id = lobby:create("Friday night", 2)
+                lobby:set_lobby_metadata(id, "map", "harbor")
+                lobby:set_ready(id, true)
+                return id
+            "#,
+            )
+            .eval()?;
+        let id = LobbyId(id);
+        assert_eq!(
+            guest.list()[0].metadata["map"],
+            ScriptValue::String("harbor".to_string())
+        );
+
+        guest.join(id)?;
+        assert_eq!(
+            service.connect(ClientId(3)).join(id),
+            Err(LobbyError::Full(id))
+        );
+        guest.set_player_metadata(id, "hero", ScriptValue::String("knight".to_string()))?;
+        assert_eq!(
+            host.backend().start(id),
+            Err(LobbyError::NotReady(id, ClientId(2)))
+        );
+        guest.set_ready(id, true)?;
+        assert_eq!(guest.start(id), Err(LobbyError::NotHost(id)));
+        host.backend().start(id)?;
+        assert!(guest.list().is_empty());
+        host.backend().confirm_start(id)?;
+        assert!(!guest.poll().contains(&LobbyEvent::Started {
+            lobby: id,
+            players: vec![ClientId(1), ClientId(2)],
+        }));
+        guest.confirm_start(id)?;
+        assert_eq!(
+            guest.poll(),
+            [LobbyEvent::Started {
+                lobby: id,
+                players: vec![ClientId(1), ClientId(2)],
+            }]
+        );
+
+        let mut world = World::new();
+        host.update(&mut world)?;
+        let kinds: Vec<String> = lua.scope(|scope| {
+            let world = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local kinds = {}
+                for _, event in ipairs((...):poll("lobby")) do table.insert(kinds, event.kind) end
+                return kinds
+            "#,
+            )
+            .call(world)
+        })?;
+        assert_eq!(kinds.first().map(String::as_str), Some("created"));
+        assert_eq!(kinds.last().map(String::as_str), Some("started"));
+        assert_eq!(world.events::<LobbyEvent>().unwrap().len(), kinds.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+pub mod authority;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "server")]
+pub mod interest;
+pub mod lobby;
+pub mod rpc;
+pub mod sim;
+pub mod stats;
+pub mod transport;
+
+pub use authority::{Authority, NetRole, accepts_update, has_authority};
+#[cfg(feature = "server")]
+pub use interest::{AlwaysRelevant, InterestDelta, InterestManager, InterestMode, InterestZone};
+pub use lobby::{
+    LOBBY_EVENT, Lobby, LobbyBackend, LobbyError, LobbyEvent, LobbyId, LobbyInfo, LobbyPlayer,
+    LobbyState, MemoryLobbies, MemoryLobbyConnection,
+};
+pub use rpc::{Channel, Rpc, RpcTarget};
+pub use sim::{NetConditions, NetSimulator, SimulatedTransport};
+pub use stats::{ChannelStats, NetStats};
+pub use transport::{MemoryNetwork, MemoryTransport, Transport};
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A connected client, numbered by the transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ClientId(pub u32);
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "client#{}", self.0)
+    }
+}
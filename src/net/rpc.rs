@@ -0,0 +1,735 @@
+//! Remote procedure calls between the server and its clients. Every call
+//! names an RPC both ends declared with the same channel; its arguments
+//! travel as a `ScriptValue` in the binary scene encoding, so anything a
+//! registered component can hold can be passed.
+
+use super::stats::{NetStats, StatsTracker};
+use super::transport::Transport;
+use super::{ClientId, NetRole};
+use crate::ecs::{ScriptValue, World};
+use crate::scene::{Scene, SceneEntity};
+use mlua::{
+    Error, FromLua, Function, IntoLua, Lua, LuaSerdeExt, Result, UserData, UserDataMethods, Value,
+};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+use std::str::FromStr;
+
+const KIND_MESSAGE: u8 = 0;
+const KIND_ACK: u8 = 1;
+const ARGS: &str = "args";
+
+/// How far ahead of the next expected sequence a reliable call may be and
+/// still be held. Later ones go unacknowledged, so they are resent once
+/// the gap closes.
+const REORDER_WINDOW: u32 = 1024;
+
+/// Where `seq` falls relative to `next`, the lowest sequence not yet
+/// handled, with sequences wrapping around.
+enum Arrival {
+    /// Already handled; only its ack was lost.
+    Old,
+    /// Within the reorder window.
+    Ahead,
+    TooFar,
+}
+
+fn arrival(seq: u32, next: u32) -> Arrival {
+    match seq.wrapping_sub(next) {
+        ahead if ahead < REORDER_WINDOW => Arrival::Ahead,
+        ahead if ahead > u32::MAX / 2 => Arrival::Old,
+        _ => Arrival::TooFar,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Channel {
+    /// Resent until acknowledged and handled in the order sent.
+    #[default]
+    ReliableOrdered,
+    /// Resent until acknowledged, handled as soon as it arrives.
+    ReliableUnordered,
+    /// Sent once; may be lost or arrive out of order.
+    Unreliable,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 3] = [
+        Channel::ReliableOrdered,
+        Channel::ReliableUnordered,
+        Channel::Unreliable,
+    ];
+
+    /// The name `declare` takes from Lua.
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::ReliableOrdered => "reliable_ordered",
+            Channel::ReliableUnordered => "reliable_unordered",
+            Channel::Unreliable => "unreliable",
+        }
+    }
+
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Channel::ReliableOrdered => 0,
+            Channel::ReliableUnordered => 1,
+            Channel::Unreliable => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Channel::ReliableOrdered),
+            1 => Some(Channel::ReliableUnordered),
+            2 => Some(Channel::Unreliable),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Channel {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, String> {
+        Channel::ALL
+            .into_iter()
+            .find(|channel| channel.name() == name)
+            .ok_or_else(|| format!("unknown channel '{}'", name))
+    }
+}
+
+/// Where a call goes. In Lua, `"server"`, `"all"` or a client id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcTarget {
+    Server,
+    Client(ClientId),
+    /// Every client the server has added.
+    AllClients,
+}
+
+impl FromLua for RpcTarget {
+    fn from_lua(value: Value, lua: &Lua) -> Result<Self> {
+        match &value {
+            Value::String(s) if s == "server" => Ok(RpcTarget::Server),
+            Value::String(s) if s == "all" => Ok(RpcTarget::AllClients),
+            Value::Integer(_) | Value::Number(_) => {
+                Ok(RpcTarget::Client(ClientId(u32::from_lua(value, lua)?)))
+            }
+            _ => Err(Error::runtime(
+                "rpc target must be \"server\", \"all\" or a client id",
+            )),
+        }
+    }
+}
+
+/// The sender as handlers see it: `"server"` or the client's id.
+fn sender_value(lua: &Lua, sender: NetRole) -> Result<Value> {
+    match sender {
+        NetRole::Server => "server".into_lua(lua),
+        NetRole::Client(id) => id.0.into_lua(lua),
+    }
+}
+
+pub type RustRpcHandler = Rc<dyn Fn(&mut World, &Lua, NetRole, Value) -> Result<()>>;
+
+#[derive(Clone)]
+enum Handler {
+    Rust(RustRpcHandler),
+    /// Called as `handler(world, sender, args)`.
+    Lua(Function),
+}
+
+struct Unacked {
+    first_sent: u64,
+    last_sent: u64,
+    resent: bool,
+    bytes: Vec<u8>,
+}
+
+#[derive(Default)]
+struct PeerState {
+    next_seq: [u32; 3],
+    /// Packets awaiting an ack, by channel and sequence.
+    unacked: BTreeMap<(u8, u32), Unacked>,
+    /// Next sequence to handle on the ordered channel, and the calls that
+    /// arrived ahead of it; `None` for one that can't run.
+    ordered_next: u32,
+    ordered_ahead: BTreeMap<u32, Option<(String, Option<ScriptValue>)>>,
+    /// Every unordered sequence before the floor has been handled.
+    unordered_floor: u32,
+    unordered_seen: BTreeSet<u32>,
+}
+
+struct RpcState {
+    local: NetRole,
+    transport: Box<dyn Transport>,
+    channels: BTreeMap<String, Channel>,
+    handlers: BTreeMap<String, Handler>,
+    clients: BTreeSet<ClientId>,
+    peers: BTreeMap<NetRole, PeerState>,
+    tick: u64,
+    resend_after: u64,
+    give_up_after: u64,
+    stats: StatsTracker,
+}
+
+/// One end's RPC layer. Call `update` once per network tick to resend
+/// what hasn't been acknowledged and run handlers for what arrived.
+/// Clones share one layer, as does the `net` Lua global.
+#[derive(Clone)]
+pub struct Rpc {
+    state: Rc<RefCell<RpcState>>,
+}
+
+fn encode_call(name: &str, args: Option<&ScriptValue>) -> Result<Vec<u8>> {
+    let mut entity = SceneEntity::new();
+    if let Some(args) = args {
+        entity.insert(ARGS.to_string(), args.clone());
+    }
+    let mut out = (name.len() as u32).to_le_bytes().to_vec();
+    out.extend_from_slice(name.as_bytes());
+    let scene = Scene {
+        entities: vec![entity],
+    };
+    out.extend_from_slice(&scene.to_binary(false)?);
+    Ok(out)
+}
+
+fn decode_call(payload: &[u8]) -> Option<(String, Option<ScriptValue>)> {
+    let len = u32::from_le_bytes(payload.get(..4)?.try_into().ok()?) as usize;
+    let name = std::str::from_utf8(payload.get(4..4 + len)?).ok()?;
+    let mut scene = Scene::from_bytes(payload.get(4 + len..)?).ok()?;
+    let args = scene.entities.pop()?.remove(ARGS);
+    Some((name.to_string(), args))
+}
+
+fn packet(kind: u8, channel: u8, seq: u32, payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![kind, channel];
+    out.extend_from_slice(&seq.to_le_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+impl Rpc {
+    pub fn new(local: NetRole, transport: impl Transport + 'static) -> Self {
+        Rpc {
+            state: Rc::new(RefCell::new(RpcState {
+                local,
+                transport: Box::new(transport),
+                channels: BTreeMap::new(),
+                handlers: BTreeMap::new(),
+                clients: BTreeSet::new(),
+                peers: BTreeMap::new(),
+                tick: 0,
+                resend_after: 10,
+                give_up_after: 300,
+                stats: StatsTracker::new(30.0),
+            })),
+        }
+    }
+
+    pub fn local(&self) -> NetRole {
+        self.state.borrow().local
+    }
+
+    /// Updates an unacknowledged packet waits before it is sent again; 10
+    /// by default.
+    pub fn set_resend_after(&self, ticks: u64) {
+        self.state.borrow_mut().resend_after = ticks.max(1);
+    }
+
+    /// Updates a reliable call is resent for before it is dropped as
+    /// undeliverable; 300 by default.
+    pub fn set_give_up_after(&self, ticks: u64) {
+        self.state.borrow_mut().give_up_after = ticks.max(1);
+    }
+
+    /// Network ticks per second, 30 by default; what `stats` turns tick
+    /// counts into rates and times with.
+    pub fn set_tick_rate(&self, ticks_per_second: f64) {
+        self.state
+            .borrow_mut()
+            .stats
+            .set_tick_rate(ticks_per_second);
+    }
+
+    /// Both ends must declare an RPC, with the same channel, before
+    /// calling or handling it.
+    pub fn declare(&self, name: &str, channel: Channel) {
+        self.state
+            .borrow_mut()
+            .channels
+            .insert(name.to_string(), channel);
+    }
+
+    pub fn on(
+        &self,
+        name: &str,
+        handler: impl Fn(&mut World, &Lua, NetRole, Value) -> Result<()> + 'static,
+    ) {
+        self.state
+            .borrow_mut()
+            .handlers
+            .insert(name.to_string(), Handler::Rust(Rc::new(handler)));
+    }
+
+    /// A handler receiving its arguments as `T`, converted the way
+    /// registered components are.
+    pub fn on_typed<T: DeserializeOwned + 'static>(
+        &self,
+        name: &str,
+        handler: impl Fn(&mut World, NetRole, T) -> Result<()> + 'static,
+    ) {
+        self.on(name, move |world, lua, sender, args| {
+            handler(world, sender, lua.from_value(args)?)
+        });
+    }
+
+    /// Server side: where `RpcTarget::AllClients` calls go.
+    pub fn add_client(&self, client: ClientId) {
+        self.state.borrow_mut().clients.insert(client);
+    }
+
+    /// Forgets the client along with anything still unacknowledged.
+    pub fn remove_client(&self, client: ClientId) {
+        let mut state = self.state.borrow_mut();
+        state.clients.remove(&client);
+        state.peers.remove(&NetRole::Client(client));
+    }
+
+    pub fn rpc(&self, target: RpcTarget, name: &str, args: Option<&ScriptValue>) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        let channel = *state
+            .channels
+            .get(name)
+            .ok_or_else(|| Error::runtime(format!("rpc '{}' is not declared", name)))?;
+        let targets: Vec<NetRole> = match (state.local, target) {
+            (NetRole::Client(_), RpcTarget::Server) => vec![NetRole::Server],
+            (NetRole::Client(_), _) => {
+                return Err(Error::runtime("clients can only call the server"));
+            }
+            (NetRole::Server, RpcTarget::Server) => {
+                return Err(Error::runtime("the server cannot call itself"));
+            }
+            (NetRole::Server, RpcTarget::Client(client)) => vec![NetRole::Client(client)],
+            (NetRole::Server, RpcTarget::AllClients) => {
+                state.clients.iter().map(|&c| NetRole::Client(c)).collect()
+            }
+        };
+        let payload = encode_call(name, args)?;
+        let tick = state.tick;
+        for to in targets {
+            let peer = state.peers.entry(to).or_default();
+            let id = channel.id();
+            let seq = peer.next_seq[id as usize];
+            peer.next_seq[id as usize] = seq.wrapping_add(1);
+            let bytes = packet(KIND_MESSAGE, id, seq, &payload);
+            if channel != Channel::Unreliable {
+                let unacked = Unacked {
+                    first_sent: tick,
+                    last_sent: tick,
+                    resent: false,
+                    bytes: bytes.clone(),
+                };
+                peer.unacked.insert((id, seq), unacked);
+                state.stats.sent_reliable(false);
+            }
+            state.stats.sent(channel, bytes.len());
+            state.transport.send(to, bytes);
+        }
+        Ok(())
+    }
+
+    /// Like `rpc`, serializing `args` the way registered components are.
+    pub fn rpc_typed<T: Serialize>(
+        &self,
+        lua: &Lua,
+        target: RpcTarget,
+        name: &str,
+        args: &T,
+    ) -> Result<()> {
+        let args: ScriptValue = lua.from_value(lua.to_value(args)?)?;
+        self.rpc(target, name, Some(&args))
+    }
+
+    /// Calls sent but not yet acknowledged, across every peer.
+    pub fn pending(&self) -> usize {
+        let state = self.state.borrow();
+        state.peers.values().map(|peer| peer.unacked.len()).sum()
+    }
+
+    /// For the replication layer to report each snapshot it sends, so
+    /// their sizes show up in `NetStats`.
+    pub fn record_snapshot(&self, bytes: usize) {
+        self.state.borrow_mut().stats.snapshot(bytes);
+    }
+
+    /// Traffic over the last second of ticks.
+    pub fn stats(&self) -> NetStats {
+        self.state.borrow().stats.stats(self.pending())
+    }
+
+    /// Acknowledges and decodes what arrived, resends overdue packets and
+    /// drops those that were never acknowledged, then runs handlers in
+    /// arrival order (ordered calls in send order). A failing handler is
+    /// logged and the rest still run. Leaves the latest `NetStats` in the
+    /// world.
+    pub fn update(&self, world: &mut World, lua: &Lua) -> Result<()> {
+        let calls = self.receive();
+        for (sender, name, args) in calls {
+            let handler = self.state.borrow().handlers.get(&name).cloned();
+            let Some(handler) = handler else {
+                log::warn!(target: "net", "no handler for rpc '{}' from {:?}", name, sender);
+                continue;
+            };
+            let args = args.map(|args| lua.to_value(&args)).transpose()?;
+            let args = args.unwrap_or(Value::Nil);
+            let result = match handler {
+                Handler::Rust(handler) => handler(world, lua, sender, args),
+                Handler::Lua(handler) => lua.scope(|scope| {
+                    let handle = scope.create_userdata_ref_mut(&mut *world)?;
+                    handler.call::<()>((handle, sender_value(lua, sender)?, args))
+                }),
+            };
+            if let Err(e) = result {
+                log::error!(target: "net", "rpc '{}' failed: {}", name, e);
+            }
+            world.apply_commands(lua)?;
+        }
+        world.insert_resource(self.stats());
+        Ok(())
+    }
+
+    fn receive(&self) -> Vec<(NetRole, String, Option<ScriptValue>)> {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        state.tick += 1;
+        state.stats.tick();
+        let (tick, resend_after) = (state.tick, state.resend_after);
+
+        let mut calls = Vec::new();
+        while let Some((from, bytes)) = state.transport.recv() {
+            let (Some(&kind), Some(channel), Some(seq)) = (
+                bytes.first(),
+                bytes.get(1).and_then(|&id| Channel::from_id(id)),
+                bytes
+                    .get(2..6)
+                    .map(|s| u32::from_le_bytes(s.try_into().expect("4 bytes"))),
+            ) else {
+                log::warn!(target: "net", "malformed packet from {:?}", from);
+                continue;
+            };
+            state.stats.received(channel, bytes.len());
+            let peer = state.peers.entry(from).or_default();
+            if kind == KIND_ACK {
+                if let Some(acked) = peer.unacked.remove(&(channel.id(), seq))
+                    && !acked.resent
+                {
+                    state.stats.round_trip(tick - acked.first_sent);
+                }
+                continue;
+            }
+            let arrived = match channel {
+                Channel::Unreliable => Arrival::Ahead,
+                Channel::ReliableUnordered => arrival(seq, peer.unordered_floor),
+                Channel::ReliableOrdered => arrival(seq, peer.ordered_next),
+            };
+            if matches!(arrived, Arrival::TooFar) {
+                continue;
+            }
+            if channel != Channel::Unreliable {
+                let ack = packet(KIND_ACK, channel.id(), seq, &[]);
+                state.stats.sent(channel, ack.len());
+                state.transport.send(from, ack);
+            }
+            if matches!(arrived, Arrival::Old) {
+                continue;
+            }
+            // A call that can't run still takes up its sequence, so the
+            // ones after it aren't held back.
+            let call = match decode_call(&bytes[6..]) {
+                None => {
+                    log::warn!(target: "net", "malformed rpc from {:?}", from);
+                    None
+                }
+                Some((name, _)) if state.channels.get(&name) != Some(&channel) => {
+                    log::warn!(target: "net", "rpc '{}' from {:?} on an undeclared channel", name, from);
+                    None
+                }
+                call => call,
+            };
+            let with_sender = |(name, args)| (from, name, args);
+            match channel {
+                Channel::Unreliable => calls.extend(call.map(with_sender)),
+                Channel::ReliableUnordered => {
+                    if peer.unordered_seen.insert(seq) {
+                        calls.extend(call.map(with_sender));
+                    }
+                    while peer.unordered_seen.remove(&peer.unordered_floor) {
+                        peer.unordered_floor = peer.unordered_floor.wrapping_add(1);
+                    }
+                }
+                Channel::ReliableOrdered => {
+                    peer.ordered_ahead.insert(seq, call);
+                    while let Some(call) = peer.ordered_ahead.remove(&peer.ordered_next) {
+                        calls.extend(call.map(with_sender));
+                        peer.ordered_next = peer.ordered_next.wrapping_add(1);
+                    }
+                }
+            }
+        }
+
+        // After taking acks, so packets acknowledged this tick aren't resent.
+        let give_up_after = state.give_up_after;
+        for (&to, peer) in &mut state.peers {
+            peer.unacked.retain(|&(id, seq), unacked| {
+                let keep = tick - unacked.first_sent < give_up_after;
+                if !keep {
+                    let channel = Channel::from_id(id).map_or("?", Channel::name);
+                    log::warn!(target: "net", "giving up on {} call {} to {:?}", channel, seq, to);
+                }
+                keep
+            });
+            for (&(id, _), unacked) in &mut peer.unacked {
+                if tick - unacked.last_sent >= resend_after {
+                    unacked.last_sent = tick;
+                    unacked.resent = true;
+                    let channel = Channel::from_id(id).expect("sent on a known channel");
+                    state.stats.sent(channel, unacked.bytes.len());
+                    state.stats.sent_reliable(true);
+                    state.transport.send(to, unacked.bytes.clone());
+                }
+            }
+        }
+        calls
+    }
+
+    /// Adds the `net` global: `net:declare(name, channel)`,
+    /// `net:on(name, function(world, sender, args))`,
+    /// `net:rpc(target, name, args)` and `net:stats()`.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("net", self.clone())
+    }
+}
+
+impl UserData for Rpc {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method(
+            "declare",
+            |_, this, (name, channel): (String, Option<String>)| {
+                let channel = match channel {
+                    Some(channel) => channel.parse().map_err(Error::runtime)?,
+                    None => Channel::default(),
+                };
+                this.declare(&name, channel);
+                Ok(())
+            },
+        );
+        methods.add_method("on", |_, this, (name, handler): (String, Function)| {
+            this.state
+                .borrow_mut()
+                .handlers
+                .insert(name, Handler::Lua(handler));
+            Ok(())
+        });
+        methods.add_method(
+            "rpc",
+            |_, this, (target, name, args): (RpcTarget, String, Option<ScriptValue>)| {
+                this.rpc(target, &name, args.as_ref())
+            },
+        );
+        methods.add_method("role", |lua, this, ()| sender_value(lua, this.local()));
+        methods.add_method("stats", |lua, this, ()| lua.to_value(&this.stats()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::transport::{MemoryNetwork, MemoryTransport};
+
+    /// Drops every other packet it sends and delivers what it receives
+    /// newest first.
+    struct Lossy {
+        inner: MemoryTransport,
+        sent: usize,
+        held: Vec<(NetRole, Vec<u8>)>,
+    }
+
+    impl Transport for Lossy {
+        fn send(&mut self, to: NetRole, packet: Vec<u8>) {
+            self.sent += 1;
+            if self.sent.is_multiple_of(2) {
+                self.inner.send(to, packet);
+            }
+        }
+
+        fn recv(&mut self) -> Option<(NetRole, Vec<u8>)> {
+            while let Some(packet) = self.inner.recv() {
+                self.held.push(packet);
+            }
+            self.held.pop()
+        }
+    }
+
+    #[test]
+    fn test_channels_survive_loss() -> Result<()> {
+        let network = MemoryNetwork::new();
+        let client_id = ClientId(7);
+        let server = Rpc::new(NetRole::Server, network.endpoint(NetRole::Server));
+        let client = Rpc::new(
+            NetRole::Client(client_id),
+            Lossy {
+                inner: network.endpoint(NetRole::Client(client_id)),
+                sent: 0,
+                held: Vec::new(),
+            },
+        );
+        for rpc in [&server, &client] {
+            rpc.set_resend_after(2);
+            rpc.declare("OpenDoor", Channel::ReliableOrdered);
+            rpc.declare("Ping", Channel::Unreliable);
+        }
+        // A window long enough to still hold the early resends.
+        client.set_tick_rate(60.0);
+        server.add_client(client_id);
+
+        let (server_lua, client_lua) = (Lua::new(), Lua::new());
+        server.register_lua(&server_lua)?;
+        client.register_lua(&client_lua)?;
+        server_lua
+            .load(
+                r#"
+                opened = {}
+                net:on("OpenDoor", function(world, sender, args)
+                    table.insert(opened, args.door)
+                    net:rpc(sender, "Ping", { door = args.door })
+                end)
+            "#,
+            )
+            .exec()?;
+        client_lua
+            .load(
+                r#"
+                pings = 0
+                net:on("Ping", function() pings += 1 end)
+                for door = 1, 5 do net:rpc("server", "OpenDoor", { door = door }) end
+                assert(not pcall(net.rpc, net, 3, "OpenDoor"))
+            "#,
+            )
+            .exec()?;
+
+        let (mut server_world, mut client_world) = (World::new(), World::new());
+        for _ in 0..40 {
+            client.update(&mut client_world, &client_lua)?;
+            server.update(&mut server_world, &server_lua)?;
+        }
+        let opened: Vec<u32> = server_lua.load("return opened").eval()?;
+        assert_eq!(opened, [1, 2, 3, 4, 5]);
+        assert_eq!(client.pending(), 0);
+        let pings: u32 = client_lua.load("return pings").eval()?;
+        assert_eq!(pings, 5);
+
+        let stats = client_world.resource::<NetStats>().unwrap().clone();
+        assert!(stats.packet_loss > 0.0 && stats.packet_loss <= 1.0);
+        assert!(stats.rtt_ms.is_some_and(|rtt| rtt > 0.0));
+        assert!(stats.channels["reliable_ordered"].bytes_sent_per_sec > 0.0);
+        let lua_loss: f64 = client_lua.load("return net:stats().packet_loss").eval()?;
+        assert_eq!(lua_loss, stats.packet_loss);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequences_wrap_and_stray_calls_never_stall() -> Result<()> {
+        let network = MemoryNetwork::new();
+        let (player, lua, mut world) = (NetRole::Client(ClientId(1)), Lua::new(), World::new());
+        let server = Rpc::new(NetRole::Server, network.endpoint(NetRole::Server));
+        let client = Rpc::new(player, network.endpoint(player));
+        for rpc in [&server, &client] {
+            rpc.declare("Step", Channel::ReliableOrdered);
+            rpc.declare("Tap", Channel::ReliableUnordered);
+        }
+        client.declare("Odd", Channel::ReliableOrdered);
+        server.declare("Odd", Channel::Unreliable);
+        let steps = Rc::new(RefCell::new(Vec::new()));
+        let taps = Rc::new(RefCell::new(0));
+        let seen = steps.clone();
+        server.on("Step", move |_, lua, _, args| {
+            seen.borrow_mut().push(lua.from_value::<f64>(args)?);
+            Ok(())
+        });
+        let tapped = taps.clone();
+        server.on("Tap", move |_, _, _, _| {
+            *tapped.borrow_mut() += 1;
+            Ok(())
+        });
+        server.on("Odd", |_, _, _, _| panic!("declared on another channel"));
+
+        // Both ends just short of wrapping.
+        let start = u32::MAX - 1;
+        client
+            .state
+            .borrow_mut()
+            .peers
+            .entry(NetRole::Server)
+            .or_default()
+            .next_seq = [start; 3];
+        {
+            let mut state = server.state.borrow_mut();
+            let peer = state.peers.entry(player).or_default();
+            (peer.ordered_next, peer.unordered_floor) = (start, start);
+        }
+        for step in 1..=2 {
+            client.rpc(
+                RpcTarget::Server,
+                "Step",
+                Some(&ScriptValue::Number(step as f64)),
+            )?;
+            client.rpc(RpcTarget::Server, "Tap", None)?;
+        }
+        client.rpc(RpcTarget::Server, "Odd", None)?;
+        for step in 3..=4 {
+            client.rpc(
+                RpcTarget::Server,
+                "Step",
+                Some(&ScriptValue::Number(step as f64)),
+            )?;
+            client.rpc(RpcTarget::Server, "Tap", None)?;
+        }
+        server.update(&mut world, &lua)?;
+        client.update(&mut world, &lua)?;
+        assert_eq!(*steps.borrow(), [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(*taps.borrow(), 4);
+        // The mismatched call was acknowledged too.
+        assert_eq!(client.pending(), 0);
+
+        // Too far ahead to hold: neither kept nor acknowledged.
+        let mut raw = network.endpoint(NetRole::Client(ClientId(2)));
+        let call = encode_call("Step", Some(&ScriptValue::Number(9.0)))?;
+        raw.send(
+            NetRole::Server,
+            packet(KIND_MESSAGE, 0, REORDER_WINDOW + 1, &call),
+        );
+        server.update(&mut world, &lua)?;
+        assert!(raw.recv().is_none());
+        let held = server.state.borrow().peers[&NetRole::Client(ClientId(2))]
+            .ordered_ahead
+            .len();
+        assert_eq!(held, 0);
+
+        // Nobody answers, so the call is dropped after a while.
+        let lonely = NetRole::Client(ClientId(3));
+        let lonely = Rpc::new(lonely, network.endpoint(lonely));
+        lonely.declare("Step", Channel::ReliableOrdered);
+        lonely.set_give_up_after(5);
+        lonely.rpc(RpcTarget::Server, "Step", None)?;
+        for _ in 0..5 {
+            lonely.update(&mut world, &lua)?;
+        }
+        assert_eq!(lonely.pending(), 0);
+        Ok(())
+    }
+}
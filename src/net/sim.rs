@@ -0,0 +1,281 @@
+use super::NetRole;
+use super::transport::Transport;
+use crate::cvar::{Cvar, CvarCallback, CvarError, CvarValue, Cvars};
+use crate::rng::GameRng;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Applied to each direction of a wrapped endpoint, so wrapping just the
+/// client adds twice the latency to a round trip.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct NetConditions {
+    pub latency_ms: f64,
+    /// Each packet's latency varies by up to this much either way, which
+    /// reorders packets sent close together.
+    pub jitter_ms: f64,
+    /// Chance a packet is dropped.
+    pub loss: f64,
+    /// Chance a packet is held back until the next one sent after it has
+    /// been delivered.
+    pub reorder: f64,
+}
+
+const CVARS: [(&str, f64, &str); 4] = [
+    ("net.sim.latency_ms", 5000.0, "simulated one-way latency"),
+    ("net.sim.jitter_ms", 1000.0, "simulated latency variation"),
+    ("net.sim.loss", 1.0, "simulated packet loss, 0 to 1"),
+    ("net.sim.reorder", 1.0, "simulated reorder chance, 0 to 1"),
+];
+
+struct SimState {
+    conditions: NetConditions,
+    /// Seconds, as advanced by the game.
+    now: f64,
+    rng: GameRng,
+    next_seq: u64,
+}
+
+/// Bad network conditions for local testing, shared by every endpoint it
+/// wraps. Time only passes through `advance`, so runs are reproducible for
+/// a given seed and call sequence. Clones share one simulator.
+#[derive(Clone)]
+pub struct NetSimulator {
+    state: Rc<RefCell<SimState>>,
+}
+
+struct InFlight {
+    due: f64,
+    seq: u64,
+    held: bool,
+    peer: NetRole,
+    packet: Vec<u8>,
+}
+
+impl NetSimulator {
+    pub fn new(seed: u64) -> Self {
+        NetSimulator {
+            state: Rc::new(RefCell::new(SimState {
+                conditions: NetConditions::default(),
+                now: 0.0,
+                rng: GameRng::new(seed),
+                next_seq: 0,
+            })),
+        }
+    }
+
+    pub fn conditions(&self) -> NetConditions {
+        self.state.borrow().conditions
+    }
+
+    pub fn set_conditions(&self, conditions: NetConditions) {
+        self.state.borrow_mut().conditions = conditions;
+    }
+
+    /// Call once a frame with the real delta; packets are delivered once
+    /// their latency has passed.
+    pub fn advance(&self, seconds: f64) {
+        self.state.borrow_mut().now += seconds.max(0.0);
+    }
+
+    pub fn wrap<T: Transport>(&self, inner: T) -> SimulatedTransport<T> {
+        SimulatedTransport {
+            inner,
+            sim: self.clone(),
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+        }
+    }
+
+    /// Registers `net.sim.latency_ms`, `net.sim.jitter_ms`, `net.sim.loss`
+    /// and `net.sim.reorder`, so the console's `set` changes conditions
+    /// while the game runs.
+    pub fn register_cvars(&self, cvars: &Cvars) -> Result<(), CvarError> {
+        for (name, max, help) in CVARS {
+            let def = Cvar::new(CvarValue::Float(0.0))
+                .with_range(0.0, max)
+                .with_help(help);
+            cvars.register(name, def)?;
+            let value = cvars.get_f64(name).unwrap_or(0.0);
+            self.set_cvar(name, value);
+            let sim = self.clone();
+            let callback = Rc::new(move |name: &str, value: &CvarValue| {
+                if let CvarValue::Float(value) = value {
+                    sim.set_cvar(name, *value);
+                }
+            });
+            cvars.on_change(name, CvarCallback::Rust(callback))?;
+        }
+        Ok(())
+    }
+
+    fn set_cvar(&self, name: &str, value: f64) {
+        let conditions = &mut self.state.borrow_mut().conditions;
+        match name {
+            "net.sim.latency_ms" => conditions.latency_ms = value,
+            "net.sim.jitter_ms" => conditions.jitter_ms = value,
+            "net.sim.loss" => conditions.loss = value,
+            "net.sim.reorder" => conditions.reorder = value,
+            _ => {}
+        }
+    }
+
+    /// Schedules `packet`, or drops it.
+    fn enqueue(&self, queue: &mut Vec<InFlight>, peer: NetRole, packet: Vec<u8>) {
+        let mut state = self.state.borrow_mut();
+        let NetConditions {
+            latency_ms,
+            jitter_ms,
+            loss,
+            reorder,
+        } = state.conditions;
+        if state.rng.chance(loss) {
+            return;
+        }
+        let jitter = match jitter_ms > 0.0 {
+            true => state.rng.range(-jitter_ms, jitter_ms),
+            false => 0.0,
+        };
+        let held = state.rng.chance(reorder);
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        queue.push(InFlight {
+            due: state.now + (latency_ms + jitter).max(0.0) / 1000.0,
+            seq,
+            held,
+            peer,
+            packet,
+        });
+    }
+
+    /// The next packet due in `queue`. A held packet waits for one sent
+    /// after it to be delivered first; with `release`, when only held
+    /// packets are due the newest goes once nothing sent after it is in
+    /// flight.
+    fn dequeue(&self, queue: &mut Vec<InFlight>, release: bool) -> Option<InFlight> {
+        let now = self.state.borrow().now;
+        queue.sort_by(|a, b| a.due.total_cmp(&b.due).then(a.seq.cmp(&b.seq)));
+        let ready = queue.iter().take_while(|p| p.due <= now);
+        let index = match ready.clone().position(|p| !p.held) {
+            Some(index) => index,
+            None if !release => return None,
+            None => {
+                let held = ready.map(|p| p.seq).max()?;
+                if queue.iter().any(|p| p.seq > held) {
+                    return None;
+                }
+                queue.iter().position(|p| p.seq == held)?
+            }
+        };
+        let packet = queue.remove(index);
+        for overtaken in queue.iter_mut().filter(|p| p.seq < packet.seq) {
+            overtaken.held = false;
+        }
+        Some(packet)
+    }
+}
+
+/// A transport behind a `NetSimulator`, delaying, dropping and reordering
+/// what goes through it in both directions.
+pub struct SimulatedTransport<T> {
+    inner: T,
+    sim: NetSimulator,
+    outgoing: Vec<InFlight>,
+    incoming: Vec<InFlight>,
+}
+
+impl<T: Transport> SimulatedTransport<T> {
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Sends what is due. Held packets are only released from `recv`, so
+    /// the next `send` has a chance to overtake them.
+    fn flush(&mut self, release: bool) {
+        while let Some(packet) = self.sim.dequeue(&mut self.outgoing, release) {
+            self.inner.send(packet.peer, packet.packet);
+        }
+        while let Some((from, packet)) = self.inner.recv() {
+            self.sim.enqueue(&mut self.incoming, from, packet);
+        }
+    }
+}
+
+impl<T: Transport> Transport for SimulatedTransport<T> {
+    fn send(&mut self, to: NetRole, packet: Vec<u8>) {
+        self.sim.enqueue(&mut self.outgoing, to, packet);
+        self.flush(false);
+    }
+
+    fn recv(&mut self) -> Option<(NetRole, Vec<u8>)> {
+        self.flush(true);
+        let packet = self.sim.dequeue(&mut self.incoming, true)?;
+        Some((packet.peer, packet.packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::Console;
+    use crate::ecs::World;
+    use crate::net::{ClientId, MemoryNetwork};
+    use mlua::Lua;
+
+    #[test]
+    fn test_latency_loss_and_reordering() -> mlua::Result<()> {
+        let network = MemoryNetwork::new();
+        let client = NetRole::Client(ClientId(1));
+        let sim = NetSimulator::new(7);
+        let mut server = network.endpoint(NetRole::Server);
+        let mut endpoint = sim.wrap(network.endpoint(client));
+
+        let cvars = Cvars::new();
+        let console = Console::new();
+        cvars.register_console(&console);
+        sim.register_cvars(&cvars)?;
+        let (mut world, lua) = (World::new(), Lua::new());
+        console.execute(&mut world, &lua, "set net.sim.latency_ms 100")?;
+        assert_eq!(sim.conditions().latency_ms, 100.0);
+
+        let mut received = |endpoint: &mut SimulatedTransport<_>, seconds: f64| {
+            sim.advance(seconds);
+            endpoint.recv();
+            std::iter::from_fn(|| server.recv())
+                .map(|(_, packet)| packet[0])
+                .collect::<Vec<u8>>()
+        };
+        endpoint.send(NetRole::Server, vec![1]);
+        assert!(received(&mut endpoint, 0.05).is_empty());
+        assert_eq!(received(&mut endpoint, 0.05), [1]);
+
+        sim.set_conditions(NetConditions {
+            reorder: 1.0,
+            ..NetConditions::default()
+        });
+        endpoint.send(NetRole::Server, vec![2]);
+        endpoint.send(NetRole::Server, vec![3]);
+        assert_eq!(received(&mut endpoint, 0.0), [3, 2]);
+
+        sim.set_conditions(NetConditions {
+            loss: 0.5,
+            ..NetConditions::default()
+        });
+        for i in 0..100 {
+            endpoint.send(NetRole::Server, vec![i]);
+        }
+        let arrived = received(&mut endpoint, 0.0).len();
+        assert!((30..70).contains(&arrived), "{} arrived", arrived);
+
+        // Incoming packets are delayed too.
+        sim.set_conditions(NetConditions {
+            latency_ms: 20.0,
+            ..NetConditions::default()
+        });
+        let mut server = network.endpoint(NetRole::Server);
+        server.send(client, vec![9]);
+        assert!(endpoint.recv().is_none());
+        sim.advance(0.02);
+        assert_eq!(endpoint.recv(), Some((NetRole::Server, vec![9])));
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use super::rpc::Channel;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ChannelStats {
+    pub bytes_sent_per_sec: f64,
+    pub bytes_received_per_sec: f64,
+    pub packets_sent_per_sec: f64,
+    pub packets_received_per_sec: f64,
+}
+
+/// One end's traffic over the last second of network ticks. `Rpc::update`
+/// keeps the latest as a world resource, where `Diagnostics` picks it up
+/// for the overlay and exporters.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct NetStats {
+    /// By channel name, acks counted on the channel they acknowledge.
+    pub channels: BTreeMap<String, ChannelStats>,
+    /// Reliable packets resent per reliable packet sent. Each resend means
+    /// the packet or its ack was lost, so this is the round-trip loss.
+    pub packet_loss: f64,
+    /// Smoothed round-trip time; `None` until a packet sent once has been
+    /// acknowledged.
+    pub rtt_ms: Option<f64>,
+    /// Calls sent but not yet acknowledged.
+    pub pending: usize,
+    /// Size of the latest snapshot the replication layer reported, and the
+    /// average over the window.
+    pub snapshot_bytes: usize,
+    pub snapshot_bytes_avg: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Sample {
+    bytes_sent: [usize; 3],
+    bytes_received: [usize; 3],
+    packets_sent: [usize; 3],
+    packets_received: [usize; 3],
+    reliable_sent: usize,
+    resent: usize,
+    snapshots: usize,
+    snapshot_bytes: usize,
+}
+
+/// Counts what the RPC layer sends and receives, one sample per tick.
+#[derive(Debug, Clone)]
+pub(crate) struct StatsTracker {
+    tick_rate: f64,
+    samples: VecDeque<Sample>,
+    current: Sample,
+    rtt_ticks: Option<f64>,
+    last_snapshot: usize,
+}
+
+impl StatsTracker {
+    pub(crate) fn new(tick_rate: f64) -> Self {
+        StatsTracker {
+            tick_rate,
+            samples: VecDeque::new(),
+            current: Sample::default(),
+            rtt_ticks: None,
+            last_snapshot: 0,
+        }
+    }
+
+    pub(crate) fn set_tick_rate(&mut self, tick_rate: f64) {
+        self.tick_rate = tick_rate.max(1.0);
+    }
+
+    /// Closes the current tick's sample, keeping a second's worth.
+    pub(crate) fn tick(&mut self) {
+        self.samples.push_back(std::mem::take(&mut self.current));
+        while self.samples.len() > self.tick_rate.ceil() as usize {
+            self.samples.pop_front();
+        }
+    }
+
+    pub(crate) fn sent(&mut self, channel: Channel, bytes: usize) {
+        self.current.bytes_sent[channel.id() as usize] += bytes;
+        self.current.packets_sent[channel.id() as usize] += 1;
+    }
+
+    pub(crate) fn sent_reliable(&mut self, resend: bool) {
+        match resend {
+            true => self.current.resent += 1,
+            false => self.current.reliable_sent += 1,
+        }
+    }
+
+    pub(crate) fn received(&mut self, channel: Channel, bytes: usize) {
+        self.current.bytes_received[channel.id() as usize] += bytes;
+        self.current.packets_received[channel.id() as usize] += 1;
+    }
+
+    /// A round trip of `ticks` for a packet that was never resent, since a
+    /// resent one's ack can't be matched to a send.
+    pub(crate) fn round_trip(&mut self, ticks: u64) {
+        let ticks = ticks as f64;
+        self.rtt_ticks = Some(match self.rtt_ticks {
+            Some(rtt) => rtt + (ticks - rtt) / 8.0,
+            None => ticks,
+        });
+    }
+
+    pub(crate) fn snapshot(&mut self, bytes: usize) {
+        self.current.snapshots += 1;
+        self.current.snapshot_bytes += bytes;
+        self.last_snapshot = bytes;
+    }
+
+    pub(crate) fn stats(&self, pending: usize) -> NetStats {
+        let mut total = Sample::default();
+        for sample in &self.samples {
+            for i in 0..3 {
+                total.bytes_sent[i] += sample.bytes_sent[i];
+                total.bytes_received[i] += sample.bytes_received[i];
+                total.packets_sent[i] += sample.packets_sent[i];
+                total.packets_received[i] += sample.packets_received[i];
+            }
+            total.reliable_sent += sample.reliable_sent;
+            total.resent += sample.resent;
+            total.snapshots += sample.snapshots;
+            total.snapshot_bytes += sample.snapshot_bytes;
+        }
+        let seconds = (self.samples.len() as f64 / self.tick_rate).max(1.0 / self.tick_rate);
+        let channels = Channel::ALL
+            .iter()
+            .map(|&channel| {
+                let i = channel.id() as usize;
+                let stats = ChannelStats {
+                    bytes_sent_per_sec: total.bytes_sent[i] as f64 / seconds,
+                    bytes_received_per_sec: total.bytes_received[i] as f64 / seconds,
+                    packets_sent_per_sec: total.packets_sent[i] as f64 / seconds,
+                    packets_received_per_sec: total.packets_received[i] as f64 / seconds,
+                };
+                (channel.name().to_string(), stats)
+            })
+            .collect();
+        NetStats {
+            channels,
+            packet_loss: match total.reliable_sent {
+                0 => 0.0,
+                sent => (total.resent as f64 / sent as f64).min(1.0),
+            },
+            rtt_ms: self.rtt_ticks.map(|ticks| ticks * 1000.0 / self.tick_rate),
+            pending,
+            snapshot_bytes: self.last_snapshot,
+            snapshot_bytes_avg: match total.snapshots {
+                0 => 0.0,
+                n => total.snapshot_bytes as f64 / n as f64,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates_loss_and_window() {
+        let mut tracker = StatsTracker::new(2.0);
+        tracker.sent(Channel::ReliableOrdered, 100);
+        tracker.sent_reliable(false);
+        tracker.sent_reliable(false);
+        tracker.sent_reliable(true);
+        tracker.received(Channel::Unreliable, 50);
+        tracker.snapshot(300);
+        tracker.tick();
+
+        // One tick at two a second is half a second of traffic.
+        let stats = tracker.stats(3);
+        let ordered = &stats.channels["reliable_ordered"];
+        assert_eq!(ordered.bytes_sent_per_sec, 200.0);
+        assert_eq!(ordered.packets_sent_per_sec, 2.0);
+        assert_eq!(stats.channels["unreliable"].bytes_received_per_sec, 100.0);
+        assert_eq!(stats.packet_loss, 0.5);
+        assert_eq!(
+            (stats.snapshot_bytes, stats.snapshot_bytes_avg),
+            (300, 300.0)
+        );
+        assert_eq!((stats.pending, stats.rtt_ms), (3, None));
+
+        tracker.round_trip(4);
+        tracker.round_trip(12);
+        assert_eq!(tracker.stats(0).rtt_ms, Some(2500.0));
+
+        // Only a second's worth of ticks is kept.
+        tracker.snapshot(100);
+        tracker.tick();
+        tracker.tick();
+        let stats = tracker.stats(0);
+        assert_eq!(stats.channels["reliable_ordered"].bytes_sent_per_sec, 0.0);
+        assert_eq!(stats.packet_loss, 0.0);
+        assert_eq!(
+            (stats.snapshot_bytes, stats.snapshot_bytes_avg),
+            (100, 100.0)
+        );
+    }
+}
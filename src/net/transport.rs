@@ -0,0 +1,81 @@
+use super::NetRole;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+
+/// Moves datagrams between the server and its clients, addressed by
+/// role. Packets may be lost, duplicated or reordered; the RPC layer adds
+/// reliability where a channel asks for it.
+pub trait Transport {
+    fn send(&mut self, to: NetRole, packet: Vec<u8>);
+    fn recv(&mut self) -> Option<(NetRole, Vec<u8>)>;
+}
+
+type Queues = BTreeMap<NetRole, VecDeque<(NetRole, Vec<u8>)>>;
+
+/// Delivers packets between endpoints in one process, in order and
+/// without loss; for local play and tests. Clones share one network.
+#[derive(Clone, Default)]
+pub struct MemoryNetwork {
+    queues: Rc<RefCell<Queues>>,
+}
+
+impl MemoryNetwork {
+    pub fn new() -> Self {
+        MemoryNetwork::default()
+    }
+
+    pub fn endpoint(&self, address: NetRole) -> MemoryTransport {
+        self.queues.borrow_mut().entry(address).or_default();
+        MemoryTransport {
+            address,
+            network: self.clone(),
+        }
+    }
+}
+
+pub struct MemoryTransport {
+    address: NetRole,
+    network: MemoryNetwork,
+}
+
+impl Transport for MemoryTransport {
+    /// Packets to an address nobody has an endpoint for are dropped.
+    fn send(&mut self, to: NetRole, packet: Vec<u8>) {
+        if let Some(queue) = self.network.queues.borrow_mut().get_mut(&to) {
+            queue.push_back((self.address, packet));
+        }
+    }
+
+    fn recv(&mut self) -> Option<(NetRole, Vec<u8>)> {
+        self.network
+            .queues
+            .borrow_mut()
+            .get_mut(&self.address)?
+            .pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::ClientId;
+
+    #[test]
+    fn test_memory_network_delivers_in_order() {
+        let network = MemoryNetwork::new();
+        let mut server = network.endpoint(NetRole::Server);
+        let mut client = network.clone().endpoint(NetRole::Client(ClientId(1)));
+
+        client.send(NetRole::Server, vec![1]);
+        client.send(NetRole::Server, vec![2]);
+        client.send(NetRole::Client(ClientId(9)), vec![3]);
+        assert_eq!(server.recv(), Some((NetRole::Client(ClientId(1)), vec![1])));
+        assert_eq!(server.recv(), Some((NetRole::Client(ClientId(1)), vec![2])));
+        assert_eq!(server.recv(), None);
+
+        server.send(NetRole::Client(ClientId(1)), vec![4]);
+        assert_eq!(client.recv(), Some((NetRole::Server, vec![4])));
+        assert_eq!(client.recv(), None);
+    }
+}
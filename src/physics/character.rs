@@ -0,0 +1,315 @@
+use super::{Collider, Position, QueryFilter, RayHit, TriggerVolume, shape_cast};
+use crate::ecs::{EcsError, Entity, World};
+use crate::math::Vec2;
+use mlua::{Lua, LuaSerdeExt, Result, UserData, UserDataMethods, Value};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Slide passes per move; each one can turn the motion along one surface.
+const ITERATIONS: usize = 4;
+/// How far below the feet the ground is looked for after moving.
+const GROUND_PROBE: f64 = 0.05;
+
+/// A kinematic body moved only by `move_and_slide`: the physics step
+/// leaves it where it is, and solid bodies bump off it as if it were
+/// static. Up is +y.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CharacterController {
+    /// Tallest ledge walked up without jumping.
+    pub step_offset: f64,
+    /// Steepest walkable slope, in degrees.
+    pub slope_limit: f64,
+    /// Gap kept between the collider and whatever it touches.
+    pub skin: f64,
+    /// Set by every move: whether it ended standing on walkable ground.
+    pub grounded: bool,
+    pub ground_normal: Vec2,
+}
+
+impl Default for CharacterController {
+    fn default() -> Self {
+        CharacterController {
+            step_offset: 0.25,
+            slope_limit: 45.0,
+            skin: 0.01,
+            grounded: false,
+            ground_normal: Vec2::ZERO,
+        }
+    }
+}
+
+impl CharacterController {
+    fn walkable(&self, normal: Vec2) -> bool {
+        normal.y >= self.slope_limit.to_radians().cos() - 1e-9
+    }
+}
+
+/// What one `move_and_slide` did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharacterMove {
+    pub moved: Vec2,
+    pub grounded: bool,
+    /// Every surface touched along the way, in order.
+    pub hits: Vec<RayHit>,
+}
+
+struct Mover<'a> {
+    world: &'a World,
+    entity: Entity,
+    collider: Collider,
+    filter: QueryFilter,
+    controller: CharacterController,
+}
+
+impl Mover<'_> {
+    fn cast(&self, from: Vec2, motion: Vec2) -> Option<RayHit> {
+        let distance = motion.length();
+        if distance < f64::EPSILON {
+            return None;
+        }
+        shape_cast(
+            self.world,
+            &self.collider.shape,
+            from,
+            motion,
+            distance + self.controller.skin,
+            &self.filter,
+        )
+        .filter(|hit| hit.entity != self.entity)
+    }
+
+    /// Moves as far along `motion` as it can without touching anything.
+    fn advance(&self, from: Vec2, motion: Vec2) -> (Vec2, Option<RayHit>) {
+        match self.cast(from, motion) {
+            None => (from + motion, None),
+            Some(hit) => {
+                let direction = motion.normalize_or_zero();
+                // Back off along the motion far enough to leave `skin`
+                // between collider and surface, even on a glancing hit.
+                let facing = (-direction.dot(hit.normal)).max(0.1);
+                let travel =
+                    (hit.distance - self.controller.skin / facing).clamp(0.0, motion.length());
+                (from + direction * travel, Some(hit))
+            }
+        }
+    }
+
+    /// Tries to climb onto a ledge: up by the step offset, across, then
+    /// back down onto walkable ground.
+    fn step_up(&self, from: Vec2, across: Vec2) -> Option<Vec2> {
+        let rise = Vec2::new(0.0, self.controller.step_offset);
+        let (raised, _) = self.advance(from, rise);
+        let climbed = raised.y - from.y;
+        if climbed <= self.controller.skin {
+            return None;
+        }
+        let (over, wall) = self.advance(raised, across);
+        if wall.is_some() && (over - raised).length() < f64::EPSILON {
+            return None;
+        }
+        let drop = Vec2::new(0.0, -(climbed + self.controller.skin));
+        let (landed, ground) = self.advance(over, drop);
+        match ground {
+            Some(hit) if self.controller.walkable(hit.normal) => Some(landed),
+            _ => None,
+        }
+    }
+}
+
+/// Moves a controlled entity by `velocity * dt`, sliding along what it
+/// hits, stepping up low ledges and refusing to climb slopes steeper than
+/// the limit, then updates its grounded state.
+pub fn move_and_slide(
+    world: &mut World,
+    entity: Entity,
+    velocity: Vec2,
+    dt: f64,
+) -> Result<CharacterMove> {
+    let (Some(start), Some(collider), Some(controller)) = (
+        world.get::<Position>(entity).map(|p| p.0),
+        world.get::<Collider>(entity).map(|c| c.clone()),
+        world.get::<CharacterController>(entity).map(|c| *c),
+    ) else {
+        return Err(EcsError::NoSuchEntity(entity).into());
+    };
+    let mut filter = QueryFilter {
+        mask: collider.mask,
+        layer: collider.layer,
+        exclude: world.query::<&TriggerVolume>().entities(),
+    };
+    filter.exclude.push(entity);
+    let mover = Mover {
+        world,
+        entity,
+        collider,
+        filter,
+        controller,
+    };
+
+    let mut position = start;
+    let mut remaining = velocity * dt;
+    let mut hits = Vec::new();
+    let mut ground = None;
+    for _ in 0..ITERATIONS {
+        if remaining.length() < f64::EPSILON {
+            break;
+        }
+        let (reached, hit) = mover.advance(position, remaining);
+        let Some(hit) = hit else {
+            position = reached;
+            break;
+        };
+        let travelled = (reached - position).length();
+        let left = remaining * (1.0 - travelled / remaining.length()).max(0.0);
+        position = reached;
+        hits.push(hit);
+        if controller.walkable(hit.normal) {
+            ground = Some(hit.normal);
+        } else if left.x.abs() > f64::EPSILON
+            && controller.step_offset > 0.0
+            && let Some(stepped) = mover.step_up(position, Vec2::new(left.x, 0.0))
+        {
+            position = stepped;
+            remaining = Vec2::new(0.0, left.y.min(0.0));
+            continue;
+        }
+        let mut slide = left - hit.normal * left.dot(hit.normal);
+        if !controller.walkable(hit.normal) && slide.y > 0.0 {
+            // Too steep to walk up: keep only the sideways part.
+            slide.y = 0.0;
+            slide = slide - hit.normal * slide.dot(hit.normal).min(0.0);
+        }
+        remaining = slide;
+    }
+
+    if ground.is_none()
+        && let Some(hit) = mover.cast(position, Vec2::new(0.0, -GROUND_PROBE))
+        && controller.walkable(hit.normal)
+    {
+        ground = Some(hit.normal);
+    }
+
+    if let Some(mut p) = world.get_mut::<Position>(entity) {
+        p.0 = position;
+    }
+    if let Some(mut state) = world.get_mut::<CharacterController>(entity) {
+        state.grounded = ground.is_some();
+        state.ground_normal = ground.unwrap_or_default();
+    }
+    Ok(CharacterMove {
+        moved: position - start,
+        grounded: ground.is_some(),
+        hits,
+    })
+}
+
+/// Queues character moves from scripts as `controller:move(entity,
+/// { x = vx, y = vy })`; `apply` carries them out with the frame's `dt`.
+/// Clones share one queue.
+#[derive(Clone, Default)]
+pub struct Controllers {
+    pending: Rc<RefCell<Vec<(Entity, Vec2)>>>,
+}
+
+impl Controllers {
+    pub fn new() -> Self {
+        Controllers::default()
+    }
+
+    /// Moves every queued entity, a later move of the same entity
+    /// replacing an earlier one. Despawned entities are skipped.
+    pub fn apply(&self, world: &mut World, dt: f64) -> Result<usize> {
+        let mut pending = std::mem::take(&mut *self.pending.borrow_mut());
+        pending.reverse();
+        let mut seen = Vec::new();
+        for (entity, velocity) in pending {
+            if seen.contains(&entity) || !world.is_alive(entity) {
+                continue;
+            }
+            seen.push(entity);
+            move_and_slide(world, entity, velocity, dt)?;
+        }
+        Ok(seen.len())
+    }
+
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("controller", self.clone())
+    }
+}
+
+impl UserData for Controllers {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("move", |lua, this, (entity, velocity): (Entity, Value)| {
+            let velocity: Vec2 = lua.from_value(velocity)?;
+            this.pending.borrow_mut().push((entity, velocity));
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::Shape;
+
+    fn solid(world: &mut World, center: Vec2, half_width: f64, half_height: f64) {
+        let entity = world.spawn();
+        world.insert(entity, Position(center)).unwrap();
+        let collider = Collider {
+            is_static: true,
+            ..Collider::new(Shape::Box {
+                half_width,
+                half_height,
+            })
+        };
+        world.insert(entity, collider).unwrap();
+    }
+
+    #[test]
+    fn test_walks_steps_and_stops_at_walls() -> Result<()> {
+        let mut world = World::new();
+        // Floor, a low ledge starting at x = 2 and a wall at x = 6.
+        solid(&mut world, Vec2::new(0.0, -0.5), 20.0, 0.5);
+        solid(&mut world, Vec2::new(4.0, 0.1), 2.0, 0.1);
+        solid(&mut world, Vec2::new(6.5, 2.0), 0.5, 2.0);
+        let player = world.spawn();
+        world.insert(player, Position(Vec2::new(0.0, 0.6)))?;
+        world.insert(player, Collider::new(Shape::Circle { radius: 0.5 }))?;
+        world.insert(player, CharacterController::default())?;
+
+        let lua = Lua::new();
+        let controllers = Controllers::new();
+        controllers.register_lua(&lua)?;
+        lua.load("local player = ... controller:move(player, { x = 0, y = -2 })")
+            .call::<()>(player)?;
+        assert_eq!(controllers.apply(&mut world, 0.1)?, 1);
+        let state = *world.get::<CharacterController>(player).unwrap();
+        assert!(state.grounded);
+        assert_eq!(state.ground_normal, Vec2::new(0.0, 1.0));
+        assert!((world.get::<Position>(player).unwrap().0.y - 0.51).abs() < 1e-6);
+
+        // Walking right climbs the 0.2 ledge, then slides to a stop at
+        // the wall.
+        for _ in 0..20 {
+            move_and_slide(&mut world, player, Vec2::new(5.0, -1.0), 0.1)?;
+        }
+        let p = world.get::<Position>(player).unwrap().0;
+        assert!((p.y - 0.71).abs() < 1e-3, "{:?}", p);
+        assert!((p.x - 5.49).abs() < 1e-3, "{:?}", p);
+        assert!(world.get::<CharacterController>(player).unwrap().grounded);
+
+        // Starting flush against the floor, walking still moves.
+        world.get_mut::<Position>(player).unwrap().0 = Vec2::new(0.0, 0.5);
+        let moved = move_and_slide(&mut world, player, Vec2::new(5.0, 0.0), 0.1)?;
+        assert!((moved.moved.x - 0.5).abs() < 1e-6, "{:?}", moved.moved);
+        assert!(moved.grounded);
+
+        // Walking off into the air is not grounded.
+        world.get_mut::<Position>(player).unwrap().0 = Vec2::new(0.0, 3.0);
+        let moved = move_and_slide(&mut world, player, Vec2::new(1.0, 0.0), 0.1)?;
+        assert!(!moved.grounded && moved.hits.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+use super::{Contact, Velocity};
+use crate::ecs::{Entity, World};
+use crate::math::Vec2;
+use mlua::{Function, Lua, Result, Table, Value};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A solid contact about to be resolved. Hooks may change any of it:
+/// scale `depth` down for a soft wall, zero `friction` for ice, or veto
+/// the contact altogether.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreSolve {
+    pub contact: Contact,
+    pub friction: f64,
+    pub restitution: f64,
+}
+
+pub type PreSolveFn = Rc<dyn Fn(&World, &mut PreSolve) -> bool>;
+
+#[derive(Clone)]
+enum PreSolveHook {
+    Rust(PreSolveFn),
+    /// Called as `f(world, contact)` with `contact` a table of `a`, `b`,
+    /// `nx`, `ny`, `depth`, `friction` and `restitution`; edits to the
+    /// table are kept, and returning false vetoes the contact.
+    Lua(Function),
+}
+
+/// Pre-solve callbacks for `physics::step_with`, run in the order they
+/// were added; the first veto wins. Clones share one list.
+#[derive(Clone, Default)]
+pub struct ContactHooks {
+    hooks: Rc<RefCell<Vec<PreSolveHook>>>,
+}
+
+impl ContactHooks {
+    pub fn new() -> Self {
+        ContactHooks::default()
+    }
+
+    pub fn on_pre_solve(&self, hook: impl Fn(&World, &mut PreSolve) -> bool + 'static) {
+        self.hooks
+            .borrow_mut()
+            .push(PreSolveHook::Rust(Rc::new(hook)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.borrow().is_empty()
+    }
+
+    /// Whether the contact should still be resolved.
+    pub(crate) fn pre_solve(
+        &self,
+        world: &mut World,
+        lua: &Lua,
+        solve: &mut PreSolve,
+    ) -> Result<bool> {
+        let hooks = self.hooks.borrow().clone();
+        for hook in hooks {
+            let keep = match hook {
+                PreSolveHook::Rust(hook) => hook(world, solve),
+                PreSolveHook::Lua(function) => {
+                    let table = to_table(lua, solve)?;
+                    let keep = lua.scope(|scope| {
+                        let handle = scope.create_userdata_ref_mut(&mut *world)?;
+                        function.call::<Value>((handle, &table))
+                    })?;
+                    from_table(&table, solve)?;
+                    !matches!(keep, Value::Boolean(false))
+                }
+            };
+            if !keep {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Adds `physics.on_pre_solve(f)`.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        let physics = match lua.globals().get::<Value>("physics")? {
+            Value::Table(table) => table,
+            _ => lua.create_table()?,
+        };
+        let this = self.clone();
+        physics.set(
+            "on_pre_solve",
+            lua.create_function(move |_, hook: Function| {
+                this.hooks.borrow_mut().push(PreSolveHook::Lua(hook));
+                Ok(())
+            })?,
+        )?;
+        lua.globals().set("physics", physics)
+    }
+}
+
+fn to_table(lua: &Lua, solve: &PreSolve) -> Result<Table> {
+    let table = lua.create_table()?;
+    table.set("a", solve.contact.a)?;
+    table.set("b", solve.contact.b)?;
+    table.set("nx", solve.contact.normal.x)?;
+    table.set("ny", solve.contact.normal.y)?;
+    table.set("depth", solve.contact.depth)?;
+    table.set("friction", solve.friction)?;
+    table.set("restitution", solve.restitution)?;
+    Ok(table)
+}
+
+fn from_table(table: &Table, solve: &mut PreSolve) -> Result<()> {
+    solve.contact.normal = Vec2::new(table.get("nx")?, table.get("ny")?);
+    solve.contact.depth = table.get("depth")?;
+    solve.friction = table.get("friction")?;
+    solve.restitution = table.get("restitution")?;
+    Ok(())
+}
+
+/// A platform bodies can jump up through and stand on: contacts only hold
+/// when the other body is on the `up` side and not moving away from it.
+/// Works through `one_way_platforms`, added as a pre-solve hook.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OneWayPlatform {
+    pub up: Vec2,
+}
+
+impl Default for OneWayPlatform {
+    fn default() -> Self {
+        OneWayPlatform {
+            up: Vec2::new(0.0, 1.0),
+        }
+    }
+}
+
+pub fn one_way_platforms(world: &World, solve: &mut PreSolve) -> bool {
+    let contact = solve.contact;
+    let (platform, other, out) = match (
+        world.get::<OneWayPlatform>(contact.a),
+        world.get::<OneWayPlatform>(contact.b),
+    ) {
+        (Some(platform), _) => (*platform, contact.b, contact.normal),
+        (_, Some(platform)) => (*platform, contact.a, contact.normal * -1.0),
+        _ => return true,
+    };
+    let up = platform.up.normalize_or_zero();
+    let rising = velocity(world, other).dot(up) > 0.0;
+    // Mostly vertical separation, so bodies overlapping from the side or
+    // halfway through pass on.
+    out.dot(up) > 0.7 && !rising
+}
+
+fn velocity(world: &World, entity: Entity) -> Vec2 {
+    world
+        .get::<Velocity>(entity)
+        .map(|v| v.0)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::{Collider, Position, Shape, step_with};
+
+    fn body(world: &mut World, at: Vec2, velocity: Vec2) -> Entity {
+        let entity = world.spawn();
+        world.insert(entity, Position(at)).unwrap();
+        world.insert(entity, Velocity(velocity)).unwrap();
+        world
+            .insert(entity, Collider::new(Shape::Circle { radius: 0.5 }))
+            .unwrap();
+        entity
+    }
+
+    #[test]
+    fn test_one_way_platform_and_lua_veto() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let platform = world.spawn();
+        world.insert(platform, Position(Vec2::ZERO))?;
+        let slab = Shape::Box {
+            half_width: 5.0,
+            half_height: 0.25,
+        };
+        world.insert(
+            platform,
+            Collider {
+                is_static: true,
+                ..Collider::new(slab)
+            },
+        )?;
+        world.insert(platform, OneWayPlatform::default())?;
+
+        let hooks = ContactHooks::new();
+        hooks.on_pre_solve(one_way_platforms);
+        hooks.register_lua(&lua)?;
+        lua.load(
+            r#"
+            physics.on_pre_solve(function(world, contact)
+                if world:get(contact.a, "Ghost") or world:get(contact.b, "Ghost") then
+                    return false
+                end
+                contact.restitution = 0
+            end)
+        "#,
+        )
+        .exec()?;
+
+        let falling = body(&mut world, Vec2::new(-3.0, 0.8), Vec2::new(0.0, -2.0));
+        let jumping = body(&mut world, Vec2::new(0.0, -0.8), Vec2::new(0.0, 2.0));
+        let ghost = body(&mut world, Vec2::new(3.0, 0.8), Vec2::new(0.0, -2.0));
+        world.set_script_component(ghost, "Ghost", crate::ecs::ScriptValue::Bool(true))?;
+
+        let contacts = step_with(&mut world, &lua, 0.1, &hooks)?;
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].b, falling);
+        let y = |entity| world.get::<Position>(entity).unwrap().0.y;
+        assert!((y(falling) - 0.75).abs() < 1e-9);
+        assert_eq!(world.get::<Velocity>(falling).unwrap().0, Vec2::ZERO);
+        assert!((y(jumping) - -0.6).abs() < 1e-9);
+        assert!((y(ghost) - 0.6).abs() < 1e-9);
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+use super::{Collider, PhysicsMaterials, Position, Velocity};
+use crate::ecs::{Entity, World};
+use crate::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Solver passes over every joint per step; more is stiffer chains.
+const ITERATIONS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum JointKind {
+    /// Keeps the anchors `length` apart; a `rope` only stops stretching.
+    Distance {
+        length: f64,
+        #[serde(default)]
+        rope: bool,
+    },
+    /// Pins the anchors together, leaving the bodies free to swing
+    /// around the shared point.
+    Revolute,
+    /// Lets the anchors slide apart only along `axis`, optionally within
+    /// `min..=max` of each other.
+    Prismatic {
+        axis: Vec2,
+        #[serde(default)]
+        min: Option<f64>,
+        #[serde(default)]
+        max: Option<f64>,
+    },
+}
+
+/// A constraint between two bodies, kept on its own entity so prefabs and
+/// scripts create joints like any other component, e.g.
+/// `{ Joint = { kind = "Distance", a = door, b = frame, length = 2 } }`.
+/// Anchors are offsets from each body's position. Joints whose bodies are
+/// gone are skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Joint {
+    pub a: Entity,
+    pub b: Entity,
+    #[serde(default)]
+    pub anchor_a: Vec2,
+    #[serde(default)]
+    pub anchor_b: Vec2,
+    #[serde(flatten)]
+    pub kind: JointKind,
+}
+
+impl Joint {
+    pub fn new(a: Entity, b: Entity, kind: JointKind) -> Self {
+        Joint {
+            a,
+            b,
+            anchor_a: Vec2::ZERO,
+            anchor_b: Vec2::ZERO,
+            kind,
+        }
+    }
+
+    /// The correction moving anchor `b` relative to anchor `a`.
+    fn error(&self, from: Vec2, to: Vec2) -> Vec2 {
+        let d = to - from;
+        match self.kind {
+            JointKind::Distance { length, rope } => {
+                let distance = d.length();
+                if distance < f64::EPSILON || (rope && distance <= length) {
+                    return Vec2::ZERO;
+                }
+                d * ((distance - length) / distance)
+            }
+            JointKind::Revolute => d,
+            JointKind::Prismatic { axis, min, max } => {
+                let axis = axis.normalize_or_zero();
+                let along = d.dot(axis);
+                let clamped = along
+                    .max(min.unwrap_or(f64::NEG_INFINITY))
+                    .min(max.unwrap_or(f64::INFINITY));
+                d - axis * clamped
+            }
+        }
+    }
+}
+
+/// How much of a correction a body takes: its inverse mass, so static
+/// colliders take none. Bodies without a collider weigh one.
+fn weight(world: &World, materials: &PhysicsMaterials, entity: Entity) -> Option<f64> {
+    world.get::<Position>(entity)?;
+    Some(match world.get::<Collider>(entity) {
+        Some(collider) => materials.inverse_mass(&collider),
+        None => 1.0,
+    })
+}
+
+/// Moves jointed bodies back into their constraints and takes the
+/// correction out of their velocities.
+pub fn solve_joints(world: &mut World, dt: f64) {
+    let joints: Vec<Joint> = world
+        .query::<&Joint>()
+        .entities()
+        .into_iter()
+        .filter_map(|entity| world.get::<Joint>(entity).map(|joint| *joint))
+        .collect();
+    if joints.is_empty() {
+        return;
+    }
+    let materials = world
+        .resource::<PhysicsMaterials>()
+        .cloned()
+        .unwrap_or_default();
+    for _ in 0..ITERATIONS {
+        for joint in &joints {
+            let (Some(wa), Some(wb)) = (
+                weight(world, &materials, joint.a),
+                weight(world, &materials, joint.b),
+            ) else {
+                continue;
+            };
+            if wa + wb == 0.0 {
+                continue;
+            }
+            let pa = world
+                .get::<Position>(joint.a)
+                .map(|p| p.0)
+                .unwrap_or_default();
+            let pb = world
+                .get::<Position>(joint.b)
+                .map(|p| p.0)
+                .unwrap_or_default();
+            let error = joint.error(pa + joint.anchor_a, pb + joint.anchor_b);
+            for (entity, share) in [(joint.a, wa / (wa + wb)), (joint.b, -wb / (wa + wb))] {
+                if share == 0.0 {
+                    continue;
+                }
+                let push = error * share;
+                if let Some(mut position) = world.get_mut::<Position>(entity) {
+                    position.0 = position.0 + push;
+                }
+                if dt > 0.0
+                    && let Some(mut velocity) = world.get_mut::<Velocity>(entity)
+                {
+                    velocity.0 = velocity.0 + push * (1.0 / dt);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::{Shape, register_components, step};
+    use mlua::Lua;
+
+    #[test]
+    fn test_joints_hold_bodies_from_lua() -> mlua::Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        register_components(&mut world);
+        let anchor = world.spawn();
+        world.insert(anchor, Position(Vec2::ZERO))?;
+        world.insert(
+            anchor,
+            Collider {
+                is_static: true,
+                ..Collider::new(Shape::Circle { radius: 0.1 })
+            },
+        )?;
+        let (weight, slider): (Entity, Entity) = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world, anchor = ...
+                local weight = world:spawn({
+                    Position = { x = 0, y = 3 }, Velocity = { x = 5, y = 0 },
+                })
+                world:spawn({ Joint = { kind = "Distance", a = anchor, b = weight, length = 2 } })
+                local slider = world:spawn({
+                    Position = { x = 0, y = -1 }, Velocity = { x = 3, y = 4 },
+                })
+                world:spawn({
+                    Joint = { kind = "Prismatic", a = anchor, b = slider, axis = { x = 1, y = 0 }, max = 1 },
+                })
+                return weight, slider
+            "#,
+            )
+            .call((handle, anchor))
+        })?;
+
+        for _ in 0..10 {
+            step(&mut world, 0.1);
+        }
+        let p = world.get::<Position>(weight).unwrap().0;
+        assert!((p.length() - 2.0).abs() < 1e-6, "{:?}", p);
+        let s = world.get::<Position>(slider).unwrap().0;
+        assert!(s.y.abs() < 1e-9 && (s.x - 1.0).abs() < 1e-9, "{:?}", s);
+        assert_eq!(world.get::<Position>(anchor).unwrap().0, Vec2::ZERO);
+
+        let door = world.spawn();
+        world.insert(door, Position(Vec2::new(3.0, 3.0)))?;
+        let mut hinge = Joint::new(anchor, door, JointKind::Revolute);
+        hinge.anchor_a = Vec2::new(2.0, 0.0);
+        hinge.anchor_b = Vec2::new(-0.5, 0.0);
+        let joint = world.spawn();
+        world.insert(joint, hinge)?;
+        step(&mut world, 0.1);
+        let p = world.get::<Position>(door).unwrap().0;
+        assert!((p - Vec2::new(2.5, 0.0)).length() < 1e-9, "{:?}", p);
+        Ok(())
+    }
+}
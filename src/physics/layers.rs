@@ -0,0 +1,91 @@
+use crate::data::{DataError, from_ron};
+use std::path::Path;
+
+pub const DEFAULT_LAYER: u32 = 1;
+pub const ALL_LAYERS: u32 = u32::MAX;
+
+/// Names for the 32 collision layer bits, loaded from a RON list where
+/// the first name is bit 0. Kept as a world resource so scripts can
+/// write masks like `"enemies|walls"`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollisionLayers {
+    names: Vec<String>,
+}
+
+impl CollisionLayers {
+    pub fn new(names: &[&str]) -> std::result::Result<Self, DataError> {
+        CollisionLayers::from_names(names.iter().map(|name| name.to_string()).collect())
+    }
+
+    fn from_names(names: Vec<String>) -> std::result::Result<Self, DataError> {
+        if names.len() > 32 {
+            return Err(DataError::Invalid(format!(
+                "{} collision layers, at most 32 fit in a mask",
+                names.len()
+            )));
+        }
+        if let Some(name) = names
+            .iter()
+            .enumerate()
+            .find_map(|(i, name)| names[..i].contains(name).then_some(name))
+        {
+            return Err(DataError::Invalid(format!(
+                "collision layer '{}' is named twice",
+                name
+            )));
+        }
+        Ok(CollisionLayers { names })
+    }
+
+    pub fn from_ron(source: &str) -> std::result::Result<Self, DataError> {
+        CollisionLayers::from_names(from_ron(source)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        CollisionLayers::from_ron(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn bit(&self, name: &str) -> Option<u32> {
+        let index = self.names.iter().position(|n| n == name)?;
+        Some(1 << index)
+    }
+
+    /// Parses `|`-separated layer names; `all` is every layer.
+    pub fn mask(&self, spec: &str) -> std::result::Result<u32, DataError> {
+        spec.split('|')
+            .map(str::trim)
+            .try_fold(0, |mask, name| match name {
+                "all" => Ok(ALL_LAYERS),
+                name => self.bit(name).map(|bit| mask | bit).ok_or_else(|| {
+                    DataError::Invalid(format!("unknown collision layer '{}'", name))
+                }),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masks_from_names() -> std::result::Result<(), DataError> {
+        let layers = CollisionLayers::from_ron(r#"["default", "enemies", "walls"]"#)?;
+        assert_eq!(layers.bit("walls"), Some(4));
+        assert_eq!(layers.bit("water"), None);
+        assert_eq!(layers.mask("enemies | walls")?, 6);
+        assert_eq!(layers.mask("walls|all")?, ALL_LAYERS);
+        let error = layers.mask("enemies|water").unwrap_err().to_string();
+        assert!(
+            error.contains("unknown collision layer 'water'"),
+            "{}",
+            error
+        );
+
+        assert!(CollisionLayers::new(&["a", "b", "a"]).is_err());
+        let names: Vec<String> = (0..33).map(|i| i.to_string()).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        assert!(CollisionLayers::new(&names[..32]).is_ok());
+        assert!(CollisionLayers::new(&names).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+use super::{Collider, Shape};
+use crate::data::{DataError, from_ron};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicsMaterial {
+    /// Share of sliding speed lost per unit of impact speed.
+    pub friction: f64,
+    /// Share of impact speed bounced back: 0 is dead, 1 is elastic.
+    pub restitution: f64,
+    /// Mass per unit of area.
+    pub density: f64,
+}
+
+impl Default for PhysicsMaterial {
+    fn default() -> Self {
+        PhysicsMaterial {
+            friction: 0.5,
+            restitution: 0.0,
+            density: 1.0,
+        }
+    }
+}
+
+/// How two touching materials' friction and restitution become one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CombineRule {
+    #[default]
+    Average,
+    Min,
+    Max,
+    Multiply,
+}
+
+impl CombineRule {
+    pub fn combine(self, a: f64, b: f64) -> f64 {
+        match self {
+            CombineRule::Average => (a + b) / 2.0,
+            CombineRule::Min => a.min(b),
+            CombineRule::Max => a.max(b),
+            CombineRule::Multiply => a * b,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct MaterialFile {
+    combine: CombineRule,
+    materials: BTreeMap<String, PhysicsMaterial>,
+}
+
+/// Named materials shared by colliders through `Collider::material`, kept
+/// as a world resource. Loaded from RON like
+/// `(combine: Min, materials: { "ice": (friction: 0.02) })`; because
+/// colliders only hold the name, a reload retunes every collider at once.
+/// Colliders without a known material use the default one.
+#[derive(Debug, Clone, Default)]
+pub struct PhysicsMaterials {
+    pub combine: CombineRule,
+    materials: BTreeMap<String, PhysicsMaterial>,
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+}
+
+impl PhysicsMaterials {
+    pub fn new() -> Self {
+        PhysicsMaterials::default()
+    }
+
+    pub fn from_ron(source: &str) -> std::result::Result<Self, DataError> {
+        let file: MaterialFile = from_ron(source)?;
+        Ok(PhysicsMaterials {
+            combine: file.combine,
+            materials: file.materials,
+            ..PhysicsMaterials::default()
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        let path = path.as_ref();
+        let mut materials = PhysicsMaterials::from_ron(&std::fs::read_to_string(path)?)?;
+        materials.modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        materials.path = Some(path.to_path_buf());
+        Ok(materials)
+    }
+
+    /// Reloads from the file it was loaded from if that changed since,
+    /// keeping the current materials if the new file fails to parse.
+    pub fn reload_if_changed(&mut self) -> std::result::Result<bool, DataError> {
+        let Some(path) = self.path.clone() else {
+            return Ok(false);
+        };
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == self.modified {
+            return Ok(false);
+        }
+        *self = PhysicsMaterials::load(&path)?;
+        Ok(true)
+    }
+
+    pub fn insert(&mut self, name: &str, material: PhysicsMaterial) {
+        self.materials.insert(name.to_string(), material);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PhysicsMaterial> {
+        self.materials.get(name)
+    }
+
+    pub fn of(&self, collider: &Collider) -> PhysicsMaterial {
+        collider
+            .material
+            .as_deref()
+            .and_then(|name| self.get(name))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Friction and restitution for a contact between the two colliders.
+    pub fn contact(&self, a: &Collider, b: &Collider) -> (f64, f64) {
+        let (a, b) = (self.of(a), self.of(b));
+        (
+            self.combine.combine(a.friction, b.friction),
+            self.combine.combine(a.restitution, b.restitution),
+        )
+    }
+
+    /// Zero for static colliders, which nothing can move.
+    pub fn inverse_mass(&self, collider: &Collider) -> f64 {
+        if collider.is_static {
+            return 0.0;
+        }
+        let area = match collider.shape {
+            Shape::Circle { radius } => std::f64::consts::PI * radius * radius,
+            Shape::Box { .. } => {
+                let half = collider.shape.half_extents();
+                4.0 * half.x * half.y
+            }
+        };
+        let mass = area * self.of(collider).density;
+        if mass > f64::EPSILON { 1.0 / mass } else { 0.0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::World;
+    use crate::math::Vec2;
+    use crate::physics::{Position, Velocity, step};
+
+    fn bounce(world: &mut World) -> Vec2 {
+        let ball = world.spawn();
+        world.insert(ball, Position(Vec2::new(1.0, 0.0))).unwrap();
+        world.insert(ball, Velocity(Vec2::new(2.0, 0.0))).unwrap();
+        let collider = Collider {
+            material: Some("rubber".to_string()),
+            ..Collider::new(Shape::Circle { radius: 0.5 })
+        };
+        world.insert(ball, collider).unwrap();
+        step(world, 0.05);
+        let velocity = world.get::<Velocity>(ball).unwrap().0;
+        world.despawn(ball);
+        velocity
+    }
+
+    #[test]
+    fn test_materials_bounce_and_reload() -> std::result::Result<(), DataError> {
+        let path = std::env::temp_dir().join(format!("materials_{}.ron", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"(combine: Max, materials: { "rubber": (restitution: 1.0, density: 2.0) })"#,
+        )?;
+        let mut world = World::new();
+        let wall = world.spawn();
+        world.insert(wall, Position(Vec2::new(2.0, 0.0))).unwrap();
+        let wall_collider = Collider {
+            is_static: true,
+            ..Collider::new(Shape::Box {
+                half_width: 0.5,
+                half_height: 2.0,
+            })
+        };
+        world.insert(wall, wall_collider).unwrap();
+        world.insert_resource(PhysicsMaterials::load(&path)?);
+
+        // Max combines the rubber's bounce with the wall's default of zero.
+        assert_eq!(bounce(&mut world), Vec2::new(-2.0, 0.0));
+
+        std::fs::write(
+            &path,
+            r#"(combine: Min, materials: { "rubber": (restitution: 1.0) })"#,
+        )?;
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(later))?;
+        let materials = world.resource_mut::<PhysicsMaterials>().unwrap();
+        assert!(materials.reload_if_changed()?);
+        assert!(!materials.reload_if_changed()?);
+        assert_eq!(materials.get("rubber").unwrap().density, 1.0);
+        assert_eq!(bounce(&mut world), Vec2::ZERO);
+        std::fs::remove_file(&path).ok();
+        Ok(())
+    }
+}
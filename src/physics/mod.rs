@@ -0,0 +1,126 @@
+mod character;
+mod contact;
+mod joint;
+mod layers;
+mod material;
+mod query;
+mod spatial;
+mod step;
+#[cfg(feature = "3d")]
+pub mod three;
+
+pub use character::{CharacterController, CharacterMove, Controllers, move_and_slide};
+pub use contact::{ContactHooks, OneWayPlatform, PreSolve, PreSolveFn, one_way_platforms};
+pub use joint::{Joint, JointKind, solve_joints};
+pub use layers::{ALL_LAYERS, CollisionLayers, DEFAULT_LAYER};
+pub use material::{CombineRule, PhysicsMaterial, PhysicsMaterials};
+pub use query::{QueryFilter, RayHit, raycast, shape_cast};
+pub use spatial::{Aabb, Bounds, SpatialGrid, SpatialIndex};
+pub use step::{Contact, TriggerEntered, TriggerExited, TriggerVolume, overlap, step, step_with};
+
+use crate::ecs::World;
+use crate::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Position(pub Vec2);
+
+/// Units per second.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Velocity(pub Vec2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Shape {
+    Box { half_width: f64, half_height: f64 },
+    Circle { radius: f64 },
+}
+
+impl Shape {
+    /// Distance from `point` to the shape placed at `center`; zero inside.
+    pub fn distance(&self, center: Vec2, point: Vec2) -> f64 {
+        let d = point - center;
+        match *self {
+            Shape::Box {
+                half_width,
+                half_height,
+            } => {
+                let outside = Vec2::new(
+                    (d.x.abs() - half_width).max(0.0),
+                    (d.y.abs() - half_height).max(0.0),
+                );
+                outside.length()
+            }
+            Shape::Circle { radius } => (d.length() - radius).max(0.0),
+        }
+    }
+
+    pub fn half_extents(&self) -> Vec2 {
+        match *self {
+            Shape::Box {
+                half_width,
+                half_height,
+            } => Vec2::new(half_width, half_height),
+            Shape::Circle { radius } => Vec2::new(radius, radius),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Collider {
+    pub shape: Shape,
+    /// Static colliders never move; the nav grid is baked from them.
+    #[serde(default)]
+    pub is_static: bool,
+    /// The layer bits this collider is on.
+    #[serde(default = "default_layer")]
+    pub layer: u32,
+    /// The layers it collides with and is seen by.
+    #[serde(default = "all_layers")]
+    pub mask: u32,
+    /// Sweeps the shape along its motion each step instead of only testing
+    /// where it ends up, so fast bodies can't pass through thin colliders.
+    #[serde(default)]
+    pub ccd: bool,
+    /// Name of a shared material in the world's `PhysicsMaterials`.
+    #[serde(default)]
+    pub material: Option<String>,
+}
+
+fn default_layer() -> u32 {
+    DEFAULT_LAYER
+}
+
+fn all_layers() -> u32 {
+    ALL_LAYERS
+}
+
+impl Collider {
+    /// A moving collider on the default layer that hits everything.
+    pub fn new(shape: Shape) -> Self {
+        Collider {
+            shape,
+            is_static: false,
+            layer: DEFAULT_LAYER,
+            mask: ALL_LAYERS,
+            ccd: false,
+            material: None,
+        }
+    }
+
+    /// Whether the two collide: each must be on a layer the other masks.
+    pub fn interacts(&self, other: &Collider) -> bool {
+        self.layer & other.mask != 0 && other.layer & self.mask != 0
+    }
+}
+
+/// Registers `Position`, `Velocity`, `Collider`, `TriggerVolume`, `Joint`,
+/// `OneWayPlatform` and `CharacterController` under those names.
+pub fn register_components(world: &mut World) {
+    world.register_component::<Position>("Position");
+    world.register_component::<Velocity>("Velocity");
+    world.register_component::<Collider>("Collider");
+    world.register_component::<TriggerVolume>("TriggerVolume");
+    world.register_component::<Joint>("Joint");
+    world.register_component::<OneWayPlatform>("OneWayPlatform");
+    world.register_component::<CharacterController>("CharacterController");
+}
@@ -0,0 +1,323 @@
+use super::{ALL_LAYERS, Aabb, Collider, Position, Shape, SpatialIndex};
+use crate::ecs::{Entity, World};
+use crate::math::Vec2;
+use std::ops::{Add, Mul, Sub};
+
+/// Which colliders a query can see.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryFilter {
+    pub mask: u32,
+    /// The querying body's layer; colliders whose mask lacks it are skipped.
+    pub layer: u32,
+    /// Typically the shooter, so a projectile's ray skips its own body.
+    pub exclude: Vec<Entity>,
+}
+
+impl Default for QueryFilter {
+    fn default() -> Self {
+        QueryFilter {
+            mask: ALL_LAYERS,
+            layer: ALL_LAYERS,
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl QueryFilter {
+    pub fn mask(mask: u32) -> Self {
+        QueryFilter {
+            mask,
+            ..QueryFilter::default()
+        }
+    }
+
+    pub fn exclude(mut self, entity: Entity) -> Self {
+        self.exclude.push(entity);
+        self
+    }
+
+    pub fn accepts(&self, entity: Entity, collider: &Collider) -> bool {
+        self.accepts_layers(entity, collider.layer, collider.mask)
+    }
+
+    pub(crate) fn accepts_layers(&self, entity: Entity, layer: u32, mask: u32) -> bool {
+        layer & self.mask != 0 && mask & self.layer != 0 && !self.exclude.contains(&entity)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit<V = Vec2> {
+    pub entity: Entity,
+    pub point: V,
+    /// Surface normal at the hit, facing back along the ray.
+    pub normal: V,
+    pub distance: f64,
+}
+
+/// What the ray tests need from a vector, so 2D and 3D share them.
+pub(crate) trait Vector:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f64, Output = Self>
+{
+    const ZERO: Self;
+    /// The unit vectors along each axis.
+    fn axes() -> impl Iterator<Item = Self>;
+    fn dot(self, other: Self) -> f64;
+    fn normalize_or_zero(self) -> Self;
+}
+
+impl Vector for Vec2 {
+    const ZERO: Vec2 = Vec2::ZERO;
+
+    fn axes() -> impl Iterator<Item = Vec2> {
+        [Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0)].into_iter()
+    }
+
+    fn dot(self, other: Vec2) -> f64 {
+        Vec2::dot(self, other)
+    }
+
+    fn normalize_or_zero(self) -> Vec2 {
+        Vec2::normalize_or_zero(self)
+    }
+}
+
+/// A shape as a box of `half` extents rounded by `radius`: circles are
+/// zero-size boxes, so the sum of two shapes is another rounded box.
+fn rounded(shape: &Shape) -> (Vec2, f64) {
+    match *shape {
+        Shape::Box { .. } => (shape.half_extents(), 0.0),
+        Shape::Circle { radius } => (Vec2::ZERO, radius),
+    }
+}
+
+pub(super) fn ray_box<V: Vector>(local: V, direction: V, half: V) -> Option<(f64, V)> {
+    let mut near = f64::NEG_INFINITY;
+    let mut far = f64::INFINITY;
+    let mut normal = V::ZERO;
+    for axis in V::axes() {
+        let (start, step, half) = (local.dot(axis), direction.dot(axis), half.dot(axis));
+        if step.abs() < f64::EPSILON {
+            if start.abs() > half {
+                return None;
+            }
+            continue;
+        }
+        let (mut t0, mut t1) = ((-half - start) / step, (half - start) / step);
+        if t0 > t1 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+        if t0 > near {
+            near = t0;
+            normal = axis * -step.signum();
+        }
+        far = far.min(t1);
+    }
+    (near <= far && near >= 0.0).then_some((near, normal))
+}
+
+/// A circle, or a sphere in 3D.
+pub(super) fn ray_ball<V: Vector>(local: V, direction: V, radius: f64) -> Option<(f64, V)> {
+    let b = local.dot(direction);
+    let discriminant = b * b - (local.dot(local) - radius * radius);
+    if b > 0.0 || discriminant < 0.0 {
+        return None;
+    }
+    let t = -b - discriminant.sqrt();
+    Some((t, (local + direction * t).normalize_or_zero()))
+}
+
+/// The nearest of `candidates` within `max_distance`, given each one's
+/// distance and normal along the ray, if it is hit at all.
+pub(super) fn nearest_hit<V: Vector>(
+    candidates: Vec<Entity>,
+    origin: V,
+    direction: V,
+    max_distance: f64,
+    mut hit: impl FnMut(Entity) -> Option<(f64, V)>,
+) -> Option<RayHit<V>> {
+    candidates
+        .into_iter()
+        .filter_map(|entity| {
+            let (distance, normal) = hit(entity)?;
+            (distance <= max_distance).then(|| RayHit {
+                entity,
+                point: origin + direction * distance,
+                normal,
+                distance,
+            })
+        })
+        .min_by(|a, b| a.distance.total_cmp(&b.distance))
+}
+
+/// Distance along the unit `direction` from `local`, relative to the
+/// center, to a box of `half` extents rounded by `radius`, with the normal
+/// there. A ray starting inside hits at zero.
+fn sweep(local: Vec2, direction: Vec2, half: Vec2, radius: f64) -> Option<(f64, Vec2)> {
+    let outside = Vec2::new(
+        (local.x.abs() - half.x).max(0.0),
+        (local.y.abs() - half.y).max(0.0),
+    );
+    if outside.length() <= radius {
+        // Starting in contact only blocks motion further in; inside, the
+        // nearest face is the way out.
+        let closest = Vec2::new(
+            local.x.clamp(-half.x, half.x),
+            local.y.clamp(-half.y, half.y),
+        );
+        let gap = half - Vec2::new(local.x.abs(), local.y.abs());
+        let normal = match (local - closest).normalize_or_zero() {
+            Vec2::ZERO if gap.x < gap.y => Vec2::new(local.x.signum(), 0.0),
+            Vec2::ZERO if gap.y < gap.x => Vec2::new(0.0, local.y.signum()),
+            Vec2::ZERO => direction * -1.0,
+            normal => normal,
+        };
+        return (direction.dot(normal) < 0.0).then_some((0.0, normal));
+    }
+    let faces = [
+        ray_box(local, direction, half + Vec2::new(radius, 0.0)),
+        ray_box(local, direction, half + Vec2::new(0.0, radius)),
+    ];
+    let corners = [(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)].map(|(x, y)| {
+        let corner = Vec2::new(half.x * x, half.y * y);
+        ray_ball(local - corner, direction, radius)
+    });
+    faces
+        .into_iter()
+        .flatten()
+        .filter(|&(t, _)| {
+            // A face hit past the rounded corner really hits the corner.
+            let point = local + direction * t;
+            point.x.abs() <= half.x + radius + 1e-9
+                && point.y.abs() <= half.y + radius + 1e-9
+                && (point.x.abs() <= half.x + 1e-9 || point.y.abs() <= half.y + 1e-9)
+        })
+        .chain(corners.into_iter().flatten())
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+}
+
+/// Moves `shape` from `origin` along `direction` for up to `max_distance`
+/// and returns the first collider on `filter` it touches. The hit's
+/// `point` is where the shape's center is at that moment. Colliders it
+/// starts touching or inside count at distance 0, unless the motion
+/// leaves them.
+pub fn shape_cast(
+    world: &World,
+    shape: &Shape,
+    origin: Vec2,
+    direction: Vec2,
+    max_distance: f64,
+    filter: &QueryFilter,
+) -> Option<RayHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec2::ZERO {
+        return None;
+    }
+    let (half, radius) = rounded(shape);
+    let reach = half + Vec2::new(radius, radius);
+    let end = origin + direction * max_distance;
+    let area = Aabb::new(
+        Vec2::new(origin.x.min(end.x), origin.y.min(end.y)) - reach,
+        Vec2::new(origin.x.max(end.x), origin.y.max(end.y)) + reach,
+    );
+    SpatialIndex::with(world, |index| {
+        nearest_hit(
+            index.query_aabb(area),
+            origin,
+            direction,
+            max_distance,
+            |entity| {
+                let position = world.get::<Position>(entity)?;
+                let collider = world.get::<Collider>(entity)?;
+                if !filter.accepts(entity, &collider) {
+                    return None;
+                }
+                let (other_half, other_radius) = rounded(&collider.shape);
+                sweep(
+                    origin - position.0,
+                    direction,
+                    half + other_half,
+                    radius + other_radius,
+                )
+            },
+        )
+    })
+}
+
+/// The nearest collider on `filter` within `max_distance` of `origin`
+/// along `direction`.
+pub fn raycast(
+    world: &World,
+    origin: Vec2,
+    direction: Vec2,
+    max_distance: f64,
+    filter: &QueryFilter,
+) -> Option<RayHit> {
+    let point = Shape::Circle { radius: 0.0 };
+    shape_cast(world, &point, origin, direction, max_distance, filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::CollisionLayers;
+    use mlua::Lua;
+
+    #[test]
+    fn test_raycast_respects_masks() -> mlua::Result<()> {
+        let layers = CollisionLayers::from_ron(r#"["default", "player", "enemies", "walls"]"#)?;
+        assert_eq!(layers.mask("enemies|walls")?, 0b1100);
+        assert!(layers.mask("ghosts").is_err());
+
+        let mut world = World::new();
+        let mut spawn = |x: f64, shape: Shape, layer: &str| {
+            let entity = world.spawn();
+            world.insert(entity, Position(Vec2::new(x, 0.0))).unwrap();
+            let collider = Collider {
+                layer: layers.bit(layer).unwrap(),
+                ..Collider::new(shape)
+            };
+            world.insert(entity, collider).unwrap();
+            entity
+        };
+        let shooter = spawn(0.0, Shape::Circle { radius: 0.5 }, "player");
+        let enemy = spawn(3.0, Shape::Circle { radius: 1.0 }, "enemies");
+        let wall = spawn(
+            6.0,
+            Shape::Box {
+                half_width: 0.5,
+                half_height: 4.0,
+            },
+            "walls",
+        );
+
+        let right = Vec2::new(1.0, 0.0);
+        let hit = raycast(&world, Vec2::ZERO, right, 10.0, &QueryFilter::default()).unwrap();
+        assert_eq!((hit.entity, hit.distance), (shooter, 0.0));
+        let filter = QueryFilter::mask(ALL_LAYERS).exclude(shooter);
+        let hit = raycast(&world, Vec2::ZERO, right, 10.0, &filter).unwrap();
+        assert_eq!(
+            (hit.entity, hit.distance, hit.normal),
+            (enemy, 2.0, Vec2::new(-1.0, 0.0))
+        );
+        let walls = QueryFilter::mask(layers.mask("walls")?);
+        let hit = raycast(&world, Vec2::new(0.0, 1.5), right, 10.0, &walls).unwrap();
+        assert_eq!((hit.entity, hit.point), (wall, Vec2::new(5.5, 1.5)));
+        assert!(raycast(&world, Vec2::ZERO, right, 5.0, &walls).is_none());
+
+        world.insert_resource(layers);
+        let lua = Lua::new();
+        let (hit, distance): (crate::ecs::Entity, f64) = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world, shooter = ...
+                local hit = world:raycast(0, 0, 1, 0, 10, { mask = "enemies|walls", exclude = shooter })
+                return hit.entity, hit.distance
+            "#,
+            )
+            .call((handle, shooter))
+        })?;
+        assert_eq!((hit, distance), (enemy, 2.0));
+        Ok(())
+    }
+}
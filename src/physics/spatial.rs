@@ -0,0 +1,275 @@
+use super::{Collider, Position};
+use crate::ecs::{Entity, World};
+use crate::math::Vec2;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// Axis-aligned bounds, `min` to `max` inclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Aabb {
+    pub fn new(min: Vec2, max: Vec2) -> Self {
+        Aabb { min, max }
+    }
+
+    pub fn around(center: Vec2, half_extents: Vec2) -> Self {
+        Aabb::new(center - half_extents, center + half_extents)
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.x >= self.min.x
+            && point.x <= self.max.x
+            && point.y >= self.min.y
+            && point.y <= self.max.y
+    }
+
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+    }
+}
+
+/// Bounds the spatial index can file entities under: `Aabb`, and `Aabb3`
+/// with the `3d` feature.
+pub trait Bounds: Copy + PartialEq + Send + Sync + 'static {
+    type Cell: Copy + Eq + Hash + Send + Sync + 'static;
+
+    fn overlaps(&self, other: &Self) -> bool;
+
+    /// Every cell of a `cell_size` grid the bounds touch.
+    fn cells(&self, cell_size: f64) -> impl Iterator<Item = Self::Cell>;
+
+    /// Calls `f` with the bounds of each collider of this dimension.
+    fn colliders(world: &World, f: impl FnMut(Entity, Self));
+}
+
+impl Bounds for Aabb {
+    type Cell = (i64, i64);
+
+    fn overlaps(&self, other: &Aabb) -> bool {
+        Aabb::overlaps(self, other)
+    }
+
+    fn cells(&self, cell_size: f64) -> impl Iterator<Item = (i64, i64)> {
+        let (x0, y0) = cell(self.min, cell_size);
+        let (x1, y1) = cell(self.max, cell_size);
+        (x0..=x1).flat_map(move |x| (y0..=y1).map(move |y| (x, y)))
+    }
+
+    fn colliders(world: &World, mut f: impl FnMut(Entity, Aabb)) {
+        world
+            .query::<(&Position, &Collider)>()
+            .for_each(|entity, (position, collider)| {
+                f(
+                    entity,
+                    Aabb::around(position.0, collider.shape.half_extents()),
+                );
+            });
+    }
+}
+
+fn cell(point: Vec2, cell_size: f64) -> (i64, i64) {
+    (
+        (point.x / cell_size).floor() as i64,
+        (point.y / cell_size).floor() as i64,
+    )
+}
+
+/// Uniform grid over collider bounds. `physics::step` keeps the world's
+/// index current; call `sync` after moving colliders anywhere else.
+/// Queries return candidates whose bounds overlap; exact shape tests are
+/// left to the caller.
+#[derive(Debug, Clone)]
+pub struct SpatialGrid<B: Bounds> {
+    pub cell_size: f64,
+    cells: HashMap<B::Cell, Vec<Entity>>,
+    bounds: HashMap<Entity, B>,
+}
+
+/// The 2D index over `Position` and `Collider`.
+pub type SpatialIndex = SpatialGrid<Aabb>;
+
+impl<B: Bounds> Default for SpatialGrid<B> {
+    fn default() -> Self {
+        SpatialGrid::new(4.0)
+    }
+}
+
+impl<B: Bounds> SpatialGrid<B> {
+    pub fn new(cell_size: f64) -> Self {
+        SpatialGrid {
+            cell_size: cell_size.max(f64::EPSILON),
+            cells: HashMap::new(),
+            bounds: HashMap::new(),
+        }
+    }
+
+    /// An index over every collider in the world.
+    pub fn build(world: &World, cell_size: f64) -> Self {
+        let mut index = SpatialGrid::new(cell_size);
+        index.rebuild(world);
+        index
+    }
+
+    /// Runs `f` with the world's index, or with one built for the call
+    /// when the world keeps none.
+    pub fn with<R>(world: &World, f: impl FnOnce(&Self) -> R) -> R {
+        match world.resource::<Self>() {
+            Some(index) => f(index),
+            None => f(&SpatialGrid::build(
+                world,
+                SpatialGrid::<B>::default().cell_size,
+            )),
+        }
+    }
+
+    pub fn rebuild(&mut self, world: &World) {
+        self.cells.clear();
+        self.bounds.clear();
+        B::colliders(world, |entity, bounds| self.insert(entity, bounds));
+    }
+
+    /// Re-files colliders whose bounds changed and drops entities that no
+    /// longer have one.
+    pub fn refresh(&mut self, world: &World) {
+        let mut seen = HashSet::new();
+        B::colliders(world, |entity, bounds| {
+            seen.insert(entity);
+            if self.bounds.get(&entity) != Some(&bounds) {
+                self.insert(entity, bounds);
+            }
+        });
+        let gone: Vec<Entity> = self
+            .bounds
+            .keys()
+            .filter(|entity| !seen.contains(entity))
+            .copied()
+            .collect();
+        for entity in gone {
+            self.remove(entity);
+        }
+    }
+
+    /// Refreshes the world's index, if it keeps one.
+    pub fn sync(world: &mut World) {
+        if let Some(mut index) = world.remove_resource::<Self>() {
+            index.refresh(world);
+            world.insert_resource(index);
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity, bounds: B) {
+        self.remove(entity);
+        for cell in bounds.cells(self.cell_size) {
+            self.cells.entry(cell).or_default().push(entity);
+        }
+        self.bounds.insert(entity, bounds);
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> bool {
+        let Some(bounds) = self.bounds.remove(&entity) else {
+            return false;
+        };
+        for cell in bounds.cells(self.cell_size) {
+            if let Some(cell) = self.cells.get_mut(&cell) {
+                cell.retain(|&e| e != entity);
+            }
+        }
+        true
+    }
+
+    pub fn bounds(&self, entity: Entity) -> Option<B> {
+        self.bounds.get(&entity).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bounds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_empty()
+    }
+
+    /// Entities whose bounds overlap `area`, each once, in entity order.
+    pub fn query_aabb(&self, area: B) -> Vec<Entity> {
+        let mut found: Vec<Entity> = area
+            .cells(self.cell_size)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(|entity| self.bounds[entity].overlaps(&area))
+            .collect();
+        found.sort();
+        found.dedup();
+        found
+    }
+}
+
+impl SpatialIndex {
+    /// Entities whose bounds contain `point`.
+    pub fn query_point(&self, point: Vec2) -> Vec<Entity> {
+        let Some(cell) = self.cells.get(&cell(point, self.cell_size)) else {
+            return Vec::new();
+        };
+        cell.iter()
+            .copied()
+            .filter(|entity| self.bounds[entity].contains(point))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::Shape;
+
+    #[test]
+    fn test_queries_follow_bounds() {
+        let mut world = World::new();
+        let small = world.spawn();
+        world.insert(small, Position(Vec2::new(1.0, 1.0))).unwrap();
+        world
+            .insert(small, Collider::new(Shape::Circle { radius: 0.5 }))
+            .unwrap();
+        let wide = world.spawn();
+        world.insert(wide, Position(Vec2::new(0.0, 0.0))).unwrap();
+        world
+            .insert(
+                wide,
+                Collider {
+                    is_static: true,
+                    ..Collider::new(Shape::Box {
+                        half_width: 10.0,
+                        half_height: 0.5,
+                    })
+                },
+            )
+            .unwrap();
+
+        let mut index = SpatialIndex::build(&world, 2.0);
+        assert_eq!(index.query_point(Vec2::new(1.2, 1.2)), vec![small]);
+        assert_eq!(index.query_point(Vec2::new(-9.0, 0.2)), vec![wide]);
+        assert_eq!(
+            index.query_aabb(Aabb::new(Vec2::new(0.0, 0.0), Vec2::new(2.0, 2.0))),
+            vec![small, wide]
+        );
+        index.remove(wide);
+        assert!(index.query_point(Vec2::new(-9.0, 0.2)).is_empty());
+        assert_eq!(index.len(), 1);
+
+        world.insert_resource(index);
+        world.get_mut::<Position>(small).unwrap().0 = Vec2::new(7.0, 7.0);
+        world.despawn(wide);
+        SpatialIndex::sync(&mut world);
+        let index = world.resource::<SpatialIndex>().unwrap();
+        assert!(index.query_point(Vec2::new(1.2, 1.2)).is_empty());
+        assert_eq!(index.query_point(Vec2::new(7.2, 7.2)), vec![small]);
+        assert_eq!(index.len(), 1);
+    }
+}
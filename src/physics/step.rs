@@ -0,0 +1,476 @@
+use super::{
+    Aabb, Collider, PhysicsMaterials, Position, QueryFilter, Shape, SpatialIndex, Velocity,
+    shape_cast, solve_joints,
+};
+use super::{CharacterController, ContactHooks, PreSolve};
+use crate::ecs::{Entity, World};
+use crate::math::Vec2;
+use mlua::{Lua, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Marks a collider as a trigger: it never pushes or is pushed, and
+/// instead reports `TriggerEntered`/`TriggerExited` as things overlap it.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct TriggerVolume;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriggerEntered {
+    pub trigger: Entity,
+    pub other: Entity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriggerExited {
+    pub trigger: Entity,
+    pub other: Entity,
+}
+
+/// Trigger overlaps seen by the last step, as `(trigger, other)`.
+#[derive(Debug, Clone, Default)]
+struct TriggerOverlaps(BTreeSet<(Entity, Entity)>);
+
+/// Two solid colliders overlapping after integration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contact {
+    pub a: Entity,
+    pub b: Entity,
+    /// Unit vector pointing from `a` towards `b`.
+    pub normal: Vec2,
+    pub depth: f64,
+}
+
+/// Normal from `a` to `b` and penetration depth, if the shapes overlap.
+pub fn overlap(a: &Shape, pa: Vec2, b: &Shape, pb: Vec2) -> Option<(Vec2, f64)> {
+    let d = pb - pa;
+    match (*a, *b) {
+        (Shape::Circle { radius: ra }, Shape::Circle { radius: rb }) => {
+            let distance = d.length();
+            let depth = ra + rb - distance;
+            if depth <= 0.0 {
+                return None;
+            }
+            let normal = if distance > 0.0 {
+                d * (1.0 / distance)
+            } else {
+                Vec2::new(1.0, 0.0)
+            };
+            Some((normal, depth))
+        }
+        (Shape::Box { .. }, Shape::Box { .. }) => {
+            let (ha, hb) = (a.half_extents(), b.half_extents());
+            let x = ha.x + hb.x - d.x.abs();
+            let y = ha.y + hb.y - d.y.abs();
+            if x <= 0.0 || y <= 0.0 {
+                return None;
+            }
+            let sign = |v: f64| if v < 0.0 { -1.0 } else { 1.0 };
+            if x < y {
+                Some((Vec2::new(sign(d.x), 0.0), x))
+            } else {
+                Some((Vec2::new(0.0, sign(d.y)), y))
+            }
+        }
+        (Shape::Circle { radius }, Shape::Box { .. }) => {
+            // Work from the box's side, then flip.
+            let half = b.half_extents();
+            let local = pa - pb;
+            let closest = Vec2::new(
+                local.x.clamp(-half.x, half.x),
+                local.y.clamp(-half.y, half.y),
+            );
+            if closest == local {
+                // The circle's center is inside the box: leave by the
+                // nearest face.
+                let x = half.x - local.x.abs();
+                let y = half.y - local.y.abs();
+                let sign = |v: f64| if v < 0.0 { -1.0 } else { 1.0 };
+                return if x < y {
+                    Some((Vec2::new(-sign(local.x), 0.0), x + radius))
+                } else {
+                    Some((Vec2::new(0.0, -sign(local.y)), y + radius))
+                };
+            }
+            let out = local - closest;
+            let distance = out.length();
+            (distance < radius).then(|| (out * (-1.0 / distance), radius - distance))
+        }
+        (Shape::Box { .. }, Shape::Circle { .. }) => {
+            overlap(b, pb, a, pa).map(|(normal, depth)| (normal * -1.0, depth))
+        }
+    }
+}
+
+/// Moves bodies by their velocity, then separates overlapping solid
+/// colliders and updates trigger overlaps, sending enter and exit events.
+/// Joints are solved after moving. Static colliders never move; `ccd`
+/// bodies stop where their sweep first touches a solid collider. Returns
+/// this step's solid contacts.
+pub fn step(world: &mut World, dt: f64) -> Vec<Contact> {
+    run(world, dt, &mut |_, _| Ok(true)).expect("no pre-solve hooks to fail")
+}
+
+/// `step`, letting `hooks` veto or change each solid contact before it is
+/// resolved.
+pub fn step_with(
+    world: &mut World,
+    lua: &Lua,
+    dt: f64,
+    hooks: &ContactHooks,
+) -> Result<Vec<Contact>> {
+    run(world, dt, &mut |world, pre_solve| {
+        hooks.pre_solve(world, lua, pre_solve)
+    })
+}
+
+fn run(
+    world: &mut World,
+    dt: f64,
+    pre_solve: &mut dyn FnMut(&mut World, &mut PreSolve) -> Result<bool>,
+) -> Result<Vec<Contact>> {
+    let mut swept = Vec::new();
+    world.query::<(&mut Position, &Velocity)>().for_each(
+        |entity, (position, velocity)| match world.get::<Collider>(entity).map(|c| c.clone()) {
+            Some(collider) if collider.is_static => {}
+            _ if world.get::<CharacterController>(entity).is_some() => {}
+            Some(collider) if collider.ccd && world.get::<TriggerVolume>(entity).is_none() => {
+                swept.push((entity, collider));
+            }
+            _ => position.0 = position.0 + velocity.0 * dt,
+        },
+    );
+
+    solve_joints(world, dt);
+
+    let mut index = world.remove_resource::<SpatialIndex>().unwrap_or_default();
+    index.rebuild(world);
+    world.insert_resource(index);
+    if !swept.is_empty() {
+        let triggers = world.query::<&TriggerVolume>().entities();
+        for (entity, collider) in swept {
+            sweep(world, entity, &collider, &triggers, dt);
+        }
+    }
+    let index = world
+        .remove_resource::<SpatialIndex>()
+        .expect("inserted above");
+
+    let mut pairs = Vec::new();
+    for entity in world.query::<(&Position, &Collider)>().entities() {
+        let bounds = index.bounds(entity).expect("indexed above");
+        for other in index.query_aabb(bounds) {
+            if entity < other {
+                pairs.push((entity, other));
+            }
+        }
+    }
+    world.insert_resource(index);
+
+    let materials = world
+        .resource::<PhysicsMaterials>()
+        .cloned()
+        .unwrap_or_default();
+    let mut contacts = Vec::new();
+    let mut overlaps = BTreeSet::new();
+    for (a, b) in pairs {
+        let (Some(ca), Some(cb)) = (
+            world.get::<Collider>(a).map(|c| c.clone()),
+            world.get::<Collider>(b).map(|c| c.clone()),
+        ) else {
+            continue;
+        };
+        if !ca.interacts(&cb) {
+            continue;
+        }
+        let pa = world.get::<Position>(a).map(|p| p.0).unwrap_or_default();
+        let pb = world.get::<Position>(b).map(|p| p.0).unwrap_or_default();
+        let Some((normal, depth)) = overlap(&ca.shape, pa, &cb.shape, pb) else {
+            continue;
+        };
+        let (ta, tb) = (
+            world.get::<TriggerVolume>(a).is_some(),
+            world.get::<TriggerVolume>(b).is_some(),
+        );
+        if ta || tb {
+            if ta {
+                overlaps.insert((a, b));
+            }
+            if tb {
+                overlaps.insert((b, a));
+            }
+            continue;
+        }
+        if ca.is_static && cb.is_static {
+            continue;
+        }
+        let (friction, restitution) = materials.contact(&ca, &cb);
+        let mut solve = PreSolve {
+            contact: Contact {
+                a,
+                b,
+                normal,
+                depth,
+            },
+            friction,
+            restitution,
+        };
+        if !pre_solve(world, &mut solve)? {
+            continue;
+        }
+        separate(world, &materials, &solve, &ca, &cb);
+        contacts.push(solve.contact);
+    }
+    // Separation moved bodies since the index was built.
+    SpatialIndex::sync(world);
+
+    let previous = world
+        .remove_resource::<TriggerOverlaps>()
+        .unwrap_or_default()
+        .0;
+    for &(trigger, other) in overlaps.difference(&previous) {
+        world.send_event(TriggerEntered { trigger, other });
+    }
+    for &(trigger, other) in previous.difference(&overlaps) {
+        world.send_event(TriggerExited { trigger, other });
+    }
+    world.insert_resource(TriggerOverlaps(overlaps));
+    Ok(contacts)
+}
+
+/// Gap left between a swept body and what it hit, so the next step
+/// doesn't start it overlapping.
+const SKIN: f64 = 1e-6;
+
+fn sweep(world: &mut World, entity: Entity, collider: &Collider, triggers: &[Entity], dt: f64) {
+    let (Some(origin), Some(velocity)) = (
+        world.get::<Position>(entity).map(|p| p.0),
+        world.get::<Velocity>(entity).map(|v| v.0),
+    ) else {
+        return;
+    };
+    let motion = velocity * dt;
+    let mut filter = QueryFilter {
+        mask: collider.mask,
+        layer: collider.layer,
+        exclude: triggers.to_vec(),
+    };
+    filter.exclude.push(entity);
+    let hit = shape_cast(
+        world,
+        &collider.shape,
+        origin,
+        motion,
+        motion.length(),
+        &filter,
+    );
+    let (end, velocity) = match hit {
+        Some(hit) => {
+            let end = origin + motion.normalize_or_zero() * (hit.distance - SKIN).max(0.0);
+            let into = velocity.dot(hit.normal);
+            (end, velocity - hit.normal * into.min(0.0))
+        }
+        None => (origin + motion, velocity),
+    };
+    if let Some(mut position) = world.get_mut::<Position>(entity) {
+        position.0 = end;
+    }
+    if let Some(mut v) = world.get_mut::<Velocity>(entity) {
+        v.0 = velocity;
+    }
+    let bounds = Aabb::around(end, collider.shape.half_extents());
+    if let Some(index) = world.resource_mut::<SpatialIndex>() {
+        index.insert(entity, bounds);
+    }
+}
+
+/// Pushes the bodies apart in inverse proportion to their mass, then
+/// applies a bounce and friction impulse.
+fn separate(
+    world: &mut World,
+    materials: &PhysicsMaterials,
+    solve: &PreSolve,
+    ca: &Collider,
+    cb: &Collider,
+) {
+    let contact = &solve.contact;
+    // Characters only move through `move_and_slide`.
+    let inverse_mass = |entity, collider| match world.get::<CharacterController>(entity) {
+        Some(_) => 0.0,
+        None => materials.inverse_mass(collider),
+    };
+    let (inv_a, inv_b) = (inverse_mass(contact.a, ca), inverse_mass(contact.b, cb));
+    let total = inv_a + inv_b;
+    if total == 0.0 {
+        return;
+    }
+    let velocity = |entity| {
+        world
+            .get::<Velocity>(entity)
+            .map(|v| v.0)
+            .unwrap_or_default()
+    };
+    let relative = velocity(contact.b) - velocity(contact.a);
+    let approach = relative.dot(contact.normal);
+    let mut impulse = Vec2::ZERO;
+    if approach < 0.0 {
+        let (friction, restitution) = (solve.friction, solve.restitution);
+        let j = -(1.0 + restitution) * approach / total;
+        impulse = contact.normal * j;
+        let slide = relative - contact.normal * approach;
+        let tangent = slide.normalize_or_zero();
+        let jt = (-relative.dot(tangent) / total).clamp(-friction * j, friction * j);
+        impulse = impulse + tangent * jt;
+    }
+    for (entity, inverse, sign) in [(contact.a, inv_a, -1.0), (contact.b, inv_b, 1.0)] {
+        if inverse == 0.0 {
+            continue;
+        }
+        let push = contact.normal * (sign * contact.depth * inverse / total);
+        if let Some(mut position) = world.get_mut::<Position>(entity) {
+            position.0 = position.0 + push;
+        }
+        if let Some(mut velocity) = world.get_mut::<Velocity>(entity) {
+            velocity.0 = velocity.0 + impulse * (sign * inverse);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solids_separate_and_triggers_report() {
+        let mut world = World::new();
+        let mut spawn = |x: f64, collider: Collider, velocity: f64| {
+            let entity = world.spawn();
+            world.insert(entity, Position(Vec2::new(x, 0.0))).unwrap();
+            world
+                .insert(entity, Velocity(Vec2::new(velocity, 0.0)))
+                .unwrap();
+            world.insert(entity, collider).unwrap();
+            entity
+        };
+        let ball = spawn(0.0, Collider::new(Shape::Circle { radius: 0.5 }), 1.0);
+        let wall = spawn(
+            2.0,
+            Collider {
+                is_static: true,
+                ..Collider::new(Shape::Box {
+                    half_width: 0.5,
+                    half_height: 2.0,
+                })
+            },
+            0.0,
+        );
+        let zone = spawn(0.25, Collider::new(Shape::Circle { radius: 0.1 }), 0.0);
+        world.insert(zone, TriggerVolume).unwrap();
+
+        // The ball moves into the trigger, which stays put.
+        assert!(step(&mut world, 0.5).is_empty());
+        assert_eq!(
+            world.drain_events::<TriggerEntered>(),
+            vec![TriggerEntered {
+                trigger: zone,
+                other: ball
+            }]
+        );
+        assert_eq!(world.get::<Position>(zone).unwrap().0, Vec2::new(0.25, 0.0));
+        assert!(step(&mut world, 0.25).is_empty());
+        assert!(world.drain_events::<TriggerEntered>().is_empty());
+
+        // Then into the wall, which stops it at its face.
+        let contacts = step(&mut world, 0.5);
+        assert_eq!(contacts.len(), 1);
+        assert_eq!((contacts[0].a, contacts[0].b), (ball, wall));
+        assert_eq!(world.get::<Position>(ball).unwrap().0, Vec2::new(1.0, 0.0));
+        assert_eq!(world.get::<Velocity>(ball).unwrap().0, Vec2::ZERO);
+        assert_eq!(
+            world.drain_events::<TriggerExited>(),
+            vec![TriggerExited {
+                trigger: zone,
+                other: ball
+            }]
+        );
+    }
+
+    #[test]
+    fn test_ccd_stops_fast_bodies_at_thin_walls() {
+        let mut world = World::new();
+        let wall = world.spawn();
+        world.insert(wall, Position(Vec2::new(5.0, 0.0))).unwrap();
+        let thin = Shape::Box {
+            half_width: 0.05,
+            half_height: 2.0,
+        };
+        world
+            .insert(
+                wall,
+                Collider {
+                    is_static: true,
+                    ..Collider::new(thin)
+                },
+            )
+            .unwrap();
+        let mut bullets = Vec::new();
+        for ccd in [false, true] {
+            let bullet = world.spawn();
+            world.insert(bullet, Position(Vec2::ZERO)).unwrap();
+            world
+                .insert(bullet, Velocity(Vec2::new(100.0, 0.0)))
+                .unwrap();
+            let collider = Collider {
+                ccd,
+                ..Collider::new(Shape::Circle { radius: 0.1 })
+            };
+            world.insert(bullet, collider).unwrap();
+            bullets.push(bullet);
+        }
+
+        step(&mut world, 0.1);
+        assert_eq!(
+            world.get::<Position>(bullets[0]).unwrap().0,
+            Vec2::new(10.0, 0.0)
+        );
+        let stopped = world.get::<Position>(bullets[1]).unwrap().0;
+        assert!((stopped.x - 4.85).abs() < 1e-5, "{:?}", stopped);
+        assert_eq!(world.get::<Velocity>(bullets[1]).unwrap().0, Vec2::ZERO);
+
+        // Starting in contact still stops motion into the wall, but not
+        // away from it.
+        world.get_mut::<Position>(bullets[1]).unwrap().0 = Vec2::new(4.85, 0.0);
+        world.get_mut::<Velocity>(bullets[1]).unwrap().0 = Vec2::new(100.0, 0.0);
+        step(&mut world, 0.1);
+        assert!(world.get::<Position>(bullets[1]).unwrap().0.x < 4.86);
+        world.get_mut::<Velocity>(bullets[1]).unwrap().0 = Vec2::new(-10.0, 0.0);
+        step(&mut world, 0.1);
+        assert!(world.get::<Position>(bullets[1]).unwrap().0.x < 3.9);
+
+        // A cast that only clips the corner hits the rounded edge.
+        let hit = crate::physics::shape_cast(
+            &world,
+            &Shape::Circle { radius: 0.5 },
+            Vec2::new(0.0, 2.3),
+            Vec2::new(1.0, 0.0),
+            10.0,
+            &QueryFilter::default(),
+        )
+        .unwrap();
+        assert_eq!(hit.entity, wall);
+        assert!((hit.normal.length() - 1.0).abs() < 1e-9 && hit.normal.y > 0.0);
+    }
+
+    #[test]
+    fn test_circle_box_overlap() {
+        let circle = Shape::Circle { radius: 1.0 };
+        let square = Shape::Box {
+            half_width: 1.0,
+            half_height: 1.0,
+        };
+        let (normal, depth) = overlap(&circle, Vec2::new(0.0, 1.5), &square, Vec2::ZERO).unwrap();
+        assert_eq!((normal, depth), (Vec2::new(0.0, -1.0), 0.5));
+        let (normal, _) = overlap(&square, Vec2::ZERO, &circle, Vec2::new(1.5, 0.0)).unwrap();
+        assert_eq!(normal, Vec2::new(1.0, 0.0));
+        assert!(overlap(&circle, Vec2::new(1.8, 1.8), &square, Vec2::ZERO).is_none());
+    }
+}
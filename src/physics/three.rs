@@ -0,0 +1,314 @@
+//! The 3D counterparts of positions, colliders, the spatial index and
+//! raycasts, behind the `3d` feature. Up is +y, as in 2D.
+
+use super::query::{Vector, nearest_hit, ray_ball, ray_box};
+use super::{Bounds, DEFAULT_LAYER, QueryFilter, RayHit, SpatialGrid, all_layers, default_layer};
+use crate::ecs::{Entity, World};
+use crate::math::Vec3;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Position3(pub Vec3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Shape3 {
+    Sphere {
+        radius: f64,
+    },
+    Aabb {
+        half_extents: Vec3,
+    },
+    /// Upright: a segment `half_height` above and below the center,
+    /// rounded by `radius`.
+    Capsule {
+        half_height: f64,
+        radius: f64,
+    },
+}
+
+impl Shape3 {
+    pub fn half_extents(&self) -> Vec3 {
+        match *self {
+            Shape3::Sphere { radius } => Vec3::new(radius, radius, radius),
+            Shape3::Aabb { half_extents } => half_extents,
+            Shape3::Capsule {
+                half_height,
+                radius,
+            } => Vec3::new(radius, half_height + radius, radius),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Collider3 {
+    pub shape: Shape3,
+    #[serde(default)]
+    pub is_static: bool,
+    #[serde(default = "default_layer")]
+    pub layer: u32,
+    #[serde(default = "all_layers")]
+    pub mask: u32,
+}
+
+impl Collider3 {
+    pub fn new(shape: Shape3) -> Self {
+        Collider3 {
+            shape,
+            is_static: false,
+            layer: DEFAULT_LAYER,
+            mask: super::ALL_LAYERS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb3 {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb3 {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Aabb3 { min, max }
+    }
+
+    pub fn around(center: Vec3, half_extents: Vec3) -> Self {
+        Aabb3::new(center - half_extents, center + half_extents)
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.min(self.min) == self.min && point.max(self.max) == self.max
+    }
+
+    pub fn overlaps(&self, other: &Aabb3) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+            && self.min.z <= other.max.z
+            && self.max.z >= other.min.z
+    }
+}
+
+impl Bounds for Aabb3 {
+    type Cell = (i64, i64, i64);
+
+    fn overlaps(&self, other: &Aabb3) -> bool {
+        Aabb3::overlaps(self, other)
+    }
+
+    fn cells(&self, cell_size: f64) -> impl Iterator<Item = (i64, i64, i64)> {
+        let cell = |point: Vec3| {
+            (
+                (point.x / cell_size).floor() as i64,
+                (point.y / cell_size).floor() as i64,
+                (point.z / cell_size).floor() as i64,
+            )
+        };
+        let (x0, y0, z0) = cell(self.min);
+        let (x1, y1, z1) = cell(self.max);
+        (x0..=x1).flat_map(move |x| (y0..=y1).flat_map(move |y| (z0..=z1).map(move |z| (x, y, z))))
+    }
+
+    fn colliders(world: &World, mut f: impl FnMut(Entity, Aabb3)) {
+        world
+            .query::<(&Position3, &Collider3)>()
+            .for_each(|entity, (position, collider)| {
+                f(
+                    entity,
+                    Aabb3::around(position.0, collider.shape.half_extents()),
+                );
+            });
+    }
+}
+
+/// The 3D index over `Position3` and `Collider3`.
+pub type SpatialIndex3 = SpatialGrid<Aabb3>;
+
+pub type RayHit3 = RayHit<Vec3>;
+
+impl Vector for Vec3 {
+    const ZERO: Vec3 = Vec3::ZERO;
+
+    fn axes() -> impl Iterator<Item = Vec3> {
+        [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ]
+        .into_iter()
+    }
+
+    fn dot(self, other: Vec3) -> f64 {
+        Vec3::dot(self, other)
+    }
+
+    fn normalize_or_zero(self) -> Vec3 {
+        Vec3::normalize_or_zero(self)
+    }
+}
+
+/// The upright cylinder between a capsule's end caps.
+fn ray_cylinder(
+    local: Vec3,
+    direction: Vec3,
+    half_height: f64,
+    radius: f64,
+) -> Option<(f64, Vec3)> {
+    let (start, step) = (
+        Vec3::new(local.x, 0.0, local.z),
+        Vec3::new(direction.x, 0.0, direction.z),
+    );
+    let a = step.dot(step);
+    if a < f64::EPSILON {
+        return None;
+    }
+    let b = start.dot(step);
+    let discriminant = b * b - a * (start.dot(start) - radius * radius);
+    if b > 0.0 || discriminant < 0.0 {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / a;
+    let point = local + direction * t;
+    (point.y.abs() <= half_height)
+        .then(|| (t, Vec3::new(point.x, 0.0, point.z).normalize_or_zero()))
+}
+
+/// Like `sweep` in 2D, but for a ray only: `local` is relative to the
+/// shape's center, and a ray starting inside hits at zero.
+fn ray_shape(shape: &Shape3, local: Vec3, direction: Vec3) -> Option<(f64, Vec3)> {
+    match *shape {
+        Shape3::Sphere { radius } => {
+            if local.length() <= radius {
+                return Some((0.0, -direction));
+            }
+            ray_ball(local, direction, radius)
+        }
+        Shape3::Aabb { half_extents } => {
+            if Aabb3::around(Vec3::ZERO, half_extents).contains(local) {
+                return Some((0.0, -direction));
+            }
+            ray_box(local, direction, half_extents)
+        }
+        Shape3::Capsule {
+            half_height,
+            radius,
+        } => {
+            let cap = Vec3::new(0.0, half_height, 0.0);
+            let nearest = Vec3::new(0.0, local.y.clamp(-half_height, half_height), 0.0);
+            if (local - nearest).length() <= radius {
+                return Some((0.0, -direction));
+            }
+            [
+                ray_cylinder(local, direction, half_height, radius),
+                ray_ball(local - cap, direction, radius),
+                ray_ball(local + cap, direction, radius),
+            ]
+            .into_iter()
+            .flatten()
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+        }
+    }
+}
+
+/// The nearest `Collider3` hit by a ray, within `max_distance`.
+pub fn raycast3(
+    world: &World,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f64,
+    filter: &QueryFilter,
+) -> Option<RayHit3> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return None;
+    }
+    let end = origin + direction * max_distance;
+    let area = Aabb3::new(origin.min(end), origin.max(end));
+    SpatialIndex3::with(world, |index| {
+        nearest_hit(
+            index.query_aabb(area),
+            origin,
+            direction,
+            max_distance,
+            |entity| {
+                let position = world.get::<Position3>(entity)?.0;
+                let collider = *world.get::<Collider3>(entity)?;
+                if !filter.accepts_layers(entity, collider.layer, collider.mask) {
+                    return None;
+                }
+                ray_shape(&collider.shape, origin - position, direction)
+            },
+        )
+    })
+}
+
+/// Registers `Position3`, `Collider3` and `Transform3` under those names.
+pub fn register_components(world: &mut World) {
+    world.register_component::<Position3>("Position3");
+    world.register_component::<Collider3>("Collider3");
+    world.register_component::<crate::math::Transform3>("Transform3");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{Quat, Transform3};
+
+    #[test]
+    fn test_raycasts_hit_3d_shapes() {
+        let mut world = World::new();
+        let shapes = [
+            (Vec3::new(0.0, 0.0, 5.0), Shape3::Sphere { radius: 1.0 }),
+            (
+                Vec3::new(3.0, 0.0, 5.0),
+                Shape3::Aabb {
+                    half_extents: Vec3::new(0.5, 0.5, 0.5),
+                },
+            ),
+            (
+                Vec3::new(-3.0, 0.0, 5.0),
+                Shape3::Capsule {
+                    half_height: 1.0,
+                    radius: 0.5,
+                },
+            ),
+        ];
+        let entities: Vec<Entity> = shapes
+            .into_iter()
+            .map(|(at, shape)| {
+                let entity = world.spawn();
+                world.insert(entity, Position3(at)).unwrap();
+                world.insert(entity, Collider3::new(shape)).unwrap();
+                entity
+            })
+            .collect();
+
+        let forward = Vec3::new(0.0, 0.0, 1.0);
+        let cast = |x: f64, y: f64| {
+            raycast3(
+                &world,
+                Vec3::new(x, y, 0.0),
+                forward,
+                10.0,
+                &QueryFilter::default(),
+            )
+        };
+        let hit = cast(0.0, 0.0).unwrap();
+        assert_eq!((hit.entity, hit.distance), (entities[0], 4.0));
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(cast(3.0, 0.4).unwrap().distance, 4.5);
+        // Through the capsule's side, then just above its upper cap.
+        assert_eq!(cast(-3.0, 0.9).unwrap().distance, 4.5);
+        assert!(cast(-3.0, 1.6).is_none());
+        assert!(cast(0.0, 3.0).is_none());
+
+        let turned = Transform3 {
+            translation: Vec3::new(1.0, 0.0, 0.0),
+            rotation: Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), std::f64::consts::FRAC_PI_2),
+            scale: Vec3::new(2.0, 1.0, 1.0),
+        };
+        let p = turned.transform_point(Vec3::new(1.0, 0.0, 0.0));
+        assert!((p - Vec3::new(1.0, 0.0, -2.0)).length() < 1e-12, "{:?}", p);
+    }
+}
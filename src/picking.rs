@@ -0,0 +1,140 @@
+use crate::ecs::{Entity, World};
+use crate::math::Vec2;
+use crate::physics::{Collider, Position, SpatialIndex};
+
+pub use crate::physics::{ALL_LAYERS, DEFAULT_LAYER};
+use serde::{Deserialize, Serialize};
+
+/// Picking settings for one entity. Higher `depth` is drawn on top and
+/// wins when shapes overlap. Entities without one are picked on their
+/// collider's layer at depth zero.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pickable {
+    pub layers: u32,
+    pub depth: f64,
+}
+
+impl Default for Pickable {
+    fn default() -> Self {
+        Pickable {
+            layers: DEFAULT_LAYER,
+            depth: 0.0,
+        }
+    }
+}
+
+pub fn register_components(world: &mut World) {
+    world.register_component::<Pickable>("Pickable");
+}
+
+/// Every collider under `point` on a layer in `mask`, topmost first. Ties
+/// in depth go to the collider added last, then to the higher entity.
+/// Screen points must be converted to world space first, and a kept
+/// `SpatialIndex` must be current (see `SpatialIndex::sync`).
+pub fn pick_all(world: &World, point: Vec2, mask: u32) -> Vec<Entity> {
+    let mut hits: Vec<(f64, u32, Entity)> = SpatialIndex::with(world, |index| {
+        index
+            .query_point(point)
+            .into_iter()
+            .filter_map(|entity| {
+                let position = world.get::<Position>(entity)?;
+                let collider = world.get::<Collider>(entity)?;
+                if collider.shape.distance(position.0, point) > 0.0 {
+                    return None;
+                }
+                let pickable = world
+                    .get::<Pickable>(entity)
+                    .map(|p| *p)
+                    .unwrap_or(Pickable {
+                        layers: collider.layer,
+                        depth: 0.0,
+                    });
+                let added = world.ticks::<Collider>(entity)?.added;
+                (pickable.layers & mask != 0).then_some((pickable.depth, added, entity))
+            })
+            .collect()
+    });
+    hits.sort_by(|a, b| b.0.total_cmp(&a.0).then(b.1.cmp(&a.1)).then(b.2.cmp(&a.2)));
+    hits.into_iter().map(|(_, _, entity)| entity).collect()
+}
+
+pub fn pick(world: &World, point: Vec2, mask: u32) -> Option<Entity> {
+    pick_all(world, point, mask).into_iter().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::Shape;
+    use mlua::Lua;
+
+    fn spawn(world: &mut World, at: Vec2, radius: f64, pickable: Option<Pickable>) -> Entity {
+        let entity = world.spawn();
+        world.insert(entity, Position(at)).unwrap();
+        world
+            .insert(entity, Collider::new(Shape::Circle { radius }))
+            .unwrap();
+        if let Some(pickable) = pickable {
+            world.insert(entity, pickable).unwrap();
+        }
+        entity
+    }
+
+    #[test]
+    fn test_pick_topmost_on_mask() -> mlua::Result<()> {
+        let mut world = World::new();
+        let ground = spawn(&mut world, Vec2::ZERO, 5.0, None);
+        let unit = spawn(
+            &mut world,
+            Vec2::new(1.0, 0.0),
+            1.0,
+            Some(Pickable {
+                layers: 2,
+                depth: 1.0,
+            }),
+        );
+        let _hidden = spawn(
+            &mut world,
+            Vec2::new(1.0, 0.0),
+            1.0,
+            Some(Pickable {
+                layers: 4,
+                depth: 2.0,
+            }),
+        );
+
+        assert_eq!(pick(&world, Vec2::new(1.0, 0.5), 1 | 2), Some(unit));
+        assert_eq!(
+            pick_all(&world, Vec2::new(1.0, 0.5), 1 | 2),
+            vec![unit, ground]
+        );
+        assert_eq!(pick(&world, Vec2::new(-3.0, 0.0), ALL_LAYERS), Some(ground));
+        assert_eq!(pick(&world, Vec2::new(9.0, 0.0), ALL_LAYERS), None);
+
+        world.insert_resource(SpatialIndex::build(&world, 1.0));
+        let lua = Lua::new();
+        let picked: Entity = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load("local world = ... return world:pick(1, 0.5, 3)")
+                .call(handle)
+        })?;
+        assert_eq!(picked, unit);
+
+        // A recycled, lower entity id added later still wins the tie.
+        let (first, second) = (
+            spawn(&mut world, Vec2::new(20.0, 0.0), 1.0, None),
+            spawn(&mut world, Vec2::new(20.0, 0.0), 1.0, None),
+        );
+        world.despawn(first);
+        world.advance_tick();
+        let third = spawn(&mut world, Vec2::new(20.0, 0.0), 1.0, None);
+        assert!(third < second);
+        SpatialIndex::sync(&mut world);
+        assert_eq!(
+            pick_all(&world, Vec2::new(20.0, 0.0), ALL_LAYERS),
+            vec![third, second]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,312 @@
+//! Storefront and console services behind one trait, so a Steam or console
+//! SDK integration is a `PlatformServices` impl and gameplay code only
+//! ever talks to `Platform`.
+
+use mlua::{Lua, Result, UserData, UserDataMethods, Value};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlatformUser {
+    /// Stable across sessions, e.g. a Steam ID.
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlatformError {
+    /// The platform has no such service, or it is offline.
+    Unavailable(&'static str),
+    UnknownAchievement(String),
+    /// The SDK reported a failure.
+    Sdk(String),
+}
+
+impl fmt::Display for PlatformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlatformError::Unavailable(service) => write!(f, "{} unavailable", service),
+            PlatformError::UnknownAchievement(id) => write!(f, "unknown achievement '{}'", id),
+            PlatformError::Sdk(message) => write!(f, "platform error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for PlatformError {}
+
+impl From<PlatformError> for mlua::Error {
+    fn from(e: PlatformError) -> Self {
+        mlua::Error::external(e)
+    }
+}
+
+pub type PlatformResult<T> = std::result::Result<T, PlatformError>;
+
+pub trait PlatformServices {
+    /// Shown in logs, e.g. `"steam"`.
+    fn name(&self) -> &str;
+    fn user(&self) -> PlatformUser;
+
+    fn unlock_achievement(&mut self, id: &str) -> PlatformResult<()>;
+    fn is_achievement_unlocked(&self, id: &str) -> bool;
+
+    /// Sets a rich presence key, or clears it with `None`.
+    fn set_presence(&mut self, key: &str, value: Option<&str>) -> PlatformResult<()>;
+
+    fn cloud_available(&self) -> bool;
+    fn cloud_read(&self, file: &str) -> PlatformResult<Option<Vec<u8>>>;
+    fn cloud_write(&mut self, file: &str, data: &[u8]) -> PlatformResult<()>;
+    fn cloud_delete(&mut self, file: &str) -> PlatformResult<()>;
+
+    /// Pumps SDK callbacks; call once a frame.
+    fn update(&mut self) {}
+}
+
+/// For builds without a platform SDK. Achievements and presence are only
+/// remembered for the session and there is no cloud storage.
+#[derive(Debug, Clone)]
+pub struct NullPlatform {
+    user: PlatformUser,
+    achievements: BTreeSet<String>,
+    presence: BTreeMap<String, String>,
+}
+
+impl Default for NullPlatform {
+    fn default() -> Self {
+        NullPlatform {
+            user: PlatformUser {
+                id: "local".to_string(),
+                name: "Player".to_string(),
+            },
+            achievements: BTreeSet::new(),
+            presence: BTreeMap::new(),
+        }
+    }
+}
+
+impl NullPlatform {
+    pub fn new() -> Self {
+        NullPlatform::default()
+    }
+
+    pub fn with_user(user: PlatformUser) -> Self {
+        NullPlatform {
+            user,
+            ..NullPlatform::default()
+        }
+    }
+
+    pub fn presence(&self) -> &BTreeMap<String, String> {
+        &self.presence
+    }
+}
+
+impl PlatformServices for NullPlatform {
+    fn name(&self) -> &str {
+        "none"
+    }
+
+    fn user(&self) -> PlatformUser {
+        self.user.clone()
+    }
+
+    fn unlock_achievement(&mut self, id: &str) -> PlatformResult<()> {
+        self.achievements.insert(id.to_string());
+        Ok(())
+    }
+
+    fn is_achievement_unlocked(&self, id: &str) -> bool {
+        self.achievements.contains(id)
+    }
+
+    fn set_presence(&mut self, key: &str, value: Option<&str>) -> PlatformResult<()> {
+        match value {
+            Some(value) => self.presence.insert(key.to_string(), value.to_string()),
+            None => self.presence.remove(key),
+        };
+        Ok(())
+    }
+
+    fn cloud_available(&self) -> bool {
+        false
+    }
+
+    fn cloud_read(&self, _file: &str) -> PlatformResult<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    fn cloud_write(&mut self, _file: &str, _data: &[u8]) -> PlatformResult<()> {
+        Err(PlatformError::Unavailable("cloud saves"))
+    }
+
+    fn cloud_delete(&mut self, _file: &str) -> PlatformResult<()> {
+        Ok(())
+    }
+}
+
+/// The game's handle on its platform. Clones share one implementation, as
+/// does the `platform` Lua global.
+#[derive(Clone)]
+pub struct Platform {
+    services: Rc<RefCell<Box<dyn PlatformServices>>>,
+}
+
+impl Default for Platform {
+    fn default() -> Self {
+        Platform::new(NullPlatform::new())
+    }
+}
+
+impl Platform {
+    pub fn new(services: impl PlatformServices + 'static) -> Self {
+        Platform {
+            services: Rc::new(RefCell::new(Box::new(services))),
+        }
+    }
+
+    pub fn services(&self) -> std::cell::RefMut<'_, Box<dyn PlatformServices>> {
+        self.services.borrow_mut()
+    }
+
+    pub fn update(&self) {
+        self.services.borrow_mut().update();
+    }
+
+    /// Adds the `platform` global: `name()`, `user()` as `{ id, name }`,
+    /// `unlock(id)`, `is_unlocked(id)`, `set_presence(key, value)`,
+    /// `cloud_available()`, `cloud_read(file)`, `cloud_write(file, data)`
+    /// and `cloud_delete(file)`, all as methods. Cloud data are strings.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("platform", self.clone())
+    }
+}
+
+impl UserData for Platform {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("name", |_, this, ()| Ok(this.services().name().to_string()));
+        methods.add_method("user", |lua, this, ()| {
+            let user = this.services().user();
+            let table = lua.create_table()?;
+            table.set("id", user.id)?;
+            table.set("name", user.name)?;
+            Ok(table)
+        });
+        methods.add_method("unlock", |_, this, id: String| {
+            Ok(this.services().unlock_achievement(&id)?)
+        });
+        methods.add_method("is_unlocked", |_, this, id: String| {
+            Ok(this.services().is_achievement_unlocked(&id))
+        });
+        methods.add_method(
+            "set_presence",
+            |_, this, (key, value): (String, Option<String>)| {
+                Ok(this.services().set_presence(&key, value.as_deref())?)
+            },
+        );
+        methods.add_method("cloud_available", |_, this, ()| {
+            Ok(this.services().cloud_available())
+        });
+        methods.add_method("cloud_read", |lua, this, file: String| {
+            match this.services().cloud_read(&file)? {
+                Some(data) => Ok(Value::String(lua.create_string(data)?)),
+                None => Ok(Value::Nil),
+            }
+        });
+        methods.add_method(
+            "cloud_write",
+            |_, this, (file, data): (String, mlua::String)| {
+                Ok(this.services().cloud_write(&file, &data.as_bytes())?)
+            },
+        );
+        methods.add_method("cloud_delete", |_, this, file: String| {
+            Ok(this.services().cloud_delete(&file)?)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cloud storage kept in memory, standing in for an SDK.
+    #[derive(Default)]
+    struct Cloudy {
+        files: BTreeMap<String, Vec<u8>>,
+    }
+
+    impl PlatformServices for Cloudy {
+        fn name(&self) -> &str {
+            "cloudy"
+        }
+
+        fn user(&self) -> PlatformUser {
+            PlatformUser {
+                id: "76561".to_string(),
+                name: "Ada".to_string(),
+            }
+        }
+
+        fn unlock_achievement(&mut self, id: &str) -> PlatformResult<()> {
+            Err(PlatformError::UnknownAchievement(id.to_string()))
+        }
+
+        fn is_achievement_unlocked(&self, _id: &str) -> bool {
+            false
+        }
+
+        fn set_presence(&mut self, _key: &str, _value: Option<&str>) -> PlatformResult<()> {
+            Ok(())
+        }
+
+        fn cloud_available(&self) -> bool {
+            true
+        }
+
+        fn cloud_read(&self, file: &str) -> PlatformResult<Option<Vec<u8>>> {
+            Ok(self.files.get(file).cloned())
+        }
+
+        fn cloud_write(&mut self, file: &str, data: &[u8]) -> PlatformResult<()> {
+            self.files.insert(file.to_string(), data.to_vec());
+            Ok(())
+        }
+
+        fn cloud_delete(&mut self, file: &str) -> PlatformResult<()> {
+            self.files.remove(file);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_platform_lua_bindings() -> Result<()> {
+        let lua = Lua::new();
+        Platform::default().register_lua(&lua)?;
+        lua.load(
+            r#"
+            assert(platform:name() == "none" and platform:user().id == "local")
+            platform:unlock("first_blood")
+            assert(platform:is_unlocked("first_blood"))
+            platform:set_presence("status", "In the harbor")
+            assert(not platform:cloud_available() and platform:cloud_read("save") == nil)
+            assert(not pcall(platform.cloud_write, platform, "save", "data"))
+        "#,
+        )
+        .exec()?;
+
+        Platform::new(Cloudy::default()).register_lua(&lua)?;
+        lua.load(
+            r#"
+            assert(platform:user().name == "Ada")
+            platform:cloud_write("save", "\0level=3")
+            assert(platform:cloud_read("save") == "\0level=3")
+            platform:cloud_delete("save")
+            assert(platform:cloud_read("save") == nil)
+            local ok, err = pcall(platform.unlock, platform, "nope")
+            assert(not ok and tostring(err):find("unknown achievement"))
+        "#,
+        )
+        .exec()
+    }
+}
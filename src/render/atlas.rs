@@ -0,0 +1,424 @@
+use super::{Sprite, UvRect};
+use crate::data::{DataError, from_ron};
+use crate::ecs::World;
+use crate::sprite::SpriteSheet;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Pixels as 8-bit RGBA rows, top row first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Fully transparent.
+    pub fn new(width: u32, height: u32) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    pub fn from_rgba(
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    ) -> std::result::Result<Self, DataError> {
+        if pixels.len() != width as usize * height as usize * 4 {
+            return Err(DataError::Invalid(format!(
+                "{} bytes is not a {}x{} RGBA image",
+                pixels.len(),
+                width,
+                height
+            )));
+        }
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+
+    /// Copies `source` in with its top-left at `x`, `y`.
+    fn blit(&mut self, source: &Image, x: u32, y: u32) {
+        let row = source.width as usize * 4;
+        for sy in 0..source.height as usize {
+            let from = sy * row;
+            let to = ((y as usize + sy) * self.width as usize + x as usize) * 4;
+            self.pixels[to..to + row].copy_from_slice(&source.pixels[from..from + row]);
+        }
+    }
+
+    #[cfg(feature = "png")]
+    pub fn decode_png(bytes: &[u8]) -> std::result::Result<Self, DataError> {
+        let invalid = |e: png::DecodingError| DataError::Invalid(format!("bad png: {}", e));
+        let mut decoder = png::Decoder::new(std::io::Cursor::new(bytes));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(invalid)?;
+        let mut buffer = vec![0; reader.output_buffer_size().unwrap_or_default()];
+        let info = reader.next_frame(&mut buffer).map_err(invalid)?;
+        buffer.truncate(info.buffer_size());
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => buffer
+                .chunks(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buffer
+                .chunks(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale => buffer.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+            png::ColorType::Indexed => {
+                return Err(DataError::Invalid(
+                    "bad png: palette not expanded".to_string(),
+                ));
+            }
+        };
+        Image::from_rgba(info.width, info.height, pixels)
+    }
+
+    #[cfg(feature = "png")]
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        Image::decode_png(&std::fs::read(path)?)
+    }
+
+    #[cfg(feature = "png")]
+    pub fn to_png(&self) -> std::result::Result<Vec<u8>, DataError> {
+        let invalid = |e: png::EncodingError| DataError::Invalid(format!("can't write png: {}", e));
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(invalid)?;
+        writer.write_image_data(&self.pixels).map_err(invalid)?;
+        writer.finish().map_err(invalid)?;
+        Ok(bytes)
+    }
+}
+
+/// Where a packed image ended up.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AtlasRegion {
+    pub page: usize,
+    pub uv: UvRect,
+}
+
+/// Loose images packed into a few large pages, so sprites from many files
+/// draw from one texture. Sprites and sheets still name their original
+/// images until `remap_sprite`, `remap_sheet` or `remap_world` points them
+/// at the pages, named `<name>_<page>.png`. The page pixels exist only in
+/// an atlas fresh from `AtlasBuilder`; one loaded from a manifest carries
+/// just the regions, leaving the pages to the renderer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Atlas {
+    pub name: String,
+    pub page_count: usize,
+    pub regions: BTreeMap<String, AtlasRegion>,
+    #[serde(skip)]
+    pub pages: Vec<Image>,
+}
+
+impl Atlas {
+    pub fn page_name(&self, page: usize) -> String {
+        format!("{}_{}.png", self.name, page)
+    }
+
+    /// The page image and region for an original image name.
+    pub fn region(&self, image: &str) -> Option<(String, UvRect)> {
+        let region = self.regions.get(image)?;
+        Some((self.page_name(region.page), region.uv))
+    }
+
+    /// Points a sprite at its image's page, keeping its sub-region. Returns
+    /// false if the image wasn't packed.
+    pub fn remap_sprite(&self, sprite: &mut Sprite) -> bool {
+        let Some((page, region)) = self.region(&sprite.image) else {
+            return false;
+        };
+        sprite.image = page;
+        sprite.uv.x += region.x;
+        sprite.uv.y += region.y;
+        true
+    }
+
+    pub fn remap_sheet(&self, sheet: &mut SpriteSheet) -> bool {
+        let Some((page, region)) = self.region(&sheet.image) else {
+            return false;
+        };
+        sheet.image = page;
+        for frame in &mut sheet.frames {
+            frame.rect.x += region.x as u32;
+            frame.rect.y += region.y as u32;
+        }
+        true
+    }
+
+    /// Remaps every `Sprite` in the world, returning how many changed.
+    pub fn remap_world(&self, world: &mut World) -> usize {
+        let mut count = 0;
+        world.query::<&mut Sprite>().for_each(|_, sprite| {
+            count += usize::from(self.remap_sprite(sprite));
+        });
+        count
+    }
+
+    pub fn from_ron(source: &str) -> std::result::Result<Self, DataError> {
+        from_ron(source)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        Atlas::from_ron(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn to_ron(&self) -> std::result::Result<String, DataError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| DataError::Invalid(e.to_string()))
+    }
+
+    /// Writes the pages and a `<name>.atlas.ron` manifest into `dir`.
+    #[cfg(feature = "png")]
+    pub fn save(&self, dir: impl AsRef<Path>) -> std::result::Result<(), DataError> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        for (i, page) in self.pages.iter().enumerate() {
+            std::fs::write(dir.join(self.page_name(i)), page.to_png()?)?;
+        }
+        std::fs::write(dir.join(format!("{}.atlas.ron", self.name)), self.to_ron()?)?;
+        Ok(())
+    }
+}
+
+/// Collects images and packs them onto pages no larger than `max_size`
+/// square, tallest first along shelves, with `padding` transparent
+/// pixels between neighbours against filtering bleed.
+#[derive(Debug, Clone)]
+pub struct AtlasBuilder {
+    pub max_size: u32,
+    pub padding: u32,
+    images: Vec<(String, Image)>,
+}
+
+impl Default for AtlasBuilder {
+    fn default() -> Self {
+        AtlasBuilder::new(2048)
+    }
+}
+
+impl AtlasBuilder {
+    pub fn new(max_size: u32) -> Self {
+        AtlasBuilder {
+            max_size,
+            padding: 1,
+            images: Vec::new(),
+        }
+    }
+
+    /// Adds an image under the name sprites refer to it by.
+    pub fn add(&mut self, name: &str, image: Image) {
+        self.images.push((name.to_string(), image));
+    }
+
+    /// Adds the PNG at `path`, named by its path under `root` with `/`
+    /// separators, the way sprites name their images.
+    #[cfg(feature = "png")]
+    pub fn add_file(
+        &mut self,
+        root: impl AsRef<Path>,
+        path: impl AsRef<Path>,
+    ) -> std::result::Result<(), DataError> {
+        let path = path.as_ref();
+        let name = relative_name(root.as_ref(), path)?;
+        self.add(&name, Image::load(path)?);
+        Ok(())
+    }
+
+    pub fn build(self, name: &str) -> std::result::Result<Atlas, DataError> {
+        let pad = self.padding;
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        order.sort_by_key(|&i| {
+            let image = &self.images[i].1;
+            (
+                std::cmp::Reverse(image.height),
+                std::cmp::Reverse(image.width),
+            )
+        });
+
+        // Shelf packing: fill rows left to right, start a row below the
+        // tallest image so far, and a page when rows run out.
+        let mut placed = vec![(0, 0, 0); self.images.len()];
+        let mut extents = vec![(0, 0)];
+        let (mut x, mut y, mut shelf) = (0, 0, 0);
+        for &i in &order {
+            let (image_name, image) = &self.images[i];
+            if image.width > self.max_size || image.height > self.max_size {
+                return Err(DataError::Invalid(format!(
+                    "{} ({}x{}) doesn't fit a {} atlas page",
+                    image_name, image.width, image.height, self.max_size
+                )));
+            }
+            if x > 0 && x + image.width > self.max_size {
+                (x, y, shelf) = (0, y + shelf + pad, 0);
+            }
+            if y > 0 && y + image.height > self.max_size {
+                (x, y, shelf) = (0, 0, 0);
+                extents.push((0, 0));
+            }
+            let page = extents.len() - 1;
+            placed[i] = (page, x, y);
+            let extent = &mut extents[page];
+            *extent = (
+                extent.0.max(x + image.width),
+                extent.1.max(y + image.height),
+            );
+            x += image.width + pad;
+            shelf = shelf.max(image.height);
+        }
+
+        let mut pages: Vec<Image> = extents.iter().map(|&(w, h)| Image::new(w, h)).collect();
+        let mut regions = BTreeMap::new();
+        for ((image_name, image), (page, x, y)) in self.images.iter().zip(placed) {
+            pages[page].blit(image, x, y);
+            let uv = UvRect {
+                x: x as f64,
+                y: y as f64,
+                w: image.width as f64,
+                h: image.height as f64,
+            };
+            regions.insert(image_name.clone(), AtlasRegion { page, uv });
+        }
+        Ok(Atlas {
+            name: name.to_string(),
+            page_count: pages.len(),
+            regions,
+            pages,
+        })
+    }
+}
+
+/// `path` relative to `root`, ignoring `.` components on either side.
+#[cfg(feature = "png")]
+fn relative_name(root: &Path, path: &Path) -> std::result::Result<String, DataError> {
+    use std::path::{Component, PathBuf};
+
+    let clean = |path: &Path| -> PathBuf {
+        path.components()
+            .filter(|c| *c != Component::CurDir)
+            .collect()
+    };
+    let path = clean(path);
+    let relative = path.strip_prefix(clean(root)).map_err(|_| {
+        DataError::Invalid(format!(
+            "{} is not under {}",
+            path.display(),
+            root.display()
+        ))
+    })?;
+    Ok(relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::math::Vec2;
+    use crate::render::DrawMode;
+
+    fn solid(width: u32, height: u32, shade: u8) -> Image {
+        Image::from_rgba(
+            width,
+            height,
+            [shade, shade, shade, 255].repeat((width * height) as usize),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn test_relative_names() -> std::result::Result<(), DataError> {
+        assert_eq!(
+            relative_name(Path::new("assets"), Path::new("./assets/sprites/hero.png"))?,
+            "sprites/hero.png"
+        );
+        assert_eq!(
+            relative_name(Path::new("."), Path::new("hero.png"))?,
+            "hero.png"
+        );
+        assert!(relative_name(Path::new("assets"), Path::new("other/hero.png")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_packs_images_and_remaps_sprites() -> std::result::Result<(), DataError> {
+        let mut builder = AtlasBuilder::new(32);
+        builder.add("hero.png", solid(16, 16, 10));
+        builder.add("coin.png", solid(8, 8, 20));
+        builder.add("wall.png", solid(32, 20, 30));
+        builder.add("gem.png", solid(15, 8, 40));
+        let atlas = builder.build("main")?;
+        assert_eq!(atlas.page_count, 2);
+        // The widest-tallest image gets a page to itself; the rest share.
+        assert_eq!(atlas.region("wall.png").unwrap().0, "main_0.png");
+        let (page, hero) = atlas.region("hero.png").unwrap();
+        assert_eq!((page.as_str(), hero.x, hero.y), ("main_1.png", 0.0, 0.0));
+        let gem = atlas.regions["gem.png"].uv;
+        assert_eq!((gem.x, gem.y), (17.0, 0.0));
+        let coin = atlas.regions["coin.png"].uv;
+        assert_eq!((coin.x, coin.y), (0.0, 17.0));
+        assert_eq!(atlas.pages[1].pixel(17, 0), [40, 40, 40, 255]);
+        assert_eq!(atlas.pages[1].pixel(16, 0), [0, 0, 0, 0]);
+        #[cfg(feature = "png")]
+        assert_eq!(
+            Image::decode_png(&atlas.pages[1].to_png()?)?,
+            atlas.pages[1]
+        );
+
+        let mut world = World::new();
+        let entity = world.spawn();
+        let sprite = Sprite {
+            image: "coin.png".to_string(),
+            uv: UvRect {
+                x: 2.0,
+                y: 2.0,
+                w: 4.0,
+                h: 4.0,
+            },
+            size: Vec2::new(1.0, 1.0),
+            mode: DrawMode::Stretch,
+            color: Color::WHITE,
+        };
+        world.insert(entity, sprite).unwrap();
+        let loaded = Atlas::from_ron(&atlas.to_ron()?)?;
+        assert!(loaded.pages.is_empty());
+        assert_eq!(loaded.remap_world(&mut world), 1);
+        let sprite = world.get::<Sprite>(entity).unwrap();
+        assert_eq!(
+            (sprite.image.as_str(), sprite.uv.x, sprite.uv.y),
+            ("main_1.png", 2.0, 19.0)
+        );
+
+        let mut builder = AtlasBuilder::new(8);
+        builder.add("huge.png", solid(9, 1, 0));
+        assert!(builder.build("small").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,361 @@
+use crate::camera::{Camera, Viewport, cameras};
+use crate::color::Color;
+use crate::ecs::{Entity, World};
+use crate::math::Vec2;
+use crate::physics::{Aabb, Collider, Position};
+use crate::text::Fonts;
+use mlua::{Lua, LuaSerdeExt, Result, UserData, UserDataMethods, Value};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod atlas;
+mod sprite;
+
+pub use atlas::{Atlas, AtlasBuilder, AtlasRegion, Image};
+pub use sprite::{DrawMode, Quad, Sprite, UvRect};
+
+/// One entity to draw, already placed on screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrawCommand {
+    pub entity: Entity,
+    pub screen: Vec2,
+    /// Pixels per world unit, for sizing the entity's sprite.
+    pub scale: f64,
+    /// The entity's `Sprite`, if it has one, centered on `screen`.
+    pub quads: Vec<Quad>,
+}
+
+/// What one camera sees. Renderers clip drawing to `viewport`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderSection {
+    pub camera: Entity,
+    pub viewport: Viewport,
+    pub commands: Vec<DrawCommand>,
+}
+
+/// One glyph of drawn text, in screen pixels. Renderers rasterize
+/// `glyph` from `font` into the rectangle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphQuad {
+    pub font: String,
+    pub glyph: char,
+    pub min: Vec2,
+    pub size: Vec2,
+    pub color: Color,
+}
+
+/// The frame's draw list, one section per camera in camera order, kept
+/// as a world resource. Game code only adds cameras; a second player's
+/// camera gets its own section without anything else changing. Text is
+/// screen space and drawn over every section.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderQueue {
+    sections: Vec<RenderSection>,
+    overlay: Vec<Quad>,
+    text: Vec<GlyphQuad>,
+}
+
+impl RenderQueue {
+    /// Culls every positioned entity against each camera's view, using
+    /// its sprite's or collider's bounds when it has them.
+    pub fn build(world: &World) -> Self {
+        let mut bodies = Vec::new();
+        world.query::<&Position>().for_each(|entity, position| {
+            let sprite = world.get::<Sprite>(entity).map(|s| s.clone());
+            let mut half = world
+                .get::<Collider>(entity)
+                .map(|c| c.shape.half_extents())
+                .unwrap_or_default();
+            if let Some(sprite) = &sprite {
+                let own = sprite.size * 0.5;
+                half = Vec2::new(half.x.max(own.x), half.y.max(own.y));
+            }
+            bodies.push((entity, position.0, Aabb::around(position.0, half), sprite));
+        });
+        let sections = cameras(world)
+            .into_iter()
+            .filter_map(|entity| {
+                let camera = *world.get::<Camera>(entity)?;
+                let visible = camera.visible();
+                let commands = bodies
+                    .iter()
+                    .filter(|(_, _, bounds, _)| bounds.overlaps(&visible))
+                    .map(|(body, position, _, sprite)| {
+                        let screen = camera.world_to_screen(*position);
+                        let quads = sprite
+                            .as_ref()
+                            .map(|sprite| {
+                                let size = sprite.size * camera.scale();
+                                sprite.quads(screen - size * 0.5, size)
+                            })
+                            .unwrap_or_default();
+                        DrawCommand {
+                            entity: *body,
+                            screen,
+                            scale: camera.scale(),
+                            quads,
+                        }
+                    })
+                    .collect();
+                Some(RenderSection {
+                    camera: entity,
+                    viewport: camera.viewport,
+                    commands,
+                })
+            })
+            .collect();
+        RenderQueue {
+            sections,
+            ..RenderQueue::default()
+        }
+    }
+
+    pub fn sections(&self) -> &[RenderSection] {
+        &self.sections
+    }
+
+    pub fn section(&self, camera: Entity) -> Option<&RenderSection> {
+        self.sections.iter().find(|s| s.camera == camera)
+    }
+
+    /// Screen-space sprites, drawn over the sections and under the text.
+    pub fn overlay(&self) -> &[Quad] {
+        &self.overlay
+    }
+
+    pub fn text(&self) -> &[GlyphQuad] {
+        &self.text
+    }
+}
+
+pub fn register_components(world: &mut World) {
+    world.register_component::<Sprite>("Sprite");
+}
+
+/// Rebuilds the world's `RenderQueue` for this frame.
+pub fn build_render_queue(world: &mut World) {
+    let queue = RenderQueue::build(world);
+    world.insert_resource(queue);
+}
+
+/// Screen-space drawing for scripts, the `render` global: text goes
+/// through `render:draw_text(font, text, { x, y }, size, color)` with
+/// `color` as `{ r, g, b, a }` or a hex string defaulting to white, and
+/// `render:measure_text(font, text, size)` returns width and height for
+/// layout. `render:draw_sprite(sprite, { x, y, w, h })` takes a table
+/// shaped like the `Sprite` component, e.g.
+/// `{ image = "ui.png", uv = { x = 0, y = 0, w = 16, h = 16 }, mode = { Tiled = { scale = 2 } } }`.
+/// Quads wait here until `submit` adds them to the world's queue after
+/// it is built. Clones share one batch.
+#[derive(Clone, Default)]
+pub struct Renderer {
+    fonts: Rc<RefCell<Fonts>>,
+    pending: Rc<RefCell<Vec<GlyphQuad>>>,
+    sprites: Rc<RefCell<Vec<Quad>>>,
+}
+
+impl Renderer {
+    pub fn new(fonts: Fonts) -> Self {
+        Renderer {
+            fonts: Rc::new(RefCell::new(fonts)),
+            pending: Rc::default(),
+            sprites: Rc::default(),
+        }
+    }
+
+    pub fn fonts_mut(&self) -> std::cell::RefMut<'_, Fonts> {
+        self.fonts.borrow_mut()
+    }
+
+    /// Queues `text` with its first line's top-left at `position`.
+    pub fn draw_text(&self, font: &str, text: &str, position: Vec2, size: f64, color: Color) {
+        let fonts = self.fonts.borrow();
+        let quads = fonts
+            .get(font)
+            .layout(text, position, size)
+            .into_iter()
+            .map(|(glyph, min, size)| GlyphQuad {
+                font: font.to_string(),
+                glyph,
+                min,
+                size,
+                color,
+            });
+        self.pending.borrow_mut().extend(quads);
+    }
+
+    /// Queues `sprite` over the screen rectangle at `min`.
+    pub fn draw_sprite(&self, sprite: &Sprite, min: Vec2, size: Vec2) {
+        self.sprites.borrow_mut().extend(sprite.quads(min, size));
+    }
+
+    pub fn measure_text(&self, font: &str, text: &str, size: f64) -> Vec2 {
+        self.fonts.borrow().get(font).measure(text, size)
+    }
+
+    /// Moves the queued quads into the world's `RenderQueue`.
+    pub fn submit(&self, world: &mut World) {
+        let quads = std::mem::take(&mut *self.pending.borrow_mut());
+        let sprites = std::mem::take(&mut *self.sprites.borrow_mut());
+        if world.resource::<RenderQueue>().is_none() {
+            world.insert_resource(RenderQueue::default());
+        }
+        let queue = world.resource_mut::<RenderQueue>().expect("inserted above");
+        queue.text.extend(quads);
+        queue.overlay.extend(sprites);
+    }
+
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("render", self.clone())
+    }
+}
+
+impl UserData for Renderer {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method(
+            "draw_text",
+            |lua,
+             this,
+             (font, text, position, size, color): (String, String, Value, f64, Value)| {
+                let color = match color {
+                    Value::Nil => Color::WHITE,
+                    color => lua.from_value(color)?,
+                };
+                this.draw_text(&font, &text, lua.from_value(position)?, size, color);
+                Ok(())
+            },
+        );
+        methods.add_method(
+            "draw_sprite",
+            |lua, this, (sprite, rect): (Value, Value)| {
+                let sprite: Sprite = lua.from_value(sprite)?;
+                let rect: UvRect = lua.from_value(rect)?;
+                this.draw_sprite(
+                    &sprite,
+                    Vec2::new(rect.x, rect.y),
+                    Vec2::new(rect.w, rect.h),
+                );
+                Ok(())
+            },
+        );
+        methods.add_method(
+            "measure_text",
+            |_, this, (font, text, size): (String, String, f64)| {
+                let size = this.measure_text(&font, &text, size);
+                Ok((size.x, size.y))
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::camera_at;
+
+    #[test]
+    fn test_split_screen_sections() {
+        let mut world = World::new();
+        let views = Viewport::split(200.0, 100.0, 2);
+        assert_eq!(
+            views[1],
+            Viewport {
+                x: 100.0,
+                y: 0.0,
+                w: 100.0,
+                h: 100.0
+            }
+        );
+        assert_eq!(Viewport::split(200.0, 100.0, 3)[2].y, 50.0);
+
+        let players = [Vec2::new(0.0, 0.0), Vec2::new(50.0, 0.0)].map(|at| {
+            let player = world.spawn();
+            world.insert(player, Position(at)).unwrap();
+            player
+        });
+        let cameras: Vec<Entity> = views
+            .iter()
+            .zip(players)
+            .map(|(&viewport, player)| {
+                let camera = world.spawn();
+                let view = Camera {
+                    center: world.get::<Position>(player).unwrap().0,
+                    viewport,
+                    ..Camera::default()
+                };
+                world.insert(camera, view).unwrap();
+                camera
+            })
+            .collect();
+
+        let sprite = Sprite {
+            image: "hero.png".to_string(),
+            uv: UvRect {
+                x: 0.0,
+                y: 0.0,
+                w: 32.0,
+                h: 32.0,
+            },
+            size: Vec2::new(1.0, 1.0),
+            mode: DrawMode::Stretch,
+            color: Color::WHITE,
+        };
+        world.insert(players[1], sprite).unwrap();
+
+        build_render_queue(&mut world);
+        let queue = world.resource::<RenderQueue>().unwrap();
+        assert_eq!(queue.sections().len(), 2);
+        let right = queue.section(cameras[1]).unwrap();
+        assert_eq!(right.commands.len(), 1);
+        assert_eq!(right.commands[0].entity, players[1]);
+        // Centered in the right half of the screen.
+        assert_eq!(right.commands[0].screen, Vec2::new(150.0, 50.0));
+        // Ten pixels per unit, so the one-unit sprite is 10 px square.
+        let quad = &right.commands[0].quads[0];
+        assert_eq!(
+            (quad.min, quad.size),
+            (Vec2::new(145.0, 45.0), Vec2::new(10.0, 10.0))
+        );
+        assert_eq!(
+            queue.section(cameras[0]).unwrap().commands[0].entity,
+            players[0]
+        );
+
+        assert_eq!(camera_at(&world, Vec2::new(120.0, 10.0)), Some(cameras[1]));
+        assert_eq!(camera_at(&world, Vec2::new(250.0, 10.0)), None);
+    }
+
+    #[test]
+    fn test_text_from_lua_lands_in_the_queue() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let mut fonts = Fonts::new();
+        fonts.insert("hud", crate::text::Font::monospace(0.5));
+        let renderer = Renderer::new(fonts);
+        renderer.register_lua(&lua)?;
+        lua.load(
+            r#"
+            local w, h = render:measure_text("hud", "Score", 20)
+            assert(w == 50 and h == 24)
+            render:draw_text("hud", "Hi", { x = 1280 - w, y = 0 }, 20, { 1, 0, 0, 1 })
+            render:draw_sprite({
+                image = "ui.png",
+                uv = { x = 0, y = 0, w = 16, h = 16 },
+                mode = { NineSlice = { left = 4, right = 4, top = 4, bottom = 4 } },
+            }, { x = 0, y = 0, w = 200, h = 50 })
+        "#,
+        )
+        .exec()?;
+
+        build_render_queue(&mut world);
+        renderer.submit(&mut world);
+        let text = world.resource::<RenderQueue>().unwrap().text().to_vec();
+        assert_eq!(text.len(), 2);
+        assert_eq!((text[1].glyph, text[1].min), ('i', Vec2::new(1240.0, 2.0)));
+        assert_eq!(text[0].color, Color::RED);
+        let overlay = world.resource::<RenderQueue>().unwrap().overlay().to_vec();
+        assert_eq!(overlay.len(), 9);
+        assert_eq!(overlay[8].min, Vec2::new(196.0, 46.0));
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+use crate::color::Color;
+use crate::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// A region of an image in pixels, `y` down; how atlas entries are
+/// addressed.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct UvRect {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl From<crate::sprite::Rect> for UvRect {
+    fn from(rect: crate::sprite::Rect) -> Self {
+        UvRect {
+            x: rect.x as f64,
+            y: rect.y as f64,
+            w: rect.w as f64,
+            h: rect.h as f64,
+        }
+    }
+}
+
+/// How a sprite's image region fills the rectangle it is drawn into.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum DrawMode {
+    #[default]
+    Stretch,
+    /// Corners keep their pixel size, edges stretch along one axis and
+    /// the middle along both, so panels resize without smearing their
+    /// borders. Borders are source pixels.
+    NineSlice {
+        left: f64,
+        right: f64,
+        top: f64,
+        bottom: f64,
+    },
+    /// Repeats the region at `scale` screen pixels per source pixel,
+    /// cutting the last row and column short.
+    Tiled { scale: f64 },
+}
+
+/// An image region to draw, on an entity (sized in world units) or
+/// straight to the screen through `render:draw_sprite`. This is the one
+/// sprite component; a `SpriteAnimator` beside it only moves `uv` along
+/// its sheet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sprite {
+    pub image: String,
+    pub uv: UvRect,
+    /// World units; ignored when drawing to a screen rectangle.
+    #[serde(default)]
+    pub size: Vec2,
+    #[serde(default)]
+    pub mode: DrawMode,
+    #[serde(default = "white")]
+    pub color: Color,
+}
+
+fn white() -> Color {
+    Color::WHITE
+}
+
+/// One textured rectangle, the only primitive renderers need to draw.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quad {
+    pub image: String,
+    pub uv: UvRect,
+    /// Top-left corner on screen.
+    pub min: Vec2,
+    pub size: Vec2,
+    pub color: Color,
+}
+
+/// Splits `0..total` of the destination and `0..source` of the region at
+/// the two borders, shrinking the borders evenly if they don't fit.
+fn slices(source: f64, total: f64, near: f64, far: f64) -> [(f64, f64, f64, f64); 3] {
+    let fit = if near + far > total && near + far > 0.0 {
+        total / (near + far)
+    } else {
+        1.0
+    };
+    let (dn, df) = (near * fit, far * fit);
+    [
+        (0.0, near, 0.0, dn),
+        (near, source - far, dn, total - df),
+        (source - far, source, total - df, total),
+    ]
+}
+
+impl Sprite {
+    /// The quads that draw this sprite over the screen rectangle at `min`.
+    pub fn quads(&self, min: Vec2, size: Vec2) -> Vec<Quad> {
+        let quad = |uv: UvRect, min: Vec2, size: Vec2| Quad {
+            image: self.image.clone(),
+            uv,
+            min,
+            size,
+            color: self.color,
+        };
+        match self.mode {
+            DrawMode::Stretch => vec![quad(self.uv, min, size)],
+            DrawMode::NineSlice {
+                left,
+                right,
+                top,
+                bottom,
+            } => {
+                let columns = slices(self.uv.w, size.x, left, right);
+                let rows = slices(self.uv.h, size.y, top, bottom);
+                let mut quads = Vec::new();
+                for (v0, v1, y0, y1) in rows {
+                    for (u0, u1, x0, x1) in columns {
+                        if x1 - x0 <= 0.0 || y1 - y0 <= 0.0 {
+                            continue;
+                        }
+                        let uv = UvRect {
+                            x: self.uv.x + u0,
+                            y: self.uv.y + v0,
+                            w: u1 - u0,
+                            h: v1 - v0,
+                        };
+                        quads.push(quad(
+                            uv,
+                            min + Vec2::new(x0, y0),
+                            Vec2::new(x1 - x0, y1 - y0),
+                        ));
+                    }
+                }
+                quads
+            }
+            DrawMode::Tiled { scale } => {
+                let tile = Vec2::new(self.uv.w * scale, self.uv.h * scale);
+                if tile.x <= 0.0 || tile.y <= 0.0 {
+                    return Vec::new();
+                }
+                let mut quads = Vec::new();
+                let mut y = 0.0;
+                while y < size.y {
+                    let h = tile.y.min(size.y - y);
+                    let mut x = 0.0;
+                    while x < size.x {
+                        let w = tile.x.min(size.x - x);
+                        let uv = UvRect {
+                            w: w / scale,
+                            h: h / scale,
+                            ..self.uv
+                        };
+                        quads.push(quad(uv, min + Vec2::new(x, y), Vec2::new(w, h)));
+                        x += tile.x;
+                    }
+                    y += tile.y;
+                }
+                quads
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite(mode: DrawMode) -> Sprite {
+        Sprite {
+            image: "ui.png".to_string(),
+            uv: UvRect {
+                x: 32.0,
+                y: 0.0,
+                w: 16.0,
+                h: 16.0,
+            },
+            size: Vec2::ZERO,
+            mode,
+            color: Color::WHITE,
+        }
+    }
+
+    #[test]
+    fn test_nine_slice_and_tiling() {
+        let panel = sprite(DrawMode::NineSlice {
+            left: 4.0,
+            right: 4.0,
+            top: 4.0,
+            bottom: 4.0,
+        });
+        let quads = panel.quads(Vec2::new(10.0, 10.0), Vec2::new(100.0, 40.0));
+        assert_eq!(quads.len(), 9);
+        // The top-right corner keeps its size and samples the region's corner.
+        assert_eq!(quads[2].min, Vec2::new(106.0, 10.0));
+        assert_eq!(quads[2].size, Vec2::new(4.0, 4.0));
+        assert_eq!((quads[2].uv.x, quads[2].uv.w), (44.0, 4.0));
+        // The middle stretches.
+        assert_eq!(quads[4].size, Vec2::new(92.0, 32.0));
+        assert_eq!(quads[4].uv.w, 8.0);
+        // Too small for the borders: they shrink to fit and the middle goes.
+        assert_eq!(panel.quads(Vec2::ZERO, Vec2::new(4.0, 4.0)).len(), 4);
+
+        let floor = sprite(DrawMode::Tiled { scale: 2.0 });
+        let quads = floor.quads(Vec2::ZERO, Vec2::new(80.0, 32.0));
+        assert_eq!(quads.len(), 3);
+        assert_eq!(quads[2].min, Vec2::new(64.0, 0.0));
+        assert_eq!(quads[2].size, Vec2::new(16.0, 32.0));
+        assert_eq!((quads[2].uv.x, quads[2].uv.w), (32.0, 8.0));
+    }
+}
@@ -0,0 +1,222 @@
+use super::Replay;
+use crate::ecs::{Entity, Schedule, World};
+use mlua::{Function, Lua, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Marks an entity mirroring `source` from a replay. Ghosts carry only the
+/// components their player copies, so colliders, pickables and the like
+/// never reach them; games skip `Ghost` entities wherever else it matters,
+/// such as when saving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ghost {
+    pub source: Entity,
+}
+
+/// Registers `Ghost` under that name.
+pub fn register_components(world: &mut World) {
+    world.register_component::<Ghost>("Ghost");
+}
+
+/// Plays a replay in a world of its own and mirrors a chosen set of
+/// components, typically positions and animation state, onto ghost
+/// entities in a live world: time-trial ghosts, kill-cams and spectating.
+/// Set the private world and schedule up the way the recording's were
+/// before the first `step`.
+pub struct GhostPlayer {
+    replay: Replay,
+    world: World,
+    schedule: Schedule,
+    input: Option<Function>,
+    /// The next frame to play, or `None` before the replay is started.
+    frame: Option<u64>,
+    components: Vec<String>,
+    /// Replay entity to the ghost mirroring it.
+    ghosts: BTreeMap<Entity, Entity>,
+}
+
+impl GhostPlayer {
+    pub fn new(replay: Replay, components: &[&str]) -> Self {
+        GhostPlayer {
+            replay,
+            world: World::new(),
+            schedule: Schedule::new(),
+            input: None,
+            frame: None,
+            components: components.iter().map(|name| name.to_string()).collect(),
+            ghosts: BTreeMap::new(),
+        }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    pub fn schedule_mut(&mut self) -> &mut Schedule {
+        &mut self.schedule
+    }
+
+    /// Called as in `Replay::seek`.
+    pub fn set_input(&mut self, input: Function) {
+        self.input = Some(input);
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame.unwrap_or(0)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame
+            .is_some_and(|frame| frame >= self.replay.frames())
+    }
+
+    pub fn ghosts(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.ghosts.values().copied()
+    }
+
+    /// Jumps to `frame` and updates the ghosts in `live` to match.
+    pub fn seek(&mut self, live: &mut World, lua: &Lua, frame: u64) -> Result<()> {
+        self.replay.seek(
+            &mut self.world,
+            &mut self.schedule,
+            lua,
+            frame,
+            self.input.as_ref(),
+        )?;
+        self.frame = Some(frame);
+        self.sync(live, lua)
+    }
+
+    /// Plays one frame, starting from frame 0 on the first call, and
+    /// updates the ghosts. Returns false once the replay has ended; the
+    /// ghosts then stay where the replay left them until `clear`.
+    pub fn step(&mut self, live: &mut World, lua: &Lua) -> Result<bool> {
+        match self.frame {
+            None => self.seek(live, lua, 0)?,
+            Some(frame) if frame < self.replay.frames() => {
+                self.replay.play_frame(
+                    &mut self.world,
+                    &mut self.schedule,
+                    lua,
+                    frame,
+                    self.input.as_ref(),
+                )?;
+                self.frame = Some(frame + 1);
+                self.sync(live, lua)?;
+            }
+            Some(_) => {}
+        }
+        Ok(!self.is_finished())
+    }
+
+    /// Despawns every ghost from `live`.
+    pub fn clear(&mut self, live: &mut World) {
+        for ghost in std::mem::take(&mut self.ghosts).into_values() {
+            live.despawn(ghost);
+        }
+    }
+
+    fn sync(&mut self, live: &mut World, lua: &Lua) -> Result<()> {
+        let world = &self.world;
+        self.ghosts.retain(|source, ghost| {
+            let keep = world.is_alive(*source) && live.is_alive(*ghost);
+            if !keep {
+                live.despawn(*ghost);
+            }
+            keep
+        });
+        for source in world.entities() {
+            let ghost = match self.ghosts.get(&source) {
+                Some(&ghost) => ghost,
+                None => {
+                    let ghost = live.spawn();
+                    live.insert(ghost, Ghost { source })?;
+                    self.ghosts.insert(source, ghost);
+                    ghost
+                }
+            };
+            for name in &self.components {
+                let value = world.get_by_name(lua, source, name)?;
+                live.set_by_name(lua, ghost, name, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::ScriptValue;
+    use crate::replay::ReplayRecorder;
+    use mlua::LuaSerdeExt;
+
+    #[test]
+    fn test_ghosts_mirror_chosen_components() -> Result<()> {
+        let lua = Lua::new();
+        let system: Function = lua
+            .load(
+                r#"
+                return function(world)
+                    for e in world:query({ "Pos" }) do
+                        local x = world:get(e, "Pos").x + 1
+                        if x > 3 then world:despawn(e) else world:set(e, "Pos", { x = x }) end
+                    end
+                end
+            "#,
+            )
+            .eval()?;
+        let input: Function = lua
+            .load("return function(world, frame, x) if x then world:spawn({ Pos = { x = x }, Solid = true }) end end")
+            .eval()?;
+
+        let mut recorded = World::new();
+        let mut schedule = Schedule::new();
+        schedule.add_lua_system("move", system.clone());
+        let mut recorder = ReplayRecorder::new(&recorded, &lua, 2)?;
+        for frame in 0..6 {
+            let x = (frame == 0).then_some(ScriptValue::Number(0.0));
+            recorder.record_frame(&recorded, &lua, x.clone())?;
+            let x = x.map(|x| lua.to_value(&x)).transpose()?;
+            lua.scope(|scope| {
+                let handle = scope.create_userdata_ref_mut(&mut recorded)?;
+                input.call::<()>((handle, frame as f64, x))
+            })?;
+            schedule.run(&mut recorded, &lua)?;
+        }
+
+        let mut live = World::new();
+        register_components(&mut live);
+        let mut player = GhostPlayer::new(recorder.finish(), &["Pos"]);
+        player.schedule_mut().add_lua_system("move", system);
+        player.set_input(input);
+
+        let mut xs = Vec::new();
+        while player.step(&mut live, &lua)? {
+            let ghosts: Vec<Entity> = player.ghosts().collect();
+            xs.push(match ghosts.as_slice() {
+                [ghost] => {
+                    assert!(live.has::<Ghost>(*ghost));
+                    assert!(!live.has_by_name(*ghost, "Solid"));
+                    live.get_by_name(&lua, *ghost, "Pos")?
+                        .as_table()
+                        .map(|pos| pos.get::<f64>("x"))
+                        .transpose()?
+                }
+                _ => None,
+            });
+        }
+        assert_eq!(xs, [None, Some(1.0), Some(2.0), Some(3.0), None, None]);
+        assert_eq!(live.len(), 0);
+
+        player.seek(&mut live, &lua, 2)?;
+        assert_eq!(live.len(), 1);
+        player.clear(&mut live);
+        assert_eq!(live.len(), 0);
+        Ok(())
+    }
+}
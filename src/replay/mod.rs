@@ -0,0 +1,558 @@
+//! Replays: the world at the first frame, every frame's input after it,
+//! and a keyframe snapshot every few frames so playback can start from the
+//! nearest one instead of simulating from the beginning.
+//!
+//! Snapshots hold what `Scene::capture` sees plus the entity allocator, so
+//! entities come back under the same ids and later spawns reuse the same
+//! slots, and the resources given to `register_resource`, such as the
+//! game's `GameRng`. Other resources and Lua globals are not recorded, so
+//! systems should keep the rest of their state in components.
+
+mod ghost;
+
+pub use ghost::{Ghost, GhostPlayer, register_components};
+
+use crate::data::DataError;
+use crate::ecs::{Entities, Schedule, ScriptValue, World};
+use crate::scene::Scene;
+use mlua::{Error, Function, Lua, LuaSerdeExt, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub const MAGIC: &[u8; 4] = b"EERP";
+/// Version 1 replays predate recorded resources, and still load.
+const VERSION: u8 = 2;
+const INPUT_COMPONENT: &str = "input";
+
+type CaptureFn = fn(&World) -> std::result::Result<Option<String>, DataError>;
+type RestoreFn = fn(&mut World, Option<&str>) -> std::result::Result<(), DataError>;
+
+/// The resources keyframes record, by name. Lives in the world, so the
+/// recording and the playback world each carry their own list.
+#[derive(Default)]
+struct ReplayResources(BTreeMap<String, (CaptureFn, RestoreFn)>);
+
+/// Records the world's `R` resource in every keyframe under `name`, as
+/// RON. The world a replay is restored into needs the same registrations.
+pub fn register_resource<R>(world: &mut World, name: &str)
+where
+    R: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn capture<R: Serialize + Send + Sync + 'static>(
+        world: &World,
+    ) -> std::result::Result<Option<String>, DataError> {
+        world
+            .resource::<R>()
+            .map(|resource| ron::to_string(resource).map_err(|e| DataError::Invalid(e.to_string())))
+            .transpose()
+    }
+
+    fn restore<R: DeserializeOwned + Send + Sync + 'static>(
+        world: &mut World,
+        saved: Option<&str>,
+    ) -> std::result::Result<(), DataError> {
+        match saved {
+            Some(saved) => {
+                world.insert_resource(ron::from_str::<R>(saved)?);
+            }
+            None => {
+                world.remove_resource::<R>();
+            }
+        }
+        Ok(())
+    }
+
+    if world.resource::<ReplayResources>().is_none() {
+        world.insert_resource(ReplayResources::default());
+    }
+    world
+        .resource_mut::<ReplayResources>()
+        .expect("replay resources were just set")
+        .0
+        .insert(name.to_string(), (capture::<R>, restore::<R>));
+}
+
+/// The world as it was at the start of `frame`, before that frame's input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframe {
+    pub frame: u64,
+    pub tick: u32,
+    generations: Vec<u32>,
+    free: Vec<u32>,
+    pub scene: Scene,
+    /// Registered resources by name, as RON; absent ones are left out.
+    pub resources: BTreeMap<String, String>,
+}
+
+impl Keyframe {
+    pub fn capture(world: &World, lua: &Lua, frame: u64) -> Result<Self> {
+        let (generations, free) = world.allocator().parts();
+        let mut resources = BTreeMap::new();
+        for (name, (capture, _)) in world
+            .resource::<ReplayResources>()
+            .into_iter()
+            .flat_map(|r| &r.0)
+        {
+            if let Some(saved) = capture(world)? {
+                resources.insert(name.clone(), saved);
+            }
+        }
+        Ok(Keyframe {
+            frame,
+            tick: world.tick(),
+            generations: generations.to_vec(),
+            free: free.to_vec(),
+            scene: Scene::capture(world, lua)?,
+            resources,
+        })
+    }
+
+    /// Despawns everything in `world` and puts the snapshot back, ids and
+    /// all, along with the registered resources. Commands queued by hooks
+    /// while doing so are dropped, since their effects are already part of
+    /// the snapshot.
+    pub fn restore(&self, world: &mut World, lua: &Lua) -> Result<()> {
+        for entity in world.entities().collect::<Vec<_>>() {
+            world.despawn(entity);
+        }
+        world.restore_allocator(
+            Entities::from_parts(self.generations.clone(), self.free.clone()),
+            self.tick,
+        );
+        let entities: Vec<_> = world.entities().collect();
+        for (entity, components) in entities.into_iter().zip(&self.scene.entities) {
+            for (name, value) in components {
+                world.set_by_name(lua, entity, name, lua.to_value(value)?)?;
+            }
+        }
+        let restores: Vec<(String, RestoreFn)> = world
+            .resource::<ReplayResources>()
+            .into_iter()
+            .flat_map(|r| &r.0)
+            .map(|(name, (_, restore))| (name.clone(), *restore))
+            .collect();
+        for (name, restore) in restores {
+            restore(world, self.resources.get(&name).map(String::as_str))?;
+        }
+        world.commands().take();
+        Ok(())
+    }
+}
+
+/// A recorded simulation. Frame `n`'s input is applied before the
+/// schedule's `n`th run, the same way `testing::simulate_frames` applies
+/// its input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    /// Script that sets up the systems the recording ran with, for
+    /// `replay play` to load.
+    pub script: Option<String>,
+    keyframe_interval: u64,
+    inputs: Vec<Option<ScriptValue>>,
+    /// Ascending by frame, starting with frame 0.
+    keyframes: Vec<Keyframe>,
+}
+
+impl Replay {
+    pub fn frames(&self) -> u64 {
+        self.inputs.len() as u64
+    }
+
+    pub fn keyframe_interval(&self) -> u64 {
+        self.keyframe_interval
+    }
+
+    pub fn input(&self, frame: u64) -> Option<&ScriptValue> {
+        self.inputs.get(frame as usize)?.as_ref()
+    }
+
+    pub fn initial(&self) -> &Keyframe {
+        &self.keyframes[0]
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// The latest keyframe at or before `frame`.
+    pub fn keyframe_before(&self, frame: u64) -> &Keyframe {
+        let after = self.keyframes.partition_point(|key| key.frame <= frame);
+        &self.keyframes[after.max(1) - 1]
+    }
+
+    /// Puts `world` in the state it had at the start of `frame`: restores
+    /// the nearest keyframe and simulates the frames after it. `input` is
+    /// called with the world, the frame and that frame's recorded input.
+    /// Returns the frame of the keyframe it started from.
+    pub fn seek(
+        &self,
+        world: &mut World,
+        schedule: &mut Schedule,
+        lua: &Lua,
+        frame: u64,
+        input: Option<&Function>,
+    ) -> Result<u64> {
+        if frame > self.frames() {
+            return Err(Error::runtime(format!(
+                "frame {} is past the end of the replay ({} frames)",
+                frame,
+                self.frames()
+            )));
+        }
+        let keyframe = self.keyframe_before(frame);
+        keyframe.restore(world, lua)?;
+        for current in keyframe.frame..frame {
+            self.play_frame(world, schedule, lua, current, input)?;
+        }
+        Ok(keyframe.frame)
+    }
+
+    /// Applies `frame`'s input and runs the schedule once.
+    pub fn play_frame(
+        &self,
+        world: &mut World,
+        schedule: &mut Schedule,
+        lua: &Lua,
+        frame: u64,
+        input: Option<&Function>,
+    ) -> Result<()> {
+        if let Some(input) = input {
+            let value = self
+                .input(frame)
+                .map(|value| lua.to_value(value))
+                .transpose()?;
+            lua.scope(|scope| {
+                let handle = scope.create_userdata_ref_mut(&mut *world)?;
+                input.call::<()>((handle, frame as f64, value))
+            })?;
+            world.apply_commands(lua)?;
+        }
+        schedule.run(world, lua)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        Replay::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        compress: bool,
+    ) -> std::result::Result<(), DataError> {
+        Ok(fs::write(path, self.to_bytes(compress)?)?)
+    }
+
+    /// Snapshots and inputs are stored as binary scenes, the inputs as one
+    /// entity per frame holding an `input` component or nothing.
+    pub fn to_bytes(&self, compress: bool) -> std::result::Result<Vec<u8>, DataError> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        out.extend_from_slice(&self.keyframe_interval.to_le_bytes());
+        let script = self.script.as_deref().unwrap_or("");
+        put_bytes(&mut out, script.as_bytes());
+        let inputs = Scene {
+            entities: self
+                .inputs
+                .iter()
+                .map(|input| {
+                    input
+                        .iter()
+                        .map(|value| (INPUT_COMPONENT.to_string(), value.clone()))
+                        .collect()
+                })
+                .collect(),
+        };
+        put_bytes(&mut out, &inputs.to_binary(compress)?);
+        out.extend_from_slice(&(self.keyframes.len() as u32).to_le_bytes());
+        for keyframe in &self.keyframes {
+            out.extend_from_slice(&keyframe.frame.to_le_bytes());
+            out.extend_from_slice(&keyframe.tick.to_le_bytes());
+            put_u32s(&mut out, &keyframe.generations);
+            put_u32s(&mut out, &keyframe.free);
+            put_bytes(&mut out, &keyframe.scene.to_binary(compress)?);
+            out.extend_from_slice(&(keyframe.resources.len() as u32).to_le_bytes());
+            for (name, saved) in &keyframe.resources {
+                put_bytes(&mut out, name.as_bytes());
+                put_bytes(&mut out, saved.as_bytes());
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> std::result::Result<Self, DataError> {
+        if !bytes.starts_with(MAGIC) || bytes.len() < 5 {
+            return Err(DataError::Invalid("not a replay".to_string()));
+        }
+        let version = bytes[4];
+        if version == 0 || version > VERSION {
+            return Err(DataError::Invalid(format!(
+                "unsupported replay version {}",
+                version
+            )));
+        }
+        let mut reader = Reader { bytes: &bytes[5..] };
+        let keyframe_interval = reader.u64()?;
+        let script = reader.string()?;
+        let inputs = Scene::from_bytes(reader.bytes()?)?
+            .entities
+            .into_iter()
+            .map(|mut entity| entity.remove(INPUT_COMPONENT))
+            .collect();
+        let count = reader.u32()?;
+        let mut keyframes: Vec<Keyframe> = Vec::with_capacity(count.min(1024) as usize);
+        for _ in 0..count {
+            let frame = reader.u64()?;
+            let tick = reader.u32()?;
+            let generations = reader.u32s()?;
+            let free = reader.u32s()?;
+            let scene = Scene::from_bytes(reader.bytes()?)?;
+            let mut resources = BTreeMap::new();
+            if version >= 2 {
+                for _ in 0..reader.u32()? {
+                    resources.insert(reader.string()?, reader.string()?);
+                }
+            }
+            let mut dead = vec![false; generations.len()];
+            for &index in &free {
+                match dead.get_mut(index as usize) {
+                    Some(seen @ false) => *seen = true,
+                    _ => return Err(DataError::Invalid("corrupt replay free list".to_string())),
+                }
+            }
+            if generations.len() - free.len() != scene.entities.len() {
+                return Err(DataError::Invalid(format!(
+                    "keyframe {} has {} entities but {} live ids",
+                    frame,
+                    scene.entities.len(),
+                    generations.len() - free.len()
+                )));
+            }
+            if keyframes.last().is_some_and(|last| last.frame >= frame) {
+                return Err(DataError::Invalid(
+                    "replay keyframes out of order".to_string(),
+                ));
+            }
+            keyframes.push(Keyframe {
+                frame,
+                tick,
+                generations,
+                free,
+                scene,
+                resources,
+            });
+        }
+        if keyframes.first().is_none_or(|first| first.frame != 0) {
+            return Err(DataError::Invalid(
+                "replay has no initial snapshot".to_string(),
+            ));
+        }
+        Ok(Replay {
+            script: (!script.is_empty()).then_some(script),
+            keyframe_interval,
+            inputs,
+            keyframes,
+        })
+    }
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn put_u32s(out: &mut Vec<u8>, values: &[u32]) {
+    out.extend_from_slice(&(values.len() as u32).to_le_bytes());
+    for value in values {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> std::result::Result<&'a [u8], DataError> {
+        if self.bytes.len() < n {
+            return Err(DataError::Invalid("replay is truncated".to_string()));
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> std::result::Result<u32, DataError> {
+        Ok(u32::from_le_bytes(
+            self.take(4)?.try_into().expect("took 4 bytes"),
+        ))
+    }
+
+    fn u64(&mut self) -> std::result::Result<u64, DataError> {
+        Ok(u64::from_le_bytes(
+            self.take(8)?.try_into().expect("took 8 bytes"),
+        ))
+    }
+
+    fn bytes(&mut self) -> std::result::Result<&'a [u8], DataError> {
+        let len = self.u64()?;
+        self.take(usize::try_from(len).unwrap_or(usize::MAX))
+    }
+
+    fn string(&mut self) -> std::result::Result<String, DataError> {
+        String::from_utf8(self.bytes()?.to_vec())
+            .map_err(|e| DataError::Invalid(format!("replay text is not UTF-8: {}", e)))
+    }
+
+    fn u32s(&mut self) -> std::result::Result<Vec<u32>, DataError> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len.saturating_mul(4))?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().expect("chunks of 4")))
+            .collect())
+    }
+}
+
+/// Builds a `Replay` while a game runs. Call `record_frame` once per
+/// frame, before that frame's input is applied.
+pub struct ReplayRecorder {
+    replay: Replay,
+}
+
+impl ReplayRecorder {
+    /// Snapshots `world` as frame 0. A `keyframe_interval` of 0 keeps only
+    /// that snapshot.
+    pub fn new(world: &World, lua: &Lua, keyframe_interval: u64) -> Result<Self> {
+        Ok(ReplayRecorder {
+            replay: Replay {
+                script: None,
+                keyframe_interval,
+                inputs: Vec::new(),
+                keyframes: vec![Keyframe::capture(world, lua, 0)?],
+            },
+        })
+    }
+
+    pub fn set_script(&mut self, script: impl Into<String>) {
+        self.replay.script = Some(script.into());
+    }
+
+    pub fn record_frame(
+        &mut self,
+        world: &World,
+        lua: &Lua,
+        input: Option<ScriptValue>,
+    ) -> Result<()> {
+        let frame = self.replay.frames();
+        let interval = self.replay.keyframe_interval;
+        if frame > 0 && interval > 0 && frame.is_multiple_of(interval) {
+            self.replay
+                .keyframes
+                .push(Keyframe::capture(world, lua, frame)?);
+        }
+        self.replay.inputs.push(input);
+        Ok(())
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.replay.frames()
+    }
+
+    pub fn finish(self) -> Replay {
+        self.replay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::GameRng;
+    use crate::testing::world_hash;
+
+    #[test]
+    fn test_seek_matches_straight_playback() -> Result<()> {
+        let lua = Lua::new();
+        let system: Function = lua
+            .load(
+                r#"
+                return function(world)
+                    for e in world:query({ "Pos" }) do
+                        local p = world:get(e, "Pos")
+                        if p.x > 20 then
+                            world:despawn(e)
+                        else
+                            world:set(e, "Pos", { x = p.x + p.vx, vx = p.vx })
+                        end
+                    end
+                end
+            "#,
+            )
+            .eval()?;
+        let input: Function = lua
+            .load("return function(world, frame, vx) if vx then world:spawn({ Pos = { x = 0, vx = vx } }) end end")
+            .eval()?;
+        let mut schedule = Schedule::new();
+        schedule.add_lua_system("move", system);
+        schedule.add_system("roll", |world| {
+            world.resource_mut::<GameRng>().unwrap().next_u64();
+            Ok(())
+        });
+
+        let mut world = World::new();
+        register_resource::<GameRng>(&mut world, "rng");
+        world.insert_resource(GameRng::new(u64::MAX - 7));
+        let mut recorder = ReplayRecorder::new(&world, &lua, 16)?;
+        recorder.set_script("game.lua");
+        let mut expected = Vec::new();
+        for frame in 0..50u64 {
+            expected.push((
+                world_hash(&world, &lua)?,
+                world.entities().collect::<Vec<_>>(),
+                world.resource::<GameRng>().cloned(),
+            ));
+            let vx = frame
+                .is_multiple_of(3)
+                .then_some(ScriptValue::Number(1.0 + (frame % 4) as f64));
+            recorder.record_frame(&world, &lua, vx.clone())?;
+            let vx = vx.map(|vx| lua.to_value(&vx)).transpose()?;
+            lua.scope(|scope| {
+                let handle = scope.create_userdata_ref_mut(&mut world)?;
+                input.call::<()>((handle, frame as f64, vx))
+            })?;
+            schedule.run(&mut world, &lua)?;
+        }
+        let replay = Replay::from_bytes(&recorder.finish().to_bytes(false).unwrap()).unwrap();
+        assert_eq!(replay.script.as_deref(), Some("game.lua"));
+        assert_eq!(replay.frames(), 50);
+        assert_eq!(
+            replay
+                .keyframes()
+                .iter()
+                .map(|k| k.frame)
+                .collect::<Vec<_>>(),
+            [0, 16, 32, 48]
+        );
+
+        let mut seeking = World::new();
+        register_resource::<GameRng>(&mut seeking, "rng");
+        for frame in [37, 5, 48, 16] {
+            let from = replay.seek(&mut seeking, &mut schedule, &lua, frame, Some(&input))?;
+            assert_eq!(from, frame / 16 * 16);
+            let state = (
+                world_hash(&seeking, &lua)?,
+                seeking.entities().collect::<Vec<_>>(),
+                seeking.resource::<GameRng>().cloned(),
+            );
+            assert_eq!(state, expected[frame as usize], "frame {}", frame);
+        }
+        assert!(
+            replay
+                .seek(&mut seeking, &mut schedule, &lua, 51, None)
+                .is_err()
+        );
+        Ok(())
+    }
+}
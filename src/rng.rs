@@ -0,0 +1,344 @@
+use crate::math::Vec2;
+use crate::physics::Aabb;
+use mlua::{Lua, LuaSerdeExt, Result, Table, UserData, UserDataMethods, Value};
+use serde::{Deserialize, Serialize};
+
+/// Small deterministic generator (SplitMix64) so that seeded content such as
+/// noise tables reproduces identically across platforms. Serializes as
+/// its state, so replays and saves can carry it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameRng {
+    state: u64,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform float in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    /// Uniform integer in `[0, bound)`; `bound` must be non-zero.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+
+    /// Uniform integer in `[min, max]`; `max` must not be below `min`.
+    pub fn int(&mut self, min: i64, max: i64) -> i64 {
+        let span = max.wrapping_sub(min) as u64;
+        let offset = match span.checked_add(1) {
+            Some(bound) => self.below(bound),
+            None => self.next_u64(),
+        };
+        min.wrapping_add(offset as i64)
+    }
+
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.next_f64()
+    }
+
+    pub fn chance(&mut self, probability: f64) -> bool {
+        self.next_f64() < probability
+    }
+
+    /// An index picked in proportion to `weights`; `None` when no weight
+    /// is positive. Negative and non-finite weights count as zero.
+    pub fn weighted(&mut self, weights: &[f64]) -> Option<usize> {
+        let weight = |w: f64| if w.is_finite() && w > 0.0 { w } else { 0.0 };
+        let total: f64 = weights.iter().map(|&w| weight(w)).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut roll = self.next_f64() * total;
+        let last = weights.iter().rposition(|&w| weight(w) > 0.0)?;
+        for (i, &w) in weights.iter().enumerate().take(last) {
+            roll -= weight(w);
+            if roll < 0.0 {
+                return Some(i);
+            }
+        }
+        Some(last)
+    }
+
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+        items.get(self.below(items.len() as u64) as usize)
+    }
+
+    /// Fisher-Yates, so every order is equally likely.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+
+    /// Gaussian sample by the Box-Muller transform.
+    pub fn normal(&mut self, mean: f64, std_dev: f64) -> f64 {
+        let u = 1.0 - self.next_f64();
+        let v = self.next_f64();
+        mean + std_dev * (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+    }
+
+    /// How many events happen in an interval that averages `mean` of
+    /// them. Counts past 30 come from the normal approximation.
+    pub fn poisson(&mut self, mean: f64) -> u64 {
+        if mean <= 0.0 {
+            return 0;
+        }
+        if mean > 30.0 {
+            return self.normal(mean, mean.sqrt()).round().max(0.0) as u64;
+        }
+        let limit = (-mean).exp();
+        let mut product = self.next_f64();
+        let mut count = 0;
+        while product > limit {
+            product *= self.next_f64();
+            count += 1;
+        }
+        count
+    }
+
+    /// Uniform over the disc, not bunched at the center.
+    pub fn in_circle(&mut self, center: Vec2, radius: f64) -> Vec2 {
+        self.in_annulus(center, 0.0, radius)
+    }
+
+    /// Uniform over the ring between `inner` and `outer`.
+    pub fn in_annulus(&mut self, center: Vec2, inner: f64, outer: f64) -> Vec2 {
+        let angle = self.range(0.0, std::f64::consts::TAU);
+        let distance = self.range(inner * inner, outer * outer).sqrt();
+        center + Vec2::new(angle.cos(), angle.sin()) * distance
+    }
+
+    pub fn in_aabb(&mut self, bounds: &Aabb) -> Vec2 {
+        Vec2::new(
+            self.range(bounds.min.x, bounds.max.x),
+            self.range(bounds.min.y, bounds.max.y),
+        )
+    }
+
+    /// `count` values evenly spaced over `min..max`, each nudged by up to
+    /// `jitter` of the spacing either way: spread out like a grid without
+    /// looking like one. `jitter` of 0.5 or less keeps them in order.
+    pub fn jittered(&mut self, min: f64, max: f64, count: usize, jitter: f64) -> Vec<f64> {
+        let step = (max - min) / count.max(1) as f64;
+        (0..count)
+            .map(|i| {
+                let nudge = self.range(-jitter, jitter) * step;
+                (min + (i as f64 + 0.5) * step + nudge).clamp(min, max)
+            })
+            .collect()
+    }
+
+    /// One point per `spacing`-sized cell of `bounds`, jittered the same
+    /// way; scatters props without the clumps of uniform sampling.
+    pub fn jittered_grid(&mut self, bounds: &Aabb, spacing: f64, jitter: f64) -> Vec<Vec2> {
+        if spacing <= 0.0 {
+            return Vec::new();
+        }
+        let size = bounds.max - bounds.min;
+        let columns = (size.x / spacing).floor().max(1.0) as usize;
+        let rows = (size.y / spacing).floor().max(1.0) as usize;
+        let cell = Vec2::new(size.x / columns as f64, size.y / rows as f64);
+        let mut points = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                let x = bounds.min.x + (column as f64 + 0.5 + self.range(-jitter, jitter)) * cell.x;
+                let y = bounds.min.y + (row as f64 + 0.5 + self.range(-jitter, jitter)) * cell.y;
+                points.push(Vec2::new(
+                    x.clamp(bounds.min.x, bounds.max.x),
+                    y.clamp(bounds.min.y, bounds.max.y),
+                ));
+            }
+        }
+        points
+    }
+}
+
+/// Scripts get generators from `rng.new(seed)`. `rng:weighted(weights)`
+/// returns a 1-based index and `rng:choose(items, weights?)` an item;
+/// `rng:shuffle(t)` shuffles the array in place and returns it. Points are
+/// `{ x, y }` tables: `rng:in_circle(center, r)`,
+/// `rng:in_annulus(center, inner, outer)`, `rng:in_aabb(min, max)` and
+/// `rng:jittered_grid(min, max, spacing, jitter)`.
+impl UserData for GameRng {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method_mut("next", |_, this, ()| Ok(this.next_f64()));
+        methods.add_method_mut("range", |_, this, (min, max): (f64, f64)| {
+            Ok(this.range(min, max))
+        });
+        methods.add_method_mut("int", |_, this, (min, max): (i64, i64)| {
+            if max < min {
+                return Err(mlua::Error::runtime("rng:int max is below min"));
+            }
+            Ok(this.int(min, max))
+        });
+        methods.add_method_mut("chance", |_, this, p: f64| Ok(this.chance(p)));
+        methods.add_method_mut("weighted", |_, this, weights: Vec<f64>| {
+            Ok(this.weighted(&weights).map(|i| i + 1))
+        });
+        methods.add_method_mut(
+            "choose",
+            |_, this, (items, weights): (Vec<Value>, Option<Vec<f64>>)| {
+                let index = match weights {
+                    Some(weights) => this.weighted(&weights[..weights.len().min(items.len())]),
+                    None if items.is_empty() => None,
+                    None => Some(this.below(items.len() as u64) as usize),
+                };
+                Ok(index.map_or(Value::Nil, |i| items[i].clone()))
+            },
+        );
+        methods.add_method_mut("shuffle", |_, this, list: Table| {
+            let mut items: Vec<Value> = list.sequence_values().collect::<Result<_>>()?;
+            this.shuffle(&mut items);
+            for (i, item) in items.into_iter().enumerate() {
+                list.raw_set(i + 1, item)?;
+            }
+            Ok(list)
+        });
+        methods.add_method_mut(
+            "normal",
+            |_, this, (mean, std_dev): (Option<f64>, Option<f64>)| {
+                Ok(this.normal(mean.unwrap_or(0.0), std_dev.unwrap_or(1.0)))
+            },
+        );
+        methods.add_method_mut("poisson", |_, this, mean: f64| Ok(this.poisson(mean)));
+        methods.add_method_mut("in_circle", |lua, this, (center, radius): (Value, f64)| {
+            lua.to_value(&this.in_circle(lua.from_value(center)?, radius))
+        });
+        methods.add_method_mut(
+            "in_annulus",
+            |lua, this, (center, inner, outer): (Value, f64, f64)| {
+                lua.to_value(&this.in_annulus(lua.from_value(center)?, inner, outer))
+            },
+        );
+        methods.add_method_mut("in_aabb", |lua, this, (min, max): (Value, Value)| {
+            let bounds = Aabb::new(lua.from_value(min)?, lua.from_value(max)?);
+            lua.to_value(&this.in_aabb(&bounds))
+        });
+        methods.add_method_mut(
+            "jittered",
+            |_, this, (min, max, count, jitter): (f64, f64, usize, f64)| {
+                Ok(this.jittered(min, max, count, jitter))
+            },
+        );
+        methods.add_method_mut(
+            "jittered_grid",
+            |lua, this, (min, max, spacing, jitter): (Value, Value, f64, f64)| {
+                let bounds = Aabb::new(lua.from_value(min)?, lua.from_value(max)?);
+                lua.to_value(&this.jittered_grid(&bounds, spacing, jitter))
+            },
+        );
+    }
+}
+
+pub fn register(lua: &Lua) -> Result<()> {
+    let rng = lua.create_table()?;
+    rng.set(
+        "new",
+        lua.create_function(|_, seed: u64| Ok(GameRng::new(seed)))?,
+    )?;
+    lua.globals().set("rng", rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_sequences_repeat() {
+        let mut a = GameRng::new(42);
+        let mut b = GameRng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+
+        let mut rng = GameRng::new(7);
+        for _ in 0..1000 {
+            let value = rng.next_f64();
+            assert!((0.0..1.0).contains(&value));
+            assert!(rng.below(10) < 10);
+            assert!((-3..=3).contains(&rng.int(-3, 3)));
+        }
+        assert_eq!(rng.int(i64::MAX, i64::MAX), i64::MAX);
+        let _ = rng.int(i64::MIN, i64::MAX);
+        assert!(rng.int(i64::MIN, i64::MIN + 1) <= i64::MIN + 1);
+        assert!(rng.int(-1, i64::MAX) >= -1);
+    }
+
+    #[test]
+    fn test_distributions() {
+        let mut rng = GameRng::new(3);
+        let mut counts = [0; 3];
+        for _ in 0..4000 {
+            counts[rng.weighted(&[1.0, 0.0, 3.0]).unwrap()] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        assert!((2800..3200).contains(&counts[2]));
+        assert_eq!(rng.weighted(&[0.0, -1.0]), None);
+
+        let mut deck: Vec<u32> = (0..20).collect();
+        rng.shuffle(&mut deck);
+        assert_ne!(deck, (0..20).collect::<Vec<_>>());
+        deck.sort();
+        assert_eq!(deck, (0..20).collect::<Vec<_>>());
+
+        let mean = (0..4000).map(|_| rng.normal(10.0, 2.0)).sum::<f64>() / 4000.0;
+        assert!((mean - 10.0).abs() < 0.2);
+        let mean = (0..4000).map(|_| rng.poisson(4.0)).sum::<u64>() as f64 / 4000.0;
+        assert!((mean - 4.0).abs() < 0.2);
+
+        for _ in 0..100 {
+            let distance = rng.in_annulus(Vec2::new(5.0, 5.0), 2.0, 3.0);
+            let distance = (distance - Vec2::new(5.0, 5.0)).length();
+            assert!((2.0..=3.0).contains(&distance));
+        }
+        let values = rng.jittered(0.0, 10.0, 5, 0.4);
+        assert!(values.windows(2).all(|w| w[0] < w[1]));
+        assert!((values[0] - 1.0).abs() <= 0.8);
+        let bounds = Aabb::new(Vec2::ZERO, Vec2::new(10.0, 4.0));
+        let points = rng.jittered_grid(&bounds, 2.0, 0.5);
+        assert_eq!(points.len(), 10);
+        assert!(points.iter().all(|&p| bounds.contains(p)));
+    }
+
+    #[test]
+    fn test_lua_rng() -> Result<()> {
+        let lua = Lua::new();
+        register(&lua)?;
+        lua.load(
+            r#"
+            local r = rng.new(9)
+            assert(r:choose({ "common", "rare" }, { 1, 0 }) == "common")
+            assert(r:weighted({ 0, 5 }) == 2)
+            local t = r:shuffle({ 1, 2, 3, 4 })
+            assert(#t == 4)
+            local p = r:in_circle({ x = 0, y = 0 }, 1)
+            assert(p.x * p.x + p.y * p.y <= 1)
+            local n = r:int(1, 6)
+            assert(n >= 1 and n <= 6)
+        "#,
+        )
+        .exec()
+    }
+}
@@ -0,0 +1,268 @@
+use crate::data::{DataError, from_ron, load_ron};
+use mlua::{Error, Lua, Result, Table, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
+
+/// Limits on what scripts may reach outside the engine.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxConfig {
+    /// Hosts `net.http` may contact. A domain also allows its subdomains.
+    pub http_allowlist: Vec<String>,
+}
+
+impl SandboxConfig {
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        load_ron(path)
+    }
+
+    pub fn allows_host(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.http_allowlist.iter().any(|domain| {
+            let domain = domain.to_ascii_lowercase();
+            host == domain
+                || host
+                    .strip_suffix(&domain)
+                    .is_some_and(|rest| rest.ends_with('.'))
+        })
+    }
+}
+
+/// Something a mod must declare in its manifest before it can use it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Network,
+    Storage,
+    SpawnEntities,
+    ReadInput,
+}
+
+impl Capability {
+    /// Globals left out of environments without the capability. The
+    /// engine registers no input bindings; an embedder's should check
+    /// `ReadInput` with `require_capability`.
+    pub fn globals(self) -> &'static [&'static str] {
+        match self {
+            Capability::Network => &["net", "lobby", "analytics", "platform"],
+            Capability::Storage => &["storage"],
+            Capability::SpawnEntities => &[],
+            Capability::ReadInput => &[],
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Capability::Network => "network",
+            Capability::Storage => "storage",
+            Capability::SpawnEntities => "spawn_entities",
+            Capability::ReadInput => "read_input",
+        })
+    }
+}
+
+/// What a mod ships in `mod.ron`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModManifest {
+    pub name: String,
+    pub version: String,
+    pub capabilities: BTreeSet<Capability>,
+    /// Mods that must be enabled and load first.
+    pub dependencies: Vec<String>,
+    /// Mods that load first when they are enabled, but aren't required.
+    pub load_after: Vec<String>,
+}
+
+impl ModManifest {
+    pub fn from_ron(source: &str) -> std::result::Result<Self, DataError> {
+        from_ron(source)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        load_ron(path)
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+/// Globals no mod gets: the fenv functions and `loadstring` reach the
+/// real globals, and the rest run, inspect or reconfigure anything.
+const ENGINE_ONLY: &[&str] = &[
+    "getfenv",
+    "setfenv",
+    "loadstring",
+    "console",
+    "cvar",
+    "debugger",
+];
+
+/// Mod environments by mod name, so bindings can tell who called.
+#[derive(Default)]
+struct ModEnvironments {
+    by_mod: HashMap<String, (Table, ModManifest)>,
+}
+
+/// Builds the globals table a mod's chunks run in: every current global
+/// except the engine-only ones and those gated behind capabilities the
+/// manifest doesn't declare. Globals registered afterwards don't reach
+/// existing environments. Run the mod's code with
+/// `Chunk::set_environment`, and call `release_mod_environment` when the
+/// mod unloads.
+pub fn mod_environment(lua: &Lua, manifest: &ModManifest) -> Result<Table> {
+    let denied: BTreeSet<&str> = [
+        Capability::Network,
+        Capability::Storage,
+        Capability::SpawnEntities,
+        Capability::ReadInput,
+    ]
+    .into_iter()
+    .filter(|&capability| !manifest.allows(capability))
+    .flat_map(Capability::globals)
+    .chain(ENGINE_ONLY)
+    .copied()
+    .collect();
+    let env = lua.create_table()?;
+    for pair in lua.globals().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        if let Value::String(name) = &key
+            && denied.contains(&*name.to_str()?)
+        {
+            continue;
+        }
+        env.raw_set(key, value)?;
+    }
+    env.raw_set("_G", &env)?;
+    if lua.app_data_ref::<ModEnvironments>().is_none() {
+        lua.set_app_data(ModEnvironments::default());
+    }
+    lua.app_data_mut::<ModEnvironments>()
+        .expect("environments were just set")
+        .by_mod
+        .insert(manifest.name.clone(), (env.clone(), manifest.clone()));
+    Ok(env)
+}
+
+/// Forgets the environment `mod_environment` built for the named mod.
+pub fn release_mod_environment(lua: &Lua, name: &str) {
+    if let Some(mut mods) = lua.app_data_mut::<ModEnvironments>() {
+        mods.by_mod.remove(name);
+    }
+}
+
+/// The manifest of the mod whose environment the nearest calling script
+/// runs in; `None` for scripts in the global environment.
+fn calling_mod(lua: &Lua) -> Option<ModManifest> {
+    let mods = lua.app_data_ref::<ModEnvironments>()?;
+    for level in 1.. {
+        let env = lua.inspect_stack(level, |debug| {
+            let source = debug.source();
+            let glue = source
+                .source
+                .is_some_and(|name| name.trim_start_matches('=').starts_with("__mlua"));
+            (source.what != "C" && !glue).then(|| debug.function().environment())
+        })?;
+        let Some(env) = env else {
+            continue;
+        };
+        let env = env?;
+        return mods
+            .by_mod
+            .values()
+            .find(|(table, _)| table.to_pointer() == env.to_pointer())
+            .map(|(_, manifest)| manifest.clone());
+    }
+    None
+}
+
+/// Fails when the nearest calling script runs in a mod environment
+/// without `capability`. Scripts in the global environment may do
+/// anything.
+pub fn require_capability(lua: &Lua, capability: Capability, binding: &str) -> Result<()> {
+    match calling_mod(lua) {
+        Some(manifest) if !manifest.allows(capability) => Err(Error::RuntimeError(format!(
+            "{} needs the '{}' capability, which mod '{}' does not declare",
+            binding, capability, manifest.name
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Fails when the nearest calling script runs in any mod environment, for
+/// bindings that belong to the player or the game, such as settings.
+pub fn require_game_script(lua: &Lua, binding: &str) -> Result<()> {
+    match calling_mod(lua) {
+        Some(manifest) => Err(Error::RuntimeError(format!(
+            "{} is not available to mods, so mod '{}' cannot use it",
+            binding, manifest.name
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::World;
+
+    #[test]
+    fn test_mod_capabilities() -> Result<()> {
+        let lua = Lua::new();
+        lua.globals().set("net", lua.create_table()?)?;
+        lua.globals().set("storage", lua.create_table()?)?;
+        let trusted =
+            ModManifest::from_ron(r#"(name: "builder", capabilities: [spawn_entities, storage])"#)
+                .unwrap();
+        let untrusted = ModManifest {
+            name: "cosmetics".to_string(),
+            ..ModManifest::default()
+        };
+
+        let mut world = World::new();
+        let source = r#"
+            local world = ...
+            local ok, err = pcall(function() return world:spawn({}) end)
+            return net ~= nil, storage ~= nil, ok, tostring(err)
+        "#;
+        let run =
+            |world: &mut World, manifest: &ModManifest| -> Result<(bool, bool, bool, String)> {
+                let env = mod_environment(&lua, manifest)?;
+                lua.scope(|scope| {
+                    let world = scope.create_userdata_ref_mut(world)?;
+                    lua.load(source).set_environment(env).call(world)
+                })
+            };
+        let (net, storage, spawned, _) = run(&mut world, &trusted)?;
+        assert_eq!((net, storage, spawned), (false, true, true));
+        let (net, storage, spawned, err) = run(&mut world, &untrusted)?;
+        assert_eq!((net, storage, spawned), (false, false, false));
+        assert!(err.contains("'spawn_entities' capability"), "{}", err);
+        assert_eq!(world.len(), 1);
+
+        // Nothing hands back the real globals.
+        lua.globals().set("console", lua.create_table()?)?;
+        let env = mod_environment(&lua, &trusted)?;
+        let sealed: bool = lua
+            .load(
+                "return getfenv == nil and setfenv == nil and loadstring == nil and console == nil",
+            )
+            .set_environment(env)
+            .eval()?;
+        assert!(sealed);
+
+        // The engine's own scripts are unrestricted.
+        lua.scope(|scope| {
+            let world = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load("local world = ... world:spawn({})")
+                .call::<()>(world)
+        })?;
+        assert_eq!(world.len(), 2);
+        Ok(())
+    }
+}
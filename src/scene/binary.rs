@@ -0,0 +1,253 @@
+//! Compact binary scenes: little-endian, every string stored once in a
+//! table up front, with the body optionally zstd-compressed.
+
+use super::{Scene, SceneEntity};
+use crate::data::DataError;
+use crate::ecs::ScriptValue;
+use std::collections::{BTreeMap, HashMap};
+
+pub const MAGIC: &[u8; 4] = b"EESC";
+const VERSION: u8 = 1;
+const FLAG_ZSTD: u8 = 1;
+
+const TAG_FALSE: u8 = 0;
+const TAG_TRUE: u8 = 1;
+const TAG_NUMBER: u8 = 2;
+const TAG_STRING: u8 = 3;
+const TAG_LIST: u8 = 4;
+const TAG_MAP: u8 = 5;
+
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+#[derive(Default)]
+struct Strings<'a> {
+    indices: HashMap<&'a str, u32>,
+    table: Vec<&'a str>,
+}
+
+impl<'a> Strings<'a> {
+    fn intern(&mut self, s: &'a str) -> u32 {
+        *self.indices.entry(s).or_insert_with(|| {
+            self.table.push(s);
+            (self.table.len() - 1) as u32
+        })
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, n: usize) {
+    out.extend_from_slice(&(n as u32).to_le_bytes());
+}
+
+fn encode_value<'a>(value: &'a ScriptValue, strings: &mut Strings<'a>, out: &mut Vec<u8>) {
+    match value {
+        ScriptValue::Bool(false) => out.push(TAG_FALSE),
+        ScriptValue::Bool(true) => out.push(TAG_TRUE),
+        ScriptValue::Number(n) => {
+            out.push(TAG_NUMBER);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+        ScriptValue::String(s) => {
+            out.push(TAG_STRING);
+            put_u32(out, strings.intern(s) as usize);
+        }
+        ScriptValue::List(items) => {
+            out.push(TAG_LIST);
+            put_u32(out, items.len());
+            for item in items {
+                encode_value(item, strings, out);
+            }
+        }
+        ScriptValue::Map(fields) => {
+            out.push(TAG_MAP);
+            put_u32(out, fields.len());
+            for (key, item) in fields {
+                put_u32(out, strings.intern(key) as usize);
+                encode_value(item, strings, out);
+            }
+        }
+    }
+}
+
+pub fn encode(scene: &Scene, compress: bool) -> Result<Vec<u8>, DataError> {
+    let mut strings = Strings::default();
+    let mut entities = Vec::new();
+    put_u32(&mut entities, scene.entities.len());
+    for entity in &scene.entities {
+        put_u32(&mut entities, entity.len());
+        for (name, value) in entity {
+            put_u32(&mut entities, strings.intern(name) as usize);
+            encode_value(value, &mut strings, &mut entities);
+        }
+    }
+
+    let mut body = Vec::with_capacity(entities.len());
+    put_u32(&mut body, strings.table.len());
+    for s in &strings.table {
+        put_u32(&mut body, s.len());
+        body.extend_from_slice(s.as_bytes());
+    }
+    body.extend_from_slice(&entities);
+
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    if compress {
+        out.push(FLAG_ZSTD);
+        out.extend_from_slice(&compress_body(&body)?);
+    } else {
+        out.push(0);
+        out.extend_from_slice(&body);
+    }
+    Ok(out)
+}
+
+#[cfg(feature = "zstd")]
+pub(crate) fn compress_body(body: &[u8]) -> Result<Vec<u8>, DataError> {
+    Ok(zstd::encode_all(body, 0)?)
+}
+
+#[cfg(feature = "zstd")]
+pub(crate) fn decompress_body(body: &[u8]) -> Result<Vec<u8>, DataError> {
+    Ok(zstd::decode_all(body)?)
+}
+
+#[cfg(not(feature = "zstd"))]
+pub(crate) fn compress_body(_body: &[u8]) -> Result<Vec<u8>, DataError> {
+    Err(DataError::Invalid(
+        "built without the zstd feature".to_string(),
+    ))
+}
+
+#[cfg(not(feature = "zstd"))]
+pub(crate) fn decompress_body(_body: &[u8]) -> Result<Vec<u8>, DataError> {
+    compress_body(&[])
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    strings: Vec<String>,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], DataError> {
+        if self.bytes.len() < n {
+            return Err(DataError::Invalid("binary scene is truncated".to_string()));
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, DataError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, DataError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().expect("took 4 bytes")) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, DataError> {
+        let index = self.u32()?;
+        self.strings
+            .get(index)
+            .cloned()
+            .ok_or_else(|| DataError::Invalid(format!("string index {} out of range", index)))
+    }
+
+    fn value(&mut self) -> Result<ScriptValue, DataError> {
+        Ok(match self.u8()? {
+            TAG_FALSE => ScriptValue::Bool(false),
+            TAG_TRUE => ScriptValue::Bool(true),
+            TAG_NUMBER => {
+                let bytes = self.take(8)?;
+                ScriptValue::Number(f64::from_le_bytes(bytes.try_into().expect("took 8 bytes")))
+            }
+            TAG_STRING => ScriptValue::String(self.string()?),
+            TAG_LIST => {
+                let len = self.u32()?;
+                ScriptValue::List((0..len).map(|_| self.value()).collect::<Result<_, _>>()?)
+            }
+            TAG_MAP => {
+                let len = self.u32()?;
+                let mut fields = BTreeMap::new();
+                for _ in 0..len {
+                    let key = self.string()?;
+                    fields.insert(key, self.value()?);
+                }
+                ScriptValue::Map(fields)
+            }
+            tag => return Err(DataError::Invalid(format!("unknown value tag {}", tag))),
+        })
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Scene, DataError> {
+    if !is_binary(bytes) || bytes.len() < 6 {
+        return Err(DataError::Invalid("not a binary scene".to_string()));
+    }
+    if bytes[4] != VERSION {
+        return Err(DataError::Invalid(format!(
+            "unsupported binary scene version {}",
+            bytes[4]
+        )));
+    }
+    let body = match bytes[5] {
+        0 => bytes[6..].to_vec(),
+        FLAG_ZSTD => decompress_body(&bytes[6..])?,
+        flags => return Err(DataError::Invalid(format!("unknown scene flags {}", flags))),
+    };
+
+    let mut reader = Reader {
+        bytes: &body,
+        strings: Vec::new(),
+    };
+    let count = reader.u32()?;
+    for _ in 0..count {
+        let len = reader.u32()?;
+        let s = std::str::from_utf8(reader.take(len)?)
+            .map_err(|e| DataError::Invalid(format!("string table is not UTF-8: {}", e)))?
+            .to_string();
+        reader.strings.push(s);
+    }
+
+    let count = reader.u32()?;
+    let mut entities = Vec::with_capacity(count.min(body.len()));
+    for _ in 0..count {
+        let len = reader.u32()?;
+        let mut entity = SceneEntity::new();
+        for _ in 0..len {
+            let name = reader.string()?;
+            entity.insert(name, reader.value()?);
+        }
+        entities.push(entity);
+    }
+    Ok(Scene { entities })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::from_ron;
+
+    #[test]
+    fn test_binary_round_trip() {
+        let scene: Scene = from_ron(
+            r#"(entities: [
+                { "Name": "Player", "Health": { "hp": 10.0 }, "Tags": ["hero", "hero"] },
+                { "Rock": true, "Health": { "hp": 1.5 } },
+            ])"#,
+        )
+        .unwrap();
+
+        for compress in [false, cfg!(feature = "zstd")] {
+            let bytes = encode(&scene, compress).unwrap();
+            assert!(is_binary(&bytes));
+            assert_eq!(decode(&bytes).unwrap(), scene);
+        }
+
+        let bytes = encode(&scene, false).unwrap();
+        assert!(decode(&bytes[..bytes.len() - 3]).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use super::{Scene, spawn_entity};
+use crate::data::DataError;
+use crate::ecs::{Entity, ScriptValue, World};
+use mlua::{Lua, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const PROGRESS_EVENT: &str = "scene_load_progress";
+pub const LOADED_EVENT: &str = "scene_loaded";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneLoadProgress {
+    pub scene: String,
+    pub loaded: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneLoaded {
+    pub scene: String,
+    pub entities: Vec<Entity>,
+}
+
+/// Spawns a scene a few entities per frame. Each step sends a
+/// `SceneLoadProgress` event and the last one a `SceneLoaded`; scripts see
+/// the same as `scene_load_progress` and `scene_loaded` script events.
+pub struct SceneLoader {
+    label: String,
+    scene: Scene,
+    per_frame: usize,
+    next: usize,
+    done: bool,
+    spawned: Vec<Entity>,
+}
+
+impl SceneLoader {
+    pub fn new(label: &str, scene: Scene, per_frame: usize) -> Self {
+        SceneLoader {
+            label: label.to_string(),
+            spawned: Vec::with_capacity(scene.entities.len()),
+            scene,
+            per_frame: per_frame.max(1),
+            next: 0,
+            done: false,
+        }
+    }
+
+    /// Reads the file up front; only spawning is spread across frames.
+    pub fn open(path: impl AsRef<Path>, per_frame: usize) -> std::result::Result<Self, DataError> {
+        let label = path.as_ref().display().to_string();
+        Ok(SceneLoader::new(&label, Scene::load(path)?, per_frame))
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn total(&self) -> usize {
+        self.scene.entities.len()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    pub fn progress(&self) -> f64 {
+        match self.total() {
+            0 => 1.0,
+            total => self.next as f64 / total as f64,
+        }
+    }
+
+    /// Spawns the next batch. Returns true once the scene has finished
+    /// loading; further calls do nothing. An entity that fails to spawn
+    /// fails the step, keeping those before it, and the next call starts
+    /// over from that entity.
+    pub fn step(&mut self, world: &mut World, lua: &Lua) -> Result<bool> {
+        if self.is_done() {
+            return Ok(true);
+        }
+
+        let end = (self.next + self.per_frame).min(self.total());
+        while self.next < end {
+            let entity = spawn_entity(world, lua, &self.scene.entities[self.next])?;
+            self.spawned.push(entity);
+            self.next += 1;
+        }
+        self.done = end == self.total();
+
+        let loaded = end;
+        let total = self.total();
+        world.send_event(SceneLoadProgress {
+            scene: self.label.clone(),
+            loaded,
+            total,
+        });
+        world.send_script_event(
+            PROGRESS_EVENT,
+            ScriptValue::Map(BTreeMap::from([
+                ("scene".to_string(), ScriptValue::String(self.label.clone())),
+                ("loaded".to_string(), ScriptValue::Number(loaded as f64)),
+                ("total".to_string(), ScriptValue::Number(total as f64)),
+            ])),
+        );
+
+        if !self.is_done() {
+            return Ok(false);
+        }
+        world.send_event(SceneLoaded {
+            scene: self.label.clone(),
+            entities: self.spawned.clone(),
+        });
+        world.send_script_event(
+            LOADED_EVENT,
+            ScriptValue::Map(BTreeMap::from([
+                ("scene".to_string(), ScriptValue::String(self.label.clone())),
+                (
+                    "count".to_string(),
+                    ScriptValue::Number(self.spawned.len() as f64),
+                ),
+            ])),
+        );
+        Ok(true)
+    }
+
+    /// An exclusive system stepping this loader once per schedule run.
+    pub fn into_system(mut self) -> impl FnMut(&mut World, &Lua) -> Result<()> {
+        move |world, lua| self.step(world, lua).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::from_ron;
+    use crate::ecs::Schedule;
+
+    #[test]
+    fn test_loader_spreads_spawning_over_frames() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let scene: Scene = from_ron(
+            r#"(entities: [{ "A": 1.0 }, { "A": 2.0 }, { "A": 3.0 }, { "A": 4.0 }, { "A": 5.0 }])"#,
+        )
+        .map_err(mlua::Error::external)?;
+
+        let mut schedule = Schedule::new();
+        schedule.add_exclusive_system("load", SceneLoader::new("level", scene, 2).into_system());
+        schedule.add_lua_system(
+            "loading screen",
+            lua.load(
+                r#"
+                return function(world)
+                    for _, p in world:poll("scene_load_progress") do
+                        last_progress = p.loaded / p.total
+                    end
+                    for _, done in world:poll("scene_loaded") do
+                        loaded_count = done.count
+                    end
+                end
+            "#,
+            )
+            .eval()?,
+        );
+
+        schedule.run(&mut world, &lua)?;
+        assert_eq!(world.len(), 2);
+        assert_eq!(lua.globals().get::<f64>("last_progress")?, 0.4);
+
+        schedule.run(&mut world, &lua)?;
+        schedule.run(&mut world, &lua)?;
+        schedule.run(&mut world, &lua)?;
+        assert_eq!(world.len(), 5);
+        assert_eq!(lua.globals().get::<u32>("loaded_count")?, 5);
+
+        let loaded = world.drain_events::<SceneLoaded>();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(world.drain_events::<SceneLoadProgress>().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_step_resumes_without_duplicates() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let scene: Scene =
+            from_ron(r#"(entities: [{ "A": 1.0 }, { "Name": { "bad": 1.0 } }, { "A": 3.0 }])"#)
+                .map_err(mlua::Error::external)?;
+        let mut loader = SceneLoader::new("level", scene, 3);
+        assert!(loader.step(&mut world, &lua).is_err());
+        assert_eq!(world.len(), 1);
+        loader.scene.entities[1] = BTreeMap::from([("A".to_string(), ScriptValue::Number(2.0))]);
+        assert!(loader.step(&mut world, &lua)?);
+        assert_eq!(world.len(), 3);
+        assert_eq!(world.drain_events::<SceneLoaded>()[0].entities.len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+pub mod binary;
+mod loader;
+mod patch;
+
+pub use loader::{LOADED_EVENT, PROGRESS_EVENT, SceneLoadProgress, SceneLoaded, SceneLoader};
+pub use patch::{SceneChange, ScenePatch, apply_patch, diff};
+
+use crate::assets::AssetSource;
+use crate::data::{DataError, from_ron};
+use crate::ecs::{Entity, ScriptValue, World};
+use mlua::{Lua, LuaSerdeExt, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Components of one scene entity, keyed by component name.
+pub type SceneEntity = BTreeMap<String, ScriptValue>;
+
+/// A list of entities stored by component name, so the same file can hold
+/// registered Rust components and script components alike.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+    #[serde(default)]
+    pub entities: Vec<SceneEntity>,
+}
+
+impl Scene {
+    /// Loads RON or binary scenes, told apart by the binary header.
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        Scene::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn load_from(source: &dyn AssetSource, path: &str) -> std::result::Result<Self, DataError> {
+        Scene::from_bytes(&source.read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> std::result::Result<Self, DataError> {
+        if binary::is_binary(bytes) {
+            return binary::decode(bytes);
+        }
+        let source = std::str::from_utf8(bytes)
+            .map_err(|e| DataError::Invalid(format!("scene is not UTF-8: {}", e)))?;
+        from_ron(source)
+    }
+
+    pub fn to_ron(&self) -> std::result::Result<String, DataError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| DataError::Invalid(e.to_string()))
+    }
+
+    pub fn to_binary(&self, compress: bool) -> std::result::Result<Vec<u8>, DataError> {
+        binary::encode(self, compress)
+    }
+
+    /// Every live entity with its registered, script and name components.
+    pub fn capture(world: &World, lua: &Lua) -> Result<Self> {
+        let entities = world
+            .entities()
+            .map(|entity| Scene::capture_entity(world, lua, entity))
+            .collect::<Result<_>>()?;
+        Ok(Scene { entities })
+    }
+
+    pub fn capture_entity(world: &World, lua: &Lua, entity: Entity) -> Result<SceneEntity> {
+        let mut components = SceneEntity::new();
+        if let Some(name) = world.name(entity) {
+            components.insert(
+                crate::ecs::NAME_COMPONENT.to_string(),
+                ScriptValue::String(name.to_string()),
+            );
+        }
+        for info in world.registry().iter() {
+            if let Some(value) = (info.get)(world, entity, lua)? {
+                components.insert(info.name.clone(), lua.from_value(value)?);
+            }
+        }
+        for name in world.script_component_names() {
+            if let Some(value) = world.script_component(entity, name) {
+                components.insert(name.to_string(), value.clone());
+            }
+        }
+        Ok(components)
+    }
+
+    /// Spawns every entity, or none if one of them fails.
+    pub fn spawn(&self, world: &mut World, lua: &Lua) -> Result<Vec<Entity>> {
+        let mut spawned = Vec::with_capacity(self.entities.len());
+        for components in &self.entities {
+            match spawn_entity(world, lua, components) {
+                Ok(entity) => spawned.push(entity),
+                Err(e) => {
+                    for entity in spawned {
+                        world.despawn(entity);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(spawned)
+    }
+}
+
+/// Spawns one entity, or none if a component fails to apply.
+pub fn spawn_entity(world: &mut World, lua: &Lua, components: &SceneEntity) -> Result<Entity> {
+    let entity = world.spawn();
+    let result = components
+        .iter()
+        .try_for_each(|(name, value)| world.set_by_name(lua, entity, name, lua.to_value(value)?));
+    if let Err(e) = result {
+        world.despawn(entity);
+        return Err(e);
+    }
+    Ok(entity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Health(f32);
+
+    #[test]
+    fn test_failed_spawn_leaves_no_entities() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.register_component::<Health>("Health");
+        let healthy = SceneEntity::from([("Health".to_string(), ScriptValue::Number(10.0))]);
+        let broken =
+            SceneEntity::from([("Health".to_string(), ScriptValue::String("full".into()))]);
+        let scene = Scene {
+            entities: vec![healthy.clone(), healthy, broken],
+        };
+        assert!(scene.spawn(&mut world, &lua).is_err());
+        assert!(world.is_empty());
+
+        let scene = Scene {
+            entities: scene.entities[..2].to_vec(),
+        };
+        assert_eq!(scene.spawn(&mut world, &lua)?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_capture_round_trips_through_ron_and_binary() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.register_component::<Health>("Health");
+        let scene = Scene {
+            entities: vec![SceneEntity::from([
+                ("Health".to_string(), ScriptValue::Number(10.0)),
+                (
+                    crate::ecs::NAME_COMPONENT.to_string(),
+                    ScriptValue::String("hero".into()),
+                ),
+            ])],
+        };
+        let spawned = scene.spawn(&mut world, &lua)?;
+        assert_eq!(world.name(spawned[0]), Some("hero"));
+
+        let captured = Scene::capture(&world, &lua)?;
+        assert_eq!(captured, scene);
+        let ron = captured.to_ron().unwrap();
+        assert_eq!(Scene::from_bytes(ron.as_bytes()).unwrap(), scene);
+        let binary = captured.to_binary(true).unwrap();
+        assert_eq!(Scene::from_bytes(&binary).unwrap(), scene);
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+use super::{Scene, SceneEntity};
+use crate::data::DataError;
+use crate::ecs::{NAME_COMPONENT, ScriptValue};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// One edit to a scene. Entities are addressed by their `Name` component
+/// with any `#` doubled, plus `#n` for the n-th repeat of a name, or as
+/// `#n` for the n-th unnamed entity.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SceneChange {
+    AddEntity {
+        key: String,
+        /// Where the entity sits in the patched scene.
+        index: usize,
+        components: SceneEntity,
+    },
+    RemoveEntity {
+        key: String,
+    },
+    SetComponent {
+        key: String,
+        component: String,
+        value: ScriptValue,
+    },
+    RemoveComponent {
+        key: String,
+        component: String,
+    },
+    SetField {
+        key: String,
+        component: String,
+        field: String,
+        value: ScriptValue,
+    },
+    RemoveField {
+        key: String,
+        component: String,
+        field: String,
+    },
+}
+
+impl fmt::Display for SceneChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneChange::AddEntity {
+                key, components, ..
+            } => {
+                let names: Vec<&str> = components.keys().map(String::as_str).collect();
+                write!(f, "+ {} [{}]", key, names.join(", "))
+            }
+            SceneChange::RemoveEntity { key } => write!(f, "- {}", key),
+            SceneChange::SetComponent {
+                key,
+                component,
+                value,
+            } => write!(f, "~ {}.{} = {}", key, component, short(value)),
+            SceneChange::RemoveComponent { key, component } => {
+                write!(f, "- {}.{}", key, component)
+            }
+            SceneChange::SetField {
+                key,
+                component,
+                field,
+                value,
+            } => write!(f, "~ {}.{}.{} = {}", key, component, field, short(value)),
+            SceneChange::RemoveField {
+                key,
+                component,
+                field,
+            } => write!(f, "- {}.{}.{}", key, component, field),
+        }
+    }
+}
+
+fn short(value: &ScriptValue) -> String {
+    ron::to_string(value).unwrap_or_else(|_| format!("{:?}", value))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScenePatch {
+    pub changes: Vec<SceneChange>,
+}
+
+impl ScenePatch {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for ScenePatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Stable, unique keys for every entity in `scene`, in order.
+fn keys(scene: &Scene) -> Vec<String> {
+    let mut unnamed = 0;
+    let mut repeats: BTreeMap<&str, usize> = BTreeMap::new();
+    scene
+        .entities
+        .iter()
+        .map(|entity| match entity.get(NAME_COMPONENT) {
+            Some(ScriptValue::String(name)) if !name.is_empty() => {
+                let key = name.replace('#', "##");
+                let seen = repeats.entry(name).or_default();
+                *seen += 1;
+                match *seen - 1 {
+                    0 => key,
+                    n => format!("{}#{}", key, n),
+                }
+            }
+            _ => {
+                unnamed += 1;
+                format!("#{}", unnamed - 1)
+            }
+        })
+        .collect()
+}
+
+fn index(scene: &Scene) -> BTreeMap<String, usize> {
+    keys(scene)
+        .into_iter()
+        .enumerate()
+        .map(|(i, key)| (key, i))
+        .collect()
+}
+
+fn diff_entity(key: &str, a: &SceneEntity, b: &SceneEntity, changes: &mut Vec<SceneChange>) {
+    for component in a.keys().filter(|name| !b.contains_key(*name)) {
+        changes.push(SceneChange::RemoveComponent {
+            key: key.to_string(),
+            component: component.clone(),
+        });
+    }
+    for (component, new) in b {
+        match (a.get(component), new) {
+            (Some(old), _) if old == new => {}
+            (Some(ScriptValue::Map(old)), ScriptValue::Map(new)) => {
+                for field in old.keys().filter(|field| !new.contains_key(*field)) {
+                    changes.push(SceneChange::RemoveField {
+                        key: key.to_string(),
+                        component: component.clone(),
+                        field: field.clone(),
+                    });
+                }
+                for (field, value) in new {
+                    if old.get(field) != Some(value) {
+                        changes.push(SceneChange::SetField {
+                            key: key.to_string(),
+                            component: component.clone(),
+                            field: field.clone(),
+                            value: value.clone(),
+                        });
+                    }
+                }
+            }
+            _ => changes.push(SceneChange::SetComponent {
+                key: key.to_string(),
+                component: component.clone(),
+                value: new.clone(),
+            }),
+        }
+    }
+}
+
+/// The changes that turn `a` into `b`.
+pub fn diff(a: &Scene, b: &Scene) -> ScenePatch {
+    let a_index = index(a);
+    let b_keys = keys(b);
+    let b_set: BTreeSet<&String> = b_keys.iter().collect();
+
+    let mut changes = Vec::new();
+    for (index, (key, entity)) in b_keys.iter().zip(&b.entities).enumerate() {
+        match a_index.get(key) {
+            Some(&i) => diff_entity(key, &a.entities[i], entity, &mut changes),
+            None => changes.push(SceneChange::AddEntity {
+                key: key.clone(),
+                index,
+                components: entity.clone(),
+            }),
+        }
+    }
+    for key in keys(a).into_iter().filter(|key| !b_set.contains(key)) {
+        changes.push(SceneChange::RemoveEntity { key });
+    }
+    ScenePatch { changes }
+}
+
+fn table_component<'a>(
+    entity: &'a mut SceneEntity,
+    key: &str,
+    component: &str,
+) -> Result<&'a mut BTreeMap<String, ScriptValue>, DataError> {
+    match entity.get_mut(component) {
+        Some(ScriptValue::Map(fields)) => Ok(fields),
+        _ => Err(DataError::Invalid(format!(
+            "'{}.{}' is not a table component",
+            key, component
+        ))),
+    }
+}
+
+/// Applies `patch` to a copy of `scene`. Keys resolve against `scene` as
+/// given, so removals don't shift the `#n` keys used by other changes.
+/// Added entities go back to their recorded index.
+pub fn apply_patch(scene: &Scene, patch: &ScenePatch) -> Result<Scene, DataError> {
+    let lookup = index(scene);
+    let resolve = |key: &str| {
+        lookup
+            .get(key)
+            .copied()
+            .ok_or_else(|| DataError::Invalid(format!("patch refers to unknown entity '{}'", key)))
+    };
+
+    let mut entities = scene.entities.clone();
+    let mut removed = BTreeSet::new();
+    let mut added = Vec::new();
+    for change in &patch.changes {
+        match change {
+            SceneChange::AddEntity {
+                index, components, ..
+            } => added.push((*index, components.clone())),
+            SceneChange::RemoveEntity { key } => {
+                removed.insert(resolve(key)?);
+            }
+            SceneChange::SetComponent {
+                key,
+                component,
+                value,
+            } => {
+                entities[resolve(key)?].insert(component.clone(), value.clone());
+            }
+            SceneChange::RemoveComponent { key, component } => {
+                entities[resolve(key)?].remove(component);
+            }
+            SceneChange::SetField {
+                key,
+                component,
+                field,
+                value,
+            } => {
+                table_component(&mut entities[resolve(key)?], key, component)?
+                    .insert(field.clone(), value.clone());
+            }
+            SceneChange::RemoveField {
+                key,
+                component,
+                field,
+            } => {
+                table_component(&mut entities[resolve(key)?], key, component)?.remove(field);
+            }
+        }
+    }
+
+    let mut entities: Vec<SceneEntity> = entities
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !removed.contains(i))
+        .map(|(_, entity)| entity)
+        .collect();
+    // In index order, so each lands where it was when the patch was made.
+    added.sort_by_key(|(index, _)| *index);
+    for (index, components) in added {
+        entities.insert(index.min(entities.len()), components);
+    }
+    Ok(Scene { entities })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::from_ron;
+
+    #[test]
+    fn test_diff_round_trips_through_patch() {
+        let a: Scene = from_ron(
+            r#"(entities: [
+                { "Name": "Player", "Health": { "hp": 10.0, "max": 10.0 } },
+                { "Rock": true },
+                { "Name": "Chest", "Loot": "gold" },
+            ])"#,
+        )
+        .unwrap();
+        let b: Scene = from_ron(
+            r#"(entities: [
+                { "Name": "Player", "Health": { "hp": 7.0 }, "Armor": 2.0 },
+                { "Name": "Door", "Locked": true },
+                { "Rock": true },
+            ])"#,
+        )
+        .unwrap();
+
+        let patch = diff(&a, &b);
+        let text = patch.to_string();
+        assert!(text.contains("~ Player.Health.hp = 7.0"), "{}", text);
+        assert!(text.contains("- Player.Health.max"), "{}", text);
+        assert!(text.contains("+ Door [Locked, Name]"), "{}", text);
+        assert!(text.contains("- Chest"), "{}", text);
+
+        let round_trip: ScenePatch = from_ron(&ron::to_string(&patch).unwrap()).unwrap();
+        assert_eq!(apply_patch(&a, &round_trip).unwrap(), b);
+        assert!(diff(&b, &b).is_empty());
+    }
+
+    #[test]
+    fn test_repeated_and_hash_names_stay_distinct() {
+        let a: Scene = from_ron(
+            r##"(entities: [
+                { "Name": "Rock", "hp": 1.0 },
+                { "Name": "Rock", "hp": 2.0 },
+                { "Name": "#0", "hp": 3.0 },
+                { "hp": 4.0 },
+            ])"##,
+        )
+        .unwrap();
+        let b: Scene = from_ron(
+            r##"(entities: [
+                { "Name": "Rock", "hp": 5.0 },
+                { "Name": "Rock", "hp": 2.0 },
+                { "Name": "#0", "hp": 3.0 },
+                { "hp": 6.0 },
+            ])"##,
+        )
+        .unwrap();
+        assert_eq!(keys(&a), ["Rock", "Rock#1", "##0", "#0"]);
+
+        let patch = diff(&a, &b);
+        assert_eq!(patch.changes.len(), 2, "{}", patch);
+        assert_eq!(apply_patch(&a, &patch).unwrap(), b);
+        assert_eq!(apply_patch(&b, &diff(&b, &a)).unwrap(), a);
+    }
+}
@@ -0,0 +1,204 @@
+use super::{Rect, SpriteAnimation, SpriteFrame, SpriteSheet};
+use crate::assets::AssetSource;
+use crate::data::DataError;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct Export {
+    frames: Frames,
+    meta: Meta,
+}
+
+/// Aseprite writes frames as a list or as a map keyed by file name,
+/// depending on the "Array"/"Hash" export option.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Frames {
+    Array(Vec<Frame>),
+    Hash(BTreeMap<String, Frame>),
+}
+
+#[derive(Deserialize)]
+struct Frame {
+    #[serde(default)]
+    filename: String,
+    frame: Rect,
+    /// Milliseconds.
+    duration: f64,
+}
+
+#[derive(Deserialize)]
+struct Meta {
+    #[serde(default)]
+    image: String,
+    #[serde(default, rename = "frameTags")]
+    frame_tags: Vec<Tag>,
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: String,
+    /// Written as a string, and only when set in the tag properties.
+    #[serde(default)]
+    repeat: Option<String>,
+}
+
+impl Tag {
+    fn sequence(&self) -> Vec<usize> {
+        let forward: Vec<usize> = (self.from..=self.to).collect();
+        let reverse: Vec<usize> = forward.iter().rev().copied().collect();
+        // Ping-pong doesn't repeat the end frames when it turns around.
+        let bounce = |there: &[usize], back: &[usize]| {
+            there
+                .iter()
+                .chain(back.iter().skip(1).take(back.len().saturating_sub(2)))
+                .copied()
+                .collect()
+        };
+        match self.direction.as_str() {
+            "reverse" => reverse,
+            "pingpong" => bounce(&forward, &reverse),
+            "pingpong_reverse" => bounce(&reverse, &forward),
+            _ => forward,
+        }
+    }
+}
+
+impl SpriteSheet {
+    pub fn from_aseprite(source: &str) -> Result<Self, DataError> {
+        let export: Export = serde_json::from_str(source)
+            .map_err(|e| DataError::Invalid(format!("invalid Aseprite export: {}", e)))?;
+
+        let frames: Vec<Frame> = match export.frames {
+            Frames::Array(frames) => frames,
+            // Hash exports keep file-name order, which matches frame order
+            // only by convention, so sort on the trailing frame number.
+            Frames::Hash(frames) => {
+                let mut frames: Vec<Frame> = frames
+                    .into_iter()
+                    .map(|(filename, frame)| Frame { filename, ..frame })
+                    .collect();
+                frames.sort_by_key(|frame| frame_number(&frame.filename));
+                frames
+            }
+        };
+
+        let mut animations = BTreeMap::new();
+        for tag in &export.meta.frame_tags {
+            if tag.to >= frames.len() || tag.from > tag.to {
+                return Err(DataError::Invalid(format!(
+                    "tag '{}' covers frames {}..={} but the sheet has {}",
+                    tag.name,
+                    tag.from,
+                    tag.to,
+                    frames.len()
+                )));
+            }
+            let repeat = match tag.repeat.as_deref().map(str::parse::<u32>) {
+                None | Some(Ok(0)) => None,
+                Some(Ok(n)) => Some(n),
+                Some(Err(e)) => {
+                    return Err(DataError::Invalid(format!(
+                        "tag '{}' has a bad repeat count: {}",
+                        tag.name, e
+                    )));
+                }
+            };
+            animations.insert(
+                tag.name.clone(),
+                SpriteAnimation {
+                    frames: tag.sequence(),
+                    repeat,
+                },
+            );
+        }
+
+        Ok(SpriteSheet {
+            image: export.meta.image,
+            frames: frames
+                .into_iter()
+                .map(|frame| SpriteFrame {
+                    rect: frame.frame,
+                    duration: frame.duration / 1000.0,
+                })
+                .collect(),
+            animations,
+        })
+    }
+
+    pub fn load_aseprite(path: impl AsRef<Path>) -> Result<Self, DataError> {
+        SpriteSheet::from_aseprite(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn load_aseprite_from(source: &dyn AssetSource, path: &str) -> Result<Self, DataError> {
+        SpriteSheet::from_aseprite(&source.read_to_string(path)?)
+    }
+}
+
+/// The last run of digits in a name like `"hero 12.aseprite"`.
+fn frame_number(name: &str) -> u64 {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let digits: String = stem
+        .chars()
+        .rev()
+        .take_while(char::is_ascii_digit)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    digits.parse().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sprite::SpriteAnimator;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_aseprite_tags_become_animations() {
+        let frames: Vec<String> = (0..4)
+            .map(|i| {
+                format!(
+                    r#""hero {i}.aseprite": {{ "frame": {{ "x": {}, "y": 0, "w": 16, "h": 16 }}, "duration": 100 }}"#,
+                    i * 16
+                )
+            })
+            .collect();
+        let source = format!(
+            r#"{{
+                "frames": {{ {} }},
+                "meta": {{
+                    "image": "hero.png",
+                    "frameTags": [
+                        {{ "name": "walk", "from": 0, "to": 3, "direction": "pingpong" }},
+                        {{ "name": "hit", "from": 2, "to": 3, "direction": "reverse", "repeat": "1" }}
+                    ]
+                }}
+            }}"#,
+            frames.join(",")
+        );
+
+        let sheet = Arc::new(SpriteSheet::from_aseprite(&source).unwrap());
+        assert_eq!(sheet.animations["walk"].frames, vec![0, 1, 2, 3, 2, 1]);
+        assert_eq!(sheet.frames[3].rect.x, 48);
+
+        let mut animator = SpriteAnimator::new(sheet);
+        assert!(animator.play("walk"));
+        animator.update(0.45);
+        assert_eq!(animator.frame(), Some(2));
+
+        assert!(animator.play("hit"));
+        animator.update(0.15);
+        assert_eq!(animator.frame(), Some(2));
+        animator.update(1.0);
+        assert!(animator.is_finished());
+        assert_eq!(animator.frame(), Some(2));
+    }
+}
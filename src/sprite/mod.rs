@@ -0,0 +1,240 @@
+mod aseprite;
+
+use crate::data::DataError;
+use crate::ecs::World;
+use crate::render::Sprite;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+/// One cell of a sprite sheet image and how long it stays on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpriteFrame {
+    pub rect: Rect,
+    /// Seconds.
+    pub duration: f64,
+}
+
+/// A named run of sheet frames, already expanded into play order so
+/// reverse and ping-pong animations play like forward ones.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpriteAnimation {
+    pub frames: Vec<usize>,
+    /// How many times the sequence plays; `None` loops forever.
+    pub repeat: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct RawSpriteSheet {
+    image: String,
+    frames: Vec<SpriteFrame>,
+    animations: BTreeMap<String, SpriteAnimation>,
+}
+
+/// Loaded sheets are checked so every animation frame is one the sheet
+/// has; see `validate`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawSpriteSheet")]
+pub struct SpriteSheet {
+    pub image: String,
+    pub frames: Vec<SpriteFrame>,
+    pub animations: BTreeMap<String, SpriteAnimation>,
+}
+
+impl TryFrom<RawSpriteSheet> for SpriteSheet {
+    type Error = DataError;
+
+    fn try_from(raw: RawSpriteSheet) -> Result<Self, DataError> {
+        let sheet = SpriteSheet {
+            image: raw.image,
+            frames: raw.frames,
+            animations: raw.animations,
+        };
+        sheet.validate()?;
+        Ok(sheet)
+    }
+}
+
+impl SpriteSheet {
+    /// Fails if an animation names a frame past the end of `frames`.
+    pub fn validate(&self) -> Result<(), DataError> {
+        for (name, animation) in &self.animations {
+            if let Some(&frame) = animation.frames.iter().find(|&&i| i >= self.frames.len()) {
+                return Err(DataError::Invalid(format!(
+                    "animation '{}' uses frame {} but the sheet has {}",
+                    name,
+                    frame,
+                    self.frames.len()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn sequence_duration(&self, animation: &SpriteAnimation) -> f64 {
+        animation
+            .frames
+            .iter()
+            .map(|&i| self.frames[i].duration)
+            .sum()
+    }
+}
+
+/// Plays animations from a shared sheet. It draws nothing itself:
+/// `animate_sprites` points the entity's `Sprite` at the current frame.
+#[derive(Debug, Clone)]
+pub struct SpriteAnimator {
+    pub sheet: Arc<SpriteSheet>,
+    pub speed: f64,
+    animation: Option<String>,
+    elapsed: f64,
+}
+
+impl SpriteAnimator {
+    pub fn new(sheet: Arc<SpriteSheet>) -> Self {
+        SpriteAnimator {
+            sheet,
+            speed: 1.0,
+            animation: None,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Starts `name` from its first frame unless it is already playing.
+    /// Returns false if the sheet has no such animation.
+    pub fn play(&mut self, name: &str) -> bool {
+        if !self.sheet.animations.contains_key(name) {
+            return false;
+        }
+        if self.animation.as_deref() != Some(name) {
+            self.animation = Some(name.to_string());
+            self.elapsed = 0.0;
+        }
+        true
+    }
+
+    pub fn animation(&self) -> Option<&str> {
+        self.animation.as_deref()
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        self.elapsed += dt * self.speed;
+    }
+
+    fn playing(&self) -> Option<&SpriteAnimation> {
+        self.sheet.animations.get(self.animation.as_deref()?)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.playing().is_some_and(|animation| {
+            animation.repeat.is_some_and(|repeat| {
+                self.elapsed >= self.sheet.sequence_duration(animation) * repeat as f64
+            })
+        })
+    }
+
+    /// Index into the sheet's frames for the current time. Finished
+    /// animations hold their last frame.
+    pub fn frame(&self) -> Option<usize> {
+        let animation = self.playing()?;
+        let total = self.sheet.sequence_duration(animation);
+        if self.is_finished() || total <= 0.0 {
+            return animation.frames.last().copied();
+        }
+
+        let mut t = self.elapsed.rem_euclid(total);
+        for &frame in &animation.frames {
+            t -= self.sheet.frames[frame].duration;
+            if t < 0.0 {
+                return Some(frame);
+            }
+        }
+        animation.frames.last().copied()
+    }
+
+    pub fn rect(&self) -> Option<Rect> {
+        Some(self.sheet.frames[self.frame()?].rect)
+    }
+}
+
+pub fn animate_sprites(world: &mut World, dt: f64) {
+    world
+        .query::<&mut SpriteAnimator>()
+        .for_each(|_, animator| animator.update(dt));
+    world
+        .query::<(&SpriteAnimator, &mut Sprite)>()
+        .for_each(|_, (animator, sprite)| {
+            if let Some(rect) = animator.rect() {
+                sprite.image.clone_from(&animator.sheet.image);
+                sprite.uv = rect.into();
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::UvRect;
+
+    #[test]
+    fn test_animators_drive_sprites() {
+        let sheet: SpriteSheet = ron::from_str(
+            r#"(
+                image: "hero.png",
+                frames: [
+                    (rect: (x: 0, y: 0, w: 8, h: 8), duration: 0.1),
+                    (rect: (x: 8, y: 0, w: 8, h: 8), duration: 0.1),
+                ],
+                animations: { "walk": (frames: [0, 1], repeat: None) },
+            )"#,
+        )
+        .unwrap();
+        let mut animator = SpriteAnimator::new(Arc::new(sheet));
+        assert!(animator.play("walk"));
+        let mut world = World::new();
+        let hero = world.spawn();
+        world.insert(hero, animator).unwrap();
+        world
+            .insert(
+                hero,
+                Sprite {
+                    image: "placeholder.png".to_string(),
+                    uv: UvRect::default(),
+                    size: Default::default(),
+                    mode: Default::default(),
+                    color: crate::color::Color::WHITE,
+                },
+            )
+            .unwrap();
+
+        animate_sprites(&mut world, 0.15);
+        let sprite = world.get::<Sprite>(hero).unwrap();
+        assert_eq!(sprite.image, "hero.png");
+        assert_eq!(sprite.uv.x, 8.0);
+    }
+
+    #[test]
+    fn test_loaded_sheets_check_frame_indices() {
+        let sheet = |frames: &str| {
+            ron::from_str::<SpriteSheet>(&format!(
+                r#"(
+                    image: "hero.png",
+                    frames: [(rect: (x: 0, y: 0, w: 8, h: 8), duration: 0.1)],
+                    animations: {{ "idle": (frames: {}, repeat: None) }},
+                )"#,
+                frames
+            ))
+        };
+        assert_eq!(sheet("[0, 0]").unwrap().animations["idle"].frames, [0, 0]);
+        let error = sheet("[0, 1]").unwrap_err().to_string();
+        assert!(error.contains("animation 'idle' uses frame 1"), "{}", error);
+    }
+}
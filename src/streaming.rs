@@ -0,0 +1,267 @@
+use crate::data::DataError;
+use crate::ecs::{Entity, World};
+use crate::math::Vec2;
+use crate::scene::Scene;
+use mlua::{Function, Lua, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ChunkCoord {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl ChunkCoord {
+    pub fn new(x: i32, y: i32) -> Self {
+        ChunkCoord { x, y }
+    }
+
+    pub fn containing(point: Vec2, chunk_size: f64) -> Self {
+        ChunkCoord {
+            x: (point.x / chunk_size).floor() as i32,
+            y: (point.y / chunk_size).floor() as i32,
+        }
+    }
+
+    /// Distance in chunks, counting diagonals as one step.
+    pub fn distance(&self, other: ChunkCoord) -> i32 {
+        (self.x - other.x).abs().max((self.y - other.y).abs())
+    }
+}
+
+/// Tags every entity spawned from a chunk file with the chunk it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkMember(pub ChunkCoord);
+
+#[derive(Debug, Clone)]
+pub struct StreamingConfig {
+    /// Directory holding `chunk_<x>_<y>.ron` scene files.
+    pub directory: PathBuf,
+    pub chunk_size: f64,
+    pub load_radius: i32,
+    /// Chunks stay loaded until further than this from the focus, so a
+    /// focus hovering on a border doesn't reload chunks every frame.
+    pub unload_radius: i32,
+}
+
+impl StreamingConfig {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        StreamingConfig {
+            directory: directory.into(),
+            chunk_size: 64.0,
+            load_radius: 1,
+            unload_radius: 2,
+        }
+    }
+
+    pub fn chunk_path(&self, coord: ChunkCoord) -> PathBuf {
+        self.directory
+            .join(format!("chunk_{}_{}.ron", coord.x, coord.y))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChunkEvent {
+    Loaded {
+        coord: ChunkCoord,
+        entities: Vec<Entity>,
+    },
+    Unloaded {
+        coord: ChunkCoord,
+        entities: Vec<Entity>,
+    },
+}
+
+/// Loads and unloads chunk scenes around a moving focus point.
+pub struct LevelStreamer {
+    config: StreamingConfig,
+    loaded: BTreeMap<ChunkCoord, Vec<Entity>>,
+    on_load: Vec<Function>,
+    on_unload: Vec<Function>,
+}
+
+impl LevelStreamer {
+    pub fn new(config: StreamingConfig) -> Self {
+        LevelStreamer {
+            config,
+            loaded: BTreeMap::new(),
+            on_load: Vec::new(),
+            on_unload: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &StreamingConfig {
+        &self.config
+    }
+
+    pub fn is_loaded(&self, coord: ChunkCoord) -> bool {
+        self.loaded.contains_key(&coord)
+    }
+
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = ChunkCoord> + '_ {
+        self.loaded.keys().copied()
+    }
+
+    pub fn entities_in(&self, coord: ChunkCoord) -> &[Entity] {
+        self.loaded.get(&coord).map_or(&[], Vec::as_slice)
+    }
+
+    /// Called as `f(world, x, y, entities)` after a chunk's entities spawn.
+    pub fn on_load(&mut self, f: Function) {
+        self.on_load.push(f);
+    }
+
+    /// Called as `f(world, x, y, entities)` before a chunk's entities despawn.
+    pub fn on_unload(&mut self, f: Function) {
+        self.on_unload.push(f);
+    }
+
+    pub fn update(&mut self, world: &mut World, lua: &Lua, focus: Vec2) -> Result<Vec<ChunkEvent>> {
+        let center = ChunkCoord::containing(focus, self.config.chunk_size);
+        let load = self.config.load_radius.max(0);
+        let keep = self.config.unload_radius.max(load);
+
+        let mut events = Vec::new();
+        let far: Vec<ChunkCoord> = self
+            .loaded
+            .keys()
+            .copied()
+            .filter(|coord| coord.distance(center) > keep)
+            .collect();
+        for coord in far {
+            events.push(self.unload(world, lua, coord)?);
+        }
+
+        let wanted: BTreeSet<ChunkCoord> = (-load..=load)
+            .flat_map(|dy| (-load..=load).map(move |dx| (dx, dy)))
+            .map(|(dx, dy)| ChunkCoord::new(center.x + dx, center.y + dy))
+            .collect();
+        for coord in wanted {
+            if !self.is_loaded(coord) {
+                events.push(self.load(world, lua, coord)?);
+            }
+        }
+        Ok(events)
+    }
+
+    pub fn unload_all(&mut self, world: &mut World, lua: &Lua) -> Result<Vec<ChunkEvent>> {
+        let coords: Vec<ChunkCoord> = self.loaded_chunks().collect();
+        coords
+            .into_iter()
+            .map(|coord| self.unload(world, lua, coord))
+            .collect()
+    }
+
+    fn load(&mut self, world: &mut World, lua: &Lua, coord: ChunkCoord) -> Result<ChunkEvent> {
+        // Chunks without a file are empty space, not an error.
+        let scene = match Scene::load(self.config.chunk_path(coord)) {
+            Ok(scene) => scene,
+            Err(DataError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => Scene::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let entities = scene.spawn(world, lua)?;
+        for &entity in &entities {
+            world.insert(entity, ChunkMember(coord))?;
+        }
+        self.loaded.insert(coord, entities.clone());
+        notify(&self.on_load, world, lua, coord, &entities)?;
+
+        Ok(ChunkEvent::Loaded { coord, entities })
+    }
+
+    fn unload(&mut self, world: &mut World, lua: &Lua, coord: ChunkCoord) -> Result<ChunkEvent> {
+        let entities = self.loaded.remove(&coord).unwrap_or_default();
+        notify(&self.on_unload, world, lua, coord, &entities)?;
+        for &entity in &entities {
+            world.despawn(entity);
+        }
+        Ok(ChunkEvent::Unloaded { coord, entities })
+    }
+}
+
+fn notify(
+    listeners: &[Function],
+    world: &mut World,
+    lua: &Lua,
+    coord: ChunkCoord,
+    entities: &[Entity],
+) -> Result<()> {
+    if listeners.is_empty() {
+        return Ok(());
+    }
+    let entities = lua.create_sequence_from(entities.iter().copied())?;
+    lua.scope(|scope| {
+        let handle = scope.create_userdata_ref_mut(world)?;
+        for listener in listeners {
+            listener.call::<()>((&handle, coord.x, coord.y, &entities))?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_follow_focus() -> Result<()> {
+        let directory =
+            std::env::temp_dir().join(format!("entity-engine-streaming-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("chunk_0_0.ron"),
+            r#"(entities: [{ "Tree": { "height": 3.0 } }, { "Rock": true }])"#,
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("chunk_1_0.ron"),
+            r#"(entities: [{ "Tree": { "height": 5.0 } }])"#,
+        )
+        .unwrap();
+
+        let lua = Lua::new();
+        let mut world = World::new();
+        let mut streamer = LevelStreamer::new(StreamingConfig {
+            load_radius: 0,
+            unload_radius: 1,
+            ..StreamingConfig::new(&directory)
+        });
+        streamer.on_load(
+            lua.load(
+                "return function(world, x, y, entities) loaded = (loaded or 0) + #entities end",
+            )
+            .eval()?,
+        );
+        streamer.on_unload(
+            lua.load("return function(world, x, y, entities) assert(world:has(entities[1], 'Tree')) unloaded = x end")
+                .eval()?,
+        );
+
+        let events = streamer.update(&mut world, &lua, Vec2::new(10.0, 10.0))?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(world.len(), 2);
+
+        streamer.update(&mut world, &lua, Vec2::new(70.0, 10.0))?;
+        assert!(streamer.is_loaded(ChunkCoord::new(0, 0)));
+        assert_eq!(world.len(), 3);
+        assert_eq!(lua.globals().get::<u32>("loaded")?, 3);
+
+        let entity = streamer.entities_in(ChunkCoord::new(1, 0))[0];
+        assert_eq!(
+            world.get::<ChunkMember>(entity).map(|member| *member),
+            Some(ChunkMember(ChunkCoord::new(1, 0)))
+        );
+
+        streamer.update(&mut world, &lua, Vec2::new(200.0, 10.0))?;
+        assert!(!streamer.is_loaded(ChunkCoord::new(0, 0)));
+        assert!(!streamer.is_loaded(ChunkCoord::new(1, 0)));
+        assert!(world.is_empty());
+        assert_eq!(lua.globals().get::<i32>("unloaded")?, 1);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+        Ok(())
+    }
+}
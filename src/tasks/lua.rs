@@ -0,0 +1,288 @@
+use crate::assets;
+use crate::ecs::{World, panic};
+use crate::math::Vec2;
+use crate::nav::NavGrid;
+use mlua::{
+    Error, FromLuaMulti, Function, IntoLua, Lua, MultiValue, Result, Thread, ThreadStatus,
+    UserData, Value,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// A finished operation's result, converted to Lua back on the main thread.
+pub type AsyncValue = Box<dyn FnOnce(&Lua) -> Result<Value> + Send + Sync>;
+pub type AsyncResult = std::result::Result<AsyncValue, String>;
+pub type AsyncJob = Box<dyn FnOnce() -> AsyncResult + Send>;
+
+/// Returned to scripts by async operations; pass it to `await`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LuaFuture(u64);
+
+impl UserData for LuaFuture {}
+
+struct AsyncDone {
+    future: u64,
+    result: AsyncResult,
+}
+
+#[derive(Default)]
+struct AsyncState {
+    next_id: u64,
+    requests: Vec<(u64, AsyncJob)>,
+    ready: HashMap<u64, AsyncResult>,
+    waiting: Vec<(Thread, u64)>,
+}
+
+/// Runs script coroutines that `await` long-running engine operations.
+/// Operations run on the world's `TaskPool`; `update` submits new ones and
+/// resumes every coroutine whose operation has finished. Clones share one
+/// bridge.
+#[derive(Clone, Default)]
+pub struct LuaAsync {
+    state: Rc<RefCell<AsyncState>>,
+}
+
+const AWAIT: &str = r#"
+local yield = coroutine.yield
+return function(future)
+    local ok, value, world = yield(future)
+    if not ok then error(value, 2) end
+    return value, world
+end
+"#;
+
+impl LuaAsync {
+    pub fn new() -> Self {
+        LuaAsync::default()
+    }
+
+    /// Queues `job` for the pool and returns the future scripts await.
+    pub fn request(&self, job: impl FnOnce() -> AsyncResult + Send + 'static) -> LuaFuture {
+        let mut state = self.state.borrow_mut();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.requests.push((id, Box::new(job)));
+        LuaFuture(id)
+    }
+
+    /// A Lua function that starts an operation with `start` and returns
+    /// its future, for binding new async operations.
+    pub fn create_function<A: FromLuaMulti>(
+        &self,
+        lua: &Lua,
+        start: impl Fn(&Lua, A) -> Result<AsyncJob> + 'static,
+    ) -> Result<Function> {
+        let this = self.clone();
+        lua.create_function(move |lua, args: A| Ok(this.request(start(lua, args)?)))
+    }
+
+    /// Starts `function` as a coroutine, running it up to its first
+    /// `await`.
+    pub fn spawn(
+        &self,
+        lua: &Lua,
+        function: Function,
+        args: impl mlua::IntoLuaMulti,
+    ) -> Result<()> {
+        let thread = lua.create_thread(function)?;
+        let yielded = thread.resume::<MultiValue>(args)?;
+        self.suspend(thread, yielded)
+    }
+
+    fn suspend(&self, thread: Thread, yielded: MultiValue) -> Result<()> {
+        if thread.status() != ThreadStatus::Resumable {
+            return Ok(());
+        }
+        let future = match yielded.front() {
+            Some(Value::UserData(data)) => data.borrow::<LuaFuture>().map(|f| f.0),
+            _ => Err(Error::runtime(
+                "async functions may only yield through await",
+            )),
+        }?;
+        self.state.borrow_mut().waiting.push((thread, future));
+        Ok(())
+    }
+
+    /// Submits queued operations, delivers finished ones at this sync
+    /// point and resumes their coroutines. A resumed coroutine gets its
+    /// value along with a fresh world handle, valid until it next awaits.
+    /// Errors raised by coroutines are logged; the first is returned once
+    /// every coroutine has been resumed.
+    pub fn update(&self, world: &mut World, lua: &Lua) -> Result<()> {
+        let requests = std::mem::take(&mut self.state.borrow_mut().requests);
+        for (future, job) in requests {
+            super::spawn(world, move || AsyncDone {
+                future,
+                result: panic::catch(job).unwrap_or_else(|(message, _)| Err(message)),
+            });
+        }
+        super::sync(world)?;
+        {
+            let mut state = self.state.borrow_mut();
+            for done in world.drain_events::<AsyncDone>() {
+                state.ready.insert(done.future, done.result);
+            }
+        }
+
+        let resumable: Vec<(Thread, AsyncResult)> = {
+            let mut state = self.state.borrow_mut();
+            let state = &mut *state;
+            let (ready, waiting) = std::mem::take(&mut state.waiting)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(_, future)| state.ready.contains_key(future));
+            state.waiting = waiting;
+            ready
+                .into_iter()
+                .map(|(thread, future)| (thread, state.ready.remove(&future).expect("ready")))
+                .collect()
+        };
+
+        let mut first_error = None;
+        for (thread, result) in resumable {
+            let resumed = lua.scope(|scope| {
+                let handle = Value::UserData(scope.create_userdata_ref_mut(&mut *world)?);
+                let (ok, value) = match result {
+                    Ok(value) => (true, value(lua)?),
+                    Err(message) => (false, message.into_lua(lua)?),
+                };
+                let yielded = thread.resume::<MultiValue>((ok, value, handle))?;
+                self.suspend(thread, yielded)
+            });
+            if let Err(e) = resumed {
+                log::error!(target: "tasks", "async script failed: {}", e);
+                first_error.get_or_insert(e);
+            }
+        }
+        world.apply_commands(lua)?;
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Coroutines waiting on an operation.
+    pub fn waiting(&self) -> usize {
+        self.state.borrow().waiting.len()
+    }
+
+    /// Adds `await(future)`, `async(f, ...)` and `assets.load(path)`, which
+    /// reads a file in the background and resolves to its contents.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        let globals = lua.globals();
+        globals.set(
+            "await",
+            lua.load(AWAIT).set_name("=await").eval::<Function>()?,
+        )?;
+        let this = self.clone();
+        globals.set(
+            "async",
+            lua.create_function(move |lua, (function, args): (Function, MultiValue)| {
+                this.spawn(lua, function, args)
+            })?,
+        )?;
+
+        let assets = lua.create_table()?;
+        assets.set(
+            "load",
+            self.create_function(lua, |lua, path: String| {
+                let source = assets::lua_source(lua);
+                Ok(Box::new(move || {
+                    let bytes = match source {
+                        Some(source) => source.read(&path).map_err(|e| e.to_string()),
+                        None => std::fs::read(&path).map_err(|e| e.to_string()),
+                    }
+                    .map_err(|e| format!("failed to load '{}': {}", path, e))?;
+                    Ok(
+                        Box::new(move |lua: &Lua| lua.create_string(bytes)?.into_lua(lua))
+                            as AsyncValue,
+                    )
+                }) as AsyncJob)
+            })?,
+        )?;
+        globals.set("assets", assets)
+    }
+
+    /// Adds `nav.find_path(from_x, from_y, to_x, to_y)` over `grid`,
+    /// resolving to a list of `{ x, y }` waypoints, or nil without a path.
+    pub fn register_nav(&self, lua: &Lua, grid: Arc<NavGrid>) -> Result<()> {
+        let nav = lua.create_table()?;
+        nav.set(
+            "find_path",
+            self.create_function(lua, move |_, (fx, fy, tx, ty): (f64, f64, f64, f64)| {
+                let grid = grid.clone();
+                Ok(Box::new(move || {
+                    let path = grid.find_path(Vec2::new(fx, fy), Vec2::new(tx, ty));
+                    Ok(Box::new(move |lua: &Lua| match path {
+                        Some(path) => lua
+                            .create_sequence_from(
+                                path.into_iter()
+                                    .map(|p| {
+                                        let point = lua.create_table()?;
+                                        point.set("x", p.x)?;
+                                        point.set("y", p.y)?;
+                                        Ok(point)
+                                    })
+                                    .collect::<Result<Vec<_>>>()?,
+                            )?
+                            .into_lua(lua),
+                        None => Ok(Value::Nil),
+                    }) as AsyncValue)
+                }) as AsyncJob)
+            })?,
+        )?;
+        lua.globals().set("nav", nav)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::ScriptValue;
+    use crate::tasks::TaskPool;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_await_asset_load() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.insert_resource(TaskPool::new(1));
+        let bridge = LuaAsync::new();
+        bridge.register_lua(&lua)?;
+
+        let path = std::env::temp_dir().join(format!("async_{}.txt", std::process::id()));
+        std::fs::write(&path, "hello").unwrap();
+        lua.globals().set("path", path.display().to_string())?;
+        lua.load(
+            r#"
+            async(function()
+                local ok, err = pcall(await, assets.load(path .. ".missing"))
+                local text, world = await(assets.load(path))
+                world:spawn({
+                    Loaded = text,
+                    Failed = not ok and string.find(err, "failed to load") ~= nil,
+                })
+            end)
+        "#,
+        )
+        .exec()?;
+        assert_eq!(bridge.waiting(), 1);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while bridge.waiting() > 0 {
+            bridge.update(&mut world, &lua)?;
+            assert!(Instant::now() < deadline, "coroutine never resumed");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        std::fs::remove_file(&path).ok();
+
+        let component = |name: &str| {
+            let entity = world.script_components(name).unwrap().entities()[0];
+            world.script_component(entity, name).cloned()
+        };
+        assert_eq!(
+            component("Loaded"),
+            Some(ScriptValue::String("hello".to_string()))
+        );
+        assert_eq!(component("Failed"), Some(ScriptValue::Bool(true)));
+        Ok(())
+    }
+}
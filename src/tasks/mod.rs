@@ -0,0 +1,268 @@
+mod lua;
+
+pub use lua::{AsyncJob, AsyncResult, AsyncValue, LuaAsync, LuaFuture};
+
+use crate::ecs::{Component, Entity, World, panic};
+use mlua::Result;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, JoinHandle, Thread};
+
+type Job = Box<dyn FnOnce() + Send>;
+type Completion = Box<dyn FnOnce(&mut World) + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TaskId(pub u64);
+
+/// Sent at the sync point for a task that panicked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskFailed {
+    pub task: TaskId,
+    pub message: String,
+}
+
+/// Background worker threads. Task results are queued and only touch the
+/// world in `sync`, so systems never see them mid-frame. Kept as a world
+/// resource; see `spawn`.
+pub struct TaskPool {
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    completed: Arc<Mutex<Vec<Completion>>>,
+    next_id: u64,
+    pending: Arc<Mutex<usize>>,
+}
+
+impl Default for TaskPool {
+    fn default() -> Self {
+        TaskPool::new(
+            thread::available_parallelism().map_or(2, |n| n.get().saturating_sub(1).max(1)),
+        )
+    }
+}
+
+impl TaskPool {
+    pub fn new(threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let receiver: Arc<Mutex<Receiver<Job>>> = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("task-{}", i))
+                    .spawn(move || {
+                        loop {
+                            let job = receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                            match job {
+                                Ok(job) => job(),
+                                Err(_) => break,
+                            }
+                        }
+                    })
+                    .expect("failed to spawn a task thread")
+            })
+            .collect();
+        TaskPool {
+            sender: Some(sender),
+            workers,
+            completed: Arc::default(),
+            next_id: 0,
+            pending: Arc::default(),
+        }
+    }
+
+    /// Tasks spawned but not yet synced.
+    pub fn pending(&self) -> usize {
+        *self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Runs `task` on a worker; `deliver` applies its result at the next
+    /// sync.
+    fn submit<T: Send + 'static>(
+        &mut self,
+        task: impl FnOnce() -> T + Send + 'static,
+        deliver: impl FnOnce(&mut World, T) + Send + 'static,
+    ) -> TaskId {
+        let id = TaskId(self.next_id);
+        self.next_id += 1;
+        *self.pending.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        let completed = self.completed.clone();
+        let job = move || {
+            let completion: Completion = match panic::catch(task) {
+                Ok(value) => Box::new(move |world: &mut World| deliver(world, value)),
+                Err((message, _)) => Box::new(move |world: &mut World| {
+                    log::error!(target: "tasks", "task {} panicked: {}", id.0, message);
+                    world.send_event(TaskFailed { task: id, message });
+                }),
+            };
+            completed
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(completion);
+        };
+        self.sender
+            .as_ref()
+            .expect("pool is running")
+            .send(Box::new(job))
+            .expect("task threads outlive the pool's sender");
+        id
+    }
+
+    /// Runs `task` in the background and sends its result as an event.
+    pub fn spawn<T: Send + Sync + 'static>(
+        &mut self,
+        task: impl FnOnce() -> T + Send + 'static,
+    ) -> TaskId {
+        self.submit(task, |world, value| world.send_event(value))
+    }
+
+    /// Drives `future` to completion on a worker and sends its output as
+    /// an event.
+    pub fn spawn_async<T: Send + Sync + 'static>(
+        &mut self,
+        future: impl Future<Output = T> + Send + 'static,
+    ) -> TaskId {
+        self.spawn(move || block_on(future))
+    }
+
+    /// Runs `task` in the background and inserts its result as a component
+    /// on `entity`, unless the entity has been despawned by then.
+    pub fn spawn_for<T: Component>(
+        &mut self,
+        entity: Entity,
+        task: impl FnOnce() -> T + Send + 'static,
+    ) -> TaskId {
+        self.submit(task, move |world, value| {
+            let _ = world.insert(entity, value);
+        })
+    }
+
+    fn take_completed(&self) -> Vec<Completion> {
+        std::mem::take(&mut *self.completed.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl Drop for TaskPool {
+    fn drop(&mut self) {
+        // Closing the channel lets each worker finish its job and exit.
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Polls `future` on the current thread, parking between wakeups.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+fn pool(world: &mut World) -> &mut TaskPool {
+    if world.resource::<TaskPool>().is_none() {
+        world.insert_resource(TaskPool::default());
+    }
+    world.resource_mut::<TaskPool>().expect("inserted above")
+}
+
+/// Spawns on the world's pool, creating one if needed. The result arrives
+/// as an `Events<T>` event at the next `sync`.
+pub fn spawn<T: Send + Sync + 'static>(
+    world: &mut World,
+    task: impl FnOnce() -> T + Send + 'static,
+) -> TaskId {
+    pool(world).spawn(task)
+}
+
+pub fn spawn_async<T: Send + Sync + 'static>(
+    world: &mut World,
+    future: impl Future<Output = T> + Send + 'static,
+) -> TaskId {
+    pool(world).spawn_async(future)
+}
+
+pub fn spawn_for<T: Component>(
+    world: &mut World,
+    entity: Entity,
+    task: impl FnOnce() -> T + Send + 'static,
+) -> TaskId {
+    pool(world).spawn_for(entity, task)
+}
+
+/// Delivers every finished task's result into the world, returning how
+/// many were delivered. Meant as an exclusive system:
+/// `schedule.add_exclusive_system("tasks", |world, _| tasks::sync(world).map(|_| ()))`.
+pub fn sync(world: &mut World) -> Result<usize> {
+    let Some(pool) = world.resource::<TaskPool>() else {
+        return Ok(0);
+    };
+    let completed = pool.take_completed();
+    *pool.pending.lock().unwrap_or_else(|e| e.into_inner()) -= completed.len();
+    let delivered = completed.len();
+    for completion in completed {
+        completion(world);
+    }
+    Ok(delivered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[derive(Debug, PartialEq)]
+    struct Path(Vec<u32>);
+
+    fn sync_all(world: &mut World) -> Result<()> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while world.resource::<TaskPool>().unwrap().pending() > 0 {
+            sync(world)?;
+            assert!(Instant::now() < deadline, "tasks did not finish");
+            thread::sleep(Duration::from_millis(1));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_results_delivered_at_sync() -> Result<()> {
+        let mut world = World::new();
+        world.insert_resource(TaskPool::new(2));
+        let agent = world.spawn();
+
+        spawn(&mut world, || 6 * 7u64);
+        spawn_async(&mut world, async { "decoded".to_string() });
+        spawn_for(&mut world, agent, || Path(vec![1, 2, 3]));
+        spawn(&mut world, || -> u8 { panic!("bad asset") });
+        assert!(world.get::<Path>(agent).is_none());
+
+        sync_all(&mut world)?;
+        assert_eq!(world.drain_events::<u64>(), vec![42]);
+        assert_eq!(world.drain_events::<String>(), vec!["decoded"]);
+        assert_eq!(
+            world.get::<Path>(agent).as_deref(),
+            Some(&Path(vec![1, 2, 3]))
+        );
+        let failed = world.drain_events::<TaskFailed>();
+        assert_eq!(failed.len(), 1);
+        assert!(failed[0].message.contains("bad asset"), "{:?}", failed);
+        assert!(world.drain_events::<u8>().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,414 @@
+use crate::ecs::{Schedule, World};
+use crate::scene::{self, Scene};
+use mlua::{Function, Lua, Result, Table};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// `expect.*` assertions for script tests. Failures raise with a message
+/// naming both values.
+const EXPECT: &str = r#"
+local function show(v, depth)
+    if type(v) == "string" then return string.format("%q", v) end
+    if type(v) ~= "table" or (depth or 0) > 3 then return tostring(v) end
+    local parts = {}
+    for k, item in v do
+        table.insert(parts, tostring(k) .. " = " .. show(item, (depth or 0) + 1))
+    end
+    table.sort(parts)
+    return "{ " .. table.concat(parts, ", ") .. " }"
+end
+
+local function deep_eq(a, b)
+    if a == b then return true end
+    if type(a) ~= "table" or type(b) ~= "table" then return false end
+    for k, v in a do
+        if not deep_eq(v, b[k]) then return false end
+    end
+    for k in b do
+        if a[k] == nil then return false end
+    end
+    return true
+end
+
+local function fail(message, extra)
+    error(if extra then message .. ": " .. extra else message, 3)
+end
+
+return {
+    eq = function(actual, expected, message)
+        if not deep_eq(actual, expected) then
+            fail("expected " .. show(expected) .. ", got " .. show(actual), message)
+        end
+    end,
+    ne = function(actual, unexpected, message)
+        if deep_eq(actual, unexpected) then
+            fail("expected anything but " .. show(unexpected), message)
+        end
+    end,
+    near = function(actual, expected, epsilon, message)
+        epsilon = epsilon or 1e-6
+        if type(actual) ~= "number" or math.abs(actual - expected) > epsilon then
+            fail("expected " .. show(expected) .. " +/- " .. epsilon .. ", got " .. show(actual), message)
+        end
+    end,
+    truthy = function(value, message)
+        if not value then fail("expected a truthy value, got " .. show(value), message) end
+    end,
+    falsy = function(value, message)
+        if value then fail("expected a falsy value, got " .. show(value), message) end
+    end,
+    errors = function(f, ...)
+        if pcall(f, ...) then fail("expected an error") end
+    end,
+}
+"#;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    pub file: PathBuf,
+    pub name: String,
+    /// The error with its traceback when the test failed.
+    pub failure: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.failure.is_none()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            let status = if result.failure.is_some() {
+                "FAIL"
+            } else {
+                "ok"
+            };
+            writeln!(f, "{} {} :: {}", status, result.file.display(), result.name)?;
+        }
+        for result in &self.results {
+            if let Some(failure) = &result.failure {
+                writeln!(f, "\n--- {} :: {}", result.file.display(), result.name)?;
+                writeln!(f, "{}", failure.trim_end())?;
+            }
+        }
+        write!(f, "\n{} passed, {} failed", self.passed(), self.failed())
+    }
+}
+
+/// Every `*_test.lua` under `root` (or `root` itself if it is a file),
+/// sorted by path.
+pub fn discover(root: impl AsRef<Path>) -> std::io::Result<Vec<PathBuf>> {
+    let root = root.as_ref();
+    if root.is_file() {
+        return Ok(vec![root.to_path_buf()]);
+    }
+
+    let mut found = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with("_test.lua"))
+            {
+                found.push(path);
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// Runs one test file. The file declares tests with `test(name, f)`; each
+/// runs as `f(world)` against a fresh world, and only tests whose name
+/// contains `filter` are run. A file that fails to load counts as one
+/// failed test.
+pub fn run_file(path: &Path, source: &str, filter: Option<&str>) -> Vec<TestResult> {
+    let result = |name: &str, failure: Option<String>| TestResult {
+        file: path.to_path_buf(),
+        name: name.to_string(),
+        failure,
+    };
+
+    let lua = Lua::new();
+    let tests = match load_tests(&lua, path, source) {
+        Ok(tests) => tests,
+        Err(e) => return vec![result("<load>", Some(e.to_string()))],
+    };
+
+    let mut results = Vec::new();
+    for pair in tests.sequence_values::<Table>() {
+        let (name, test) = match pair.and_then(|t| Ok((t.get::<String>(1)?, t.get::<Function>(2)?)))
+        {
+            Ok(pair) => pair,
+            Err(e) => {
+                results.push(result("<load>", Some(e.to_string())));
+                continue;
+            }
+        };
+        if filter.is_some_and(|filter| !name.contains(filter)) {
+            continue;
+        }
+        let failure = run_test(&lua, &test).err().map(|e| match e {
+            mlua::Error::RuntimeError(message) => message,
+            e => e.to_string(),
+        });
+        results.push(result(&name, failure));
+    }
+    results
+}
+
+fn load_tests(lua: &Lua, path: &Path, source: &str) -> Result<Table> {
+    crate::register(lua)?;
+    lua.globals().set(
+        "expect",
+        lua.load(EXPECT).set_name("=expect").eval::<Table>()?,
+    )?;
+
+    let tests = lua.create_table()?;
+    let list = tests.clone();
+    lua.globals().set(
+        "test",
+        lua.create_function(move |lua, (name, f): (String, Function)| {
+            list.push(lua.create_sequence_from([
+                mlua::Value::String(lua.create_string(&name)?),
+                mlua::Value::Function(f),
+            ])?)
+        })?,
+    )?;
+    lua.load(source)
+        .set_name(format!("@{}", path.display()))
+        .exec()?;
+    Ok(tests)
+}
+
+fn run_test(lua: &Lua, test: &Function) -> Result<()> {
+    let mut world = World::new();
+    let traceback: Function = lua.globals().get::<Table>("debug")?.get("traceback")?;
+    let xpcall: Function = lua.globals().get("xpcall")?;
+    lua.scope(|scope| {
+        let handle = scope.create_userdata_ref_mut(&mut world)?;
+        let (ok, error): (bool, Option<String>) = xpcall.call((test, traceback, handle))?;
+        match ok {
+            true => Ok(()),
+            false => Err(mlua::Error::RuntimeError(error.unwrap_or_default())),
+        }
+    })?;
+    world.apply_commands(lua)
+}
+
+pub fn run(paths: &[PathBuf], filter: Option<&str>) -> std::io::Result<TestReport> {
+    let mut report = TestReport::default();
+    for path in paths {
+        let source = std::fs::read_to_string(path)?;
+        report.results.extend(run_file(path, &source, filter));
+    }
+    Ok(report)
+}
+
+/// Runs `schedule` `frames` times. When given, `input` is called as
+/// `input(world, frame)` before each frame, starting at frame 0, to play
+/// back scripted input.
+pub fn simulate_frames(
+    world: &mut World,
+    schedule: &mut Schedule,
+    lua: &Lua,
+    frames: u64,
+    input: Option<&Function>,
+) -> Result<()> {
+    for frame in 0..frames {
+        if let Some(input) = input {
+            lua.scope(|scope| {
+                let handle = scope.create_userdata_ref_mut(&mut *world)?;
+                input.call::<()>((handle, frame as f64))
+            })?;
+            world.apply_commands(lua)?;
+        }
+        schedule.run(world, lua)?;
+    }
+    Ok(())
+}
+
+/// A stable hash of every entity's components, for asserting that a
+/// simulation still ends in the same state.
+pub fn world_hash(world: &World, lua: &Lua) -> Result<u64> {
+    let bytes = Scene::capture(world, lua)?.to_binary(false)?;
+    // FNV-1a, so the hash doesn't change between Rust releases.
+    Ok(bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    }))
+}
+
+pub const UPDATE_GOLDEN_VAR: &str = "ENTITY_ENGINE_UPDATE_GOLDEN";
+
+/// Compares the world against the RON snapshot at `path`, panicking with a
+/// scene diff on mismatch. A missing snapshot is written instead, as is
+/// any snapshot while `ENTITY_ENGINE_UPDATE_GOLDEN` is set.
+pub fn assert_snapshot(world: &World, lua: &Lua, path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    let actual = Scene::capture(world, lua).expect("failed to capture the world");
+    if !path.exists() || std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        let ron = actual.to_ron().expect("failed to serialize the snapshot");
+        std::fs::write(path, ron).expect("failed to write the snapshot");
+        return;
+    }
+
+    let expected = Scene::load(path).expect("failed to load the snapshot");
+    let patch = scene::diff(&expected, &actual);
+    assert!(
+        patch.is_empty(),
+        "world differs from snapshot {} (set {} to accept):\n{}",
+        path.display(),
+        UPDATE_GOLDEN_VAR,
+        patch
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runs_script_tests_in_fresh_worlds() {
+        let results = run_file(
+            Path::new("combat_test.lua"),
+            r#"
+            test("spawns into an empty world", function(world)
+                expect.eq(world:len(), 0)
+                world:spawn({ Health = { hp = 3 } })
+                expect.eq(world:len(), 1)
+            end)
+
+            test("world is fresh again", function(world)
+                expect.eq(world:len(), 0)
+            end)
+
+            test("reports the failing line", function(world)
+                expect.eq({ hp = 1 }, { hp = 2 }, "hp after hit")
+            end)
+        "#,
+            None,
+        );
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].failure.is_none() && results[1].failure.is_none());
+        let failure = results[2].failure.as_deref().unwrap();
+        assert!(failure.contains("combat_test.lua:13"), "{}", failure);
+        assert!(
+            failure.contains("expected { hp = 2 }, got { hp = 1 }: hp after hit"),
+            "{}",
+            failure
+        );
+    }
+
+    #[test]
+    fn test_simulation_hash_and_snapshot() -> Result<()> {
+        let run = || -> Result<(u64, World, Lua)> {
+            let lua = Lua::new();
+            let mut world = World::new();
+            let mut schedule = Schedule::new();
+            schedule.add_lua_system(
+                "move",
+                lua.load(
+                    r#"
+                    return function(world)
+                        for e in world:query({ "Pos" }) do
+                            local p = world:get(e, "Pos")
+                            world:set(e, "Pos", { x = p.x + p.vx, vx = p.vx })
+                        end
+                    end
+                "#,
+                )
+                .eval()?,
+            );
+            let input: Function = lua
+                .load("return function(world, frame) if frame % 200 == 0 then world:spawn({ Pos = { x = 0, vx = frame / 100 } }) end end")
+                .eval()?;
+            simulate_frames(&mut world, &mut schedule, &lua, 600, Some(&input))?;
+            Ok((world_hash(&world, &lua)?, world, lua))
+        };
+
+        let (hash, world, lua) = run()?;
+        assert_eq!(hash, run()?.0);
+        assert_eq!(world.len(), 3);
+
+        let path = std::env::temp_dir().join(format!("golden_{}.ron", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert_snapshot(&world, &lua, &path);
+        assert_snapshot(&world, &lua, &path);
+        std::fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_identical_runs_fire_identically() -> Result<()> {
+        #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+        struct Log(Vec<String>);
+
+        let run = || -> Result<(u64, Vec<String>)> {
+            let lua = Lua::new();
+            let mut world = World::new();
+            world.register_component::<Log>("Log");
+            world.insert_resource(Log(Vec::new()));
+            macro_rules! columns {
+                ($($ty:ty),+) => {$(
+                    world.register_component::<$ty>(stringify!($ty));
+                    world.on_remove::<$ty>(|world, _| {
+                        world.resource_mut::<Log>().unwrap().0.push(stringify!($ty).to_string());
+                        Ok(())
+                    });
+                )+};
+            }
+            columns!(u8, u16, u32, u64, i8, i16, i32, i64);
+            let mut schedule = Schedule::new();
+            schedule.add_lua_system(
+                "cull",
+                lua.load(
+                    r#"
+                    return function(world)
+                        for e in world:query({ "u8" }) do
+                            if world:get(e, "u8") % 3 == 0 then world:despawn(e) end
+                        end
+                    end
+                "#,
+                )
+                .eval()?,
+            );
+            let input: Function = lua
+                .load(
+                    r#"
+                    return function(world, frame)
+                        world:spawn({ i64 = frame, u32 = 1, i8 = 2, u64 = 3, u8 = frame, u16 = 4 })
+                    end
+                "#,
+                )
+                .eval()?;
+            simulate_frames(&mut world, &mut schedule, &lua, 12, Some(&input))?;
+            let log = world.resource::<Log>().unwrap().0.clone();
+            Ok((world_hash(&world, &lua)?, log))
+        };
+
+        let (hash, log) = run()?;
+        assert_eq!(run()?, (hash, log.clone()));
+        // Columns are created in the order the spawn table's fields are
+        // visited, so only the count per despawn is fixed here.
+        assert_eq!(log.len(), 4 * 6);
+        assert_eq!(log[..6], log[6..12]);
+        Ok(())
+    }
+}
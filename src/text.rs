@@ -0,0 +1,273 @@
+use crate::data::DataError;
+use crate::math::Vec2;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Where one glyph goes relative to the pen, in pixels with `y` down from
+/// the top of the line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GlyphMetrics {
+    offset: Vec2,
+    size: Vec2,
+    advance: f64,
+}
+
+enum FontKind {
+    /// Every glyph a fixed fraction of the size wide; the fallback when no
+    /// font file is loaded.
+    Monospace { advance: f64 },
+    #[cfg(feature = "fonts")]
+    Ttf(fontdue::Font),
+}
+
+/// A font asset. TrueType files need the `fonts` feature; without it only
+/// the built-in monospace metrics are available.
+pub struct Font {
+    kind: FontKind,
+}
+
+impl std::fmt::Debug for Font {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            FontKind::Monospace { advance } => write!(f, "Font::Monospace({})", advance),
+            #[cfg(feature = "fonts")]
+            FontKind::Ttf(font) => write!(f, "Font::Ttf({:?})", font.name()),
+        }
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Font::monospace(0.6)
+    }
+}
+
+impl Font {
+    /// Glyphs `advance` times the size wide and as tall as the size.
+    pub fn monospace(advance: f64) -> Self {
+        Font {
+            kind: FontKind::Monospace { advance },
+        }
+    }
+
+    #[cfg(feature = "fonts")]
+    pub fn from_ttf(bytes: &[u8]) -> std::result::Result<Self, DataError> {
+        let font = fontdue::Font::from_bytes(bytes, fontdue::FontSettings::default())
+            .map_err(|e| DataError::Invalid(format!("bad font: {}", e)))?;
+        Ok(Font {
+            kind: FontKind::Ttf(font),
+        })
+    }
+
+    #[cfg(feature = "fonts")]
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        Font::from_ttf(&std::fs::read(path)?)
+    }
+
+    #[cfg(not(feature = "fonts"))]
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        Err(DataError::Invalid(format!(
+            "can't load {}: built without the `fonts` feature",
+            path.as_ref().display()
+        )))
+    }
+
+    /// Distance from one line's top to the next's.
+    pub fn line_height(&self, size: f64) -> f64 {
+        match &self.kind {
+            FontKind::Monospace { .. } => size * 1.2,
+            #[cfg(feature = "fonts")]
+            FontKind::Ttf(font) => font
+                .horizontal_line_metrics(size as f32)
+                .map(|m| m.new_line_size as f64)
+                .unwrap_or(size * 1.2),
+        }
+    }
+
+    fn glyph(&self, glyph: char, size: f64) -> GlyphMetrics {
+        match &self.kind {
+            FontKind::Monospace { advance } => GlyphMetrics {
+                offset: Vec2::new(0.0, size * 0.1),
+                size: if glyph.is_whitespace() {
+                    Vec2::ZERO
+                } else {
+                    Vec2::new(size * advance, size)
+                },
+                advance: size * advance,
+            },
+            #[cfg(feature = "fonts")]
+            FontKind::Ttf(font) => {
+                let metrics = font.metrics(glyph, size as f32);
+                let ascent = font
+                    .horizontal_line_metrics(size as f32)
+                    .map(|m| m.ascent as f64)
+                    .unwrap_or(size);
+                let height = metrics.height as f64;
+                GlyphMetrics {
+                    offset: Vec2::new(metrics.xmin as f64, ascent - metrics.ymin as f64 - height),
+                    size: Vec2::new(metrics.width as f64, height),
+                    advance: metrics.advance_width as f64,
+                }
+            }
+        }
+    }
+
+    /// Width of the longest line and height of all lines.
+    pub fn measure(&self, text: &str, size: f64) -> Vec2 {
+        let width = text
+            .lines()
+            .map(|line| {
+                line.chars()
+                    .map(|c| self.glyph(c, size).advance)
+                    .sum::<f64>()
+            })
+            .fold(0.0, f64::max);
+        let lines = text.lines().count().max(1) + usize::from(text.ends_with('\n'));
+        Vec2::new(width, lines as f64 * self.line_height(size))
+    }
+
+    /// Every visible glyph's top-left corner and size, for text whose first
+    /// line starts at `origin`.
+    pub fn layout(&self, text: &str, origin: Vec2, size: f64) -> Vec<(char, Vec2, Vec2)> {
+        let mut glyphs = Vec::new();
+        let mut pen = origin;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            // CRLF breaks a line once, as `measure` counts it.
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                continue;
+            }
+            if c == '\n' {
+                pen = Vec2::new(origin.x, pen.y + self.line_height(size));
+                continue;
+            }
+            let metrics = self.glyph(c, size);
+            if metrics.size.x > 0.0 && metrics.size.y > 0.0 {
+                glyphs.push((c, pen + metrics.offset, metrics.size));
+            }
+            pen = pen + Vec2::new(metrics.advance, 0.0);
+        }
+        glyphs
+    }
+}
+
+/// Loaded fonts by name. Looking up an unknown name gives the monospace
+/// fallback, so missing assets show up as blocky text rather than none.
+#[derive(Debug, Clone, Default)]
+pub struct Fonts {
+    fonts: BTreeMap<String, Arc<Font>>,
+    fallback: Arc<Font>,
+}
+
+impl Fonts {
+    pub fn new() -> Self {
+        Fonts::default()
+    }
+
+    pub fn insert(&mut self, name: &str, font: Font) {
+        self.fonts.insert(name.to_string(), Arc::new(font));
+    }
+
+    pub fn load(
+        &mut self,
+        name: &str,
+        path: impl AsRef<Path>,
+    ) -> std::result::Result<(), DataError> {
+        self.insert(name, Font::load(path)?);
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.fonts.contains_key(name)
+    }
+
+    pub fn get(&self, name: &str) -> &Font {
+        self.fonts.get(name).unwrap_or(&self.fallback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ttf-parser's 400 byte `demo.ttf` (MIT or Apache-2.0): one glyph,
+    /// 'A', 540 units wide on a 1000 unit em.
+    #[cfg(feature = "fonts")]
+    const DEMO_TTF: &[u8] = &[
+        0x00, 0x01, 0x00, 0x00, 0x00, 0x07, 0x00, 0x40, 0x00, 0x02, 0x00, 0x30, 0x63, 0x6d, 0x61,
+        0x70, 0x00, 0x09, 0x00, 0x76, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x67, 0x6c,
+        0x79, 0x66, 0xf1, 0xcb, 0x66, 0x98, 0x00, 0x00, 0x01, 0x34, 0x00, 0x00, 0x00, 0x5c, 0x68,
+        0x65, 0x61, 0x64, 0xf2, 0x35, 0xdd, 0xf8, 0x00, 0x00, 0x00, 0x7c, 0x00, 0x00, 0x00, 0x36,
+        0x68, 0x68, 0x65, 0x61, 0x06, 0x61, 0x00, 0xca, 0x00, 0x00, 0x00, 0xb4, 0x00, 0x00, 0x00,
+        0x24, 0x68, 0x6d, 0x74, 0x78, 0x04, 0x74, 0x00, 0x6a, 0x00, 0x00, 0x00, 0xf8, 0x00, 0x00,
+        0x00, 0x08, 0x6c, 0x6f, 0x63, 0x61, 0x00, 0x2e, 0x00, 0x14, 0x00, 0x00, 0x01, 0x2c, 0x00,
+        0x00, 0x00, 0x06, 0x6d, 0x61, 0x78, 0x70, 0x00, 0x05, 0x00, 0x0b, 0x00, 0x00, 0x00, 0xd8,
+        0x00, 0x00, 0x00, 0x20, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0xf5, 0x9c, 0x29,
+        0x44, 0x5f, 0x0f, 0x3c, 0xf5, 0x00, 0x02, 0x03, 0xe8, 0x00, 0x00, 0x00, 0x00, 0xb4, 0x92,
+        0xf4, 0x00, 0x00, 0x00, 0x00, 0x00, 0xdc, 0x2f, 0xa6, 0x5c, 0x00, 0x06, 0x00, 0x00, 0x02,
+        0x58, 0x02, 0xbc, 0x00, 0x00, 0x00, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x01, 0x00, 0x00, 0x04, 0x00, 0xfe, 0x70, 0x00, 0x00, 0x02, 0x58, 0x00, 0x06, 0xff,
+        0xff, 0x02, 0x58, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x0b, 0x00,
+        0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x58, 0x00, 0x64, 0x02, 0x1c, 0x00,
+        0x06, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x04,
+        0x00, 0x20, 0x00, 0x00, 0x00, 0x04, 0x00, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x41, 0xff,
+        0xff, 0x00, 0x00, 0x00, 0x41, 0xff, 0xff, 0xff, 0xc0, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x14, 0x00, 0x2e, 0x00, 0x00, 0x00, 0x02, 0x00, 0x64, 0x00, 0x00, 0x02,
+        0x58, 0x02, 0xbc, 0x00, 0x03, 0x00, 0x07, 0x00, 0x00, 0x33, 0x11, 0x21, 0x11, 0x25, 0x21,
+        0x11, 0x21, 0x64, 0x01, 0xf4, 0xfe, 0x34, 0x01, 0xa4, 0xfe, 0x5c, 0x02, 0xbc, 0xfd, 0x44,
+        0x28, 0x02, 0x6c, 0x00, 0x02, 0x00, 0x06, 0x00, 0x00, 0x02, 0x1d, 0x02, 0x90, 0x00, 0x02,
+        0x00, 0x0a, 0x00, 0x00, 0x13, 0x33, 0x03, 0x01, 0x13, 0x33, 0x13, 0x23, 0x27, 0x23, 0x07,
+        0xad, 0xc4, 0x63, 0xfe, 0xf8, 0xda, 0x60, 0xdd, 0x59, 0x3e, 0xef, 0x42, 0x01, 0x0b, 0x01,
+        0x40, 0xfd, 0xb5, 0x02, 0x90, 0xfd, 0x70, 0xc8, 0xc8, 0x00,
+    ];
+
+    #[test]
+    fn test_monospace_layout_and_measure() {
+        let font = Font::monospace(0.5);
+        assert_eq!(font.measure("abcd\nab", 10.0), Vec2::new(20.0, 24.0));
+        let glyphs = font.layout("a b\nc", Vec2::new(100.0, 50.0), 10.0);
+        let placed: Vec<(char, Vec2)> = glyphs.iter().map(|&(c, at, _)| (c, at)).collect();
+        assert_eq!(
+            placed,
+            vec![
+                ('a', Vec2::new(100.0, 51.0)),
+                ('b', Vec2::new(110.0, 51.0)),
+                ('c', Vec2::new(100.0, 63.0)),
+            ]
+        );
+        assert_eq!(glyphs[0].2, Vec2::new(5.0, 10.0));
+
+        let fonts = Fonts::new();
+        assert!(!fonts.contains("title"));
+        assert_eq!(fonts.get("title").measure("ab", 10.0).x, 12.0);
+        #[cfg(not(feature = "fonts"))]
+        assert!(Font::load("title.ttf").is_err());
+    }
+
+    #[test]
+    fn test_crlf_breaks_lines_once() {
+        let font = Font::monospace(0.5);
+        assert_eq!(
+            font.measure("ab\r\nc\r\n", 10.0),
+            font.measure("ab\nc\n", 10.0)
+        );
+        let crlf = font.layout("ab\r\nc", Vec2::ZERO, 10.0);
+        assert_eq!(crlf, font.layout("ab\nc", Vec2::ZERO, 10.0));
+        assert_eq!(crlf[2].1, Vec2::new(0.0, 13.0));
+    }
+
+    #[test]
+    #[cfg(feature = "fonts")]
+    fn test_truetype_metrics() {
+        let font = Font::from_ttf(DEMO_TTF).unwrap();
+        assert_eq!(font.measure("AA", 100.0).x, 108.0);
+        let glyphs = font.layout("A\r\nA", Vec2::new(10.0, 0.0), 100.0);
+        assert_eq!(glyphs.len(), 2);
+        assert_eq!(glyphs[1].1.x, glyphs[0].1.x);
+        assert_eq!(glyphs[1].1.y - glyphs[0].1.y, font.line_height(100.0));
+        assert!(Font::from_ttf(b"not a font").is_err());
+    }
+}
@@ -0,0 +1,451 @@
+mod tiled;
+
+pub use tiled::{TiledImport, TiledObject};
+
+use crate::data::DataError;
+use crate::ecs::{EcsError, Entity, ScriptValue, World};
+use crate::math::Vec2;
+use crate::physics::{Collider, Position, Shape};
+use mlua::{AnyUserData, LuaSerdeExt, UserData, UserDataMethods, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const CHUNK_SIZE: i32 = 16;
+
+/// Index into the map's tile definitions; 0 is always an empty cell.
+pub type TileId = u32;
+
+pub const EMPTY_TILE: TileId = 0;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TileDef {
+    pub name: String,
+    #[serde(default)]
+    pub solid: bool,
+    #[serde(default)]
+    pub properties: BTreeMap<String, ScriptValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<TileId>", into = "Vec<TileId>")]
+struct TileChunk {
+    tiles: Vec<TileId>,
+}
+
+impl TileChunk {
+    fn new() -> Self {
+        TileChunk {
+            tiles: vec![EMPTY_TILE; (CHUNK_SIZE * CHUNK_SIZE) as usize],
+        }
+    }
+
+    fn index(x: i32, y: i32) -> usize {
+        (y.rem_euclid(CHUNK_SIZE) * CHUNK_SIZE + x.rem_euclid(CHUNK_SIZE)) as usize
+    }
+}
+
+impl TryFrom<Vec<TileId>> for TileChunk {
+    type Error = DataError;
+
+    fn try_from(tiles: Vec<TileId>) -> std::result::Result<Self, DataError> {
+        if tiles.len() != (CHUNK_SIZE * CHUNK_SIZE) as usize {
+            return Err(DataError::Invalid(format!(
+                "tile chunk has {} tiles, expected {}",
+                tiles.len(),
+                CHUNK_SIZE * CHUNK_SIZE
+            )));
+        }
+        Ok(TileChunk { tiles })
+    }
+}
+
+impl From<TileChunk> for Vec<TileId> {
+    fn from(chunk: TileChunk) -> Self {
+        chunk.tiles
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TileLayer {
+    name: String,
+    /// Stored as a list of `[[cx, cy], tiles]` pairs, since JSON and
+    /// `ScriptValue` maps only take string keys.
+    #[serde(with = "chunk_list")]
+    chunks: BTreeMap<(i32, i32), TileChunk>,
+}
+
+mod chunk_list {
+    use super::TileChunk;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S: Serializer>(
+        chunks: &BTreeMap<(i32, i32), TileChunk>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(chunks.iter())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<(i32, i32), TileChunk>, D::Error> {
+        Ok(Vec::<((i32, i32), TileChunk)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+fn chunk_of(x: i32, y: i32) -> (i32, i32) {
+    (x.div_euclid(CHUNK_SIZE), y.div_euclid(CHUNK_SIZE))
+}
+
+/// Marks the collider entities generated for a map's solid tiles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileCollider;
+
+/// The tick a map's colliders were last generated at, kept on the map
+/// entity so edits after it can be spotted from the change ticks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CollidersSynced(u32);
+
+/// A layered tile grid stored in `CHUNK_SIZE` square chunks, so sparse or
+/// unbounded maps only pay for the areas that hold tiles. Scripts edit it
+/// in place through `world:tilemap(e)`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileMap {
+    tile_size: f64,
+    defs: BTreeMap<TileId, TileDef>,
+    layers: Vec<TileLayer>,
+}
+
+impl TileMap {
+    pub fn new(tile_size: f64) -> Self {
+        TileMap {
+            tile_size,
+            defs: BTreeMap::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn tile_size(&self) -> f64 {
+        self.tile_size
+    }
+
+    pub fn define_tile(&mut self, id: TileId, def: TileDef) {
+        self.defs.insert(id, def);
+    }
+
+    pub fn tile_def(&self, id: TileId) -> Option<&TileDef> {
+        self.defs.get(&id)
+    }
+
+    /// Layers draw in the order they were added. Adding an existing name
+    /// returns its index.
+    pub fn add_layer(&mut self, name: &str) -> usize {
+        if let Some(index) = self.layer(name) {
+            return index;
+        }
+        self.layers.push(TileLayer {
+            name: name.to_string(),
+            chunks: BTreeMap::new(),
+        });
+        self.layers.len() - 1
+    }
+
+    pub fn layer(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    pub fn layer_names(&self) -> Vec<String> {
+        self.layers.iter().map(|l| l.name.clone()).collect()
+    }
+
+    pub fn get_tile(&self, layer: usize, x: i32, y: i32) -> TileId {
+        self.layers
+            .get(layer)
+            .and_then(|layer| layer.chunks.get(&chunk_of(x, y)))
+            .map_or(EMPTY_TILE, |chunk| chunk.tiles[TileChunk::index(x, y)])
+    }
+
+    pub fn set_tile(&mut self, layer: usize, x: i32, y: i32, id: TileId) -> Result<(), EcsError> {
+        if id != EMPTY_TILE && !self.defs.contains_key(&id) {
+            return Err(EcsError::InvalidComponent {
+                component: "TileMap".to_string(),
+                message: format!("tile {} is not defined", id),
+            });
+        }
+        let Some(tiles) = self.layers.get_mut(layer) else {
+            return Err(EcsError::InvalidComponent {
+                component: "TileMap".to_string(),
+                message: format!("no layer {}", layer),
+            });
+        };
+        let chunk = chunk_of(x, y);
+        if id == EMPTY_TILE && !tiles.chunks.contains_key(&chunk) {
+            return Ok(());
+        }
+        tiles
+            .chunks
+            .entry(chunk)
+            .or_insert_with(TileChunk::new)
+            .tiles[TileChunk::index(x, y)] = id;
+        Ok(())
+    }
+
+    /// Whether any layer has a solid tile at the cell.
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        self.layers.iter().any(|layer| {
+            layer
+                .chunks
+                .get(&chunk_of(x, y))
+                .and_then(|chunk| self.defs.get(&chunk.tiles[TileChunk::index(x, y)]))
+                .is_some_and(|def| def.solid)
+        })
+    }
+
+    fn solid_cells(&self) -> BTreeSet<(i32, i32)> {
+        let mut cells = BTreeSet::new();
+        for layer in &self.layers {
+            for (&(cx, cy), chunk) in &layer.chunks {
+                for (i, id) in chunk.tiles.iter().enumerate() {
+                    if self.defs.get(id).is_some_and(|def| def.solid) {
+                        let (x, y) = (i as i32 % CHUNK_SIZE, i as i32 / CHUNK_SIZE);
+                        cells.insert((cx * CHUNK_SIZE + x, cy * CHUNK_SIZE + y));
+                    }
+                }
+            }
+        }
+        cells
+    }
+
+    /// Box colliders in map space covering every solid tile, merging each
+    /// horizontal run of solid tiles into one box.
+    pub fn colliders(&self) -> Vec<(Vec2, Collider)> {
+        let size = self.tile_size;
+        // Cells sort by x first, so walk them grouped by row instead.
+        let mut rows: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+        for (x, y) in self.solid_cells() {
+            rows.entry(y).or_default().push(x);
+        }
+
+        let mut colliders = Vec::new();
+        for (y, xs) in rows {
+            let mut start = xs[0];
+            let mut end = xs[0];
+            // `None` closes the last run; a run ending at `i32::MAX` can't
+            // be extended, so `checked_add` never wraps onto a new cell.
+            for x in xs.iter().skip(1).copied().map(Some).chain([None]) {
+                if x.is_some() && end.checked_add(1) == x {
+                    end += 1;
+                    continue;
+                }
+                let width = (i64::from(end) - i64::from(start) + 1) as f64 * size;
+                colliders.push((
+                    Vec2::new(start as f64 * size + width / 2.0, (y as f64 + 0.5) * size),
+                    Collider {
+                        is_static: true,
+                        ..Collider::new(Shape::Box {
+                            half_width: width / 2.0,
+                            half_height: size / 2.0,
+                        })
+                    },
+                ));
+                if let Some(x) = x {
+                    start = x;
+                    end = x;
+                }
+            }
+        }
+        colliders
+    }
+
+    /// Resolves the optional layer argument scripts pass: a layer name, or
+    /// the first layer when omitted.
+    fn script_layer(&self, layer: Option<String>) -> mlua::Result<usize> {
+        match layer {
+            None if !self.layers.is_empty() => Ok(0),
+            None => Err(mlua::Error::runtime("tile map has no layers")),
+            Some(name) => self
+                .layer(&name)
+                .ok_or_else(|| mlua::Error::runtime(format!("no layer named '{}'", name))),
+        }
+    }
+}
+
+/// Regenerates the `TileCollider` children of every map changed since the
+/// last call. Colliders are placed relative to the map entity's `Position`,
+/// if it has one.
+pub fn sync_tile_colliders(world: &mut World) -> Result<(), EcsError> {
+    let mut dirty: Vec<(Entity, Vec<(Vec2, Collider)>)> = Vec::new();
+    world.query::<&TileMap>().for_each(|entity, map| {
+        let changed = world.ticks::<TileMap>(entity).map_or(0, |t| t.changed);
+        let synced = world.get::<CollidersSynced>(entity).map(|s| s.0);
+        if synced.is_none_or(|synced| changed >= synced) {
+            dirty.push((entity, map.colliders()));
+        }
+    });
+
+    let tick = world.tick();
+    for (entity, colliders) in dirty {
+        for child in world.children(entity) {
+            if world.get::<TileCollider>(child).is_some() {
+                world.despawn(child);
+            }
+        }
+        let origin = world.get::<Position>(entity).map_or(Vec2::ZERO, |p| p.0);
+        for (center, collider) in colliders {
+            let child = world.spawn();
+            world.insert(child, Position(origin + center))?;
+            world.insert(child, collider)?;
+            world.insert(child, TileCollider)?;
+            world.set_parent(child, Some(entity))?;
+        }
+        // Edits later in this same tick can't be told apart from the ones
+        // just synced, so the map is regenerated once more after them.
+        world.insert(entity, CollidersSynced(tick))?;
+    }
+    Ok(())
+}
+
+/// What `world:tilemap(e)` returns: reads and writes go through the world,
+/// so every script edit marks the map changed.
+pub(crate) struct TileMapHandle {
+    pub(crate) world: AnyUserData,
+    pub(crate) entity: Entity,
+}
+
+impl TileMapHandle {
+    fn with<R>(&self, f: impl FnOnce(&TileMap) -> mlua::Result<R>) -> mlua::Result<R> {
+        self.world.borrow_scoped::<World, _>(|world| {
+            let map = world
+                .get::<TileMap>(self.entity)
+                .ok_or_else(|| mlua::Error::runtime(format!("{} has no tile map", self.entity)))?;
+            f(&map)
+        })?
+    }
+
+    fn with_mut<R>(&self, f: impl FnOnce(&mut TileMap) -> mlua::Result<R>) -> mlua::Result<R> {
+        self.world.borrow_scoped::<World, _>(|world| {
+            let mut map = world
+                .get_mut::<TileMap>(self.entity)
+                .ok_or_else(|| mlua::Error::runtime(format!("{} has no tile map", self.entity)))?;
+            f(&mut map)
+        })?
+    }
+}
+
+impl UserData for TileMapHandle {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method(
+            "get_tile",
+            |_, this, (x, y, layer): (i32, i32, Option<String>)| {
+                this.with(|map| Ok(map.get_tile(map.script_layer(layer)?, x, y)))
+            },
+        );
+
+        methods.add_method(
+            "set_tile",
+            |_, this, (x, y, id, layer): (i32, i32, TileId, Option<String>)| {
+                this.with_mut(|map| Ok(map.set_tile(map.script_layer(layer)?, x, y, id)?))
+            },
+        );
+
+        methods.add_method("is_solid", |_, this, (x, y): (i32, i32)| {
+            this.with(|map| Ok(map.is_solid(x, y)))
+        });
+
+        // `{ name, solid, properties }` for a tile id, or nil.
+        methods.add_method("tile", |lua, this, id: TileId| {
+            this.with(|map| match map.tile_def(id) {
+                Some(def) => lua.to_value(def),
+                None => Ok(Value::Nil),
+            })
+        });
+
+        methods.add_method("layers", |lua, this, ()| {
+            this.with(|map| lua.create_sequence_from(map.layer_names()))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mlua::{Lua, Result};
+
+    #[test]
+    fn test_tiles_from_lua_and_generated_colliders() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let mut map = TileMap::new(2.0);
+        map.add_layer("ground");
+        map.define_tile(
+            1,
+            TileDef {
+                name: "wall".to_string(),
+                solid: true,
+                properties: BTreeMap::from([("hp".to_string(), ScriptValue::Number(3.0))]),
+            },
+        );
+        let entity = world.spawn();
+        world.insert(entity, map)?;
+
+        lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load(
+                r#"
+                local world, e = ...
+                local map = world:tilemap(e)
+                for x = -1, 1 do map:set_tile(x, 0, 1) end
+                map:set_tile(20, 5, 1, "ground")
+                assert(map:get_tile(-1, 0) == 1 and map:get_tile(2, 0) == 0)
+                assert(map:tile(1).properties.hp == 3)
+                assert(not pcall(map.set_tile, map, 0, 0, 7))
+            "#,
+            )
+            .call::<()>((handle, entity))
+        })?;
+
+        sync_tile_colliders(&mut world)?;
+        let colliders = world.children(entity);
+        assert_eq!(colliders.len(), 2);
+        assert_eq!(
+            world.get::<Position>(colliders[0]).map(|p| p.0),
+            Some(Vec2::new(1.0, 1.0))
+        );
+
+        // Untouched maps keep their colliders once a later tick has synced.
+        world.advance_tick();
+        sync_tile_colliders(&mut world)?;
+        let colliders = world.children(entity);
+        world.advance_tick();
+        sync_tile_colliders(&mut world)?;
+        assert_eq!(world.children(entity), colliders);
+
+        world
+            .get_mut::<TileMap>(entity)
+            .unwrap()
+            .set_tile(0, 20, 5, EMPTY_TILE)?;
+        sync_tile_colliders(&mut world)?;
+        assert_eq!(world.children(entity).len(), 1);
+
+        // Maps round-trip through JSON, and runs at the edge of the grid
+        // don't overflow.
+        let mut map = world.get::<TileMap>(entity).unwrap().clone();
+        map.set_tile(0, i32::MAX, 0, 1)?;
+        map.set_tile(0, i32::MAX - 1, 0, 1)?;
+        let json = serde_json::to_string(&map).map_err(mlua::Error::external)?;
+        let loaded: TileMap = serde_json::from_str(&json).map_err(mlua::Error::external)?;
+        assert_eq!(loaded, map);
+        let widths = loaded
+            .colliders()
+            .into_iter()
+            .map(|(_, collider)| match collider.shape {
+                Shape::Box { half_width, .. } => half_width * 2.0,
+                _ => 0.0,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(widths, vec![6.0, 4.0]);
+        Ok(())
+    }
+}
@@ -0,0 +1,518 @@
+use super::{TileDef, TileId, TileMap};
+use crate::data::DataError;
+use crate::ecs::{Entity, ScriptValue, World};
+use crate::math::Vec2;
+use crate::scene::{Scene, SceneEntity};
+use mlua::{Lua, Result};
+use serde_json::Value as Json;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The top bits of a Tiled gid hold flip flags rather than the tile.
+const GID_MASK: u32 = 0x0fff_ffff;
+
+/// An object from an object layer, in map pixels with y pointing down as in
+/// the editor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiledObject {
+    pub name: String,
+    /// The object's class (`type` in older Tiled versions).
+    pub kind: String,
+    pub position: Vec2,
+    pub properties: BTreeMap<String, ScriptValue>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledImport {
+    /// Tile ids are Tiled gids, so they stay unique across tilesets.
+    pub map: TileMap,
+    pub objects: Vec<TiledObject>,
+}
+
+impl TiledImport {
+    /// Loads `.tmj` (JSON) or `.tmx` (XML) maps, told apart by extension.
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("tmx") => TiledImport::from_tmx(&source),
+            Some("tmj") | Some("json") => TiledImport::from_tmj(&source),
+            _ => Err(DataError::Invalid(format!(
+                "'{}' is not a .tmx or .tmj map",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn from_tmj(source: &str) -> std::result::Result<Self, DataError> {
+        let json: Json = serde_json::from_str(source)
+            .map_err(|e| DataError::Invalid(format!("invalid Tiled JSON: {}", e)))?;
+        let mut builder = Builder::new(json_f64(&json, "tilewidth")?);
+
+        for tileset in json_array(&json, "tilesets")? {
+            if tileset.get("source").is_some() {
+                return Err(external_tileset());
+            }
+            let first_gid = json_f64(tileset, "firstgid")? as TileId;
+            let name = json_str(tileset, "name");
+            for local in 0..json_f64(tileset, "tilecount").unwrap_or(0.0) as TileId {
+                builder.define(first_gid + local, &name, local, BTreeMap::new());
+            }
+            for tile in tileset
+                .get("tiles")
+                .and_then(Json::as_array)
+                .into_iter()
+                .flatten()
+            {
+                let local = json_f64(tile, "id")? as TileId;
+                builder.define(first_gid + local, &name, local, json_properties(tile));
+            }
+        }
+
+        let mut layers = json_array(&json, "layers")?.iter().collect::<Vec<_>>();
+        while !layers.is_empty() {
+            let layer = layers.remove(0);
+            match layer.get("type").and_then(Json::as_str) {
+                Some("tilelayer") => {
+                    let index = builder.map.add_layer(&json_str(layer, "name"));
+                    match layer.get("chunks").and_then(Json::as_array) {
+                        Some(chunks) => {
+                            for chunk in chunks {
+                                builder.fill_json(index, chunk)?;
+                            }
+                        }
+                        None => builder.fill_json(index, layer)?,
+                    }
+                }
+                Some("objectgroup") => {
+                    for object in layer
+                        .get("objects")
+                        .and_then(Json::as_array)
+                        .into_iter()
+                        .flatten()
+                    {
+                        builder.objects.push(TiledObject {
+                            name: json_str(object, "name"),
+                            kind: match json_str(object, "class") {
+                                class if class.is_empty() => json_str(object, "type"),
+                                class => class,
+                            },
+                            position: Vec2::new(json_f64(object, "x")?, json_f64(object, "y")?),
+                            properties: json_properties(object),
+                        });
+                    }
+                }
+                Some("group") => {
+                    let children = json_array(layer, "layers")?;
+                    layers.splice(0..0, children.iter());
+                }
+                _ => {}
+            }
+        }
+        Ok(builder.finish())
+    }
+
+    pub fn from_tmx(source: &str) -> std::result::Result<Self, DataError> {
+        let document = roxmltree::Document::parse(source)
+            .map_err(|e| DataError::Invalid(format!("invalid Tiled XML: {}", e)))?;
+        let root = document.root_element();
+        let mut builder = Builder::new(xml_f64(root, "tilewidth")?);
+
+        for tileset in root.children().filter(|n| n.has_tag_name("tileset")) {
+            if tileset.has_attribute("source") {
+                return Err(external_tileset());
+            }
+            let first_gid = xml_f64(tileset, "firstgid")? as TileId;
+            let name = tileset.attribute("name").unwrap_or_default().to_string();
+            for local in 0..xml_f64(tileset, "tilecount").unwrap_or(0.0) as TileId {
+                builder.define(first_gid + local, &name, local, BTreeMap::new());
+            }
+            for tile in tileset.children().filter(|n| n.has_tag_name("tile")) {
+                let local = xml_f64(tile, "id")? as TileId;
+                builder.define(first_gid + local, &name, local, xml_properties(tile));
+            }
+        }
+
+        let mut layers: Vec<_> = root.children().filter(|n| n.is_element()).collect();
+        while !layers.is_empty() {
+            let layer = layers.remove(0);
+            match layer.tag_name().name() {
+                "layer" => {
+                    let index = builder
+                        .map
+                        .add_layer(layer.attribute("name").unwrap_or_default());
+                    let Some(data) = layer.children().find(|n| n.has_tag_name("data")) else {
+                        continue;
+                    };
+                    if data.attribute("encoding") != Some("csv") {
+                        return Err(DataError::Invalid(
+                            "only CSV-encoded Tiled layers are supported".to_string(),
+                        ));
+                    }
+                    let chunks: Vec<_> = data
+                        .children()
+                        .filter(|n| n.has_tag_name("chunk"))
+                        .collect();
+                    if chunks.is_empty() {
+                        let width = xml_f64(layer, "width")? as i32;
+                        builder.fill_csv(index, data.text().unwrap_or_default(), 0, 0, width)?;
+                    }
+                    for chunk in chunks {
+                        builder.fill_csv(
+                            index,
+                            chunk.text().unwrap_or_default(),
+                            xml_f64(chunk, "x")? as i32,
+                            xml_f64(chunk, "y")? as i32,
+                            xml_f64(chunk, "width")? as i32,
+                        )?;
+                    }
+                }
+                "objectgroup" => {
+                    for object in layer.children().filter(|n| n.has_tag_name("object")) {
+                        builder.objects.push(TiledObject {
+                            name: object.attribute("name").unwrap_or_default().to_string(),
+                            kind: object
+                                .attribute("class")
+                                .or_else(|| object.attribute("type"))
+                                .unwrap_or_default()
+                                .to_string(),
+                            position: Vec2::new(xml_f64(object, "x")?, xml_f64(object, "y")?),
+                            properties: xml_properties(object),
+                        });
+                    }
+                }
+                "group" => {
+                    let children: Vec<_> = layer.children().filter(|n| n.is_element()).collect();
+                    layers.splice(0..0, children);
+                }
+                _ => {}
+            }
+        }
+        Ok(builder.finish())
+    }
+
+    /// Spawns an entity holding the tile map, then one child per object
+    /// whose class (or, failing that, name) has an entry in `prefabs`.
+    /// Objects get a `Position`, and properties named `Component.field`
+    /// override that field of the prefab.
+    pub fn spawn(
+        &self,
+        world: &mut World,
+        lua: &Lua,
+        prefabs: &BTreeMap<String, SceneEntity>,
+    ) -> Result<(Entity, Vec<Entity>)> {
+        let mut scene = Scene::default();
+        for object in &self.objects {
+            let prefab = prefabs
+                .get(&object.kind)
+                .filter(|_| !object.kind.is_empty())
+                .or_else(|| prefabs.get(&object.name));
+            let Some(prefab) = prefab else {
+                continue;
+            };
+
+            let mut components = prefab.clone();
+            components.insert(
+                "Position".to_string(),
+                ScriptValue::Map(BTreeMap::from([
+                    ("x".to_string(), ScriptValue::Number(object.position.x)),
+                    ("y".to_string(), ScriptValue::Number(object.position.y)),
+                ])),
+            );
+            for (key, value) in &object.properties {
+                let Some((component, field)) = key.split_once('.') else {
+                    continue;
+                };
+                if let Some(ScriptValue::Map(fields)) = components.get_mut(component) {
+                    fields.insert(field.to_string(), value.clone());
+                }
+            }
+            scene.entities.push(components);
+        }
+
+        let map = world.spawn();
+        world.insert(map, self.map.clone())?;
+        let objects = match scene.spawn(world, lua) {
+            Ok(objects) => objects,
+            Err(e) => {
+                world.despawn(map);
+                return Err(e);
+            }
+        };
+        for &object in &objects {
+            world.set_parent(object, Some(map))?;
+        }
+        Ok((map, objects))
+    }
+}
+
+struct Builder {
+    map: TileMap,
+    objects: Vec<TiledObject>,
+}
+
+impl Builder {
+    fn new(tile_size: f64) -> Self {
+        Builder {
+            map: TileMap::new(tile_size),
+            objects: Vec::new(),
+        }
+    }
+
+    /// Tiles use their `type` property as the name when present, and the
+    /// bool property `solid` for collision.
+    fn define(
+        &mut self,
+        id: TileId,
+        tileset: &str,
+        local: TileId,
+        mut properties: BTreeMap<String, ScriptValue>,
+    ) {
+        let name = match properties.remove("type") {
+            Some(ScriptValue::String(name)) => name,
+            _ => format!("{}:{}", tileset, local),
+        };
+        let solid = matches!(properties.remove("solid"), Some(ScriptValue::Bool(true)));
+        self.map.define_tile(
+            id,
+            TileDef {
+                name,
+                solid,
+                properties,
+            },
+        );
+    }
+
+    fn fill(
+        &mut self,
+        layer: usize,
+        gids: impl IntoIterator<Item = u32>,
+        x0: i32,
+        y0: i32,
+        width: i32,
+    ) -> std::result::Result<(), DataError> {
+        for (i, gid) in gids.into_iter().enumerate() {
+            let id = gid & GID_MASK;
+            if id == 0 {
+                continue;
+            }
+            let (x, y) = (x0 + i as i32 % width.max(1), y0 + i as i32 / width.max(1));
+            self.map
+                .set_tile(layer, x, y, id)
+                .map_err(|e| DataError::Invalid(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn fill_json(&mut self, layer: usize, data: &Json) -> std::result::Result<(), DataError> {
+        let gids = data
+            .get("data")
+            .and_then(Json::as_array)
+            .ok_or_else(|| {
+                DataError::Invalid("only uncompressed array Tiled layers are supported".to_string())
+            })?
+            .iter()
+            .map(|gid| gid.as_u64().unwrap_or(0) as u32);
+        let x = json_f64(data, "x").unwrap_or(0.0) as i32;
+        let y = json_f64(data, "y").unwrap_or(0.0) as i32;
+        let width = json_f64(data, "width")? as i32;
+        self.fill(layer, gids.collect::<Vec<_>>(), x, y, width)
+    }
+
+    fn fill_csv(
+        &mut self,
+        layer: usize,
+        text: &str,
+        x: i32,
+        y: i32,
+        width: i32,
+    ) -> std::result::Result<(), DataError> {
+        let gids = text
+            .split(',')
+            .map(|gid| gid.trim().parse::<u32>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| DataError::Invalid(format!("bad tile in layer data: {}", e)))?;
+        self.fill(layer, gids, x, y, width)
+    }
+
+    fn finish(self) -> TiledImport {
+        TiledImport {
+            map: self.map,
+            objects: self.objects,
+        }
+    }
+}
+
+fn external_tileset() -> DataError {
+    DataError::Invalid("external tilesets are not supported; embed the tileset".to_string())
+}
+
+fn json_f64(json: &Json, key: &str) -> std::result::Result<f64, DataError> {
+    json.get(key)
+        .and_then(Json::as_f64)
+        .ok_or_else(|| DataError::Invalid(format!("missing number '{}'", key)))
+}
+
+fn json_str(json: &Json, key: &str) -> String {
+    json.get(key)
+        .and_then(Json::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn json_array<'a>(json: &'a Json, key: &str) -> std::result::Result<&'a Vec<Json>, DataError> {
+    json.get(key)
+        .and_then(Json::as_array)
+        .ok_or_else(|| DataError::Invalid(format!("missing list '{}'", key)))
+}
+
+fn json_properties(json: &Json) -> BTreeMap<String, ScriptValue> {
+    let mut properties = BTreeMap::new();
+    for property in json
+        .get("properties")
+        .and_then(Json::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let value = match property.get("value") {
+            Some(Json::Bool(b)) => ScriptValue::Bool(*b),
+            Some(Json::Number(n)) => ScriptValue::Number(n.as_f64().unwrap_or_default()),
+            Some(Json::String(s)) => ScriptValue::String(s.clone()),
+            _ => continue,
+        };
+        properties.insert(json_str(property, "name"), value);
+    }
+    properties
+}
+
+fn xml_f64(node: roxmltree::Node, key: &str) -> std::result::Result<f64, DataError> {
+    node.attribute(key)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| {
+            DataError::Invalid(format!(
+                "missing number '{}' on <{}>",
+                key,
+                node.tag_name().name()
+            ))
+        })
+}
+
+fn xml_properties(node: roxmltree::Node) -> BTreeMap<String, ScriptValue> {
+    let mut properties = BTreeMap::new();
+    let Some(list) = node.children().find(|n| n.has_tag_name("properties")) else {
+        return properties;
+    };
+    for property in list.children().filter(|n| n.has_tag_name("property")) {
+        let text = property
+            .attribute("value")
+            .or_else(|| property.text())
+            .unwrap_or_default();
+        let value = match property.attribute("type") {
+            Some("bool") => ScriptValue::Bool(text == "true"),
+            Some("int") | Some("float") => match text.parse() {
+                Ok(n) => ScriptValue::Number(n),
+                Err(_) => continue,
+            },
+            _ => ScriptValue::String(text.to_string()),
+        };
+        properties.insert(
+            property.attribute("name").unwrap_or_default().to_string(),
+            value,
+        );
+    }
+    properties
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16">
+ <tileset firstgid="1" name="terrain" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <tile id="1">
+   <properties>
+    <property name="type" value="wall"/>
+    <property name="solid" type="bool" value="true"/>
+   </properties>
+  </tile>
+ </tileset>
+ <layer id="1" name="ground" width="3" height="2">
+  <data encoding="csv">
+1,2,2147483650,
+0,1,1
+</data>
+ </layer>
+ <objectgroup id="2" name="spawns">
+  <object id="1" name="boss" type="Enemy" x="32" y="8">
+   <properties>
+    <property name="Health.hp" type="int" value="50"/>
+   </properties>
+  </object>
+  <object id="2" name="note" x="0" y="0"/>
+ </objectgroup>
+</map>"#;
+
+    #[test]
+    fn test_tmx_import_and_spawn() -> Result<()> {
+        let import = TiledImport::from_tmx(TMX)?;
+        let map = &import.map;
+        let ground = map.layer("ground").unwrap();
+        assert_eq!(map.get_tile(ground, 1, 0), 2);
+        // The flip flag on the third tile is dropped.
+        assert_eq!(map.get_tile(ground, 2, 0), 2);
+        assert_eq!(map.get_tile(ground, 0, 1), 0);
+        assert!(map.is_solid(2, 0) && !map.is_solid(1, 1));
+        assert_eq!(map.tile_def(2).unwrap().name, "wall");
+
+        let lua = Lua::new();
+        let mut world = World::new();
+        let prefabs = BTreeMap::from([(
+            "Enemy".to_string(),
+            SceneEntity::from([(
+                "Health".to_string(),
+                ScriptValue::Map(BTreeMap::from([(
+                    "hp".to_string(),
+                    ScriptValue::Number(10.0),
+                )])),
+            )]),
+        )]);
+        let (map_entity, objects) = import.spawn(&mut world, &lua, &prefabs)?;
+        assert_eq!(objects.len(), 1);
+        assert_eq!(world.parent(objects[0]), Some(map_entity));
+        assert_eq!(
+            world.script_component(objects[0], "Health"),
+            Some(&ScriptValue::Map(BTreeMap::from([(
+                "hp".to_string(),
+                ScriptValue::Number(50.0)
+            )])))
+        );
+
+        // A prefab that fails to spawn takes the map entity with it.
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Health(f64);
+        let mut world = World::new();
+        world.register_component::<Health>("Health");
+        assert!(import.spawn(&mut world, &lua, &prefabs).is_err());
+        assert!(world.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tmj_infinite_chunks() {
+        let import = TiledImport::from_tmj(
+            r#"{
+                "tilewidth": 8, "infinite": true,
+                "tilesets": [{ "firstgid": 1, "name": "t", "tilecount": 2 }],
+                "layers": [{ "type": "group", "layers": [{
+                    "type": "tilelayer", "name": "walls",
+                    "chunks": [{ "x": -16, "y": 0, "width": 2, "height": 1, "data": [2, 1] }]
+                }]}]
+            }"#,
+        )
+        .unwrap();
+        let walls = import.map.layer("walls").unwrap();
+        assert_eq!(import.map.get_tile(walls, -16, 0), 2);
+        assert_eq!(import.map.get_tile(walls, -15, 0), 1);
+    }
+}
@@ -0,0 +1,318 @@
+use crate::ecs::{Schedule, World};
+use mlua::{IntoLua, Lua, Result, UserData, UserDataMethods, Value};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[derive(Debug)]
+struct ScaleState {
+    scale: f64,
+    /// Ticks requested with `step_frame` while paused.
+    steps: u32,
+}
+
+/// How fast simulation time runs relative to real time: 0 is paused, 0.5
+/// slow motion. Kept as a world resource; clones share one setting, which
+/// is how the `time` Lua global controls it.
+#[derive(Debug, Clone)]
+pub struct TimeScale {
+    state: Arc<Mutex<ScaleState>>,
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        TimeScale::new(1.0)
+    }
+}
+
+impl TimeScale {
+    pub fn new(scale: f64) -> Self {
+        TimeScale {
+            state: Arc::new(Mutex::new(ScaleState {
+                scale: scale.max(0.0),
+                steps: 0,
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ScaleState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn scale(&self) -> f64 {
+        self.lock().scale
+    }
+
+    pub fn set_scale(&self, scale: f64) {
+        self.lock().scale = scale.max(0.0);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.scale() == 0.0
+    }
+
+    pub fn pause(&self) {
+        self.set_scale(0.0);
+    }
+
+    pub fn resume(&self) {
+        self.set_scale(1.0);
+    }
+
+    /// Queues exactly one fixed tick. Only meaningful while paused.
+    pub fn step_frame(&self) {
+        self.lock().steps += 1;
+    }
+
+    fn take_steps(&self) -> u32 {
+        std::mem::take(&mut self.lock().steps)
+    }
+}
+
+impl UserData for TimeScale {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("scale", |_, this, ()| Ok(this.scale()));
+        methods.add_method("set_scale", |_, this, scale: f64| {
+            this.set_scale(scale);
+            Ok(())
+        });
+        methods.add_method("is_paused", |_, this, ()| Ok(this.is_paused()));
+        methods.add_method("pause", |_, this, ()| {
+            this.pause();
+            Ok(())
+        });
+        methods.add_method("resume", |_, this, ()| {
+            this.resume();
+            Ok(())
+        });
+        methods.add_method("step_frame", |_, this, ()| {
+            this.step_frame();
+            Ok(())
+        });
+    }
+}
+
+/// Fixed-step simulation clock. Real frame time, scaled by the world's
+/// `TimeScale`, is accumulated and spent in whole `step`s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixedTimestep {
+    pub step: f64,
+    /// Caps the catch-up after a long frame so a hitch can't snowball.
+    pub max_steps: u32,
+    accumulator: f64,
+    ticks: u64,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        FixedTimestep::new(1.0 / 60.0)
+    }
+}
+
+impl FixedTimestep {
+    pub fn new(step: f64) -> Self {
+        FixedTimestep {
+            step,
+            max_steps: 8,
+            accumulator: 0.0,
+            ticks: 0,
+        }
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// How far between the last tick and the next one simulation time is,
+    /// for interpolating rendering.
+    pub fn alpha(&self) -> f64 {
+        self.accumulator / self.step
+    }
+
+    /// Returns how many ticks to run for `real_dt` seconds. While paused
+    /// only ticks requested with `step_frame` run, and time does not
+    /// accumulate.
+    pub fn advance(&mut self, real_dt: f64, scale: &TimeScale) -> u32 {
+        let steps = if scale.is_paused() {
+            scale.take_steps().min(self.max_steps)
+        } else {
+            scale.take_steps();
+            self.accumulator += real_dt.max(0.0) * scale.scale();
+            let steps = (self.accumulator / self.step).floor() as u32;
+            let steps = steps.min(self.max_steps);
+            self.accumulator = (self.accumulator - steps as f64 * self.step).min(self.step);
+            steps
+        };
+        self.ticks += steps as u64;
+        steps
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Clock {
+    /// Seconds this clock advanced in the last frame.
+    pub delta: f64,
+    pub elapsed: f64,
+}
+
+impl Clock {
+    fn tick(&mut self, delta: f64) {
+        self.delta = delta;
+        self.elapsed += delta;
+    }
+}
+
+/// The frame's clocks. `real` is wall time as measured, `unscaled` is the
+/// same with long frames clamped to `max_delta`, for UI and other effects
+/// that must keep running while gameplay is paused, and `simulated` is the
+/// time the fixed-step simulation actually advanced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Time {
+    pub real: Clock,
+    pub unscaled: Clock,
+    pub simulated: Clock,
+    pub max_delta: f64,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Time {
+            real: Clock::default(),
+            unscaled: Clock::default(),
+            simulated: Clock::default(),
+            max_delta: 0.25,
+        }
+    }
+}
+
+impl Time {
+    pub fn update(&mut self, real_dt: f64, simulated_dt: f64) {
+        let real_dt = real_dt.max(0.0);
+        self.real.tick(real_dt);
+        self.unscaled.tick(real_dt.min(self.max_delta));
+        self.simulated.tick(simulated_dt);
+    }
+}
+
+impl IntoLua for Clock {
+    fn into_lua(self, lua: &Lua) -> Result<Value> {
+        let table = lua.create_table()?;
+        table.set("delta", self.delta)?;
+        table.set("elapsed", self.elapsed)?;
+        table.into_lua(lua)
+    }
+}
+
+impl IntoLua for Time {
+    fn into_lua(self, lua: &Lua) -> Result<Value> {
+        let table = lua.create_table()?;
+        table.set("real", self.real)?;
+        table.set("unscaled", self.unscaled)?;
+        table.set("simulated", self.simulated)?;
+        table.into_lua(lua)
+    }
+}
+
+/// Advances the world's `FixedTimestep` by `real_dt` and runs `schedule`
+/// once per resulting tick, inserting default time resources if missing.
+/// `Time` is updated before the first tick, so systems see this frame's
+/// clocks.
+pub fn run_fixed(
+    world: &mut World,
+    schedule: &mut Schedule,
+    lua: &Lua,
+    real_dt: f64,
+) -> Result<u32> {
+    if world.resource::<TimeScale>().is_none() {
+        world.insert_resource(TimeScale::default());
+    }
+    if world.resource::<FixedTimestep>().is_none() {
+        world.insert_resource(FixedTimestep::default());
+    }
+    let scale = world
+        .resource::<TimeScale>()
+        .cloned()
+        .expect("inserted above");
+    if world.resource::<Time>().is_none() {
+        world.insert_resource(Time::default());
+    }
+    let fixed = world
+        .resource_mut::<FixedTimestep>()
+        .expect("inserted above");
+    let steps = fixed.advance(real_dt, &scale);
+    let simulated_dt = steps as f64 * fixed.step;
+    world
+        .resource_mut::<Time>()
+        .expect("inserted above")
+        .update(real_dt, simulated_dt);
+    for _ in 0..steps {
+        schedule.run(world, lua)?;
+    }
+    Ok(steps)
+}
+
+/// Exposes `scale` to scripts as the `time` global, e.g. `time:pause()`,
+/// `time:set_scale(0.5)` and `time:step_frame()`.
+pub fn register(lua: &Lua, scale: &TimeScale) -> Result<()> {
+    lua.globals().set("time", scale.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_pause_and_step() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let scale = TimeScale::default();
+        world.insert_resource(scale.clone());
+        world.insert_resource(FixedTimestep::new(0.25));
+        register(&lua, &scale)?;
+
+        let mut schedule = Schedule::new();
+        schedule.add_system("count", |world| {
+            world.spawn();
+            Ok(())
+        });
+
+        assert_eq!(run_fixed(&mut world, &mut schedule, &lua, 0.875)?, 3);
+        lua.load("time:set_scale(0.5)").exec()?;
+        assert_eq!(run_fixed(&mut world, &mut schedule, &lua, 0.75)?, 2);
+
+        lua.load("time:pause()").exec()?;
+        assert_eq!(run_fixed(&mut world, &mut schedule, &lua, 1.0)?, 0);
+        lua.load("time:step_frame()").exec()?;
+        assert_eq!(run_fixed(&mut world, &mut schedule, &lua, 1.0)?, 1);
+        assert_eq!(run_fixed(&mut world, &mut schedule, &lua, 1.0)?, 0);
+
+        assert_eq!(world.len(), 6);
+        assert_eq!(world.resource::<FixedTimestep>().unwrap().ticks(), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_clocks_while_paused() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.insert_resource(TimeScale::new(0.0));
+        world.insert_resource(FixedTimestep::new(0.25));
+        let mut schedule = Schedule::new();
+
+        run_fixed(&mut world, &mut schedule, &lua, 0.125)?;
+        run_fixed(&mut world, &mut schedule, &lua, 2.0)?;
+        let time = world.resource::<Time>().unwrap();
+        assert_eq!(time.real.elapsed, 2.125);
+        assert_eq!(time.unscaled.elapsed, 0.375);
+        assert_eq!(time.simulated.elapsed, 0.0);
+
+        let time = lua.scope(|scope| {
+            let handle = scope.create_userdata_ref_mut(&mut world)?;
+            lua.load("return function(world) return world:time().unscaled.delta end")
+                .eval::<mlua::Function>()?
+                .call::<f64>(handle)
+        })?;
+        assert_eq!(time, 0.25);
+        Ok(())
+    }
+}
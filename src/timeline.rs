@@ -0,0 +1,577 @@
+//! Cutscenes as data: a `Timeline` keys transforms, animations, the camera,
+//! sounds and script callbacks to time, and a `Sequencer` plays it back with
+//! pause and seek, so a scripted sequence needn't be a coroutine counting
+//! frames.
+
+use crate::assets::read_lua_asset;
+use crate::camera::{Camera, main_camera};
+use crate::curve::Curve;
+use crate::data::{self, DataError};
+use crate::ecs::World;
+use crate::math::{Transform, Vec2};
+use crate::physics::Position;
+#[cfg(feature = "client")]
+use crate::sprite::SpriteAnimator;
+use crate::time::FixedTimestep;
+use mlua::{Error, Function, Lua, Result, UserData, UserDataMethods};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Script event every `TimelineEvent` is also sent under, as a table with
+/// a `kind` field.
+pub const TIMELINE_EVENT: &str = "timeline";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cue {
+    pub time: f64,
+    pub name: String,
+}
+
+/// Entity targets are names, as in `world:find`. Unset curves leave their
+/// value alone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Track {
+    /// Sets the target's `Transform` and `Position`, whichever it has.
+    Transform {
+        target: String,
+        #[serde(default)]
+        x: Option<Curve>,
+        #[serde(default)]
+        y: Option<Curve>,
+        #[serde(default)]
+        rotation: Option<Curve>,
+        #[serde(default)]
+        scale: Option<Curve>,
+    },
+    /// Plays each cue's animation on the target's `SpriteAnimator`; servers
+    /// skip it.
+    Animation { target: String, cues: Vec<Cue> },
+    /// Moves the named camera, or the main one.
+    Camera {
+        #[serde(default)]
+        camera: Option<String>,
+        #[serde(default)]
+        x: Option<Curve>,
+        #[serde(default)]
+        y: Option<Curve>,
+        #[serde(default)]
+        zoom: Option<Curve>,
+    },
+    /// Sends each cue's sound as `TimelineEvent::Audio` for the audio layer.
+    Audio { cues: Vec<Cue> },
+    /// Calls the callback registered under each cue's name.
+    Script { cues: Vec<Cue> },
+}
+
+impl Track {
+    fn cues(&self) -> &[Cue] {
+        match self {
+            Track::Animation { cues, .. } | Track::Audio { cues } | Track::Script { cues } => cues,
+            _ => &[],
+        }
+    }
+
+    fn curves(&self) -> Vec<&Curve> {
+        let curves = match self {
+            Track::Transform {
+                x,
+                y,
+                rotation,
+                scale,
+                ..
+            } => vec![x, y, rotation, scale],
+            Track::Camera { x, y, zoom, .. } => vec![x, y, zoom],
+            _ => Vec::new(),
+        };
+        curves.into_iter().flatten().collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Timeline {
+    /// Defaults to the last key or cue.
+    #[serde(default)]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub looping: bool,
+    pub tracks: Vec<Track>,
+}
+
+impl Timeline {
+    pub fn length(&self) -> f64 {
+        self.duration.unwrap_or_else(|| {
+            let cues = self.tracks.iter().flat_map(Track::cues).map(|cue| cue.time);
+            let keys = self
+                .tracks
+                .iter()
+                .flat_map(Track::curves)
+                .filter_map(|curve| curve.keys().last().map(|key| key.time));
+            cues.chain(keys).fold(0.0, f64::max)
+        })
+    }
+}
+
+/// Timelines by name, loaded from a RON map such as
+/// `{ "intro": (tracks: [Script(cues: [(time: 2.0, name: "door")])]) }`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Timelines {
+    timelines: BTreeMap<String, Timeline>,
+}
+
+impl Timelines {
+    pub fn new() -> Self {
+        Timelines::default()
+    }
+
+    pub fn from_ron(source: &str) -> std::result::Result<Self, DataError> {
+        data::from_ron(source)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::result::Result<Self, DataError> {
+        data::load_ron(path)
+    }
+
+    pub fn insert(&mut self, name: &str, timeline: Timeline) {
+        self.timelines.insert(name.to_string(), timeline);
+    }
+
+    pub fn extend(&mut self, other: Timelines) {
+        self.timelines.extend(other.timelines);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Timeline> {
+        self.timelines.get(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TimelineEvent {
+    Started {
+        timeline: String,
+    },
+    /// A script cue, sent whether or not a callback handles it.
+    Cue {
+        timeline: String,
+        name: String,
+    },
+    Audio {
+        timeline: String,
+        sound: String,
+    },
+    /// Reached the end without looping; `stop` sends nothing.
+    Finished {
+        timeline: String,
+    },
+}
+
+pub type RustCueHook = Rc<dyn Fn(&mut World, &Lua, &str) -> Result<()>>;
+
+#[derive(Clone)]
+enum CueHook {
+    Rust(RustCueHook),
+    /// Called as `hook(world, timeline)`.
+    Lua(Function),
+}
+
+#[derive(Debug, Clone)]
+struct Playback {
+    time: f64,
+    paused: bool,
+    /// Cues at exactly `time` already fired, as they have after advancing;
+    /// a fresh start or a seek leaves them to fire.
+    fired: bool,
+    started: bool,
+}
+
+#[derive(Default)]
+struct SequencerState {
+    timelines: Timelines,
+    hooks: BTreeMap<String, CueHook>,
+    playing: BTreeMap<String, Playback>,
+}
+
+/// Plays timelines, any number at once, each at most once at a time.
+/// Clones share playback, as does the `timeline` Lua global.
+#[derive(Clone, Default)]
+pub struct Sequencer {
+    state: Rc<RefCell<SequencerState>>,
+}
+
+fn missing(name: &str) -> Error {
+    Error::runtime(format!("timeline '{}' is not playing", name))
+}
+
+impl Sequencer {
+    pub fn new(timelines: Timelines) -> Self {
+        let sequencer = Sequencer::default();
+        sequencer.state.borrow_mut().timelines = timelines;
+        sequencer
+    }
+
+    pub fn timelines_mut(&self) -> std::cell::RefMut<'_, Timelines> {
+        std::cell::RefMut::map(self.state.borrow_mut(), |state| &mut state.timelines)
+    }
+
+    pub fn on(&self, cue: &str, f: impl Fn(&mut World, &Lua, &str) -> Result<()> + 'static) {
+        let mut state = self.state.borrow_mut();
+        state
+            .hooks
+            .insert(cue.to_string(), CueHook::Rust(Rc::new(f)));
+    }
+
+    /// Plays `name` from the start, restarting it if already playing.
+    pub fn play(&self, name: &str) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        if state.timelines.get(name).is_none() {
+            return Err(Error::runtime(format!("no timeline named '{}'", name)));
+        }
+        let playback = Playback {
+            time: 0.0,
+            paused: false,
+            fired: false,
+            started: false,
+        };
+        state.playing.insert(name.to_string(), playback);
+        Ok(())
+    }
+
+    pub fn pause(&self, name: &str) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        state
+            .playing
+            .get_mut(name)
+            .ok_or_else(|| missing(name))?
+            .paused = true;
+        Ok(())
+    }
+
+    pub fn resume(&self, name: &str) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        state
+            .playing
+            .get_mut(name)
+            .ok_or_else(|| missing(name))?
+            .paused = false;
+        Ok(())
+    }
+
+    /// Jumps to `time` without firing the cues in between; the tracks pose
+    /// there at the next update, paused or not.
+    pub fn seek(&self, name: &str, time: f64) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        let length = state.timelines.get(name).map_or(0.0, Timeline::length);
+        let playback = state.playing.get_mut(name).ok_or_else(|| missing(name))?;
+        playback.time = time.clamp(0.0, length);
+        playback.fired = false;
+        Ok(())
+    }
+
+    /// Stops `name` where it is, leaving everything as last posed.
+    pub fn stop(&self, name: &str) -> bool {
+        self.state.borrow_mut().playing.remove(name).is_some()
+    }
+
+    pub fn time(&self, name: &str) -> Option<f64> {
+        self.state.borrow().playing.get(name).map(|p| p.time)
+    }
+
+    pub fn is_playing(&self, name: &str) -> bool {
+        self.state.borrow().playing.contains_key(name)
+    }
+
+    pub fn is_paused(&self, name: &str) -> bool {
+        self.state
+            .borrow()
+            .playing
+            .get(name)
+            .is_some_and(|p| p.paused)
+    }
+
+    /// Advances every unpaused timeline by `dt`, fires the cues passed and
+    /// poses every track.
+    pub fn update(&self, world: &mut World, lua: &Lua, dt: f64) -> Result<()> {
+        let names: Vec<String> = self.state.borrow().playing.keys().cloned().collect();
+        for name in names {
+            let (timeline, playback) = {
+                let state = self.state.borrow();
+                let (Some(timeline), Some(playback)) =
+                    (state.timelines.get(&name), state.playing.get(&name))
+                else {
+                    continue;
+                };
+                (timeline.clone(), playback.clone())
+            };
+            if !playback.started {
+                world.emit(
+                    TIMELINE_EVENT,
+                    TimelineEvent::Started {
+                        timeline: name.clone(),
+                    },
+                )?;
+            }
+            let length = timeline.length();
+            let (from, mut to) = (playback.time, playback.time);
+            let mut spans = Vec::new();
+            let mut finished = false;
+            if !playback.paused {
+                to += dt;
+                spans.push((from, to.min(length), !playback.fired));
+                if to >= length {
+                    if timeline.looping && length > 0.0 {
+                        to %= length;
+                        spans.push((0.0, to, true));
+                    } else {
+                        to = length;
+                        finished = true;
+                    }
+                }
+            }
+            if let Some(stored) = self.state.borrow_mut().playing.get_mut(&name) {
+                stored.time = to;
+                stored.started = true;
+                stored.fired |= !playback.paused;
+            }
+            for (start, end, inclusive) in spans {
+                for track in &timeline.tracks {
+                    let passed = track.cues().iter().filter(|cue| {
+                        (cue.time > start || inclusive && cue.time == start) && cue.time <= end
+                    });
+                    for cue in passed {
+                        fire(self, world, lua, &name, track, cue)?;
+                    }
+                }
+            }
+            for track in &timeline.tracks {
+                pose(world, track, to);
+            }
+            if finished {
+                self.state.borrow_mut().playing.remove(&name);
+                world.emit(TIMELINE_EVENT, TimelineEvent::Finished { timeline: name })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// An exclusive system advancing playback by the fixed step.
+    pub fn into_system(self) -> impl FnMut(&mut World, &Lua) -> Result<()> {
+        move |world, lua| {
+            let dt = world
+                .resource::<FixedTimestep>()
+                .map_or(0.0, |fixed| fixed.step);
+            self.update(world, lua, dt)
+        }
+    }
+
+    /// Adds the `timeline` global: `play(name)`, `pause(name)`,
+    /// `resume(name)`, `seek(name, time)`, `stop(name)`, `time(name)`,
+    /// `playing(name)`, `on(cue, function(world, timeline))` and
+    /// `load(path)`, all as methods.
+    pub fn register_lua(&self, lua: &Lua) -> Result<()> {
+        lua.globals().set("timeline", self.clone())
+    }
+}
+
+fn fire(
+    sequencer: &Sequencer,
+    world: &mut World,
+    lua: &Lua,
+    timeline: &str,
+    track: &Track,
+    cue: &Cue,
+) -> Result<()> {
+    match track {
+        #[cfg(feature = "client")]
+        Track::Animation { target, .. } => {
+            let animator = world
+                .find(target)
+                .and_then(|entity| world.get_mut::<SpriteAnimator>(entity));
+            if !animator.is_some_and(|mut animator| animator.play(&cue.name)) {
+                log::warn!(target: "timeline", "'{}' can't play '{}'", target, cue.name);
+            }
+            Ok(())
+        }
+        Track::Audio { .. } => world.emit(
+            TIMELINE_EVENT,
+            TimelineEvent::Audio {
+                timeline: timeline.to_string(),
+                sound: cue.name.clone(),
+            },
+        ),
+        Track::Script { .. } => {
+            let hook = sequencer.state.borrow().hooks.get(&cue.name).cloned();
+            match hook {
+                Some(CueHook::Rust(hook)) => hook(world, lua, timeline)?,
+                Some(CueHook::Lua(hook)) => lua.scope(|scope| {
+                    let handle = scope.create_userdata_ref_mut(&mut *world)?;
+                    hook.call::<()>((handle, timeline))
+                })?,
+                None => {}
+            }
+            world.emit(
+                TIMELINE_EVENT,
+                TimelineEvent::Cue {
+                    timeline: timeline.to_string(),
+                    name: cue.name.clone(),
+                },
+            )
+        }
+        _ => Ok(()),
+    }
+}
+
+fn pose(world: &mut World, track: &Track, time: f64) {
+    let sample = |curve: &Option<Curve>| curve.as_ref().map(|curve| curve.sample(time));
+    match track {
+        Track::Transform {
+            target,
+            x,
+            y,
+            rotation,
+            scale,
+        } => {
+            let Some(entity) = world.find(target) else {
+                return;
+            };
+            let (x, y) = (sample(x), sample(y));
+            if let Some(mut transform) = world.get_mut::<Transform>(entity) {
+                let at = transform.translation;
+                transform.translation = Vec2::new(x.unwrap_or(at.x), y.unwrap_or(at.y));
+                transform.rotation = sample(rotation).unwrap_or(transform.rotation);
+                if let Some(scale) = sample(scale) {
+                    transform.scale = Vec2::new(scale, scale);
+                }
+            }
+            if let Some(mut position) = world.get_mut::<Position>(entity) {
+                position.0 = Vec2::new(x.unwrap_or(position.0.x), y.unwrap_or(position.0.y));
+            }
+        }
+        Track::Camera { camera, x, y, zoom } => {
+            let entity = match camera {
+                Some(name) => world.find(name),
+                None => main_camera(world),
+            };
+            if let Some(mut camera) = entity.and_then(|entity| world.get_mut::<Camera>(entity)) {
+                let at = camera.center;
+                camera.center = Vec2::new(sample(x).unwrap_or(at.x), sample(y).unwrap_or(at.y));
+                camera.zoom = sample(zoom).unwrap_or(camera.zoom);
+            }
+        }
+        _ => {}
+    }
+}
+
+impl UserData for Sequencer {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("play", |_, this, name: String| this.play(&name));
+        methods.add_method("pause", |_, this, name: String| this.pause(&name));
+        methods.add_method("resume", |_, this, name: String| this.resume(&name));
+        methods.add_method("seek", |_, this, (name, time): (String, f64)| {
+            this.seek(&name, time)
+        });
+        methods.add_method("stop", |_, this, name: String| Ok(this.stop(&name)));
+        methods.add_method("time", |_, this, name: String| Ok(this.time(&name)));
+        methods.add_method(
+            "playing",
+            |_, this, name: String| Ok(this.is_playing(&name)),
+        );
+        methods.add_method("on", |_, this, (cue, hook): (String, Function)| {
+            this.state
+                .borrow_mut()
+                .hooks
+                .insert(cue, CueHook::Lua(hook));
+            Ok(())
+        });
+        methods.add_method("load", |lua, this, path: String| {
+            let loaded = Timelines::from_ron(&read_lua_asset(lua, &path)?)?;
+            this.timelines_mut().extend(loaded);
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTRO: &str = r#"{
+        "intro": (
+            tracks: [
+                Transform(target: "hero", x: Some((keys: [(time: 0, value: 0), (time: 2, value: 10)]))),
+                Camera(zoom: Some((keys: [(time: 0, value: 1), (time: 2, value: 2)]))),
+                Audio(cues: [(time: 0, name: "drums")]),
+                Script(cues: [(time: 1, name: "door"), (time: 2, name: "title")]),
+            ],
+        ),
+    }"#;
+
+    #[test]
+    fn test_playback_cues_pause_and_seek() -> Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        let hero = world.spawn();
+        world.set_name(hero, "hero")?;
+        world.insert(hero, Transform::default())?;
+        let camera = world.spawn();
+        world.insert(camera, Camera::default())?;
+
+        let sequencer = Sequencer::new(Timelines::from_ron(INTRO)?);
+        sequencer.register_lua(&lua)?;
+        lua.load(
+            r#"
+            doors = 0
+            timeline:on("door", function(world, name) doors = doors + 1 end)
+            timeline:play("intro")
+        "#,
+        )
+        .exec()?;
+        let x = |world: &World| world.get::<Transform>(hero).unwrap().translation.x;
+
+        sequencer.update(&mut world, &lua, 0.5)?;
+        assert_eq!(x(&world), 2.5);
+        sequencer.update(&mut world, &lua, 0.5)?;
+        assert_eq!(lua.globals().get::<u32>("doors")?, 1);
+
+        // Paused, time stands still; a seek poses without firing cues.
+        lua.load("timeline:pause('intro'); timeline:seek('intro', 0.5)")
+            .exec()?;
+        sequencer.update(&mut world, &lua, 1.0)?;
+        assert_eq!((x(&world), sequencer.time("intro")), (2.5, Some(0.5)));
+        sequencer.resume("intro")?;
+        sequencer.update(&mut world, &lua, 0.5)?;
+        assert_eq!(lua.globals().get::<u32>("doors")?, 2);
+        sequencer.update(&mut world, &lua, 5.0)?;
+        assert_eq!(x(&world), 10.0);
+        assert_eq!(world.get::<Camera>(camera).unwrap().zoom, 2.0);
+        assert!(!sequencer.is_playing("intro"));
+
+        let events: Vec<TimelineEvent> = world.drain_events::<TimelineEvent>();
+        let cue = |name: &str| TimelineEvent::Cue {
+            timeline: "intro".to_string(),
+            name: name.to_string(),
+        };
+        assert_eq!(
+            events,
+            [
+                TimelineEvent::Started {
+                    timeline: "intro".to_string()
+                },
+                TimelineEvent::Audio {
+                    timeline: "intro".to_string(),
+                    sound: "drums".to_string()
+                },
+                cue("door"),
+                cue("door"),
+                cue("title"),
+                TimelineEvent::Finished {
+                    timeline: "intro".to_string()
+                },
+            ]
+        );
+        Ok(())
+    }
+}
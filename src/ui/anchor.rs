@@ -0,0 +1,195 @@
+use crate::camera::{Camera, Viewport, main_camera};
+use crate::ecs::{Entity, World};
+use crate::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Which point of the viewport an element is pinned to. The element's
+/// own matching point goes there, so a `TopRight` panel hangs from the
+/// top-right corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+    /// Fills the viewport, less `margin` on every side.
+    Stretch,
+}
+
+impl Anchor {
+    /// The anchor point as a fraction of width and height from the top
+    /// left.
+    fn fraction(self) -> Vec2 {
+        let (x, y) = match self {
+            Anchor::TopLeft | Anchor::Stretch => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        };
+        Vec2::new(x, y)
+    }
+}
+
+/// Screen-space placement for a HUD element, in pixels with `y` down.
+/// `layout_ui` resolves it against a camera's viewport into a `UiRect`
+/// every frame, so elements keep their place when the window resizes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiAnchor {
+    pub anchor: Anchor,
+    /// Added after anchoring; negative moves left and up.
+    pub offset: Vec2,
+    /// Ignored when stretching.
+    pub size: Vec2,
+    pub margin: f64,
+    /// The camera whose viewport it sits in; the main camera when unset.
+    pub camera: Option<Entity>,
+}
+
+impl Default for UiAnchor {
+    fn default() -> Self {
+        UiAnchor {
+            anchor: Anchor::TopLeft,
+            offset: Vec2::ZERO,
+            size: Vec2::ZERO,
+            margin: 0.0,
+            camera: None,
+        }
+    }
+}
+
+impl UiAnchor {
+    pub fn resolve(&self, viewport: &Viewport) -> UiRect {
+        if self.anchor == Anchor::Stretch {
+            return UiRect {
+                x: viewport.x + self.margin + self.offset.x,
+                y: viewport.y + self.margin + self.offset.y,
+                w: (viewport.w - 2.0 * self.margin).max(0.0),
+                h: (viewport.h - 2.0 * self.margin).max(0.0),
+            };
+        }
+        let fraction = self.anchor.fraction();
+        UiRect {
+            x: viewport.x + (viewport.w - self.size.x) * fraction.x + self.offset.x,
+            y: viewport.y + (viewport.h - self.size.y) * fraction.y + self.offset.y,
+            w: self.size.x,
+            h: self.size.y,
+        }
+    }
+}
+
+/// Where `layout_ui` last put an element, in screen pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct UiRect {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl UiRect {
+    pub fn center(&self) -> Vec2 {
+        Vec2::new(self.x + self.w / 2.0, self.y + self.h / 2.0)
+    }
+}
+
+pub fn register_components(world: &mut World) {
+    world.register_component::<UiAnchor>("UiAnchor");
+    world.register_component::<UiRect>("UiRect");
+}
+
+/// Writes a `UiRect` for every `UiAnchor`. Elements whose camera is gone
+/// follow the main camera, or a default viewport without any camera.
+pub fn layout_ui(world: &mut World) {
+    let main = main_camera(world);
+    let mut rects = Vec::new();
+    world.query::<&UiAnchor>().for_each(|entity, anchor| {
+        let viewport = anchor
+            .camera
+            .and_then(|camera| world.get::<Camera>(camera).map(|c| c.viewport))
+            .or_else(|| main.and_then(|camera| world.get::<Camera>(camera).map(|c| c.viewport)))
+            .unwrap_or_default();
+        rects.push((entity, anchor.resolve(&viewport)));
+    });
+    for (entity, rect) in rects {
+        world.insert(entity, rect).expect("queried alive");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchors_follow_the_viewport() {
+        let mut world = World::new();
+        let camera = world.spawn();
+        world.insert(camera, Camera::default()).unwrap();
+        let health = world.spawn();
+        let anchor = UiAnchor {
+            anchor: Anchor::TopRight,
+            offset: Vec2::new(-10.0, 10.0),
+            size: Vec2::new(200.0, 20.0),
+            ..UiAnchor::default()
+        };
+        world.insert(health, anchor).unwrap();
+        let backdrop = world.spawn();
+        let stretch = UiAnchor {
+            anchor: Anchor::Stretch,
+            margin: 5.0,
+            ..UiAnchor::default()
+        };
+        world.insert(backdrop, stretch).unwrap();
+
+        layout_ui(&mut world);
+        let rect = |world: &World, entity| *world.get::<UiRect>(entity).unwrap();
+        assert_eq!(
+            rect(&world, health),
+            UiRect {
+                x: 1070.0,
+                y: 10.0,
+                w: 200.0,
+                h: 20.0
+            }
+        );
+
+        world.get_mut::<Camera>(camera).unwrap().viewport = Viewport {
+            x: 0.0,
+            y: 0.0,
+            w: 800.0,
+            h: 600.0,
+        };
+        layout_ui(&mut world);
+        assert_eq!(rect(&world, health).x, 590.0);
+        assert_eq!(
+            rect(&world, backdrop),
+            UiRect {
+                x: 5.0,
+                y: 5.0,
+                w: 790.0,
+                h: 590.0
+            }
+        );
+
+        let centered = UiAnchor {
+            anchor: Anchor::Center,
+            size: Vec2::new(100.0, 50.0),
+            ..UiAnchor::default()
+        };
+        assert_eq!(
+            centered.resolve(&Viewport::default()).center(),
+            Vec2::new(640.0, 360.0)
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use crate::ecs::{Component, Entity, ScriptValue, World};
+use serde::Serialize;
+use std::any::Any;
+use std::collections::BTreeMap;
+
+pub type PropertySource = Box<dyn Fn(&World) -> Option<ScriptValue>>;
+pub type WidgetHandler = Box<dyn Fn(&mut World, ScriptValue)>;
+
+/// Converts through JSON, so anything serializable to plain data binds.
+fn to_script_value<T: Serialize>(value: &T) -> Option<ScriptValue> {
+    serde_json::to_value(value)
+        .and_then(serde_json::from_value)
+        .ok()
+}
+
+/// Glue between the world and an external UI layer. Named properties are
+/// read from components and resources, and widget events are turned into
+/// engine events, so the UI only ever deals in names and plain values.
+#[derive(Default)]
+pub struct Bindings {
+    properties: BTreeMap<String, PropertySource>,
+    widgets: BTreeMap<String, WidgetHandler>,
+    /// What `sync` last reported for each property.
+    values: BTreeMap<String, ScriptValue>,
+}
+
+impl Bindings {
+    pub fn new() -> Self {
+        Bindings::default()
+    }
+
+    pub fn bind(
+        &mut self,
+        property: &str,
+        source: impl Fn(&World) -> Option<ScriptValue> + 'static,
+    ) {
+        self.properties
+            .insert(property.to_string(), Box::new(source));
+        self.values.remove(property);
+    }
+
+    pub fn bind_resource<R: Any + Send + Sync + Serialize>(&mut self, property: &str) {
+        self.bind(property, |world| to_script_value(world.resource::<R>()?));
+    }
+
+    pub fn bind_component<T: Component + Serialize>(&mut self, property: &str, entity: Entity) {
+        self.bind(property, move |world| {
+            to_script_value(&*world.get::<T>(entity)?)
+        });
+    }
+
+    pub fn bind_script_component(&mut self, property: &str, entity: Entity, component: &str) {
+        let component = component.to_string();
+        self.bind(property, move |world| {
+            world.script_component(entity, &component).cloned()
+        });
+    }
+
+    pub fn unbind(&mut self, property: &str) -> bool {
+        self.values.remove(property);
+        self.properties.remove(property).is_some()
+    }
+
+    /// The value `sync` last reported for `property`.
+    pub fn get(&self, property: &str) -> Option<&ScriptValue> {
+        self.values.get(property)
+    }
+
+    /// Re-reads every property and returns those whose value changed since
+    /// the last sync, with `None` for a source that has gone away (e.g. a
+    /// despawned entity).
+    pub fn sync(&mut self, world: &World) -> Vec<(String, Option<ScriptValue>)> {
+        let mut changed = Vec::new();
+        for (property, source) in &self.properties {
+            let value = source(world);
+            if value.as_ref() == self.values.get(property) {
+                continue;
+            }
+            match &value {
+                Some(value) => self.values.insert(property.clone(), value.clone()),
+                None => self.values.remove(property),
+            };
+            changed.push((property.clone(), value));
+        }
+        changed
+    }
+
+    pub fn on_widget(
+        &mut self,
+        widget_event: &str,
+        handler: impl Fn(&mut World, ScriptValue) + 'static,
+    ) {
+        self.widgets
+            .insert(widget_event.to_string(), Box::new(handler));
+    }
+
+    /// Turns `widget_event` into a typed event built from its payload.
+    /// Payloads `event` rejects are dropped.
+    pub fn widget_to_event<E: Any + Send + Sync>(
+        &mut self,
+        widget_event: &str,
+        event: impl Fn(ScriptValue) -> Option<E> + 'static,
+    ) {
+        self.on_widget(widget_event, move |world, payload| {
+            if let Some(event) = event(payload) {
+                world.send_event(event);
+            }
+        });
+    }
+
+    /// Re-sends `widget_event` as the script event `name`, for
+    /// `world:poll(name)` in Lua systems.
+    pub fn widget_to_script_event(&mut self, widget_event: &str, name: &str) {
+        let name = name.to_string();
+        self.on_widget(widget_event, move |world, payload| {
+            world.send_script_event(&name, payload)
+        });
+    }
+
+    /// Called by the UI layer when a widget fires. Returns whether anything
+    /// handles `widget_event`.
+    pub fn dispatch(&self, world: &mut World, widget_event: &str, payload: ScriptValue) -> bool {
+        match self.widgets.get(widget_event) {
+            Some(handler) => {
+                handler(world, payload);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Score(u32);
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Health {
+        hp: f64,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct StartGame;
+
+    #[test]
+    fn test_properties_sync_and_widgets_dispatch() {
+        let mut world = World::new();
+        world.insert_resource(Score(10));
+        let player = world.spawn();
+        world.insert(player, Health { hp: 3.0 }).unwrap();
+
+        let mut bindings = Bindings::new();
+        bindings.bind_resource::<Score>("hud.score");
+        bindings.bind_component::<Health>("hud.health", player);
+        bindings.widget_to_event("menu.start", |_| Some(StartGame));
+        bindings.widget_to_script_event("menu.volume", "volume_changed");
+
+        assert_eq!(bindings.sync(&world).len(), 2);
+        assert!(bindings.sync(&world).is_empty());
+
+        world.resource_mut::<Score>().unwrap().0 = 11;
+        world.despawn(player);
+        assert_eq!(
+            bindings.sync(&world),
+            vec![
+                ("hud.health".to_string(), None),
+                ("hud.score".to_string(), Some(ScriptValue::Number(11.0))),
+            ]
+        );
+
+        assert!(bindings.dispatch(&mut world, "menu.start", ScriptValue::Bool(true)));
+        assert!(bindings.dispatch(&mut world, "menu.volume", ScriptValue::Number(0.5)));
+        assert!(!bindings.dispatch(&mut world, "menu.quit", ScriptValue::Bool(true)));
+        assert_eq!(world.drain_events::<StartGame>(), vec![StartGame]);
+        assert_eq!(
+            world.drain_script_events("volume_changed"),
+            vec![ScriptValue::Number(0.5)]
+        );
+    }
+}
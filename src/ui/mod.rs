@@ -0,0 +1,9 @@
+mod anchor;
+mod bindings;
+#[cfg(feature = "egui")]
+mod overlay;
+
+pub use anchor::{Anchor, UiAnchor, UiRect, layout_ui, register_components};
+pub use bindings::{Bindings, PropertySource, WidgetHandler};
+#[cfg(feature = "egui")]
+pub use overlay::DebugOverlay;
@@ -0,0 +1,338 @@
+use crate::console::Console;
+use crate::diagnostics::WorldMetrics;
+use crate::ecs::{Entity, Events, FrameOverrun, Schedule, ScriptValue, SystemFailed, World};
+use crate::engine::AppExit;
+use crate::tasks::TaskFailed;
+use egui::{Color32, Context, ScrollArea, Sense, Shape, Stroke, Window, pos2, vec2};
+use mlua::{FromLua, Lua};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Debug;
+use std::time::Duration;
+
+type EventWatcher = Box<dyn Fn(&World, &mut usize) -> Vec<String>>;
+
+/// Built-in debug windows drawn with egui: an entity browser over the
+/// component registry, per-system timing graphs, an event log and the
+/// console. The integrator owns the egui context and renders its output.
+pub struct DebugOverlay {
+    pub open: bool,
+    pub history_len: usize,
+    filter: String,
+    selected: Option<Entity>,
+    /// Milliseconds per frame for each system, oldest first.
+    history: BTreeMap<String, VecDeque<f32>>,
+    last_totals: BTreeMap<String, Duration>,
+    watchers: Vec<(EventWatcher, usize)>,
+    log: VecDeque<String>,
+    input: String,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        let mut overlay = DebugOverlay {
+            open: true,
+            history_len: 120,
+            filter: String::new(),
+            selected: None,
+            history: BTreeMap::new(),
+            last_totals: BTreeMap::new(),
+            watchers: Vec::new(),
+            log: VecDeque::new(),
+            input: String::new(),
+        };
+        overlay.watch_events::<SystemFailed>("system failed");
+        overlay.watch_events::<FrameOverrun>("frame overrun");
+        overlay.watch_events::<TaskFailed>("task failed");
+        overlay.watch_events::<AppExit>("exit");
+        overlay
+    }
+}
+
+impl DebugOverlay {
+    pub fn new() -> Self {
+        DebugOverlay::default()
+    }
+
+    /// Logs every `E` event as it is sent. Events are only looked at, so
+    /// the systems that drain them still see them.
+    pub fn watch_events<E: Debug + Send + Sync + 'static>(&mut self, label: &str) {
+        let label = label.to_string();
+        let watcher: EventWatcher = Box::new(move |world, seen| {
+            let Some(events) = world.resource::<Events<E>>() else {
+                return Vec::new();
+            };
+            // The queue was drained since we last looked.
+            if events.len() < *seen {
+                *seen = 0;
+            }
+            let lines = events
+                .iter()
+                .skip(*seen)
+                .map(|event| format!("{}: {:?}", label, event))
+                .collect();
+            *seen = events.len();
+            lines
+        });
+        self.watchers.push((watcher, 0));
+    }
+
+    pub fn log(&mut self, line: impl Into<String>) {
+        self.log.push_back(line.into());
+        while self.log.len() > self.history_len {
+            self.log.pop_front();
+        }
+    }
+
+    /// Samples the schedule's timings after a frame. Needs
+    /// `Schedule::set_timing(true)`.
+    pub fn record_frame(&mut self, schedule: &Schedule) {
+        for (name, total) in schedule.timings() {
+            let last = self.last_totals.insert(name.to_string(), total);
+            let frame = total.saturating_sub(last.unwrap_or(total));
+            let samples = self.history.entry(name.to_string()).or_default();
+            samples.push_back(frame.as_secs_f32() * 1000.0);
+            while samples.len() > self.history_len {
+                samples.pop_front();
+            }
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context, world: &mut World, lua: &Lua, console: Option<&Console>) {
+        let lines: Vec<String> = self
+            .watchers
+            .iter_mut()
+            .flat_map(|(watcher, seen)| watcher(world, seen))
+            .collect();
+        for line in lines {
+            self.log(line);
+        }
+        if !self.open {
+            return;
+        }
+        self.entities_window(ctx, world, lua);
+        self.profiler_window(ctx);
+        if let Some(metrics) = world.resource::<WorldMetrics>() {
+            metrics_window(ctx, metrics);
+        }
+        self.log_window(ctx);
+        if let Some(console) = console {
+            self.console_window(ctx, world, lua, console);
+        }
+    }
+
+    fn entities_window(&mut self, ctx: &Context, world: &World, lua: &Lua) {
+        Window::new("Entities")
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                ui.text_edit_singleline(&mut self.filter);
+                ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                    for entity in world.entities() {
+                        let label = match world.name(entity) {
+                            Some(name) => format!("{} {}", entity, name),
+                            None => entity.to_string(),
+                        };
+                        if !label.contains(&self.filter) {
+                            continue;
+                        }
+                        if ui
+                            .selectable_label(self.selected == Some(entity), label)
+                            .clicked()
+                        {
+                            self.selected = Some(entity);
+                        }
+                    }
+                });
+                ui.separator();
+                let Some(entity) = self.selected.filter(|&e| world.is_alive(e)) else {
+                    ui.label("No entity selected");
+                    return;
+                };
+                for (name, value) in components(world, lua, entity) {
+                    ui.collapsing(name, |ui| ui.monospace(value));
+                }
+            });
+    }
+
+    fn profiler_window(&self, ctx: &Context) {
+        Window::new("Systems").default_width(320.0).show(ctx, |ui| {
+            let peak = self
+                .history
+                .values()
+                .flatten()
+                .fold(0.1f32, |peak, &ms| peak.max(ms));
+            for (name, samples) in &self.history {
+                let latest = samples.back().copied().unwrap_or_default();
+                ui.label(format!("{}: {:.2} ms", name, latest));
+                let (rect, _) =
+                    ui.allocate_exact_size(vec2(ui.available_width(), 32.0), Sense::hover());
+                let step = rect.width() / self.history_len.max(2) as f32;
+                let points = samples
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &ms)| {
+                        pos2(
+                            rect.left() + i as f32 * step,
+                            rect.bottom() - rect.height() * ms / peak,
+                        )
+                    })
+                    .collect();
+                ui.painter()
+                    .add(Shape::line(points, Stroke::new(1.0, Color32::LIGHT_GREEN)));
+            }
+        });
+    }
+
+    fn log_window(&self, ctx: &Context) {
+        Window::new("Events").default_width(320.0).show(ctx, |ui| {
+            ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
+                for line in &self.log {
+                    ui.monospace(line);
+                }
+            });
+        });
+    }
+
+    fn console_window(&mut self, ctx: &Context, world: &mut World, lua: &Lua, console: &Console) {
+        Window::new("Console").default_width(420.0).show(ctx, |ui| {
+            ScrollArea::vertical()
+                .max_height(240.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for line in console.output() {
+                        ui.monospace(line);
+                    }
+                });
+            let response = ui.text_edit_singleline(&mut self.input);
+            if response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+                let line = std::mem::take(&mut self.input);
+                // Errors are already printed to the console.
+                let _ = console.execute(world, lua, &line);
+                response.request_focus();
+            }
+        });
+    }
+}
+
+fn metrics_window(ctx: &Context, metrics: &WorldMetrics) {
+    Window::new("Diagnostics")
+        .default_width(320.0)
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "{} entities, {} archetypes",
+                metrics.entities, metrics.archetypes
+            ));
+            for (name, bytes) in &metrics.lua_memory {
+                ui.label(format!("lua {}: {:.1} KiB", name, *bytes as f64 / 1024.0));
+            }
+            ui.collapsing("Components", |ui| {
+                for component in &metrics.components {
+                    ui.monospace(format!(
+                        "{}: {} ({} B)",
+                        component.name, component.count, component.bytes
+                    ));
+                }
+            });
+            ui.collapsing("Event queues", |ui| {
+                for (name, depth) in &metrics.events {
+                    ui.monospace(format!("{}: {}", name, depth));
+                }
+            });
+            if let Some(net) = &metrics.net {
+                ui.collapsing("Network", |ui| {
+                    let rtt = net
+                        .rtt_ms
+                        .map_or("-".to_string(), |rtt| format!("{:.0} ms", rtt));
+                    ui.label(format!(
+                        "rtt {}, loss {:.1}%, {} pending",
+                        rtt,
+                        net.packet_loss * 100.0,
+                        net.pending
+                    ));
+                    for (channel, stats) in &net.channels {
+                        ui.monospace(format!(
+                            "{}: {:.0} B/s out, {:.0} B/s in",
+                            channel, stats.bytes_sent_per_sec, stats.bytes_received_per_sec
+                        ));
+                    }
+                    ui.monospace(format!(
+                        "snapshots: {} B, {:.0} B avg",
+                        net.snapshot_bytes, net.snapshot_bytes_avg
+                    ));
+                });
+            }
+        });
+}
+
+/// Every registered and script component on `entity`, rendered as text.
+fn components(world: &World, lua: &Lua, entity: Entity) -> Vec<(String, String)> {
+    let mut found = Vec::new();
+    for info in world.registry().iter() {
+        let value = match (info.get)(world, entity, lua) {
+            Ok(Some(value)) => ScriptValue::from_lua(value, lua)
+                .map(|value| format!("{:#?}", value))
+                .unwrap_or_else(|e| e.to_string()),
+            Ok(None) => continue,
+            Err(e) => e.to_string(),
+        };
+        found.push((info.name.clone(), value));
+    }
+    for name in world.script_component_names() {
+        if let Some(value) = world.script_component(entity, name) {
+            found.push((name.to_string(), format!("{:#?}", value)));
+        }
+    }
+    found.sort();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Health {
+        hp: f64,
+    }
+
+    #[test]
+    fn test_overlay_runs_headless() -> mlua::Result<()> {
+        let lua = Lua::new();
+        let mut world = World::new();
+        world.register_component::<Health>("Health");
+        let player = world.spawn();
+        world.insert(player, Health { hp: 3.0 })?;
+        world.set_script_component(player, "Tag", ScriptValue::String("hero".to_string()))?;
+
+        let mut schedule = Schedule::new();
+        schedule.set_timing(true);
+        schedule.add_system("idle", |_| Ok(()));
+        schedule.run(&mut world, &lua)?;
+
+        let mut overlay = DebugOverlay::new();
+        overlay.selected = Some(player);
+        overlay.record_frame(&schedule);
+        world.send_event(AppExit { code: 0 });
+        crate::diagnostics::Diagnostics::new().update(&mut world);
+
+        let console = Console::new();
+        let ctx = Context::default();
+        for _ in 0..2 {
+            let mut output = ctx.run_ui(egui::RawInput::default(), |ui| {
+                overlay.show(ui.ctx(), &mut world, &lua, Some(&console));
+            });
+            assert!(!output.shapes.is_empty());
+            output.textures_delta.clear();
+        }
+
+        assert_eq!(overlay.log.len(), 1, "{:?}", overlay.log);
+        assert!(overlay.log[0].starts_with("exit"));
+        let shown: Vec<String> = components(&world, &lua, player)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(shown, ["Health", "Tag"]);
+        assert_eq!(overlay.history["idle"].len(), 1);
+        Ok(())
+    }
+}